@@ -0,0 +1,156 @@
+//! Automatic gain control
+//! Slowly rides the level of sources that are much too quiet or too loud toward a target
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::config::AgcConfig;
+use crate::dsp::AudioProcessor;
+
+/// RMS detector time constant
+const DETECTOR_MS: f32 = 300.0;
+/// Meters are published about ten times a second
+const METER_INTERVAL_MS: u32 = 100;
+/// Reported level of digital silence
+const FLOOR_DB: f32 = -96.0;
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn power_to_db(power: f32) -> f32 {
+    if power > 0.0 {
+        (10.0 * power.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant
+fn coefficient(ms: f32, sample_rate: u32) -> f32 {
+    let frames = (ms.max(0.1) / 1000.0 * sample_rate as f32).max(1.0);
+    1.0 - (-1.0 / frames).exp()
+}
+
+/// Levels before and after the AGC, read by the GUI
+#[derive(Debug, Default)]
+pub struct AgcMeter {
+    input_db: AtomicU32,
+    output_db: AtomicU32,
+    gain_db: AtomicU32,
+    /// Pass audio through untouched (levels are still measured)
+    bypass: AtomicBool,
+}
+
+impl AgcMeter {
+    /// RMS level of the raw input (dBFS)
+    pub fn input_db(&self) -> f32 {
+        f32::from_bits(self.input_db.load(Ordering::Relaxed))
+    }
+
+    /// RMS level after the AGC (dBFS)
+    pub fn output_db(&self) -> f32 {
+        f32::from_bits(self.output_db.load(Ordering::Relaxed))
+    }
+
+    /// Gain currently applied (dB)
+    pub fn gain_db(&self) -> f32 {
+        f32::from_bits(self.gain_db.load(Ordering::Relaxed))
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass.load(Ordering::Relaxed)
+    }
+
+    pub fn set_bypass(&self, bypass: bool) {
+        self.bypass.store(bypass, Ordering::Relaxed);
+        log::info!("[AGC] {}", if bypass { "Bypassed" } else { "Active" });
+    }
+}
+
+/// Gain rider: reduces gain quickly (attack) and raises it slowly (release)
+pub struct Agc {
+    meter: Arc<AgcMeter>,
+    channels: usize,
+    target_db: f32,
+    max_gain_db: f32,
+    /// Below this input level the gain is held (no boosting of noise floors)
+    gate_db: f32,
+    detector: f32,
+    attack: f32,
+    release: f32,
+    input_power: f32,
+    output_power: f32,
+    gain_db: f32,
+    meter_frames: u32,
+    frames_since_meter: u32,
+}
+
+impl Agc {
+    pub fn new(config: &AgcConfig, sample_rate: u32, channels: u16) -> (Self, Arc<AgcMeter>) {
+        let meter = Arc::new(AgcMeter::default());
+        meter.input_db.store(FLOOR_DB.to_bits(), Ordering::Relaxed);
+        meter.output_db.store(FLOOR_DB.to_bits(), Ordering::Relaxed);
+        (Self::with_meter(config, sample_rate, channels, meter.clone()), meter)
+    }
+
+    /// An AGC reporting to an existing meter (rebuilt with new settings; bypass carries over)
+    pub fn with_meter(config: &AgcConfig, sample_rate: u32, channels: u16, meter: Arc<AgcMeter>) -> Self {
+        log::info!(
+            "[AGC] Target {:.1} dBFS, max gain {:.1} dB, attack {}ms, release {}ms",
+            config.target_db, config.max_gain_db, config.attack_ms, config.release_ms
+        );
+        Self {
+            meter,
+            channels: channels.max(1) as usize,
+            target_db: config.target_db,
+            max_gain_db: config.max_gain_db.max(0.0),
+            gate_db: config.gate_db,
+            detector: coefficient(DETECTOR_MS, sample_rate),
+            attack: coefficient(config.attack_ms as f32, sample_rate),
+            release: coefficient(config.release_ms as f32, sample_rate),
+            input_power: 0.0,
+            output_power: 0.0,
+            gain_db: 0.0,
+            meter_frames: (sample_rate * METER_INTERVAL_MS / 1000).max(1),
+            frames_since_meter: 0,
+        }
+    }
+
+    fn publish(&self) {
+        self.meter.input_db.store(power_to_db(self.input_power).to_bits(), Ordering::Relaxed);
+        self.meter.output_db.store(power_to_db(self.output_power).to_bits(), Ordering::Relaxed);
+        self.meter.gain_db.store(self.gain_db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl AudioProcessor for Agc {
+    fn process(&mut self, samples: &mut [f32]) {
+        let bypass = self.meter.is_bypassed();
+
+        for frame in samples.chunks_mut(self.channels) {
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            self.input_power += self.detector * (power - self.input_power);
+
+            if !bypass {
+                let level_db = power_to_db(self.input_power);
+                if level_db > self.gate_db {
+                    let wanted = (self.target_db - level_db).clamp(-self.max_gain_db, self.max_gain_db);
+                    let speed = if wanted < self.gain_db { self.attack } else { self.release };
+                    self.gain_db += speed * (wanted - self.gain_db);
+                }
+                let gain = db_to_linear(self.gain_db);
+                frame.iter_mut().for_each(|s| *s = (*s * gain).clamp(-1.0, 1.0));
+            }
+
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            self.output_power += self.detector * (power - self.output_power);
+
+            self.frames_since_meter += 1;
+            if self.frames_since_meter >= self.meter_frames {
+                self.frames_since_meter = 0;
+                self.publish();
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Application channel
+//! One application captured on its own and sent next to the full mix, so `opus-mux` WebSocket
+//! players can balance the two (game loud, music quiet)
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::audio::{self, AudioSample, CaptureSource};
+use crate::config::{AppChannelConfig, OpusConfig};
+use crate::drops::Stage;
+use crate::dsp::{AudioProcessor, FadeControl, GainRamp};
+use crate::hub::BroadcastHub;
+use crate::opus_encoder::OpusEncoder;
+use crate::queue::{self, QueueBudget};
+use crate::server::OpusStreamInfo;
+use crate::sessions::{SessionCapture, SessionFilter};
+
+/// Channel id of the application in `opus-mux` frames (0 is the full mix)
+pub const CHANNEL_ID: u8 = 1;
+/// How often an idle channel checks for players and the stream
+const IDLE_POLL: Duration = Duration::from_millis(500);
+/// Wait before opening the capture again after it failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Captured audio waiting for the encoder
+const MAX_QUEUE_MS: u32 = 100;
+
+/// Settings of the application channel, for the WebSocket hello and /status
+#[derive(Debug)]
+pub struct AppChannel {
+    /// Executable name, lowercase
+    app: String,
+    bitrate: u32,
+}
+
+/// A running capture of the application with its encoder
+struct Running {
+    capture: SessionCapture,
+    rx: Receiver<AudioSample>,
+    channels: u16,
+    encoder: OpusEncoder,
+    ramp: GainRamp,
+}
+
+impl AppChannel {
+    /// Start the channel thread. It captures only while the stream is on and a player has
+    /// joined the channels, and follows the stream's mute (fades, privacy pause).
+    pub fn spawn(
+        config: &AppChannelConfig,
+        opus: &OpusConfig,
+        hub: Arc<BroadcastHub>,
+        info: &OpusStreamInfo,
+        fade: Arc<FadeControl>,
+        is_streaming: Arc<AtomicBool>,
+    ) -> Result<Arc<Self>, String> {
+        let app = config.app.trim().to_lowercase();
+        if app.is_empty() {
+            return Err("app_channel.app is empty".to_string());
+        }
+        if !cfg!(windows) {
+            return Err("The application channel needs Windows 10 (2004) or later".to_string());
+        }
+        let bitrate = config.bitrate.clamp(6, 256);
+        let (opus, channels) = (opus.clone(), info.channels);
+
+        let name = app.clone();
+        thread::Builder::new()
+            .name("app-channel".into())
+            .spawn(move || {
+                let mut running: Option<Running> = None;
+                // Logged once until the capture opens, the app may simply not be running yet
+                let mut error: Option<String> = None;
+                loop {
+                    if !is_streaming.load(Ordering::SeqCst) || hub.channel_count() == 0 {
+                        if let Some(mut idle) = running.take() {
+                            idle.capture.stop();
+                        }
+                        thread::sleep(IDLE_POLL);
+                        continue;
+                    }
+                    let current = match &mut running {
+                        Some(current) => current,
+                        None => match open(&name, bitrate, &opus, channels, fade.clone()) {
+                            Ok(opened) => {
+                                error = None;
+                                running.insert(opened)
+                            }
+                            Err(e) => {
+                                let message = e.to_string();
+                                if error.as_ref() != Some(&message) {
+                                    log::warn!("[APP] Cannot capture {}: {}", name, message);
+                                }
+                                error = Some(message);
+                                thread::sleep(RETRY_INTERVAL);
+                                continue;
+                            }
+                        },
+                    };
+
+                    let mut samples = match current.rx.recv_timeout(IDLE_POLL) {
+                        Ok(samples) => samples,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            current.capture.stop();
+                            running = None;
+                            continue;
+                        }
+                    };
+                    if current.channels != channels {
+                        samples = audio::remix(&samples, current.channels, channels);
+                    }
+                    current.ramp.process(&mut samples);
+                    match current.encoder.encode_raw(&samples) {
+                        Ok(packets) => {
+                            for packet in packets.into_iter().filter(|p| !p.is_empty()) {
+                                hub.publish_channel(CHANNEL_ID, packet);
+                            }
+                        }
+                        Err(e) => log::warn!("[APP] {}", e),
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+
+        log::info!("[APP] {} as channel {} at {}kbps", app, CHANNEL_ID, bitrate);
+        Ok(Arc::new(Self { app, bitrate }))
+    }
+
+    /// Executable name of the application
+    pub fn app(&self) -> &str {
+        &self.app
+    }
+
+    /// For /status
+    pub fn info(&self, hub: &BroadcastHub) -> serde_json::Value {
+        serde_json::json!({
+            "id": CHANNEL_ID,
+            "app": self.app,
+            "bitrate": self.bitrate,
+            "listeners": hub.channel_count(),
+        })
+    }
+}
+
+/// Capture the application at the output device's rate, encoded in the stream's channel count
+fn open(
+    app: &str,
+    bitrate: u32,
+    opus: &OpusConfig,
+    channels: u16,
+    fade: Arc<FadeControl>,
+) -> Result<Running, Box<dyn std::error::Error>> {
+    let filter = SessionFilter { exclude_system_sounds: true, only: Some(app.to_string()) };
+    let mut capture = SessionCapture::new(filter)?;
+    let (sample_rate, capture_channels) = (capture.sample_rate(), capture.channels());
+    let mut encoder = OpusEncoder::new(sample_rate, channels, bitrate)?;
+    encoder.tune(opus)?;
+
+    let rate = sample_rate.max(1) as f64 * capture_channels.max(1) as f64;
+    let (tx, rx) = queue::channel("app", Stage::Capture, QueueBudget::fixed(MAX_QUEUE_MS), move |samples: &AudioSample| {
+        Duration::from_secs_f64(samples.len() as f64 / rate)
+    });
+    capture.start(tx)?;
+    Ok(Running {
+        capture,
+        rx,
+        channels: capture_channels,
+        encoder,
+        ramp: GainRamp::new(fade, sample_rate, channels),
+    })
+}
@@ -1,194 +1,1151 @@
-//! Audio capture module using WASAPI (Windows Audio Session API)
-//! Captures system audio output (loopback)
+//! Audio capture backends
+//! System output loopback by default (WASAPI, or a loopback device on macOS); input devices,
+//! a signal generator, WAV files or silence via `capture`
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Stream, StreamConfig};
-use crossbeam_channel::{Receiver, Sender};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use cpal::{Device, SizedSample, Stream, StreamConfig};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::{CaptureConfig, DeviceLostPolicy, FailbackPolicy, StandbyConfig, Waveform};
+use crate::glitches::{self, Glitch};
+use crate::queue::PipelineSender;
+#[cfg(windows)]
+use crate::sessions::{self, SessionCapture, SessionFilter};
 
 /// Audio sample data
 pub type AudioSample = Vec<f32>;
 
-/// Audio capture handle
-pub struct AudioCapture {
+/// Length of the buffers produced by the software sources
+const SOFTWARE_CHUNK_MS: u32 = 10;
+/// Time between looking for a lost input device
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// The main device counts as recovered for failback while its last signal is this recent
+const SIGNAL_HOLD: Duration = Duration::from_secs(1);
+/// A buffer may start this much later than the previous one ended before it counts as a gap
+/// (timestamp jitter of the drivers)
+const GAP_TOLERANCE: Duration = Duration::from_millis(20);
+
+/// Opus' own rate: captured at it, the encoder skips its resampler
+#[cfg(windows)]
+const OPUS_RATE: u32 = 48_000;
+
+/// Input device state of the running main source, for /status and the GUI
+static DEVICE: Mutex<Option<DeviceStatus>> = Mutex::new(None);
+
+/// Something that delivers interleaved f32 samples to the encoder thread
+pub trait CaptureSource {
+    /// Human-readable description for the log
+    fn describe(&self) -> String;
+
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    /// Start sending buffers to `tx` (no-op if already running)
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Stop sending buffers
+    fn stop(&mut self);
+
+    /// Called about every 100ms by the audio control thread while the main source runs;
+    /// recovers from a lost device. An error stops the stream.
+    fn poll(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Create the capture source selected in the config
+pub fn open(config: &CaptureConfig) -> Result<Box<dyn CaptureSource>, Box<dyn std::error::Error>> {
+    let source: Box<dyn CaptureSource> = match config {
+        #[cfg(windows)]
+        CaptureConfig::Loopback { exclude_system_sounds, apps } if *exclude_system_sounds || !apps.is_empty() => {
+            sessions::set_rules(apps);
+            Box::new(SessionCapture::new(SessionFilter { exclude_system_sounds: *exclude_system_sounds, only: None })?)
+        }
+        #[cfg(windows)]
+        CaptureConfig::Loopback { .. } => Box::new(WasapiLoopback::new()?),
+        #[cfg(target_os = "macos")]
+        CaptureConfig::Loopback { .. } => Box::new(InputDevice::macos_loopback()?),
+        #[cfg(not(any(windows, target_os = "macos")))]
+        CaptureConfig::Loopback { .. } => {
+            return Err("Loopback capture is not available here; use capture type \"input\" with a monitor device".into())
+        }
+        CaptureConfig::Input { device, on_lost, standby } => {
+            let mut source = InputDevice::new(device, *on_lost)?;
+            if let Some(standby) = standby {
+                source.set_standby(standby);
+            }
+            Box::new(source)
+        }
+        CaptureConfig::Generator { waveform, frequency_hz, level_db, sample_rate, channels } => Box::new(
+            SignalGenerator::new(*waveform, *frequency_hz, *level_db, *sample_rate, *channels),
+        ),
+        CaptureConfig::File { path, looped } => Box::new(FilePlayer::open(path, *looped)?),
+        CaptureConfig::Null { sample_rate, channels } => Box::new(Null::new(*sample_rate, *channels)),
+    };
+    log::info!("Using audio source: {}", source.describe());
+    Ok(source)
+}
+
+/// Hand a captured buffer to the encoder thread without ever blocking the producer
+pub fn forward(tx: &PipelineSender<AudioSample>, samples: AudioSample) {
+    let len = samples.len();
+    match tx.try_send(samples) {
+        Ok(_) => {},
+        Err(crossbeam_channel::TrySendError::Full(_)) => {
+            log::warn!("[AUDIO] 채널 버퍼 풀! 오디오 샘플 {} 개 드롭됨", len);
+        },
+        Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
+            log::error!("[AUDIO] 채널 연결 끊김!");
+        }
+    }
+}
+
+/// Interleaved `from`-channel audio as `to` channels. Mono is the average of all
+/// channels; stereo keeps the first two (front left/right) and duplicates mono.
+pub fn remix(samples: &[f32], from: u16, to: u16) -> AudioSample {
+    let (from, to) = (from.max(1) as usize, to.max(1) as usize);
+    let mut out = Vec::with_capacity(samples.len() / from * to);
+    for frame in samples.chunks_exact(from) {
+        if to == 1 {
+            out.push(frame.iter().sum::<f32>() / from as f32);
+        } else {
+            out.extend((0..to).map(|ch| frame[ch.min(from - 1)]));
+        }
+    }
+    out
+}
+
+/// Format to capture `device` in: its shared-mode default, or on Windows the same at 48kHz when
+/// WASAPI format negotiation accepts that rate (`output`: loopback of an output device)
+fn capture_config(device: &Device, output: bool) -> Result<cpal::SupportedStreamConfig, Box<dyn std::error::Error>> {
+    let default = if output { device.default_output_config()? } else { device.default_input_config()? };
+    #[cfg(windows)]
+    if default.sample_rate().0 != OPUS_RATE {
+        let ranges: Vec<cpal::SupportedStreamConfigRange> = if output {
+            device.supported_output_configs().map(|ranges| ranges.collect())
+        } else {
+            device.supported_input_configs().map(|ranges| ranges.collect())
+        }
+        .unwrap_or_default();
+        // Same channels (shared mode offers no others), the default sample format if possible
+        let at_opus_rate = |format: cpal::SampleFormat| {
+            ranges.iter().find(|range| {
+                range.channels() == default.channels()
+                    && range.sample_format() == format
+                    && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&OPUS_RATE)
+            })
+        };
+        if let Some(range) = at_opus_rate(default.sample_format()).or_else(|| at_opus_rate(cpal::SampleFormat::F32)) {
+            log::debug!("[AUDIO] Capturing at {}Hz instead of the default {}Hz", OPUS_RATE, default.sample_rate().0);
+            return Ok(range.clone().with_sample_rate(cpal::SampleRate(OPUS_RATE)));
+        }
+    }
+    Ok(default)
+}
+
+/// Input stream converting any supported sample format to f32 with `channels` channels;
+/// `lost` is set when the device goes away, `watch` sees every buffer. `gaps`: holes in the
+/// capture timestamps count as glitches (not for WASAPI loopback, which sends nothing while
+/// nothing plays).
+fn build_stream(
+    device: &Device,
+    config: &cpal::SupportedStreamConfig,
+    channels: u16,
+    tx: PipelineSender<AudioSample>,
+    lost: Arc<AtomicBool>,
+    watch: Arc<StreamWatch>,
+    gaps: bool,
+) -> Result<Stream, Box<dyn std::error::Error>> {
+    let stream_config: StreamConfig = config.clone().into();
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_typed_stream::<f32>(device, &stream_config, channels, tx, lost, watch, gaps)?,
+        cpal::SampleFormat::I16 => build_typed_stream::<i16>(device, &stream_config, channels, tx, lost, watch, gaps)?,
+        cpal::SampleFormat::U16 => build_typed_stream::<u16>(device, &stream_config, channels, tx, lost, watch, gaps)?,
+        _ => return Err("Unsupported sample format".into()),
+    };
+    stream.play()?;
+    Ok(stream)
+}
+
+fn build_typed_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    channels: u16,
+    tx: PipelineSender<AudioSample>,
+    lost: Arc<AtomicBool>,
+    watch: Arc<StreamWatch>,
+    gaps: bool,
+) -> Result<Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let err_fn = move |err| {
+        if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+            lost.store(true, Ordering::SeqCst);
+        } else {
+            glitches::record(Glitch::Error);
+        }
+        log::error!("Audio stream error: {}", err);
+    };
+
+    let from = config.channels;
+    let rate = config.sample_rate.0;
+    // Capture time the next buffer should start at
+    let mut next: Option<cpal::StreamInstant> = None;
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], info: &cpal::InputCallbackInfo| {
+            let captured = info.timestamp().capture;
+            let gap = next.and_then(|next| captured.duration_since(&next)).filter(|gap| *gap > GAP_TOLERANCE);
+            let frames = data.len() / from.max(1) as usize;
+            next = captured.add(Duration::from_secs_f64(frames as f64 / rate as f64));
+
+            let samples: AudioSample = data.iter().map(|&s| s.to_sample::<f32>()).collect();
+            watch.saw(&samples);
+            if !watch.forwarding() {
+                return;
+            }
+            if let Some(gap) = gap.filter(|_| gaps) {
+                glitches::record(Glitch::Gap);
+                log::debug!("[AUDIO] Capture gap of {}ms", gap.as_millis());
+                if let Some(len) = glitches::fill_len(gap, rate, channels) {
+                    forward(&tx, vec![0.0; len]);
+                }
+            }
+            // A replacement device may have another channel count than the stream
+            forward(&tx, if from == channels { samples } else { remix(&samples, from, channels) });
+        },
+        err_fn,
+        None,
+    )?;
+
+    Ok(stream)
+}
+
+/// What an input stream delivered lately, to tell a dead or silent device from a working one
+struct StreamWatch {
+    /// Buffers go to the pipeline; off for a hot standby that is only watched
+    forward: AtomicBool,
+    /// Linear level a buffer must exceed to count as signal
+    threshold: f32,
+    started: Instant,
+    /// ms after `started` of the last buffer, and of the last buffer with signal
+    last_buffer: AtomicU64,
+    last_signal: AtomicU64,
+}
+
+impl StreamWatch {
+    fn new(silence_db: f32) -> Arc<Self> {
+        Arc::new(Self {
+            forward: AtomicBool::new(true),
+            threshold: 10f32.powf(silence_db / 20.0),
+            started: Instant::now(),
+            last_buffer: AtomicU64::new(0),
+            last_signal: AtomicU64::new(0),
+        })
+    }
+
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn saw(&self, samples: &[f32]) {
+        let now = self.now();
+        self.last_buffer.store(now, Ordering::Relaxed);
+        if samples.iter().any(|s| s.abs() > self.threshold) {
+            self.last_signal.store(now, Ordering::Relaxed);
+        }
+    }
+
+    fn forwarding(&self) -> bool {
+        self.forward.load(Ordering::Relaxed)
+    }
+
+    fn set_forwarding(&self, forward: bool) {
+        self.forward.store(forward, Ordering::SeqCst);
+    }
+
+    /// Count from now, for a stream just opened
+    fn reset(&self) {
+        let now = self.now();
+        self.last_buffer.store(now, Ordering::Relaxed);
+        self.last_signal.store(now, Ordering::Relaxed);
+    }
+
+    /// Whether a buffer arrived within the last `limit`
+    fn receiving(&self, limit: Duration) -> bool {
+        self.now().saturating_sub(self.last_buffer.load(Ordering::Relaxed)) <= limit.as_millis() as u64
+    }
+
+    /// Why the stream counts as failed over the last `limit`, None while it works
+    fn failure(&self, limit: Duration) -> Option<&'static str> {
+        if !self.receiving(limit) {
+            Some("no audio")
+        } else if self.now().saturating_sub(self.last_signal.load(Ordering::Relaxed)) > limit.as_millis() as u64 {
+            Some("silence")
+        } else {
+            None
+        }
+    }
+}
+
+/// System audio output captured through WASAPI loopback
+#[cfg(windows)]
+pub struct WasapiLoopback {
+    device: Device,
+    config: cpal::SupportedStreamConfig,
     stream: Option<Stream>,
-    pub sample_rate: u32,
-    pub channels: u16,
-    is_capturing: Arc<AtomicBool>,
 }
 
-impl AudioCapture {
-    /// Create a new audio capture instance
-    pub fn new() -> Result<(Self, Receiver<AudioSample>), Box<dyn std::error::Error>> {
-        // Use WASAPI host on Windows
+#[cfg(windows)]
+impl WasapiLoopback {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
         let host = cpal::host_from_id(cpal::HostId::Wasapi)?;
-        
+
         // Get the default output device for loopback capture
         let device = host
             .default_output_device()
             .ok_or("No output device available")?;
-        
-        log::info!("Using audio device: {}", device.name().unwrap_or_default());
 
-        // Get supported config
-        let config = device.default_output_config()?;
+        let config = capture_config(&device, true)?;
         log::info!("Audio config: {:?}", config);
+        if config.sample_rate().0 != OPUS_RATE {
+            log::info!(
+                "[AUDIO] The output device only mixes at {}Hz, resampled to 48kHz for Opus (set it to 48000 Hz \
+                 in the Windows sound settings to avoid that)",
+                config.sample_rate().0
+            );
+        }
 
-        let sample_rate = config.sample_rate().0;
-        let channels = config.channels();
+        Ok(Self { device, config, stream: None })
+    }
+}
 
-        let (_tx, rx): (Sender<AudioSample>, Receiver<AudioSample>) = crossbeam_channel::bounded(4);
-        let is_capturing = Arc::new(AtomicBool::new(false));
+#[cfg(windows)]
+impl CaptureSource for WasapiLoopback {
+    fn describe(&self) -> String {
+        format!("{} (loopback)", self.device.name().unwrap_or_default())
+    }
 
-        let capture = Self {
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate().0
+    }
+
+    fn channels(&self) -> u16 {
+        self.config.channels()
+    }
+
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.stream.is_none() {
+            // Building an input stream on an output device gives loopback on WASAPI
+            let lost = Arc::new(AtomicBool::new(false));
+            let watch = StreamWatch::new(f32::NEG_INFINITY);
+            self.stream = Some(build_stream(&self.device, &self.config, self.config.channels(), tx, lost, watch, false)?);
+            log::info!("Audio capture started");
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if self.stream.take().is_some() {
+            log::info!("Audio capture stopped");
+        }
+    }
+}
+
+/// Virtual devices that route the macOS system output back to an input
+#[cfg(target_os = "macos")]
+const MACOS_LOOPBACK_DEVICES: &[&str] = &["blackhole", "soundflower", "loopback audio", "background music"];
+
+/// A microphone or line input (any platform cpal supports)
+pub struct InputDevice {
+    device: Device,
+    /// Format of the device opened first; the stream keeps it across reconnects
+    config: cpal::SupportedStreamConfig,
+    stream: Option<Stream>,
+    /// Captures the system output through a virtual loopback device
+    loopback: bool,
+    /// Requested (partial) name, lowercase; empty = default input
+    wanted: String,
+    on_lost: DeviceLostPolicy,
+    /// Set by the stream's error callback when the device goes away
+    lost: Arc<AtomicBool>,
+    /// Level and liveness of `stream`; forwarding is off while the standby is streamed
+    watch: Arc<StreamWatch>,
+    standby: Option<Standby>,
+    tx: Option<PipelineSender<AudioSample>>,
+    state: DeviceState,
+    /// Silence while no device is captured, so listeners stay connected
+    filler: Option<PacedThread>,
+    next_try: Instant,
+    /// Why the last reconnect failed (logged once per message)
+    error: Option<String>,
+}
+
+/// Backup input kept open next to the main device, switched to when that one fails
+struct Standby {
+    config: StandbyConfig,
+    /// Requested (partial) name, lowercase; empty = default input
+    wanted: String,
+    /// Name of the device opened, None while it is missing
+    name: Option<String>,
+    stream: Option<Stream>,
+    lost: Arc<AtomicBool>,
+    watch: Arc<StreamWatch>,
+    next_try: Instant,
+    /// Why opening it failed the last time (logged once per message)
+    error: Option<String>,
+    /// Since when the main device delivers signal again, for `failback: auto`
+    recovered: Option<Instant>,
+}
+
+impl Standby {
+    fn failover_after(&self) -> Duration {
+        Duration::from_secs(self.config.failover_secs.max(1) as u64)
+    }
+}
+
+impl InputDevice {
+    /// `name` selects a device by (partial) name; empty uses the default input
+    pub fn new(name: &str, on_lost: DeviceLostPolicy) -> Result<Self, Box<dyn std::error::Error>> {
+        let wanted = name.to_lowercase();
+        let device = find_input(&wanted).ok_or_else(|| {
+            if name.is_empty() {
+                "No input device available".to_string()
+            } else {
+                format!("Input device not found: {}", name)
+            }
+        })?;
+
+        let mut source = Self::with_device(device, false)?;
+        source.wanted = wanted;
+        source.on_lost = on_lost;
+        Ok(source)
+    }
+
+    /// System output on macOS, which has no WASAPI-style loopback: CoreAudio input from a
+    /// virtual loopback driver such as BlackHole that the output is routed through
+    #[cfg(target_os = "macos")]
+    pub fn macos_loopback() -> Result<Self, Box<dyn std::error::Error>> {
+        let host = cpal::default_host();
+        let device = host
+            .input_devices()?
+            .find(|d| {
+                let name = d.name().unwrap_or_default().to_lowercase();
+                MACOS_LOOPBACK_DEVICES.iter().any(|known| name.contains(known))
+            })
+            .ok_or(
+                "No loopback device found. Install BlackHole (https://existential.audio/blackhole/), \
+                 add it to a Multi-Output Device in Audio MIDI Setup and select that as the output",
+            )?;
+        Self::with_device(device, true)
+    }
+
+    fn with_device(device: Device, loopback: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = capture_config(&device, false)?;
+        log::info!("Audio config: {:?}", config);
+
+        Ok(Self {
+            device,
+            config,
             stream: None,
-            sample_rate,
-            channels,
-            is_capturing,
+            loopback,
+            wanted: String::new(),
+            on_lost: DeviceLostPolicy::default(),
+            lost: Arc::new(AtomicBool::new(false)),
+            watch: StreamWatch::new(f32::NEG_INFINITY),
+            standby: None,
+            tx: None,
+            state: DeviceState::Active,
+            filler: None,
+            next_try: Instant::now(),
+            error: None,
+        })
+    }
+
+    /// Keep `config.device` open alongside and stream it while the main device fails.
+    /// Must be called before start.
+    pub fn set_standby(&mut self, config: &StandbyConfig) {
+        self.watch = StreamWatch::new(config.silence_db);
+        let watch = StreamWatch::new(config.silence_db);
+        watch.set_forwarding(false);
+        self.standby = Some(Standby {
+            config: config.clone(),
+            wanted: config.device.to_lowercase(),
+            name: None,
+            stream: None,
+            lost: Arc::new(AtomicBool::new(false)),
+            watch,
+            next_try: Instant::now(),
+            error: None,
+            recovered: None,
+        });
+    }
+
+    /// Stream from `device` at the stream's rate and channel count
+    fn open_stream(
+        &self,
+        device: &Device,
+        lost: &Arc<AtomicBool>,
+        watch: &Arc<StreamWatch>,
+    ) -> Result<Stream, Box<dyn std::error::Error>> {
+        let tx = self.tx.clone().ok_or("Capture is not running")?;
+        let config = capture_config(device, false)?;
+        if config.sample_rate() != self.config.sample_rate() {
+            return Err(format!(
+                "{} runs at {}Hz, the stream at {}Hz",
+                device.name().unwrap_or_default(),
+                config.sample_rate().0,
+                self.config.sample_rate().0
+            )
+            .into());
+        }
+        lost.store(false, Ordering::SeqCst);
+        watch.reset();
+        build_stream(device, &config, self.config.channels(), tx, lost.clone(), watch.clone(), true)
+    }
+
+    /// Capture `device` in place of the current one
+    fn connect(&mut self, device: Device) -> Result<(), Box<dyn std::error::Error>> {
+        self.stream = Some(self.open_stream(&device, &self.lost, &self.watch)?);
+        self.device = device;
+        self.filler = None;
+        self.error = None;
+        Ok(())
+    }
+
+    fn connect_failed(&mut self, e: Box<dyn std::error::Error>) {
+        let message = e.to_string();
+        if self.error.as_ref() != Some(&message) {
+            log::warn!("[DEVICE] Cannot capture: {}", message);
+        }
+        self.error = Some(message);
+    }
+
+    /// Try the wanted device, or per `on_lost` the default input while it is missing
+    fn reconnect(&mut self) {
+        // The wanted device is tried in fallback too, so capture goes back to it once it returns
+        let result = match find_input(&self.wanted) {
+            Some(device) => self.connect(device).map(|_| DeviceState::Active),
+            None if self.on_lost == DeviceLostPolicy::FollowDefault && self.state == DeviceState::Waiting => {
+                match cpal::default_host().default_input_device() {
+                    Some(device) => self.connect(device).map(|_| DeviceState::Fallback),
+                    None => return,
+                }
+            }
+            None => return,
         };
+        match result {
+            Ok(state) => {
+                let name = self.device.name().unwrap_or_default();
+                match state {
+                    DeviceState::Fallback => log::warn!("[DEVICE] Capturing the default input {} meanwhile", name),
+                    _ => log::info!("[DEVICE] Reconnected to {}", name),
+                }
+                self.state = state;
+            }
+            Err(e) => self.connect_failed(e),
+        }
+    }
 
-        // We'll store device and config info for later stream creation
-        Ok((capture, rx))
+    /// The main device is gone and no standby takes over: act per `on_lost`
+    fn lose_device(&mut self, now: Instant) -> Result<(), Box<dyn std::error::Error>> {
+        self.next_try = now;
+        if self.on_lost == DeviceLostPolicy::Stop {
+            self.state = DeviceState::Stopped;
+            *DEVICE.lock().unwrap() = Some(self.status());
+            return Err(format!("Capture device lost: {}", self.device.name().unwrap_or_default()).into());
+        }
+        self.state = DeviceState::Waiting;
+        if let Some(tx) = self.tx.clone() {
+            self.filler = Some(PacedThread::spawn(self.sample_rate(), self.channels(), tx, |_| {}));
+        }
+        Ok(())
     }
 
-    /// Start capturing audio
-    pub fn start(&mut self, tx: Sender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
-        if self.is_capturing.load(Ordering::SeqCst) {
-            return Ok(());
+    /// Open the standby device if it isn't open yet; retried while it is missing
+    fn connect_standby(&mut self, now: Instant) {
+        let Some(standby) = self.standby.as_ref().filter(|s| s.stream.is_none() && now >= s.next_try) else {
+            return;
+        };
+        let result = match find_input(&standby.wanted) {
+            Some(device) => self.open_stream(&device, &standby.lost, &standby.watch).map(|stream| (device, stream)),
+            None => Err(format!("Standby device not found: {}", standby.wanted).into()),
+        };
+        let Some(standby) = self.standby.as_mut() else {
+            return;
+        };
+        standby.next_try = now + RECONNECT_INTERVAL;
+        match result {
+            Ok((device, stream)) => {
+                let name = device.name().unwrap_or_default();
+                log::info!("[DEVICE] Standby {} ready", name);
+                standby.name = Some(name);
+                standby.stream = Some(stream);
+                standby.error = None;
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if standby.error.as_ref() != Some(&message) {
+                    log::warn!("[DEVICE] No standby: {}", message);
+                }
+                standby.error = Some(message);
+            }
         }
+    }
 
-        let host = cpal::host_from_id(cpal::HostId::Wasapi)?;
-        let device = host
-            .default_output_device()
-            .ok_or("No output device available")?;
-        
-        let config = device.default_output_config()?;
-        let stream_config: StreamConfig = config.clone().into();
-
-        let _is_capturing = self.is_capturing.clone();
-        
-        // Build input stream for loopback capture
-        let stream = match config.sample_format() {
-            cpal::SampleFormat::F32 => self.build_stream::<f32>(&device, &stream_config, tx)?,
-            cpal::SampleFormat::I16 => self.build_stream_i16(&device, &stream_config, tx)?,
-            cpal::SampleFormat::U16 => self.build_stream_u16(&device, &stream_config, tx)?,
-            _ => return Err("Unsupported sample format".into()),
+    /// Stream the standby instead of the main device, if it delivers audio
+    fn fail_over(&mut self, reason: &str) -> bool {
+        let Some(standby) = self.standby.as_mut() else {
+            return false;
         };
+        if standby.stream.is_none() || !standby.watch.receiving(standby.failover_after()) {
+            return false;
+        }
+        // Forwarding stops before it starts on the other side, so nothing is doubled
+        self.watch.set_forwarding(false);
+        standby.watch.set_forwarding(true);
+        standby.recovered = None;
+        log::warn!(
+            "[DEVICE] {} failed ({}), streaming the standby {}",
+            self.device.name().unwrap_or_default(),
+            reason,
+            standby.name.as_deref().unwrap_or_default()
+        );
+        if self.state == DeviceState::Fallback {
+            // That was the default input; the wanted device is reopened for failback
+            self.stream = None;
+        }
+        self.filler = None;
+        self.state = DeviceState::Standby;
+        true
+    }
 
-        stream.play()?;
-        self.stream = Some(stream);
-        self.is_capturing.store(true, Ordering::SeqCst);
-        
-        log::info!("Audio capture started");
+    /// Close the standby if it went away or stopped delivering audio; true if it did
+    fn standby_failed(&mut self, now: Instant) -> bool {
+        let Some(standby) = self.standby.as_mut() else {
+            return false;
+        };
+        let lost = standby.lost.swap(false, Ordering::SeqCst);
+        if !lost && standby.watch.receiving(standby.failover_after()) {
+            return false;
+        }
+        let Some(_) = standby.stream.take() else {
+            return false;
+        };
+        let name = standby.name.take().unwrap_or_default();
+        log::warn!("[DEVICE] Standby {} {}", name, if lost { "disappeared" } else { "stopped delivering audio" });
+        standby.watch.set_forwarding(false);
+        standby.recovered = None;
+        standby.next_try = now;
+        true
+    }
+
+    /// While the standby is streamed: reopen the main device if it was lost, and go back to it
+    /// per `failback` once it delivers signal again
+    fn fail_back(&mut self, now: Instant) {
+        if self.stream.is_none() && now >= self.next_try {
+            self.next_try = now + RECONNECT_INTERVAL;
+            if let Some(device) = find_input(&self.wanted) {
+                match self.connect(device) {
+                    Ok(()) => log::info!("[DEVICE] {} is back", self.device.name().unwrap_or_default()),
+                    Err(e) => self.connect_failed(e),
+                }
+            }
+        }
+        let Some(standby) = self.standby.as_mut() else {
+            return;
+        };
+        if self.stream.is_none() || self.watch.failure(SIGNAL_HOLD).is_some() {
+            standby.recovered = None;
+            return;
+        }
+        let since = *standby.recovered.get_or_insert(now);
+        let hold = Duration::from_secs(standby.config.failback_secs as u64);
+        if standby.config.failback == FailbackPolicy::Manual || now.duration_since(since) < hold {
+            return;
+        }
+        standby.watch.set_forwarding(false);
+        self.watch.set_forwarding(true);
+        standby.recovered = None;
+        self.state = DeviceState::Active;
+        log::info!("[DEVICE] Back on {}, the standby is idle again", self.device.name().unwrap_or_default());
+    }
+
+    fn status(&self) -> DeviceStatus {
+        let using = match (&self.standby, self.state) {
+            (Some(standby), DeviceState::Standby) => standby.name.clone(),
+            _ => self.stream.as_ref().map(|_| self.device.name().unwrap_or_default()),
+        };
+        DeviceStatus {
+            device: self.wanted.clone(),
+            using,
+            state: self.state,
+            on_lost: self.on_lost,
+            standby: self.standby.as_ref().and_then(|standby| standby.name.clone()),
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl CaptureSource for InputDevice {
+    fn describe(&self) -> String {
+        let kind = if self.loopback { "loopback" } else { "input" };
+        format!("{} ({})", self.device.name().unwrap_or_default(), kind)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate().0
+    }
+
+    fn channels(&self) -> u16 {
+        self.config.channels()
+    }
+
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.tx.is_none() {
+            self.tx = Some(tx);
+            if let Err(e) = self.connect(self.device.clone()) {
+                self.tx = None;
+                return Err(e);
+            }
+            self.state = DeviceState::Active;
+            self.connect_standby(Instant::now());
+            log::info!("Audio capture started");
+        }
         Ok(())
     }
 
-    fn build_stream<T>(
-        &self,
-        device: &Device,
-        config: &StreamConfig,
-        tx: Sender<AudioSample>,
-    ) -> Result<Stream, Box<dyn std::error::Error>>
+    fn stop(&mut self) {
+        self.stream = None;
+        self.filler = None;
+        self.watch.set_forwarding(true);
+        if let Some(standby) = self.standby.as_mut() {
+            standby.stream = None;
+            standby.name = None;
+            standby.recovered = None;
+            standby.watch.set_forwarding(false);
+        }
+        if self.tx.take().is_some() {
+            log::info!("Audio capture stopped");
+        }
+    }
+
+    fn poll(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.loopback || self.tx.is_none() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        self.connect_standby(now);
+
+        if self.lost.swap(false, Ordering::SeqCst) && self.stream.take().is_some() {
+            let name = self.device.name().unwrap_or_default();
+            log::warn!("[DEVICE] {} disappeared (on_lost: {})", name, self.on_lost.as_str());
+            self.next_try = now;
+            // Already on the standby: it carries on and the device is reopened for failback
+            if self.state != DeviceState::Standby && !self.fail_over("disappeared") {
+                self.lose_device(now)?;
+            }
+        }
+
+        // Still there but stalled or silent
+        let limit = self.standby.as_ref().map(Standby::failover_after).filter(|_| self.state == DeviceState::Active);
+        if let Some(reason) = limit.and_then(|limit| self.watch.failure(limit)) {
+            self.fail_over(reason);
+        }
+
+        if self.standby_failed(now) && self.state == DeviceState::Standby {
+            self.watch.set_forwarding(true);
+            if self.stream.is_some() {
+                log::warn!("[DEVICE] Back on {}", self.device.name().unwrap_or_default());
+                self.state = DeviceState::Active;
+            } else {
+                self.lose_device(now)?;
+            }
+        }
+
+        match self.state {
+            DeviceState::Standby => self.fail_back(now),
+            DeviceState::Waiting | DeviceState::Fallback => {
+                if !self.fail_over("disappeared") && now >= self.next_try {
+                    self.next_try = now + RECONNECT_INTERVAL;
+                    self.reconnect();
+                }
+            }
+            DeviceState::Active | DeviceState::Stopped => {}
+        }
+        *DEVICE.lock().unwrap() = Some(self.status());
+        Ok(())
+    }
+}
+
+/// Input device whose name contains `wanted` (lowercase), or the default input for ""
+fn find_input(wanted: &str) -> Option<Device> {
+    let host = cpal::default_host();
+    if wanted.is_empty() {
+        return host.default_input_device();
+    }
+    host.input_devices()
+        .ok()?
+        .find(|d| d.name().map(|n| n.to_lowercase().contains(wanted)).unwrap_or(false))
+}
+
+/// Where the input device of the main source stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceState {
+    /// Capturing the configured device
+    Active,
+    /// Device gone; sending silence until it returns
+    Waiting,
+    /// Device gone; capturing the default input meanwhile
+    Fallback,
+    /// Device gone and streaming stopped (`on_lost: "stop"`)
+    Stopped,
+    /// Device failed (gone, no audio or silence); capturing the standby device
+    Standby,
+}
+
+/// Input device of the main source as shown in /status
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatus {
+    /// Configured name (lowercase, empty = default input)
+    pub device: String,
+    /// Device actually captured, None while waiting
+    pub using: Option<String>,
+    pub state: DeviceState,
+    pub on_lost: DeviceLostPolicy,
+    /// Standby device kept open, None without one or while it is missing
+    pub standby: Option<String>,
+    /// Why reconnecting failed the last time
+    pub error: Option<String>,
+}
+
+/// Input device state, None unless an input device is the main source
+pub fn device_status() -> Option<DeviceStatus> {
+    DEVICE.lock().unwrap().clone()
+}
+
+/// Forget the device state once streaming is stopped on purpose
+pub fn clear_device_status() {
+    *DEVICE.lock().unwrap() = None;
+}
+
+/// Thread that fills fixed-size buffers in real time, like a sound card would
+pub struct PacedThread {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PacedThread {
+    pub fn spawn<F>(sample_rate: u32, channels: u16, tx: PipelineSender<AudioSample>, mut fill: F) -> Self
     where
-        T: cpal::Sample + cpal::SizedSample + Into<f32>,
+        F: FnMut(&mut [f32]) + Send + 'static,
     {
-        let err_fn = |err| log::error!("Audio stream error: {}", err);
-        
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                let samples: Vec<f32> = data.to_vec();
-                match tx.try_send(samples) {
-                    Ok(_) => {},
-                    Err(crossbeam_channel::TrySendError::Full(_)) => {
-                        log::warn!("[AUDIO] 채널 버퍼 풀! 오디오 샘플 {} 개 드롭됨", data.len());
-                    },
-                    Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
-                        log::error!("[AUDIO] 채널 연결 끊김!");
-                    }
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        let frames = (sample_rate * SOFTWARE_CHUNK_MS / 1000).max(1) as usize;
+        let chunk = Duration::from_millis(SOFTWARE_CHUNK_MS as u64);
+
+        let handle = thread::spawn(move || {
+            let mut deadline = Instant::now();
+            while flag.load(Ordering::SeqCst) {
+                let mut samples = vec![0.0; frames * channels as usize];
+                fill(&mut samples);
+                forward(&tx, samples);
+
+                deadline += chunk;
+                match deadline.checked_duration_since(Instant::now()) {
+                    Some(wait) => thread::sleep(wait),
+                    // Fell behind (suspended, debugger, ...); don't burst to catch up
+                    None => deadline = Instant::now(),
                 }
-            },
-            err_fn,
-            None,
-        )?;
+            }
+        });
 
-        Ok(stream)
+        Self { running, handle: Some(handle) }
     }
+}
 
-    fn build_stream_i16(
-        &self,
-        device: &Device,
-        config: &StreamConfig,
-        tx: Sender<AudioSample>,
-    ) -> Result<Stream, Box<dyn std::error::Error>> {
-        let err_fn = |err| log::error!("Audio stream error: {}", err);
-        
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                let samples: Vec<f32> = data.iter().map(|&s| s as f32 / 32768.0).collect();
-                match tx.try_send(samples) {
-                    Ok(_) => {},
-                    Err(crossbeam_channel::TrySendError::Full(_)) => {
-                        log::warn!("[AUDIO] 채널 버퍼 풀! i16 오디오 샘플 {} 개 드롭됨", data.len());
-                    },
-                    Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
-                        log::error!("[AUDIO] 채널 연결 끊김!");
+impl Drop for PacedThread {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Test signal, for checking the pipeline and player levels without playing anything
+pub struct SignalGenerator {
+    waveform: Waveform,
+    frequency_hz: f32,
+    amplitude: f32,
+    sample_rate: u32,
+    channels: u16,
+    thread: Option<PacedThread>,
+}
+
+impl SignalGenerator {
+    pub fn new(waveform: Waveform, frequency_hz: f32, level_db: f32, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            waveform,
+            frequency_hz: frequency_hz.clamp(1.0, sample_rate as f32 / 2.0),
+            amplitude: 10f32.powf(level_db.min(0.0) / 20.0),
+            sample_rate,
+            channels: channels.clamp(1, 2),
+            thread: None,
+        }
+    }
+}
+
+impl CaptureSource for SignalGenerator {
+    fn describe(&self) -> String {
+        format!(
+            "{:?} generator, {}Hz at {:.1} dBFS ({}Hz, {}ch)",
+            self.waveform,
+            self.frequency_hz,
+            20.0 * self.amplitude.log10(),
+            self.sample_rate,
+            self.channels
+        )
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.thread.is_some() {
+            return Ok(());
+        }
+
+        let (waveform, amplitude, channels) = (self.waveform, self.amplitude, self.channels as usize);
+        let step = self.frequency_hz / self.sample_rate as f32;
+        let mut phase = 0.0f32;
+        let mut noise = 0x2545_f491u32;
+
+        self.thread = Some(PacedThread::spawn(self.sample_rate, self.channels, tx, move |samples| {
+            for frame in samples.chunks_mut(channels) {
+                let value = match waveform {
+                    Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+                    Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+                    Waveform::Noise => {
+                        // xorshift32
+                        noise ^= noise << 13;
+                        noise ^= noise >> 17;
+                        noise ^= noise << 5;
+                        noise as f32 / u32::MAX as f32 * 2.0 - 1.0
                     }
-                }
-            },
-            err_fn,
-            None,
-        )?;
+                };
+                frame.fill(value * amplitude);
+                phase = (phase + step).fract();
+            }
+        }));
+        log::info!("Audio capture started");
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if self.thread.take().is_some() {
+            log::info!("Audio capture stopped");
+        }
+    }
+}
+
+/// A WAV file played in real time (PCM 16/24/32-bit or 32-bit float)
+pub struct FilePlayer {
+    path: String,
+    /// Whole file, interleaved
+    samples: Arc<Vec<f32>>,
+    sample_rate: u32,
+    channels: u16,
+    looped: bool,
+    thread: Option<PacedThread>,
+}
+
+impl FilePlayer {
+    pub fn open(path: &str, looped: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let (sample_rate, channels, samples) =
+            parse_wav(&data).map_err(|e| format!("{}: {}", path, e))?;
+
+        Ok(Self {
+            path: path.to_string(),
+            samples: Arc::new(samples),
+            sample_rate,
+            channels,
+            looped,
+            thread: None,
+        })
+    }
+}
 
-        Ok(stream)
+impl CaptureSource for FilePlayer {
+    fn describe(&self) -> String {
+        let secs = self.samples.len() as f32 / self.channels as f32 / self.sample_rate as f32;
+        format!(
+            "{} ({:.1}s, {}Hz, {}ch{})",
+            self.path,
+            secs,
+            self.sample_rate,
+            self.channels,
+            if self.looped { ", looped" } else { "" }
+        )
     }
 
-    fn build_stream_u16(
-        &self,
-        device: &Device,
-        config: &StreamConfig,
-        tx: Sender<AudioSample>,
-    ) -> Result<Stream, Box<dyn std::error::Error>> {
-        let err_fn = |err| log::error!("Audio stream error: {}", err);
-        
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[u16], _: &cpal::InputCallbackInfo| {
-                let samples: Vec<f32> = data.iter().map(|&s| (s as f32 - 32768.0) / 32768.0).collect();
-                match tx.try_send(samples) {
-                    Ok(_) => {},
-                    Err(crossbeam_channel::TrySendError::Full(_)) => {
-                        log::warn!("[AUDIO] 채널 버퍼 풀! u16 오디오 샘플 {} 개 드롭됨", data.len());
-                    },
-                    Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
-                        log::error!("[AUDIO] 채널 연결 끊김!");
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Playback restarts from the beginning on every start
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.thread.is_some() {
+            return Ok(());
+        }
+
+        let samples = self.samples.clone();
+        let looped = self.looped;
+        let mut pos = 0;
+
+        self.thread = Some(PacedThread::spawn(self.sample_rate, self.channels, tx, move |out| {
+            for slot in out.iter_mut() {
+                if pos >= samples.len() {
+                    if !looped || samples.is_empty() {
+                        // Past the end: silence keeps the stream (and its timing) going
+                        return;
                     }
+                    pos = 0;
                 }
-            },
-            err_fn,
-            None,
-        )?;
+                *slot = samples[pos];
+                pos += 1;
+            }
+        }));
+        log::info!("Audio capture started");
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if self.thread.take().is_some() {
+            log::info!("Audio capture stopped");
+        }
+    }
+}
+
+/// Silence, for running the server without any audio hardware
+pub struct Null {
+    sample_rate: u32,
+    channels: u16,
+    thread: Option<PacedThread>,
+}
 
-        Ok(stream)
+impl Null {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self { sample_rate, channels: channels.clamp(1, 2), thread: None }
     }
+}
 
-    /// Stop capturing audio
-    pub fn stop(&mut self) {
-        self.stream = None;
-        self.is_capturing.store(false, Ordering::SeqCst);
-        log::info!("Audio capture stopped");
+impl CaptureSource for Null {
+    fn describe(&self) -> String {
+        format!("silence ({}Hz, {}ch)", self.sample_rate, self.channels)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
     }
 
-    /// Check if currently capturing
-    pub fn is_capturing(&self) -> bool {
-        self.is_capturing.load(Ordering::SeqCst)
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.thread.is_none() {
+            self.thread = Some(PacedThread::spawn(self.sample_rate, self.channels, tx, |_| {}));
+            log::info!("Audio capture started");
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if self.thread.take().is_some() {
+            log::info!("Audio capture stopped");
+        }
     }
 }
 
-impl Drop for AudioCapture {
-    fn drop(&mut self) {
-        self.stop();
+/// Decode a RIFF/WAVE file into (sample rate, channels, interleaved f32 samples)
+fn parse_wav(data: &[u8]) -> Result<(u32, u16, Vec<f32>), String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("not a WAV file".into());
     }
+
+    let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let len = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        let end = (body + len).min(data.len());
+
+        match id {
+            b"fmt " if len >= 16 => {
+                let mut tag = u16_at(body);
+                // WAVE_FORMAT_EXTENSIBLE: the real format is the start of the sub-format GUID
+                if tag == 0xFFFE && len >= 26 {
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => {
+                let (tag, channels, sample_rate, bits) = format.ok_or("data chunk before fmt chunk")?;
+                if channels == 0 || channels > 2 {
+                    return Err(format!("{} channels not supported (mono or stereo only)", channels));
+                }
+                let body = &data[body..end];
+                let samples = match (tag, bits) {
+                    (1, 16) => body
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                        .collect(),
+                    (1, 24) => body
+                        .chunks_exact(3)
+                        .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0)
+                        .collect(),
+                    (1, 32) => body
+                        .chunks_exact(4)
+                        .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0)
+                        .collect(),
+                    (3, 32) => body
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                    _ => return Err(format!("unsupported sample format (tag {}, {} bits)", tag, bits)),
+                };
+                return Ok((sample_rate, channels, samples));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        pos = body + len + (len & 1);
+    }
+
+    Err("no data chunk".into())
 }
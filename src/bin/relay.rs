@@ -0,0 +1,305 @@
+//! RustCast relay server
+//! Public end of the tunnel (`tunnel` in the station's config): listeners connect here, and
+//! their connections are carried to the station over the WebSocket it keeps open. Built with
+//! `cargo build --release --features relay --bin rustcast-relay`.
+//!
+//! ```text
+//! rustcast-relay --listen 0.0.0.0:8000 --token <secret> [--public-url http://radio.example.com:8000]
+//! ```
+
+#[path = "../tunnel_protocol.rs"]
+mod tunnel_protocol;
+#[path = "../ws.rs"]
+mod ws;
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use tunnel_protocol::{FrameReader, Message, MAX_DATA};
+use ws::Opcode;
+
+/// How often the station is pinged (well within its idle timeout)
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Request head of a new connection: wait this long, read at most this much
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEAD: usize = 8192;
+/// Station data a listener may fall behind by before it is dropped (DATA messages)
+const LISTENER_QUEUE: usize = 512;
+/// A station that doesn't take our data for this long is treated as gone
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Options {
+    listen: String,
+    token: String,
+    public_url: String,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Options { listen: "0.0.0.0:8000".to_string(), token: String::new(), public_url: String::new() };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--listen" => options.listen = value()?,
+                "--token" => options.token = value()?,
+                "--public-url" => options.public_url = value()?,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+        if options.token.is_empty() {
+            options.token = std::env::var("RUSTCAST_RELAY_TOKEN").unwrap_or_default();
+        }
+        if options.token.is_empty() {
+            return Err("A token is required (--token or RUSTCAST_RELAY_TOKEN)".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// The connected station
+struct Station {
+    uplink: Mutex<TcpStream>,
+    /// Per listener: data waiting to be written to it
+    listeners: Mutex<HashMap<u32, Sender<Vec<u8>>>>,
+}
+
+impl Station {
+    fn send(&self, first_byte: u8, payload: &[u8]) -> std::io::Result<()> {
+        self.uplink.lock().unwrap().write_all(&ws::encode_frame(first_byte, payload))
+    }
+
+    fn message(&self, message: &Message) -> std::io::Result<()> {
+        self.send(0x82, &message.encode())
+    }
+
+    /// Stop writing to a listener; its writer closes the connection
+    fn drop_listener(&self, stream: u32) -> bool {
+        self.listeners.lock().unwrap().remove(&stream).is_some()
+    }
+}
+
+struct Relay {
+    options: Options,
+    station: Mutex<Option<Arc<Station>>>,
+    next_stream: AtomicU32,
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format_timestamp(Some(env_logger::TimestampPrecision::Seconds))
+        .init();
+
+    let options = match Options::parse() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\nUsage: rustcast-relay --listen 0.0.0.0:8000 --token <secret> [--public-url <url>]", e);
+            std::process::exit(2);
+        }
+    };
+    let listener = match TcpListener::bind(&options.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("[RELAY] Cannot listen on {}: {}", options.listen, e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("[RELAY] Listening on {}; waiting for the station", options.listen);
+
+    let relay = Arc::new(Relay { options, station: Mutex::new(None), next_stream: AtomicU32::new(1) });
+    for connection in listener.incoming() {
+        let Ok(connection) = connection else { continue };
+        let relay = relay.clone();
+        let _ = thread::Builder::new().name("relay-conn".into()).spawn(move || accept(&relay, connection));
+    }
+}
+
+/// Read the request head and hand the connection to the station or the tunnel
+fn accept(relay: &Arc<Relay>, mut connection: TcpStream) {
+    let remote = connection.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+    let _ = connection.set_nodelay(true);
+    let _ = connection.set_read_timeout(Some(HEAD_TIMEOUT));
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 2048];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match connection.read(&mut chunk) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => head.extend_from_slice(&chunk[..read]),
+        }
+        if head.len() > MAX_HEAD {
+            return;
+        }
+    }
+    let _ = connection.set_read_timeout(None);
+
+    let text = String::from_utf8_lossy(&head).into_owned();
+    let path = text.split_whitespace().nth(1).unwrap_or_default();
+    if path.split('?').next() == Some(tunnel_protocol::PATH) {
+        station(relay, connection, &text, &remote);
+    } else {
+        listener(relay, connection, head, remote);
+    }
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Compare tokens without leaking how many leading bytes matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn respond(connection: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = connection.write_all(response.as_bytes());
+}
+
+/// The station connecting: check its token, upgrade and carry its side of every stream
+fn station(relay: &Arc<Relay>, mut connection: TcpStream, head: &str, remote: &str) {
+    let token = header(head, "Authorization").and_then(|value| value.strip_prefix("Bearer ")).unwrap_or_default();
+    if !constant_time_eq(token.trim().as_bytes(), relay.options.token.as_bytes()) {
+        log::warn!("[RELAY] Refused a station from {}: wrong token", remote);
+        respond(&mut connection, "401 Unauthorized", "Wrong token\n");
+        return;
+    }
+    let Some(key) = header(head, "Sec-WebSocket-Key") else {
+        respond(&mut connection, "400 Bad Request", "Expected a WebSocket upgrade\n");
+        return;
+    };
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: {}\r\n\r\n",
+        ws::accept_key(key),
+        tunnel_protocol::PROTOCOL
+    );
+    if connection.write_all(response.as_bytes()).is_err() {
+        return;
+    }
+    let (Ok(uplink), Ok(()), Ok(())) = (
+        connection.try_clone(),
+        connection.set_read_timeout(Some(tunnel_protocol::IDLE_TIMEOUT)),
+        connection.set_write_timeout(Some(WRITE_TIMEOUT)),
+    ) else {
+        return;
+    };
+
+    let station = Arc::new(Station { uplink: Mutex::new(uplink), listeners: Mutex::new(HashMap::new()) });
+    let hello = serde_json::json!({ "type": "hello", "public_url": relay.options.public_url });
+    if station.send(0x81, hello.to_string().as_bytes()).is_err() {
+        return;
+    }
+    // A new connection replaces the old one (the station reconnected before we noticed)
+    if let Some(previous) = relay.station.lock().unwrap().replace(station.clone()) {
+        let _ = previous.uplink.lock().unwrap().shutdown(Shutdown::Both);
+    }
+    log::info!("[RELAY] Station connected from {}", remote);
+    spawn_pinger(Arc::downgrade(&station));
+
+    let mut reader = FrameReader::new(connection, Vec::new(), false);
+    let reason = loop {
+        let frame = match reader.next() {
+            Ok(frame) => frame,
+            Err(e) => break e.to_string(),
+        };
+        match frame.opcode {
+            Opcode::Close => break "closed by the station".to_string(),
+            Opcode::Ping => {
+                let _ = station.send(0x8A, &frame.payload);
+            }
+            Opcode::Binary => match Message::decode(&frame.payload) {
+                Some(Message::Data { stream, data }) => {
+                    let queued = station.listeners.lock().unwrap().get(&stream).map(|tx| tx.try_send(data));
+                    if let Some(Err(TrySendError::Full(_))) = queued {
+                        log::info!("[RELAY] Stream #{} fell too far behind; dropped", stream);
+                        station.drop_listener(stream);
+                        let _ = station.message(&Message::Close { stream });
+                    }
+                }
+                Some(Message::Close { stream }) => {
+                    station.drop_listener(stream);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    };
+
+    log::warn!("[RELAY] Station from {} disconnected: {}", remote, reason);
+    let mut current = relay.station.lock().unwrap();
+    if current.as_ref().is_some_and(|current| Arc::ptr_eq(current, &station)) {
+        *current = None;
+    }
+    drop(current);
+    station.listeners.lock().unwrap().clear();
+    let _ = station.uplink.lock().unwrap().shutdown(Shutdown::Both);
+}
+
+/// Ping the station until it goes away, so both sides notice a dead connection
+fn spawn_pinger(station: Weak<Station>) {
+    let _ = thread::Builder::new().name("relay-ping".into()).spawn(move || loop {
+        thread::sleep(PING_INTERVAL);
+        match station.upgrade() {
+            Some(station) if station.send(0x89, &[]).is_ok() => {}
+            _ => return,
+        }
+    });
+}
+
+/// A listener: carried to the station as a new stream, starting with the head already read
+fn listener(relay: &Relay, mut connection: TcpStream, head: Vec<u8>, remote: String) {
+    let Some(station) = relay.station.lock().unwrap().clone() else {
+        respond(&mut connection, "503 Service Unavailable", "The station is offline\n");
+        return;
+    };
+    let Ok(writing) = connection.try_clone() else { return };
+    let stream = relay.next_stream.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = crossbeam_channel::bounded(LISTENER_QUEUE);
+    station.listeners.lock().unwrap().insert(stream, tx);
+    let _ = thread::Builder::new().name("relay-write".into()).spawn(move || write_listener(writing, rx));
+
+    if station.message(&Message::Open { stream, remote }).is_err()
+        || station.message(&Message::Data { stream, data: head }).is_err()
+    {
+        station.drop_listener(stream);
+        return;
+    }
+    let mut buffer = vec![0u8; MAX_DATA];
+    loop {
+        let read = match connection.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        if station.message(&Message::Data { stream, data: buffer[..read].to_vec() }).is_err() {
+            break;
+        }
+    }
+    // Gone on this side; the station hears about it unless it closed the stream itself
+    if station.drop_listener(stream) {
+        let _ = station.message(&Message::Close { stream });
+    }
+}
+
+/// Write the station's data to a listener until the stream is dropped
+fn write_listener(mut connection: TcpStream, rx: Receiver<Vec<u8>>) {
+    for data in rx {
+        if connection.write_all(&data).is_err() {
+            break;
+        }
+    }
+    let _ = connection.shutdown(Shutdown::Both);
+}
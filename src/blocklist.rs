@@ -0,0 +1,73 @@
+//! Client blocklist
+//! Addresses and player device IDs banned from the GUI client list, refused before any route runs
+
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tiny_http::Response;
+
+use crate::config::BlocklistConfig;
+use crate::hub::BroadcastHub;
+
+/// Shown to a banned listener
+const FORBIDDEN_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>403 Forbidden</title>
+    <style>
+        body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; background: #1a1a2e; color: #eee;
+               display: flex; align-items: center; justify-content: center; min-height: 100vh; margin: 0; text-align: center; }
+        h1 { color: #e94560; }
+    </style>
+</head>
+<body>
+    <div>
+        <h1>403 Forbidden</h1>
+        <p>The host has blocked this device from listening to the stream.</p>
+    </div>
+</body>
+</html>
+"#;
+
+/// Bans in force, replaced whenever the config is saved
+pub struct Blocklist {
+    config: Mutex<BlocklistConfig>,
+    /// Hubs whose listeners are dropped when they get banned
+    hubs: Vec<Arc<BroadcastHub>>,
+}
+
+impl Blocklist {
+    pub fn new(config: &BlocklistConfig, hubs: Vec<Arc<BroadcastHub>>) -> Arc<Self> {
+        let count = config.ips.len() + config.devices.len();
+        if count > 0 {
+            log::info!("[BLOCK] {} banned address(es) / device(s)", count);
+        }
+        Arc::new(Self { config: Mutex::new(config.clone()), hubs })
+    }
+
+    /// Apply a saved blocklist and disconnect the listeners it now covers
+    pub fn set(&self, config: &BlocklistConfig) {
+        *self.config.lock().unwrap() = config.clone();
+        for hub in &self.hubs {
+            for client in hub.snapshot() {
+                let ip = client.remote_addr.map(|addr| addr.ip());
+                if config.blocks(ip, client.device.as_deref()) && hub.disconnect(client.id) {
+                    log::info!("[BLOCK] Client #{} disconnected (banned)", client.id);
+                }
+            }
+        }
+    }
+
+    pub fn is_blocked(&self, ip: Option<IpAddr>, device: Option<&str>) -> bool {
+        self.config.lock().unwrap().blocks(ip, device)
+    }
+}
+
+/// 403 page for banned listeners
+pub fn forbidden_response() -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(FORBIDDEN_PAGE)
+        .with_status_code(403)
+        .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap())
+}
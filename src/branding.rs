@@ -0,0 +1,195 @@
+//! Player page branding
+//! Station metadata, accent color and logo templated into the embedded web players
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{Config, PlayerTheme};
+use crate::station::StationInfo;
+
+const DEFAULT_ACCENT: &str = "#3498db";
+/// Logo files above this size are not served
+const MAX_LOGO_BYTES: u64 = 1024 * 1024;
+
+/// Branding shown by the web players
+#[derive(Debug, Clone)]
+pub struct PlayerBranding {
+    /// Station name, description, genre and website (current when the page is rendered)
+    pub station: StationInfo,
+    /// CSS color used for buttons, borders and highlights
+    pub accent_color: String,
+    /// Logo `src` shown above the title (None = no logo)
+    pub logo_src: Option<String>,
+    /// Local logo image served at /logo
+    pub logo_file: Option<LogoFile>,
+    /// Target buffer new listeners start with (ms)
+    pub buffer_ms: u32,
+    /// Start playing when the page opens
+    pub autoplay: bool,
+    pub theme: PlayerTheme,
+    /// Show the level/spectrum bars
+    pub visualizer: bool,
+}
+
+/// Logo image loaded from disk at startup
+#[derive(Debug, Clone)]
+pub struct LogoFile {
+    pub content_type: &'static str,
+    pub bytes: Arc<Vec<u8>>,
+}
+
+impl Default for PlayerBranding {
+    fn default() -> Self {
+        Self {
+            station: StationInfo::default(),
+            accent_color: DEFAULT_ACCENT.to_string(),
+            logo_src: None,
+            logo_file: None,
+            buffer_ms: 60,
+            autoplay: false,
+            theme: PlayerTheme::default(),
+            visualizer: true,
+        }
+    }
+}
+
+impl PlayerBranding {
+    pub fn from_config(config: &Config) -> Self {
+        let accent = config.player.accent_color.trim();
+        let accent_color = if is_css_color(accent) {
+            accent.to_string()
+        } else {
+            log::warn!("Invalid player accent color {:?}, using {}", accent, DEFAULT_ACCENT);
+            DEFAULT_ACCENT.to_string()
+        };
+
+        let logo = config.player.logo.trim();
+        let (logo_src, logo_file) = if logo.is_empty() {
+            (None, None)
+        } else if logo.starts_with("http://") || logo.starts_with("https://") {
+            (Some(logo.to_string()), None)
+        } else {
+            match load_logo(Path::new(logo)) {
+                Ok(file) => (Some("/logo".to_string()), Some(file)),
+                Err(e) => {
+                    log::warn!("Player logo not loaded ({}): {}", logo, e);
+                    (None, None)
+                }
+            }
+        };
+
+        Self {
+            station: StationInfo::from_config(config),
+            accent_color,
+            logo_src,
+            logo_file,
+            buffer_ms: config.player.buffer_ms.clamp(20, 1000),
+            autoplay: config.player.autoplay,
+            theme: config.player.theme,
+            visualizer: config.player.visualizer,
+        }
+    }
+
+    /// Copy with the station metadata replaced (renamed while running)
+    pub fn with_station(&self, station: StationInfo) -> Self {
+        Self { station, ..self.clone() }
+    }
+
+    /// Station name escaped for the player HTML
+    pub fn station_html(&self) -> String {
+        html_escape(self.station.display_name())
+    }
+
+    /// Station name as a JavaScript string literal
+    pub fn station_js(&self) -> String {
+        js_string(self.station.display_name())
+    }
+
+    /// Station description escaped for the player HTML, or `fallback`
+    pub fn subtitle_html(&self, fallback: &str) -> String {
+        match self.station.description.as_str() {
+            "" => html_escape(fallback),
+            description => html_escape(description),
+        }
+    }
+
+    /// Genre and website link under the subtitle (empty when neither is set)
+    pub fn station_meta_html(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.station.genre.is_empty() {
+            parts.push(html_escape(&self.station.genre));
+        }
+        if !self.station.website.is_empty() {
+            let url = html_escape(&self.station.website);
+            parts.push(format!(r#"<a href="{}" target="_blank" rel="noopener">{}</a>"#, url, url));
+        }
+        parts.join(" · ")
+    }
+
+    /// Lock-screen artwork URL as a JavaScript string literal (logo, else the app icon)
+    pub fn artwork_js(&self) -> String {
+        js_string(self.logo_src.as_deref().unwrap_or("/icon-512.png"))
+    }
+
+    /// Class of the player's `<body>` for the theme (`theme-auto` is resolved by the page)
+    pub fn theme_class(&self) -> &'static str {
+        match self.theme {
+            PlayerTheme::Dark => "",
+            PlayerTheme::Light => "light",
+            PlayerTheme::Auto => "theme-auto",
+        }
+    }
+
+    /// `<img>` tag for the logo, or an empty string
+    pub fn logo_html(&self) -> String {
+        match &self.logo_src {
+            Some(src) => format!(r#"<img class="logo" src="{}" alt="">"#, html_escape(src)),
+            None => String::new(),
+        }
+    }
+}
+
+fn load_logo(path: &Path) -> Result<LogoFile, Box<dyn std::error::Error>> {
+    let content_type = match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => return Err("unsupported image type".into()),
+    };
+    if std::fs::metadata(path)?.len() > MAX_LOGO_BYTES {
+        return Err("file is larger than 1MB".into());
+    }
+    Ok(LogoFile { content_type, bytes: Arc::new(std::fs::read(path)?) })
+}
+
+/// Hex colors (#rgb, #rrggbb, with optional alpha) or named colors
+fn is_css_color(value: &str) -> bool {
+    match value.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 4 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit()),
+        None => !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphabetic()),
+    }
+}
+
+/// JSON-encode a string for an inline <script> (no `</script>` breakout)
+fn js_string(text: &str) -> String {
+    serde_json::Value::from(text).to_string().replace("</", "<\\/")
+}
+
+/// Escape text for use inside HTML content and attribute values
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
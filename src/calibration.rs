@@ -0,0 +1,483 @@
+//! Latency calibration
+//! `/calibrate` asks for a known chirp, which replaces the audio for a moment right after the
+//! configured stages; the page finds it in what it plays (or hears through the microphone) and
+//! keeps the measured latency under its device ID in `calibration.json`
+
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::dsp::AudioProcessor;
+
+/// The chirp: a linear sweep over this range and time, Hann-windowed (the page builds the same)
+const CHIRP_START_HZ: f32 = 1000.0;
+const CHIRP_END_HZ: f32 = 8000.0;
+const CHIRP_SECS: f32 = 0.15;
+const CHIRP_LEVEL: f32 = 0.5;
+/// Shortest time between two chirps (every listener hears them)
+const COOLDOWN: Duration = Duration::from_secs(3);
+/// No chunk processed within this long: no audio is flowing
+const INJECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest latency accepted from a receiver (ms)
+const MAX_LATENCY_MS: f64 = 60_000.0;
+/// Receivers remembered; the longest unmeasured is forgotten first
+const MAX_RECEIVERS: usize = 256;
+
+/// Last measurement of one receiver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiverOffset {
+    /// From the chirp being processed here to it leaving the receiver's speaker (ms)
+    pub latency_ms: f64,
+    /// `microphone` (heard) or `stream` (scheduled playback plus the reported output latency)
+    pub method: String,
+    /// Unix time of the measurement (s)
+    pub measured_at: u64,
+}
+
+/// Why no chirp was sent
+#[derive(Debug)]
+pub enum ChirpError {
+    /// Another one went out moments ago
+    TooSoon,
+    /// The pipeline didn't process any audio
+    NoAudio,
+}
+
+impl std::fmt::Display for ChirpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChirpError::TooSoon => write!(f, "A chirp was just sent, try again in a few seconds"),
+            ChirpError::NoAudio => write!(f, "No audio is flowing (is the stream running?)"),
+        }
+    }
+}
+
+impl std::error::Error for ChirpError {}
+
+pub struct Calibration {
+    /// Set by a chirp request, taken by the injector with the next chunk
+    request: Mutex<Option<Sender<()>>>,
+    last_chirp: Mutex<Option<Instant>>,
+    offsets: Mutex<BTreeMap<String, ReceiverOffset>>,
+    path: Option<PathBuf>,
+}
+
+impl Calibration {
+    /// Load the saved offsets; put the returned injector in the DSP chain
+    pub fn new(sample_rate: u32, channels: u16) -> (Arc<Self>, ChirpInjector) {
+        let path = Config::data_dir().map(|dir| dir.join("calibration.json"));
+        let offsets: BTreeMap<String, ReceiverOffset> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if !offsets.is_empty() {
+            log::info!("[CALIBRATE] {} receiver offset(s) loaded", offsets.len());
+        }
+        let calibration = Arc::new(Self {
+            request: Mutex::new(None),
+            last_chirp: Mutex::new(None),
+            offsets: Mutex::new(offsets),
+            path,
+        });
+        let injector = ChirpInjector {
+            calibration: calibration.clone(),
+            chirp: chirp(sample_rate),
+            channels: channels.max(1) as usize,
+            position: None,
+        };
+        (calibration, injector)
+    }
+
+    /// Send the chirp with the next chunk; returns how long that took to come
+    pub fn chirp(&self) -> Result<Duration, ChirpError> {
+        {
+            let mut last = self.last_chirp.lock().unwrap();
+            if last.is_some_and(|at| at.elapsed() < COOLDOWN) {
+                return Err(ChirpError::TooSoon);
+            }
+            *last = Some(Instant::now());
+        }
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        *self.request.lock().unwrap() = Some(tx);
+        let asked = Instant::now();
+        match rx.recv_timeout(INJECT_TIMEOUT) {
+            Ok(()) => {
+                log::info!("[CALIBRATE] Chirp sent");
+                Ok(asked.elapsed())
+            }
+            Err(_) => {
+                self.request.lock().unwrap().take();
+                Err(ChirpError::NoAudio)
+            }
+        }
+    }
+
+    /// Measured latency of a receiver (the player's device ID), for synchronized playback
+    pub fn offset(&self, device: &str) -> Option<ReceiverOffset> {
+        self.offsets.lock().unwrap().get(device).cloned()
+    }
+
+    /// Keep a receiver's measurement, replacing its last one
+    pub fn record(&self, device: &str, latency_ms: f64, method: &str) -> Result<(), String> {
+        if device.is_empty() || device.len() > 64 || !device.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err("Invalid device ID".to_string());
+        }
+        if !(0.0..=MAX_LATENCY_MS).contains(&latency_ms) {
+            return Err(format!("latency_ms must be 0-{}", MAX_LATENCY_MS));
+        }
+        if !matches!(method, "microphone" | "stream") {
+            return Err("method must be microphone or stream".to_string());
+        }
+        let measured_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut offsets = self.offsets.lock().unwrap();
+        offsets.insert(device.to_string(), ReceiverOffset { latency_ms, method: method.to_string(), measured_at });
+        while offsets.len() > MAX_RECEIVERS {
+            let oldest = offsets.iter().min_by_key(|(_, offset)| offset.measured_at).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                offsets.remove(&oldest);
+            }
+        }
+        log::info!("[CALIBRATE] Device {}: {:.0} ms ({})", device, latency_ms, method);
+
+        let Some(path) = &self.path else { return Ok(()) };
+        let content = serde_json::to_string_pretty(&*offsets).map_err(|e| e.to_string())?;
+        drop(offsets);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, content).map_err(|e| format!("Saving {:?}: {}", path, e))
+    }
+}
+
+/// Mono chirp at the pipeline's rate
+fn chirp(sample_rate: u32) -> Vec<f32> {
+    let rate = sample_rate.max(1) as f32;
+    let len = (CHIRP_SECS * rate).round() as usize;
+    let sweep = (CHIRP_END_HZ - CHIRP_START_HZ) / CHIRP_SECS;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / rate;
+            let window = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / (len - 1) as f32).cos();
+            let phase = std::f32::consts::TAU * (CHIRP_START_HZ * t + sweep * t * t / 2.0);
+            CHIRP_LEVEL * window * phase.sin()
+        })
+        .collect()
+}
+
+/// Replaces the audio with the chirp when one is asked for
+pub struct ChirpInjector {
+    calibration: Arc<Calibration>,
+    chirp: Vec<f32>,
+    channels: usize,
+    /// Next chirp sample while one plays
+    position: Option<usize>,
+}
+
+impl AudioProcessor for ChirpInjector {
+    fn process(&mut self, samples: &mut [f32]) {
+        if self.position.is_none() {
+            // Never wait on the HTTP thread here
+            let request = self.calibration.request.try_lock().ok().and_then(|mut request| request.take());
+            if let Some(sent) = request {
+                self.position = Some(0);
+                let _ = sent.send(());
+            }
+        }
+        let Some(position) = self.position.as_mut() else { return };
+        for frame in samples.chunks_mut(self.channels) {
+            let Some(&value) = self.chirp.get(*position) else { break };
+            frame.fill(value);
+            *position += 1;
+        }
+        if *position >= self.chirp.len() {
+            self.position = None;
+        }
+    }
+}
+
+/// The calibration page with the chirp it looks for
+pub fn page() -> String {
+    let chirp = serde_json::json!({ "start_hz": CHIRP_START_HZ, "end_hz": CHIRP_END_HZ, "secs": CHIRP_SECS });
+    PAGE.replace("__CHIRP__", &chirp.to_string())
+}
+
+/// Plays the stream, asks for a chirp and finds it by cross-correlation (FFT), either in the
+/// decoded audio or in what the microphone hears
+const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>RustCast latency calibration</title>
+<style>
+    body { font-family: system-ui, sans-serif; background: #15161a; color: #e8e8ea; margin: 0; padding: 32px 16px; }
+    main { max-width: 520px; margin: 0 auto; text-align: center; }
+    h1 { font-size: 1.4em; margin-bottom: 4px; }
+    p { color: #9a9aa2; }
+    button.action { padding: 10px 18px; border-radius: 8px; border: 0; background: #4f8cff; color: #fff;
+        font: inherit; cursor: pointer; margin: 4px; }
+    button.action.quiet { background: #33343b; }
+    button:disabled { opacity: 0.5; cursor: default; }
+    #result { font-size: 2.4em; color: #e8e8ea; margin: 24px 0 4px; }
+    #status { min-height: 1.4em; }
+</style>
+</head>
+<body>
+<main>
+    <h1>Latency calibration</h1>
+    <p>Times a short chirp from the station to this device's speaker. Everyone listening hears the chirp.</p>
+    <button class="action" id="microphone">🎤 Measure with the microphone</button>
+    <button class="action quiet" id="stream">Measure the stream only</button>
+    <p id="result">-</p>
+    <p id="status"></p>
+    <p><small>The microphone hears what the speaker really plays; the stream measurement trusts the output
+        delay the browser reports. The result is kept for this device (<code id="device"></code>).</small></p>
+</main>
+<script type="module">
+    const OPUS_DECODER_URL = 'https://cdn.jsdelivr.net/npm/opus-decoder@0.7.11/+esm';
+    const CHIRP = __CHIRP__;
+    const SAMPLE_RATE = 48000;
+    const BUFFER_S = 0.15;
+    // Playback settles this long before the chirp; then we listen this long for it
+    const SETTLE_S = 2;
+    const LISTEN_S = 5;
+    const RECORDER = `registerProcessor('recorder', class extends AudioWorkletProcessor {
+        process(inputs) {
+            const input = inputs[0][0];
+            if (input) this.port.postMessage({ frame: currentFrame, data: input.slice() });
+            return true;
+        }
+    });`;
+    const $ = (id) => document.getElementById(id);
+
+    // Same key as the player, so the offset belongs to this browser's player
+    let DEVICE_ID = localStorage.getItem('rustcast_device');
+    if (!DEVICE_ID) {
+        DEVICE_ID = Math.random().toString(36).slice(2, 10) + Date.now().toString(36);
+        localStorage.setItem('rustcast_device', DEVICE_ID);
+    }
+    $('device').textContent = DEVICE_ID;
+
+    let OpusDecoder = null;
+    let ctx = null, ws = null, decoder = null, mic = null;
+    let nextTime = 0, queue = Promise.resolve();
+    // Audio blocks with their context time while we listen for the chirp
+    let recording = null;
+
+    const status = (text) => ($('status').textContent = text);
+    const show = (offset) => ($('result').textContent = offset ? `${Math.round(offset.latency_ms)} ms` : '-');
+
+    function record(time, data) {
+        if (recording && time >= recording.from) recording.blocks.push({ time, data });
+    }
+
+    function chirpTemplate() {
+        const n = Math.round(CHIRP.secs * SAMPLE_RATE);
+        const sweep = (CHIRP.end_hz - CHIRP.start_hz) / CHIRP.secs;
+        const template = new Float64Array(n);
+        for (let i = 0; i < n; i++) {
+            const t = i / SAMPLE_RATE;
+            const window = 0.5 - 0.5 * Math.cos(2 * Math.PI * i / (n - 1));
+            template[i] = window * Math.sin(2 * Math.PI * (CHIRP.start_hz * t + sweep * t * t / 2));
+        }
+        return template;
+    }
+
+    // In-place radix-2 FFT; re and im have a power-of-two length
+    function fft(re, im, inverse) {
+        const n = re.length;
+        for (let i = 1, j = 0; i < n; i++) {
+            let bit = n >> 1;
+            for (; j & bit; bit >>= 1) j ^= bit;
+            j ^= bit;
+            if (i < j) {
+                [re[i], re[j]] = [re[j], re[i]];
+                [im[i], im[j]] = [im[j], im[i]];
+            }
+        }
+        for (let len = 2; len <= n; len <<= 1) {
+            const angle = (inverse ? 2 : -2) * Math.PI / len;
+            const wr = Math.cos(angle), wi = Math.sin(angle);
+            for (let i = 0; i < n; i += len) {
+                let cr = 1, ci = 0;
+                for (let k = 0; k < len / 2; k++) {
+                    const a = i + k, b = a + len / 2;
+                    const tr = re[b] * cr - im[b] * ci, ti = re[b] * ci + im[b] * cr;
+                    re[b] = re[a] - tr; im[b] = im[a] - ti;
+                    re[a] += tr; im[a] += ti;
+                    [cr, ci] = [cr * wr - ci * wi, cr * wi + ci * wr];
+                }
+            }
+        }
+    }
+
+    // Where the chirp starts in `signal` (samples), or null when it doesn't stand out
+    function findChirp(signal, template) {
+        const lags = signal.length - template.length + 1;
+        if (lags <= 0) return null;
+        let n = 1;
+        while (n < signal.length + template.length) n <<= 1;
+        const sr = new Float64Array(n), si = new Float64Array(n);
+        const tr = new Float64Array(n), ti = new Float64Array(n);
+        sr.set(signal);
+        tr.set(template);
+        fft(sr, si, false);
+        fft(tr, ti, false);
+        // Signal times the conjugate of the template: their cross-correlation
+        for (let i = 0; i < n; i++) {
+            const re = sr[i] * tr[i] + si[i] * ti[i];
+            si[i] = si[i] * tr[i] - sr[i] * ti[i];
+            sr[i] = re;
+        }
+        fft(sr, si, true);
+        let best = 0, at = 0, energy = 0;
+        for (let i = 0; i < lags; i++) {
+            const value = Math.abs(sr[i]);
+            energy += value * value;
+            if (value > best) { best = value; at = i; }
+        }
+        return best > 6 * Math.sqrt(energy / lags) ? at : null;
+    }
+
+    // Context time of sample `index` of the joined blocks
+    function locate(blocks, index) {
+        for (const block of blocks) {
+            if (index < block.data.length) return block.time + index / SAMPLE_RATE;
+            index -= block.data.length;
+        }
+        return null;
+    }
+
+    async function play(method) {
+        status('Loading the Opus decoder...');
+        if (!OpusDecoder) ({ OpusDecoder } = await import(OPUS_DECODER_URL));
+        ctx = new AudioContext({ sampleRate: SAMPLE_RATE, latencyHint: 'interactive' });
+        await ctx.resume();
+        if (method === 'microphone') {
+            status('Waiting for the microphone...');
+            mic = await navigator.mediaDevices.getUserMedia({
+                audio: { echoCancellation: false, noiseSuppression: false, autoGainControl: false },
+            });
+            const module = URL.createObjectURL(new Blob([RECORDER], { type: 'application/javascript' }));
+            await ctx.audioWorklet.addModule(module);
+            const recorder = new AudioWorkletNode(ctx, 'recorder');
+            recorder.port.onmessage = (event) => record(event.data.frame / SAMPLE_RATE, event.data.data);
+            ctx.createMediaStreamSource(mic).connect(recorder);
+            recorder.connect(ctx.destination);
+        }
+
+        const scheme = location.protocol === 'https:' ? 'wss:' : 'ws:';
+        await new Promise((resolve, reject) => {
+            ws = new WebSocket(`${scheme}//${location.host}/ws?device=${DEVICE_ID}`);
+            ws.binaryType = 'arraybuffer';
+            ws.onmessage = async (event) => {
+                if (typeof event.data === 'string') {
+                    const message = JSON.parse(event.data);
+                    if (message.type !== 'hello') return;
+                    decoder = new OpusDecoder({ channels: message.channels, sampleRate: SAMPLE_RATE });
+                    await decoder.ready;
+                    resolve();
+                    return;
+                }
+                if (!decoder) return;
+                queue = queue.then(async () => {
+                    const decoded = await decoder.decodeFrame(new Uint8Array(event.data));
+                    if (!decoded || !decoded.samplesDecoded) return;
+                    if (nextTime < ctx.currentTime) nextTime = ctx.currentTime + BUFFER_S;
+                    const buffer = ctx.createBuffer(decoded.channelData.length, decoded.samplesDecoded, SAMPLE_RATE);
+                    decoded.channelData.forEach((data, ch) => buffer.copyToChannel(data, ch));
+                    const source = ctx.createBufferSource();
+                    source.buffer = buffer;
+                    source.connect(ctx.destination);
+                    source.start(nextTime);
+                    if (method === 'stream') record(nextTime, decoded.channelData[0]);
+                    nextTime += decoded.samplesDecoded / SAMPLE_RATE;
+                }).catch((e) => console.warn('Decode error:', e));
+            };
+            ws.onclose = () => reject(new Error('Disconnected (is the stream running with the Opus codec?)'));
+        });
+    }
+
+    async function measure(method) {
+        await play(method);
+        status('Playing...');
+        await new Promise((resolve) => setTimeout(resolve, SETTLE_S * 1000));
+
+        status('Sending the chirp...');
+        recording = { from: ctx.currentTime, blocks: [] };
+        const sent = performance.now();
+        const response = await fetch('/api/calibration/chirp', { method: 'POST' });
+        const answered = performance.now(), answeredAt = ctx.currentTime;
+        const body = await response.json();
+        if (!response.ok) throw new Error(body.error);
+        // The chirp went out while the server waited; the network took about half of the rest
+        const network = Math.max(0, answered - sent - body.waited_ms) / 2;
+        const chirpAt = answeredAt - network / 1000;
+
+        status('Listening for the chirp...');
+        await new Promise((resolve) => setTimeout(resolve, LISTEN_S * 1000));
+        const blocks = recording.blocks;
+        recording = null;
+        const signal = new Float64Array(blocks.reduce((sum, block) => sum + block.data.length, 0));
+        let offset = 0;
+        for (const block of blocks) {
+            signal.set(block.data, offset);
+            offset += block.data.length;
+        }
+        const index = findChirp(signal, chirpTemplate());
+        const heardAt = index === null ? null : locate(blocks, index);
+        if (heardAt === null) {
+            throw new Error(method === 'microphone'
+                ? 'Chirp not heard; turn the volume up or move the microphone closer'
+                : `Chirp not found in the stream (a broadcast delay over ${LISTEN_S} s hides it)`);
+        }
+        const output = method === 'stream' ? (ctx.outputLatency || ctx.baseLatency || 0) : 0;
+        const latency_ms = Math.max(0, (heardAt + output - chirpAt) * 1000);
+
+        const saved = await fetch('/api/calibration', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ device: DEVICE_ID, latency_ms, method }),
+        });
+        show({ latency_ms });
+        status(saved.ok ? `Saved for this device (${method})` : (await saved.json()).error);
+    }
+
+    function stop() {
+        recording = null;
+        const socket = ws;
+        ws = null;
+        if (socket) { socket.onclose = null; socket.close(); }
+        if (decoder) decoder.free();
+        decoder = null;
+        if (mic) mic.getTracks().forEach((track) => track.stop());
+        mic = null;
+        if (ctx) ctx.close();
+        ctx = null;
+        nextTime = 0;
+    }
+
+    for (const method of ['microphone', 'stream']) {
+        $(method).onclick = async () => {
+            document.querySelectorAll('button').forEach((button) => (button.disabled = true));
+            try {
+                await measure(method);
+            } catch (e) {
+                status(e.message);
+            } finally {
+                stop();
+                document.querySelectorAll('button').forEach((button) => (button.disabled = false));
+            }
+        };
+    }
+    fetch(`/api/calibration?device=${DEVICE_ID}`).then((r) => r.json()).then((body) => show(body.offset)).catch(() => {});
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,129 @@
+//! Network chaos for a test listener (`debug.chaos`)
+//! Delay, jitter, packet loss and a bandwidth cap on one client's stream, to exercise the player's
+//! jitter buffer and tier switching without a bad network
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::config::ChaosConfig;
+use crate::hub::{self, Packet, SinkAdapter, Subscription};
+
+/// A packet this far off its place on the audio clock means the stream stopped or stalled; the
+/// clock starts over from it
+const RESYNC: Duration = Duration::from_secs(1);
+
+/// Wraps a listener's sink and holds back, drops or throttles its audio
+pub struct ChaosSink<'a> {
+    inner: &'a mut dyn SinkAdapter,
+    config: ChaosConfig,
+    /// xorshift32 state
+    rng: u32,
+    /// When the audio clock was at zero (None until the first packet)
+    clock: Option<Instant>,
+    /// Audio handed in so far, by the packets' sample counts
+    media: Duration,
+    /// Nothing goes out before this: keeps packets in order and paces the bandwidth cap
+    next_send: Instant,
+}
+
+impl<'a> ChaosSink<'a> {
+    pub fn new(inner: &'a mut dyn SinkAdapter, config: &ChaosConfig, seed: u64) -> Self {
+        Self {
+            inner,
+            config: config.clone(),
+            rng: (seed as u32 ^ 0x9E37_79B9).max(1),
+            clock: None,
+            media: Duration::ZERO,
+            next_send: Instant::now(),
+        }
+    }
+
+    /// Uniform in 0..1
+    fn roll(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f64 / u32::MAX as f64
+    }
+
+    /// When the packet would have gone out on a clean network. Opus packets follow the audio
+    /// clock, so a backlog built up by earlier delays doesn't push later packets back further;
+    /// packets without a sample count (MP3, Vorbis) count from now.
+    fn arrival(&mut self, packet: &Packet) -> Instant {
+        let now = Instant::now();
+        if packet.samples() == 0 {
+            return now;
+        }
+        let mut at = *self.clock.get_or_insert(now) + self.media;
+        if at + RESYNC < now || at > now + RESYNC {
+            self.clock = Some(now - self.media);
+            at = now;
+        }
+        self.media += Duration::from_secs_f64(packet.samples() as f64 / 48_000.0);
+        at
+    }
+}
+
+impl SinkAdapter for ChaosSink<'_> {
+    fn preamble(&mut self) -> Vec<u8> {
+        self.inner.preamble()
+    }
+
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+        let arrival = self.arrival(packet);
+        if self.roll() * 100.0 < self.config.loss_percent as f64 {
+            return Vec::new();
+        }
+        let jitter = Duration::from_secs_f64(self.roll() * self.config.jitter_ms as f64 / 1000.0);
+        let due = (arrival + Duration::from_millis(self.config.delay_ms as u64) + jitter).max(self.next_send);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+
+        let data = self.inner.wrap(packet);
+        self.next_send = due;
+        if self.config.bandwidth_kbps > 0 {
+            self.next_send += Duration::from_secs_f64(data.len() as f64 * 8.0 / (self.config.bandwidth_kbps as f64 * 1000.0));
+        }
+        data
+    }
+
+    fn wrap_text(&mut self, text: &str) -> Option<Vec<u8>> {
+        self.inner.wrap_text(text)
+    }
+
+    fn wrap_channel(&mut self, channel: u8, packet: &[u8]) -> Option<Vec<u8>> {
+        self.inner.wrap_channel(channel, packet)
+    }
+
+    fn flush_each_packet(&self) -> bool {
+        self.inner.flush_each_packet()
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.inner.finish()
+    }
+}
+
+/// `hub::pump`, through a `ChaosSink` if `chaos` is set
+pub fn pump<W: Write + ?Sized>(
+    subscription: &Subscription,
+    adapter: &mut dyn SinkAdapter,
+    writer: &mut W,
+    chaos: Option<&ChaosConfig>,
+) -> std::io::Result<()> {
+    match chaos {
+        Some(config) => {
+            log::info!(
+                "[CHAOS] Client #{}: delay {}ms, jitter {}ms, loss {}%, bandwidth {}",
+                subscription.id,
+                config.delay_ms,
+                config.jitter_ms,
+                config.loss_percent,
+                if config.bandwidth_kbps > 0 { format!("{} kbps", config.bandwidth_kbps) } else { "unlimited".to_string() }
+            );
+            hub::pump(subscription, &mut ChaosSink::new(adapter, config, subscription.id), writer)
+        }
+        None => hub::pump(subscription, adapter, writer),
+    }
+}
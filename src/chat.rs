@@ -0,0 +1,144 @@
+//! Listener chat for the web player
+//! Messages are posted via POST /api/chat and pushed to players as WebSocket text frames
+
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::hub::BroadcastHub;
+
+/// Maximum display name length (characters)
+pub const MAX_NAME_LEN: usize = 24;
+/// Maximum message length (characters)
+pub const MAX_TEXT_LEN: usize = 300;
+/// Messages kept for newly connected players and the host GUI
+const HISTORY_LEN: usize = 50;
+
+/// A single chat message
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessage {
+    pub id: u64,
+    pub name: String,
+    pub text: String,
+    /// Unix time in seconds
+    pub timestamp: u64,
+    /// Sender address, used for moderation only (never sent to players)
+    #[serde(skip)]
+    pub ip: String,
+}
+
+/// Why a chat message was rejected
+#[derive(Debug)]
+pub enum ChatError {
+    Muted,
+    Empty,
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::Muted => write!(f, "You have been muted by the host"),
+            ChatError::Empty => write!(f, "Message is empty"),
+        }
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+/// Chat room shared by all listeners
+pub struct ChatRoom {
+    hub: Arc<BroadcastHub>,
+    history: Mutex<VecDeque<ChatMessage>>,
+    muted: Mutex<HashSet<String>>,
+    next_id: AtomicU64,
+}
+
+impl ChatRoom {
+    pub fn new(hub: Arc<BroadcastHub>) -> Self {
+        Self {
+            hub,
+            history: Mutex::new(VecDeque::with_capacity(HISTORY_LEN)),
+            muted: Mutex::new(HashSet::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Validate, store, and broadcast a message from a listener
+    pub fn post(&self, ip: &str, name: &str, text: &str) -> Result<ChatMessage, ChatError> {
+        if self.muted.lock().unwrap().contains(ip) {
+            return Err(ChatError::Muted);
+        }
+
+        let text = sanitize(text, MAX_TEXT_LEN);
+        if text.is_empty() {
+            return Err(ChatError::Empty);
+        }
+        let mut name = sanitize(name, MAX_NAME_LEN);
+        if name.is_empty() {
+            name = "Listener".to_string();
+        }
+
+        let message = ChatMessage {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            name,
+            text,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            ip: ip.to_string(),
+        };
+
+        {
+            let mut history = self.history.lock().unwrap();
+            if history.len() >= HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(message.clone());
+        }
+
+        self.hub.publish_text(&chat_event(&message));
+        Ok(message)
+    }
+
+    /// Recent messages, oldest first
+    pub fn history(&self) -> Vec<ChatMessage> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Replay recent messages to a newly connected player
+    pub fn send_history_to(&self, subscriber_id: u64) {
+        for message in self.history.lock().unwrap().iter() {
+            self.hub.send_text_to(subscriber_id, &chat_event(message));
+        }
+    }
+
+    /// Block further messages from an address
+    pub fn mute(&self, ip: &str) {
+        log::info!("[CHAT] Muted {}", ip);
+        self.muted.lock().unwrap().insert(ip.to_string());
+    }
+
+    /// Remove all messages here and on every connected player
+    pub fn clear(&self) {
+        self.history.lock().unwrap().clear();
+        self.hub.publish_text(r#"{"type":"chat_clear"}"#);
+        log::info!("[CHAT] Cleared");
+    }
+}
+
+fn chat_event(message: &ChatMessage) -> String {
+    serde_json::json!({ "type": "chat", "message": message }).to_string()
+}
+
+/// Trim, drop control characters, and cap the length
+fn sanitize(input: &str, max_chars: usize) -> String {
+    input
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(max_chars)
+        .collect()
+}
@@ -0,0 +1,112 @@
+//! Primary codec selection
+//! Which encoder feeds the main stream, switchable while the stream is running
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::config::{Codec, OpusConfig};
+use crate::encoder::Mp3Encoder;
+use crate::hub::{BroadcastHub, SinkKind};
+use crate::opus_encoder::OpusEncoder;
+
+/// Codec the encoder thread should use, shared with the GUI
+#[derive(Debug)]
+pub struct CodecSwitch {
+    codec: AtomicU8,
+}
+
+impl CodecSwitch {
+    pub fn new(codec: Codec) -> Arc<Self> {
+        Arc::new(Self { codec: AtomicU8::new(codec as u8) })
+    }
+
+    pub fn current(&self) -> Codec {
+        match self.codec.load(Ordering::Relaxed) {
+            x if x == Codec::Mp3 as u8 => Codec::Mp3,
+            _ => Codec::Opus,
+        }
+    }
+
+    /// Picked up by the encoder thread with the next captured chunk
+    pub fn set(&self, codec: Codec) {
+        if self.codec.swap(codec as u8, Ordering::Relaxed) != codec as u8 {
+            log::info!("[CODEC] Switching to {}", codec.as_str());
+        }
+    }
+}
+
+/// Encoder of the main stream
+pub enum PrimaryEncoder {
+    /// Raw Opus packets, broadcast through the main hub
+    Opus(OpusEncoder),
+    /// MP3 frames, broadcast through the MP3 hub
+    Mp3(Mp3Encoder),
+}
+
+impl PrimaryEncoder {
+    pub fn new(codec: Codec, sample_rate: u32, channels: u16, bitrate: u32, opus: &OpusConfig) -> Result<Self, String> {
+        Ok(match codec {
+            Codec::Opus => {
+                let mut encoder = OpusEncoder::new(sample_rate, channels, bitrate)?;
+                encoder.tune(opus)?;
+                Self::Opus(encoder)
+            }
+            Codec::Mp3 => Self::Mp3(Mp3Encoder::new(sample_rate, channels, bitrate)?),
+        })
+    }
+
+    pub fn codec(&self) -> Codec {
+        match self {
+            Self::Opus(_) => Codec::Opus,
+            Self::Mp3(_) => Codec::Mp3,
+        }
+    }
+
+    /// Current effort setting, for the log and /status
+    pub fn effort(&self) -> String {
+        match self {
+            Self::Opus(opus) => format!("complexity {}", opus.complexity()),
+            Self::Mp3(mp3) => format!("quality {}", mp3.quality()),
+        }
+    }
+
+    /// Spend less CPU per frame: Opus complexity down one, LAME quality one step faster.
+    /// Returns MP3 bytes flushed on the way (to publish), or None when already at the floor.
+    pub fn lower_effort(&mut self) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Self::Opus(opus) if opus.complexity() > 0 => {
+                opus.set_complexity(opus.complexity() - 1)?;
+                Ok(Some(Vec::new()))
+            }
+            Self::Mp3(mp3) if mp3.quality() < 9 => mp3.set_quality(mp3.quality() + 1).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// `lower_effort` up to `steps` times on a new encoder, so a rebuilt one keeps the steps
+    /// taken off the one it replaces (counted in steps, since Opus and LAME scales differ)
+    pub fn lower_effort_by(&mut self, steps: u32) -> Result<(), String> {
+        for _ in 0..steps {
+            // Nothing encoded yet, so an MP3 flush on the way has nothing to publish
+            if self.lower_effort()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tell players to reload and drop plain HTTP listeners, so both reconnect to the new format
+pub fn announce(codec: Codec, hub: &BroadcastHub, mp3_hub: &BroadcastHub) {
+    hub.publish_text(&serde_json::json!({ "type": "codec", "codec": codec.as_str() }).to_string());
+    for (hub, subscriber) in hub
+        .snapshot()
+        .into_iter()
+        .map(|s| (hub, s))
+        .chain(mp3_hub.snapshot().into_iter().map(|s| (mp3_hub, s)))
+    {
+        if subscriber.kind == SinkKind::Http {
+            hub.disconnect(subscriber.id);
+        }
+    }
+}
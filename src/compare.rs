@@ -0,0 +1,296 @@
+//! A/B listening page
+//! Two extra Opus encodes of the processed audio (`compare.a`, `compare.b`) sent together in
+//! one WebSocket frame, so /compare can switch between them without a gap or an offset
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::config::{CompareConfig, CompareEncode};
+use crate::encode_pool::{EncodePool, PcmEncoder, PcmFeed};
+use crate::hub::{BroadcastHub, Packet, SinkKind, Subscription};
+use crate::opus_encoder::OpusEncoder;
+
+/// The comparison encodes and the hub /compare/ws listeners subscribe to
+pub struct Compare {
+    a: CompareEncode,
+    b: CompareEncode,
+    channels: u16,
+    hub: Arc<BroadcastHub>,
+}
+
+/// Encodes every buffer twice while someone listens
+struct CompareJob {
+    a: OpusEncoder,
+    b: OpusEncoder,
+    hub: Arc<BroadcastHub>,
+}
+
+impl PcmEncoder for CompareJob {
+    fn encode(&mut self, samples: &[f32]) {
+        if self.hub.client_counts().total() == 0 {
+            return;
+        }
+        let (a, b) = match (self.a.encode_raw(samples), self.b.encode_raw(samples)) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => {
+                log::warn!("[COMPARE] {}", e);
+                return;
+            }
+        };
+        // Same frame size on both sides, so the packets pair up
+        for (a, b) in a.iter().zip(&b) {
+            self.hub.publish(Packet::with_samples(frame(a, b), a.samples()));
+        }
+    }
+}
+
+impl Compare {
+    /// Add both encoders to the pool; feed the returned `PcmFeed` with the processed audio
+    pub fn spawn(
+        pool: &EncodePool,
+        config: &CompareConfig,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<(PcmFeed, Arc<Self>), String> {
+        let hub = Arc::new(BroadcastHub::new());
+        let (a, b, job_hub) = (config.a.clone(), config.b.clone(), hub.clone());
+        let (feed, ()) = pool.add("compare", sample_rate, channels, move || {
+            let job = CompareJob {
+                a: encoder(&a, sample_rate, channels)?,
+                b: encoder(&b, sample_rate, channels)?,
+                hub: job_hub,
+            };
+            Ok((Box::new(job) as Box<dyn PcmEncoder>, ()))
+        })?;
+        log::info!(
+            "[COMPARE] A: {}kbps complexity {}, B: {}kbps complexity {}",
+            config.a.bitrate,
+            config.a.opus.complexity,
+            config.b.bitrate,
+            config.b.opus.complexity
+        );
+        Ok((feed, Arc::new(Self { a: config.a.clone(), b: config.b.clone(), channels, hub })))
+    }
+
+    pub fn hub(&self) -> &Arc<BroadcastHub> {
+        &self.hub
+    }
+
+    /// Register a /compare/ws listener and tell it what A and B are
+    pub fn subscribe(&self, remote_addr: Option<SocketAddr>) -> Subscription {
+        let subscription = self.hub.subscribe(SinkKind::WebSocket, remote_addr);
+        let hello = serde_json::json!({
+            "type": "hello",
+            "client_id": subscription.id,
+            "sample_rate": 48000,
+            "channels": self.channels,
+            "a": self.a,
+            "b": self.b,
+        });
+        self.hub.send_text_to(subscription.id, &hello.to_string());
+        subscription
+    }
+
+    /// For /status
+    pub fn info(&self) -> serde_json::Value {
+        serde_json::json!({
+            "a": self.a,
+            "b": self.b,
+            "listeners": self.hub.client_counts().total(),
+        })
+    }
+}
+
+fn encoder(config: &CompareEncode, sample_rate: u32, channels: u16) -> Result<OpusEncoder, String> {
+    let mut encoder = OpusEncoder::new(sample_rate, channels, config.bitrate.clamp(6, 510))?;
+    encoder.tune(&config.opus)?;
+    Ok(encoder)
+}
+
+/// One WebSocket frame: length of A (u16, big-endian), A, then B
+fn frame(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(2 + a.len() + b.len());
+    frame.extend_from_slice(&(a.len() as u16).to_be_bytes());
+    frame.extend_from_slice(a);
+    frame.extend_from_slice(b);
+    frame
+}
+
+/// The comparison player: both encodes decoded and scheduled at the same times, the switch only
+/// moves two gains
+pub const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>RustCast A/B</title>
+<style>
+    body { font-family: system-ui, sans-serif; background: #15161a; color: #e8e8ea; margin: 0; padding: 32px 16px; }
+    main { max-width: 520px; margin: 0 auto; text-align: center; }
+    h1 { font-size: 1.4em; margin-bottom: 4px; }
+    p { color: #9a9aa2; }
+    .sides { display: flex; gap: 12px; margin: 24px 0; }
+    .side { flex: 1; padding: 20px 12px; border-radius: 12px; border: 2px solid #33343b; background: #1d1e24;
+        color: inherit; font: inherit; cursor: pointer; }
+    .side.on { border-color: #4f8cff; background: #1f2a44; }
+    .side b { display: block; font-size: 2em; margin-bottom: 6px; }
+    .side small { color: #9a9aa2; }
+    button.action { padding: 10px 18px; border-radius: 8px; border: 0; background: #4f8cff; color: #fff;
+        font: inherit; cursor: pointer; margin: 4px; }
+    button.action.quiet { background: #33343b; }
+    #status { min-height: 1.4em; }
+</style>
+</head>
+<body>
+<main>
+    <h1>A/B comparison</h1>
+    <p>The same audio through two encoder settings, switched in place. Keys: 1 / 2 or A / B, space toggles.</p>
+    <div class="sides">
+        <button class="side on" id="sideA"><b id="nameA">A</b><small id="infoA">-</small></button>
+        <button class="side" id="sideB"><b id="nameB">B</b><small id="infoB">-</small></button>
+    </div>
+    <button class="action" id="play">▶ Play</button>
+    <button class="action quiet" id="blind">Blind test</button>
+    <p id="status"></p>
+</main>
+<script type="module">
+    const OPUS_DECODER_URL = 'https://cdn.jsdelivr.net/npm/opus-decoder@0.7.11/+esm';
+    const BUFFER_S = 0.15;
+    const FADE_S = 0.005;
+    const $ = (id) => document.getElementById(id);
+
+    let OpusDecoder = null;
+    let ctx = null, ws = null, decoders = null, gains = null;
+    let hello = null, nextTime = 0, queue = Promise.resolve();
+    // Which encode each button plays; swapped at random in a blind test
+    let order = ['a', 'b'], selected = 0, blind = false;
+    const received = { a: 0, b: 0 };
+    let countedSince = 0;
+
+    function describe(side) {
+        const s = hello[side];
+        return `${s.bitrate} kbps, complexity ${s.complexity}, ${s.signal}, bandwidth ${s.max_bandwidth}`;
+    }
+
+    function label() {
+        ['A', 'B'].forEach((name, i) => {
+            $('name' + name).textContent = blind ? ['X', 'Y'][i] : name;
+            const side = order[i];
+            const seconds = (performance.now() - countedSince) / 1000;
+            const measured = seconds > 1 ? ` (${Math.round(received[side] * 8 / seconds / 1000)} kbps measured)` : '';
+            $('info' + name).textContent = !hello ? '-' : blind ? 'hidden' : describe(side) + measured;
+            $('side' + name).classList.toggle('on', i === selected);
+        });
+    }
+
+    function select(i) {
+        selected = i;
+        if (gains && ctx) {
+            const now = ctx.currentTime;
+            for (const side of ['a', 'b']) {
+                gains[side].gain.setTargetAtTime(order[i] === side ? 1 : 0, now, FADE_S);
+            }
+        }
+        label();
+    }
+
+    function schedule(side, decoded, at) {
+        if (!decoded || !decoded.samplesDecoded) return;
+        const buffer = ctx.createBuffer(decoded.channelData.length, decoded.samplesDecoded, 48000);
+        decoded.channelData.forEach((data, ch) => buffer.copyToChannel(data, ch));
+        const source = ctx.createBufferSource();
+        source.buffer = buffer;
+        source.connect(gains[side]);
+        source.start(at);
+    }
+
+    async function handle(data) {
+        const frame = new Uint8Array(data);
+        const split = 2 + ((frame[0] << 8) | frame[1]);
+        const packets = { a: frame.subarray(2, split), b: frame.subarray(split) };
+        received.a += packets.a.length;
+        received.b += packets.b.length;
+        const [a, b] = await Promise.all([decoders.a.decodeFrame(packets.a), decoders.b.decodeFrame(packets.b)]);
+        // Both halves go out at the same time, so switching never shifts the audio
+        if (nextTime < ctx.currentTime) nextTime = ctx.currentTime + BUFFER_S;
+        schedule('a', a, nextTime);
+        schedule('b', b, nextTime);
+        nextTime += a.samplesDecoded / 48000;
+    }
+
+    async function start() {
+        $('status').textContent = 'Loading the Opus decoder...';
+        if (!OpusDecoder) ({ OpusDecoder } = await import(OPUS_DECODER_URL));
+        ctx = new AudioContext({ sampleRate: 48000 });
+        await ctx.resume();
+        gains = { a: ctx.createGain(), b: ctx.createGain() };
+        for (const side of ['a', 'b']) gains[side].connect(ctx.destination);
+        select(selected);
+
+        const scheme = location.protocol === 'https:' ? 'wss:' : 'ws:';
+        ws = new WebSocket(`${scheme}//${location.host}/compare/ws`);
+        ws.binaryType = 'arraybuffer';
+        ws.onmessage = async (event) => {
+            if (typeof event.data === 'string') {
+                const message = JSON.parse(event.data);
+                if (message.type !== 'hello') return;
+                hello = message;
+                decoders = {};
+                for (const side of ['a', 'b']) {
+                    decoders[side] = new OpusDecoder({ channels: hello.channels, sampleRate: 48000 });
+                    await decoders[side].ready;
+                }
+                received.a = received.b = 0;
+                countedSince = performance.now();
+                $('status').textContent = '🟢 Playing';
+                label();
+                return;
+            }
+            if (!decoders) return;
+            // Decoded in arrival order, one frame at a time
+            queue = queue.then(() => handle(event.data)).catch((e) => console.warn('Decode error:', e));
+        };
+        ws.onclose = () => {
+            if (ws) $('status').textContent = 'Disconnected (is compare.enabled set and the stream running?)';
+        };
+        $('play').textContent = '⏹ Stop';
+    }
+
+    function stop() {
+        const socket = ws;
+        ws = null;
+        if (socket) socket.close();
+        if (decoders) Object.values(decoders).forEach((d) => d.free());
+        decoders = null;
+        if (ctx) ctx.close();
+        ctx = null;
+        nextTime = 0;
+        $('play').textContent = '▶ Play';
+        $('status').textContent = '';
+    }
+
+    $('play').onclick = () => (ws ? stop() : start().catch((e) => ($('status').textContent = e.message)));
+    $('sideA').onclick = () => select(0);
+    $('sideB').onclick = () => select(1);
+    $('blind').onclick = () => {
+        blind = !blind;
+        if (blind) {
+            order = Math.random() < 0.5 ? ['a', 'b'] : ['b', 'a'];
+            $('blind').textContent = 'Reveal';
+        } else {
+            $('status').textContent = `X was ${order[0].toUpperCase()}, Y was ${order[1].toUpperCase()}`;
+            order = ['a', 'b'];
+            $('blind').textContent = 'Blind test';
+        }
+        select(selected);
+    };
+    document.addEventListener('keydown', (e) => {
+        if (e.key === '1' || e.key.toLowerCase() === 'a') select(0);
+        else if (e.key === '2' || e.key.toLowerCase() === 'b') select(1);
+        else if (e.key === ' ') { e.preventDefault(); select(1 - selected); }
+    });
+    setInterval(() => { if (hello && ws) label(); }, 1000);
+</script>
+</body>
+</html>
+"#;
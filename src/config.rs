@@ -3,37 +3,1388 @@
 
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     /// HTTP server port
     pub port: u16,
-    /// Audio bitrate for MP3 encoding (kbps)
+    /// What to do when `port` is already taken by another program
+    pub port_fallback: PortFallback,
+    /// Address listeners reach the server at, behind a reverse proxy or DDNS
+    /// (e.g. "https://radio.example.com", empty = the address the page was opened with)
+    pub public_url: String,
+    /// Extra ports serving the same stream (e.g. a forwarded HTTPS port for listeners outside the LAN)
+    pub listeners: Vec<ListenerConfig>,
+    /// TCP options of listener connections
+    pub socket: SocketConfig,
+    /// Audio bitrate of the main stream (kbps)
     pub bitrate: u32,
+    /// Codec of the main stream
+    pub codec: Codec,
+    /// Opus signal type and bandwidth hints
+    pub opus: OpusConfig,
     /// Auto-start streaming on launch
     pub auto_start: bool,
+    /// Start with only the tray icon (no settings window)
+    pub start_minimized: bool,
+    /// What a left click on the tray icon does
+    pub tray_click: TrayClick,
+    /// Stream in mono (downmixed right after capture); halve `bitrate` to halve the bandwidth
+    pub mono: bool,
+    /// Fade-in/out length when streaming starts or stops (ms)
+    pub fade_ms: u32,
+    /// Keep the stream mute and the Windows master mute in step, both ways
+    pub link_system_mute: bool,
+    /// Undo the Windows master volume on the loopback capture, so turning the PC down doesn't
+    /// turn the stream down
+    pub volume_compensation: VolumeCompensationConfig,
+    /// Station name (player header, directory listings, icy-name, OpusTags)
+    pub stream_name: String,
+    /// Station genre (icy-genre, OpusTags, directory listings)
+    pub stream_genre: String,
+    /// One-line station description (player subtitle, icy-description)
+    pub stream_description: String,
+    /// Station homepage (player link, icy-url)
+    pub stream_website: String,
+    /// Separate port for the control API (0 = off, /status stays on the public port)
+    pub control_port: u16,
+    /// Address the control API binds to
+    pub control_bind: String,
+    /// Control API tokens (empty = no authentication)
+    pub control_tokens: Vec<ControlToken>,
+    /// Local command port on 127.0.0.1 taking the control API as JSON lines (0 = off)
+    pub ipc_port: u16,
+    /// Allow listeners to chat from the web player
+    pub chat_enabled: bool,
+    /// How long a dropped player connection can resume its session (s, 0 = off)
+    pub session_grace_secs: u64,
+    /// Minutes of audio kept for pause/rewind in the player (0 = off)
+    pub timeshift_minutes: u32,
+    /// Raw TCP port for embedded receivers (0 = off)
+    pub embedded_port: u16,
+    /// Answer LAN searches (mDNS `_rustcast._tcp`) so receivers can list this server
+    pub mdns: bool,
+    /// Broadcast (profanity) delay in seconds, up to 30 (0 = off)
+    pub delay_secs: f32,
+    /// Where the streamed audio comes from (system output loopback by default)
+    pub capture: CaptureConfig,
+    /// Latency budget of the queues between capture, encoder and server
+    pub pipeline: PipelineConfig,
+    /// Public stream directory (Icecast YP) announcement
+    pub directory: DirectoryConfig,
+    /// Audio fingerprint now-playing fallback
+    pub fingerprint: FingerprintConfig,
+    /// Ogg Vorbis stream for players without Opus support
+    pub vorbis: VorbisConfig,
+    /// Two extra Opus encodes played side by side on /compare, for picking settings by ear
+    pub compare: CompareConfig,
+    /// Latency calibration page (/calibrate) that sends a chirp through the stream
+    pub calibration: CalibrationConfig,
+    /// Hourly loudness / true-peak log
+    pub loudness: LoudnessConfig,
+    /// Microphone mixed over the captured audio
+    pub mic: MicConfig,
+    /// Automatic gain control before encoding
+    pub agc: AgcConfig,
+    /// Processing stages between the mic mix and the fades, in order
+    pub dsp: Vec<DspStage>,
+    /// Band levels pushed to players for visualizers
+    pub spectrum: SpectrumConfig,
+    /// Second, low-bitrate encode for listeners on slow connections
+    pub low_tier: LowTierConfig,
+    /// One application sent as a second WebSocket channel next to the full mix
+    pub app_channel: AppChannelConfig,
+    /// Settings window placement and size, remembered between launches
+    pub window: WindowConfig,
+    /// Always-on-top mini status window
+    pub overlay: OverlayConfig,
+    /// Server identity and custom response headers
+    pub http: HttpConfig,
+    /// Public endpoints that can be turned off
+    pub endpoints: EndpointsConfig,
+    /// Per-IP request limits and login bans
+    pub rate_limit: RateLimitConfig,
+    /// Listeners banned from the client list, refused with a 403 page
+    pub blocklist: BlocklistConfig,
+    /// Rough location of remote listeners in the client lists
+    pub geoip: GeoIpConfig,
+    /// Outbound connection to a relay server listeners connect to (no port forwarding needed)
+    pub tunnel: TunnelConfig,
+    /// Opus over QUIC datagrams for the web player (needs a build with `--features webtransport`)
+    pub webtransport: WebTransportConfig,
+    /// Mute or stop the stream while meeting apps and the like are running
+    pub privacy: PrivacyConfig,
+    /// Server-side outputs started with the stream (recorders, ...)
+    pub sinks: Vec<SinkConfig>,
+    /// Shell commands run on stream events (ON AIR lights, notifications, ...)
+    pub hooks: Vec<HookConfig>,
+    /// Web player look (the station name is `stream_name`)
+    pub player: PlayerConfig,
+    /// Developer checks, off for normal use
+    pub debug: DebugConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             port: 3000,
+            port_fallback: PortFallback::default(),
+            public_url: String::new(),
+            listeners: Vec::new(),
+            socket: SocketConfig::default(),
             bitrate: 192,
+            codec: Codec::default(),
+            opus: OpusConfig::default(),
+            mono: false,
             auto_start: true,
+            start_minimized: false,
+            tray_click: TrayClick::default(),
+            fade_ms: 50,
+            link_system_mute: false,
+            volume_compensation: VolumeCompensationConfig::default(),
+            stream_name: "RustCast".to_string(),
+            stream_genre: String::new(),
+            stream_description: String::new(),
+            stream_website: String::new(),
+            control_port: 0,
+            control_bind: "127.0.0.1".to_string(),
+            control_tokens: Vec::new(),
+            ipc_port: 0,
+            chat_enabled: true,
+            session_grace_secs: 30,
+            timeshift_minutes: 0,
+            delay_secs: 0.0,
+            embedded_port: 0,
+            mdns: true,
+            directory: DirectoryConfig::default(),
+            fingerprint: FingerprintConfig::default(),
+            vorbis: VorbisConfig::default(),
+            compare: CompareConfig::default(),
+            calibration: CalibrationConfig::default(),
+            loudness: LoudnessConfig::default(),
+            mic: MicConfig::default(),
+            agc: AgcConfig::default(),
+            dsp: Vec::new(),
+            spectrum: SpectrumConfig::default(),
+            low_tier: LowTierConfig::default(),
+            app_channel: AppChannelConfig::default(),
+            capture: CaptureConfig::default(),
+            pipeline: PipelineConfig::default(),
+            window: WindowConfig::default(),
+            overlay: OverlayConfig::default(),
+            http: HttpConfig::default(),
+            endpoints: EndpointsConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            blocklist: BlocklistConfig::default(),
+            geoip: GeoIpConfig::default(),
+            tunnel: TunnelConfig::default(),
+            webtransport: WebTransportConfig::default(),
+            privacy: PrivacyConfig::default(),
+            sinks: Vec::new(),
+            hooks: Vec::new(),
+            player: PlayerConfig::default(),
+            debug: DebugConfig::default(),
+        }
+    }
+}
+
+/// Server-side output, also accepted by `POST /api/sinks` on the control API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    /// Ogg/Opus recording; `{time}` in `path` becomes the UTC start time
+    File { name: String, path: String },
+    /// Always-on aircheck archive in `dir`, one file per segment (UTC), pruned after
+    /// `retention_days` (0 = keep forever)
+    Archive {
+        name: String,
+        dir: String,
+        #[serde(default)]
+        segment: Segment,
+        #[serde(default)]
+        retention_days: u32,
+        /// Only record while at least one listener is connected
+        #[serde(default)]
+        only_with_listeners: bool,
+    },
+}
+
+impl SinkConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SinkConfig::File { name, .. } | SinkConfig::Archive { name, .. } => name,
+        }
+    }
+}
+
+/// Event a hook runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    StreamStarted,
+    StreamStopped,
+    /// The first listener connected
+    FirstClient,
+    /// The last listener left
+    LastClient,
+    /// Streaming failed to start or stopped on an error
+    Error,
+    /// Capture switched to the standby device
+    Failover,
+    /// Capture went back from the standby device to the main one
+    Failback,
+}
+
+impl HookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::StreamStarted => "stream_started",
+            HookEvent::StreamStopped => "stream_stopped",
+            HookEvent::FirstClient => "first_client",
+            HookEvent::LastClient => "last_client",
+            HookEvent::Error => "error",
+            HookEvent::Failover => "failover",
+            HookEvent::Failback => "failback",
+        }
+    }
+}
+
+/// Shell command run on an event (`cmd /C` on Windows, `sh -c` elsewhere), with
+/// `RUSTCAST_*` environment variables describing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+    pub on: HookEvent,
+    pub command: String,
+}
+
+/// Audio source feeding the encoder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CaptureConfig {
+    /// Everything the default output device plays (WASAPI loopback, Windows only)
+    Loopback {
+        /// Leave the "System Sounds" session (notification pings, UAC) out: every application is
+        /// captured on its own and mixed instead (Windows 10 2004 or later)
+        #[serde(default)]
+        exclude_system_sounds: bool,
+        /// Per-app include/exclude and trim of the streamed mix, keyed by executable name
+        /// (also captures per application; edited in the GUI mixer tab)
+        #[serde(default)]
+        apps: BTreeMap<String, AppMix>,
+    },
+    /// Microphone or line input; `device` matches part of the name (empty = default input)
+    Input {
+        #[serde(default)]
+        device: String,
+        /// What to do when the device disappears (USB unplugged or reset)
+        #[serde(default)]
+        on_lost: DeviceLostPolicy,
+        /// Second device kept open and switched to when `device` fails (hot standby)
+        #[serde(default)]
+        standby: Option<StandbyConfig>,
+    },
+    /// Test signal
+    Generator {
+        #[serde(default)]
+        waveform: Waveform,
+        #[serde(default = "default_frequency_hz")]
+        frequency_hz: f32,
+        #[serde(default = "default_level_db")]
+        level_db: f32,
+        #[serde(default = "default_sample_rate")]
+        sample_rate: u32,
+        #[serde(default = "default_channels")]
+        channels: u16,
+    },
+    /// WAV file played in real time, silence after the end unless `looped`
+    File {
+        path: String,
+        #[serde(default)]
+        looped: bool,
+    },
+    /// Silence, for running without audio hardware
+    Null {
+        #[serde(default = "default_sample_rate")]
+        sample_rate: u32,
+        #[serde(default = "default_channels")]
+        channels: u16,
+    },
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig::Loopback { exclude_system_sounds: false, apps: BTreeMap::new() }
+    }
+}
+
+/// One application in the streamed mix (local playback is untouched)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppMix {
+    pub include: bool,
+    /// Level trim (dB)
+    pub gain_db: f32,
+}
+
+impl Default for AppMix {
+    fn default() -> Self {
+        Self { include: true, gain_db: 0.0 }
+    }
+}
+
+impl AppMix {
+    /// Linear gain in the mix (0 when excluded)
+    pub fn gain(&self) -> f32 {
+        if self.include {
+            10f32.powf(self.gain_db / 20.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+fn default_frequency_hz() -> f32 {
+    1000.0
+}
+
+fn default_level_db() -> f32 {
+    -18.0
+}
+
+fn default_sample_rate() -> u32 {
+    48000
+}
+
+fn default_channels() -> u16 {
+    2
+}
+
+/// Signal generator waveform
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Square,
+    Noise,
+}
+
+/// Reaction to the capture device disappearing while streaming
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceLostPolicy {
+    /// Send silence until the same device is back, then reconnect to it
+    #[default]
+    Wait,
+    /// Capture the default input meanwhile, and go back to the device once it returns
+    FollowDefault,
+    /// Stop streaming with an error
+    Stop,
+}
+
+impl DeviceLostPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceLostPolicy::Wait => "wait",
+            DeviceLostPolicy::FollowDefault => "follow_default",
+            DeviceLostPolicy::Stop => "stop",
+        }
+    }
+}
+
+/// Backup input of `capture.input`, captured alongside the main device but only streamed while
+/// that one fails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StandbyConfig {
+    /// (Partial) name of the backup device; empty = default input
+    pub device: String,
+    /// Silence or no audio at all from the main device for this long switches to the backup (s)
+    pub failover_secs: u32,
+    /// Level below which the main device counts as silent (dBFS)
+    pub silence_db: f32,
+    pub failback: FailbackPolicy,
+    /// With `failback: auto`, how long the main device must deliver signal again before
+    /// capture goes back to it (s)
+    pub failback_secs: u32,
+}
+
+impl Default for StandbyConfig {
+    fn default() -> Self {
+        Self {
+            device: String::new(),
+            failover_secs: 5,
+            silence_db: -90.0,
+            failback: FailbackPolicy::Auto,
+            failback_secs: 10,
+        }
+    }
+}
+
+/// When capture goes back from the backup device to the main one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailbackPolicy {
+    /// Once the main device has delivered signal for `failback_secs`
+    #[default]
+    Auto,
+    /// Stay on the backup until streaming is restarted
+    Manual,
+}
+
+/// Reaction to the configured port being in use at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortFallback {
+    /// Offer the next free port in a dialog (headless: use it)
+    #[default]
+    Ask,
+    /// Use the next free port without asking
+    Next,
+    /// Fail to start
+    Off,
+}
+
+/// Codec of the main stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Low latency, used by the web player and every Opus-based feature
+    #[default]
+    Opus,
+    /// For players without Opus support (served as /stream.mp3)
+    Mp3,
+}
+
+impl Codec {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Codec::Opus => "opus",
+            Codec::Mp3 => "mp3",
+        }
+    }
+}
+
+/// Opus encoder hints (main stream and low tier)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpusConfig {
+    /// What the encoder is tuned for
+    pub signal: OpusSignal,
+    /// Highest audio bandwidth the encoder may use
+    pub max_bandwidth: OpusBandwidth,
+    /// Encoder complexity (0-10, lower = less CPU)
+    pub complexity: u8,
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            signal: OpusSignal::default(),
+            max_bandwidth: OpusBandwidth::default(),
+            complexity: 5,
+        }
+    }
+}
+
+/// Opus signal type hint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpusSignal {
+    /// Switch between voice and music by analysing the audio
+    #[default]
+    Auto,
+    Music,
+    Voice,
+}
+
+/// Opus audio bandwidth limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpusBandwidth {
+    /// No limit, the encoder picks from the bitrate
+    #[default]
+    Auto,
+    /// 4kHz
+    Narrowband,
+    /// 6kHz
+    Mediumband,
+    /// 8kHz
+    Wideband,
+    /// 12kHz
+    Superwideband,
+    /// 20kHz
+    Fullband,
+}
+
+/// Tray icon left-click action
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrayClick {
+    /// Open the settings window
+    #[default]
+    Settings,
+    /// Start or stop streaming
+    Toggle,
+    /// Open the player in the browser
+    Browser,
+}
+
+/// Length of one archive file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Segment {
+    Hourly,
+    #[default]
+    Daily,
+}
+
+/// What a control API token may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// View status, pause/resume streaming
+    Listener,
+    /// Everything, including moderation and server settings
+    Admin,
+}
+
+/// Bearer token for the control API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlToken {
+    /// Shown in logs instead of the token
+    #[serde(default)]
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+/// Public stream directory settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoryConfig {
+    /// Announce the stream to the directory server
+    pub enabled: bool,
+    /// YP directory endpoint (plain http only)
+    pub yp_url: String,
+    /// Publicly reachable stream URL listeners will be given
+    pub listen_url: String,
+}
+
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            yp_url: "http://dir.xiph.org/cgi-bin/yp-cgi".to_string(),
+            listen_url: String::new(),
+        }
+    }
+}
+
+/// Additional HTTP(S) port, sharing the broadcast hub with `port`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    pub port: u16,
+    /// Address to bind ("0.0.0.0" = every interface, "127.0.0.1" = this machine only)
+    pub bind: String,
+    /// PEM certificate chain; with `tls_key` this port serves HTTPS
+    pub tls_cert: String,
+    /// PEM private key
+    pub tls_key: String,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            bind: "0.0.0.0".to_string(),
+            tls_cert: String::new(),
+            tls_key: String::new(),
+        }
+    }
+}
+
+impl ListenerConfig {
+    pub fn is_tls(&self) -> bool {
+        !self.tls_cert.is_empty() || !self.tls_key.is_empty()
+    }
+}
+
+/// TCP socket options per connection type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketConfig {
+    /// Disable Nagle's algorithm so small Opus frames are sent right away
+    pub nodelay: bool,
+    /// SO_SNDBUF of player connections, WebSocket and HTTP (KB, 0 = OS default)
+    pub web_send_buffer_kb: u32,
+    /// SO_SNDBUF of embedded receivers (KB, 0 = OS default)
+    pub embedded_send_buffer_kb: u32,
+    /// Disconnect listeners whose socket accepts nothing for this long (s, 0 = never)
+    pub write_timeout_secs: u32,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            web_send_buffer_kb: 0,
+            embedded_send_buffer_kb: 0,
+            write_timeout_secs: 5,
+        }
+    }
+}
+
+/// Queue sizing between pipeline threads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// Audio each queue may hold before it drops (ms)
+    pub latency_ms: u32,
+    /// How far sustained drops may grow that budget (ms, at or below `latency_ms` = no auto-tuning)
+    pub max_latency_ms: u32,
+    /// Lower Opus complexity / MP3 quality a notch whenever the encoder thread nears real time
+    pub auto_effort: bool,
+    /// Worker threads for secondary encoders like Vorbis (0 = one per spare core, at most two)
+    pub encode_workers: u32,
+    /// Insert silence where the capture device skipped audio (up to 1s), so recordings and
+    /// listeners stay in time
+    pub fill_gaps: bool,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            latency_ms: 60,
+            max_latency_ms: 250,
+            auto_effort: true,
+            encode_workers: 0,
+            fill_gaps: false,
+        }
+    }
+}
+
+/// Settings window placement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub x: i32,
+    pub y: i32,
+    /// Client area size (the window can be resized)
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self { x: 300, y: 200, width: 400, height: 737 }
+    }
+}
+
+/// Overlay window (toggled from the tray, visibility and position are remembered)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayConfig {
+    /// Shown on launch
+    pub enabled: bool,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self { enabled: false, x: 20, y: 20 }
+    }
+}
+
+/// Ogg Vorbis stream settings (opt-in, costs a second encoder)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VorbisConfig {
+    /// Serve /stream.vorbis.ogg
+    pub enabled: bool,
+    /// Target bitrate (kbps)
+    pub bitrate: u32,
+}
+
+impl Default for VorbisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bitrate: 160,
+        }
+    }
+}
+
+/// A/B listening page (opt-in, costs two encoders while someone listens)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompareConfig {
+    /// Serve /compare
+    pub enabled: bool,
+    pub a: CompareEncode,
+    pub b: CompareEncode,
+}
+
+impl Default for CompareConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            a: CompareEncode { bitrate: 96, opus: OpusConfig { complexity: 3, ..OpusConfig::default() } },
+            b: CompareEncode { bitrate: 160, opus: OpusConfig { complexity: 10, ..OpusConfig::default() } },
+        }
+    }
+}
+
+/// Settings of one side of /compare
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompareEncode {
+    /// Target bitrate (kbps)
+    pub bitrate: u32,
+    /// `signal`, `max_bandwidth` and `complexity` as in `opus`
+    #[serde(flatten)]
+    pub opus: OpusConfig,
+}
+
+impl Default for CompareEncode {
+    fn default() -> Self {
+        Self { bitrate: 128, opus: OpusConfig::default() }
+    }
+}
+
+/// Master volume compensation (opt-in). The loopback capture is taken after the Windows volume,
+/// so the inverse gain brings the stream back to the level the applications play at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeCompensationConfig {
+    pub enabled: bool,
+    /// Most boost applied (dB); below this the stream gets quieter with the PC after all
+    pub max_gain_db: f32,
+    /// Safety limiter after the boost
+    pub limiter: LimiterConfig,
+}
+
+impl Default for VolumeCompensationConfig {
+    fn default() -> Self {
+        Self { enabled: false, max_gain_db: 24.0, limiter: LimiterConfig::default() }
+    }
+}
+
+/// Latency calibration settings (opt-in: any listener can make everyone hear a chirp)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalibrationConfig {
+    /// Serve /calibrate and its API
+    pub enabled: bool,
+}
+
+/// Web player branding and defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    /// Accent color (#rrggbb or a CSS color name)
+    pub accent_color: String,
+    /// Logo image: an http(s) URL, or a local image file served at /logo
+    pub logo: String,
+    /// Target buffer for listeners who haven't picked one (ms, 20-1000)
+    pub buffer_ms: u32,
+    /// Start playing when the page opens (browsers may hold the audio until the first tap)
+    pub autoplay: bool,
+    pub theme: PlayerTheme,
+    /// Show the level/spectrum bars
+    pub visualizer: bool,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            accent_color: "#3498db".to_string(),
+            logo: String::new(),
+            buffer_ms: 60,
+            autoplay: false,
+            theme: PlayerTheme::default(),
+            visualizer: true,
+        }
+    }
+}
+
+/// Web player color scheme
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerTheme {
+    #[default]
+    Dark,
+    Light,
+    /// Follow the device's light/dark setting
+    Auto,
+}
+
+impl PlayerTheme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlayerTheme::Dark => "dark",
+            PlayerTheme::Light => "light",
+            PlayerTheme::Auto => "auto",
+        }
+    }
+}
+
+/// Developer checks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Parse every Opus packet, Ogg page and MP3 frame before it is broadcast and count
+    /// malformed ones (/status `validation`); costs some CPU
+    pub validate_output: bool,
+    /// Delay, jitter, loss and a bandwidth cap on a test listener's stream
+    pub chaos: ChaosConfig,
+}
+
+/// Simulated bad network for designated listeners: connections opened with `?chaos=1`
+/// (e.g. the player at `/?chaos=1`) and connections from `ips`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// Listener addresses that always get the chaos
+    pub ips: Vec<IpAddr>,
+    /// Added to every audio packet (ms)
+    pub delay_ms: u32,
+    /// Random extra delay per packet, up to this much (ms); packets stay in order
+    pub jitter_ms: u32,
+    /// Audio packets dropped at random (%)
+    pub loss_percent: f32,
+    /// Link speed cap (kbps, 0 = unlimited)
+    pub bandwidth_kbps: u32,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self { enabled: false, ips: Vec::new(), delay_ms: 150, jitter_ms: 80, loss_percent: 2.0, bandwidth_kbps: 0 }
+    }
+}
+
+/// HTTP response header settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// `Server` header value (empty = no header)
+    pub server_name: String,
+    /// Extra headers added to every response (e.g. X-Robots-Tag)
+    pub headers: BTreeMap<String, String>,
+    /// Extra headers added to the player pages only (e.g. Content-Security-Policy)
+    pub page_headers: BTreeMap<String, String>,
+    /// Opus frames per Ogg page on /stream.opus (1 = lowest latency, 5-10 = less overhead for VLC/DLNA)
+    pub ogg_frames_per_page: u32,
+    /// One Ogg stream per tier and grouping shared by all HTTP listeners: each page is built
+    /// (and checksummed) once, at the cost of identical serials for everyone
+    pub shared_ogg: bool,
+    /// While the stream is stopped, send a little Opus silence this often (s, 0 = off)
+    pub keepalive_secs: u32,
+    /// Accept permessage-deflate on /ws for text frames (chat, metadata, stats); audio stays uncompressed
+    pub ws_compression: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            server_name: "RustCast".to_string(),
+            headers: BTreeMap::new(),
+            page_headers: BTreeMap::new(),
+            ogg_frames_per_page: 1,
+            shared_ogg: false,
+            keepalive_secs: 5,
+            ws_compression: true,
+        }
+    }
+}
+
+/// Public endpoint switches; turned-off routes answer 404
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointsConfig {
+    /// Low-latency player page and its PWA files (/, /manifest.json, /sw.js, icons, /logo)
+    pub player: bool,
+    /// HTML5 audio player at /legacy
+    pub legacy: bool,
+    /// WebSocket stream at /ws
+    pub ws: bool,
+    /// Ogg streams (/stream.opus, /stream.vorbis.ogg)
+    pub stream: bool,
+    /// Listener APIs (/api/*, /timeshift)
+    pub api: bool,
+    /// /status (only served publicly without a control port)
+    pub status: bool,
+}
+
+impl Default for EndpointsConfig {
+    fn default() -> Self {
+        Self {
+            player: true,
+            legacy: true,
+            ws: true,
+            stream: true,
+            api: true,
+            status: true,
+        }
+    }
+}
+
+impl EndpointsConfig {
+    /// Names of the endpoints that are turned off
+    pub fn disabled(&self) -> Vec<&'static str> {
+        [
+            ("player", self.player),
+            ("legacy", self.legacy),
+            ("ws", self.ws),
+            ("stream", self.stream),
+            ("api", self.api),
+            ("status", self.status),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| !enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// Per-IP limits for the listener APIs (/api/*) and the control API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Sustained requests per second per address
+    pub requests_per_sec: f64,
+    /// Requests an address may make in a burst
+    pub burst: u32,
+    /// Failed control API logins in a row before the address is banned (0 = never)
+    pub ban_after_failures: u32,
+    /// Length of a ban (s)
+    pub ban_secs: u32,
+    /// Never limit 127.0.0.1 / ::1 (local tools; turn off behind a local reverse proxy)
+    pub exempt_loopback: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            requests_per_sec: 5.0,
+            burst: 20,
+            ban_after_failures: 5,
+            ban_secs: 600,
+            exempt_loopback: true,
+        }
+    }
+}
+
+/// Banned listeners, kept across restarts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlocklistConfig {
+    /// Client addresses
+    pub ips: Vec<IpAddr>,
+    /// Device IDs the web player sends (`?device=`, kept in the browser's local storage)
+    pub devices: Vec<String>,
+}
+
+impl BlocklistConfig {
+    /// Whether a listener with this address and/or device ID is banned
+    pub fn blocks(&self, ip: Option<IpAddr>, device: Option<&str>) -> bool {
+        ip.is_some_and(|ip| self.ips.contains(&ip))
+            || device.is_some_and(|device| self.devices.iter().any(|banned| banned == device))
+    }
+}
+
+/// Offline IP geolocation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeoIpConfig {
+    /// MaxMind DB file with country / city records, e.g. GeoLite2-City.mmdb or
+    /// dbip-city-lite.mmdb (empty = off). LAN addresses are never looked up.
+    pub database: String,
+}
+
+/// Relay tunnel client (`rustcast-relay` on a public server)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TunnelConfig {
+    pub enabled: bool,
+    /// Relay address, e.g. "ws://relay.example.com:8000"
+    pub relay: String,
+    /// The relay's `--token`
+    pub token: String,
+}
+
+/// WebTransport endpoint for the web player (see `webtransport.rs`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebTransportConfig {
+    pub enabled: bool,
+    /// UDP port (0 = the same number as `port`)
+    pub port: u16,
+    /// PEM certificate chain and key; empty = a self-signed certificate the player pins by hash
+    pub cert: String,
+    pub key: String,
+}
+
+/// Applications that must never be heard on the stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// Executable names, case-insensitive (e.g. "zoom.exe", "ms-teams.exe")
+    pub apps: Vec<String>,
+    /// What happens while one of them is running
+    pub action: PrivacyAction,
+    /// How often the process list is checked (s)
+    pub poll_secs: u32,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self { apps: Vec::new(), action: PrivacyAction::default(), poll_secs: 2 }
+    }
+}
+
+/// Privacy pause behaviour
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyAction {
+    /// Keep listeners connected and send silence
+    #[default]
+    Mute,
+    /// Stop streaming, and start again once the application exits
+    Stop,
+}
+
+impl PrivacyAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrivacyAction::Mute => "mute",
+            PrivacyAction::Stop => "stop",
+        }
+    }
+}
+
+/// Microphone mixing (e.g. commentary over game audio)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MicConfig {
+    pub enabled: bool,
+    /// Input device name or part of it (empty = default input device)
+    pub device: String,
+    /// Mic level in the mix (dB)
+    pub gain_db: f32,
+    /// Lower the captured audio by this much while the mic is active (dB, 0 = no ducking)
+    pub duck_db: f32,
+    /// Mic level that counts as talking (dBFS)
+    pub threshold_db: f32,
+    /// When the mic is heard
+    pub mode: MicMode,
+    /// Key held to talk in `ptt` mode (F1-F24, A-Z, 0-9, Space, CapsLock, ScrollLock, Pause, Mouse4, Mouse5)
+    pub ptt_key: String,
+}
+
+impl Default for MicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: String::new(),
+            gain_db: 0.0,
+            duck_db: 0.0,
+            threshold_db: -40.0,
+            mode: MicMode::default(),
+            ptt_key: "F9".to_string(),
+        }
+    }
+}
+
+/// When the microphone is mixed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MicMode {
+    /// Always open
+    #[default]
+    Always,
+    /// Only while voice is detected (background noise stays out)
+    Vad,
+    /// Only while the push-to-talk key is held
+    Ptt,
+}
+
+/// Automatic gain control settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgcConfig {
+    pub enabled: bool,
+    /// Level the AGC steers toward (RMS dBFS)
+    pub target_db: f32,
+    /// Most gain added or removed (dB)
+    pub max_gain_db: f32,
+    /// How fast gain drops when the source gets louder (ms)
+    pub attack_ms: u32,
+    /// How fast gain recovers when the source gets quieter (ms)
+    pub release_ms: u32,
+    /// Inputs quieter than this hold the current gain (dBFS)
+    pub gate_db: f32,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_db: -18.0,
+            max_gain_db: 15.0,
+            attack_ms: 50,
+            release_ms: 3000,
+            gate_db: -50.0,
+        }
+    }
+}
+
+/// One stage of `dsp`, e.g. `{ "type": "gain", "gain_db": -3 }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DspStage {
+    /// Fixed gain
+    Gain {
+        #[serde(default)]
+        gain_db: f32,
+    },
+    /// Parametric EQ, bands applied in order
+    Eq { bands: Vec<EqBand> },
+    /// Peak limiter
+    Limiter(LimiterConfig),
+    /// Automatic gain control (settings as in `agc`, `enabled` is ignored)
+    Agc(AgcConfig),
+    /// Every channel replaced by the average of all of them (the channel count stays; `mono`
+    /// sends one channel)
+    Downmix,
+}
+
+impl DspStage {
+    /// `dsp` with an AGC added at the end when `agc.enabled` and the list has none
+    pub fn with_agc(dsp: &[DspStage], agc: &AgcConfig) -> Vec<DspStage> {
+        let mut stages = dsp.to_vec();
+        if agc.enabled && !stages.iter().any(|stage| matches!(stage, DspStage::Agc(_))) {
+            stages.push(DspStage::Agc(agc.clone()));
+        }
+        stages
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DspStage::Gain { .. } => "gain",
+            DspStage::Eq { .. } => "eq",
+            DspStage::Limiter(_) => "limiter",
+            DspStage::Agc(_) => "agc",
+            DspStage::Downmix => "downmix",
+        }
+    }
+}
+
+/// One band of an `eq` stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqBand {
+    #[serde(rename = "type")]
+    pub kind: EqKind,
+    /// Center, corner or cutoff frequency (Hz)
+    pub freq_hz: f32,
+    /// Boost or cut (dB); not used by the pass filters
+    pub gain_db: f32,
+    /// Bandwidth (peak), slope (shelves) or resonance (pass filters)
+    pub q: f32,
+}
+
+impl Default for EqBand {
+    fn default() -> Self {
+        Self { kind: EqKind::Peak, freq_hz: 1000.0, gain_db: 0.0, q: std::f32::consts::FRAC_1_SQRT_2 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EqKind {
+    #[default]
+    Peak,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+/// Settings of a `limiter` stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimiterConfig {
+    /// Highest peak let through (dBFS)
+    pub ceiling_db: f32,
+    /// How fast the gain recovers after a peak (ms)
+    pub release_ms: u32,
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        Self { ceiling_db: -1.0, release_ms: 100 }
+    }
+}
+
+/// Server-side spectrum analyzer settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumConfig {
+    pub enabled: bool,
+    /// Number of log-spaced bands (4-128)
+    pub bands: usize,
+    /// Updates per second (1-60)
+    pub rate_hz: u32,
+}
+
+impl Default for SpectrumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bands: 32,
+            rate_hz: 20,
+        }
+    }
+}
+
+/// Low-bitrate tier settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LowTierConfig {
+    pub enabled: bool,
+    /// Opus bitrate of the low tier (kbps)
+    pub bitrate: u32,
+    /// Listeners whose User-Agent contains one of these (case-insensitive) get the low tier
+    pub user_agents: Vec<String>,
+}
+
+impl Default for LowTierConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bitrate: 32,
+            user_agents: Vec::new(),
+        }
+    }
+}
+
+/// Separate application channel for the `opus-mux` WebSocket payload (opt-in, Windows)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppChannelConfig {
+    pub enabled: bool,
+    /// Executable name of the application (e.g. "spotify.exe")
+    pub app: String,
+    /// Opus bitrate of the channel (kbps)
+    pub bitrate: u32,
+}
+
+impl Default for AppChannelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            app: String::new(),
+            bitrate: 96,
+        }
+    }
+}
+
+/// Loudness logging settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoudnessConfig {
+    /// Measure the stream and append hourly values to loudness.csv in the data directory
+    pub enabled: bool,
+    /// Loudness target the stream should meet (LUFS), recorded alongside the values
+    pub target_lufs: f64,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_lufs: -23.0,
+        }
+    }
+}
+
+/// Audio fingerprint lookup settings (opt-in)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FingerprintConfig {
+    /// Identify the playing track from captured audio
+    pub enabled: bool,
+    /// Path to Chromaprint's fpcalc executable
+    pub fpcalc_path: String,
+    /// AcoustID-compatible lookup endpoint (plain http only)
+    pub lookup_url: String,
+    /// AcoustID application API key
+    pub api_key: String,
+    /// Seconds between lookups
+    pub interval_secs: u64,
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fpcalc_path: "fpcalc".to_string(),
+            lookup_url: "http://api.acoustid.org/v2/lookup".to_string(),
+            api_key: String::new(),
+            interval_secs: 60,
         }
     }
 }
 
 impl Config {
+    /// The `dsp` stages to build
+    pub fn dsp_stages(&self) -> Vec<DspStage> {
+        DspStage::with_agc(&self.dsp, &self.agc)
+    }
+
     /// Get the config file path
-    fn config_path() -> Option<PathBuf> {
-        ProjectDirs::from("com", "rustcast", "RustCast").map(|dirs| {
-            let config_dir = dirs.config_dir();
-            config_dir.join("config.json")
-        })
+    pub fn config_path() -> Option<PathBuf> {
+        Dirs::resolve().map(|dirs| dirs.config.join("config.json"))
+    }
+
+    /// Directory for logs and other data files
+    pub fn data_dir() -> Option<PathBuf> {
+        Dirs::resolve().map(|dirs| dirs.data)
+    }
+
+    /// Move files left where an earlier version kept them; call once before `load`
+    pub fn migrate() {
+        let Some(dirs) = Dirs::resolve() else { return };
+        let Some(project) = ProjectDirs::from("com", "rustcast", "RustCast") else { return };
+        if dirs.overridden {
+            // A fresh override starts from the settings of the normal location (copied, the
+            // normal location may still be used without the variable)
+            let (from, to) = (project.config_dir().join("config.json"), dirs.config.join("config.json"));
+            if from.exists() && !to.exists() {
+                let copied = fs::create_dir_all(&dirs.config).and_then(|()| fs::copy(&from, &to));
+                match copied {
+                    Ok(_) => log::info!("[CONFIG] Copied {:?} to {:?}", from, to),
+                    Err(e) => log::warn!("[CONFIG] Could not copy {:?} to {:?}: {}", from, to, e),
+                }
+            }
+            return;
+        }
+        // Data files used to go to the XDG data directory; logs and measurements are state
+        if dirs.data != project.data_dir() {
+            move_files(project.data_dir(), &dirs.data);
+        }
     }
 
     /// Load configuration from file, or create default if not exists
@@ -65,3 +1416,53 @@ impl Config {
         Ok(())
     }
 }
+
+
+/// Overrides the directory of the config file and the data files (portable installs, services)
+pub const CONFIG_DIR_ENV: &str = "RUSTCAST_CONFIG_DIR";
+
+/// Where the config file and the data files live
+struct Dirs {
+    config: PathBuf,
+    data: PathBuf,
+    /// Set by `RUSTCAST_CONFIG_DIR`
+    overridden: bool,
+}
+
+impl Dirs {
+    /// `RUSTCAST_CONFIG_DIR` for both, else the platform's: `%APPDATA%\rustcast\RustCast` on
+    /// Windows, `~/Library/Application Support/com.rustcast.RustCast` on macOS, and on Linux
+    /// `$XDG_CONFIG_HOME/rustcast` for the config and `$XDG_STATE_HOME/rustcast` for the rest
+    fn resolve() -> Option<Self> {
+        if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            let dir = PathBuf::from(dir);
+            return Some(Self { config: dir.clone(), data: dir, overridden: true });
+        }
+        let project = ProjectDirs::from("com", "rustcast", "RustCast")?;
+        let data = project.state_dir().unwrap_or(project.data_dir()).to_path_buf();
+        Some(Self { config: project.config_dir().to_path_buf(), data, overridden: false })
+    }
+}
+
+/// Move the files directly in `from` to `to` (never over an existing file), then remove `from`
+/// if that emptied it
+fn move_files(from: &Path, to: &Path) {
+    let Ok(entries) = fs::read_dir(from) else { return };
+    for entry in entries.flatten() {
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if !source.is_file() || target.exists() {
+            continue;
+        }
+        // Across filesystems a rename fails; copy and remove instead
+        let moved = fs::create_dir_all(to).and_then(|()| {
+            fs::rename(&source, &target).or_else(|_| fs::copy(&source, &target).and_then(|_| fs::remove_file(&source)))
+        });
+        match moved {
+            Ok(()) => log::info!("[CONFIG] Moved {:?} to {:?}", source, target),
+            Err(e) => log::warn!("[CONFIG] Could not move {:?} to {:?}: {}", source, target, e),
+        }
+    }
+    // Only succeeds when nothing is left
+    let _ = fs::remove_dir(from);
+}
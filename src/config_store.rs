@@ -0,0 +1,127 @@
+//! Config store
+//! The saved config, shared by the GUI, the control API and the pipeline: every change is saved
+//! and pushed to subscribers, which apply what they can while streaming
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::config::Config;
+
+/// Changes queued per subscriber; past this a subscriber that stopped reading misses changes
+/// (each carries the whole config, so the next one it reads is current)
+const QUEUE: usize = 64;
+
+/// Who changed the config (a subscriber skips its own changes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Gui,
+    /// `PATCH /api/config`
+    Api,
+    /// Port fallback before the pipeline starts
+    Startup,
+}
+
+/// One saved change
+#[derive(Debug, Clone)]
+pub struct ConfigChange {
+    pub config: Arc<Config>,
+    pub previous: Arc<Config>,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub origin: Origin,
+}
+
+/// Writing config.json failed; the change was not made
+#[derive(Debug)]
+pub struct SaveError(String);
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to save config: {}", self.0)
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+struct Subscriber {
+    tx: Sender<ConfigChange>,
+    wake: Option<Arc<dyn Fn() + Send + Sync>>,
+}
+
+pub struct ConfigStore {
+    current: RwLock<Arc<Config>>,
+    /// Held from reading the config to publishing the change, so concurrent updates don't
+    /// drop each other's fields
+    writing: Mutex<()>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl ConfigStore {
+    /// `config` as saved (not with the port picked for this run)
+    pub fn new(config: Config) -> Arc<Self> {
+        Arc::new(Self { current: RwLock::new(Arc::new(config)), writing: Mutex::new(()), subscribers: Mutex::new(Vec::new()) })
+    }
+
+    /// The config as saved now
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Change, save and publish; returns the new config
+    pub fn update(&self, origin: Origin, change: impl FnOnce(&mut Config)) -> Result<Arc<Config>, SaveError> {
+        self.try_update(origin, |config| {
+            change(config);
+            Ok(())
+        })
+    }
+
+    /// Like `update`, but `change` may refuse (validation); nothing is saved then
+    pub fn try_update<E: From<SaveError>>(
+        &self,
+        origin: Origin,
+        change: impl FnOnce(&mut Config) -> Result<(), E>,
+    ) -> Result<Arc<Config>, E> {
+        let _writing = self.writing.lock().unwrap();
+        let previous = self.get();
+        let mut config = (*previous).clone();
+        change(&mut config)?;
+        // Nothing changed (a window that didn't move): no save, no notification
+        if serde_json::to_value(&config).ok() == serde_json::to_value(&*previous).ok() {
+            return Ok(previous);
+        }
+        config.save().map_err(|e| SaveError(e.to_string()))?;
+
+        let config = Arc::new(config);
+        *self.current.write().unwrap() = config.clone();
+        self.publish(ConfigChange { config: config.clone(), previous, origin });
+        Ok(config)
+    }
+
+    /// Receive every change from now on; `wake` runs after each one is queued.
+    /// The subscription ends when the receiver is dropped.
+    pub fn subscribe(&self, wake: Option<Box<dyn Fn() + Send + Sync>>) -> Receiver<ConfigChange> {
+        let (tx, rx) = crossbeam_channel::bounded(QUEUE);
+        self.subscribers.lock().unwrap().push(Subscriber { tx, wake: wake.map(Arc::from) });
+        rx
+    }
+
+    fn publish(&self, change: ConfigChange) {
+        // Wakers run after the lock is released, like in events.rs
+        let mut wakes = Vec::new();
+        self.subscribers.lock().unwrap().retain(|sub| match sub.tx.try_send(change.clone()) {
+            Ok(()) => {
+                wakes.extend(sub.wake.clone());
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                log::warn!("[CONFIG] A subscriber is not keeping up; change dropped for it");
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+        for wake in wakes {
+            wake();
+        }
+    }
+}
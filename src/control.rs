@@ -0,0 +1,493 @@
+//! Control API on a separate (usually localhost-only) port
+//! Keeps management - status, stream toggle, chat moderation - off the public listener port
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Method, Request, Response, Server};
+
+use crate::chat::ChatRoom;
+use crate::config::{ControlToken, Role, SinkConfig};
+use crate::cue::CueMarker;
+use crate::delay::BroadcastDelay;
+use crate::geoip::GeoIp;
+use crate::drops;
+use crate::glitches;
+use crate::encoder_load;
+use crate::events;
+use crate::health;
+use crate::resources;
+use crate::hub::{BroadcastHub, SinkKind, Tier};
+use crate::loudness::LoudnessLog;
+use crate::mixer::MicMeter;
+use crate::openapi;
+use crate::rate_limit::RateLimiter;
+use crate::response::{ContentKind, ResponseHeaders};
+use crate::server::{json_response, read_json_body, status_json};
+use crate::sink::SinkRegistry;
+use crate::sockopt::{SocketKind, SocketOptions};
+use crate::spectrum::SpectrumControl;
+use crate::stats_feed::StatsFeed;
+use crate::station::{Station, StationInfo};
+use crate::test_tone::TestTone;
+use crate::tier::LowTier;
+use crate::tuning::{self, LiveTuning, PatchError};
+use crate::app_channel::AppChannel;
+use crate::validate;
+use crate::waveform::WaveformHistory;
+use crate::ws;
+
+/// State the control API can inspect and change
+pub struct ControlContext {
+    pub hub: Arc<BroadcastHub>,
+    pub chat: Arc<ChatRoom>,
+    /// Requested streaming state (picked up by the audio control thread)
+    pub should_stream: Arc<AtomicBool>,
+    pub is_streaming: Arc<AtomicBool>,
+    pub headers: ResponseHeaders,
+    /// Accepted bearer tokens (empty = every request is admin)
+    pub tokens: Vec<ControlToken>,
+    pub sinks: Arc<SinkRegistry>,
+    pub loudness: Option<Arc<LoudnessLog>>,
+    pub cues: Arc<CueMarker>,
+    pub delay: Option<Arc<BroadcastDelay>>,
+    /// Public endpoints turned off in the config (shown in /status)
+    pub disabled_endpoints: Vec<&'static str>,
+    pub spectrum: Option<Arc<SpectrumControl>>,
+    pub low_tier: Option<Arc<LowTier>>,
+    /// Application channel of `opus-mux` players (shown in /status)
+    pub app_channel: Option<Arc<AppChannel>>,
+    /// Mic voice activity and push-to-talk (None = mic off)
+    pub mic: Option<Arc<MicMeter>>,
+    /// Station metadata (renames last until restart, config.json keeps the saved values)
+    pub station: Arc<Station>,
+    /// Effective TCP options, shown per client in /api/clients
+    pub sockets: Arc<SocketOptions>,
+    /// Per-IP limits and login bans (shared with the public port)
+    pub rate_limit: Arc<RateLimiter>,
+    /// Levels of the last minute for /api/waveform
+    pub waveform: Arc<WaveformHistory>,
+    /// Client list, levels and drops for /api/stats (the GUI reads the same feed)
+    pub stats: Arc<StatsFeed>,
+    /// Location of remote listeners in /api/clients (None = `geoip.database` not set)
+    pub geoip: Option<Arc<GeoIp>>,
+    /// Bitrate and DSP stages changed live by PATCH /api/config
+    pub tuning: Arc<LiveTuning>,
+    /// POST /api/test-tone
+    pub test_tone: Arc<TestTone>,
+}
+
+/// Stats sample, or the pushed feed over WebSocket
+const STATS_PATH: &str = "/api/stats";
+/// Recent events, or every new one pushed over WebSocket
+const EVENTS_PATH: &str = "/api/events";
+/// Ping interval of an idle stats or event feed
+const FEED_PING: Duration = Duration::from_secs(15);
+
+/// Start the control server on `bind:port`
+pub fn spawn(bind: &str, port: u16, ctx: Arc<ControlContext>) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = format!("{}:{}", bind, port);
+    let server = Server::http(&addr).map_err(|e| format!("Failed to start control server: {}", e))?;
+
+    if !matches!(bind, "127.0.0.1" | "localhost" | "::1") && ctx.tokens.is_empty() {
+        log::warn!("[CONTROL] Control API is bound to {} and reachable from other machines without a token", bind);
+    }
+    log::info!("[CONTROL] Control API on http://{}", addr);
+
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let ip = request.remote_addr().map(|addr| addr.ip());
+            // Throttled or banned: refused before the token is looked at, so it's no failed login
+            if let Err(refusal) = ctx.rate_limit.check(ip) {
+                let _ = request.respond(ctx.headers.apply(refusal.response(), ContentKind::Api));
+                continue;
+            }
+            let response = match authorize(&request, &ctx.tokens) {
+                Ok(role) => {
+                    if !ctx.tokens.is_empty() {
+                        ctx.rate_limit.auth_succeeded(ip);
+                    }
+                    let url = request.url().to_string();
+                    let method = request.method().clone();
+                    // The stats and event feeds keep the connection; everything else is one response
+                    if let Some((path, key)) = feed_upgrade(&request).filter(|(path, _)| role >= required_role(&method, path)) {
+                        if path == STATS_PATH {
+                            feed_websocket(request, &key, &ctx, "stats", ctx.stats.subscribe(None), |stats| {
+                                serde_json::to_string(&**stats).unwrap_or_default()
+                            });
+                        } else {
+                            feed_websocket(request, &key, &ctx, "events", events::subscribe(None), |event| {
+                                serde_json::to_string(event).unwrap_or_default()
+                            });
+                        }
+                        continue;
+                    }
+                    let body = read_json_body(&mut request);
+                    route(&method, &url, body, &ctx, role)
+                }
+                Err(response) => {
+                    ctx.rate_limit.auth_failed(ip);
+                    response
+                }
+            };
+            let _ = request.respond(ctx.headers.apply(response, ContentKind::Api));
+        }
+    });
+
+    Ok(())
+}
+
+/// Path and `Sec-WebSocket-Key` of a WebSocket upgrade to the stats or event feed
+fn feed_upgrade(request: &Request) -> Option<(&'static str, String)> {
+    if request.method() != &Method::Get {
+        return None;
+    }
+    let path = request.url().split('?').next();
+    let path = [STATS_PATH, EVENTS_PATH].into_iter().find(|feed| path == Some(*feed))?;
+    let header = |name: &'static str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str());
+    header("Upgrade").filter(|value| value.eq_ignore_ascii_case("websocket"))?;
+    header("Sec-WebSocket-Key").map(|key| (path, key.to_string()))
+}
+
+/// Push every item of `feed` as a JSON text frame until the client goes away
+fn feed_websocket<T: Send + 'static>(
+    request: Request,
+    key: &str,
+    ctx: &ControlContext,
+    name: &'static str,
+    feed: Receiver<T>,
+    to_json: fn(&T) -> String,
+) {
+    let accept_key = ws::accept_key(key);
+    let head = ctx.headers.raw_head(
+        "101 Switching Protocols",
+        ContentKind::Api,
+        &[("Upgrade", "websocket"), ("Connection", "Upgrade"), ("Sec-WebSocket-Accept", accept_key.as_str())],
+    );
+    let result = thread::Builder::new().name(format!("control-{}", name)).spawn(move || {
+        let mut stream = request.into_writer();
+        let mut send = |frame: &[u8]| stream.write_all(frame).and_then(|_| stream.flush());
+        if send(&head).is_err() {
+            return;
+        }
+        loop {
+            let frame = match feed.recv_timeout(FEED_PING) {
+                Ok(item) => ws::text_frame(&to_json(&item)),
+                // Nothing new for a while; a ping finds out whether the client is still there
+                Err(RecvTimeoutError::Timeout) => ws::encode_frame(0x89, &[]),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if let Err(e) = send(&frame) {
+                log::debug!("[CONTROL] Feed /api/{} closed: {}", name, e);
+                return;
+            }
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("[CONTROL] Feed /api/{} not started: {}", name, e);
+    }
+}
+
+/// Role of the request's `Authorization: Bearer` token
+fn authorize(request: &Request, tokens: &[ControlToken]) -> Result<Role, Response<std::io::Cursor<Vec<u8>>>> {
+    let presented = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .map(str::trim);
+    role_of(presented, tokens).ok_or_else(|| json_response(serde_json::json!({ "error": "Missing or invalid token" }), 401))
+}
+
+/// Role a presented token grants (every caller is admin without configured tokens)
+pub fn role_of(presented: Option<&str>, tokens: &[ControlToken]) -> Option<Role> {
+    if tokens.is_empty() {
+        return Some(Role::Admin);
+    }
+    presented
+        .and_then(|p| tokens.iter().find(|t| !t.token.is_empty() && constant_time_eq(t.token.as_bytes(), p.as_bytes())))
+        .map(|token| token.role)
+}
+
+/// Compare tokens without leaking how many leading bytes matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Raw value of a query parameter
+fn query<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Lowest role allowed to call an endpoint
+fn required_role(method: &Method, path: &str) -> Role {
+    match (method, path) {
+        (Method::Get, "/status")
+        | (Method::Get, "/metrics")
+        | (Method::Get, "/api/openapi.json")
+        | (Method::Post, "/api/stream") => Role::Listener,
+        _ => Role::Admin,
+    }
+}
+
+/// Handle one API call; `body` is the parsed JSON body, if any (shared with the IPC port)
+pub fn route(
+    method: &Method,
+    url: &str,
+    body: Option<serde_json::Value>,
+    ctx: &ControlContext,
+    role: Role,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let path = url.split('?').next().unwrap_or(url);
+
+    if role < required_role(method, path) {
+        return json_response(serde_json::json!({ "error": "Not allowed for this token" }), 403);
+    }
+
+    match (method.clone(), path) {
+        (Method::Get, "/status") => {
+            let mut status = status_json(&ctx.hub);
+            status["streaming"] = ctx.is_streaming.load(Ordering::SeqCst).into();
+            if let Some(loudness) = &ctx.loudness {
+                status["loudness"] = loudness.summary();
+            }
+            if let Some(delay) = &ctx.delay {
+                status["delay"] = delay.info();
+            }
+            if let Some(low_tier) = &ctx.low_tier {
+                status["low_tier"] = low_tier.info(&ctx.hub);
+            }
+            if let Some(app_channel) = &ctx.app_channel {
+                status["app_channel"] = app_channel.info(&ctx.hub);
+            }
+            if let Some(mic) = &ctx.mic {
+                status["mic"] = mic.info();
+            }
+            status["station"] = serde_json::json!(ctx.station.get());
+            status["disabled_endpoints"] = ctx.disabled_endpoints.clone().into();
+            json_response(status, 200)
+        }
+        // Prometheus text format
+        (Method::Get, "/metrics") => {
+            let counts = ctx.hub.client_counts();
+            let mut text = drops::snapshot().metrics();
+            text.push_str(&glitches::snapshot().metrics());
+            text.push_str("# HELP rustcast_clients Connected listeners\n# TYPE rustcast_clients gauge\n");
+            for (kind, value) in [("websocket", counts.websocket), ("http", counts.http), ("tcp", counts.tcp)] {
+                text.push_str(&format!("rustcast_clients{{type=\"{}\"}} {}\n", kind, value));
+            }
+            text.push_str(&encoder_load::snapshot().metrics());
+            text.push_str(&resources::snapshot().metrics());
+            if let Some(health) = health::snapshot() {
+                text.push_str(&health.metrics());
+            }
+            text.push_str(&ctx.rate_limit.metrics());
+            text.push_str(&validate::metrics());
+            text.push_str(&events::metrics());
+            Response::from_string(text).with_header(
+                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap(),
+            )
+        }
+        // {"streaming": true|false}
+        (Method::Post, "/api/stream") => {
+            let Some(streaming) = body.and_then(|b| b["streaming"].as_bool()) else {
+                return json_response(serde_json::json!({ "error": "Expected {\"streaming\": bool}" }), 400);
+            };
+            ctx.should_stream.store(streaming, Ordering::SeqCst);
+            log::info!("[CONTROL] Streaming requested: {}", streaming);
+            json_response(serde_json::json!({ "ok": true, "streaming": streaming }), 200)
+        }
+        // {"ip": "192.168.0.10"}
+        (Method::Post, "/api/chat/mute") => {
+            let Some(ip) = body.and_then(|b| b["ip"].as_str().map(str::to_string)) else {
+                return json_response(serde_json::json!({ "error": "Expected {\"ip\": string}" }), 400);
+            };
+            ctx.chat.mute(&ip);
+            json_response(serde_json::json!({ "ok": true }), 200)
+        }
+        (Method::Post, "/api/chat/clear") => {
+            ctx.chat.clear();
+            json_response(serde_json::json!({ "ok": true }), 200)
+        }
+        (Method::Get, "/api/clients") => {
+            let clients: Vec<serde_json::Value> = ctx
+                .hub
+                .snapshot()
+                .into_iter()
+                .filter(|client| client.kind != SinkKind::Output)
+                .map(|client| {
+                    let socket = match client.kind {
+                        SinkKind::Tcp => ctx.sockets.effective(SocketKind::Embedded),
+                        _ => ctx.sockets.effective(SocketKind::Web),
+                    };
+                    serde_json::json!({
+                        "id": client.id,
+                        "kind": client.kind.as_str(),
+                        "tier": client.tier.as_str(),
+                        "mode": client.class.as_str(),
+                        "queued": client.queued,
+                        "dropped": client.dropped,
+                        "bytes_sent": client.bytes_sent,
+                        "backlog": client.backlog,
+                        "kiosk": client.kiosk,
+                        "ip": client.remote_addr.map(|addr| addr.ip()),
+                        "device": client.device,
+                        "location": client.remote_addr.and_then(|addr| Some(ctx.geoip.as_ref()?.locate(addr.ip())?.to_string())),
+                        "socket": socket,
+                    })
+                })
+                .collect();
+            json_response(serde_json::json!({ "clients": clients }), 200)
+        }
+        // One sample; with `Upgrade: websocket` every change is pushed instead (stats_websocket)
+        (Method::Get, STATS_PATH) => json_response(serde_json::json!(ctx.stats.current()), 200),
+        (Method::Get, EVENTS_PATH) => json_response(serde_json::json!({ "events": events::recent() }), 200),
+        // Schema of this API (openapi.rs, kept in step with the routes here)
+        (Method::Get, "/api/openapi.json") => json_response(openapi::control_api(), 200),
+        (Method::Get, "/api/rate-limit") => json_response(
+            serde_json::json!({ "refused": ctx.rate_limit.refused(), "limited": ctx.rate_limit.limited() }),
+            200,
+        ),
+        (Method::Delete, path) if path.starts_with("/api/rate-limit/") => {
+            match path["/api/rate-limit/".len()..].parse() {
+                Ok(ip) if ctx.rate_limit.unban(ip) => json_response(serde_json::json!({ "ok": true }), 200),
+                Ok(_) => json_response(serde_json::json!({ "error": "Address is not limited" }), 404),
+                Err(_) => json_response(serde_json::json!({ "error": "Invalid address" }), 400),
+            }
+        }
+        (Method::Get, "/api/sinks") => json_response(ctx.sinks.list(), 200),
+        // {"type": "file", "name": "rec", "path": "C:/rec/{time}.opus"}
+        (Method::Post, "/api/sinks") => {
+            let Some(config) = body.and_then(|b| serde_json::from_value::<SinkConfig>(b).ok()) else {
+                return json_response(serde_json::json!({ "error": "Invalid sink config" }), 400);
+            };
+            match ctx.sinks.add_from_config(&config) {
+                Ok(()) => json_response(serde_json::json!({ "ok": true }), 200),
+                Err(e) => json_response(serde_json::json!({ "error": e }), 409),
+            }
+        }
+        (Method::Delete, path) if path.starts_with("/api/sinks/") => {
+            if ctx.sinks.remove(&path["/api/sinks/".len()..]) {
+                json_response(serde_json::json!({ "ok": true }), 200)
+            } else {
+                json_response(serde_json::json!({ "error": "No such sink" }), 404)
+            }
+        }
+        (Method::Post, "/api/test-tone") => match ctx.test_tone.start() {
+            Ok(duration) => json_response(serde_json::json!({ "ok": true, "duration_ms": duration.as_millis() as u64 }), 200),
+            Err(e) => json_response(serde_json::json!({ "error": e.to_string() }), 409),
+        },
+        // {"label": "Interview start"} (label optional)
+        (Method::Post, "/api/cue") => {
+            let body = body.unwrap_or_default();
+            let (event, recorded) = ctx.cues.mark(body["label"].as_str().unwrap_or(""));
+            json_response(serde_json::json!({ "ok": true, "cue": event, "recordings": recorded }), 200)
+        }
+        // {"enabled": true|false}
+        (Method::Post, "/api/spectrum") => {
+            let Some(spectrum) = &ctx.spectrum else {
+                return json_response(serde_json::json!({ "error": "Spectrum analyzer is not configured" }), 409);
+            };
+            let Some(enabled) = body.and_then(|b| b["enabled"].as_bool()) else {
+                return json_response(serde_json::json!({ "error": "Expected {\"enabled\": bool}" }), 400);
+            };
+            spectrum.set_enabled(enabled);
+            json_response(serde_json::json!({ "ok": true, "enabled": enabled }), 200)
+        }
+        // {"client_id": 12, "tier": "low"|"full"}
+        (Method::Post, "/api/tier") => {
+            if ctx.low_tier.is_none() {
+                return json_response(serde_json::json!({ "error": "Low tier is not configured" }), 409);
+            }
+            let body = body.unwrap_or_default();
+            let (Some(id), Some(tier)) = (body["client_id"].as_u64(), body["tier"].as_str().and_then(|t| t.parse::<Tier>().ok())) else {
+                return json_response(serde_json::json!({ "error": "Expected {\"client_id\": number, \"tier\": \"low\"|\"full\"}" }), 400);
+            };
+            if ctx.hub.set_tier(id, tier) {
+                json_response(serde_json::json!({ "ok": true, "tier": tier.as_str() }), 200)
+            } else {
+                json_response(serde_json::json!({ "error": "No such client" }), 404)
+            }
+        }
+        // ?format=png&height=80 for a thumbnail, JSON arrays otherwise
+        (Method::Get, "/api/waveform") => match query(url, "format") {
+            Some("png") => {
+                let height = query(url, "height").and_then(|h| h.parse().ok()).unwrap_or(80);
+                Response::from_data(ctx.waveform.png(height))
+                    .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"image/png"[..]).unwrap())
+            }
+            Some("json") | None => json_response(ctx.waveform.json(), 200),
+            Some(_) => json_response(serde_json::json!({ "error": "format must be json or png" }), 400),
+        },
+        (Method::Get, "/api/station") => json_response(serde_json::json!(ctx.station.get()), 200),
+        // {"name": "...", "description": "...", "genre": "...", "website": "https://..."} (fields optional)
+        (Method::Post, "/api/station") => {
+            let Some(serde_json::Value::Object(fields)) = body else {
+                return json_response(serde_json::json!({ "error": "Expected a JSON object" }), 400);
+            };
+            let mut merged = serde_json::json!(ctx.station.get());
+            for (key, value) in fields {
+                if value.is_string() {
+                    merged[key] = value;
+                }
+            }
+            let Ok(info) = serde_json::from_value::<StationInfo>(merged) else {
+                return json_response(serde_json::json!({ "error": "Invalid station metadata" }), 400);
+            };
+            let changed = ctx.station.set(info, &ctx.hub);
+            json_response(serde_json::json!({ "ok": true, "changed": changed, "station": ctx.station.get() }), 200)
+        }
+        // {"ptt": true|false} - push-to-talk for stream decks and platforms without the hotkey
+        (Method::Post, "/api/mic") => {
+            let Some(mic) = &ctx.mic else {
+                return json_response(serde_json::json!({ "error": "Mic is not configured" }), 409);
+            };
+            let Some(held) = body.and_then(|b| b["ptt"].as_bool()) else {
+                return json_response(serde_json::json!({ "error": "Expected {\"ptt\": bool}" }), 400);
+            };
+            mic.set_ptt(held);
+            json_response(serde_json::json!({ "ok": true, "mic": mic.info() }), 200)
+        }
+        // The running bitrate / agc / dsp, and which config fields change without a restart
+        (Method::Get, "/api/config") => {
+            let mut config = serde_json::json!(ctx.tuning.settings());
+            config["live_fields"] = tuning::LIVE_FIELDS.into();
+            json_response(config, 200)
+        }
+        // {"bitrate": 128, "dsp": [...]} - any config fields, merged into config.json
+        (Method::Patch, "/api/config") => {
+            let Some(serde_json::Value::Object(patch)) = body else {
+                return json_response(serde_json::json!({ "error": "Expected a JSON object" }), 400);
+            };
+            match ctx.tuning.patch(patch) {
+                Ok(outcome) => json_response(
+                    serde_json::json!({
+                        "ok": true,
+                        "applied": outcome.applied,
+                        "restart_required": outcome.restart_required,
+                        "config": ctx.tuning.settings(),
+                    }),
+                    200,
+                ),
+                Err(PatchError::Invalid(e)) => json_response(serde_json::json!({ "error": e }), 400),
+                Err(PatchError::Dsp(errors)) => json_response(
+                    serde_json::json!({ "error": "Invalid dsp stages", "dsp_errors": errors }),
+                    400,
+                ),
+                Err(PatchError::Save(e)) => json_response(serde_json::json!({ "error": e }), 500),
+            }
+        }
+        (Method::Post, "/api/delay/dump") => match &ctx.delay {
+            Some(delay) => {
+                let dumped = delay.dump();
+                json_response(serde_json::json!({ "ok": true, "dumped_frames": dumped }), 200)
+            }
+            None => json_response(serde_json::json!({ "error": "Broadcast delay is off" }), 409),
+        },
+        _ => json_response(serde_json::json!({ "error": "Not found" }), 404),
+    }
+}
@@ -0,0 +1,42 @@
+//! Cue points
+//! Labelled markers written next to running recordings and announced to players
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::hub::BroadcastHub;
+use crate::sink::{utc_timestamp, SinkRegistry};
+
+/// Labels are cut to this many characters
+const MAX_LABEL_CHARS: usize = 100;
+
+/// Drops cue points from the control API and the GUI hotkey
+pub struct CueMarker {
+    hub: Arc<BroadcastHub>,
+    sinks: Arc<SinkRegistry>,
+}
+
+impl CueMarker {
+    pub fn new(hub: Arc<BroadcastHub>, sinks: Arc<SinkRegistry>) -> Arc<Self> {
+        Arc::new(Self { hub, sinks })
+    }
+
+    /// Record a cue in every recording and notify players.
+    /// Returns the event sent to players and the number of recordings that stored it.
+    pub fn mark(&self, label: &str) -> (serde_json::Value, usize) {
+        let label: String = match label.trim() {
+            "" => "Cue".to_string(),
+            label => label.chars().take(MAX_LABEL_CHARS).collect(),
+        };
+        let recorded = self.sinks.cue(&label);
+
+        let event = serde_json::json!({
+            "type": "cue",
+            "label": label,
+            "time_utc": utc_timestamp(SystemTime::now()),
+        });
+        self.hub.publish_text(&event.to_string());
+        log::info!("[CUE] {} ({} recordings)", label, recorded);
+        (event, recorded)
+    }
+}
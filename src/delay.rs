@@ -0,0 +1,82 @@
+//! Broadcast delay
+//! Holds encoded frames for a fixed time before they go out, with a dump to skip what is held
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::hub::Packet;
+use crate::opus_encoder::is_silent_packet;
+
+/// Longest delay that can be configured (s)
+pub const MAX_DELAY_SECS: f32 = 30.0;
+
+struct Line {
+    frames: VecDeque<Packet>,
+    /// Frames currently held back (below the target after a dump)
+    held: usize,
+}
+
+/// Fixed delay between the encoder and the hub.
+///
+/// Frames are counted rather than timed: the encoder produces them in real time, so
+/// holding N frames delays by N frame lengths. After a dump the delay is rebuilt by
+/// sending silent frames twice, so listeners never hear a gap. Ogg clients add up the
+/// samples of the packets they get, which stays continuous either way.
+pub struct BroadcastDelay {
+    line: Mutex<Line>,
+    target: usize,
+    frame_ms: u32,
+}
+
+impl BroadcastDelay {
+    pub fn new(delay_secs: f32, frame_ms: u32) -> Self {
+        let frame_ms = frame_ms.max(1);
+        let target = (delay_secs.clamp(0.0, MAX_DELAY_SECS) * 1000.0 / frame_ms as f32).round() as usize;
+        log::info!("[DELAY] Broadcast delay {:.1}s ({} frames)", delay_secs, target);
+        Self {
+            line: Mutex::new(Line {
+                frames: VecDeque::with_capacity(target + 1),
+                held: target,
+            }),
+            target,
+            frame_ms,
+        }
+    }
+
+    /// Add a frame from the encoder and return the frames to broadcast now
+    pub fn push(&self, packet: Packet) -> Vec<Packet> {
+        let mut line = self.line.lock().unwrap();
+        line.frames.push_back(packet);
+        if line.frames.len() <= line.held {
+            return Vec::new();
+        }
+
+        let released = line.frames.pop_front().expect("line is not empty");
+        // Rebuilding after a dump: repeat silence to stretch the delay back out
+        if line.held < self.target && is_silent_packet(&released) {
+            line.held += 1;
+            return vec![released.clone(), released];
+        }
+        vec![released]
+    }
+
+    /// Throw away everything held and go live; the delay rebuilds during silence
+    pub fn dump(&self) -> usize {
+        let mut line = self.line.lock().unwrap();
+        let dumped = line.frames.len();
+        line.frames.clear();
+        line.held = 0;
+        log::info!("[DELAY] Dumped {:.1}s", (dumped as u64 * self.frame_ms as u64) as f32 / 1000.0);
+        dumped
+    }
+
+    /// `{"target_secs", "current_secs"}` for /status
+    pub fn info(&self) -> serde_json::Value {
+        let line = self.line.lock().unwrap();
+        let secs = |frames: usize| (frames as u64 * self.frame_ms as u64) as f64 / 1000.0;
+        serde_json::json!({
+            "target_secs": secs(self.target),
+            "current_secs": secs(line.frames.len()),
+        })
+    }
+}
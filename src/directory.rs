@@ -0,0 +1,126 @@
+//! Public stream directory announcement (Icecast YP protocol)
+//! Registers the stream with a YP directory server and keeps the listing fresh
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::http_client;
+use crate::hub::BroadcastHub;
+use crate::links;
+use crate::station::StationInfo;
+
+/// Fallback refresh interval when the directory does not send TouchFreq
+const DEFAULT_TOUCH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Wait before retrying after a failed registration
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parsed YP directory response headers
+#[derive(Debug, Default)]
+struct YpResponse {
+    ok: bool,
+    message: String,
+    sid: Option<String>,
+    touch_freq: Option<u64>,
+}
+
+/// Start the announcement thread if enabled in config
+pub fn spawn(config: &Config, hub: Arc<BroadcastHub>) {
+    let mut directory = config.directory.clone();
+    if !directory.enabled {
+        return;
+    }
+    if directory.listen_url.is_empty() {
+        // The main stream under public_url
+        match links::public_base(&config.public_url) {
+            Some(base) => directory.listen_url = format!("{}/stream", base),
+            None => {
+                log::warn!("[YP] directory.listen_url and public_url are empty - skipping directory announcement");
+                return;
+            }
+        }
+    }
+
+    let station = StationInfo::from_config(config);
+    let name = station.display_name().to_string();
+    let description = if station.description.is_empty() { name.clone() } else { station.description.clone() };
+    let website = if station.website.is_empty() { directory.listen_url.clone() } else { station.website.clone() };
+    let genre = station.genre;
+    let bitrate = config.bitrate;
+
+    thread::spawn(move || {
+        let mut sid: Option<String> = None;
+
+        loop {
+            let result = match &sid {
+                None => yp_request(&directory.yp_url, &[
+                    ("action", "add"),
+                    ("sn", &name),
+                    ("genre", &genre),
+                    ("cpswd", ""),
+                    ("desc", &description),
+                    ("url", &website),
+                    ("listenurl", &directory.listen_url),
+                    ("type", "audio/ogg"),
+                    ("stype", "Opus"),
+                    ("b", &bitrate.to_string()),
+                ]),
+                Some(id) => yp_request(&directory.yp_url, &[
+                    ("action", "touch"),
+                    ("sid", id),
+                    ("st", ""),
+                    ("listeners", &hub.client_counts().total().to_string()),
+                ]),
+            };
+
+            let wait = match result {
+                Ok(response) if response.ok => {
+                    if let Some(new_sid) = response.sid {
+                        log::info!("[YP] Listed on directory (sid={})", new_sid);
+                        sid = Some(new_sid);
+                    }
+                    response
+                        .touch_freq
+                        .map(Duration::from_secs)
+                        .unwrap_or(DEFAULT_TOUCH_INTERVAL)
+                }
+                Ok(response) => {
+                    log::warn!("[YP] Directory rejected request: {}", response.message);
+                    // Listing expired or was never created - register again
+                    sid = None;
+                    RETRY_INTERVAL
+                }
+                Err(e) => {
+                    log::warn!("[YP] Directory request failed: {}", e);
+                    RETRY_INTERVAL
+                }
+            };
+
+            thread::sleep(wait);
+        }
+    });
+}
+
+/// Send a form-encoded POST to the YP directory and parse its response headers
+fn yp_request(url: &str, fields: &[(&str, &str)]) -> Result<YpResponse, Box<dyn std::error::Error>> {
+    let response = http_client::post_form(url, fields)?;
+    Ok(parse_yp_response(&response.headers))
+}
+
+fn parse_yp_response(headers: &[(String, String)]) -> YpResponse {
+    let mut result = YpResponse::default();
+
+    for (key, value) in headers {
+        match key.as_str() {
+            "ypresponse" => result.ok = value == "1",
+            "ypmessage" => result.message = value.clone(),
+            "sid" => result.sid = Some(value.clone()),
+            "touchfreq" => result.touch_freq = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    result
+}
@@ -0,0 +1,70 @@
+//! Frame drop accounting
+//! Counts what each channel boundary of the pipeline threw away, for /status, /metrics and the GUI
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CAPTURE: AtomicU64 = AtomicU64::new(0);
+static ENCODE: AtomicU64 = AtomicU64::new(0);
+static CLIENT: AtomicU64 = AtomicU64::new(0);
+
+/// Channel boundary where something was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Capture → encoder thread (captured buffers)
+    Capture,
+    /// Encoder → server (encoded packets)
+    Encode,
+    /// Hub → subscriber queues (packets, summed over every client and output)
+    Client,
+}
+
+impl Stage {
+    fn counter(self) -> &'static AtomicU64 {
+        match self {
+            Stage::Capture => &CAPTURE,
+            Stage::Encode => &ENCODE,
+            Stage::Client => &CLIENT,
+        }
+    }
+}
+
+/// Count a drop at a stage
+pub fn record(stage: Stage) {
+    stage.counter().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Drops per stage since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DropCounts {
+    pub capture: u64,
+    pub encode: u64,
+    pub client: u64,
+}
+
+impl DropCounts {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn total(&self) -> u64 {
+        self.capture + self.encode + self.client
+    }
+
+    /// Prometheus text exposition of the counters
+    pub fn metrics(&self) -> String {
+        let mut out = String::from(
+            "# HELP rustcast_dropped_total Buffers or packets dropped at a pipeline stage\n\
+             # TYPE rustcast_dropped_total counter\n",
+        );
+        for (stage, value) in [("capture", self.capture), ("encode", self.encode), ("client", self.client)] {
+            out.push_str(&format!("rustcast_dropped_total{{stage=\"{}\"}} {}\n", stage, value));
+        }
+        out
+    }
+}
+
+pub fn snapshot() -> DropCounts {
+    DropCounts {
+        capture: CAPTURE.load(Ordering::Relaxed),
+        encode: ENCODE.load(Ordering::Relaxed),
+        client: CLIENT.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,473 @@
+//! Audio processing between capture and encoding
+//! Processors run in order on interleaved f32 samples inside the encoder thread; the stages
+//! listed in `config.dsp` are built by `ChainBuilder`
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::agc::{Agc, AgcMeter};
+use crate::config::{AgcConfig, DspStage, EqBand, EqKind, LimiterConfig};
+
+/// Problems found in `config.dsp` when the chain was built, for the GUI and /status
+static CONFIG_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A single processing stage
+pub trait AudioProcessor: Send {
+    /// Process interleaved samples in place
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// Ordered list of processors
+#[derive(Default)]
+pub struct DspChain {
+    processors: Vec<Box<dyn AudioProcessor>>,
+}
+
+impl DspChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a processor to the end of the chain
+    pub fn push(&mut self, processor: Box<dyn AudioProcessor>) {
+        self.processors.push(processor);
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for processor in self.processors.iter_mut() {
+            processor.process(samples);
+        }
+    }
+}
+
+/// A chain runs as one stage of another (the configured stages inside the fixed ones)
+impl AudioProcessor for DspChain {
+    fn process(&mut self, samples: &mut [f32]) {
+        DspChain::process(self, samples);
+    }
+}
+
+/// Builds the `config.dsp` stages, in the configured order
+pub struct ChainBuilder {
+    sample_rate: u32,
+    channels: u16,
+}
+
+/// What `ChainBuilder::build` made
+pub struct BuiltChain {
+    pub chain: DspChain,
+    /// Meter of the AGC stage, if there is one
+    pub agc: Option<Arc<AgcMeter>>,
+}
+
+impl ChainBuilder {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self { sample_rate, channels }
+    }
+
+    /// Every problem in `stages`, each naming its stage ("dsp[1] (eq): ...")
+    pub fn validate(&self, stages: &[DspStage]) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut agc_seen = false;
+        for (index, stage) in stages.iter().enumerate() {
+            let mut problems = self.check(stage);
+            if matches!(stage, DspStage::Agc(_)) && std::mem::replace(&mut agc_seen, true) {
+                problems.push("only one agc stage is allowed".to_string());
+            }
+            errors.extend(problems.into_iter().map(|problem| format!("dsp[{}] ({}): {}", index, stage.as_str(), problem)));
+        }
+        errors
+    }
+
+    fn check(&self, stage: &DspStage) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut range = |name: &str, value: f32, min: f32, max: f32| {
+            if !(min..=max).contains(&value) {
+                problems.push(format!("{} {} is outside {}..{}", name, value, min, max));
+            }
+        };
+        match stage {
+            DspStage::Gain { gain_db } => range("gain_db", *gain_db, -60.0, 24.0),
+            DspStage::Eq { bands } => {
+                let nyquist = self.sample_rate as f32 / 2.0;
+                for (i, band) in bands.iter().enumerate() {
+                    range(&format!("bands[{}].freq_hz", i), band.freq_hz, 10.0, nyquist * 0.95);
+                    range(&format!("bands[{}].q", i), band.q, 0.1, 20.0);
+                    range(&format!("bands[{}].gain_db", i), band.gain_db, -24.0, 24.0);
+                }
+                if bands.is_empty() {
+                    problems.push("no bands".to_string());
+                }
+            }
+            DspStage::Limiter(limiter) => {
+                range("ceiling_db", limiter.ceiling_db, -30.0, 0.0);
+                range("release_ms", limiter.release_ms as f32, 1.0, 5000.0);
+            }
+            DspStage::Agc(agc) => {
+                range("target_db", agc.target_db, -60.0, 0.0);
+                range("max_gain_db", agc.max_gain_db, 0.0, 40.0);
+                range("attack_ms", agc.attack_ms as f32, 1.0, 10_000.0);
+                range("release_ms", agc.release_ms as f32, 1.0, 60_000.0);
+            }
+            DspStage::Downmix => {}
+        }
+        problems
+    }
+
+    /// Instantiate `stages` in order. Stages with errors are left out and the errors kept for
+    /// `config_errors()`, so a typo in the config doesn't keep the stream from starting.
+    pub fn build(&self, stages: &[DspStage]) -> BuiltChain {
+        self.build_with_meter(stages, None)
+    }
+
+    /// `build`, with an AGC stage reporting to `meter` instead of a new one
+    pub fn build_with_meter(&self, stages: &[DspStage], meter: Option<Arc<AgcMeter>>) -> BuiltChain {
+        let errors = self.validate(stages);
+        for error in &errors {
+            log::error!("[DSP] {}, stage skipped", error);
+        }
+        let broken: Vec<usize> = stages
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| errors.iter().any(|error| error.starts_with(&format!("dsp[{}] ", index))))
+            .map(|(index, _)| index)
+            .collect();
+        *CONFIG_ERRORS.lock().unwrap() = errors;
+
+        let mut built = BuiltChain { chain: DspChain::new(), agc: None };
+        let mut names = Vec::new();
+        for (index, stage) in stages.iter().enumerate() {
+            if broken.contains(&index) {
+                continue;
+            }
+            let processor: Box<dyn AudioProcessor> = match stage {
+                DspStage::Gain { gain_db } => Box::new(Gain::new(*gain_db)),
+                DspStage::Eq { bands } => Box::new(Equalizer::new(bands, self.sample_rate, self.channels)),
+                DspStage::Limiter(limiter) => Box::new(Limiter::new(limiter, self.sample_rate, self.channels)),
+                DspStage::Agc(config) => {
+                    let config = AgcConfig { enabled: true, ..config.clone() };
+                    let (agc, meter) = match &meter {
+                        Some(meter) => (Agc::with_meter(&config, self.sample_rate, self.channels, meter.clone()), meter.clone()),
+                        None => Agc::new(&config, self.sample_rate, self.channels),
+                    };
+                    built.agc = Some(meter);
+                    Box::new(agc)
+                }
+                DspStage::Downmix => Box::new(Downmix { channels: self.channels.max(1) as usize }),
+            };
+            built.chain.push(processor);
+            names.push(stage.as_str());
+        }
+        if !names.is_empty() {
+            log::info!("[DSP] {}", names.join(" -> "));
+        }
+        built
+    }
+}
+
+/// Problems found in `config.dsp` at startup (those stages were skipped)
+pub fn config_errors() -> Vec<String> {
+    CONFIG_ERRORS.lock().unwrap().clone()
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Fixed gain (`gain` stage)
+pub struct Gain {
+    gain: f32,
+}
+
+impl Gain {
+    pub fn new(gain_db: f32) -> Self {
+        Self { gain: db_to_linear(gain_db) }
+    }
+}
+
+impl AudioProcessor for Gain {
+    fn process(&mut self, samples: &mut [f32]) {
+        samples.iter_mut().for_each(|s| *s *= self.gain);
+    }
+}
+
+/// Second-order filter (RBJ audio EQ cookbook), transposed direct form II per channel
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    /// Two state values per channel
+    state: Vec<[f64; 2]>,
+}
+
+impl Biquad {
+    fn new(band: &EqBand, sample_rate: u32, channels: usize) -> Self {
+        let w0 = 2.0 * PI * band.freq_hz as f64 / sample_rate.max(1) as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.01) as f64);
+        let a = 10f64.powf(band.gain_db as f64 / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            EqKind::Peak => (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a),
+            EqKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            EqKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+            EqKind::LowPass => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            EqKind::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+        };
+        Self { b: [b0 / a0, b1 / a0, b2 / a0], a: [a1 / a0, a2 / a0], state: vec![[0.0; 2]; channels] }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.state.len();
+        for frame in samples.chunks_mut(channels) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let x = *sample as f64;
+                let y = self.b[0] * x + state[0];
+                state[0] = self.b[1] * x - self.a[0] * y + state[1];
+                state[1] = self.b[2] * x - self.a[1] * y;
+                *sample = y as f32;
+            }
+        }
+    }
+}
+
+/// Parametric EQ (`eq` stage)
+pub struct Equalizer {
+    bands: Vec<Biquad>,
+}
+
+impl Equalizer {
+    pub fn new(bands: &[EqBand], sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self { bands: bands.iter().map(|band| Biquad::new(band, sample_rate, channels)).collect() }
+    }
+}
+
+impl AudioProcessor for Equalizer {
+    fn process(&mut self, samples: &mut [f32]) {
+        for band in self.bands.iter_mut() {
+            band.process(samples);
+        }
+    }
+}
+
+/// Peak limiter (`limiter` stage): gain drops at once to keep every frame under the ceiling
+/// and recovers over `release_ms`
+pub struct Limiter {
+    channels: usize,
+    ceiling: f32,
+    /// One-pole recovery coefficient per frame
+    release: f32,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(config: &LimiterConfig, sample_rate: u32, channels: u16) -> Self {
+        let frames = (config.release_ms.max(1) as f32 / 1000.0 * sample_rate as f32).max(1.0);
+        Self {
+            channels: channels.max(1) as usize,
+            ceiling: db_to_linear(config.ceiling_db),
+            release: 1.0 - (-1.0 / frames).exp(),
+            gain: 1.0,
+        }
+    }
+}
+
+impl AudioProcessor for Limiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            let peak = frame.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+            let wanted = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+            if wanted < self.gain {
+                self.gain = wanted;
+            } else {
+                self.gain += self.release * (wanted - self.gain);
+            }
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+    }
+}
+
+/// Same signal on every channel (`downmix` stage)
+pub struct Downmix {
+    channels: usize,
+}
+
+impl AudioProcessor for Downmix {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            let mean = frame.iter().sum::<f32>() / frame.len() as f32;
+            frame.iter_mut().for_each(|s| *s = mean);
+        }
+    }
+}
+
+/// Shared handle that tells a `GainRamp` where to go
+#[derive(Debug)]
+pub struct FadeControl {
+    /// Target gain (f32 bits)
+    target: AtomicU32,
+    /// Ramp length in milliseconds
+    ramp_ms: u32,
+    /// Silence the stream without stopping it (overlay mute button)
+    muted: AtomicBool,
+    /// Silenced while a privacy app runs; kept apart so the overlay can't unmute it
+    privacy_muted: AtomicBool,
+}
+
+impl FadeControl {
+    /// Start silent; call `fade_in` once audio flows
+    pub fn new(ramp_ms: u32) -> Arc<Self> {
+        Arc::new(Self {
+            target: AtomicU32::new(0f32.to_bits()),
+            ramp_ms,
+            muted: AtomicBool::new(false),
+            privacy_muted: AtomicBool::new(false),
+        })
+    }
+
+    pub fn fade_in(&self) {
+        self.target.store(1f32.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn fade_out(&self) {
+        self.target.store(0f32.to_bits(), Ordering::SeqCst);
+    }
+
+    /// Time a full fade takes
+    pub fn ramp_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.ramp_ms as u64)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::SeqCst)
+    }
+
+    /// Fade to silence and back, independent of fade_in/fade_out
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::SeqCst);
+        log::info!("Stream {}", if muted { "muted" } else { "unmuted" });
+    }
+
+    pub fn is_privacy_muted(&self) -> bool {
+        self.privacy_muted.load(Ordering::SeqCst)
+    }
+
+    /// Silence for the privacy watcher, on top of the overlay mute
+    pub fn set_privacy_muted(&self, muted: bool) {
+        self.privacy_muted.store(muted, Ordering::SeqCst);
+    }
+
+    fn target(&self) -> f32 {
+        if self.is_muted() || self.is_privacy_muted() {
+            return 0.0;
+        }
+        f32::from_bits(self.target.load(Ordering::SeqCst))
+    }
+}
+
+/// Linear gain ramp toward the `FadeControl` target, avoids clicks on transitions
+pub struct GainRamp {
+    control: Arc<FadeControl>,
+    channels: usize,
+    /// Gain change per sample frame
+    step: f32,
+    gain: f32,
+}
+
+impl GainRamp {
+    pub fn new(control: Arc<FadeControl>, sample_rate: u32, channels: u16) -> Self {
+        let ramp_frames = (sample_rate as u64 * control.ramp_ms as u64 / 1000).max(1);
+        Self {
+            channels: channels.max(1) as usize,
+            step: 1.0 / ramp_frames as f32,
+            gain: control.target(),
+            control,
+        }
+    }
+}
+
+impl AudioProcessor for GainRamp {
+    fn process(&mut self, samples: &mut [f32]) {
+        let target = self.control.target();
+
+        // Steady state: nothing to ramp
+        if self.gain == target {
+            if target < 1.0 {
+                samples.iter_mut().for_each(|s| *s *= target);
+            }
+            return;
+        }
+
+        for frame in samples.chunks_mut(self.channels) {
+            if self.gain < target {
+                self.gain = (self.gain + self.step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - self.step).max(target);
+            }
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+    }
+}
+
+/// Peak level read by meters (dBFS)
+#[derive(Debug)]
+pub struct PeakLevel {
+    peak_db: AtomicU32,
+}
+
+impl PeakLevel {
+    pub fn db(&self) -> f32 {
+        f32::from_bits(self.peak_db.load(Ordering::Relaxed))
+    }
+}
+
+/// Peak meter with a falling needle; measures only, leaves the samples alone
+pub struct PeakMeter {
+    level: Arc<PeakLevel>,
+    /// Multiplier applied to the held peak per sample (about 20 dB/s)
+    fall: f32,
+    peak: f32,
+}
+
+impl PeakMeter {
+    /// Lowest level reported (digital silence)
+    pub const FLOOR_DB: f32 = -96.0;
+
+    pub fn new(sample_rate: u32, channels: u16) -> (Self, Arc<PeakLevel>) {
+        let level = Arc::new(PeakLevel {
+            peak_db: AtomicU32::new(Self::FLOOR_DB.to_bits()),
+        });
+        let samples_per_sec = (sample_rate * channels.max(1) as u32).max(1) as f32;
+        let meter = Self {
+            level: level.clone(),
+            fall: 10f32.powf(-20.0 / 20.0 / samples_per_sec),
+            peak: 0.0,
+        };
+        (meter, level)
+    }
+}
+
+impl AudioProcessor for PeakMeter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter() {
+            self.peak = (self.peak * self.fall).max(sample.abs());
+        }
+        let db = if self.peak > 0.0 { (20.0 * self.peak.log10()).max(Self::FLOOR_DB) } else { Self::FLOOR_DB };
+        self.level.peak_db.store(db.to_bits(), Ordering::Relaxed);
+    }
+}
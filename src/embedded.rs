@@ -0,0 +1,133 @@
+//! Embedded receiver protocol
+//! Length-prefixed Opus over plain TCP for microcontrollers that can't do HTTP or WebSocket
+//!
+//! Enabled with `embedded_port`. The server only sends; after connecting the client reads:
+//!
+//! ```text
+//! offset size  field
+//! 0      4     magic "RCST"
+//! 4      1     protocol version (1)
+//! 5      1     codec (1 = Opus)
+//! 6      1     channels
+//! 7      1     reserved (0)
+//! 8      4     sample rate, big-endian (48000)
+//! 12     2     samples per channel in each packet, big-endian (960 = 20ms)
+//! 14     2     reserved (0)
+//! ```
+//!
+//! followed by frames of a 2-byte big-endian length and one Opus packet, until the
+//! connection closes. Receivers should skip zero-length frames (reserved).
+//!
+//! Example receiver for an ESP32 (Arduino + libopus):
+//!
+//! ```c
+//! WiFiClient client;
+//! client.connect("192.168.0.10", 3100);
+//!
+//! uint8_t header[16];
+//! client.readBytes(header, sizeof(header));
+//! if (memcmp(header, "RCST", 4) != 0 || header[4] != 1 || header[5] != 1) return;
+//! int channels = header[6];
+//! uint32_t rate = (header[8] << 24) | (header[9] << 16) | (header[10] << 8) | header[11];
+//!
+//! int err;
+//! OpusDecoder *dec = opus_decoder_create(rate, channels, &err);
+//! static uint8_t packet[1500];
+//! static int16_t pcm[5760 * 2];
+//! while (client.connected()) {
+//!     uint8_t len_be[2];
+//!     if (client.readBytes(len_be, 2) != 2) break;
+//!     uint16_t len = (len_be[0] << 8) | len_be[1];
+//!     if (len == 0 || len > sizeof(packet)) continue;
+//!     if (client.readBytes(packet, len) != len) break;
+//!     int samples = opus_decode(dec, packet, len, pcm, 5760, 0);
+//!     if (samples > 0) i2s_write_samples(pcm, samples * channels);
+//! }
+//! ```
+
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+
+use crate::blocklist::Blocklist;
+use crate::hub::{self, BroadcastHub, Packet, SinkAdapter, SinkKind};
+use crate::server::OpusStreamInfo;
+use crate::sockopt::{SocketKind, SocketOptions};
+
+pub const MAGIC: &[u8; 4] = b"RCST";
+pub const VERSION: u8 = 1;
+pub const CODEC_OPUS: u8 = 1;
+
+/// The 16-byte stream header sent once after connecting
+pub fn header(info: &OpusStreamInfo) -> [u8; 16] {
+    let mut header = [0u8; 16];
+    header[0..4].copy_from_slice(MAGIC);
+    header[4] = VERSION;
+    header[5] = CODEC_OPUS;
+    header[6] = info.channels as u8;
+    header[8..12].copy_from_slice(&info.sample_rate.to_be_bytes());
+    header[12..14].copy_from_slice(&(info.frame_size as u16).to_be_bytes());
+    header
+}
+
+/// Writes the header, then length-prefixed packets
+struct FramedSink {
+    header: Vec<u8>,
+}
+
+impl SinkAdapter for FramedSink {
+    fn preamble(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.header)
+    }
+
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(packet.len() + 2);
+        frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        frame.extend_from_slice(packet);
+        frame
+    }
+}
+
+/// Accept embedded receivers on `0.0.0.0:port`; banned addresses are closed right away
+pub fn spawn(
+    port: u16,
+    hub: Arc<BroadcastHub>,
+    info: OpusStreamInfo,
+    sockets: Arc<SocketOptions>,
+    blocklist: Arc<Blocklist>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = sockets
+        .bind(("0.0.0.0", port), SocketKind::Embedded)
+        .map_err(|e| format!("Failed to start embedded receiver port {}: {}", port, e))?;
+    log::info!("[EMBEDDED] Raw Opus over TCP on port {}", port);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) if blocklist.is_blocked(stream.peer_addr().ok().map(|addr| addr.ip()), None) => {
+                    log::debug!("[EMBEDDED] Refused banned receiver {:?}", stream.peer_addr().ok());
+                }
+                Ok(stream) => {
+                    sockets.apply_stream(&stream, SocketKind::Embedded);
+                    serve(stream, &hub, &info)
+                }
+                Err(e) => log::debug!("[EMBEDDED] Accept failed: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, hub: &Arc<BroadcastHub>, info: &OpusStreamInfo) {
+    let peer = stream.peer_addr().ok();
+    let subscription = hub.subscribe(SinkKind::Tcp, peer);
+    let header = header(info).to_vec();
+
+    thread::spawn(move || {
+        let mut sink = FramedSink { header };
+        if let Err(e) = hub::pump(&subscription, &mut sink, &mut stream) {
+            log::debug!("[EMBEDDED] Receiver {:?} disconnected: {}", peer, e);
+        }
+    });
+}
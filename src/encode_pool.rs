@@ -0,0 +1,268 @@
+//! Encoder worker pool
+//! Secondary encoders (Vorbis, ...) share a few worker threads, each with its own queue and per-frame deadlines
+
+use crossbeam_channel::{Receiver, Select, Sender, TryRecvError, TrySendError};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Buffers an encoder may have waiting before new ones are dropped
+const QUEUE_LEN: usize = 8;
+
+/// Every encoder that ever joined a pool, for /status
+static JOBS: Mutex<Vec<Arc<JobStats>>> = Mutex::new(Vec::new());
+
+/// An encoder fed with the processed PCM of the main stream. Created on, and never leaves,
+/// its worker thread, so it doesn't have to be Send.
+pub trait PcmEncoder {
+    /// Encode one buffer of interleaved samples (and publish the result)
+    fn encode(&mut self, samples: &[f32]);
+}
+
+/// Lateness counters of one encoder
+struct JobStats {
+    name: &'static str,
+    worker: usize,
+    frames: AtomicU64,
+    late: AtomicU64,
+    dropped: AtomicU64,
+    late_us: AtomicU64,
+    max_late_us: AtomicU64,
+    busy_us: AtomicU64,
+}
+
+impl JobStats {
+    fn finished(&self, busy: Duration, lateness: Option<Duration>) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.busy_us.fetch_add(busy.as_micros() as u64, Ordering::Relaxed);
+        if let Some(lateness) = lateness {
+            let us = lateness.as_micros() as u64;
+            self.late.fetch_add(1, Ordering::Relaxed);
+            self.late_us.fetch_add(us, Ordering::Relaxed);
+            self.max_late_us.fetch_max(us, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Buffer waiting for an encoder; due once the same amount of audio has been captured after it
+struct Frame {
+    samples: Vec<f32>,
+    deadline: Instant,
+}
+
+/// Input of an encoder in the pool. Never blocks the main encoder thread: a full queue drops.
+#[derive(Clone)]
+pub struct PcmFeed {
+    tx: Sender<Frame>,
+    stats: Arc<JobStats>,
+    /// Interleaved samples per second
+    sample_rate: f64,
+}
+
+impl PcmFeed {
+    pub fn send(&self, samples: Vec<f32>) {
+        let duration = Duration::from_secs_f64(samples.len() as f64 / self.sample_rate);
+        let frame = Frame { samples, deadline: Instant::now() + duration };
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(frame) {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Job {
+    encoder: Box<dyn PcmEncoder>,
+    rx: Receiver<Frame>,
+    pending: VecDeque<Frame>,
+    stats: Arc<JobStats>,
+    connected: bool,
+}
+
+type Builder = Box<dyn FnOnce() -> Option<Job> + Send>;
+
+struct Worker {
+    tx: Sender<Builder>,
+    jobs: usize,
+}
+
+/// Worker threads for secondary encoders, started as encoders are added
+pub struct EncodePool {
+    max_workers: usize,
+    workers: Mutex<Vec<Worker>>,
+}
+
+impl EncodePool {
+    /// `workers` = 0 picks one per spare core, at most two
+    pub fn new(workers: usize) -> Arc<Self> {
+        let max_workers = match workers {
+            0 => thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1)).clamp(1, 2),
+            n => n,
+        };
+        Arc::new(Self { max_workers, workers: Mutex::new(Vec::new()) })
+    }
+
+    /// Create an encoder on the least busy worker. `build` runs there and returns the encoder
+    /// plus whatever the caller needs back (header pages, ...).
+    pub fn add<T, F>(&self, name: &'static str, sample_rate: u32, channels: u16, build: F) -> Result<(PcmFeed, T), String>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<(Box<dyn PcmEncoder>, T), String> + Send + 'static,
+    {
+        let (tx, rx) = crossbeam_channel::bounded(QUEUE_LEN);
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded::<Result<T, String>>(1);
+
+        let mut workers = self.workers.lock().unwrap();
+        if workers.len() < self.max_workers {
+            let index = workers.len();
+            workers.push(spawn_worker(index));
+        }
+        let (index, worker) = workers
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, worker)| worker.jobs)
+            .ok_or("No encoder workers")?;
+        worker.jobs += 1;
+
+        let stats = Arc::new(JobStats {
+            name,
+            worker: index,
+            frames: AtomicU64::new(0),
+            late: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            late_us: AtomicU64::new(0),
+            max_late_us: AtomicU64::new(0),
+            busy_us: AtomicU64::new(0),
+        });
+        let job_stats = stats.clone();
+        let builder: Builder = Box::new(move || match build() {
+            Ok((encoder, value)) => {
+                let _ = ready_tx.send(Ok(value));
+                Some(Job { encoder, rx, pending: VecDeque::new(), stats: job_stats, connected: true })
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                None
+            }
+        });
+        worker.tx.send(builder).map_err(|_| format!("Encoder worker {} exited", index))?;
+        drop(workers);
+
+        let value = ready_rx.recv().map_err(|_| format!("{} encoder failed to start", name))??;
+        log::info!("[ENCODE] {} encoder on worker {} of {}", name, index, self.max_workers);
+        JOBS.lock().unwrap().push(stats.clone());
+        let sample_rate = sample_rate.max(1) as f64 * channels.max(1) as f64;
+        Ok((PcmFeed { tx, stats, sample_rate }, value))
+    }
+}
+
+fn spawn_worker(index: usize) -> Worker {
+    let (tx, rx) = crossbeam_channel::unbounded::<Builder>();
+    thread::Builder::new()
+        .name(format!("encode-{}", index))
+        .spawn(move || run_worker(rx))
+        .expect("failed to spawn encoder worker");
+    Worker { tx, jobs: 0 }
+}
+
+/// Wait for buffers on any queue, then encode whatever is pending, earliest deadline first
+fn run_worker(control: Receiver<Builder>) {
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut control_open = true;
+    loop {
+        let mut select = Select::new();
+        if control_open {
+            select.recv(&control);
+        }
+        for job in &jobs {
+            select.recv(&job.rx);
+        }
+        if !control_open && jobs.is_empty() {
+            return;
+        }
+        select.ready();
+
+        loop {
+            match control.try_recv() {
+                Ok(build) => jobs.extend(build()),
+                Err(TryRecvError::Disconnected) => {
+                    control_open = false;
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        loop {
+            for job in &mut jobs {
+                loop {
+                    match job.rx.try_recv() {
+                        Ok(frame) => job.pending.push_back(frame),
+                        Err(TryRecvError::Disconnected) => {
+                            job.connected = false;
+                            break;
+                        }
+                        Err(TryRecvError::Empty) => break,
+                    }
+                }
+            }
+            let Some(job) = jobs
+                .iter_mut()
+                .filter(|job| !job.pending.is_empty())
+                .min_by_key(|job| job.pending[0].deadline)
+            else {
+                break;
+            };
+            let frame = job.pending.pop_front().unwrap();
+            let started = Instant::now();
+            job.encoder.encode(&frame.samples);
+            let finished = Instant::now();
+            job.stats.finished(finished - started, finished.checked_duration_since(frame.deadline));
+        }
+
+        jobs.retain(|job| {
+            if !job.connected {
+                JOBS.lock().unwrap().retain(|stats| !Arc::ptr_eq(stats, &job.stats));
+            }
+            job.connected
+        });
+    }
+}
+
+/// Lateness of one encoder since startup
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderStats {
+    pub name: &'static str,
+    pub worker: usize,
+    pub frames: u64,
+    /// Buffers finished after their deadline, and by how much
+    pub late: u64,
+    pub late_avg_ms: f64,
+    pub late_max_ms: f64,
+    /// Buffers dropped because the queue was full
+    pub dropped: u64,
+    /// Average encode time per buffer
+    pub encode_avg_ms: f64,
+}
+
+pub fn snapshot() -> Vec<EncoderStats> {
+    JOBS.lock()
+        .unwrap()
+        .iter()
+        .map(|stats| {
+            let frames = stats.frames.load(Ordering::Relaxed);
+            let late = stats.late.load(Ordering::Relaxed);
+            EncoderStats {
+                name: stats.name,
+                worker: stats.worker,
+                frames,
+                late,
+                late_avg_ms: stats.late_us.load(Ordering::Relaxed) as f64 / late.max(1) as f64 / 1000.0,
+                late_max_ms: stats.max_late_us.load(Ordering::Relaxed) as f64 / 1000.0,
+                dropped: stats.dropped.load(Ordering::Relaxed),
+                encode_avg_ms: stats.busy_us.load(Ordering::Relaxed) as f64 / frames.max(1) as f64 / 1000.0,
+            }
+        })
+        .collect()
+}
@@ -1,18 +1,29 @@
 //! MP3 encoding module
 //! Encodes raw PCM audio to MP3 for streaming
 
-use mp3lame_encoder::{Builder, Encoder, FlushNoGap, InterleavedPcm};
+use mp3lame_encoder::{Builder, Encoder, FlushNoGap, InterleavedPcm, MonoPcm};
 use std::mem::MaybeUninit;
 
+/// LAME quality used unless the encoder thread falls behind (0 = best, 9 = fastest)
+const DEFAULT_QUALITY: u8 = 8;
+
 /// MP3 encoder wrapper
 pub struct Mp3Encoder {
     encoder: Encoder,
     channels: u16,
+    sample_rate: u32,
+    bitrate: u32,
+    quality: u8,
 }
 
 impl Mp3Encoder {
     /// Create a new MP3 encoder
     pub fn new(sample_rate: u32, channels: u16, bitrate: u32) -> Result<Self, String> {
+        Self::with_quality(sample_rate, channels, bitrate, DEFAULT_QUALITY)
+    }
+
+    /// Create an encoder with a LAME quality level (0 = best, 9 = fastest)
+    pub fn with_quality(sample_rate: u32, channels: u16, bitrate: u32, quality: u8) -> Result<Self, String> {
         let mut builder = Builder::new().ok_or("Failed to create MP3 encoder builder")?;
         
         builder.set_sample_rate(sample_rate).map_err(|e| format!("set_sample_rate: {:?}", e))?;
@@ -27,13 +38,37 @@ impl Mp3Encoder {
             320 => mp3lame_encoder::Bitrate::Kbps320,
             _ => mp3lame_encoder::Bitrate::Kbps192,
         }).map_err(|e| format!("set_brate: {:?}", e))?;
-        // Use SecondWorst quality (8) for low latency while maintaining acceptable audio quality
-        // Worst (9) has too many artifacts, Best (0) has too much latency
-        builder.set_quality(mp3lame_encoder::Quality::SecondWorst).map_err(|e| format!("set_quality: {:?}", e))?;
+        // SecondWorst quality (8) by default for low latency while maintaining acceptable audio quality
+        // Worst (9) has too many artifacts (only used when the CPU can't keep up), Best (0) has too much latency
+        builder.set_quality(match quality {
+            0 => mp3lame_encoder::Quality::Best,
+            1 => mp3lame_encoder::Quality::SecondBest,
+            2 => mp3lame_encoder::Quality::NearBest,
+            3 => mp3lame_encoder::Quality::VeryNice,
+            4 => mp3lame_encoder::Quality::Nice,
+            5 => mp3lame_encoder::Quality::Good,
+            6 => mp3lame_encoder::Quality::Decent,
+            7 => mp3lame_encoder::Quality::Ok,
+            8 => mp3lame_encoder::Quality::SecondWorst,
+            _ => mp3lame_encoder::Quality::Worst,
+        }).map_err(|e| format!("set_quality: {:?}", e))?;
 
         let encoder = builder.build().map_err(|e| format!("build: {:?}", e))?;
         
-        Ok(Self { encoder, channels })
+        Ok(Self { encoder, channels, sample_rate, bitrate, quality: quality.min(9) })
+    }
+
+    pub fn quality(&self) -> u8 {
+        self.quality
+    }
+
+    /// Switch to another quality level. LAME can't change it on a running encoder, so this
+    /// starts a new one; returns the flushed tail of the old one.
+    pub fn set_quality(&mut self, quality: u8) -> Result<Vec<u8>, String> {
+        let next = Self::with_quality(self.sample_rate, self.channels, self.bitrate, quality)?;
+        let tail = self.flush()?;
+        *self = next;
+        Ok(tail)
     }
 
     /// Encode PCM samples to MP3
@@ -47,8 +82,12 @@ impl Mp3Encoder {
         // Prepare output buffer (worst case: input size + some extra)
         let mut mp3_buffer: Vec<MaybeUninit<u8>> = vec![MaybeUninit::uninit(); pcm_i16.len() * 2 + 7200];
 
-        let input = InterleavedPcm(&pcm_i16);
-        let encoded_size = self.encoder.encode(input, &mut mp3_buffer).map_err(|e| format!("encode: {:?}", e))?;
+        let encoded = if self.channels == 1 {
+            self.encoder.encode(MonoPcm(&pcm_i16), &mut mp3_buffer)
+        } else {
+            self.encoder.encode(InterleavedPcm(&pcm_i16), &mut mp3_buffer)
+        };
+        let encoded_size = encoded.map_err(|e| format!("encode: {:?}", e))?;
         
         // Convert MaybeUninit to initialized bytes
         let result: Vec<u8> = mp3_buffer[..encoded_size]
@@ -72,3 +111,32 @@ impl Mp3Encoder {
         Ok(result)
     }
 }
+
+/// One Layer III frame of digital silence at the stream's rate and channel count, for keepalives
+/// while nothing is encoded: lowest bitrate, no CRC, side info and main data all zero (no
+/// Huffman data, so decoders output zeros). MPEG-1 (32/44.1/48kHz) or MPEG-2 (16/22.05/24kHz);
+/// None for other rates.
+pub fn silent_frame(sample_rate: u32, channels: u16) -> Option<Vec<u8>> {
+    // Version bits, sample rate index, bytes per frame at bitrate index 1 (32 / 8 kbps)
+    let (version, rate_index, len) = match sample_rate {
+        44100 => (0b11, 0, 144 * 32000 / 44100),
+        48000 => (0b11, 1, 144 * 32000 / 48000),
+        32000 => (0b11, 2, 144 * 32000 / 32000),
+        22050 => (0b10, 0, 72 * 8000 / 22050),
+        24000 => (0b10, 1, 72 * 8000 / 24000),
+        16000 => (0b10, 2, 72 * 8000 / 16000),
+        _ => return None,
+    };
+    let mode = match channels {
+        1 => 0b11,
+        2 => 0b00,
+        _ => return None,
+    };
+    let mut frame = vec![0u8; len];
+    frame[0] = 0xFF;
+    // Sync, version, layer III, no CRC
+    frame[1] = 0xE0 | (version << 3) | (0b01 << 1) | 1;
+    frame[2] = (1 << 4) | (rate_index << 2);
+    frame[3] = mode << 6;
+    Some(frame)
+}
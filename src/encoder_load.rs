@@ -0,0 +1,124 @@
+//! Encoder thread load
+//! Processing time of the main encoder thread against the audio it covers, for /status and effort auto-scaling
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Load of the last window (‰ of real time), its worst chunk, and how often effort was lowered
+static LOAD: AtomicU32 = AtomicU32::new(0);
+static PEAK: AtomicU32 = AtomicU32::new(0);
+static LOWERED: AtomicU32 = AtomicU32::new(0);
+/// Current encoder setting ("complexity 5", "quality 8")
+static SETTING: Mutex<String> = Mutex::new(String::new());
+
+/// Length of a measurement window
+const WINDOW: Duration = Duration::from_secs(5);
+/// Average share of real time above which the thread counts as overloaded
+const AVERAGE_LIMIT: f64 = 0.5;
+/// A chunk taking this share of its own duration was a near miss
+const NEAR_MISS: f64 = 0.8;
+/// Near misses per window that count as overloaded (a few percent of 10ms chunks)
+const NEAR_MISS_LIMIT: u32 = 10;
+
+/// Measures the encoder thread; owned by it
+pub struct LoadTracker {
+    window_start: Instant,
+    busy: Duration,
+    audio: Duration,
+    peak: f64,
+    near_misses: u32,
+}
+
+impl LoadTracker {
+    pub fn new() -> Self {
+        Self {
+            window_start: Instant::now(),
+            busy: Duration::ZERO,
+            audio: Duration::ZERO,
+            peak: 0.0,
+            near_misses: 0,
+        }
+    }
+
+    /// Record that processing `audio` worth of samples took `busy`. Returns true at the end of
+    /// a window in which the thread came close to falling behind.
+    pub fn record(&mut self, busy: Duration, audio: Duration) -> bool {
+        if !audio.is_zero() {
+            let ratio = busy.as_secs_f64() / audio.as_secs_f64();
+            self.peak = self.peak.max(ratio);
+            if ratio >= NEAR_MISS {
+                self.near_misses += 1;
+            }
+        }
+        self.busy += busy;
+        self.audio += audio;
+        if self.window_start.elapsed() < WINDOW || self.audio.is_zero() {
+            return false;
+        }
+
+        let load = self.busy.as_secs_f64() / self.audio.as_secs_f64();
+        LOAD.store((load * 1000.0) as u32, Ordering::Relaxed);
+        PEAK.store((self.peak * 1000.0) as u32, Ordering::Relaxed);
+        let overloaded = load >= AVERAGE_LIMIT || self.near_misses >= NEAR_MISS_LIMIT;
+        if overloaded {
+            log::warn!(
+                "[ENCODER] Load {:.0}% of real time (worst chunk {:.0}%, {} near misses in {}s)",
+                load * 100.0,
+                self.peak * 100.0,
+                self.near_misses,
+                WINDOW.as_secs()
+            );
+        }
+        *self = Self::new();
+        overloaded
+    }
+}
+
+/// Report the encoder setting now in use
+pub fn set_setting(setting: String) {
+    *SETTING.lock().unwrap() = setting;
+}
+
+/// Count an automatic effort reduction
+pub fn record_lowered() {
+    LOWERED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Encoder thread load as shown in /status
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderLoad {
+    /// Processing time as a share of real time over the last window (%)
+    pub load_percent: f32,
+    /// Slowest single chunk of that window, against its own duration (%)
+    pub peak_percent: f32,
+    pub setting: String,
+    /// Times the effort was lowered since startup
+    pub lowered: u32,
+}
+
+impl EncoderLoad {
+    /// Prometheus text exposition
+    pub fn metrics(&self) -> String {
+        format!(
+            "# HELP rustcast_encoder_load Encoder thread processing time as a share of real time\n\
+             # TYPE rustcast_encoder_load gauge\n\
+             rustcast_encoder_load {}\n\
+             # HELP rustcast_encoder_effort_lowered_total Automatic encoder effort reductions\n\
+             # TYPE rustcast_encoder_effort_lowered_total counter\n\
+             rustcast_encoder_effort_lowered_total {}\n",
+            self.load_percent / 100.0,
+            self.lowered
+        )
+    }
+}
+
+pub fn snapshot() -> EncoderLoad {
+    EncoderLoad {
+        load_percent: LOAD.load(Ordering::Relaxed) as f32 / 10.0,
+        peak_percent: PEAK.load(Ordering::Relaxed) as f32 / 10.0,
+        setting: SETTING.lock().unwrap().clone(),
+        lowered: LOWERED.load(Ordering::Relaxed),
+    }
+}
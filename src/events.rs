@@ -0,0 +1,108 @@
+//! Event bus
+//! Typed application events (stream state, listeners, capture device, errors) published by the
+//! audio thread and the listener hubs; hooks, the GUI and the control API subscribe to them
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::audio::DeviceState;
+
+/// Events kept for `GET /api/events`
+const RECENT: usize = 50;
+/// Events queued per subscriber; past this a subscriber that stopped reading misses events
+/// instead of growing without bound
+const QUEUE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    /// Capture started; audio flows to the encoder
+    StreamStarted,
+    /// Capture stopped, on request or after an error
+    StreamStopped,
+    /// A listener joined the Opus or MP3 stream (`kind`: websocket, http, tcp)
+    ClientConnected { id: u64, kind: &'static str, ip: Option<IpAddr> },
+    ClientDisconnected { id: u64 },
+    /// The input device of the main source changed state; `device` is the one captured now
+    DeviceChanged { state: DeviceState, device: String },
+    /// Streaming failed: capture could not start, or the device was lost with `on_lost: "stop"`
+    Error { message: String },
+}
+
+/// An event and when it was published
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// Unix time in milliseconds
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: AppEvent,
+}
+
+struct Subscriber {
+    tx: Sender<Event>,
+    wake: Option<Arc<dyn Fn() + Send + Sync>>,
+}
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static HISTORY: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+/// Events not delivered because a subscriber's queue was full
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Send `event` to every subscriber
+pub fn publish(event: AppEvent) {
+    let at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+    let event = Event { at, event };
+    {
+        let mut history = HISTORY.lock().unwrap();
+        if history.len() == RECENT {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+    }
+    // Wakers run after the lock is released: one that blocks or subscribes can't stall publishers
+    let mut wakes = Vec::new();
+    SUBSCRIBERS.lock().unwrap().retain(|sub| match sub.tx.try_send(event.clone()) {
+        Ok(()) => {
+            wakes.extend(sub.wake.clone());
+            true
+        }
+        Err(TrySendError::Full(_)) => {
+            if DROPPED.fetch_add(1, Ordering::Relaxed) == 0 {
+                log::warn!("[EVENTS] A subscriber is not keeping up; events dropped for it");
+            }
+            true
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+    for wake in wakes {
+        wake();
+    }
+}
+
+/// Receive every event from now on; `wake` runs after each one is queued.
+/// The subscription ends when the receiver is dropped.
+pub fn subscribe(wake: Option<Box<dyn Fn() + Send + Sync>>) -> Receiver<Event> {
+    let (tx, rx) = crossbeam_channel::bounded(QUEUE);
+    SUBSCRIBERS.lock().unwrap().push(Subscriber { tx, wake: wake.map(Arc::from) });
+    rx
+}
+
+/// Prometheus text exposition of the events dropped for slow subscribers
+pub fn metrics() -> String {
+    format!(
+        "# HELP rustcast_events_dropped_total Events not delivered to a subscriber whose queue was full\n\
+         # TYPE rustcast_events_dropped_total counter\n\
+         rustcast_events_dropped_total {}\n",
+        DROPPED.load(Ordering::Relaxed)
+    )
+}
+
+/// The last events, oldest first
+pub fn recent() -> Vec<Event> {
+    HISTORY.lock().unwrap().iter().cloned().collect()
+}
@@ -0,0 +1,217 @@
+//! Audio fingerprint now-playing provider
+//! Fingerprints recent audio with Chromaprint's `fpcalc` and looks it up on an AcoustID-compatible server
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::FingerprintConfig;
+use crate::http_client;
+use crate::metadata::{MetadataProvider, NowPlaying};
+
+/// Seconds of audio kept for fingerprinting (AcoustID needs ~10s or more)
+const SNIPPET_SECONDS: u32 = 20;
+
+/// Snippets quieter than this (RMS) are treated as silence and skipped
+const SILENCE_RMS: f32 = 0.005;
+
+/// Rolling buffer of the most recent captured audio (interleaved f32)
+pub struct SnippetBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    capacity: usize,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl SnippetBuffer {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let capacity = (sample_rate * SNIPPET_SECONDS) as usize * channels as usize;
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            sample_rate,
+            channels,
+        }
+    }
+
+    /// Append captured samples, discarding the oldest ones
+    pub fn push(&self, samples: &[f32]) {
+        let mut buffer = self.samples.lock().unwrap();
+        buffer.extend(samples.iter().copied());
+        let excess = buffer.len().saturating_sub(self.capacity);
+        buffer.drain(..excess);
+    }
+
+    /// Copy of the buffer, or `None` until it has filled up once
+    fn snapshot(&self) -> Option<Vec<f32>> {
+        let buffer = self.samples.lock().unwrap();
+        if buffer.len() < self.capacity {
+            return None;
+        }
+        Some(buffer.iter().copied().collect())
+    }
+}
+
+/// Lowest-priority fallback for when no other provider knows the track
+pub struct FingerprintProvider {
+    config: FingerprintConfig,
+    snippet: Arc<SnippetBuffer>,
+}
+
+impl FingerprintProvider {
+    pub fn new(config: FingerprintConfig, snippet: Arc<SnippetBuffer>) -> Self {
+        Self { config, snippet }
+    }
+
+    fn identify(&self, samples: &[f32]) -> Result<Option<NowPlaying>, Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join("rustcast_fingerprint.wav");
+        write_wav(&path, samples, self.snippet.sample_rate, self.snippet.channels)?;
+
+        let (duration, fingerprint) = run_fpcalc(&self.config.fpcalc_path, &path)?;
+        let _ = std::fs::remove_file(&path);
+
+        let response = http_client::post_form(&self.config.lookup_url, &[
+            ("client", &self.config.api_key),
+            ("duration", &duration.to_string()),
+            ("fingerprint", &fingerprint),
+            ("meta", "recordings"),
+        ])?;
+        if response.status != 200 {
+            return Err(format!("lookup returned HTTP {}", response.status).into());
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&response.body)?;
+        if json["status"] != "ok" {
+            return Err(format!("lookup failed: {}", json["error"]["message"]).into());
+        }
+
+        Ok(parse_lookup(&json))
+    }
+}
+
+impl MetadataProvider for FingerprintProvider {
+    fn name(&self) -> &'static str {
+        "fingerprint"
+    }
+
+    fn priority(&self) -> u8 {
+        100
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval_secs.max(15))
+    }
+
+    fn poll(&mut self) -> Option<NowPlaying> {
+        let samples = self.snippet.snapshot()?;
+        if rms(&samples) < SILENCE_RMS {
+            return None;
+        }
+
+        match self.identify(&samples) {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("[METADATA] Fingerprint lookup failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Run `fpcalc -json` and return (duration seconds, fingerprint)
+fn run_fpcalc(fpcalc: &str, wav: &Path) -> Result<(u64, String), Box<dyn std::error::Error>> {
+    let mut command = Command::new(fpcalc);
+    command.arg("-json").arg(wav);
+
+    // Don't flash a console window from the GUI app
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| format!("could not run {}: {}", fpcalc, e))?;
+    if !output.status.success() {
+        return Err(format!("fpcalc failed: {}", String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let duration = json["duration"].as_f64().ok_or("fpcalc output has no duration")?;
+    let fingerprint = json["fingerprint"]
+        .as_str()
+        .ok_or("fpcalc output has no fingerprint")?;
+
+    Ok((duration.round() as u64, fingerprint.to_string()))
+}
+
+/// Pick the best-scoring result that has recording metadata
+fn parse_lookup(json: &serde_json::Value) -> Option<NowPlaying> {
+    let mut results: Vec<&serde_json::Value> = json["results"].as_array()?.iter().collect();
+    results.sort_by(|a, b| {
+        let a = a["score"].as_f64().unwrap_or(0.0);
+        let b = b["score"].as_f64().unwrap_or(0.0);
+        b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    results.iter().find_map(|result| {
+        let recording = result["recordings"].as_array()?.first()?;
+        let title = recording["title"].as_str()?.to_string();
+        let artist = recording["artists"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|a| a["name"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default();
+
+        Some(NowPlaying {
+            title,
+            artist,
+            album: String::new(),
+            source: "fingerprint",
+        })
+    })
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Write interleaved f32 samples as a 16-bit PCM WAV file
+fn write_wav(path: &Path, samples: &[f32], sample_rate: u32, channels: u16) -> std::io::Result<()> {
+    let data_len = (samples.len() * 2) as u32;
+    let byte_rate = sample_rate * channels as u32 * 2;
+
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&byte_rate.to_le_bytes());
+    out.extend_from_slice(&(channels * 2).to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    std::fs::File::create(path)?.write_all(&out)
+}
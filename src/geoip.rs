@@ -0,0 +1,397 @@
+//! Offline IP geolocation
+//! Coarse "City, CC" for remote listeners from a MaxMind DB file (GeoLite2 / DB-IP Lite, `.mmdb`)
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Starts the metadata section, searched for from the end of the file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// The metadata is within this many bytes of the end
+const METADATA_MAX_BYTES: usize = 128 * 1024;
+/// Zero bytes between the search tree and the data section
+const DATA_SEPARATOR: usize = 16;
+/// Pointers and containers nested deeper than this are treated as a broken file
+const MAX_DEPTH: usize = 32;
+/// Looked-up addresses kept; the cache starts over once it holds this many
+const CACHE_MAX: usize = 1024;
+
+/// Where an address is, as far as the database knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// ISO 3166 country code ("KR")
+    pub country: Option<String>,
+    /// English city name
+    pub city: Option<String>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.city, &self.country) {
+            (Some(city), Some(country)) => write!(f, "{}, {}", city, country),
+            (Some(place), None) | (None, Some(place)) => f.write_str(place),
+            (None, None) => f.write_str("?"),
+        }
+    }
+}
+
+/// A MaxMind DB file read into memory
+pub struct GeoDatabase {
+    data: Vec<u8>,
+    node_count: u32,
+    record_size: u16,
+    ip_version: u16,
+    /// Node IPv4 lookups start at in an IPv6 tree (`::/96`)
+    ipv4_start: u32,
+}
+
+impl GeoDatabase {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let search_from = data.len().saturating_sub(METADATA_MAX_BYTES);
+        let marker = data[search_from..]
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or("Not a MaxMind DB file (no metadata)")?;
+        let metadata_start = search_from + marker + METADATA_MARKER.len();
+        let metadata = Decoder { data: &data[metadata_start..] }.decode(0, 0).map(|(value, _)| value)?;
+
+        let field = |name: &str| metadata.get(name).and_then(Value::as_u64).ok_or(format!("Metadata has no {}", name));
+        let node_count = u32::try_from(field("node_count")?).map_err(|_| "node_count out of range")?;
+        let record_size = field("record_size")? as u16;
+        let ip_version = field("ip_version")? as u16;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(format!("Unsupported record size {}", record_size));
+        }
+        if !matches!(ip_version, 4 | 6) {
+            return Err(format!("Unsupported IP version {}", ip_version));
+        }
+        let tree_bytes = node_count as usize * record_size as usize / 4;
+        if tree_bytes + DATA_SEPARATOR > metadata_start {
+            return Err("Search tree runs past the data section".to_string());
+        }
+
+        let mut db = Self { data, node_count, record_size, ip_version, ipv4_start: 0 };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = db.record(node, 0);
+            }
+            db.ipv4_start = node;
+        }
+        Ok(db)
+    }
+
+    /// The database's record for an address (None = not in the database)
+    pub fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        let (bits, start) = match ip {
+            IpAddr::V4(v4) => (u32::from(v4) as u128, self.ipv4_start),
+            IpAddr::V6(v6) if self.ip_version == 6 => (u128::from(v6), 0),
+            IpAddr::V6(v6) => (u32::from(v6.to_ipv4_mapped()?) as u128, 0),
+        };
+        let width = if matches!(ip, IpAddr::V6(_)) && self.ip_version == 6 { 128 } else { 32 };
+
+        let mut node = start;
+        for i in (0..width).rev() {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, ((bits >> i) & 1) as usize);
+        }
+        // node_count itself means "no data"; above it points into the data section
+        let offset = (node.checked_sub(self.node_count)? as usize).checked_sub(DATA_SEPARATOR)?;
+        let tree_bytes = self.node_count as usize * self.record_size as usize / 4;
+        let data = self.data.get(tree_bytes + DATA_SEPARATOR..)?;
+        Decoder { data }.decode(offset, 0).ok().map(|(value, _)| value)
+    }
+
+    /// Country and city of an address
+    pub fn locate(&self, ip: IpAddr) -> Option<Location> {
+        let record = self.lookup(ip)?;
+        let country = ["country", "registered_country"]
+            .iter()
+            .find_map(|key| record.get(key)?.get("iso_code")?.as_str())
+            .map(str::to_string);
+        let city = record.get("city").and_then(|city| city.get("names")?.get("en")?.as_str()).map(str::to_string);
+        (country.is_some() || city.is_some()).then_some(Location { country, city })
+    }
+
+    /// Left (`side` 0) or right record of a search tree node
+    fn record(&self, node: u32, side: usize) -> u32 {
+        let node_bytes = self.record_size as usize / 4;
+        let start = node as usize * node_bytes;
+        let Some(bytes) = self.data.get(start..start + node_bytes) else {
+            return self.node_count;
+        };
+        let be = |b: &[u8]| b.iter().fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+        match (self.record_size, side) {
+            (24, 0) => be(&bytes[0..3]),
+            (24, _) => be(&bytes[3..6]),
+            // The middle byte holds the top 4 bits of both records
+            (28, 0) => ((bytes[3] as u32 & 0xF0) << 20) | be(&bytes[0..3]),
+            (28, _) => ((bytes[3] as u32 & 0x0F) << 24) | be(&bytes[4..7]),
+            (_, 0) => be(&bytes[0..4]),
+            (_, _) => be(&bytes[4..8]),
+        }
+    }
+}
+
+/// Reads values from a data section (MaxMind DB spec, "Output Data Section")
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, at: usize, len: usize) -> Result<&[u8], String> {
+        self.data.get(at..at + len).ok_or_else(|| "Data section truncated".to_string())
+    }
+
+    fn uint(&self, at: usize, len: usize) -> Result<u128, String> {
+        Ok(self.bytes(at, len)?.iter().fold(0u128, |acc, &byte| (acc << 8) | byte as u128))
+    }
+
+    /// Value at `at` and the offset right after it
+    fn decode(&self, at: usize, depth: usize) -> Result<(Value, usize), String> {
+        if depth > MAX_DEPTH {
+            return Err("Data nested too deep".to_string());
+        }
+        let control = self.bytes(at, 1)?[0];
+        let mut pos = at + 1;
+        let mut kind = control >> 5;
+
+        if kind == 1 {
+            // Pointer: 11 to 32 bit offset into the data section
+            let size = ((control >> 3) & 0x3) as usize;
+            let low = (control & 0x7) as u128;
+            let target = match size {
+                0 => (low << 8) | self.uint(pos, 1)?,
+                1 => ((low << 16) | self.uint(pos, 2)?) + 2048,
+                2 => ((low << 24) | self.uint(pos, 3)?) + 526_336,
+                _ => self.uint(pos, 4)?,
+            };
+            let (value, _) = self.decode(target as usize, depth + 1)?;
+            return Ok((value, pos + size + 1));
+        }
+        if kind == 0 {
+            kind = self.bytes(pos, 1)?[0].saturating_add(7);
+            pos += 1;
+        }
+        let size = match control & 0x1F {
+            29 => 29 + self.uint(pos, 1)? as usize,
+            30 => 285 + self.uint(pos, 2)? as usize,
+            31 => 65_821 + self.uint(pos, 3)? as usize,
+            size => size as usize,
+        };
+        pos += match control & 0x1F {
+            29 => 1,
+            30 => 2,
+            31 => 3,
+            _ => 0,
+        };
+
+        let value = match kind {
+            2 => Value::String(String::from_utf8_lossy(self.bytes(pos, size)?).into_owned()),
+            3 if size == 8 => serde_json::json!(f64::from_be_bytes(self.bytes(pos, 8)?.try_into().unwrap_or_default())),
+            4 => Value::Array(self.bytes(pos, size)?.iter().map(|&b| Value::from(b)).collect()),
+            5 | 6 | 9 if size <= 8 => Value::from(self.uint(pos, size)? as u64),
+            8 if size <= 4 => Value::from(self.uint(pos, size)? as u32 as i32),
+            10 if size <= 16 => Value::String(self.uint(pos, size)?.to_string()),
+            7 => {
+                let mut map = Map::new();
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    pos = next;
+                    map.insert(key.as_str().unwrap_or_default().to_string(), value);
+                }
+                return Ok((Value::Object(map), pos));
+            }
+            11 => {
+                let mut items = Vec::with_capacity(size.min(256));
+                for _ in 0..size {
+                    let (item, next) = self.decode(pos, depth + 1)?;
+                    pos = next;
+                    items.push(item);
+                }
+                return Ok((Value::Array(items), pos));
+            }
+            // Boolean: the value is the size
+            14 => return Ok((Value::Bool(size != 0), pos)),
+            15 if size == 4 => serde_json::json!(f32::from_be_bytes(self.bytes(pos, 4)?.try_into().unwrap_or_default())),
+            _ => return Err(format!("Unsupported data type {} (size {})", kind, size)),
+        };
+        Ok((value, pos + size))
+    }
+}
+
+/// Whether an address is on this network (nothing to look up)
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified(),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_local(IpAddr::V4(v4)),
+            // fc00::/7 unique local, fe80::/10 link local
+            None => v6.is_loopback() || v6.is_unspecified() || (v6.segments()[0] & 0xFE00) == 0xFC00 || (v6.segments()[0] & 0xFFC0) == 0xFE80,
+        },
+    }
+}
+
+/// Looks up remote listeners, remembering what it found per address
+pub struct GeoIp {
+    db: GeoDatabase,
+    cache: Mutex<HashMap<IpAddr, Option<Location>>>,
+}
+
+impl GeoIp {
+    /// Open `path` (empty = geolocation off); a file that can't be read is logged and skipped
+    pub fn open(path: &str) -> Option<Arc<Self>> {
+        if path.trim().is_empty() {
+            return None;
+        }
+        match GeoDatabase::open(path.trim()) {
+            Ok(db) => {
+                log::info!("[GEOIP] Using {} ({} nodes, IPv{})", path.trim(), db.node_count, db.ip_version);
+                Some(Arc::new(Self { db, cache: Mutex::new(HashMap::new()) }))
+            }
+            Err(e) => {
+                log::warn!("[GEOIP] Location lookup off: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Location of a remote listener (None for LAN addresses and unknown ones)
+    pub fn locate(&self, ip: IpAddr) -> Option<Location> {
+        if is_local(ip) {
+            return None;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(location) = cache.get(&ip) {
+            return location.clone();
+        }
+        if cache.len() >= CACHE_MAX {
+            cache.clear();
+        }
+        let location = self.db.locate(ip);
+        cache.insert(ip, location.clone());
+        location
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = vec![(2 << 5) | s.len() as u8];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn map(pairs: usize) -> Vec<u8> {
+        vec![(7 << 5) | pairs as u8]
+    }
+
+    fn uint16(value: u16) -> Vec<u8> {
+        let mut out = vec![(5 << 5) | 2];
+        out.extend_from_slice(&value.to_be_bytes());
+        out
+    }
+
+    fn uint32(value: u32) -> Vec<u8> {
+        let mut out = vec![(6 << 5) | 4];
+        out.extend_from_slice(&value.to_be_bytes());
+        out
+    }
+
+    /// IPv4 tree with one node (24-bit records): 0.0.0.0/1 -> `record`, 128.0.0.0/1 -> nothing
+    fn database(record: Vec<u8>) -> GeoDatabase {
+        let node_count = 1u32;
+        let data_record = node_count + DATA_SEPARATOR as u32;
+        let mut file = Vec::new();
+        file.extend_from_slice(&data_record.to_be_bytes()[1..]);
+        file.extend_from_slice(&node_count.to_be_bytes()[1..]);
+        file.extend_from_slice(&[0; DATA_SEPARATOR]);
+        file.extend(record);
+        file.extend_from_slice(METADATA_MARKER);
+        file.extend(map(3));
+        file.extend(string("node_count"));
+        file.extend(uint32(node_count));
+        file.extend(string("record_size"));
+        file.extend(uint16(24));
+        file.extend(string("ip_version"));
+        file.extend(uint16(4));
+        GeoDatabase::from_bytes(file).unwrap()
+    }
+
+    fn seoul() -> Vec<u8> {
+        [
+            map(2),
+            string("city"),
+            map(1),
+            string("names"),
+            map(1),
+            string("en"),
+            string("Seoul"),
+            string("country"),
+            map(1),
+            string("iso_code"),
+            string("KR"),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn locates_city_and_country() {
+        let db = database(seoul());
+        let location = db.locate("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(location.to_string(), "Seoul, KR");
+        assert_eq!(db.locate("200.1.1.1".parse().unwrap()), None);
+        assert_eq!(db.locate("::ffff:8.8.8.8".parse().unwrap()), Some(location));
+    }
+
+    #[test]
+    fn follows_pointers() {
+        // {"country": {"iso_code": "JP"}, "registered_country": <pointer to the first country map>}
+        let country_at = string("country").len() + 1;
+        let record = [
+            map(2),
+            string("country"),
+            map(1),
+            string("iso_code"),
+            string("JP"),
+            string("registered_country"),
+            vec![1 << 5, country_at as u8],
+        ]
+        .concat();
+        let db = database(record);
+        let value = db.lookup("1.2.3.4".parse().unwrap()).unwrap();
+        assert_eq!(value["registered_country"]["iso_code"], "JP");
+        assert_eq!(db.locate("1.2.3.4".parse().unwrap()).unwrap().to_string(), "JP");
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(GeoDatabase::from_bytes(b"not a database".to_vec()).is_err());
+        assert!(Decoder { data: &[(2 << 5) | 10, b'a'] }.decode(0, 0).is_err());
+    }
+
+    #[test]
+    fn skips_lan_addresses() {
+        for ip in ["192.168.0.10", "10.1.2.3", "127.0.0.1", "169.254.1.1", "::1", "fe80::1", "fd00::5", "::ffff:192.168.1.1"] {
+            assert!(is_local(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "2001:4860::8888", "::ffff:1.1.1.1"] {
+            assert!(!is_local(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Capture glitch accounting
+//! Counts discontinuities and stream errors of the capture device, for /status, /metrics and the
+//! GUI; with `pipeline.fill_gaps` a gap is replaced by silence so downstream timing holds
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Longer gaps are an outage, not a hiccup: not filled (the device-lost handling takes over)
+const MAX_FILL: Duration = Duration::from_secs(1);
+
+static GAPS: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static FILLED_MS: AtomicU64 = AtomicU64::new(0);
+static FILL: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glitch {
+    /// Audio missing between two buffers: flagged by WASAPI (`AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY`)
+    /// or seen in the capture timestamps
+    Gap,
+    /// Error reported by the audio backend while the stream kept running
+    Error,
+}
+
+/// Count a glitch
+pub fn record(glitch: Glitch) {
+    match glitch {
+        Glitch::Gap => &GAPS,
+        Glitch::Error => &ERRORS,
+    }
+    .fetch_add(1, Ordering::Relaxed);
+}
+
+/// Insert silence for gaps from now on (`pipeline.fill_gaps`)
+pub fn set_fill(fill: bool) {
+    FILL.store(fill, Ordering::Relaxed);
+}
+
+/// Interleaved samples of silence to insert for a gap of `gap`; None when filling is off or the
+/// gap is too long. Counted as filled.
+pub fn fill_len(gap: Duration, sample_rate: u32, channels: u16) -> Option<usize> {
+    if !FILL.load(Ordering::Relaxed) || gap > MAX_FILL {
+        return None;
+    }
+    let frames = (gap.as_secs_f64() * sample_rate as f64).round() as usize;
+    FILLED_MS.fetch_add(gap.as_millis() as u64, Ordering::Relaxed);
+    Some(frames * channels as usize)
+}
+
+/// Glitches since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GlitchCounts {
+    pub gaps: u64,
+    pub errors: u64,
+    /// Silence inserted for gaps (ms)
+    pub filled_ms: u64,
+}
+
+impl GlitchCounts {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn total(&self) -> u64 {
+        self.gaps + self.errors
+    }
+
+    /// Prometheus text exposition of the counters
+    pub fn metrics(&self) -> String {
+        let mut out = String::from(
+            "# HELP rustcast_capture_glitches_total Capture discontinuities and backend stream errors\n\
+             # TYPE rustcast_capture_glitches_total counter\n",
+        );
+        for (kind, value) in [("gap", self.gaps), ("error", self.errors)] {
+            out.push_str(&format!("rustcast_capture_glitches_total{{kind=\"{}\"}} {}\n", kind, value));
+        }
+        out.push_str(&format!(
+            "# HELP rustcast_capture_filled_seconds_total Silence inserted for capture gaps\n\
+             # TYPE rustcast_capture_filled_seconds_total counter\n\
+             rustcast_capture_filled_seconds_total {}\n",
+            self.filled_ms as f64 / 1000.0
+        ));
+        out
+    }
+}
+
+pub fn snapshot() -> GlitchCounts {
+    GlitchCounts {
+        gaps: GAPS.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+        filled_ms: FILLED_MS.load(Ordering::Relaxed),
+    }
+}
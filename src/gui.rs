@@ -1,16 +1,40 @@
 //! Native Windows GUI module for RustCast Settings Panel
 //! Provides a settings window with streaming controls, status indicator, and port configuration
-//! Now with modern Windows 11 styling and GridLayout
+//! Now with modern Windows 11 styling and a resizable flexbox/grid layout
 
 #![cfg(windows)]
 
 use native_windows_gui as nwg;
-use std::cell::RefCell;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use nwg::stretch::geometry::{Rect, Size};
+use nwg::stretch::style::{Dimension as D, FlexDirection};
+use std::cell::{Cell, RefCell};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
+use crossbeam_channel::Receiver;
 
-use crate::config::Config;
+use crate::chat::ChatRoom;
+use crate::config::{AppMix, CaptureConfig, Codec, Config, MicMode, OverlayConfig, TrayClick, WindowConfig};
+use crate::config_store::{ConfigChange, ConfigStore, Origin};
+use crate::agc::AgcMeter;
+use crate::audio::{self, DeviceState};
+use crate::dsp::{self, FadeControl, PeakLevel};
+use crate::drops::DropCounts;
+use crate::glitches::GlitchCounts;
+use crate::events::{self, AppEvent, Event};
+use crate::health::{self, Issue};
+use crate::hub::BroadcastHub;
+use crate::interaction::Interactions;
+use crate::mixer::MicMeter;
+use crate::passthrough;
+use crate::pcm_dump::{PcmDump, DEFAULT_DUMP_SECS};
+use crate::privacy;
+use crate::resources;
+use crate::sessions;
+use crate::stats_feed::{Listener, Stats, StatsFeed};
+use crate::system_volume;
+use crate::test_tone::{TestTone, ToneError};
 
 // Windows 11 DWM attributes
 #[allow(dead_code)]
@@ -37,11 +61,45 @@ mod dwm {
     }
 }
 
+/// Smallest settings window the layout still fits in
+const MIN_WIDTH: i32 = 380;
+const MIN_HEIGHT: i32 = 683;
+/// How long the status indicator stays red after a pipeline drop
+const DROP_ALERT_DURATION: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Row of the bitrate combo box for a configured bitrate (192 kbps if it isn't listed)
+fn bitrate_index(bitrate: u32) -> usize {
+    match bitrate {
+        64 => 0,
+        96 => 1,
+        128 => 2,
+        160 => 3,
+        192 => 4,
+        256 => 5,
+        320 => 6,
+        _ => 4,
+    }
+}
+
+/// What a row of the listener list refers to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientRow {
+    Listener { ip: Option<IpAddr>, device: Option<String> },
+    BannedIp(IpAddr),
+    BannedDevice(String),
+}
+
 /// Actions from the GUI
 #[derive(Debug, Clone)]
 pub enum GuiAction {
     ToggleStream,
-    SaveConfig(Config),
+    /// Block chat messages from a listener address
+    MuteChatUser(String),
+    ClearChat,
+    /// Drop a cue point (global hotkey)
+    DropCue,
+    /// Skip the audio held by the broadcast delay
+    DumpDelay,
     OpenBrowser,
     Quit,
 }
@@ -49,8 +107,27 @@ pub enum GuiAction {
 /// Shared application state for the GUI
 pub struct AppState {
     pub is_streaming: Arc<AtomicBool>,
-    pub client_count: Arc<AtomicUsize>,
-    pub config: RefCell<Config>,
+    pub hub: Arc<BroadcastHub>,
+    pub chat: Arc<ChatRoom>,
+    pub interactions: Arc<Interactions>,
+    /// Input/output levels of the AGC (None = AGC off)
+    pub agc: Option<Arc<AgcMeter>>,
+    /// Voice activity / push-to-talk of the mixed-in mic (None = mic off)
+    pub mic: Option<Arc<MicMeter>>,
+    /// Stream fade, also muted from the overlay
+    pub fade: Arc<FadeControl>,
+    /// Peak level of the outgoing audio (overlay meter)
+    pub level: Arc<PeakLevel>,
+    /// Raw capture dump for bug reports (hidden tray menu)
+    pub pcm_dump: Arc<PcmDump>,
+    /// Client list, levels and drops, pushed when they change
+    pub stats: Arc<StatsFeed>,
+    /// Tone for checking the setup end to end
+    pub test_tone: Arc<TestTone>,
+    /// Player link for listeners (`public_url` or the LAN address)
+    pub share_url: String,
+    /// Saved config; the GUI, the control API and the pipeline all change it through the store
+    pub config: Arc<ConfigStore>,
 }
 
 /// Settings Panel Window
@@ -61,18 +138,32 @@ pub struct SettingsPanel {
     pub tray_menu: nwg::Menu,
     pub tray_item_open: nwg::MenuItem,
     pub tray_item_settings: nwg::MenuItem,
+    pub tray_item_share: nwg::MenuItem,
+    pub tray_item_overlay: nwg::MenuItem,
+    pub tray_item_link_mute: nwg::MenuItem,
     pub tray_item_sep: nwg::MenuSeparator,
     pub tray_item_quit: nwg::MenuItem,
+    /// Shown instead of `tray_menu` on Shift + right-click
+    pub tray_debug_menu: nwg::Menu,
+    pub tray_item_pcm_dump: nwg::MenuItem,
     
     // Status group
     pub status_frame: nwg::Frame,
     pub status_label: nwg::Label,
     pub status_indicator: nwg::Label,
     pub clients_label: nwg::Label,
+    pub dump_button: nwg::Button,
+    pub test_tone_button: nwg::Button,
     
     // Controls
     pub stream_button: nwg::Button,
     pub open_browser_button: nwg::Button,
+    pub agc_label: nwg::Label,
+    pub agc_bypass_check: nwg::CheckBox,
+    pub mic_label: nwg::Label,
+    pub system_volume_label: nwg::Label,
+    pub resources_label: nwg::Label,
+    pub health_label: nwg::Label,
     
     // Settings group
     pub settings_frame: nwg::Frame,
@@ -80,13 +171,112 @@ pub struct SettingsPanel {
     pub port_input: nwg::TextInput,
     pub bitrate_label: nwg::Label,
     pub bitrate_combo: nwg::ComboBox<String>,
+    pub codec_label: nwg::Label,
+    pub codec_combo: nwg::ComboBox<String>,
+    pub mono_check: nwg::CheckBox,
     pub autostart_check: nwg::CheckBox,
+    pub start_minimized_check: nwg::CheckBox,
+    pub endpoints_label: nwg::Label,
+    pub station_name_label: nwg::Label,
+    pub station_name_input: nwg::TextInput,
+    pub station_genre_label: nwg::Label,
+    pub station_genre_input: nwg::TextInput,
+    pub station_description_label: nwg::Label,
+    pub station_description_input: nwg::TextInput,
+    pub station_website_label: nwg::Label,
+    pub station_website_input: nwg::TextInput,
+    pub privacy_label: nwg::Label,
+    pub privacy_input: nwg::TextInput,
+    
+    // Chat / stream mixer / listener tabs
+    pub tabs: nwg::TabsContainer,
+    pub chat_tab: nwg::Tab,
+    pub mixer_tab: nwg::Tab,
+    pub clients_tab: nwg::Tab,
+    
+    // Chat moderation group
+    pub chat_frame: nwg::Frame,
+    pub chat_list: nwg::ListBox<String>,
+    pub reactions_label: nwg::Label,
+    pub chat_mute_button: nwg::Button,
+    pub chat_clear_button: nwg::Button,
+    /// Id of the newest message shown (0 = empty)
+    pub chat_last_id: Cell<u64>,
+    /// Sender address of each row in chat_list
+    pub chat_ips: RefCell<Vec<String>>,
+    
+    // Stream mixer tab (per-app include/exclude and trim)
+    pub mixer_hint: nwg::Label,
+    pub mixer_list: nwg::ListBox<String>,
+    pub mixer_toggle_button: nwg::Button,
+    pub mixer_down_button: nwg::Button,
+    pub mixer_up_button: nwg::Button,
+    /// Rows shown and the app of each row
+    pub mixer_rows: RefCell<Vec<String>>,
+    pub mixer_apps: RefCell<Vec<String>>,
+    
+    // Listener tab (connected listeners and bans)
+    pub client_list: nwg::ListBox<String>,
+    pub ban_ip_button: nwg::Button,
+    pub ban_device_button: nwg::Button,
+    pub unban_button: nwg::Button,
+    /// Listeners in the last stats sample
+    pub listeners: RefCell<Vec<Listener>>,
+    /// Rows shown and what each row refers to
+    pub client_rows: RefCell<Vec<String>>,
+    pub client_targets: RefCell<Vec<ClientRow>>,
     
     // Bottom buttons
     pub save_button: nwg::Button,
     
     // Timer for status updates
     pub status_timer: nwg::AnimationTimer,
+    /// Raised by the stats feed thread; `stats_rx` holds what it sent
+    pub stats_notice: nwg::Notice,
+    pub stats_rx: Receiver<Arc<Stats>>,
+    /// Raised when the control API saves the config; `config_rx` holds the change
+    pub config_notice: nwg::Notice,
+    pub config_rx: Receiver<ConfigChange>,
+    /// Raised by the event bus; errors and device failover become tray toasts
+    pub event_notice: nwg::Notice,
+    pub event_rx: Receiver<Event>,
+    
+    // Layouts (kept alive with the window)
+    pub layout: nwg::FlexboxLayout,
+    pub status_layout: nwg::GridLayout,
+    pub settings_layout: nwg::GridLayout,
+    pub chat_layout: nwg::FlexboxLayout,
+    pub chat_buttons_layout: nwg::FlexboxLayout,
+    pub chat_tab_layout: nwg::FlexboxLayout,
+    pub mixer_layout: nwg::FlexboxLayout,
+    pub mixer_buttons_layout: nwg::FlexboxLayout,
+    pub clients_layout: nwg::FlexboxLayout,
+    pub clients_buttons_layout: nwg::FlexboxLayout,
+    
+    /// Last position/size the user gave the window (minimized states are ignored)
+    pub placement: Cell<Option<WindowConfig>>,
+    
+    // Overlay (always-on-top mini window)
+    pub overlay_window: nwg::Window,
+    pub overlay_status: nwg::Label,
+    pub overlay_clients: nwg::Label,
+    pub overlay_meter: nwg::ProgressBar,
+    pub overlay_mute_button: nwg::Button,
+    /// Faster than status_timer so the meter moves; only runs while the overlay is shown
+    pub overlay_timer: nwg::AnimationTimer,
+    pub overlay_moved_to: Cell<Option<(i32, i32)>>,
+    
+    /// Pipeline drops counted so far (from the stats feed) and when the count last grew
+    pub drops: Cell<DropCounts>,
+    pub drops_at: Cell<Option<std::time::Instant>>,
+    /// Capture glitches counted so far (from the stats feed); they alert like drops
+    pub glitches: Cell<GlitchCounts>,
+    /// Paint the status indicator in the warning color
+    pub indicator_alert: Cell<bool>,
+    /// The passthrough warning was shown for the current bitstream
+    pub bitstream_warned: Cell<bool>,
+    /// The `dsp` config errors were shown
+    pub dsp_warned: Cell<bool>,
     
     // Communication
     pub action_tx: RefCell<Option<Sender<GuiAction>>>,
@@ -105,13 +295,22 @@ impl SettingsPanel {
         // Try to load icon
         let icon = Self::load_icon()?;
         
-        // Build window
+        // Build window where it was last closed
+        let saved = state.config.get().window;
+        let default = WindowConfig::default();
+        let position = Self::restore_position((saved.x, saved.y), (default.x, default.y));
+        let size = (saved.width.max(MIN_WIDTH as u32), saved.height.max(MIN_HEIGHT as u32));
         let mut window = nwg::Window::default();
         nwg::Window::builder()
-            .size((400, 310))
-            .position((300, 200))
+            .size((size.0 as i32, size.1 as i32))
+            .position(position)
             .title("RustCast 설정")
-            .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::MINIMIZE_BOX)
+            .flags(
+                nwg::WindowFlags::WINDOW
+                    | nwg::WindowFlags::MINIMIZE_BOX
+                    | nwg::WindowFlags::MAXIMIZE_BOX
+                    | nwg::WindowFlags::RESIZABLE,
+            )
             .icon(Some(&icon))
             .build(&mut window)?;
         
@@ -147,6 +346,26 @@ impl SettingsPanel {
             .text("설정 열기")
             .build(&mut tray_item_settings)?;
         
+        let mut tray_item_share = nwg::MenuItem::default();
+        nwg::MenuItem::builder()
+            .parent(&tray_menu)
+            .text("공유 링크 복사")
+            .build(&mut tray_item_share)?;
+        
+        let mut tray_item_overlay = nwg::MenuItem::default();
+        nwg::MenuItem::builder()
+            .parent(&tray_menu)
+            .text("오버레이 표시")
+            .check(state.config.get().overlay.enabled)
+            .build(&mut tray_item_overlay)?;
+        
+        let mut tray_item_link_mute = nwg::MenuItem::default();
+        nwg::MenuItem::builder()
+            .parent(&tray_menu)
+            .text("Windows 음소거와 연동")
+            .check(state.config.get().link_system_mute)
+            .build(&mut tray_item_link_mute)?;
+        
         let mut tray_item_sep = nwg::MenuSeparator::default();
         nwg::MenuSeparator::builder()
             .parent(&tray_menu)
@@ -158,45 +377,63 @@ impl SettingsPanel {
             .text("종료")
             .build(&mut tray_item_quit)?;
         
-        // ===== Status Section (with absolute positioning) =====
+        // Hidden debug menu (Shift + right-click on the tray icon)
+        let mut tray_debug_menu = nwg::Menu::default();
+        nwg::Menu::builder()
+            .popup(true)
+            .parent(&window)
+            .build(&mut tray_debug_menu)?;
+        
+        let mut tray_item_pcm_dump = nwg::MenuItem::default();
+        nwg::MenuItem::builder()
+            .parent(&tray_debug_menu)
+            .text(&format!("캡처 원본 WAV 녹음 (디버그, 최대 {}초)", DEFAULT_DUMP_SECS))
+            .build(&mut tray_item_pcm_dump)?;
+        
+        // ===== Status Section (laid out below) =====
         let mut status_frame = nwg::Frame::default();
         nwg::Frame::builder()
             .parent(&window)
-            .position((15, 15))
-            .size((360, 95))
             .build(&mut status_frame)?;
         
         let mut status_label = nwg::Label::default();
         nwg::Label::builder()
             .parent(&status_frame)
             .text("서버 상태:")
-            .position((12, 12))
-            .size((80, 22))
             .build(&mut status_label)?;
         
         let mut status_indicator = nwg::Label::default();
         nwg::Label::builder()
             .parent(&status_frame)
             .text("● 정지됨")
-            .position((95, 12))
-            .size((250, 22))
             .build(&mut status_indicator)?;
         
+        // Broadcast delay dump (only with a delay configured)
+        let mut dump_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&status_frame)
+            .text("⏭ 딜레이 덤프")
+            .enabled(state.config.get().delay_secs > 0.0)
+            .build(&mut dump_button)?;
+        
         let mut clients_label = nwg::Label::default();
         nwg::Label::builder()
             .parent(&status_frame)
             .text("연결된 클라이언트: 0")
-            .position((12, 36))
-            .size((200, 22))
             .build(&mut clients_label)?;
         
+        // 3 seconds of tone, to check the setup from a listening device (/check)
+        let mut test_tone_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&status_frame)
+            .text("🔔 테스트 톤")
+            .build(&mut test_tone_button)?;
+        
         // Stream toggle button
         let mut stream_button = nwg::Button::default();
         nwg::Button::builder()
             .parent(&status_frame)
             .text("▶ 스트리밍 시작")
-            .position((12, 62))
-            .size((165, 28))
             .build(&mut stream_button)?;
         
         // Open browser button
@@ -204,49 +441,79 @@ impl SettingsPanel {
         nwg::Button::builder()
             .parent(&status_frame)
             .text("🌐 브라우저에서 열기")
-            .position((185, 62))
-            .size((165, 28))
             .build(&mut open_browser_button)?;
         
-        // ===== Settings Section (with absolute positioning) =====
+        // AGC levels, raw vs processed
+        let mut agc_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&status_frame)
+            .text(if state.agc.is_some() { "AGC: -" } else { "AGC: 꺼짐" })
+            .build(&mut agc_label)?;
+        
+        let mut agc_bypass_check = nwg::CheckBox::default();
+        nwg::CheckBox::builder()
+            .parent(&status_frame)
+            .text("바이패스")
+            .enabled(state.agc.is_some())
+            .build(&mut agc_bypass_check)?;
+        
+        // Mic voice activity / push-to-talk
+        let mut mic_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&status_frame)
+            .text(if state.mic.is_some() { "마이크: -" } else { "마이크: 꺼짐" })
+            .build(&mut mic_label)?;
+        
+        // Windows master volume (linked mute is marked with 🔗)
+        let mut system_volume_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&status_frame)
+            .text("")
+            .build(&mut system_volume_label)?;
+        
+        // RustCast's own CPU / memory / send rate
+        let mut resources_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&status_frame)
+            .text("리소스: -")
+            .build(&mut resources_label)?;
+        
+        // Health score and the most important suggestion
+        let mut health_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&status_frame)
+            .text("상태 점수: -")
+            .build(&mut health_label)?;
+        
+        // ===== Settings Section (laid out below) =====
         let mut settings_frame = nwg::Frame::default();
         nwg::Frame::builder()
             .parent(&window)
-            .position((15, 120))
-            .size((360, 100))
             .build(&mut settings_frame)?;
         
         let mut port_label = nwg::Label::default();
         nwg::Label::builder()
             .parent(&settings_frame)
             .text("포트:")
-            .position((12, 14))
-            .size((85, 22))
             .build(&mut port_label)?;
         
-        let config = state.config.borrow();
+        let config = state.config.get();
         
         let mut port_input = nwg::TextInput::default();
         nwg::TextInput::builder()
             .parent(&settings_frame)
             .text(&config.port.to_string())
-            .position((105, 10))
-            .size((80, 24))
             .build(&mut port_input)?;
         
         let mut bitrate_label = nwg::Label::default();
         nwg::Label::builder()
             .parent(&settings_frame)
             .text("비트레이트:")
-            .position((12, 44))
-            .size((90, 22))
             .build(&mut bitrate_label)?;
         
         let mut bitrate_combo = nwg::ComboBox::default();
         nwg::ComboBox::builder()
             .parent(&settings_frame)
-            .position((105, 40))
-            .size((100, 200))
             .collection(vec![
                 "64 kbps".to_string(),
                 "96 kbps".to_string(),
@@ -259,40 +526,403 @@ impl SettingsPanel {
             .build(&mut bitrate_combo)?;
         
         // Set current bitrate selection
-        let bitrate_index = match config.bitrate {
-            64 => 0,
-            96 => 1,
-            128 => 2,
-            160 => 3,
-            192 => 4,
-            256 => 5,
-            320 => 6,
-            _ => 4, // default to 192
-        };
-        bitrate_combo.set_selection(Some(bitrate_index));
+        bitrate_combo.set_selection(Some(bitrate_index(config.bitrate)));
+        
+        let mut codec_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("코덱:")
+            .build(&mut codec_label)?;
+        
+        let mut codec_combo = nwg::ComboBox::default();
+        nwg::ComboBox::builder()
+            .parent(&settings_frame)
+            .collection(vec!["Opus".to_string(), "MP3".to_string()])
+            .selected_index(Some(match config.codec {
+                Codec::Opus => 0,
+                Codec::Mp3 => 1,
+            }))
+            .build(&mut codec_combo)?;
+        
+        let mut mono_check = nwg::CheckBox::default();
+        nwg::CheckBox::builder()
+            .parent(&settings_frame)
+            .text("모노 (음성용)")
+            .check_state(if config.mono { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+            .build(&mut mono_check)?;
         
         let mut autostart_check = nwg::CheckBox::default();
         nwg::CheckBox::builder()
             .parent(&settings_frame)
             .text("시작 시 자동으로 스트리밍 시작")
-            .position((12, 72))
-            .size((280, 22))
             .check_state(if config.auto_start { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
             .build(&mut autostart_check)?;
         
+        let mut start_minimized_check = nwg::CheckBox::default();
+        nwg::CheckBox::builder()
+            .parent(&settings_frame)
+            .text("시작 시 트레이로 최소화 (창 숨김)")
+            .check_state(if config.start_minimized { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+            .build(&mut start_minimized_check)?;
+        
+        // Endpoints turned off in config.json (not editable here)
+        let disabled = config.endpoints.disabled();
+        let mut endpoints_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text(&if disabled.is_empty() {
+                "꺼진 엔드포인트: 없음".to_string()
+            } else {
+                format!("꺼진 엔드포인트: {}", disabled.join(", "))
+            })
+            .build(&mut endpoints_label)?;
+        
+        // Station metadata (player header, icy-* headers, OpusTags)
+        let mut station_name_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("방송 이름:")
+            .build(&mut station_name_label)?;
+        
+        let mut station_name_input = nwg::TextInput::default();
+        nwg::TextInput::builder()
+            .parent(&settings_frame)
+            .text(&config.stream_name)
+            .build(&mut station_name_input)?;
+        
+        let mut station_genre_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("장르:")
+            .build(&mut station_genre_label)?;
+        
+        let mut station_genre_input = nwg::TextInput::default();
+        nwg::TextInput::builder()
+            .parent(&settings_frame)
+            .text(&config.stream_genre)
+            .build(&mut station_genre_input)?;
+        
+        let mut station_description_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("설명:")
+            .build(&mut station_description_label)?;
+        
+        let mut station_description_input = nwg::TextInput::default();
+        nwg::TextInput::builder()
+            .parent(&settings_frame)
+            .text(&config.stream_description)
+            .build(&mut station_description_input)?;
+        
+        let mut station_website_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("웹사이트:")
+            .build(&mut station_website_label)?;
+        
+        let mut station_website_input = nwg::TextInput::default();
+        nwg::TextInput::builder()
+            .parent(&settings_frame)
+            .text(&config.stream_website)
+            .placeholder_text(Some("https://"))
+            .build(&mut station_website_input)?;
+        
+        // Privacy apps, comma separated (stream muted/stopped while one runs)
+        let mut privacy_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&settings_frame)
+            .text("차단할 앱:")
+            .build(&mut privacy_label)?;
+        
+        let mut privacy_input = nwg::TextInput::default();
+        nwg::TextInput::builder()
+            .parent(&settings_frame)
+            .text(&config.privacy.apps.join(", "))
+            .placeholder_text(Some("zoom.exe, ms-teams.exe"))
+            .build(&mut privacy_input)?;
+        
         // info_label removed - cleaner without it
         
         drop(config);
         
+        // ===== Chat and stream mixer tabs (laid out below) =====
+        let mut tabs = nwg::TabsContainer::default();
+        nwg::TabsContainer::builder()
+            .parent(&window)
+            .build(&mut tabs)?;
+        
+        let mut chat_tab = nwg::Tab::default();
+        nwg::Tab::builder()
+            .parent(&tabs)
+            .text("채팅")
+            .build(&mut chat_tab)?;
+        
+        let mut mixer_tab = nwg::Tab::default();
+        nwg::Tab::builder()
+            .parent(&tabs)
+            .text("스트림 믹서")
+            .build(&mut mixer_tab)?;
+        
+        let mut clients_tab = nwg::Tab::default();
+        nwg::Tab::builder()
+            .parent(&tabs)
+            .text("청취자")
+            .build(&mut clients_tab)?;
+        
+        let mut chat_frame = nwg::Frame::default();
+        nwg::Frame::builder()
+            .parent(&chat_tab)
+            .build(&mut chat_frame)?;
+        
+        let mut chat_list = nwg::ListBox::default();
+        nwg::ListBox::builder()
+            .parent(&chat_frame)
+            .collection(Vec::new())
+            .build(&mut chat_list)?;
+        
+        let mut reactions_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&chat_frame)
+            .text("반응: 없음 | 신청곡: 0")
+            .build(&mut reactions_label)?;
+        
+        let mut chat_mute_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&chat_frame)
+            .text("🔇 선택한 사용자 음소거")
+            .build(&mut chat_mute_button)?;
+        
+        let mut chat_clear_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&chat_frame)
+            .text("🧹 채팅 지우기")
+            .build(&mut chat_clear_button)?;
+        
+        // Stream mixer: what each app contributes to the stream (local playback is untouched)
+        let mut mixer_hint = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&mixer_tab)
+            .text("")
+            .build(&mut mixer_hint)?;
+        
+        let mut mixer_list = nwg::ListBox::default();
+        nwg::ListBox::builder()
+            .parent(&mixer_tab)
+            .collection(Vec::new())
+            .build(&mut mixer_list)?;
+        
+        let mut mixer_toggle_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&mixer_tab)
+            .text("포함 / 제외")
+            .build(&mut mixer_toggle_button)?;
+        
+        let mut mixer_down_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&mixer_tab)
+            .text("- 1 dB")
+            .build(&mut mixer_down_button)?;
+        
+        let mut mixer_up_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&mixer_tab)
+            .text("+ 1 dB")
+            .build(&mut mixer_up_button)?;
+        
+        // Listeners: ban an address or a player device (saved to blocklist, refused with 403)
+        let mut client_list = nwg::ListBox::default();
+        nwg::ListBox::builder()
+            .parent(&clients_tab)
+            .collection(Vec::new())
+            .build(&mut client_list)?;
+        
+        let mut ban_ip_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&clients_tab)
+            .text("🚫 IP 차단")
+            .build(&mut ban_ip_button)?;
+        
+        let mut ban_device_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&clients_tab)
+            .text("🚫 기기 차단")
+            .build(&mut ban_device_button)?;
+        
+        let mut unban_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&clients_tab)
+            .text("차단 해제")
+            .build(&mut unban_button)?;
+        
         // Save button
         let mut save_button = nwg::Button::default();
         nwg::Button::builder()
             .parent(&window)
             .text("💾 설정 저장")
-            .position((15, 230))
-            .size((360, 35))
             .build(&mut save_button)?;
         
+        // ===== Layout: fixed-height status/settings, chat takes the remaining height =====
+        let cell_margin = |top: f32| Rect { start: D::Points(0.0), end: D::Points(0.0), top: D::Points(top), bottom: D::Points(0.0) };
+        let fixed_height = |height: f32| Size { width: D::Auto, height: D::Points(height) };
+        
+        let status_layout = nwg::GridLayout::default();
+        nwg::GridLayout::builder()
+            .parent(&status_frame)
+            .margin([8, 12, 8, 12])
+            .spacing(4)
+            .max_column(Some(4))
+            .max_row(Some(7))
+            .child_item(nwg::GridLayoutItem::new(&status_label, 0, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&status_indicator, 1, 0, 2, 1))
+            .child_item(nwg::GridLayoutItem::new(&dump_button, 3, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&clients_label, 0, 1, 3, 1))
+            .child_item(nwg::GridLayoutItem::new(&test_tone_button, 3, 1, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&stream_button, 0, 2, 2, 1))
+            .child_item(nwg::GridLayoutItem::new(&open_browser_button, 2, 2, 2, 1))
+            .child_item(nwg::GridLayoutItem::new(&agc_label, 0, 3, 3, 1))
+            .child_item(nwg::GridLayoutItem::new(&agc_bypass_check, 3, 3, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&mic_label, 0, 4, 3, 1))
+            .child_item(nwg::GridLayoutItem::new(&system_volume_label, 3, 4, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&resources_label, 0, 5, 4, 1))
+            .child_item(nwg::GridLayoutItem::new(&health_label, 0, 6, 4, 1))
+            .build(&status_layout)?;
+        
+        let settings_layout = nwg::GridLayout::default();
+        nwg::GridLayout::builder()
+            .parent(&settings_frame)
+            .margin([8, 12, 8, 12])
+            .spacing(4)
+            .max_column(Some(4))
+            .max_row(Some(9))
+            .child_item(nwg::GridLayoutItem::new(&port_label, 0, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&port_input, 1, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&codec_label, 2, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&codec_combo, 3, 0, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&bitrate_label, 0, 1, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&bitrate_combo, 1, 1, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&mono_check, 2, 1, 2, 1))
+            .child_item(nwg::GridLayoutItem::new(&autostart_check, 0, 2, 4, 1))
+            .child_item(nwg::GridLayoutItem::new(&start_minimized_check, 0, 3, 4, 1))
+            .child_item(nwg::GridLayoutItem::new(&endpoints_label, 0, 4, 4, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_name_label, 0, 5, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_name_input, 1, 5, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_genre_label, 2, 5, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_genre_input, 3, 5, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_description_label, 0, 6, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_description_input, 1, 6, 3, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_website_label, 0, 7, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&station_website_input, 1, 7, 3, 1))
+            .child_item(nwg::GridLayoutItem::new(&privacy_label, 0, 8, 1, 1))
+            .child_item(nwg::GridLayoutItem::new(&privacy_input, 1, 8, 3, 1))
+            .build(&settings_layout)?;
+        
+        let chat_buttons_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&chat_frame)
+            .flex_direction(FlexDirection::Row)
+            .child(&chat_mute_button)
+                .child_flex_grow(1.0)
+            .child(&chat_clear_button)
+                .child_flex_grow(1.0)
+                .child_margin(Rect { start: D::Points(8.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .build_partial(&chat_buttons_layout)?;
+        
+        let chat_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&chat_frame)
+            .flex_direction(FlexDirection::Column)
+            .padding(Rect { start: D::Points(12.0), end: D::Points(12.0), top: D::Points(10.0), bottom: D::Points(8.0) })
+            .child(&chat_list)
+                .child_flex_grow(1.0)
+            .child(&reactions_label)
+                .child_size(fixed_height(22.0))
+                .child_margin(cell_margin(2.0))
+            .child_layout(&chat_buttons_layout)
+                .child_size(fixed_height(28.0))
+                .child_margin(cell_margin(2.0))
+            .build(&chat_layout)?;
+        
+        let chat_tab_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&chat_tab)
+            .padding(Rect { start: D::Points(0.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .child(&chat_frame)
+                .child_flex_grow(1.0)
+            .build(&chat_tab_layout)?;
+        
+        let mixer_buttons_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&mixer_tab)
+            .flex_direction(FlexDirection::Row)
+            .child(&mixer_toggle_button)
+                .child_flex_grow(2.0)
+            .child(&mixer_down_button)
+                .child_flex_grow(1.0)
+                .child_margin(Rect { start: D::Points(8.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .child(&mixer_up_button)
+                .child_flex_grow(1.0)
+                .child_margin(Rect { start: D::Points(8.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .build_partial(&mixer_buttons_layout)?;
+        
+        let mixer_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&mixer_tab)
+            .flex_direction(FlexDirection::Column)
+            .padding(Rect { start: D::Points(12.0), end: D::Points(12.0), top: D::Points(10.0), bottom: D::Points(8.0) })
+            .child(&mixer_hint)
+                .child_size(fixed_height(22.0))
+            .child(&mixer_list)
+                .child_flex_grow(1.0)
+                .child_margin(cell_margin(2.0))
+            .child_layout(&mixer_buttons_layout)
+                .child_size(fixed_height(28.0))
+                .child_margin(cell_margin(2.0))
+            .build(&mixer_layout)?;
+        
+        let clients_buttons_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&clients_tab)
+            .flex_direction(FlexDirection::Row)
+            .child(&ban_ip_button)
+                .child_flex_grow(1.0)
+            .child(&ban_device_button)
+                .child_flex_grow(1.0)
+                .child_margin(Rect { start: D::Points(8.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .child(&unban_button)
+                .child_flex_grow(1.0)
+                .child_margin(Rect { start: D::Points(8.0), end: D::Points(0.0), top: D::Points(0.0), bottom: D::Points(0.0) })
+            .build_partial(&clients_buttons_layout)?;
+        
+        let clients_layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&clients_tab)
+            .flex_direction(FlexDirection::Column)
+            .padding(Rect { start: D::Points(12.0), end: D::Points(12.0), top: D::Points(10.0), bottom: D::Points(8.0) })
+            .child(&client_list)
+                .child_flex_grow(1.0)
+            .child_layout(&clients_buttons_layout)
+                .child_size(fixed_height(28.0))
+                .child_margin(cell_margin(2.0))
+            .build(&clients_layout)?;
+        
+        let layout = nwg::FlexboxLayout::default();
+        nwg::FlexboxLayout::builder()
+            .parent(&window)
+            .flex_direction(FlexDirection::Column)
+            .padding(Rect { start: D::Points(15.0), end: D::Points(15.0), top: D::Points(15.0), bottom: D::Points(15.0) })
+            .child(&status_frame)
+                .child_size(fixed_height(200.0))
+            .child(&settings_frame)
+                .child_size(fixed_height(252.0))
+                .child_margin(cell_margin(10.0))
+            .child(&tabs)
+                .child_flex_grow(1.0)
+                .child_margin(cell_margin(10.0))
+            .child(&save_button)
+                .child_size(fixed_height(35.0))
+                .child_margin(cell_margin(10.0))
+            .build(&layout)?;
+        
         // Status update timer (500ms interval)
         let mut status_timer = nwg::AnimationTimer::default();
         nwg::AnimationTimer::builder()
@@ -300,6 +930,76 @@ impl SettingsPanel {
             .interval(std::time::Duration::from_millis(500))
             .build(&mut status_timer)?;
         
+        // Clients, levels and drops arrive from the stats feed instead of the timer
+        let mut stats_notice = nwg::Notice::default();
+        nwg::Notice::builder().parent(&window).build(&mut stats_notice)?;
+        let notice = stats_notice.sender();
+        let stats_rx = state.stats.subscribe(Some(Box::new(move || notice.notice())));
+        
+        // Config saved over the control API, shown in the inputs and tray menu
+        let mut config_notice = nwg::Notice::default();
+        nwg::Notice::builder().parent(&window).build(&mut config_notice)?;
+        let notice = config_notice.sender();
+        let config_rx = state.config.subscribe(Some(Box::new(move || notice.notice())));
+        
+        let mut event_notice = nwg::Notice::default();
+        nwg::Notice::builder().parent(&window).build(&mut event_notice)?;
+        let notice = event_notice.sender();
+        let event_rx = events::subscribe(Some(Box::new(move || notice.notice())));
+        
+        // ===== Overlay (separate always-on-top window, hidden until toggled) =====
+        let saved = state.config.get().overlay;
+        let default = OverlayConfig::default();
+        let mut overlay_window = nwg::Window::default();
+        nwg::Window::builder()
+            .size((230, 95))
+            .position(Self::restore_position((saved.x, saved.y), (default.x, default.y)))
+            .title("RustCast")
+            .flags(nwg::WindowFlags::WINDOW)
+            .ex_flags(winapi::um::winuser::WS_EX_TOOLWINDOW)
+            .topmost(true)
+            .icon(Some(&icon))
+            .build(&mut overlay_window)?;
+        
+        let mut overlay_status = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&overlay_window)
+            .text("○ 정지됨")
+            .position((10, 8))
+            .size((130, 22))
+            .build(&mut overlay_status)?;
+        
+        let mut overlay_clients = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&overlay_window)
+            .text("👥 0")
+            .position((145, 8))
+            .size((75, 22))
+            .build(&mut overlay_clients)?;
+        
+        // -60..0 dBFS peak
+        let mut overlay_meter = nwg::ProgressBar::default();
+        nwg::ProgressBar::builder()
+            .parent(&overlay_window)
+            .range(0..60)
+            .position((10, 38))
+            .size((150, 16))
+            .build(&mut overlay_meter)?;
+        
+        let mut overlay_mute_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&overlay_window)
+            .text("🔇")
+            .position((168, 32))
+            .size((52, 28))
+            .build(&mut overlay_mute_button)?;
+        
+        let mut overlay_timer = nwg::AnimationTimer::default();
+        nwg::AnimationTimer::builder()
+            .parent(&window)
+            .interval(std::time::Duration::from_millis(100))
+            .build(&mut overlay_timer)?;
+        
         let panel = Self {
             window,
             icon,
@@ -307,22 +1007,105 @@ impl SettingsPanel {
             tray_menu,
             tray_item_open,
             tray_item_settings,
+            tray_item_share,
+            tray_item_overlay,
+            tray_item_link_mute,
             tray_item_sep,
             tray_item_quit,
+            tray_debug_menu,
+            tray_item_pcm_dump,
             status_frame,
             status_label,
             status_indicator,
             clients_label,
+            dump_button,
+            test_tone_button,
             stream_button,
             open_browser_button,
+            agc_label,
+            agc_bypass_check,
+            mic_label,
+            system_volume_label,
+            resources_label,
+            health_label,
             settings_frame,
             port_label,
             port_input,
             bitrate_label,
             bitrate_combo,
+            codec_label,
+            codec_combo,
+            mono_check,
             autostart_check,
+            start_minimized_check,
+            endpoints_label,
+            station_name_label,
+            station_name_input,
+            station_genre_label,
+            station_genre_input,
+            station_description_label,
+            station_description_input,
+            station_website_label,
+            station_website_input,
+            privacy_label,
+            privacy_input,
+            tabs,
+            chat_tab,
+            mixer_tab,
+            clients_tab,
+            chat_frame,
+            chat_list,
+            reactions_label,
+            chat_mute_button,
+            chat_clear_button,
+            chat_last_id: Cell::new(0),
+            chat_ips: RefCell::new(Vec::new()),
+            mixer_hint,
+            mixer_list,
+            mixer_toggle_button,
+            mixer_down_button,
+            mixer_up_button,
+            mixer_rows: RefCell::new(Vec::new()),
+            mixer_apps: RefCell::new(Vec::new()),
+            client_list,
+            ban_ip_button,
+            ban_device_button,
+            unban_button,
+            listeners: RefCell::new(Vec::new()),
+            client_rows: RefCell::new(Vec::new()),
+            client_targets: RefCell::new(Vec::new()),
             save_button,
             status_timer,
+            stats_notice,
+            stats_rx,
+            config_notice,
+            config_rx,
+            event_notice,
+            event_rx,
+            layout,
+            status_layout,
+            settings_layout,
+            chat_layout,
+            chat_buttons_layout,
+            chat_tab_layout,
+            mixer_layout,
+            mixer_buttons_layout,
+            clients_layout,
+            clients_buttons_layout,
+            placement: Cell::new(None),
+            overlay_window,
+            overlay_status,
+            overlay_clients,
+            overlay_meter,
+            overlay_mute_button,
+            overlay_timer,
+            overlay_moved_to: Cell::new(None),
+            drops: Cell::new(DropCounts::default()),
+            drops_at: Cell::new(None),
+            glitches: Cell::new(GlitchCounts::default()),
+            indicator_alert: Cell::new(false),
+            bitstream_warned: Cell::new(false),
+            dsp_warned: Cell::new(false),
             action_tx: RefCell::new(Some(tx)),
             state: RefCell::new(Some(state)),
         };
@@ -330,6 +1113,103 @@ impl SettingsPanel {
         Ok(panel)
     }
     
+    /// Saved position, or the default if it is no longer on any monitor
+    fn restore_position(saved: (i32, i32), default: (i32, i32)) -> (i32, i32) {
+        use winapi::um::winuser::{
+            GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+        };
+        
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
+        
+        // Keep at least the title bar reachable (e.g. after unplugging a monitor)
+        let (x, y) = saved;
+        let visible = x >= left - 300
+            && x < left + width - 100
+            && y >= top
+            && y < top + height - 50;
+        
+        if visible {
+            saved
+        } else {
+            default
+        }
+    }
+    
+    /// Remember where the user moved the window and how they sized it
+    fn track_window_placement(&self) {
+        let minimized_or_maximized = self
+            .window
+            .handle
+            .hwnd()
+            .map(|hwnd| unsafe {
+                winapi::um::winuser::IsIconic(hwnd as _) != 0 || winapi::um::winuser::IsZoomed(hwnd as _) != 0
+            })
+            .unwrap_or(true);
+        if !minimized_or_maximized {
+            let (x, y) = self.window.position();
+            let (width, height) = self.window.size();
+            self.placement.set(Some(WindowConfig { x, y, width, height }));
+        }
+    }
+    
+    /// Show or hide the overlay and remember the choice
+    fn set_overlay_visible(&self, visible: bool) {
+        self.overlay_window.set_visible(visible);
+        self.tray_item_overlay.set_checked(visible);
+        if visible {
+            self.update_overlay();
+            self.overlay_timer.start();
+        } else {
+            self.overlay_timer.stop();
+        }
+        self.save_overlay_state(visible);
+    }
+    
+    /// Link the stream mute with the Windows mute and remember the choice
+    fn set_link_mute(&self, linked: bool) {
+        self.tray_item_link_mute.set_checked(linked);
+        self.update_config(|config| config.link_system_mute = linked);
+    }
+    
+    /// Persist overlay visibility and position (saved only if they changed)
+    fn save_overlay_state(&self, visible: bool) {
+        let moved_to = self.overlay_moved_to.get();
+        self.update_config(|config| {
+            let (x, y) = moved_to.unwrap_or((config.overlay.x, config.overlay.y));
+            config.overlay = OverlayConfig { enabled: visible, x, y };
+        });
+    }
+    
+    /// Refresh the overlay: live state, listeners, output level, mute
+    fn update_overlay(&self) {
+        if let Some(state) = self.state.borrow().as_ref() {
+            let muted = state.fade.is_muted();
+            self.overlay_status.set_text(match (state.is_streaming.load(Ordering::SeqCst), muted) {
+                (true, false) => "● LIVE",
+                (true, true) => "● LIVE (음소거)",
+                (false, _) => "○ 정지됨",
+            });
+            self.overlay_clients.set_text(&format!("👥 {}", state.hub.client_counts().total()));
+            self.overlay_meter.set_pos((state.level.db() + 60.0).clamp(0.0, 60.0) as u32);
+            self.overlay_mute_button.set_text(if muted { "🔊" } else { "🔇" });
+        }
+    }
+    
+    /// Persist the window position and size (saved only if they changed)
+    fn save_window_position(&self) {
+        let Some(placement) = self.placement.get() else {
+            return;
+        };
+        self.update_config(|config| config.window = placement);
+    }
+    
     fn load_icon() -> Result<nwg::Icon, nwg::NwgError> {
         // Try to load from file first
         let icon_paths = [
@@ -368,25 +1248,457 @@ impl SettingsPanel {
     pub fn update_status(&self) {
         if let Some(state) = self.state.borrow().as_ref() {
             let is_streaming = state.is_streaming.load(Ordering::SeqCst);
-            let client_count = state.client_count.load(Ordering::SeqCst);
             
-            if is_streaming {
-                self.status_indicator.set_text("● 스트리밍 중");
+            // Drops turn the indicator red for a while after they happen
+            let drops = self.drops.get();
+            let glitches = self.glitches.get();
+            let bitstream = is_streaming && passthrough::detected();
+            self.indicator_alert.set(
+                bitstream || (is_streaming && self.drops_at.get().is_some_and(|at| at.elapsed() < DROP_ALERT_DURATION)),
+            );
+            
+            let privacy_app = privacy::active_app();
+            let device = audio::device_status();
+            let device_name = device.as_ref().map(|d| if d.device.is_empty() { "기본 입력".to_string() } else { d.device.clone() });
+            let device_state = device.as_ref().map(|d| d.state);
+            if let Some(app) = privacy_app.as_ref().filter(|_| is_streaming && state.fade.is_privacy_muted()) {
+                self.status_indicator.set_text(&format!("● 음소거됨 ({} 실행 중)", app));
+                self.stream_button.set_text("⏹ 스트리밍 정지");
+            } else if let Some(app) = privacy_app.as_ref().filter(|_| !is_streaming) {
+                self.status_indicator.set_text(&format!("● 정지됨 ({} 실행 중)", app));
+                self.stream_button.set_text("▶ 스트리밍 시작");
+            } else if bitstream {
+                self.status_indicator.set_text("● 무음 송출 중 (Dolby/DTS 비트스트림 감지됨)");
+                self.stream_button.set_text("⏹ 스트리밍 정지");
+            } else if let Some(name) = device_name.as_ref().filter(|_| is_streaming && device_state == Some(DeviceState::Waiting)) {
+                self.status_indicator.set_text(&format!("● 장치 기다리는 중 ({} 연결 끊김)", name));
+                self.stream_button.set_text("⏹ 스트리밍 정지");
+            } else if let Some(name) = device_name.as_ref().filter(|_| is_streaming && device_state == Some(DeviceState::Fallback)) {
+                self.status_indicator.set_text(&format!("● 기본 입력으로 대체 중 ({} 연결 끊김)", name));
+                self.stream_button.set_text("⏹ 스트리밍 정지");
+            } else if let Some(name) = device_name.as_ref().filter(|_| is_streaming && device_state == Some(DeviceState::Standby)) {
+                self.status_indicator.set_text(&format!("● 대기 장치로 송출 중 ({} 장애)", name));
+                self.stream_button.set_text("⏹ 스트리밍 정지");
+            } else if let Some(name) = device_name.as_ref().filter(|_| !is_streaming && device_state == Some(DeviceState::Stopped)) {
+                self.status_indicator.set_text(&format!("● 정지됨 ({} 연결 끊김)", name));
+                self.stream_button.set_text("▶ 스트리밍 시작");
+            } else if is_streaming {
+                let mut problems = Vec::new();
+                if drops.total() > 0 {
+                    problems.push(format!("드롭: 캡처 {}, 인코딩 {}, 전송 {}", drops.capture, drops.encode, drops.client));
+                }
+                if glitches.total() > 0 {
+                    problems.push(format!("캡처 끊김 {}, 장치 오류 {}", glitches.gaps, glitches.errors));
+                }
+                if problems.is_empty() {
+                    self.status_indicator.set_text("● 스트리밍 중");
+                } else {
+                    self.status_indicator.set_text(&format!("● 스트리밍 중 ({})", problems.join(" / ")));
+                }
                 self.stream_button.set_text("⏹ 스트리밍 정지");
             } else {
                 self.status_indicator.set_text("● 정지됨");
                 self.stream_button.set_text("▶ 스트리밍 시작");
             }
             
-            self.clients_label.set_text(&format!("연결된 클라이언트: {}", client_count));
+            // Once per bitstream; set first, the timer keeps firing while the box is open
+            if bitstream && !self.bitstream_warned.replace(true) {
+                nwg::modal_info_message(
+                    &self.window,
+                    "비트스트림 출력 감지",
+                    "출력 장치로 Dolby/DTS 비트스트림(패스스루)이 나가고 있어 소리를 캡처할 수 없습니다.\n\
+                     잡음 대신 무음을 송출합니다.\n\n\
+                     해결 방법:\n\
+                     1. 재생 프로그램이나 게임의 오디오 출력을 PCM(스테레오)으로 바꾸고 \
+                     패스스루/비트스트림(S/PDIF, HDMI) 출력을 끄세요.\n\
+                     2. Windows 소리 설정 → 장치 속성 → 추가 장치 속성 → 고급에서 \
+                     '응용 프로그램이 이 장치를 단독으로 제어하도록 허용'을 끄세요.\n\
+                     3. Dolby Atmos, DTS:X 같은 공간 음향을 쓰고 있다면 끄세요.",
+                );
+            } else if !bitstream {
+                self.bitstream_warned.set(false);
+            }
+
+            // Found when the pipeline was built at startup; shown once
+            let dsp_errors = dsp::config_errors();
+            if !dsp_errors.is_empty() && !self.dsp_warned.replace(true) {
+                nwg::modal_info_message(
+                    &self.window,
+                    "DSP 설정 오류",
+                    &format!(
+                        "설정 파일의 dsp 항목에 문제가 있어 아래 단계를 건너뛰고 송출합니다.\n\n{}\n\n\
+                         설정 파일을 고친 뒤 프로그램을 다시 시작하세요.",
+                        dsp_errors.join("\n")
+                    ),
+                );
+            }
+            
+            // The dump also ends on its own at the length cap
+            self.tray_item_pcm_dump.set_checked(state.pcm_dump.is_active());
+            
+            let usage = resources::snapshot();
+            let mut text = match (usage.cpu_percent, usage.memory_bytes) {
+                (Some(cpu), Some(memory)) => format!(
+                    "리소스: CPU {:.1}%, 메모리 {:.0} MB",
+                    cpu,
+                    memory as f64 / (1024.0 * 1024.0)
+                ),
+                _ => "리소스: -".to_string(),
+            };
+            text.push_str(&format!(", 전송 {:.0} KB/s", usage.send_bytes_per_sec as f64 / 1024.0));
+            self.resources_label.set_text(&text);
+            
+            self.system_volume_label.set_text(&match system_volume::snapshot() {
+                Some(volume) => format!(
+                    "{} {}%{}{}",
+                    if volume.muted { "🔇" } else { "🔊" },
+                    volume.volume_percent,
+                    if volume.linked { " 🔗" } else { "" },
+                    if volume.compensation_db > 0.05 { format!(" (방송 +{:.1} dB 보정)", volume.compensation_db) } else { String::new() }
+                ),
+                None => String::new(),
+            });
+            
+            let health = health::snapshot();
+            self.health_label.set_text(&match health.as_ref() {
+                Some(health) => match health.issues.first() {
+                    Some(worst) => format!("상태 점수: {} - {}", health.score, Self::issue_text(&worst.issue)),
+                    None => format!("상태 점수: {} (문제 없음)", health.score),
+                },
+                None => "상태 점수: -".to_string(),
+            });
+            
+            self.update_chat(&state.chat);
+            // Listing sessions costs a COM round trip; only while the tab is shown
+            if self.tabs.selected_tab() == 1 {
+                self.update_mixer();
+            }
+            self.update_interactions(&state.interactions);
+        }
+    }
+    
+    /// Show the newest sample of the stats feed (older queued ones are skipped)
+    fn apply_stats(&self) {
+        let Some(stats) = self.stats_rx.try_iter().last() else {
+            return;
+        };
+        
+        let dropped = stats.drops.total() > self.drops.replace(stats.drops).total();
+        let glitched = stats.capture_glitches.total() > self.glitches.replace(stats.capture_glitches).total();
+        if dropped || glitched {
+            self.drops_at.set(Some(std::time::Instant::now()));
+        }
+        
+        let counts = stats.clients;
+        let mut clients = format!(
+            "연결된 클라이언트: {} (브라우저 {}, HTTP {}",
+            counts.total,
+            counts.websocket,
+            counts.http
+        );
+        if counts.tcp > 0 {
+            clients.push_str(&format!(", TCP {}", counts.tcp));
+        }
+        clients.push(')');
+        self.clients_label.set_text(&clients);
+        *self.listeners.borrow_mut() = stats.listeners.clone();
+        self.update_client_list();
+        
+        if let Some(agc) = &stats.agc {
+            self.agc_label.set_text(&format!(
+                "AGC: 입력 {:.0} dB → 출력 {:.0} dB ({:+.1} dB)",
+                agc.input_db,
+                agc.output_db,
+                if agc.bypassed { 0.0 } else { agc.gain_db }
+            ));
+        }
+        
+        let ptt = self.state.borrow().as_ref().and_then(|state| state.mic.as_ref().map(|mic| mic.mode() == MicMode::Ptt));
+        if let Some(mic) = &stats.mic {
+            let activity = match (mic.open, mic.voice) {
+                (true, true) => "말하는 중",
+                (true, false) => "열림",
+                (false, _) if ptt == Some(true) => "PTT 대기",
+                (false, _) => "닫힘",
+            };
+            self.mic_label.set_text(&format!(
+                "마이크: {} ({:.0} dB, 잡음 {:.0} dB)",
+                activity,
+                mic.level_db,
+                mic.noise_db
+            ));
+        }
+    }
+    
+    /// Refresh reaction/request counters and show new ones as tray toasts
+    fn update_interactions(&self, interactions: &Interactions) {
+        let reactions: Vec<String> = interactions
+            .reaction_counts()
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(emoji, count)| format!("{}{}", emoji, count))
+            .collect();
+        self.reactions_label.set_text(&format!(
+            "반응: {} | 신청곡: {}",
+            if reactions.is_empty() { "없음".to_string() } else { reactions.join(" ") },
+            interactions.requests().len()
+        ));
+        
+        let toasts = interactions.take_toasts();
+        if let Some(last) = toasts.last() {
+            let text = if toasts.len() > 1 {
+                format!("{}\n(외 {}개)", last, toasts.len() - 1)
+            } else {
+                last.clone()
+            };
+            self.tray.show(&text, Some("RustCast"), Some(nwg::TrayNotificationFlags::INFO_ICON), None);
+        }
+    }
+    
+    /// Tray toasts for streaming errors and input device failover; the status panel shows the rest
+    fn show_events(&self) {
+        for Event { event, .. } in self.event_rx.try_iter() {
+            let (text, flags) = match event {
+                AppEvent::Error { message } => (format!("방송 오류: {}", message), nwg::TrayNotificationFlags::WARNING_ICON),
+                AppEvent::DeviceChanged { state: DeviceState::Standby, device } => (
+                    format!("입력 장치에 문제가 있어 대기 장치로 전환했습니다: {}", device),
+                    nwg::TrayNotificationFlags::WARNING_ICON,
+                ),
+                _ => continue,
+            };
+            self.tray.show(&text, Some("RustCast"), Some(flags), None);
+        }
+    }
+    
+    /// Short suggestion for the status panel (the full English text is in /status)
+    fn issue_text(issue: &Issue) -> String {
+        match issue {
+            Issue::CaptureDrops { per_min } => format!("캡처 드롭 {}/분, 무거운 프로그램을 닫으세요", per_min),
+            Issue::EncodeDrops { per_min } => format!("인코딩 드롭 {}/분, CPU가 부족합니다", per_min),
+            Issue::EncoderLoad { load_percent } => {
+                format!("인코더 부하 {:.0}%, 비트레이트나 복잡도를 낮추세요", load_percent)
+            }
+            Issue::EncoderLate { per_min, .. } => format!("보조 인코더 지연 {}/분, 안 쓰는 출력을 끄세요", per_min),
+            Issue::SlowListener { ip, behind_ms, .. } => format!(
+                "{} 청취자가 {:.1}초 뒤처짐, 비트레이트를 낮추거나 저음질 티어로",
+                ip.map(|ip| ip.to_string()).unwrap_or_else(|| "?".to_string()),
+                *behind_ms as f64 / 1000.0
+            ),
+            Issue::ListenerDrops { per_min } => format!("느린 청취자에게 드롭 {}/분", per_min),
+            Issue::HighCpu { cpu_percent } => format!("CPU {:.0}% 사용 중", cpu_percent),
+            Issue::DeviceLost { device, fallback: false } => format!("{} 연결 끊김, 무음 송출 중", device),
+            Issue::DeviceLost { device, fallback: true } => format!("{} 연결 끊김, 기본 입력 사용 중", device),
+            Issue::Failover { device, standby } => format!("{} 장애, 대기 장치 {} 송출 중", device, standby),
+            Issue::Bitstream => "Dolby/DTS 패스스루 출력, 무음 송출 중".to_string(),
+        }
+    }
+    
+    /// Refresh the chat list when new messages arrived or it was cleared
+    fn update_chat(&self, chat: &ChatRoom) {
+        let history = chat.history();
+        let last_id = history.last().map(|m| m.id).unwrap_or(0);
+        if last_id == self.chat_last_id.get() {
+            return;
+        }
+        self.chat_last_id.set(last_id);
+        
+        self.chat_list.set_collection(
+            history.iter().map(|m| format!("[{}] {}", m.name, m.text)).collect(),
+        );
+        *self.chat_ips.borrow_mut() = history.into_iter().map(|m| m.ip).collect();
+    }
+    
+    /// Refresh the stream mixer tab: playing apps first, then apps that only have a rule
+    fn update_mixer(&self) {
+        sessions::refresh();
+        let per_app = self.state.borrow().as_ref().map(|state| {
+            matches!(&state.config.get().capture, CaptureConfig::Loopback { .. })
+        });
+        self.mixer_hint.set_text(match per_app {
+            Some(false) => "오디오 소스가 loopback일 때만 쓸 수 있습니다",
+            _ if sessions::is_capturing() => "앱별 캡처 중 (변경은 방송에만 바로 적용)",
+            _ => "전체 루프백 중: 앱을 조절하면 다시 시작한 뒤부터 적용",
+        });
+        
+        let describe = |mix: AppMix| {
+            if !mix.include {
+                "제외".to_string()
+            } else if mix.gain_db == 0.0 {
+                "포함".to_string()
+            } else {
+                format!("포함 {:+.0} dB", mix.gain_db)
+            }
+        };
+        let mut rows = Vec::new();
+        let mut apps: Vec<String> = Vec::new();
+        for session in sessions::latest() {
+            if apps.iter().any(|app| app.eq_ignore_ascii_case(&session.name)) {
+                continue;
+            }
+            let level = if session.peak > 0.0 {
+                format!("{:.0} dB", 20.0 * session.peak.log10())
+            } else {
+                "무음".to_string()
+            };
+            rows.push(format!("{} · {} · {}", session.name, level, describe(sessions::rule(&session.name))));
+            apps.push(session.name);
+        }
+        for (name, mix) in sessions::rules() {
+            if !apps.iter().any(|app| app.eq_ignore_ascii_case(&name)) {
+                rows.push(format!("{} · 재생 안 함 · {}", name, describe(mix)));
+                apps.push(name);
+            }
+        }
+        
+        if *self.mixer_rows.borrow() == rows {
+            return;
+        }
+        let selected = self.mixer_list.selection().and_then(|i| self.mixer_apps.borrow().get(i).cloned());
+        self.mixer_list.set_collection(rows.clone());
+        self.mixer_list.set_selection(selected.and_then(|name| apps.iter().position(|app| *app == name)));
+        *self.mixer_rows.borrow_mut() = rows;
+        *self.mixer_apps.borrow_mut() = apps;
+    }
+    
+    /// Include/exclude or trim the selected app in the stream mix; saved right away
+    fn adjust_selected_app(&self, change: impl FnOnce(&mut AppMix)) {
+        let Some(name) = self.mixer_list.selection().and_then(|i| self.mixer_apps.borrow().get(i).cloned()) else {
+            nwg::modal_info_message(&self.window, "스트림 믹서", "조절할 앱을 선택하세요.");
+            return;
+        };
+        let mut mix = sessions::rule(&name);
+        change(&mut mix);
+        mix.gain_db = mix.gain_db.clamp(-30.0, 12.0);
+        sessions::set_rule(&name, mix);
+        
+        self.update_config(|config| {
+            if let CaptureConfig::Loopback { apps, .. } = &mut config.capture {
+                *apps = sessions::rules();
+            }
+        });
+        self.update_mixer();
+    }
+    
+    /// Mute the sender of the selected chat message
+    fn mute_selected_chat_user(&self) {
+        let ip = self
+            .chat_list
+            .selection()
+            .and_then(|i| self.chat_ips.borrow().get(i).cloned());
+        
+        match ip {
+            Some(ip) => {
+                self.send_action(GuiAction::MuteChatUser(ip.clone()));
+                nwg::modal_info_message(&self.window, "음소거", &format!("{} 의 채팅을 차단했습니다.", ip));
+            }
+            None => {
+                nwg::modal_info_message(&self.window, "음소거", "음소거할 메시지를 선택하세요.");
+            }
+        }
+    }
+    
+    /// Refresh the listener tab: connected listeners, then the bans
+    fn update_client_list(&self) {
+        let Some(blocklist) = self.state.borrow().as_ref().map(|state| state.config.get().blocklist.clone()) else {
+            return;
+        };
+        let mut rows = Vec::new();
+        let mut targets = Vec::new();
+        for listener in self.listeners.borrow().iter() {
+            let kind = match listener.kind {
+                "websocket" => "브라우저",
+                "http" => "HTTP",
+                _ => "TCP",
+            };
+            let mut row = format!("#{} · {}", listener.id, kind);
+            if let Some(ip) = listener.ip {
+                row.push_str(&format!(" · {}", ip));
+            }
+            if let Some(location) = &listener.location {
+                row.push_str(&format!(" ({})", location));
+            }
+            if let Some(device) = &listener.device {
+                row.push_str(&format!(" · 기기 {}", device));
+            }
+            if listener.kiosk {
+                row.push_str(" · 키오스크");
+            }
+            rows.push(row);
+            targets.push(ClientRow::Listener { ip: listener.ip, device: listener.device.clone() });
         }
+        for ip in &blocklist.ips {
+            rows.push(format!("차단됨 · IP {}", ip));
+            targets.push(ClientRow::BannedIp(*ip));
+        }
+        for device in &blocklist.devices {
+            rows.push(format!("차단됨 · 기기 {}", device));
+            targets.push(ClientRow::BannedDevice(device.clone()));
+        }
+        
+        if *self.client_rows.borrow() == rows {
+            return;
+        }
+        let selected = self.client_list.selection().and_then(|i| self.client_targets.borrow().get(i).cloned());
+        self.client_list.set_collection(rows.clone());
+        self.client_list.set_selection(selected.and_then(|target| targets.iter().position(|t| *t == target)));
+        *self.client_rows.borrow_mut() = rows;
+        *self.client_targets.borrow_mut() = targets;
     }
     
-    /// Get the current config from UI inputs
-    pub fn get_config_from_ui(&self) -> Config {
-        let port: u16 = self.port_input.text().parse().unwrap_or(3000);
+    /// Ban the selected listener's address or device, or lift the selected ban; saved right away
+    /// (banned listeners are disconnected when the saved config is applied)
+    fn ban_selected_client(&self, by_device: bool, unban: bool) {
+        let Some(target) = self.client_list.selection().and_then(|i| self.client_targets.borrow().get(i).cloned()) else {
+            nwg::modal_info_message(&self.window, "청취자", "청취자나 차단 항목을 선택하세요.");
+            return;
+        };
+        let Some(state) = self.state.borrow().clone() else {
+            return;
+        };
+        let before = state.config.get();
+        let mut message = String::new();
+        let saved = state.config.update(Origin::Gui, |config| {
+            let blocklist = &mut config.blocklist;
+            message = match (target, unban) {
+                (ClientRow::BannedIp(ip), true) => {
+                    blocklist.ips.retain(|banned| *banned != ip);
+                    format!("{} 의 차단을 해제했습니다.", ip)
+                }
+                (ClientRow::BannedDevice(device), true) => {
+                    blocklist.devices.retain(|banned| *banned != device);
+                    format!("기기 {} 의 차단을 해제했습니다.", device)
+                }
+                (ClientRow::Listener { device: Some(device), .. }, false) if by_device => {
+                    if !blocklist.devices.contains(&device) {
+                        blocklist.devices.push(device.clone());
+                    }
+                    format!("기기 {} 를 차단했습니다.", device)
+                }
+                (ClientRow::Listener { ip: Some(ip), .. }, false) if !by_device => {
+                    if !blocklist.ips.contains(&ip) {
+                        blocklist.ips.push(ip);
+                    }
+                    format!("{} 를 차단했습니다.", ip)
+                }
+                (ClientRow::Listener { .. }, false) if by_device => "기기 ID를 보내지 않는 청취자입니다 (웹 플레이어만 보냄).".to_string(),
+                (ClientRow::Listener { .. }, false) => "주소를 알 수 없는 청취자입니다.".to_string(),
+                (ClientRow::Listener { .. }, true) => "차단된 항목을 선택하세요.".to_string(),
+                (_, false) => "이미 차단된 항목입니다.".to_string(),
+            };
+        });
+        match saved {
+            Ok(config) if config.blocklist != before.blocklist => self.update_client_list(),
+            Ok(_) => {}
+            Err(e) => message = e.to_string(),
+        }
+        nwg::modal_info_message(&self.window, "청취자", &message);
+    }
+    
+    /// Copy the settings inputs into `config` (fields without a control stay as they are)
+    fn apply_settings(&self, config: &mut Config) {
+        config.port = self.port_input.text().parse().unwrap_or(3000);
         
-        let bitrate: u32 = match self.bitrate_combo.selection() {
+        config.bitrate = match self.bitrate_combo.selection() {
             Some(0) => 64,
             Some(1) => 96,
             Some(2) => 128,
@@ -397,12 +1709,71 @@ impl SettingsPanel {
             _ => 192,
         };
         
-        let auto_start = self.autostart_check.check_state() == nwg::CheckBoxState::Checked;
+        config.codec = match self.codec_combo.selection() {
+            Some(1) => Codec::Mp3,
+            _ => Codec::Opus,
+        };
         
-        Config {
-            port,
-            bitrate,
-            auto_start,
+        config.mono = self.mono_check.check_state() == nwg::CheckBoxState::Checked;
+        config.auto_start = self.autostart_check.check_state() == nwg::CheckBoxState::Checked;
+        config.start_minimized = self.start_minimized_check.check_state() == nwg::CheckBoxState::Checked;
+        config.stream_name = self.station_name_input.text().trim().to_string();
+        config.stream_genre = self.station_genre_input.text().trim().to_string();
+        config.stream_description = self.station_description_input.text().trim().to_string();
+        config.stream_website = self.station_website_input.text().trim().to_string();
+        config.privacy.apps = self
+            .privacy_input
+            .text()
+            .split(',')
+            .map(|app| app.trim().to_string())
+            .filter(|app| !app.is_empty())
+            .collect();
+    }
+    
+    /// Show a config saved elsewhere (`PATCH /api/config`) in the settings inputs and tray menu
+    fn show_config(&self, config: &Config) {
+        self.port_input.set_text(&config.port.to_string());
+        self.bitrate_combo.set_selection(Some(bitrate_index(config.bitrate)));
+        self.codec_combo.set_selection(Some(match config.codec {
+            Codec::Opus => 0,
+            Codec::Mp3 => 1,
+        }));
+        let check = |on: bool| if on { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked };
+        self.mono_check.set_check_state(check(config.mono));
+        self.autostart_check.set_check_state(check(config.auto_start));
+        self.start_minimized_check.set_check_state(check(config.start_minimized));
+        self.station_name_input.set_text(&config.stream_name);
+        self.station_genre_input.set_text(&config.stream_genre);
+        self.station_description_input.set_text(&config.stream_description);
+        self.station_website_input.set_text(&config.stream_website);
+        self.privacy_input.set_text(&config.privacy.apps.join(", "));
+        self.tray_item_link_mute.set_checked(config.link_system_mute);
+        self.update_client_list();
+    }
+    
+    /// Show changes the control API saved; the GUI's own are already on screen
+    fn apply_config_changes(&self) {
+        let external = self.config_rx.try_iter().filter(|change| change.origin != Origin::Gui).count() > 0;
+        let Some(config) = self.state.borrow().as_ref().map(|state| state.config.get()) else {
+            return;
+        };
+        if external {
+            self.show_config(&config);
+        }
+    }
+    
+    /// Change and save the config; the pipeline applies what it can while streaming.
+    /// Returns false (after logging) if it could not be saved.
+    fn update_config(&self, change: impl FnOnce(&mut Config)) -> bool {
+        let Some(store) = self.state.borrow().as_ref().map(|state| state.config.clone()) else {
+            return false;
+        };
+        match store.update(Origin::Gui, change) {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("{}", e);
+                false
+            }
         }
     }
     
@@ -419,8 +1790,69 @@ impl SettingsPanel {
         nwg::Window::set_focus(&self.window);
     }
     
+    /// Put the listener link on the clipboard
+    fn copy_share_url(&self) {
+        let Some(url) = self.state.borrow().as_ref().map(|state| state.share_url.clone()) else {
+            return;
+        };
+        nwg::Clipboard::set_data_text(&self.window, &url);
+        self.tray.show(&format!("링크를 복사했습니다: {}", url), Some("RustCast"), Some(nwg::TrayNotificationFlags::INFO_ICON), None);
+    }
+    
+    /// Send the test tone and say where to check it
+    fn play_test_tone(&self) {
+        let Some((tone, share_url)) = self.state.borrow().as_ref().map(|state| (state.test_tone.clone(), state.share_url.clone())) else {
+            return;
+        };
+        let message = match tone.start() {
+            Ok(duration) => format!(
+                "{}초 동안 테스트 톤을 보냅니다. 청취 기기에서 {}/check 를 열어 확인하세요.",
+                duration.as_secs(),
+                share_url.trim_end_matches('/')
+            ),
+            Err(ToneError::NotStreaming) => "스트리밍이 멈춰 있습니다. 먼저 스트리밍을 시작하세요.".to_string(),
+            Err(ToneError::Muted) => "방송이 음소거되어 있어 테스트 톤도 들리지 않습니다. 음소거를 풀어 주세요.".to_string(),
+        };
+        self.tray.show(&message, Some("RustCast"), Some(nwg::TrayNotificationFlags::INFO_ICON), None);
+    }
+    
+    /// Start or finish the raw capture dump (hidden debug menu)
+    fn toggle_pcm_dump(&self) {
+        let Some(dump) = self.state.borrow().as_ref().map(|state| state.pcm_dump.clone()) else {
+            return;
+        };
+        let message = if dump.is_active() {
+            dump.stop().map(|path| format!("캡처 원본을 저장했습니다: {}", path.display()))
+        } else {
+            Some(match dump.start(None, DEFAULT_DUMP_SECS) {
+                Ok(path) => format!("캡처 원본 녹음 중 (최대 {}초): {}", DEFAULT_DUMP_SECS, path.display()),
+                Err(e) => format!("캡처 원본 녹음 실패: {}", e),
+            })
+        };
+        self.tray_item_pcm_dump.set_checked(dump.is_active());
+        if let Some(message) = message {
+            self.tray.show(&message, Some("RustCast"), Some(nwg::TrayNotificationFlags::INFO_ICON), None);
+        }
+    }
+    
+    /// Left click on the tray icon, as configured by `tray_click`
+    fn tray_clicked(&self) {
+        let action = self
+            .state
+            .borrow()
+            .as_ref()
+            .map(|state| state.config.get().tray_click)
+            .unwrap_or_default();
+        match action {
+            TrayClick::Settings => self.show(),
+            TrayClick::Toggle => self.send_action(GuiAction::ToggleStream),
+            TrayClick::Browser => self.send_action(GuiAction::OpenBrowser),
+        }
+    }
+    
     /// Hide to tray
     pub fn hide_to_tray(&self) {
+        self.save_window_position();
         self.window.set_visible(false);
     }
 }
@@ -429,9 +1861,21 @@ impl SettingsPanel {
 mod settings_panel_events {
     use super::*;
     
+    /// Global hotkey Ctrl+Alt+M drops a cue point
+    const CUE_HOTKEY_ID: i32 = 1;
+    const CUE_HOTKEY_KEY: u32 = b'M' as u32;
+    /// Raw handler ids below 0x10000 are reserved by nwg
+    const HOTKEY_HANDLER_ID: usize = 0x10000;
+    const MIN_SIZE_HANDLER_ID: usize = 0x10001;
+    const STATUS_COLOR_HANDLER_ID: usize = 0x10002;
+    
     pub struct SettingsPanelEvents {
         inner: std::rc::Rc<SettingsPanel>,
         default_handler: RefCell<Option<nwg::EventHandler>>,
+        overlay_handler: RefCell<Option<nwg::EventHandler>>,
+        hotkey_handler: RefCell<Option<nwg::RawEventHandler>>,
+        min_size_handler: RefCell<Option<nwg::RawEventHandler>>,
+        status_color_handler: RefCell<Option<nwg::RawEventHandler>>,
     }
     
     impl nwg::NativeUi<SettingsPanelEvents> for SettingsPanel {
@@ -442,6 +1886,10 @@ mod settings_panel_events {
             let ui = SettingsPanelEvents {
                 inner: std::rc::Rc::new(data),
                 default_handler: RefCell::new(None),
+                overlay_handler: RefCell::new(None),
+                hotkey_handler: RefCell::new(None),
+                min_size_handler: RefCell::new(None),
+                status_color_handler: RefCell::new(None),
             };
             
             let evt_ui = std::rc::Rc::downgrade(&ui.inner);
@@ -453,6 +1901,8 @@ mod settings_panel_events {
                             if &handle == &ui.window {
                                 // Hide to tray instead of closing
                                 ui.hide_to_tray();
+                            } else if &handle == &ui.overlay_window {
+                                ui.set_overlay_visible(false);
                             }
                         }
                         nwg::Event::OnWindowMinimize => {
@@ -461,17 +1911,29 @@ mod settings_panel_events {
                             }
                         }
                         
+                        nwg::Event::OnMove | nwg::Event::OnResize => {
+                            if &handle == &ui.window {
+                                ui.track_window_placement();
+                            } else if &handle == &ui.overlay_window {
+                                ui.overlay_moved_to.set(Some(ui.overlay_window.position()));
+                            }
+                        }
+                        
                         // Tray events
                         nwg::Event::OnContextMenu => {
                             if &handle == &ui.tray {
                                 let (x, y) = nwg::GlobalCursor::position();
-                                ui.tray_menu.popup(x, y);
+                                let shift = unsafe { winapi::um::winuser::GetKeyState(winapi::um::winuser::VK_SHIFT) } < 0;
+                                if shift {
+                                    ui.tray_debug_menu.popup(x, y);
+                                } else {
+                                    ui.tray_menu.popup(x, y);
+                                }
                             }
                         }
                         nwg::Event::OnMousePress(nwg::MousePressEvent::MousePressLeftUp) => {
-                            // Left click on tray icon opens settings window
                             if &handle == &ui.tray {
-                                ui.show();
+                                ui.tray_clicked();
                             }
                         }
                         
@@ -481,7 +1943,17 @@ mod settings_panel_events {
                                 ui.send_action(GuiAction::OpenBrowser);
                             } else if &handle == &ui.tray_item_settings {
                                 ui.show();
+                            } else if &handle == &ui.tray_item_share {
+                                ui.copy_share_url();
+                            } else if &handle == &ui.tray_item_overlay {
+                                ui.set_overlay_visible(!ui.tray_item_overlay.checked());
+                            } else if &handle == &ui.tray_item_link_mute {
+                                ui.set_link_mute(!ui.tray_item_link_mute.checked());
+                            } else if &handle == &ui.tray_item_pcm_dump {
+                                ui.toggle_pcm_dump();
                             } else if &handle == &ui.tray_item_quit {
+                                ui.save_window_position();
+                                ui.save_overlay_state(ui.overlay_window.visible());
                                 ui.send_action(GuiAction::Quit);
                                 nwg::stop_thread_dispatch();
                             }
@@ -493,10 +1965,51 @@ mod settings_panel_events {
                                 ui.send_action(GuiAction::ToggleStream);
                             } else if &handle == &ui.open_browser_button {
                                 ui.send_action(GuiAction::OpenBrowser);
+                            } else if &handle == &ui.dump_button {
+                                ui.send_action(GuiAction::DumpDelay);
+                            } else if &handle == &ui.test_tone_button {
+                                ui.play_test_tone();
+                            } else if &handle == &ui.agc_bypass_check {
+                                if let Some(agc) = ui.state.borrow().as_ref().and_then(|s| s.agc.clone()) {
+                                    agc.set_bypass(ui.agc_bypass_check.check_state() == nwg::CheckBoxState::Checked);
+                                }
+                            } else if &handle == &ui.overlay_mute_button {
+                                if let Some(state) = ui.state.borrow().as_ref() {
+                                    state.fade.set_muted(!state.fade.is_muted());
+                                }
+                                ui.update_overlay();
+                            } else if &handle == &ui.chat_mute_button {
+                                ui.mute_selected_chat_user();
+                            } else if &handle == &ui.chat_clear_button {
+                                ui.send_action(GuiAction::ClearChat);
+                            } else if &handle == &ui.mixer_toggle_button {
+                                ui.adjust_selected_app(|mix| mix.include = !mix.include);
+                            } else if &handle == &ui.mixer_down_button {
+                                ui.adjust_selected_app(|mix| mix.gain_db -= 1.0);
+                            } else if &handle == &ui.mixer_up_button {
+                                ui.adjust_selected_app(|mix| mix.gain_db += 1.0);
+                            } else if &handle == &ui.ban_ip_button {
+                                ui.ban_selected_client(false, false);
+                            } else if &handle == &ui.ban_device_button {
+                                ui.ban_selected_client(true, false);
+                            } else if &handle == &ui.unban_button {
+                                ui.ban_selected_client(false, true);
                             } else if &handle == &ui.save_button {
-                                let config = ui.get_config_from_ui();
-                                ui.send_action(GuiAction::SaveConfig(config));
-                                nwg::modal_info_message(&ui.window, "저장 완료", "설정이 저장되었습니다.\n포트/비트레이트 변경은 재시작 후 적용됩니다.");
+                                if ui.update_config(|config| ui.apply_settings(config)) {
+                                    nwg::modal_info_message(&ui.window, "저장 완료", "설정이 저장되었습니다.\n코덱, 방송 정보, 차단할 앱은 바로 적용되고, 포트/비트레이트/모노 변경은 재시작 후 적용됩니다.");
+                                } else {
+                                    nwg::modal_error_message(&ui.window, "저장 실패", "설정 파일을 저장하지 못했습니다. 로그를 확인하세요.");
+                                }
+                            }
+                        }
+                        
+                        nwg::Event::OnNotice => {
+                            if &handle == &ui.stats_notice {
+                                ui.apply_stats();
+                            } else if &handle == &ui.config_notice {
+                                ui.apply_config_changes();
+                            } else if &handle == &ui.event_notice {
+                                ui.show_events();
                             }
                         }
                         
@@ -504,6 +2017,8 @@ mod settings_panel_events {
                         nwg::Event::OnTimerTick => {
                             if &handle == &ui.status_timer {
                                 ui.update_status();
+                            } else if &handle == &ui.overlay_timer {
+                                ui.update_overlay();
                             }
                         }
                         
@@ -512,20 +2027,119 @@ mod settings_panel_events {
                 }
             };
             
+            // The overlay is a top-level window of its own and needs its own binding
+            *ui.overlay_handler.borrow_mut() = Some(nwg::full_bind_event_handler(
+                &ui.inner.overlay_window.handle,
+                handle_events.clone(),
+            ));
             *ui.default_handler.borrow_mut() = Some(nwg::full_bind_event_handler(
                 &ui.inner.window.handle,
                 handle_events,
             ));
             
+            ui.register_cue_hotkey();
+            ui.limit_window_size();
+            ui.color_status_indicator();
+            
             Ok(ui)
         }
     }
     
+    impl SettingsPanelEvents {
+        /// Keep the resizable window from shrinking below what the layout needs
+        fn limit_window_size(&self) {
+            use winapi::um::winuser::{MINMAXINFO, WM_GETMINMAXINFO};
+            
+            let handler = nwg::bind_raw_event_handler(&self.inner.window.handle, MIN_SIZE_HANDLER_ID, |_hwnd, msg, _wparam, lparam| {
+                if msg == WM_GETMINMAXINFO {
+                    let info = unsafe { &mut *(lparam as *mut MINMAXINFO) };
+                    info.ptMinTrackSize.x = MIN_WIDTH;
+                    info.ptMinTrackSize.y = MIN_HEIGHT;
+                }
+                None
+            });
+            match handler {
+                Ok(handler) => *self.min_size_handler.borrow_mut() = Some(handler),
+                Err(e) => log::warn!("Minimum window size not enforced: {}", e),
+            }
+        }
+        
+        /// Paint the status indicator text red while `indicator_alert` is set
+        /// (labels report their colors to the parent frame with WM_CTLCOLORSTATIC)
+        fn color_status_indicator(&self) {
+            use winapi::shared::windef::{HBRUSH, HDC};
+            use winapi::um::wingdi::{SetBkColor, SetTextColor, RGB};
+            use winapi::um::winuser::{GetSysColor, GetSysColorBrush, COLOR_BTNFACE, WM_CTLCOLORSTATIC};
+            
+            let evt_ui = std::rc::Rc::downgrade(&self.inner);
+            let handler = nwg::bind_raw_event_handler(&self.inner.status_frame.handle, STATUS_COLOR_HANDLER_ID, move |_hwnd, msg, wparam, lparam| {
+                let ui = evt_ui.upgrade()?;
+                if msg != WM_CTLCOLORSTATIC || !ui.indicator_alert.get() {
+                    return None;
+                }
+                if ui.status_indicator.handle.hwnd() != Some(lparam as _) {
+                    return None;
+                }
+                unsafe {
+                    let hdc = wparam as HDC;
+                    SetTextColor(hdc, RGB(0xD0, 0x30, 0x20));
+                    SetBkColor(hdc, GetSysColor(COLOR_BTNFACE));
+                    let brush: HBRUSH = GetSysColorBrush(COLOR_BTNFACE);
+                    Some(brush as isize)
+                }
+            });
+            match handler {
+                Ok(handler) => *self.status_color_handler.borrow_mut() = Some(handler),
+                Err(e) => log::warn!("Status indicator color not available: {}", e),
+            }
+        }
+        
+        /// Register the cue hotkey and listen for WM_HOTKEY on the main window
+        fn register_cue_hotkey(&self) {
+            use winapi::um::winuser::{RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, WM_HOTKEY};
+            
+            let Some(hwnd) = self.inner.window.handle.hwnd() else {
+                return;
+            };
+            let modifiers = (MOD_CONTROL | MOD_ALT | MOD_NOREPEAT) as u32;
+            if unsafe { RegisterHotKey(hwnd, CUE_HOTKEY_ID, modifiers, CUE_HOTKEY_KEY) } == 0 {
+                log::warn!("Cue hotkey Ctrl+Alt+M is used by another program");
+                return;
+            }
+            
+            let evt_ui = std::rc::Rc::downgrade(&self.inner);
+            let handler = nwg::bind_raw_event_handler(&self.inner.window.handle, HOTKEY_HANDLER_ID, move |_hwnd, msg, wparam, _lparam| {
+                if msg == WM_HOTKEY && wparam as i32 == CUE_HOTKEY_ID {
+                    if let Some(ui) = evt_ui.upgrade() {
+                        ui.send_action(GuiAction::DropCue);
+                    }
+                }
+                None
+            });
+            match handler {
+                Ok(handler) => *self.hotkey_handler.borrow_mut() = Some(handler),
+                Err(e) => log::warn!("Cue hotkey handler not installed: {}", e),
+            }
+        }
+    }
+    
     impl Drop for SettingsPanelEvents {
         fn drop(&mut self) {
             if let Some(handler) = self.default_handler.borrow_mut().take() {
                 nwg::unbind_event_handler(&handler);
             }
+            if let Some(handler) = self.overlay_handler.borrow_mut().take() {
+                nwg::unbind_event_handler(&handler);
+            }
+            if let Some(handler) = self.min_size_handler.borrow_mut().take() {
+                let _ = nwg::unbind_raw_event_handler(&handler);
+            }
+            if let Some(handler) = self.hotkey_handler.borrow_mut().take() {
+                let _ = nwg::unbind_raw_event_handler(&handler);
+                if let Some(hwnd) = self.inner.window.handle.hwnd() {
+                    unsafe { winapi::um::winuser::UnregisterHotKey(hwnd, CUE_HOTKEY_ID) };
+                }
+            }
         }
     }
     
@@ -546,8 +2160,18 @@ pub fn run_gui(
 ) -> Result<(), Box<dyn std::error::Error>> {
     use native_windows_gui::NativeUi;
     
+    let start_minimized = state.config.get().start_minimized;
+    let overlay = state.config.get().overlay.enabled;
+    
+    // The window is created hidden; the tray icon exists either way
     let panel = SettingsPanel::build(tx, state)?;
-    let _ui = SettingsPanel::build_ui(panel)?;
+    let ui = SettingsPanel::build_ui(panel)?;
+    if !start_minimized {
+        ui.show();
+    }
+    if overlay {
+        ui.set_overlay_visible(true);
+    }
     
     nwg::dispatch_thread_events();
     
@@ -0,0 +1,295 @@
+//! Stream health score
+//! Drops, encoder load and lateness, listener backlogs and the capture device folded into one
+//! 0-100 score with plain-language suggestions, for /status and the GUI
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio::{self, DeviceState};
+use crate::drops::{self, DropCounts};
+use crate::hub::{BroadcastHub, SinkKind};
+use crate::{encode_pool, encoder_load, passthrough, resources};
+
+/// Time between evaluations
+const INTERVAL: Duration = Duration::from_secs(1);
+/// Drop and lateness rates are taken over this window
+const WINDOW: Duration = Duration::from_secs(30);
+/// Duration of one queued packet (Opus; MP3 frames are a little longer)
+const PACKET_MS: u64 = 20;
+/// A listener this far behind gets its own finding
+const BEHIND_MS: u64 = 1000;
+/// Encoder load (% of real time) from which it counts against the score
+const LOAD_WARN_PERCENT: f32 = 80.0;
+/// RustCast's own CPU share (% of all cores) from which it counts against the score
+const CPU_WARN_PERCENT: f32 = 50.0;
+
+static HEALTH: Mutex<Option<Health>> = Mutex::new(None);
+
+/// Something that lowers the score
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum Issue {
+    /// Captured buffers dropped before the encoder
+    CaptureDrops { per_min: u64 },
+    /// Encoded packets dropped before the server
+    EncodeDrops { per_min: u64 },
+    /// Encoder thread close to real time
+    EncoderLoad { load_percent: f32 },
+    /// Secondary encoders (Vorbis, low tier, ...) finishing after their deadline
+    EncoderLate { per_min: u64, late_max_ms: f64 },
+    /// One listener whose queue is far behind live
+    SlowListener { id: u64, kind: &'static str, ip: Option<IpAddr>, behind_ms: u64 },
+    /// Packets dropped to slow listeners
+    ListenerDrops { per_min: u64 },
+    /// RustCast itself using a lot of CPU
+    HighCpu { cpu_percent: f32 },
+    /// Capture device gone; silence or the default input is streamed
+    DeviceLost { device: String, fallback: bool },
+    /// Capture device failed; the standby device is streamed
+    Failover { device: String, standby: String },
+    /// The output device gets a Dolby/DTS bitstream; silence is streamed
+    Bitstream,
+}
+
+impl Issue {
+    /// Points taken off the score
+    fn penalty(&self) -> u32 {
+        match self {
+            Issue::CaptureDrops { per_min } => (5 + per_min * 2).min(30) as u32,
+            Issue::EncodeDrops { per_min } => (5 + per_min).min(20) as u32,
+            Issue::EncoderLoad { load_percent } => ((load_percent - LOAD_WARN_PERCENT) as u32 + 5).min(25),
+            Issue::EncoderLate { .. } => 10,
+            Issue::SlowListener { behind_ms, .. } => (5 + behind_ms / 1000 * 2).min(15) as u32,
+            Issue::ListenerDrops { per_min } => (2 + per_min / 10).min(10) as u32,
+            Issue::HighCpu { .. } => 10,
+            Issue::DeviceLost { fallback: false, .. } => 40,
+            Issue::DeviceLost { fallback: true, .. } => 15,
+            Issue::Failover { .. } => 10,
+            Issue::Bitstream => 40,
+        }
+    }
+
+    /// What is wrong and what to do about it
+    pub fn suggestion(&self) -> String {
+        match self {
+            Issue::CaptureDrops { per_min } => format!(
+                "The host drops captured audio ({} buffers/min) because the encoder can't keep up: close heavy \
+                 programs or raise pipeline.latency_ms",
+                per_min
+            ),
+            Issue::EncodeDrops { per_min } => format!(
+                "Encoded audio is dropped before it reaches the server ({} packets/min): the PC is short on CPU",
+                per_min
+            ),
+            Issue::EncoderLoad { load_percent } => format!(
+                "The encoder needs {:.0}% of real time: lower opus.complexity or the bitrate",
+                load_percent
+            ),
+            Issue::EncoderLate { per_min, late_max_ms } => format!(
+                "Extra encoders (Vorbis, low tier) finish {} buffers/min late, up to {:.0}ms: turn off outputs nobody \
+                 uses or raise pipeline.encode_workers",
+                per_min, late_max_ms
+            ),
+            Issue::SlowListener { kind, ip, behind_ms, .. } => format!(
+                "{} listener {} is {:.1}s behind (weak WiFi or mobile data?): lower the bitrate or move it to the low tier",
+                kind,
+                ip.map(|ip| ip.to_string()).unwrap_or_else(|| "?".to_string()),
+                *behind_ms as f64 / 1000.0
+            ),
+            Issue::ListenerDrops { per_min } => format!(
+                "{} packets/min are dropped to slow listeners: their connections are slower than the bitrate",
+                per_min
+            ),
+            Issue::HighCpu { cpu_percent } => format!(
+                "RustCast uses {:.0}% of the CPU: lower opus.complexity or turn off the spectrum analyzer and extra outputs",
+                cpu_percent
+            ),
+            Issue::DeviceLost { device, fallback: false } => format!(
+                "Capture device {} is gone; listeners hear silence until it is back. Check the cable or USB hub",
+                device
+            ),
+            Issue::DeviceLost { device, fallback: true } => {
+                format!("Capture device {} is gone; the default input is streamed until it is back", device)
+            }
+            Issue::Failover { device, standby } => format!(
+                "Capture device {} went silent, stalled or is gone; the standby {} is streamed. Check the device; \
+                 with failback \"auto\" capture goes back to it once it delivers audio again",
+                device, standby
+            ),
+            Issue::Bitstream => "The output device receives Dolby/DTS passthrough, which can't be captured; listeners \
+                hear silence. Set the player or game to PCM/stereo output, or turn off exclusive mode in the Windows \
+                sound device settings"
+                .to_string(),
+        }
+    }
+}
+
+/// One entry of the troubleshooting list
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    #[serde(flatten)]
+    pub issue: Issue,
+    pub penalty: u32,
+    pub message: String,
+}
+
+/// Overall verdict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    Good,
+    Fair,
+    Poor,
+}
+
+/// Health as shown in /status
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    /// 100 = nothing to report
+    pub score: u32,
+    pub grade: Grade,
+    /// Worst first
+    pub issues: Vec<Finding>,
+}
+
+impl Health {
+    fn from_issues(issues: Vec<Issue>) -> Self {
+        let mut issues: Vec<Finding> = issues
+            .into_iter()
+            .map(|issue| Finding { penalty: issue.penalty(), message: issue.suggestion(), issue })
+            .collect();
+        issues.sort_by_key(|finding| std::cmp::Reverse(finding.penalty));
+        let score = 100u32.saturating_sub(issues.iter().map(|finding| finding.penalty).sum());
+        let grade = match score {
+            90.. => Grade::Good,
+            60..=89 => Grade::Fair,
+            _ => Grade::Poor,
+        };
+        Self { score, grade, issues }
+    }
+
+    /// Prometheus text exposition
+    pub fn metrics(&self) -> String {
+        format!(
+            "# HELP rustcast_health_score Stream health score (100 = nothing to report)\n\
+             # TYPE rustcast_health_score gauge\n\
+             rustcast_health_score {}\n",
+            self.score
+        )
+    }
+}
+
+/// Counters at one evaluation, for rates over the window
+struct Sample {
+    at: Instant,
+    drops: DropCounts,
+    late: u64,
+}
+
+/// Start the evaluation thread for the main hub
+pub fn start(hub: Arc<BroadcastHub>) {
+    let result = thread::Builder::new()
+        .name("health".into())
+        .spawn(move || run(hub));
+    if let Err(e) = result {
+        log::warn!("[HEALTH] Not started: {}", e);
+    }
+}
+
+fn run(hub: Arc<BroadcastHub>) {
+    let mut samples: VecDeque<Sample> = VecDeque::new();
+    let mut last_grade = Grade::Good;
+    loop {
+        let now = Instant::now();
+        let encoders = encode_pool::snapshot();
+        samples.push_back(Sample { at: now, drops: drops::snapshot(), late: encoders.iter().map(|e| e.late).sum() });
+        while samples.len() > 2 && now.duration_since(samples[0].at) > WINDOW {
+            samples.pop_front();
+        }
+
+        let health = Health::from_issues(evaluate(&hub, &samples, &encoders));
+        if health.grade != last_grade {
+            match health.issues.first() {
+                Some(worst) => log::warn!("[HEALTH] Score {}: {}", health.score, worst.message),
+                None => log::info!("[HEALTH] Score {}, back to normal", health.score),
+            }
+            last_grade = health.grade;
+        }
+        *HEALTH.lock().unwrap() = Some(health);
+
+        thread::sleep(INTERVAL);
+    }
+}
+
+fn evaluate(hub: &BroadcastHub, samples: &VecDeque<Sample>, encoders: &[encode_pool::EncoderStats]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    if let (Some(first), Some(last)) = (samples.front(), samples.back()) {
+        let minutes = last.at.duration_since(first.at).as_secs_f64().max(1.0) / 60.0;
+        let per_min = |before: u64, after: u64| (after.saturating_sub(before) as f64 / minutes).round() as u64;
+        let capture = per_min(first.drops.capture, last.drops.capture);
+        if capture > 0 {
+            issues.push(Issue::CaptureDrops { per_min: capture });
+        }
+        let encode = per_min(first.drops.encode, last.drops.encode);
+        if encode > 0 {
+            issues.push(Issue::EncodeDrops { per_min: encode });
+        }
+        let client = per_min(first.drops.client, last.drops.client);
+        if client > 0 {
+            issues.push(Issue::ListenerDrops { per_min: client });
+        }
+        let late = per_min(first.late, last.late);
+        if late > 0 {
+            let late_max_ms = encoders.iter().map(|e| e.late_max_ms).fold(0.0, f64::max);
+            issues.push(Issue::EncoderLate { per_min: late, late_max_ms });
+        }
+    }
+
+    let load = encoder_load::snapshot();
+    if load.load_percent >= LOAD_WARN_PERCENT {
+        issues.push(Issue::EncoderLoad { load_percent: load.load_percent });
+    }
+    if let Some(cpu) = resources::snapshot().cpu_percent.filter(|cpu| *cpu >= CPU_WARN_PERCENT) {
+        issues.push(Issue::HighCpu { cpu_percent: cpu });
+    }
+
+    for sub in hub.snapshot().into_iter().filter(|sub| sub.kind != SinkKind::Output) {
+        let behind_ms = sub.backlog as u64 * PACKET_MS;
+        if behind_ms >= BEHIND_MS {
+            issues.push(Issue::SlowListener {
+                id: sub.id,
+                kind: sub.kind.as_str(),
+                ip: sub.remote_addr.map(|addr| addr.ip()),
+                behind_ms,
+            });
+        }
+    }
+
+    if let Some(device) = audio::device_status() {
+        let name = if device.device.is_empty() { "(default input)".to_string() } else { device.device };
+        match device.state {
+            DeviceState::Waiting => issues.push(Issue::DeviceLost { device: name, fallback: false }),
+            DeviceState::Fallback => issues.push(Issue::DeviceLost { device: name, fallback: true }),
+            DeviceState::Standby => {
+                issues.push(Issue::Failover { device: name, standby: device.using.unwrap_or_default() })
+            }
+            DeviceState::Active | DeviceState::Stopped => {}
+        }
+    }
+
+    if passthrough::detected() {
+        issues.push(Issue::Bitstream);
+    }
+
+    issues
+}
+
+/// Latest evaluation (None until the first second has passed)
+pub fn snapshot() -> Option<Health> {
+    HEALTH.lock().unwrap().clone()
+}
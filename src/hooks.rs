@@ -0,0 +1,123 @@
+//! Command hooks
+//! Shell commands run when the stream starts or stops, the first listener arrives or the last
+//! one leaves, streaming fails or capture fails over to the standby device (e.g. to switch an
+//! ON AIR sign)
+
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+
+use crate::audio::DeviceState;
+use crate::config::{HookConfig, HookEvent};
+use crate::events::{self, AppEvent, Event};
+use crate::hub::BroadcastHub;
+use crate::station::Station;
+
+pub struct Hooks {
+    hooks: Vec<HookConfig>,
+    station: Arc<Station>,
+    port: u16,
+    /// Hubs whose listeners are counted (Opus and MP3)
+    hubs: Vec<Arc<BroadcastHub>>,
+}
+
+impl Hooks {
+    pub fn new(hooks: &[HookConfig], station: Arc<Station>, port: u16, hubs: Vec<Arc<BroadcastHub>>) -> Arc<Self> {
+        let hooks: Vec<HookConfig> = hooks.iter().filter(|hook| !hook.command.trim().is_empty()).cloned().collect();
+        for hook in &hooks {
+            log::info!("[HOOK] {}: {}", hook.on.as_str(), hook.command);
+        }
+        Arc::new(Self { hooks, station, port, hubs })
+    }
+
+    /// Run the hooks as events arrive on the event bus
+    pub fn start(self: &Arc<Self>) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let events = events::subscribe(None);
+        let hooks = self.clone();
+        let result = thread::Builder::new().name("hooks".into()).spawn(move || {
+            let mut had_clients = false;
+            let mut standby = false;
+            for Event { event, .. } in events {
+                match event {
+                    AppEvent::StreamStarted => hooks.fire(HookEvent::StreamStarted, ""),
+                    AppEvent::StreamStopped => {
+                        standby = false;
+                        hooks.fire(HookEvent::StreamStopped, "");
+                    }
+                    AppEvent::Error { message } => hooks.fire(HookEvent::Error, &message),
+                    AppEvent::DeviceChanged { state, device } => {
+                        match (standby, state) {
+                            (false, DeviceState::Standby) => hooks.fire(HookEvent::Failover, &device),
+                            (true, DeviceState::Active) => hooks.fire(HookEvent::Failback, &device),
+                            _ => {}
+                        }
+                        standby = state == DeviceState::Standby;
+                    }
+                    // Counted over the Opus and MP3 hubs
+                    AppEvent::ClientConnected { .. } | AppEvent::ClientDisconnected { .. } => {
+                        let has_clients = hooks.listeners() > 0;
+                        if has_clients != had_clients {
+                            hooks.fire(if has_clients { HookEvent::FirstClient } else { HookEvent::LastClient }, "");
+                            had_clients = has_clients;
+                        }
+                    }
+                }
+            }
+        });
+        if let Err(e) = result {
+            log::warn!("[HOOK] Event listener not started: {}", e);
+        }
+    }
+
+    /// Run every hook of `event` in the background; `message` is the error text for `error`, the
+    /// device now captured for `failover`/`failback`
+    pub fn fire(&self, event: HookEvent, message: &str) {
+        for hook in self.hooks.iter().filter(|hook| hook.on == event) {
+            let mut command = shell(&hook.command);
+            command
+                .env("RUSTCAST_EVENT", event.as_str())
+                .env("RUSTCAST_MESSAGE", message)
+                .env("RUSTCAST_STATION", self.station.get().display_name())
+                .env("RUSTCAST_PORT", self.port.to_string())
+                .env("RUSTCAST_URL", format!("http://localhost:{}/", self.port))
+                .env("RUSTCAST_LISTENERS", self.listeners().to_string());
+
+            let line = hook.command.clone();
+            let result = thread::Builder::new().name("hook".into()).spawn(move || match command.status() {
+                Ok(status) if status.success() => log::debug!("[HOOK] {} finished", line),
+                Ok(status) => log::warn!("[HOOK] {} exited with {}", line, status),
+                Err(e) => log::warn!("[HOOK] {} failed to run: {}", line, e),
+            });
+            if let Err(e) = result {
+                log::warn!("[HOOK] {} not started: {}", hook.command, e);
+            }
+        }
+    }
+
+    fn listeners(&self) -> usize {
+        self.hubs.iter().map(|hub| hub.client_counts().total()).sum()
+    }
+}
+
+/// The command line run by the platform shell
+fn shell(line: &str) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // Don't flash a console window from the GUI app
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut command = Command::new("cmd");
+        // raw_arg: cmd parses quotes itself, Rust's argument quoting would break them
+        command.arg("/C").raw_arg(line).creation_flags(CREATE_NO_WINDOW);
+        command
+    }
+    #[cfg(not(windows))]
+    {
+        let mut command = Command::new("sh");
+        command.args(["-c", line]);
+        command
+    }
+}
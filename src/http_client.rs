@@ -0,0 +1,111 @@
+//! Minimal blocking HTTP/1.0 client for outbound requests
+//! Plain http:// only - used for directory announcements and metadata lookups
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Parsed HTTP response
+#[derive(Debug, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// Header names are lower-cased
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Send a form-encoded POST and read the whole response
+pub fn post_form(url: &str, fields: &[(&str, &str)]) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let (host, port, path) = parse_http_url(url)?;
+
+    let body = fields
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, form_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut stream = TcpStream::connect((host.as_str(), port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+
+    let request = format!(
+        "POST {} HTTP/1.0\r\n\
+         Host: {}\r\n\
+         User-Agent: RustCast/{}\r\n\
+         Content-Type: application/x-www-form-urlencoded\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {}",
+        path,
+        host,
+        env!("CARGO_PKG_VERSION"),
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    Ok(parse_response(&String::from_utf8_lossy(&response)))
+}
+
+fn parse_response(response: &str) -> HttpResponse {
+    let mut result = HttpResponse::default();
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .or_else(|| response.split_once("\n\n"))
+        .unwrap_or((response, ""));
+    result.body = body.to_string();
+
+    let mut lines = head.lines();
+    if let Some(status_line) = lines.next() {
+        result.status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+    }
+    for line in lines {
+        if let Some((key, value)) = line.split_once(':') {
+            result
+                .headers
+                .push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    result
+}
+
+/// Split "http://host[:port]/path" into its parts
+fn parse_http_url(url: &str) -> Result<(String, u16, String), Box<dyn std::error::Error>> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("Only http:// URLs are supported")?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse()?),
+        None => (authority, 80),
+    };
+
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+/// application/x-www-form-urlencoded encoding
+fn form_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            b' ' => out.push('+'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
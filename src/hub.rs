@@ -0,0 +1,854 @@
+//! Broadcast hub for encoded audio
+//! Fans out encoded packets from the encoder to every connected sink (WebSocket, HTTP/Ogg, ...)
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use crate::drops::{self, Stage};
+use crate::events::{self, AppEvent};
+use crate::resources;
+
+/// Encoded packet shared between all subscribers (cloning only bumps a refcount), with the
+/// audio it covers as reported by the encoder
+#[derive(Debug, Clone)]
+pub struct Packet {
+    data: Arc<[u8]>,
+    /// Samples per channel at 48kHz (Opus); 0 where unknown (MP3 frames, Vorbis pages)
+    samples: u32,
+}
+
+impl Packet {
+    /// An Opus packet that decodes to `samples` samples per channel (at 48kHz)
+    pub fn with_samples(data: Vec<u8>, samples: u32) -> Self {
+        Self { data: data.into(), samples }
+    }
+
+    /// Samples per channel at 48kHz, 0 if the producer didn't say
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+}
+
+impl std::ops::Deref for Packet {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Vec<u8>> for Packet {
+    fn from(data: Vec<u8>) -> Self {
+        Self { data: data.into(), samples: 0 }
+    }
+}
+
+impl From<&[u8]> for Packet {
+    fn from(data: &[u8]) -> Self {
+        Self { data: data.into(), samples: 0 }
+    }
+}
+
+/// Item queued for a subscriber
+#[derive(Debug, Clone)]
+pub enum Outgoing {
+    /// Encoded audio packet
+    Audio(Packet),
+    /// Text/control message (JSON), only carried by sinks that support it (WebSocket)
+    Text(Arc<str>),
+    /// Encoded packet of an extra channel (1 = application), for sinks that multiplex channels
+    Channel(u8, Packet),
+}
+
+/// How much a packet matters when a subscriber's queue is congested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FramePriority {
+    /// Silence/DTX frames, the decoder conceals them without audible artifacts
+    Droppable,
+    Normal,
+    /// Onsets after silence, dropping these is clearly audible
+    Critical,
+}
+
+impl FramePriority {
+    /// Queue length from which packets of this priority are dropped
+    fn drop_threshold(self, capacity: usize, class: ClientClass) -> usize {
+        match (class, self) {
+            (ClientClass::Realtime, FramePriority::Droppable) => capacity / 2,
+            (ClientClass::Realtime, FramePriority::Normal) => capacity * 9 / 10,
+            (ClientClass::Buffered, FramePriority::Droppable) => capacity * 9 / 10,
+            (_, _) => capacity,
+        }
+    }
+}
+
+/// Per-subscriber queue length in packets (~5s of 20ms Opus frames)
+pub const DEFAULT_QUEUE_CAPACITY: usize = 250;
+
+/// Recent packets a buffered subscriber starts with (~2s of 20ms Opus frames)
+pub const PREROLL_PACKETS: usize = 100;
+
+/// How a subscriber trades latency for robustness (`?mode=realtime|buffered`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientClass {
+    /// Joins live, drops early under congestion so the delay stays short
+    #[default]
+    Realtime,
+    /// Starts with a burst of recent audio, has twice the queue and drops only when it is full
+    /// (speakers on flaky WiFi, players that buffer anyway)
+    Buffered,
+}
+
+impl ClientClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientClass::Realtime => "realtime",
+            ClientClass::Buffered => "buffered",
+        }
+    }
+
+    fn queue_capacity(self, base: usize) -> usize {
+        match self {
+            ClientClass::Realtime => base,
+            ClientClass::Buffered => base * 2,
+        }
+    }
+}
+
+impl std::str::FromStr for ClientClass {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "realtime" => Ok(ClientClass::Realtime),
+            "buffered" => Ok(ClientClass::Buffered),
+            _ => Err(format!("Unknown client mode: {} (realtime, buffered)", name)),
+        }
+    }
+}
+
+/// Kind of output a subscriber feeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkKind {
+    WebSocket,
+    Http,
+    /// Embedded receiver on the raw TCP port
+    Tcp,
+    /// Server-side output (recorder, ...), not counted as a listener
+    Output,
+}
+
+/// Which encoding of the stream a subscriber receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tier {
+    /// The main encoder output
+    Full,
+    /// Low-bitrate re-encode for slow connections (see `tier.rs`)
+    Low,
+}
+
+impl Tier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tier::Full => "full",
+            Tier::Low => "low",
+        }
+    }
+}
+
+impl std::str::FromStr for Tier {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "full" => Ok(Tier::Full),
+            "low" => Ok(Tier::Low),
+            _ => Err(format!("Unknown tier: {}", name)),
+        }
+    }
+}
+
+impl SinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SinkKind::WebSocket => "websocket",
+            SinkKind::Http => "http",
+            SinkKind::Tcp => "tcp",
+            SinkKind::Output => "output",
+        }
+    }
+}
+
+/// Number of connected clients per output type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientCounts {
+    pub websocket: usize,
+    pub http: usize,
+    pub tcp: usize,
+}
+
+impl ClientCounts {
+    pub fn total(&self) -> usize {
+        self.websocket + self.http + self.tcp
+    }
+}
+
+impl std::fmt::Display for ClientCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (WebSocket {}, HTTP {}, TCP {})", self.total(), self.websocket, self.http, self.tcp)
+    }
+}
+
+/// Counters kept for each subscriber
+#[derive(Debug, Default)]
+pub struct SubscriberStats {
+    /// Packets queued for this subscriber
+    pub queued: AtomicU64,
+    /// Packets dropped because the subscriber's queue was full
+    pub dropped: AtomicU64,
+    /// Bytes written to the subscriber's socket
+    pub bytes_sent: AtomicU64,
+}
+
+/// Point-in-time copy of a subscriber's state
+#[derive(Debug, Clone)]
+pub struct SubscriberSnapshot {
+    pub id: u64,
+    pub kind: SinkKind,
+    pub tier: Tier,
+    pub class: ClientClass,
+    pub queued: u64,
+    pub dropped: u64,
+    pub bytes_sent: u64,
+    pub backlog: usize,
+    pub remote_addr: Option<SocketAddr>,
+    /// Unattended player (`/kiosk`)
+    pub kiosk: bool,
+    /// Device ID the web player sent (`?device=`)
+    pub device: Option<String>,
+}
+
+struct Subscriber {
+    id: u64,
+    /// Unique per connection (a resumed session reuses `id`, never `key`)
+    key: u64,
+    kind: SinkKind,
+    tier: Tier,
+    class: ClientClass,
+    remote_addr: Option<SocketAddr>,
+    /// Unattended player (`/kiosk`)
+    kiosk: bool,
+    /// Device ID the web player sent (`?device=`)
+    device: Option<String>,
+    /// Also receives the extra channels (`opus-mux` WebSocket players)
+    channels: bool,
+    tx: Sender<Outgoing>,
+    /// Queue length of `tx`
+    capacity: usize,
+    stats: Arc<SubscriberStats>,
+    /// The previous packet was dropped for this subscriber
+    dropped_last: bool,
+}
+
+/// Sees every audio packet before it is queued for anyone (shared Ogg muxing)
+pub trait PacketTap: Send + Sync {
+    fn published(&self, tier: Tier, packet: &Packet);
+}
+
+/// Fan-out point between the encoder and all output sinks
+pub struct BroadcastHub {
+    subscribers: Mutex<Vec<Subscriber>>,
+    next_id: AtomicU64,
+    queue_capacity: usize,
+    /// How long a dropped WebSocket session can be resumed under its old id
+    resume_grace: Duration,
+    /// Ended WebSocket sessions still within the grace period: id -> (ended at, address)
+    ended: Mutex<HashMap<u64, (Instant, Option<SocketAddr>)>>,
+    /// Last packets of each tier, the pre-roll of buffered subscribers
+    recent: Mutex<HashMap<Tier, VecDeque<Packet>>>,
+    taps: Mutex<Vec<Arc<dyn PacketTap>>>,
+    /// Publish listeners joining and leaving on the event bus
+    announce: bool,
+}
+
+impl Default for BroadcastHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BroadcastHub {
+    /// Create a new hub with the default per-subscriber queue length
+    pub fn new() -> Self {
+        Self::with_queue_capacity(DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Create a new hub with a custom per-subscriber queue length
+    pub fn with_queue_capacity(queue_capacity: usize) -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            queue_capacity: queue_capacity.max(1),
+            resume_grace: Duration::ZERO,
+            ended: Mutex::new(HashMap::new()),
+            recent: Mutex::new(HashMap::new()),
+            taps: Mutex::new(Vec::new()),
+            announce: false,
+        }
+    }
+
+    /// Let WebSocket players that lose their connection resume the same session
+    /// (same client id, sleep timer, ...) within `grace`
+    pub fn with_resume_grace(mut self, grace: Duration) -> Self {
+        self.resume_grace = grace;
+        self
+    }
+
+    /// Publish `ClientConnected` / `ClientDisconnected` events (the hubs listeners play from,
+    /// not internal ones like the A/B comparison)
+    pub fn announce_clients(mut self) -> Self {
+        self.announce = true;
+        self
+    }
+
+    /// Show every published audio packet to `tap` before any subscriber can receive it
+    pub fn add_tap(&self, tap: Arc<dyn PacketTap>) {
+        self.taps.lock().unwrap().push(tap);
+    }
+
+    /// Register a new subscriber. It is removed again when the `Subscription` is dropped,
+    /// so client counts stay correct on every exit path of the sink.
+    pub fn subscribe(self: &Arc<Self>, kind: SinkKind, remote_addr: Option<SocketAddr>) -> Subscription {
+        self.subscribe_tier(kind, remote_addr, Tier::Full)
+    }
+
+    /// Register a new subscriber receiving the given tier
+    pub fn subscribe_tier(self: &Arc<Self>, kind: SinkKind, remote_addr: Option<SocketAddr>, tier: Tier) -> Subscription {
+        self.subscribe_class(kind, remote_addr, tier, ClientClass::Realtime)
+    }
+
+    /// Register a new subscriber receiving the given tier with the given latency/robustness trade-off
+    pub fn subscribe_class(
+        self: &Arc<Self>,
+        kind: SinkKind,
+        remote_addr: Option<SocketAddr>,
+        tier: Tier,
+        class: ClientClass,
+    ) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let subscription = self.add_subscriber(id, kind, remote_addr, tier, class);
+        let mut detail = kind.as_str().to_string();
+        if tier != Tier::Full {
+            detail.push_str(&format!(", {} tier", tier.as_str()));
+        }
+        if class != ClientClass::Realtime {
+            detail.push_str(&format!(", {}", class.as_str()));
+        }
+        log::info!("Client #{} connected ({}). Total: {}", id, detail, self.client_counts());
+        self.announce_connected(id, kind, remote_addr);
+        subscription
+    }
+
+    /// Register a WebSocket player that had the session `previous_id` before.
+    ///
+    /// The old id is reused if that session ended within the resume grace, or if it is
+    /// still registered from the same IP (a backgrounded phone whose old socket hasn't
+    /// timed out yet - that connection is closed). Otherwise this is a new session.
+    pub fn resume(self: &Arc<Self>, remote_addr: Option<SocketAddr>, previous_id: u64, tier: Tier, class: ClientClass) -> Subscription {
+        let same_ip = |addr: Option<SocketAddr>| {
+            matches!((addr, remote_addr), (Some(a), Some(b)) if a.ip() == b.ip())
+        };
+
+        let resumable = !self.resume_grace.is_zero() && {
+            let mut ended = self.ended.lock().unwrap();
+            ended.retain(|_, (at, _)| at.elapsed() < self.resume_grace);
+            match ended.get(&previous_id) {
+                Some((_, addr)) if same_ip(*addr) => {
+                    ended.remove(&previous_id);
+                    true
+                }
+                Some(_) => false,
+                None => {
+                    let mut subscribers = self.subscribers.lock().unwrap();
+                    let before = subscribers.len();
+                    subscribers.retain(|sub| {
+                        !(sub.id == previous_id && sub.kind == SinkKind::WebSocket && same_ip(sub.remote_addr))
+                    });
+                    subscribers.len() != before
+                }
+            }
+        };
+
+        if !resumable {
+            return self.subscribe_class(SinkKind::WebSocket, remote_addr, tier, class);
+        }
+        let subscription = self.add_subscriber(previous_id, SinkKind::WebSocket, remote_addr, tier, class);
+        log::info!("Client #{} resumed (websocket). Total: {}", previous_id, self.client_counts());
+        self.announce_connected(previous_id, SinkKind::WebSocket, remote_addr);
+        subscription
+    }
+
+    fn add_subscriber(
+        self: &Arc<Self>,
+        id: u64,
+        kind: SinkKind,
+        remote_addr: Option<SocketAddr>,
+        tier: Tier,
+        class: ClientClass,
+    ) -> Subscription {
+        let capacity = class.queue_capacity(self.queue_capacity);
+        let (tx, rx) = crossbeam_channel::bounded(capacity);
+        let key = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let stats = Arc::new(SubscriberStats::default());
+
+        // Locked before `recent` (same order as publishing), so the pre-roll neither
+        // repeats nor misses a packet
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if class == ClientClass::Buffered {
+            if let Some(recent) = self.recent.lock().unwrap().get(&tier) {
+                for packet in recent {
+                    if tx.try_send(Outgoing::Audio(packet.clone())).is_ok() {
+                        stats.queued.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        subscribers.push(Subscriber {
+            id,
+            key,
+            kind,
+            tier,
+            class,
+            remote_addr,
+            kiosk: false,
+            device: None,
+            channels: false,
+            tx,
+            capacity,
+            stats: stats.clone(),
+            dropped_last: false,
+        });
+        drop(subscribers);
+
+        Subscription {
+            id,
+            key,
+            rx,
+            stats,
+            hub: Arc::downgrade(self),
+        }
+    }
+
+    /// Queue a packet for every full-tier subscriber. Returns the number of subscribers it reached.
+    ///
+    /// A subscriber whose queue is full loses this packet instead of stalling the others.
+    pub fn publish(&self, packet: Packet) -> usize {
+        self.publish_with_priority(packet, FramePriority::Normal)
+    }
+
+    /// Queue a packet, dropping it early for congested subscribers if it is unimportant.
+    ///
+    /// Low-priority packets are dropped once a queue is half full, leaving the remaining
+    /// room for the rest. After a drop the next packet is kept if at all possible, so
+    /// losses are spread out (single lost frames are concealed well, bursts are not).
+    pub fn publish_with_priority(&self, packet: Packet, priority: FramePriority) -> usize {
+        self.publish_tier(Tier::Full, packet, priority)
+    }
+
+    /// Queue a packet for the subscribers of one tier
+    pub fn publish_tier(&self, tier: Tier, packet: Packet, priority: FramePriority) -> usize {
+        for tap in self.taps.lock().unwrap().iter() {
+            tap.published(tier, &packet);
+        }
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut delivered = 0;
+
+        {
+            let mut recent = self.recent.lock().unwrap();
+            let recent = recent.entry(tier).or_default();
+            if recent.len() >= PREROLL_PACKETS {
+                recent.pop_front();
+            }
+            recent.push_back(packet.clone());
+        }
+
+        subscribers.retain_mut(|sub| {
+            if sub.tier != tier {
+                return true;
+            }
+            let priority = if sub.dropped_last { FramePriority::Critical } else { priority };
+            let result = if sub.tx.len() >= priority.drop_threshold(sub.capacity, sub.class) {
+                Err(TrySendError::Full(Outgoing::Audio(packet.clone())))
+            } else {
+                sub.tx.try_send(Outgoing::Audio(packet.clone()))
+            };
+            match result {
+                Ok(_) => {
+                    sub.stats.queued.fetch_add(1, Ordering::Relaxed);
+                    sub.dropped_last = false;
+                    delivered += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    sub.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    drops::record(Stage::Client);
+                    sub.dropped_last = true;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+
+        delivered
+    }
+
+    /// Queue a text message for every WebSocket subscriber
+    pub fn publish_text(&self, text: &str) {
+        let text: Arc<str> = Arc::from(text);
+        for sub in self.subscribers.lock().unwrap().iter() {
+            if sub.kind == SinkKind::WebSocket {
+                Self::queue_text(sub, text.clone());
+            }
+        }
+    }
+
+    /// Queue a text message for a single subscriber. Returns false if it is gone.
+    pub fn send_text_to(&self, id: u64, text: &str) -> bool {
+        match self.subscribers.lock().unwrap().iter().find(|sub| sub.id == id) {
+            Some(sub) => {
+                Self::queue_text(sub, Arc::from(text));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn queue_text(sub: &Subscriber, text: Arc<str>) {
+        if sub.tx.try_send(Outgoing::Text(text)).is_err() {
+            sub.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Queue a packet of an extra channel for the subscribers that joined the channels.
+    /// Channel audio yields to the main stream: it is dropped once a queue is half full.
+    pub fn publish_channel(&self, channel: u8, packet: Packet) -> usize {
+        let mut delivered = 0;
+        for sub in self.subscribers.lock().unwrap().iter().filter(|sub| sub.channels) {
+            if sub.tx.len() >= sub.capacity / 2 || sub.tx.try_send(Outgoing::Channel(channel, packet.clone())).is_err() {
+                sub.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                drops::record(Stage::Client);
+            } else {
+                sub.stats.queued.fetch_add(1, Ordering::Relaxed);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Let a connected subscriber receive the extra channels. Returns false if it is gone.
+    pub fn join_channels(&self, id: u64) -> bool {
+        match self.subscribers.lock().unwrap().iter_mut().find(|sub| sub.id == id) {
+            Some(sub) => {
+                sub.channels = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of subscribers receiving the extra channels
+    pub fn channel_count(&self) -> usize {
+        self.subscribers.lock().unwrap().iter().filter(|sub| sub.channels).count()
+    }
+
+    /// Move a connected subscriber to another tier. Returns false if it is gone.
+    pub fn set_tier(&self, id: u64, tier: Tier) -> bool {
+        match self.subscribers.lock().unwrap().iter_mut().find(|sub| sub.id == id) {
+            Some(sub) => {
+                if sub.tier != tier {
+                    log::info!("Client #{} moved to the {} tier", id, tier.as_str());
+                }
+                sub.tier = tier;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mark a connected subscriber as a kiosk player (shown in client lists)
+    pub fn mark_kiosk(&self, id: u64) {
+        if let Some(sub) = self.subscribers.lock().unwrap().iter_mut().find(|sub| sub.id == id) {
+            if !sub.kiosk {
+                log::info!("Client #{} is a kiosk player", id);
+            }
+            sub.kiosk = true;
+        }
+    }
+
+    /// Remember the device ID a connected subscriber's player sent (for bans by device)
+    pub fn set_device(&self, id: u64, device: String) {
+        if let Some(sub) = self.subscribers.lock().unwrap().iter_mut().find(|sub| sub.id == id) {
+            sub.device = Some(device);
+        }
+    }
+
+    /// Number of subscribers receiving a tier
+    pub fn tier_count(&self, tier: Tier) -> usize {
+        self.subscribers.lock().unwrap().iter().filter(|sub| sub.tier == tier).count()
+    }
+
+    /// Whether a subscriber is still registered
+    pub fn is_connected(&self, id: u64) -> bool {
+        self.subscribers.lock().unwrap().iter().any(|sub| sub.id == id)
+    }
+
+    /// Whether a session is connected or may still be resumed
+    pub fn is_alive(&self, id: u64) -> bool {
+        self.is_connected(id)
+            || self
+                .ended
+                .lock()
+                .unwrap()
+                .get(&id)
+                .is_some_and(|(at, _)| at.elapsed() < self.resume_grace)
+    }
+
+    /// Address a subscriber connected from
+    pub fn remote_addr(&self, id: u64) -> Option<SocketAddr> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|sub| sub.id == id)
+            .and_then(|sub| sub.remote_addr)
+    }
+
+    /// Drop a subscriber's queue so its sink finishes after the items already queued.
+    /// Returns false if it is already gone. The session can't be resumed afterwards.
+    pub fn disconnect(&self, id: u64) -> bool {
+        self.ended.lock().unwrap().remove(&id);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let before = subscribers.len();
+        subscribers.retain(|sub| sub.id != id);
+        subscribers.len() != before
+    }
+
+    /// Drop every subscriber (shutdown); each sink finishes what is queued and ends its stream.
+    /// Returns how many there were.
+    pub fn disconnect_all(&self) -> usize {
+        self.ended.lock().unwrap().clear();
+        std::mem::take(&mut *self.subscribers.lock().unwrap()).len()
+    }
+
+    /// Connected clients per output type
+    pub fn client_counts(&self) -> ClientCounts {
+        Self::count(&self.subscribers.lock().unwrap())
+    }
+
+    fn count(subscribers: &[Subscriber]) -> ClientCounts {
+        let mut counts = ClientCounts::default();
+        for sub in subscribers {
+            match sub.kind {
+                SinkKind::WebSocket => counts.websocket += 1,
+                SinkKind::Http => counts.http += 1,
+                SinkKind::Tcp => counts.tcp += 1,
+                SinkKind::Output => {}
+            }
+        }
+        counts
+    }
+
+    /// Snapshot of every subscriber's counters
+    pub fn snapshot(&self) -> Vec<SubscriberSnapshot> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|sub| SubscriberSnapshot {
+                id: sub.id,
+                kind: sub.kind,
+                tier: sub.tier,
+                class: sub.class,
+                queued: sub.stats.queued.load(Ordering::Relaxed),
+                dropped: sub.stats.dropped.load(Ordering::Relaxed),
+                bytes_sent: sub.stats.bytes_sent.load(Ordering::Relaxed),
+                backlog: sub.tx.len(),
+                remote_addr: sub.remote_addr,
+                kiosk: sub.kiosk,
+                device: sub.device.clone(),
+            })
+            .collect()
+    }
+
+    fn unsubscribe(&self, id: u64, key: u64) {
+        let (removed, counts) = {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            let removed = subscribers
+                .iter()
+                .position(|sub| sub.key == key)
+                .map(|index| subscribers.remove(index));
+            (removed, Self::count(&subscribers))
+        };
+        // Only sessions that dropped on their own can be resumed
+        if let Some(sub) = removed {
+            if sub.kind == SinkKind::WebSocket && !self.resume_grace.is_zero() {
+                self.ended.lock().unwrap().insert(id, (Instant::now(), sub.remote_addr));
+            }
+            log::info!("Client #{} disconnected. Total: {}", id, counts);
+            if self.announce && sub.kind != SinkKind::Output {
+                events::publish(AppEvent::ClientDisconnected { id });
+            }
+        }
+    }
+
+    /// Recordings and other outputs aren't listeners
+    fn announce_connected(&self, id: u64, kind: SinkKind, remote_addr: Option<SocketAddr>) {
+        if self.announce && kind != SinkKind::Output {
+            events::publish(AppEvent::ClientConnected { id, kind: kind.as_str(), ip: remote_addr.map(|addr| addr.ip()) });
+        }
+    }
+}
+
+/// Receiving end of a hub subscription
+pub struct Subscription {
+    pub id: u64,
+    key: u64,
+    rx: Receiver<Outgoing>,
+    stats: Arc<SubscriberStats>,
+    hub: Weak<BroadcastHub>,
+}
+
+impl Subscription {
+    /// Block until the next item arrives. Returns `None` once the hub is gone.
+    pub fn recv(&self) -> Option<Outgoing> {
+        self.rx.recv().ok()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(hub) = self.hub.upgrade() {
+            hub.unsubscribe(self.id, self.key);
+        }
+    }
+}
+
+/// Converts encoded packets into the wire format of one output protocol
+pub trait SinkAdapter {
+    /// Bytes written once before the first packet (container headers, etc.)
+    fn preamble(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Wrap a single encoded packet for the wire
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8>;
+
+    /// Wrap a text message for the wire, or `None` if the protocol can't carry text
+    fn wrap_text(&mut self, _text: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Wrap a packet of an extra channel, or `None` if the protocol carries only the main stream
+    fn wrap_channel(&mut self, _channel: u8, _packet: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Flush the writer after every packet
+    fn flush_each_packet(&self) -> bool {
+        true
+    }
+
+    /// Bytes written once when the hub ends the subscription (end-of-stream page, close frame)
+    fn finish(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+/// Subscribers disconnected because they stopped reading (socket write timeout)
+static WRITE_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+/// Clients reaped since startup because a write timed out
+pub fn write_timeouts() -> u64 {
+    WRITE_TIMEOUTS.load(Ordering::Relaxed)
+}
+
+/// Connections currently inside `pump`
+static PUMPING: AtomicUsize = AtomicUsize::new(0);
+
+/// Connections still writing (shutdown waits for their last bytes)
+pub fn pumping() -> usize {
+    PUMPING.load(Ordering::SeqCst)
+}
+
+/// Drive a subscription into a writer through the given adapter until the peer goes away.
+///
+/// A peer that keeps the connection open but stops reading fails the write once the socket's
+/// write timeout (`socket.write_timeout_secs`) passes, which ends the subscription like any other
+/// disconnect.
+pub fn pump<W: Write + ?Sized>(
+    subscription: &Subscription,
+    adapter: &mut dyn SinkAdapter,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    PUMPING.fetch_add(1, Ordering::SeqCst);
+    let result = pump_until_error(subscription, adapter, writer);
+    PUMPING.fetch_sub(1, Ordering::SeqCst);
+    if let Err(e) = &result {
+        if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) {
+            WRITE_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            log::info!("Client #{} stopped reading, disconnecting (write timed out)", subscription.id);
+        }
+    }
+    result
+}
+
+fn pump_until_error<W: Write + ?Sized>(
+    subscription: &Subscription,
+    adapter: &mut dyn SinkAdapter,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    let preamble = adapter.preamble();
+    if !preamble.is_empty() {
+        writer.write_all(&preamble)?;
+        writer.flush()?;
+        subscription.stats.bytes_sent.fetch_add(preamble.len() as u64, Ordering::Relaxed);
+        resources::record_sent(preamble.len());
+    }
+
+    while let Some(item) = subscription.recv() {
+        let data = match item {
+            Outgoing::Audio(packet) => adapter.wrap(&packet),
+            Outgoing::Text(text) => match adapter.wrap_text(&text) {
+                Some(data) => data,
+                None => continue,
+            },
+            Outgoing::Channel(channel, packet) => match adapter.wrap_channel(channel, &packet) {
+                Some(data) => data,
+                None => continue,
+            },
+        };
+        // Adapters that batch packets (Ogg page grouping) return nothing until a batch is full
+        if data.is_empty() {
+            continue;
+        }
+        writer.write_all(&data)?;
+        if adapter.flush_each_packet() {
+            writer.flush()?;
+        }
+        subscription.stats.bytes_sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+        resources::record_sent(data.len());
+    }
+
+    // Disconnected by the hub (shutdown, kick, codec switch): end the stream cleanly
+    let tail = adapter.finish();
+    if !tail.is_empty() {
+        writer.write_all(&tail)?;
+        resources::record_sent(tail.len());
+    }
+    writer.flush()
+}
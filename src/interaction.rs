@@ -0,0 +1,192 @@
+//! Listener interactions: emoji reactions and song requests
+//! Posted via POST /api/react and /api/request, surfaced to the host as tray toasts and GUI counters
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Emoji listeners can react with
+pub const REACTIONS: [&str; 6] = ["👍", "❤️", "🔥", "😂", "👏", "🎵"];
+/// Maximum song request length (characters)
+pub const MAX_REQUEST_LEN: usize = 120;
+/// Song requests kept for the host
+const REQUEST_HISTORY_LEN: usize = 50;
+/// Toasts waiting for the GUI are capped so an idle window doesn't pile them up
+const MAX_PENDING_TOASTS: usize = 20;
+
+/// A song request from a listener
+#[derive(Debug, Clone, Serialize)]
+pub struct SongRequest {
+    pub id: u64,
+    pub text: String,
+    /// Unix time in seconds
+    pub timestamp: u64,
+}
+
+/// Why an interaction was rejected
+#[derive(Debug)]
+pub enum InteractionError {
+    RateLimited,
+    UnknownReaction,
+    Empty,
+}
+
+impl std::fmt::Display for InteractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InteractionError::RateLimited => write!(f, "Too many requests, slow down"),
+            InteractionError::UnknownReaction => write!(f, "Unknown reaction"),
+            InteractionError::Empty => write!(f, "Request is empty"),
+        }
+    }
+}
+
+impl std::error::Error for InteractionError {}
+
+/// Sliding-window limiter keyed by client address
+pub struct RateLimiter {
+    max_hits: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_hits: usize, window: Duration) -> Self {
+        Self {
+            max_hits,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a hit for `key`. Returns false if the key is over its limit.
+    pub fn check(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+
+        // Forget clients whose window has fully passed
+        hits.retain(|_, times| times.back().is_some_and(|t| now.duration_since(*t) < self.window));
+
+        let times = hits.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= self.window) {
+            times.pop_front();
+        }
+        if times.len() >= self.max_hits {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// Reaction counters, song requests, and pending host toasts
+pub struct Interactions {
+    reactions: Mutex<HashMap<&'static str, u64>>,
+    requests: Mutex<VecDeque<SongRequest>>,
+    toasts: Mutex<VecDeque<String>>,
+    next_request_id: AtomicU64,
+    reaction_limiter: RateLimiter,
+    request_limiter: RateLimiter,
+}
+
+impl Default for Interactions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interactions {
+    pub fn new() -> Self {
+        Self {
+            reactions: Mutex::new(HashMap::new()),
+            requests: Mutex::new(VecDeque::with_capacity(REQUEST_HISTORY_LEN)),
+            toasts: Mutex::new(VecDeque::new()),
+            next_request_id: AtomicU64::new(1),
+            // 5 reactions per 10s, 1 song request per 30s per client
+            reaction_limiter: RateLimiter::new(5, Duration::from_secs(10)),
+            request_limiter: RateLimiter::new(1, Duration::from_secs(30)),
+        }
+    }
+
+    /// Count a reaction from a listener
+    pub fn react(&self, ip: &str, emoji: &str) -> Result<(), InteractionError> {
+        let emoji = REACTIONS
+            .iter()
+            .copied()
+            .find(|r| *r == emoji)
+            .ok_or(InteractionError::UnknownReaction)?;
+        if !self.reaction_limiter.check(ip) {
+            return Err(InteractionError::RateLimited);
+        }
+
+        *self.reactions.lock().unwrap().entry(emoji).or_insert(0) += 1;
+        self.push_toast(format!("{} 반응", emoji));
+        Ok(())
+    }
+
+    /// Store a song request from a listener
+    pub fn request_song(&self, ip: &str, text: &str) -> Result<SongRequest, InteractionError> {
+        let text: String = text
+            .trim()
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_REQUEST_LEN)
+            .collect();
+        if text.is_empty() {
+            return Err(InteractionError::Empty);
+        }
+        if !self.request_limiter.check(ip) {
+            return Err(InteractionError::RateLimited);
+        }
+
+        let request = SongRequest {
+            id: self.next_request_id.fetch_add(1, Ordering::SeqCst),
+            text,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+
+        {
+            let mut requests = self.requests.lock().unwrap();
+            if requests.len() >= REQUEST_HISTORY_LEN {
+                requests.pop_front();
+            }
+            requests.push_back(request.clone());
+        }
+
+        log::info!("[REQUEST] #{} from {}: {}", request.id, ip, request.text);
+        self.push_toast(format!("🎶 신청곡: {}", request.text));
+        Ok(request)
+    }
+
+    /// Reaction counts in display order
+    pub fn reaction_counts(&self) -> Vec<(&'static str, u64)> {
+        let reactions = self.reactions.lock().unwrap();
+        REACTIONS
+            .iter()
+            .map(|r| (*r, reactions.get(r).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    /// Recent song requests, oldest first
+    pub fn requests(&self) -> Vec<SongRequest> {
+        self.requests.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Drain toasts the host hasn't seen yet
+    pub fn take_toasts(&self) -> Vec<String> {
+        self.toasts.lock().unwrap().drain(..).collect()
+    }
+
+    fn push_toast(&self, text: String) {
+        let mut toasts = self.toasts.lock().unwrap();
+        if toasts.len() >= MAX_PENDING_TOASTS {
+            toasts.pop_front();
+        }
+        toasts.push_back(text);
+    }
+}
@@ -0,0 +1,96 @@
+//! Local command port
+//! The control API as JSON lines over TCP on 127.0.0.1, for AutoHotkey scripts and Stream Deck
+//! plugins that can't (or shouldn't) go through HTTP
+//!
+//! Enabled with `ipc_port`. Each line sent is one call, each line received its answer:
+//!
+//! ```text
+//! > {"method": "POST", "path": "/api/stream", "body": {"streaming": true}}
+//! < {"status": 200, "body": {"ok": true, "streaming": true}}
+//! > {"method": "GET", "path": "/status", "token": "secret"}
+//! < {"status": 200, "body": {...}}
+//! ```
+//!
+//! `method` defaults to GET; `token` is only needed when `control_tokens` are set. Paths,
+//! bodies and answers are those of the control API; non-JSON answers (`/metrics`) are strings.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use tiny_http::Method;
+
+use crate::control::{self, ControlContext};
+
+/// Longest accepted command line
+const MAX_LINE: u64 = 64 * 1024;
+
+/// Listen on 127.0.0.1:`port`, one thread per connection
+pub fn spawn(port: u16, ctx: Arc<ControlContext>) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| format!("Failed to open the IPC port {}: {}", port, e))?;
+    log::info!("[IPC] Commands on 127.0.0.1:{}", port);
+
+    thread::Builder::new().name("ipc".into()).spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let ctx = ctx.clone();
+            let result = thread::Builder::new().name("ipc-client".into()).spawn(move || {
+                if let Err(e) = serve(stream, &ctx) {
+                    log::debug!("[IPC] Connection closed: {}", e);
+                }
+            });
+            if let Err(e) = result {
+                log::warn!("[IPC] Connection refused: {}", e);
+            }
+        }
+    })?;
+    Ok(())
+}
+
+fn serve(stream: TcpStream, ctx: &ControlContext) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if reader.by_ref().take(MAX_LINE).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = execute(line.trim(), ctx);
+        writer.write_all(format!("{}\n", answer).as_bytes())?;
+        writer.flush()?;
+    }
+}
+
+/// Run one command line through the control API
+fn execute(line: &str, ctx: &ControlContext) -> serde_json::Value {
+    let Ok(command) = serde_json::from_str::<serde_json::Value>(line) else {
+        return answer(400, serde_json::json!({ "error": "Expected one JSON object per line" }));
+    };
+    let Some(path) = command["path"].as_str().filter(|path| path.starts_with('/')) else {
+        return answer(400, serde_json::json!({ "error": "Expected \"path\": \"/...\"" }));
+    };
+    let method = match command["method"].as_str().unwrap_or("GET").to_uppercase().as_str() {
+        "GET" => Method::Get,
+        "POST" => Method::Post,
+        "DELETE" => Method::Delete,
+        "PATCH" => Method::Patch,
+        other => return answer(405, serde_json::json!({ "error": format!("Unsupported method {}", other) })),
+    };
+    let Some(role) = control::role_of(command["token"].as_str(), &ctx.tokens) else {
+        return answer(401, serde_json::json!({ "error": "Missing or invalid token" }));
+    };
+
+    let body = command.get("body").cloned();
+    let response = control::route(&method, path, body, ctx, role);
+    let status = response.status_code().0;
+    let data = response.into_reader().into_inner();
+    let text = String::from_utf8_lossy(&data);
+    answer(status, serde_json::from_str(&text).unwrap_or_else(|_| serde_json::Value::String(text.into_owned())))
+}
+
+fn answer(status: u16, body: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "status": status, "body": body })
+}
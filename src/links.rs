@@ -0,0 +1,41 @@
+//! Public links
+//! Addresses handed to listeners (player WebSocket URL, share link, directory listing)
+
+use std::net::{IpAddr, UdpSocket};
+
+/// `public_url` without the trailing slash, or None when unset or not http(s)
+pub fn public_base(public_url: &str) -> Option<String> {
+    let url = public_url.trim().trim_end_matches('/');
+    if url.is_empty() {
+        return None;
+    }
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        log::warn!("[LINKS] public_url must start with http:// or https:// (got {:?}), ignoring it", url);
+        return None;
+    }
+    Some(url.to_string())
+}
+
+/// WebSocket endpoint under an http(s) base URL (https gives wss)
+pub fn ws_url(base: &str) -> String {
+    match base.strip_prefix("https://") {
+        Some(rest) => format!("wss://{}/ws", rest),
+        None => format!("ws://{}/ws", base.trim_start_matches("http://")),
+    }
+}
+
+/// Player link for other people: `public_url`, or this machine's LAN address
+pub fn share_url(public_url: &str, port: u16) -> String {
+    if let Some(base) = public_base(public_url) {
+        return format!("{}/", base);
+    }
+    let host = lan_ip().map(|ip| ip.to_string()).unwrap_or_else(|| "localhost".to_string());
+    format!("http://{}:{}/", host, port)
+}
+
+/// Address of the interface with the default route (nothing is sent)
+pub fn lan_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
@@ -0,0 +1,306 @@
+//! Loudness compliance logging
+//! ITU-R BS.1770 integrated loudness and true peak of the outgoing audio, logged per hour
+
+use std::f64::consts::PI;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dsp::AudioProcessor;
+use crate::sink::utc_timestamp;
+
+/// Blocks quieter than this are ignored (absolute gate)
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Blocks this far below the ungated level are ignored (relative gate)
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// True-peak oversampling factor and FIR taps per phase
+const OVERSAMPLE: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Loudness of one finished (or running) hour
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct HourSummary {
+    /// Start of the hour (Unix seconds, UTC)
+    pub hour_start: u64,
+    /// Integrated loudness (LUFS), None if the hour was silent
+    pub integrated_lufs: Option<f64>,
+    /// Highest true peak (dBTP)
+    pub true_peak_dbtp: f64,
+    /// Loudest 400ms block (LUFS)
+    pub max_momentary_lufs: Option<f64>,
+}
+
+/// Values shared with /status
+#[derive(Debug, Default)]
+struct Shared {
+    current: Option<HourSummary>,
+    last_hour: Option<HourSummary>,
+}
+
+/// Handle for reading the meter's results
+pub struct LoudnessLog {
+    shared: Mutex<Shared>,
+    target_lufs: f64,
+}
+
+impl LoudnessLog {
+    /// `{"target_lufs", "current_hour", "last_hour"}` for /status
+    pub fn summary(&self) -> serde_json::Value {
+        let shared = self.shared.lock().unwrap();
+        serde_json::json!({
+            "target_lufs": self.target_lufs,
+            "current_hour": shared.current,
+            "last_hour": shared.last_hour,
+        })
+    }
+}
+
+/// Second-order IIR section (direct form I)
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    fn run(&self, x: f64, state: &mut [f64; 4]) -> f64 {
+        let y = self.b[0] * x + self.b[1] * state[0] + self.b[2] * state[1] - self.a[0] * state[2] - self.a[1] * state[3];
+        *state = [x, state[0], y, state[2]];
+        y
+    }
+}
+
+/// K-weighting filter coefficients for `sample_rate` (BS.1770 pre-filter + RLB high-pass)
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Windowed-sinc interpolation filter, one row of taps per oversampling phase
+fn true_peak_filter() -> Vec<[f64; TAPS_PER_PHASE]> {
+    let len = OVERSAMPLE * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.0;
+    (0..OVERSAMPLE)
+        .map(|phase| {
+            let mut taps = [0.0; TAPS_PER_PHASE];
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let n = (i * OVERSAMPLE + phase) as f64;
+                let x = (n - center) / OVERSAMPLE as f64;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                let window = 0.5 - 0.5 * (2.0 * PI * n / (len - 1) as f64).cos();
+                *tap = sinc * window;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Measures the audio passing through the DSP chain (does not change it)
+pub struct LoudnessMeter {
+    log: Arc<LoudnessLog>,
+    csv_path: Option<PathBuf>,
+    channels: usize,
+    filters: [Biquad; 2],
+    filter_state: Vec<[[f64; 4]; 2]>,
+    peak_filter: Vec<[f64; TAPS_PER_PHASE]>,
+    peak_history: Vec<[f64; TAPS_PER_PHASE]>,
+    /// Frames per 100ms sub-block
+    step_frames: usize,
+    step_frames_done: usize,
+    step_energy: f64,
+    /// Last four 100ms sub-blocks, a gating block is 400ms with 75% overlap
+    steps: [f64; 4],
+    steps_filled: usize,
+    steps_since_publish: u32,
+    /// Mean-square energy of every gating block this hour
+    blocks: Vec<f64>,
+    hour_start: u64,
+    true_peak: f64,
+    max_block: f64,
+}
+
+impl LoudnessMeter {
+    /// Create the meter and the handle /status reads. Hourly rows are appended to `csv_path`.
+    pub fn new(sample_rate: u32, channels: u16, target_lufs: f64, csv_path: Option<PathBuf>) -> (Self, Arc<LoudnessLog>) {
+        let channels = channels.max(1) as usize;
+        let log = Arc::new(LoudnessLog {
+            shared: Mutex::new(Shared::default()),
+            target_lufs,
+        });
+        if let Some(path) = &csv_path {
+            log::info!("[LOUDNESS] Logging hourly loudness to {}", path.display());
+        }
+        let meter = Self {
+            log: log.clone(),
+            csv_path,
+            channels,
+            filters: k_weighting(sample_rate as f64),
+            filter_state: vec![[[0.0; 4]; 2]; channels],
+            peak_filter: true_peak_filter(),
+            peak_history: vec![[0.0; TAPS_PER_PHASE]; channels],
+            step_frames: (sample_rate as usize / 10).max(1),
+            step_frames_done: 0,
+            step_energy: 0.0,
+            steps: [0.0; 4],
+            steps_filled: 0,
+            steps_since_publish: 0,
+            blocks: Vec::new(),
+            hour_start: current_hour(),
+            true_peak: 0.0,
+            max_block: 0.0,
+        };
+        (meter, log)
+    }
+
+    fn true_peak_of(&mut self, channel: usize, sample: f64) -> f64 {
+        let history = &mut self.peak_history[channel];
+        history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+        history[0] = sample;
+        self.peak_filter
+            .iter()
+            .map(|taps| taps.iter().zip(history.iter()).map(|(t, x)| t * x).sum::<f64>().abs())
+            .fold(sample.abs(), f64::max)
+    }
+
+    /// Close a 100ms sub-block; every one completes a 400ms gating block
+    fn finish_step(&mut self) {
+        let energy = self.step_energy / self.step_frames as f64;
+        self.steps.rotate_left(1);
+        self.steps[3] = energy;
+        self.steps_filled = (self.steps_filled + 1).min(4);
+        self.step_energy = 0.0;
+        self.step_frames_done = 0;
+
+        if self.steps_filled == 4 {
+            let block = self.steps.iter().sum::<f64>() / 4.0;
+            self.blocks.push(block);
+            self.max_block = self.max_block.max(block);
+        }
+
+        // Publish about once a second
+        self.steps_since_publish += 1;
+        if self.steps_since_publish >= 10 {
+            self.steps_since_publish = 0;
+            let summary = self.summary();
+            self.log.shared.lock().unwrap().current = Some(summary);
+        }
+
+        let hour = current_hour();
+        if hour != self.hour_start {
+            self.finish_hour(hour);
+        }
+    }
+
+    fn summary(&self) -> HourSummary {
+        HourSummary {
+            hour_start: self.hour_start,
+            integrated_lufs: integrated(&self.blocks),
+            true_peak_dbtp: 20.0 * self.true_peak.max(1e-10).log10(),
+            max_momentary_lufs: (self.max_block > 0.0).then(|| lufs(self.max_block)),
+        }
+    }
+
+    fn finish_hour(&mut self, next_hour: u64) {
+        let summary = self.summary();
+        if let Err(e) = self.append_csv(&summary) {
+            log::warn!("[LOUDNESS] Failed to write log: {}", e);
+        }
+        log::info!(
+            "[LOUDNESS] Hour {}: {} LUFS integrated, {:.1} dBTP",
+            utc_timestamp(UNIX_EPOCH + std::time::Duration::from_secs(summary.hour_start)),
+            summary.integrated_lufs.map_or("-inf".to_string(), |l| format!("{:.1}", l)),
+            summary.true_peak_dbtp
+        );
+
+        {
+            let mut shared = self.log.shared.lock().unwrap();
+            shared.last_hour = Some(summary);
+            shared.current = None;
+        }
+        self.blocks.clear();
+        self.true_peak = 0.0;
+        self.max_block = 0.0;
+        self.hour_start = next_hour;
+    }
+
+    fn append_csv(&self, summary: &HourSummary) -> std::io::Result<()> {
+        let Some(path) = &self.csv_path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let new_file = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if new_file {
+            writeln!(file, "hour_utc,integrated_lufs,true_peak_dbtp,max_momentary_lufs,target_lufs")?;
+        }
+        let format = |v: Option<f64>| v.map_or(String::new(), |v| format!("{:.1}", v));
+        writeln!(
+            file,
+            "{},{},{:.1},{},{:.1}",
+            utc_timestamp(UNIX_EPOCH + std::time::Duration::from_secs(summary.hour_start)),
+            format(summary.integrated_lufs),
+            summary.true_peak_dbtp,
+            format(summary.max_momentary_lufs),
+            self.log.target_lufs
+        )
+    }
+}
+
+impl AudioProcessor for LoudnessMeter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let x = sample as f64;
+                let peak = self.true_peak_of(channel, x);
+                self.true_peak = self.true_peak.max(peak);
+
+                let state = &mut self.filter_state[channel];
+                let y = self.filters[0].run(x, &mut state[0]);
+                let y = self.filters[1].run(y, &mut state[1]);
+                self.step_energy += y * y;
+            }
+            self.step_frames_done += 1;
+            if self.step_frames_done == self.step_frames {
+                self.finish_step();
+            }
+        }
+    }
+}
+
+/// Gated integrated loudness of the given block energies
+fn integrated(blocks: &[f64]) -> Option<f64> {
+    let gated_mean = |threshold: f64| {
+        let above: Vec<f64> = blocks.iter().copied().filter(|&b| b > 0.0 && lufs(b) > threshold).collect();
+        (!above.is_empty()).then(|| above.iter().sum::<f64>() / above.len() as f64)
+    };
+    let ungated = gated_mean(ABSOLUTE_GATE_LUFS)?;
+    gated_mean((lufs(ungated) + RELATIVE_GATE_LU).max(ABSOLUTE_GATE_LUFS)).map(lufs)
+}
+
+fn current_hour() -> u64 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    secs - secs % 3600
+}
@@ -5,30 +5,141 @@
 //! Features:
 //! - Native settings panel with streaming controls
 //! - System tray icon with right-click menu
+//! - Headless mode (`--headless`, always on macOS/Linux)
+//! - Raw capture dump for bug reports (`--dump-pcm[=path]`, `--dump-secs=N`)
+//! - Receiver mode (`--receive [URL]`): play another server found on the LAN
 //! - Low-latency Opus streaming via HTTP
 //! - Configurable port and bitrate
 //! - Auto-start streaming on launch
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod agc;
+mod app_channel;
 mod audio;
+mod blocklist;
+mod chaos;
+mod compare;
+mod branding;
+mod calibration;
+mod chat;
+mod codec;
 mod config;
+mod config_store;
+mod control;
+mod cue;
+mod delay;
+mod directory;
+mod drops;
+mod dsp;
+mod embedded;
+mod encode_pool;
 mod encoder;
+mod encoder_load;
+mod events;
+mod geoip;
+mod glitches;
+mod fingerprint;
+mod health;
+mod hooks;
 #[cfg(windows)]
 mod gui;
+mod http_client;
+mod hub;
+mod interaction;
+mod ipc;
+mod links;
+mod mdns;
+mod loudness;
+mod metadata;
+mod mixer;
+mod ogg_mux;
+mod openapi;
 mod opus_encoder;
+mod passthrough;
+mod pcm_dump;
+mod privacy;
+mod pwa;
+mod queue;
+mod rate_limit;
+mod receiver;
+#[cfg(windows)]
+mod receiver_gui;
+mod resources;
+mod response;
+mod router;
 mod server;
+mod sessions;
+mod system_volume;
+mod shutdown;
+mod sink;
+mod signal;
+mod sockopt;
+mod sleep_timer;
+mod spectrum;
+mod stats_feed;
+mod station;
+mod test_tone;
+mod tier;
+mod tuning;
+mod tunnel;
+mod tunnel_protocol;
+mod timeshift;
+mod validate;
+mod vorbis_encoder;
+mod waveform;
+mod webtransport;
+mod ws;
 
-use audio::AudioCapture;
-use config::Config;
-use opus_encoder::OpusEncoder;
+use audio::{CaptureSource, DeviceState};
+use branding::PlayerBranding;
+use calibration::Calibration;
+use chat::ChatRoom;
+use codec::{CodecSwitch, PrimaryEncoder};
+use compare::Compare;
+use config::{CaptureConfig, Codec, Config, DeviceLostPolicy, MicMode, PortFallback};
+use config_store::{ConfigChange, ConfigStore, Origin};
+use control::ControlContext;
+use cue::CueMarker;
+use delay::BroadcastDelay;
+use encode_pool::EncodePool;
+use dsp::{AudioProcessor, DspChain, FadeControl, GainRamp, PeakLevel, PeakMeter};
+use fingerprint::{FingerprintProvider, SnippetBuffer};
+use hub::{BroadcastHub, Packet};
+use events::AppEvent;
+use hooks::Hooks;
+use interaction::Interactions;
+use loudness::LoudnessMeter;
+use metadata::{MetadataProvider, NowPlayingService};
+use mixer::{MicMeter, MicMixer};
+use passthrough::BitstreamGuard;
+use pcm_dump::PcmDump;
+use queue::QueueBudget;
+use rate_limit::RateLimiter;
+use blocklist::Blocklist;
+use geoip::GeoIp;
+use response::ResponseHeaders;
 #[cfg(windows)]
 use gui::{AppState, GuiAction};
-use server::StreamServer;
+use server::{OpusStreamInfo, StreamServer};
+use shutdown::Shutdown;
+use sink::SinkRegistry;
+use sockopt::SocketOptions;
+use spectrum::SpectrumAnalyzer;
+use station::{Station, StationInfo};
+use system_volume::{Compensation, VolumeCompensation};
+use test_tone::TestTone;
+use tier::LowTier;
+use tuning::LiveTuning;
+use app_channel::AppChannel;
+use timeshift::TimeShiftBuffer;
+use validate::OutputValidator;
+use sleep_timer::SleepTimers;
+use waveform::WaveformTap;
+use stats_feed::{StatsFeed, StatsSources};
 
-use crossbeam_channel::{self, Receiver, Sender};
-use std::cell::RefCell;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(windows)]
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
@@ -39,36 +150,113 @@ fn main() {
         .format_timestamp(Some(env_logger::TimestampPrecision::Seconds))
         .init();
 
+    // Receiver mode (`--receive [URL]`): play another RustCast server instead of streaming
+    let headless = !cfg!(windows) || std::env::args().any(|arg| arg == "--headless");
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--receive") {
+        let target = args.get(index + 1).filter(|arg| !arg.starts_with("--")).cloned();
+        #[cfg(windows)]
+        let result = if headless { receiver::run(target) } else { receiver_gui::run(target).map_err(|e| e.to_string()) };
+        #[cfg(not(windows))]
+        let result = receiver::run(target);
+        if let Err(e) = result {
+            log::error!("Receiver error: {}", e);
+            #[cfg(windows)]
+            if !headless {
+                show_error_message(&format!("RustCast 수신기 오류:\n{}", e));
+            }
+            std::process::exit(1);
+        }
+        return;
+    }
+
     log::info!("🎵 RustCast starting...");
 
-    // Load configuration
-    let config = Config::load();
+    // Own CPU/memory/send rate for /status and the GUI
+    resources::start();
+
+    // Load configuration (from RUSTCAST_CONFIG_DIR if set), after moving files left by older versions
+    Config::migrate();
+    let mut config = Config::load();
+    let store = ConfigStore::new(config.clone());
     log::info!(
         "Configuration: port={}, bitrate={}kbps",
         config.port,
         config.bitrate
     );
 
-    // Run the application
+    // Run the application (no GUI outside Windows, or with --headless)
+    resolve_port(&store, &mut config, !headless);
+    if headless {
+        if let Err(e) = run_headless(config, store) {
+            log::error!("Application error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     #[cfg(windows)]
     {
-        if let Err(e) = run_app_with_gui(config) {
+        if let Err(e) = run_app_with_gui(config, store) {
             log::error!("Application error: {}", e);
             show_error_message(&format!("RustCast Error:\n{}", e));
             std::process::exit(1);
         }
     }
+}
 
-    #[cfg(not(windows))]
-    {
-        log::error!("RustCast only supports Windows");
-        std::process::exit(1);
+/// Move off a port another program already holds, as `port_fallback` says.
+/// With `Off` (or no free port) the server start reports the taken port.
+#[cfg_attr(not(windows), allow(unused_variables))]
+fn resolve_port(store: &ConfigStore, config: &mut Config, interactive: bool) {
+    if config.port_fallback == PortFallback::Off || server::port_available(config.port) {
+        return;
     }
+    let Some(free) = server::next_free_port(config.port) else {
+        log::warn!("Port {} is in use and no free port follows it", config.port);
+        return;
+    };
+    log::warn!("Port {} is already in use; port {} is free", config.port, free);
+
+    #[cfg(windows)]
+    if interactive && config.port_fallback == PortFallback::Ask {
+        let message = format!(
+            "포트 {}을(를) 이미 다른 프로그램이 사용 중입니다.\n\n\
+             예: 비어 있는 포트 {}을(를) 사용하고 설정에 저장\n\
+             아니요: 설정 파일을 열어 직접 수정 (RustCast 종료)",
+            config.port, free
+        );
+        let flags = winapi::um::winuser::MB_YESNO | winapi::um::winuser::MB_ICONWARNING;
+        if message_box(&message, flags) != winapi::um::winuser::IDYES {
+            // Write the file first so there is something to open on a fresh install
+            let _ = config.save();
+            if let Some(path) = Config::config_path() {
+                if let Err(e) = open_browser(&path.to_string_lossy()) {
+                    log::warn!("Could not open {:?}: {}", path, e);
+                }
+            }
+            std::process::exit(0);
+        }
+        config.port = free;
+        if let Err(e) = store.update(Origin::Startup, |saved| saved.port = free) {
+            log::error!("{}", e);
+        }
+        return;
+    }
+
+    log::info!("Using port {} for this run", free);
+    config.port = free;
 }
 
 /// Show error message box on Windows
 #[cfg(windows)]
 fn show_error_message(message: &str) {
+    message_box(message, winapi::um::winuser::MB_OK | winapi::um::winuser::MB_ICONERROR);
+}
+
+/// Blocking message box, returns the button pressed (IDOK, IDYES, ...)
+#[cfg(windows)]
+fn message_box(message: &str, flags: u32) -> i32 {
     use std::ffi::OsStr;
     use std::iter::once;
     use std::os::windows::ffi::OsStrExt;
@@ -76,53 +264,314 @@ fn show_error_message(message: &str) {
     let message: Vec<u16> = OsStr::new(message).encode_wide().chain(once(0)).collect();
     let title: Vec<u16> = OsStr::new("RustCast").encode_wide().chain(once(0)).collect();
 
-    unsafe {
-        winapi::um::winuser::MessageBoxW(
-            std::ptr::null_mut(),
-            message.as_ptr(),
-            title.as_ptr(),
-            winapi::um::winuser::MB_OK | winapi::um::winuser::MB_ICONERROR,
-        );
-    }
+    unsafe { winapi::um::winuser::MessageBoxW(std::ptr::null_mut(), message.as_ptr(), title.as_ptr(), flags) }
 }
 
-/// Run application with native Windows GUI
-#[cfg(windows)]
-fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    // Create channels for audio data (small buffers for low latency)
-    let (audio_tx, audio_rx): (Sender<Vec<f32>>, Receiver<Vec<f32>>) =
-        crossbeam_channel::bounded(4);
-    let (mp3_tx, mp3_rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = crossbeam_channel::bounded(4);
+/// Handles the GUI / headless front ends need once the pipeline is running
+#[cfg_attr(not(windows), allow(dead_code))]
+struct Pipeline {
+    is_streaming: Arc<AtomicBool>,
+    should_stream: Arc<AtomicBool>,
+    app_quit: Arc<AtomicBool>,
+    hub: Arc<BroadcastHub>,
+    chat: Arc<ChatRoom>,
+    interactions: Arc<Interactions>,
+    agc: Option<Arc<agc::AgcMeter>>,
+    mic: Option<Arc<MicMeter>>,
+    fade: Arc<FadeControl>,
+    level: Arc<PeakLevel>,
+    cues: Arc<CueMarker>,
+    delay: Option<Arc<BroadcastDelay>>,
+    pcm_dump: Arc<PcmDump>,
+    stats: Arc<StatsFeed>,
+    shutdown: Arc<Shutdown>,
+    test_tone: Arc<TestTone>,
+}
 
+/// Start capture, encoding, the server and every optional service
+fn start_pipeline(config: &Config, store: &Arc<ConfigStore>) -> Result<Pipeline, Box<dyn std::error::Error>> {
     // Initialize audio capture (get sample rate/channels info only)
-    let (audio_capture_info, _) = AudioCapture::new()?;
-    let sample_rate = audio_capture_info.sample_rate;
-    let channels = audio_capture_info.channels;
+    glitches::set_fill(config.pipeline.fill_gaps);
+    let audio_capture_info = audio::open(&config.capture)?;
+    let sample_rate = audio_capture_info.sample_rate();
+    let capture_channels = audio_capture_info.channels();
     drop(audio_capture_info); // Drop to release resources, we'll create new one in audio thread
 
-    log::info!("Audio: {}Hz, {} channels", sample_rate, channels);
+    // Channels of everything after capture: mono when asked, never more than stereo
+    let channels = if config.mono { 1 } else { capture_channels.min(2) };
+    log::info!("Audio: {}Hz, {} channels (streamed as {})", sample_rate, capture_channels, channels);
 
-    // Create Opus encoder (low-latency)
-    let mut encoder = OpusEncoder::new(sample_rate, channels, config.bitrate)?;
-    let opus_frame_size = encoder.frame_size();
+    // Queues between capture, encoder and server, sized by the latency they may add
+    let budget = QueueBudget::from_config(&config.pipeline);
+    let capture_rate = sample_rate.max(1) as f64 * capture_channels.max(1) as f64;
+    let (audio_tx, audio_rx) = queue::channel("capture", drops::Stage::Capture, budget, move |samples: &Vec<f32>| {
+        std::time::Duration::from_secs_f64(samples.len() as f64 / capture_rate)
+    });
+    // Opus packets are 20ms; MP3 frames (24-26ms) are close enough for sizing
+    let (opus_tx, opus_rx) = queue::channel("encode", drops::Stage::Encode, budget, |_: &Packet| {
+        std::time::Duration::from_millis(20)
+    });
+
+    // Main stream encoder (Opus unless MP3 is selected); swapped when the codec changes
+    let codec = CodecSwitch::new(config.codec);
+    let mut encoder = PrimaryEncoder::new(config.codec, sample_rate, channels, config.bitrate, &config.opus)?;
+    let opus_frame_size = opus_encoder::FRAME_SIZE;
+    let mut bitrate = config.bitrate;
+    let opus_config = config.opus.clone();
+    let auto_effort = config.pipeline.auto_effort;
+    // Steps `lower_effort` has taken, applied again when the encoder is rebuilt
+    let mut effort_lowered = 0u32;
+    encoder_load::set_setting(encoder.effort());
 
     // Streaming state flags
     let is_streaming = Arc::new(AtomicBool::new(false));
-    let client_count = Arc::new(AtomicUsize::new(0));
+    let hub = Arc::new(
+        BroadcastHub::new()
+            .with_resume_grace(std::time::Duration::from_secs(config.session_grace_secs))
+            .announce_clients(),
+    );
+    health::start(hub.clone());
+    let chat = Arc::new(ChatRoom::new(hub.clone()));
+    let interactions = Arc::new(Interactions::new());
+    let now_playing = Arc::new(NowPlayingService::new(hub.clone()));
     let should_stream = Arc::new(AtomicBool::new(config.auto_start));
     let app_quit = Arc::new(AtomicBool::new(false));
 
-    // Start encoding thread - outputs raw Opus packets (not Ogg wrapped)
+    // Raw capture tee for bug reports (`--dump-pcm`, or Shift + right-click on the tray icon)
+    let pcm_dump = PcmDump::new(sample_rate, capture_channels);
+    pcm_dump::start_from_args(&pcm_dump);
+    let pcm_dump_for_encoder = pcm_dump.clone();
+
+    // Dolby/DTS passthrough would be streamed as noise; checked on the samples as captured
+    let mut bitstream = BitstreamGuard::new(sample_rate, capture_channels);
+
+    // Processing applied to captured audio before encoding
+    let fade = FadeControl::new(config.fade_ms);
+    let mut dsp = DspChain::new();
+
+    // Undo the Windows volume first: only the loopback of the whole device is taken after it
+    let whole_device = matches!(&config.capture, CaptureConfig::Loopback { exclude_system_sounds: false, apps } if apps.is_empty());
+    let compensation = if config.volume_compensation.enabled && cfg!(windows) && whole_device {
+        let compensation = Compensation::new(&config.volume_compensation);
+        dsp.push(Box::new(VolumeCompensation::new(compensation.clone(), &config.volume_compensation, sample_rate, channels)));
+        Some(compensation)
+    } else {
+        if config.volume_compensation.enabled {
+            log::info!("[VOLUME] Volume compensation only applies to the Windows loopback of the whole output; off");
+        }
+        None
+    };
+
+    // Microphone mixed in first, so the AGC and meters see the mix
+    let mic_capture = CaptureConfig::Input {
+        device: config.mic.device.clone(),
+        on_lost: DeviceLostPolicy::default(),
+        standby: None,
+    };
+    let (mic_tx, mic_meter) = if config.mic.enabled {
+        match audio::open(&mic_capture) {
+            Ok(mic) => {
+                let (mixer, tx, meter) =
+                    MicMixer::new(&config.mic, mic.sample_rate(), mic.channels(), sample_rate, channels);
+                dsp.push(Box::new(mixer));
+                (Some(tx), Some(meter))
+            }
+            Err(e) => {
+                log::error!("[MIC] {}", e);
+                (None, None)
+            }
+        }
+    } else {
+        (None, None)
+    };
+    if let Some(meter) = mic_meter.as_ref().filter(|m| m.mode() == MicMode::Ptt) {
+        #[cfg(windows)]
+        mixer::spawn_ptt_poller(&config.mic.ptt_key, meter.clone());
+        #[cfg(not(windows))]
+        log::warn!("[MIC] No push-to-talk hotkey on this platform; hold it through POST /api/mic on the control API");
+    }
+    // Configured stages (`dsp`, plus `agc` when enabled), retuned live over the control API
+    let (tuning, configured) = LiveTuning::new(config, store.clone(), sample_rate, channels);
+    let agc = tuning.agc_meter();
+    dsp.push(Box::new(configured));
+    // Test tone (GUI button, POST /api/test-tone), also after the stages
+    let (test_tone, tone_injector) =
+        TestTone::new(sample_rate, capture_channels, channels, audio_tx.clone(), is_streaming.clone(), fade.clone());
+    dsp.push(Box::new(tone_injector));
+    // Latency chirp for /calibrate, after the stages so they don't reshape it
+    let calibration = config.calibration.enabled.then(|| {
+        let (calibration, injector) = Calibration::new(sample_rate, channels);
+        dsp.push(Box::new(injector));
+        calibration
+    });
+    dsp.push(Box::new(GainRamp::new(fade.clone(), sample_rate, channels)));
+
+    // Level of what goes out, for the overlay meter
+    let (peak_meter, level) = PeakMeter::new(sample_rate, channels);
+    dsp.push(Box::new(peak_meter));
+    let (waveform_tap, waveform) = WaveformTap::new(sample_rate, channels);
+    dsp.push(Box::new(waveform_tap));
+
+    // Visualizer data for players (skipped while no player is connected)
+    let spectrum = config.spectrum.enabled.then(|| {
+        let (analyzer, control) = SpectrumAnalyzer::new(&config.spectrum, hub.clone(), sample_rate, channels);
+        dsp.push(Box::new(analyzer));
+        control
+    });
+
+    // Compliance metering of what actually goes out (last in the chain)
+    let loudness = config.loudness.enabled.then(|| {
+        let csv_path = Config::data_dir().map(|dir| dir.join("loudness.csv"));
+        let (meter, log) = LoudnessMeter::new(sample_rate, channels, config.loudness.target_lufs, csv_path);
+        dsp.push(Box::new(meter));
+        log
+    });
+
+    // Recent audio for fingerprint lookups (only kept when enabled)
+    let snippet = config
+        .fingerprint
+        .enabled
+        .then(|| Arc::new(SnippetBuffer::new(sample_rate, channels)));
+    let snippet_for_encoder = snippet.clone();
+
+    // Optional Ogg Vorbis feed for legacy players (own encoder thread and hub)
+    // Secondary encoders run on a small worker pool, each with its own queue
+    let encode_pool = EncodePool::new(config.pipeline.encode_workers as usize);
+    let vorbis = if config.vorbis.enabled {
+        let vorbis_hub = Arc::new(BroadcastHub::new());
+        let (pcm_tx, headers) =
+            vorbis_encoder::spawn(&encode_pool, sample_rate, channels, config.vorbis.bitrate, vorbis_hub.clone())?;
+        Some((pcm_tx, vorbis_hub, headers))
+    } else {
+        None
+    };
+    let vorbis_pcm_tx = vorbis.as_ref().map(|(pcm_tx, _, _)| pcm_tx.clone());
+    // A/B page: two more encodes of the same audio, idle until someone opens /compare
+    let compare = if config.compare.enabled {
+        match Compare::spawn(&encode_pool, &config.compare, sample_rate, channels) {
+            Ok(compare) => Some(compare),
+            Err(e) => {
+                log::error!("[COMPARE] {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let compare_pcm_tx = compare.as_ref().map(|(pcm_tx, _)| pcm_tx.clone());
+
+    // MP3 frames go straight to their own hub (no Ogg framing, no broadcast delay)
+    let mp3_hub = Arc::new(BroadcastHub::new().announce_clients());
+    if config.delay_secs > 0.0 && config.codec == Codec::Mp3 {
+        log::warn!("[CODEC] The broadcast delay only applies to Opus; MP3 listeners hear the stream undelayed");
+    }
+
+    // Debug: parse everything the encoders publish before listeners get it
+    if config.debug.validate_output {
+        let info = OpusStreamInfo { channels, sample_rate, frame_size: opus_frame_size };
+        hub.add_tap(OutputValidator::opus(&info));
+        mp3_hub.add_tap(OutputValidator::mp3());
+        if let Some((_, vorbis_hub, headers)) = &vorbis {
+            vorbis_hub.add_tap(OutputValidator::vorbis(headers));
+        }
+    }
+
+    // Start encoding thread - outputs raw Opus packets (not Ogg wrapped) or MP3 frames
+    let codec_for_encoder = codec.clone();
+    let tuning_for_encoder = tuning.clone();
+    let hub_for_encoder = hub.clone();
+    let mp3_hub_for_encoder = mp3_hub.clone();
     thread::spawn(move || {
         let mut total_encoded = 0u64;
         let mut total_dropped = 0u64;
         let mut last_log = std::time::Instant::now();
+        let mut load = encoder_load::LoadTracker::new();
         
-        while let Ok(samples) = audio_rx.recv() {
-            if let Ok(opus_packets) = encoder.encode_raw(&samples) {
+        while let Ok(mut samples) = audio_rx.recv() {
+            let started = std::time::Instant::now();
+            pcm_dump_for_encoder.push(&samples);
+            bitstream.process(&mut samples);
+            if capture_channels != channels {
+                samples = audio::remix(&samples, capture_channels, channels);
+            }
+            
+            // Bitrate changed over the control API: Opus takes it between frames, LAME needs a
+            // new encoder
+            let mut rebuild = false;
+            let wanted_bitrate = tuning_for_encoder.bitrate();
+            if wanted_bitrate != bitrate {
+                bitrate = wanted_bitrate;
+                match &mut encoder {
+                    PrimaryEncoder::Opus(opus) => {
+                        if let Err(e) = opus.set_bitrate(bitrate) {
+                            log::error!("[ENCODER] {}", e);
+                        }
+                    }
+                    PrimaryEncoder::Mp3(_) => rebuild = true,
+                }
+                log::info!("[ENCODER] Bitrate {}kbps", bitrate);
+            }
+
+            // Codec changed: restart the encoder and move listeners over
+            let wanted = codec_for_encoder.current();
+            if wanted != encoder.codec() || rebuild {
+                let next = PrimaryEncoder::new(wanted, sample_rate, channels, bitrate, &opus_config)
+                    .and_then(|mut next| next.lower_effort_by(effort_lowered).map(|()| next));
+                match next {
+                    Ok(next) => {
+                        if let PrimaryEncoder::Mp3(mp3) = &mut encoder {
+                            match mp3.flush() {
+                                Ok(tail) if !tail.is_empty() => {
+                                    mp3_hub_for_encoder.publish(tail.into());
+                                }
+                                _ => {}
+                            }
+                        }
+                        let switched = wanted != encoder.codec();
+                        encoder = next;
+                        encoder_load::set_setting(encoder.effort());
+                        if switched {
+                            codec::announce(wanted, &hub_for_encoder, &mp3_hub_for_encoder);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("[CODEC] {}", e);
+                        codec_for_encoder.set(encoder.codec());
+                    }
+                }
+            }
+            
+            dsp.process(&mut samples);
+            
+            if let Some(snippet) = &snippet_for_encoder {
+                snippet.push(&samples);
+            }
+            if let Some(vorbis_tx) = &vorbis_pcm_tx {
+                // Vorbis falling behind must never hold up Opus
+                vorbis_tx.send(samples.clone());
+            }
+            if let Some(compare_tx) = &compare_pcm_tx {
+                compare_tx.send(samples.clone());
+            }
+            
+            let opus_packets = match &mut encoder {
+                PrimaryEncoder::Opus(opus) => opus.encode_raw(&samples),
+                PrimaryEncoder::Mp3(mp3) => {
+                    match mp3.encode(&samples) {
+                        Ok(frames) if !frames.is_empty() => {
+                            mp3_hub_for_encoder.publish(frames.into());
+                            total_encoded += 1;
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::warn!("[ENCODER] {}", e),
+                    }
+                    Ok(Vec::new())
+                }
+            };
+            if let Ok(opus_packets) = opus_packets {
                 for packet in opus_packets {
                     if !packet.is_empty() {
-                        match mp3_tx.try_send(packet) {
+                        match opus_tx.try_send(packet) {
                             Ok(_) => {
                                 total_encoded += 1;
                             },
@@ -139,6 +588,27 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
             
+            // Falling behind real time: trade some quality for CPU
+            let audio_time = std::time::Duration::from_secs_f64(
+                samples.len() as f64 / (channels as f64 * sample_rate as f64),
+            );
+            if load.record(started.elapsed(), audio_time) && auto_effort {
+                let before = encoder.effort();
+                match encoder.lower_effort() {
+                    Ok(Some(tail)) => {
+                        if !tail.is_empty() {
+                            mp3_hub_for_encoder.publish(tail.into());
+                        }
+                        effort_lowered += 1;
+                        encoder_load::set_setting(encoder.effort());
+                        encoder_load::record_lowered();
+                        log::warn!("[ENCODER] Lowered {} -> {} to keep up", before, encoder.effort());
+                    }
+                    Ok(None) => log::warn!("[ENCODER] Already at the lowest effort ({})", before),
+                    Err(e) => log::error!("[ENCODER] {}", e),
+                }
+            }
+            
             // 5초마다 통계 출력
             if last_log.elapsed().as_secs() >= 5 {
                 log::info!("[ENCODER] 통계: 인코딩됨={}, 드롭됨={}, 드롭률={:.1}%", 
@@ -151,19 +621,233 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    // Create and start server with shared client_count and stream info
-    let mut server = StreamServer::with_client_count(config.port, client_count.clone());
+    // Station metadata, renamed live from the GUI / control API
+    let station = Arc::new(Station::new(StationInfo::from_config(config)));
+
+    // User commands on stream events
+    let hooks = Hooks::new(&config.hooks, station.clone(), config.port, vec![hub.clone(), mp3_hub.clone()]);
+    hooks.start();
+
+    // Create and start server with shared hub (client counts) and stream info
+    let response_headers = ResponseHeaders::from_config(config, station.clone());
+    let sockets = SocketOptions::new(&config.socket);
+    // One limiter for both ports, so a login ban also covers the listener APIs
+    let rate_limit = RateLimiter::new(&config.rate_limit);
+    let mut banned_from = vec![hub.clone(), mp3_hub.clone()];
+    if let Some((_, vorbis_hub, _)) = &vorbis {
+        banned_from.push(vorbis_hub.clone());
+    }
+    if let Some((_, compare)) = &compare {
+        banned_from.push(compare.hub().clone());
+    }
+    let blocklist = Blocklist::new(&config.blocklist, banned_from);
+    let mut server = StreamServer::with_hub(config.port, hub.clone());
+    server.set_socket_options(sockets.clone());
+    server.set_rate_limit(rate_limit.clone());
+    server.set_blocklist(blocklist.clone());
+    server.set_chaos(&config.debug.chaos);
+    server.set_ws_compression(config.http.ws_compression);
+    server.set_webtransport(&config.webtransport);
+    server.set_response_headers(response_headers.clone());
+    server.set_branding(PlayerBranding::from_config(config));
     server.set_opus_info(channels, sample_rate, opus_frame_size);
-    server.start(mp3_rx)?;
+    server.set_ogg_frames_per_page(config.http.ogg_frames_per_page as usize);
+    server.set_shared_ogg(config.http.shared_ogg);
+    server.set_keepalive(Some(std::time::Duration::from_secs(config.http.keepalive_secs as u64)));
+    if config.chat_enabled {
+        server.set_chat(chat.clone());
+    }
+    server.set_interactions(interactions.clone());
+    server.set_now_playing(now_playing.clone());
+    server.set_sleep_timers(SleepTimers::spawn(hub.clone()));
+    server.set_status_enabled(config.control_port == 0);
+    server.set_endpoints(config.endpoints.clone());
+    if let Some(loudness) = &loudness {
+        server.set_loudness(loudness.clone());
+    }
+    if let Some(meter) = &mic_meter {
+        server.set_mic(meter.clone());
+    }
+    let frame_ms = (opus_frame_size as u64 * 1000 / sample_rate as u64) as u32;
+    if config.timeshift_minutes > 0 {
+        server.set_timeshift(TimeShiftBuffer::spawn(hub.clone(), config.timeshift_minutes, frame_ms));
+    }
+    let delay = (config.delay_secs > 0.0).then(|| Arc::new(BroadcastDelay::new(config.delay_secs, frame_ms)));
+    if let Some(delay) = &delay {
+        server.set_delay(delay.clone());
+    }
+    if let Some((_, vorbis_hub, headers)) = vorbis {
+        server.set_vorbis(vorbis_hub, headers);
+    }
+    if let Some((_, compare)) = compare {
+        server.set_compare(compare);
+    }
+    if let Some(calibration) = calibration {
+        server.set_calibration(calibration);
+    }
+    // Second encode for slow listeners, made from what the main tier broadcasts
+    let low_tier = if config.low_tier.enabled {
+        let info = OpusStreamInfo { channels, sample_rate, frame_size: opus_frame_size };
+        match LowTier::spawn(&config.low_tier, &config.opus, hub.clone(), &info) {
+            Ok(low_tier) => Some(low_tier),
+            Err(e) => {
+                log::error!("[TIER] {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(low_tier) = &low_tier {
+        server.set_low_tier(low_tier.clone());
+    }
+    // One application on its own next to the mix, for players that balance the two
+    let app_channel = if config.app_channel.enabled {
+        let info = OpusStreamInfo { channels, sample_rate, frame_size: opus_frame_size };
+        match AppChannel::spawn(&config.app_channel, &config.opus, hub.clone(), &info, fade.clone(), is_streaming.clone()) {
+            Ok(app_channel) => Some(app_channel),
+            Err(e) => {
+                log::error!("[APP] {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(app_channel) = &app_channel {
+        if delay.is_some() {
+            log::warn!("[APP] The broadcast delay doesn't apply to the application channel");
+        }
+        server.set_app_channel(app_channel.clone());
+    }
+    server.set_codec(codec.clone(), mp3_hub, encoder::silent_frame(sample_rate, channels));
+    server.set_station(station.clone());
+    if let Some(base) = links::public_base(&config.public_url) {
+        server.set_public_url(base);
+    }
+    for listener in &config.listeners {
+        if listener.port == config.port || listener.port == 0 {
+            log::warn!("[SERVER] Skipping extra listener on port {}", listener.port);
+            continue;
+        }
+        server.add_listener(listener.clone());
+    }
+    server.start(opus_rx)?;
+
+    // Recorders and other server-side outputs
+    let sinks = SinkRegistry::new(hub.clone(), OpusStreamInfo {
+        channels,
+        sample_rate,
+        frame_size: opus_frame_size,
+    }, station.clone());
+    for sink in &config.sinks {
+        if let Err(e) = sinks.add_from_config(sink) {
+            log::error!("[SINK] {}", e);
+        }
+    }
+
+    let cues = CueMarker::new(hub.clone(), sinks.clone());
+    let shutdown = Shutdown::new(should_stream.clone(), is_streaming.clone(), app_quit.clone(), hub.clone(), sinks.clone());
+
+    // Length-prefixed Opus for microcontroller receivers
+    if config.embedded_port != 0 {
+        let info = OpusStreamInfo { channels, sample_rate, frame_size: opus_frame_size };
+        if let Err(e) = embedded::spawn(config.embedded_port, hub.clone(), info, sockets.clone(), blocklist.clone()) {
+            log::error!("[EMBEDDED] {}", e);
+        }
+    }
+
+    // Where remote listeners are, for the client lists
+    let geoip = GeoIp::open(&config.geoip.database);
+
+    // Pushed to the GUI and control API dashboards instead of each polling
+    let stats = StatsFeed::spawn(StatsSources {
+        hub: hub.clone(),
+        level: level.clone(),
+        agc: agc.clone(),
+        mic: mic_meter.clone(),
+        geoip: geoip.clone(),
+    });
+
+    // Management API on its own (localhost) port, and as JSON lines on the local IPC port
+    if config.control_port != 0 || config.ipc_port != 0 {
+        let control = Arc::new(ControlContext {
+            hub: hub.clone(),
+            chat: chat.clone(),
+            should_stream: should_stream.clone(),
+            is_streaming: is_streaming.clone(),
+            headers: response_headers,
+            tokens: config.control_tokens.clone(),
+            sinks: sinks.clone(),
+            loudness: loudness.clone(),
+            cues: cues.clone(),
+            delay: delay.clone(),
+            disabled_endpoints: config.endpoints.disabled(),
+            spectrum: spectrum.clone(),
+            low_tier: low_tier.clone(),
+            app_channel: app_channel.clone(),
+            mic: mic_meter.clone(),
+            station: station.clone(),
+            sockets: sockets.clone(),
+            rate_limit: rate_limit.clone(),
+            waveform: waveform.clone(),
+            stats: stats.clone(),
+            geoip,
+            tuning: tuning.clone(),
+            test_tone: test_tone.clone(),
+        });
+        if config.control_port != 0 {
+            control::spawn(&config.control_bind, config.control_port, control.clone())?;
+        }
+        if config.ipc_port != 0 {
+            if let Err(e) = ipc::spawn(config.ipc_port, control) {
+                log::error!("[IPC] {}", e);
+            }
+        }
+    }
+
+    // Opt-in public directory listing
+    directory::spawn(config, hub.clone());
+
+    // Listeners reach the station through a relay instead of a forwarded port
+    if config.tunnel.enabled {
+        tunnel::spawn(&config.tunnel, config.port);
+    }
+
+    // Receivers on the LAN find the station without a typed address
+    if config.mdns {
+        mdns::advertise(&config.stream_name, config.port);
+    }
+
+    // Now-playing metadata providers (highest priority first)
+    let mut providers: Vec<Box<dyn MetadataProvider>> = Vec::new();
+    if let Some(snippet) = snippet {
+        providers.push(Box::new(FingerprintProvider::new(config.fingerprint.clone(), snippet)));
+    }
+    now_playing.spawn(providers);
+
+    // Mute/stop while a listed meeting app is running
+    privacy::start(&config.privacy, fade.clone(), should_stream.clone());
+    system_volume::start(config.link_system_mute, compensation, fade.clone());
+
+    // Saved changes (settings window, PATCH /api/config) that apply while streaming
+    spawn_config_applier(store.subscribe(None), codec.clone(), station.clone(), hub.clone(), blocklist.clone());
 
     // Audio control thread - handles audio capture in its own thread
     let audio_tx_clone = audio_tx.clone();
     let is_streaming_clone = is_streaming.clone();
     let should_stream_clone = should_stream.clone();
     let app_quit_clone = app_quit.clone();
+    let fade_clone = fade.clone();
+    let capture_config = config.capture.clone();
 
     thread::spawn(move || {
-        let mut audio_capture: Option<AudioCapture> = None;
+        let mut audio_capture: Option<Box<dyn CaptureSource>> = None;
+        let mut mic: Option<Box<dyn CaptureSource>> = None;
+        // Starting is retried every loop; the error event is published once per failing streak
+        let mut start_failed = false;
+        // Last device state published (a fresh start begins on the configured device)
+        let mut device_state = DeviceState::Active;
 
         loop {
             if app_quit_clone.load(Ordering::SeqCst) {
@@ -172,30 +856,82 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
 
             let want_stream = should_stream_clone.load(Ordering::SeqCst);
             let currently_streaming = audio_capture.is_some();
+            start_failed &= want_stream;
 
             if want_stream && !currently_streaming {
                 // Start streaming
-                match AudioCapture::new() {
-                    Ok((mut capture, _)) => {
+                match audio::open(&capture_config) {
+                    Ok(mut capture) => {
                         if let Err(e) = capture.start(audio_tx_clone.clone()) {
                             log::error!("Failed to start audio capture: {}", e);
+                            if !start_failed {
+                                events::publish(AppEvent::Error { message: format!("Failed to start audio capture: {}", e) });
+                            }
+                            start_failed = true;
                         } else {
                             audio_capture = Some(capture);
+                            // The mic runs alongside the main source; failing it doesn't stop the stream
+                            if let Some(mic_tx) = &mic_tx {
+                                let started = audio::open(&mic_capture).and_then(|mut source| {
+                                    source.start(mic_tx.clone())?;
+                                    Ok(source)
+                                });
+                                match started {
+                                    Ok(source) => mic = Some(source),
+                                    Err(e) => log::error!("[MIC] Failed to start: {}", e),
+                                }
+                            }
+                            fade_clone.fade_in();
                             is_streaming_clone.store(true, Ordering::SeqCst);
                             log::info!("Audio streaming started");
+                            start_failed = false;
+                            events::publish(AppEvent::StreamStarted);
                         }
                     }
                     Err(e) => {
                         log::error!("Failed to create audio capture: {}", e);
+                        if !start_failed {
+                            events::publish(AppEvent::Error { message: format!("Failed to create audio capture: {}", e) });
+                        }
+                        start_failed = true;
                     }
                 }
             } else if !want_stream && currently_streaming {
-                // Stop streaming
+                // Stop streaming - fade out while audio still flows, then cut
+                fade_clone.fade_out();
+                std::thread::sleep(fade_clone.ramp_duration() + std::time::Duration::from_millis(20));
                 if let Some(mut capture) = audio_capture.take() {
                     capture.stop();
                 }
+                if let Some(mut source) = mic.take() {
+                    source.stop();
+                }
+                audio::clear_device_status();
+                device_state = DeviceState::Active;
                 is_streaming_clone.store(false, Ordering::SeqCst);
                 log::info!("Audio streaming stopped");
+                events::publish(AppEvent::StreamStopped);
+            } else if let Some(capture) = audio_capture.as_mut() {
+                // Lost input devices are handled here; an error means on_lost is "stop"
+                let result = capture.poll();
+                if let Some(device) = audio::device_status().filter(|device| device.state != device_state) {
+                    device_state = device.state;
+                    events::publish(AppEvent::DeviceChanged { state: device.state, device: device.using.unwrap_or_default() });
+                }
+                if let Err(e) = result {
+                    log::error!("[DEVICE] {}, streaming stopped", e);
+                    device_state = DeviceState::Active;
+                    events::publish(AppEvent::Error { message: e.to_string() });
+                    events::publish(AppEvent::StreamStopped);
+                    should_stream_clone.store(false, Ordering::SeqCst);
+                    if let Some(mut capture) = audio_capture.take() {
+                        capture.stop();
+                    }
+                    if let Some(mut source) = mic.take() {
+                        source.stop();
+                    }
+                    is_streaming_clone.store(false, Ordering::SeqCst);
+                }
             }
 
             std::thread::sleep(std::time::Duration::from_millis(100));
@@ -205,13 +941,80 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(mut capture) = audio_capture {
             capture.stop();
         }
+        if let Some(mut source) = mic {
+            source.stop();
+        }
+    });
+
+    Ok(Pipeline {
+        is_streaming,
+        should_stream,
+        app_quit,
+        hub,
+        chat,
+        interactions,
+        agc,
+        mic: mic_meter,
+        fade,
+        level,
+        cues,
+        delay,
+        pcm_dump,
+        stats,
+        shutdown,
+        test_tone,
+    })
+}
+
+/// Apply what can change while streaming whenever the saved config changes; the rest waits for a restart
+fn spawn_config_applier(
+    changes: crossbeam_channel::Receiver<ConfigChange>,
+    codec: Arc<CodecSwitch>,
+    station: Arc<Station>,
+    hub: Arc<BroadcastHub>,
+    blocklist: Arc<Blocklist>,
+) {
+    let result = thread::Builder::new().name("config-apply".into()).spawn(move || {
+        for ConfigChange { config, previous, .. } in changes {
+            codec.set(config.codec);
+            station.set(StationInfo::from_config(&config), &hub);
+            if config.privacy.apps != previous.privacy.apps {
+                privacy::set_apps(&config.privacy.apps);
+            }
+            if config.blocklist != previous.blocklist {
+                blocklist.set(&config.blocklist);
+            }
+            if config.link_system_mute != previous.link_system_mute {
+                system_volume::set_linked(config.link_system_mute);
+            }
+        }
     });
+    if let Err(e) = result {
+        log::warn!("[CONFIG] Changes will apply after a restart only: {}", e);
+    }
+}
+
+/// Run application with native Windows GUI
+#[cfg(windows)]
+fn run_app_with_gui(config: Config, store: Arc<ConfigStore>) -> Result<(), Box<dyn std::error::Error>> {
+    let Pipeline { is_streaming, should_stream, app_quit: _, hub, chat, interactions, agc, mic, fade, level, cues, delay, pcm_dump, stats, shutdown, test_tone } =
+        start_pipeline(&config, &store)?;
 
     // Create shared state for GUI
     let app_state = Arc::new(AppState {
         is_streaming: is_streaming.clone(),
-        client_count: client_count.clone(),
-        config: RefCell::new(config.clone()),
+        hub: hub.clone(),
+        chat: chat.clone(),
+        interactions: interactions.clone(),
+        agc: agc.clone(),
+        mic,
+        fade,
+        level,
+        pcm_dump,
+        stats,
+        test_tone,
+        share_url: links::share_url(&config.public_url, config.port),
+        config: store,
     });
 
     // Create channel for GUI actions
@@ -219,7 +1022,6 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
 
     // Spawn thread to handle GUI actions
     let should_stream_for_actions = should_stream.clone();
-    let app_quit_for_actions = app_quit.clone();
     let port = config.port;
 
     thread::spawn(move || {
@@ -230,11 +1032,18 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
                     should_stream_for_actions.store(!current, Ordering::SeqCst);
                     log::info!("Toggle streaming: {} -> {}", current, !current);
                 }
-                GuiAction::SaveConfig(new_config) => {
-                    if let Err(e) = new_config.save() {
-                        log::error!("Failed to save config: {}", e);
-                    } else {
-                        log::info!("Config saved");
+                GuiAction::MuteChatUser(ip) => {
+                    chat.mute(&ip);
+                }
+                GuiAction::ClearChat => {
+                    chat.clear();
+                }
+                GuiAction::DropCue => {
+                    cues.mark("Hotkey");
+                }
+                GuiAction::DumpDelay => {
+                    if let Some(delay) = &delay {
+                        delay.dump();
                     }
                 }
                 GuiAction::OpenBrowser => {
@@ -245,7 +1054,7 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
                 }
                 GuiAction::Quit => {
                     log::info!("Quitting...");
-                    app_quit_for_actions.store(true, Ordering::SeqCst);
+                    shutdown.run();
                     std::process::exit(0);
                 }
             }
@@ -253,6 +1062,7 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     });
 
     log::info!("✅ RustCast ready! Open http://localhost:{}", config.port);
+    log::info!("Share with listeners: {}", links::share_url(&config.public_url, config.port));
 
     // Run the GUI (this blocks until quit)
     gui::run_gui(action_tx, app_state)?;
@@ -260,7 +1070,27 @@ fn run_app_with_gui(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Open URL in default browser
+/// Run without a window; streaming is controlled with `auto_start` and the control API
+fn run_headless(config: Config, store: Arc<ConfigStore>) -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = start_pipeline(&config, &store)?;
+
+    log::info!("✅ RustCast ready (headless)! Open http://localhost:{}", config.port);
+    log::info!("Share with listeners: {}", links::share_url(&config.public_url, config.port));
+    if !config.auto_start {
+        log::info!("auto_start is off; start the stream with POST /api/stream on the control API");
+    }
+
+    // systemd / service wrapper stops go through the same shutdown as Quit
+    shutdown::handle_signals(pipeline.shutdown.clone());
+
+    while !pipeline.app_quit.load(Ordering::SeqCst) {
+        thread::sleep(std::time::Duration::from_millis(500));
+    }
+    Ok(())
+}
+
+/// Open URL in default browser (files open in their default app)
+#[cfg_attr(not(windows), allow(dead_code))]
 fn open_browser(url: &str) -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(windows)]
     {
@@ -268,7 +1098,11 @@ fn open_browser(url: &str) -> Result<(), Box<dyn std::error::Error>> {
             .args(["/C", "start", "", url])
             .spawn()?;
     }
-    #[cfg(not(windows))]
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("open").arg(url).spawn()?;
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
     {
         std::process::Command::new("xdg-open").arg(url).spawn()?;
     }
@@ -0,0 +1,418 @@
+//! LAN discovery (mDNS / DNS-SD)
+//! Servers answer queries for `_rustcast._tcp.local` (`mdns`); receivers (`--receive` without an
+//! address) ask and list who answered. Only the records RustCast itself sends are understood.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::links;
+
+/// Service type servers are announced under
+pub const SERVICE: &str = "_rustcast._tcp.local";
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+/// Record lifetime: receivers only browse, nothing caches it for long
+const TTL: u32 = 120;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Records this host alone owns (RFC 6762 section 10.2)
+const CACHE_FLUSH: u16 = 0x8000;
+/// Longest DNS label
+const MAX_LABEL: usize = 63;
+const MAX_PACKET: usize = 9000;
+
+/// A server that answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    /// Station name (the instance label)
+    pub name: String,
+    pub host: IpAddr,
+    pub port: u16,
+}
+
+impl Server {
+    pub fn url(&self) -> String {
+        match self.host {
+            IpAddr::V4(ip) => format!("http://{}:{}/", ip, self.port),
+            IpAddr::V6(ip) => format!("http://[{}]:{}/", ip, self.port),
+        }
+    }
+}
+
+/// Answer queries for this server on the LAN from a thread of its own
+pub fn advertise(station: &str, port: u16) {
+    let Some(IpAddr::V4(ip)) = links::lan_ip() else {
+        log::warn!("[MDNS] No IPv4 LAN address, not announced");
+        return;
+    };
+    let socket = match responder_socket(ip) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::warn!("[MDNS] Not announced (UDP port {}): {}", PORT, e);
+            return;
+        }
+    };
+    let record = Record::new(station, ip, port);
+    log::info!("[MDNS] Announcing \"{}\" as {}", record.instance, SERVICE);
+    let result = thread::Builder::new().name("mdns".into()).spawn(move || {
+        let mut buffer = vec![0u8; MAX_PACKET];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    log::debug!("[MDNS] {}", e);
+                    continue;
+                }
+            };
+            let Some(query) = Query::parse(&buffer[..len]) else {
+                continue;
+            };
+            if !query.asks_for_service() {
+                continue;
+            }
+            // Queriers on another port can't hear multicast answers (RFC 6762 section 6.7)
+            let (answer, to) = if from.port() == PORT {
+                (record.response(None), SocketAddr::from((GROUP, PORT)))
+            } else {
+                (record.response(Some(&query)), from)
+            };
+            if let Err(e) = socket.send_to(&answer, to) {
+                log::debug!("[MDNS] Answer to {}: {}", to, e);
+            }
+        }
+    });
+    if let Err(e) = result {
+        log::warn!("[MDNS] Not started: {}", e);
+    }
+}
+
+/// Shared port 5353 socket in the mDNS group; other responders on this machine keep working
+fn responder_socket(ip: Ipv4Addr) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    // macOS's own responder holds the port with SO_REUSEPORT
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    socket.join_multicast_v4(&GROUP, &ip)?;
+    socket.set_multicast_if_v4(&ip)?;
+    socket.set_multicast_ttl_v4(255)?;
+    Ok(socket.into())
+}
+
+/// Ask the LAN for RustCast servers and collect the answers that arrive within `wait`
+pub fn browse(wait: Duration) -> std::io::Result<Vec<Server>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.send_to(&Query::service().encode(), SocketAddrV4::new(GROUP, PORT))?;
+
+    let deadline = Instant::now() + wait;
+    let mut servers = Vec::new();
+    let mut buffer = vec![0u8; MAX_PACKET];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(left))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        for server in parse_response(&buffer[..len], from.ip()) {
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
+        }
+    }
+    Ok(servers)
+}
+
+/// The records of one server
+struct Record {
+    /// Instance label, from the station name
+    instance: String,
+    /// Host name unique on the LAN, from the address
+    host: String,
+    ip: Ipv4Addr,
+    port: u16,
+}
+
+impl Record {
+    fn new(station: &str, ip: Ipv4Addr, port: u16) -> Self {
+        let station = station.trim();
+        let station = if station.is_empty() { "RustCast" } else { station };
+        let mut instance = String::new();
+        for ch in station.chars() {
+            if instance.len() + ch.len_utf8() > MAX_LABEL {
+                break;
+            }
+            instance.push(ch);
+        }
+        let host = format!("rustcast-{}.local", ip.to_string().replace('.', "-"));
+        Self { instance, host, ip, port }
+    }
+
+    /// PTR answer, SRV/TXT/A as additional records; a unicast answer repeats the query's id
+    /// and question
+    fn response(&self, query: Option<&Query>) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(256);
+        let id = query.map_or(0, |query| query.id);
+        let questions = query.map_or(0, |_| 1);
+        for field in [id, 0x8400, questions, 1, 0, 3] {
+            packet.extend_from_slice(&field.to_be_bytes());
+        }
+        if query.is_some() {
+            write_name(&mut packet, &[SERVICE]);
+            packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+            packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        // Unicast answers don't set the cache-flush bit (RFC 6762 section 6.7)
+        let flush = if query.is_some() { 0 } else { CACHE_FLUSH };
+
+        let mut instance = Vec::new();
+        write_name(&mut instance, &[&self.instance, SERVICE]);
+        let mut host = Vec::new();
+        write_name(&mut host, &[&self.host]);
+
+        write_record(&mut packet, &[SERVICE], TYPE_PTR, CLASS_IN, &instance);
+        let mut srv = Vec::new();
+        srv.extend_from_slice(&[0, 0, 0, 0]);
+        srv.extend_from_slice(&self.port.to_be_bytes());
+        srv.extend_from_slice(&host);
+        write_record(&mut packet, &[&self.instance, SERVICE], TYPE_SRV, CLASS_IN | flush, &srv);
+        let mut txt = vec![b"path=/".len() as u8];
+        txt.extend_from_slice(b"path=/");
+        write_record(&mut packet, &[&self.instance, SERVICE], TYPE_TXT, CLASS_IN | flush, &txt);
+        write_record(&mut packet, &[&self.host], TYPE_A, CLASS_IN | flush, &self.ip.octets());
+        packet
+    }
+}
+
+/// First question of a query, and its id
+struct Query {
+    id: u16,
+    name: String,
+    kind: u16,
+}
+
+impl Query {
+    fn service() -> Self {
+        Self { id: 0, name: SERVICE.to_string(), kind: TYPE_PTR }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(64);
+        for field in [self.id, 0, 1, 0, 0, 0] {
+            packet.extend_from_slice(&field.to_be_bytes());
+        }
+        write_name(&mut packet, &[&self.name]);
+        packet.extend_from_slice(&self.kind.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    /// None for responses and packets without a question
+    fn parse(packet: &[u8]) -> Option<Self> {
+        let id = read_u16(packet, 0)?;
+        let flags = read_u16(packet, 2)?;
+        if flags & 0x8000 != 0 || read_u16(packet, 4)? == 0 {
+            return None;
+        }
+        let (name, end) = read_name(packet, 12)?;
+        Some(Self { id, name, kind: read_u16(packet, end)? })
+    }
+
+    fn asks_for_service(&self) -> bool {
+        self.name.eq_ignore_ascii_case(SERVICE) && matches!(self.kind, TYPE_PTR | TYPE_ANY)
+    }
+}
+
+/// Servers in an answer; the sender's address stands in for a missing A record
+fn parse_response(packet: &[u8], source: IpAddr) -> Vec<Server> {
+    let Some(records) = read_records(packet) else {
+        return Vec::new();
+    };
+    let find = |owner: &str, kind: u16| {
+        records.iter().find(|record| record.kind == kind && record.owner.eq_ignore_ascii_case(owner))
+    };
+    let mut servers = Vec::new();
+    for pointer in records.iter().filter(|record| record.kind == TYPE_PTR && record.owner.eq_ignore_ascii_case(SERVICE)) {
+        let Some((instance, _)) = read_name(packet, pointer.data) else {
+            continue;
+        };
+        let Some(srv) = find(&instance, TYPE_SRV) else {
+            continue;
+        };
+        let (Some(port), Some((target, _))) = (read_u16(packet, srv.data + 4), read_name(packet, srv.data + 6)) else {
+            continue;
+        };
+        let host = find(&target, TYPE_A)
+            .and_then(|a| packet.get(a.data..a.data + 4))
+            .map_or(source, |octets| IpAddr::from([octets[0], octets[1], octets[2], octets[3]]));
+        let name = instance.strip_suffix(SERVICE).map_or(instance.as_str(), |name| name.trim_end_matches('.'));
+        servers.push(Server { name: name.to_string(), host, port });
+    }
+    servers
+}
+
+/// A resource record: owner name, type and where its data starts in the packet
+struct RawRecord {
+    owner: String,
+    kind: u16,
+    data: usize,
+}
+
+/// Answer, authority and additional records of a response
+fn read_records(packet: &[u8]) -> Option<Vec<RawRecord>> {
+    if read_u16(packet, 2)? & 0x8000 == 0 {
+        return None;
+    }
+    let questions = read_u16(packet, 4)?;
+    let count = read_u16(packet, 6)? as usize + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+    let mut at = 12;
+    for _ in 0..questions {
+        at = read_name(packet, at)?.1 + 4;
+    }
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (owner, end) = read_name(packet, at)?;
+        let kind = read_u16(packet, end)?;
+        let len = read_u16(packet, end + 8)? as usize;
+        let data = end + 10;
+        if data + len > packet.len() {
+            return None;
+        }
+        records.push(RawRecord { owner, kind, data });
+        at = data + len;
+    }
+    Some(records)
+}
+
+/// Labels of each part in turn (a part may hold dots, e.g. the service type), then the root
+fn write_name(packet: &mut Vec<u8>, parts: &[&str]) {
+    for (index, part) in parts.iter().enumerate() {
+        // The instance label is taken whole; it may contain dots
+        let labels: Vec<&str> = if index == 0 && parts.len() > 1 { vec![part] } else { part.split('.').collect() };
+        for label in labels.into_iter().filter(|label| !label.is_empty()) {
+            let len = label.len().min(MAX_LABEL);
+            packet.push(len as u8);
+            packet.extend_from_slice(&label.as_bytes()[..len]);
+        }
+    }
+    packet.push(0);
+}
+
+fn write_record(packet: &mut Vec<u8>, owner: &[&str], kind: u16, class: u16, data: &[u8]) {
+    write_name(packet, owner);
+    packet.extend_from_slice(&kind.to_be_bytes());
+    packet.extend_from_slice(&class.to_be_bytes());
+    packet.extend_from_slice(&TTL.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+}
+
+/// Name at `at` (following compression pointers), dot-joined, and where it ends in place
+fn read_name(packet: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Pointers only go backwards in sane packets; the limit stops loops in the others
+    for _ in 0..128 {
+        let len = *packet.get(at)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(at + 1)));
+            }
+            0xC0.. => {
+                let target = (read_u16(packet, at)? & 0x3FFF) as usize;
+                end.get_or_insert(at + 2);
+                at = target;
+            }
+            1..=MAX_LABEL => {
+                labels.push(String::from_utf8_lossy(packet.get(at + 1..at + 1 + len)?).into_owned());
+                at += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn read_u16(packet: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(packet.get(at..at + 2)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 99));
+
+    #[test]
+    fn query_round_trip() {
+        let query = Query::parse(&Query::service().encode()).unwrap();
+        assert!(query.asks_for_service());
+        assert_eq!(query.id, 0);
+        // An answer is no query
+        let record = Record::new("Station", Ipv4Addr::new(192, 0, 2, 10), 3000);
+        assert!(Query::parse(&record.response(None)).is_none());
+    }
+
+    #[test]
+    fn response_lists_server() {
+        let record = Record::new("Kamil's radio. Live", Ipv4Addr::new(192, 0, 2, 10), 3000);
+        let servers = parse_response(&record.response(None), SOURCE);
+        assert_eq!(
+            servers,
+            [Server { name: "Kamil's radio. Live".into(), host: IpAddr::from([192, 0, 2, 10]), port: 3000 }]
+        );
+        assert_eq!(servers[0].url(), "http://192.0.2.10:3000/");
+    }
+
+    #[test]
+    fn unicast_answer_repeats_question() {
+        let mut query = Query::service();
+        query.id = 0x1234;
+        let record = Record::new("Station", Ipv4Addr::new(192, 0, 2, 10), 3000);
+        let answer = record.response(Some(&query));
+        assert_eq!(read_u16(&answer, 0), Some(0x1234));
+        assert_eq!(parse_response(&answer, SOURCE).len(), 1);
+    }
+
+    #[test]
+    fn long_station_name_fits_a_label() {
+        let record = Record::new(&"가".repeat(40), Ipv4Addr::new(192, 0, 2, 10), 3000);
+        assert!(record.instance.len() <= MAX_LABEL);
+        assert_eq!(parse_response(&record.response(None), SOURCE)[0].name, record.instance);
+    }
+
+    #[test]
+    fn compressed_names() {
+        // PTR answer whose instance name points back at the question's service name
+        let mut packet = vec![0, 0, 0x84, 0, 0, 1, 0, 2, 0, 0, 0, 0];
+        write_name(&mut packet, &[SERVICE]);
+        packet.extend_from_slice(&[0, 12, 0, 1]);
+        let instance = packet.len() + 12;
+        // Answer 1: PTR, owner = pointer to offset 12
+        packet.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1, 0, 0, 0, 120, 0, 6]);
+        packet.extend_from_slice(&[3, b'S', b't', b'n', 0xC0, 12]);
+        // Answer 2: SRV for the instance, target without an A record
+        packet.extend_from_slice(&[0xC0, instance as u8, 0, 33, 0, 1, 0, 0, 0, 120, 0, 11]);
+        packet.extend_from_slice(&[0, 0, 0, 0, 0x0B, 0xB8, 3, b'p', b'c', b'1', 0]);
+        assert_eq!(parse_response(&packet, SOURCE), [Server { name: "Stn".into(), host: SOURCE, port: 3000 }]);
+    }
+
+    #[test]
+    fn pointer_loop_is_rejected() {
+        let packet = [0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0xC0, 12];
+        assert!(parse_response(&packet, SOURCE).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Now-playing metadata
+//! Polls metadata providers in priority order and pushes changes to web players
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hub::BroadcastHub;
+
+/// How often the service checks whether a provider is due
+const TICK: Duration = Duration::from_secs(1);
+
+/// Track currently playing on the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub album: String,
+    /// Provider that produced this entry
+    pub source: &'static str,
+}
+
+/// A source of now-playing metadata
+pub trait MetadataProvider: Send {
+    fn name(&self) -> &'static str;
+
+    /// Lower values win when several providers have metadata
+    fn priority(&self) -> u8;
+
+    /// Minimum time between polls (expensive providers should poll rarely)
+    fn interval(&self) -> Duration;
+
+    /// Current track, or `None` if this provider doesn't know
+    fn poll(&mut self) -> Option<NowPlaying>;
+}
+
+struct ProviderSlot {
+    provider: Box<dyn MetadataProvider>,
+    last_poll: Option<Instant>,
+    last_result: Option<NowPlaying>,
+}
+
+/// Holds the current track and broadcasts changes
+pub struct NowPlayingService {
+    hub: Arc<BroadcastHub>,
+    current: Mutex<Option<NowPlaying>>,
+}
+
+impl NowPlayingService {
+    pub fn new(hub: Arc<BroadcastHub>) -> Self {
+        Self {
+            hub,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Start polling the given providers. Does nothing if the list is empty.
+    pub fn spawn(self: &Arc<Self>, providers: Vec<Box<dyn MetadataProvider>>) {
+        if providers.is_empty() {
+            return;
+        }
+
+        let mut slots: Vec<ProviderSlot> = providers
+            .into_iter()
+            .map(|provider| ProviderSlot {
+                provider,
+                last_poll: None,
+                last_result: None,
+            })
+            .collect();
+        slots.sort_by_key(|slot| slot.provider.priority());
+
+        log::info!(
+            "[METADATA] Providers: {}",
+            slots.iter().map(|s| s.provider.name()).collect::<Vec<_>>().join(" > ")
+        );
+
+        let service = self.clone();
+        thread::spawn(move || loop {
+            for slot in slots.iter_mut() {
+                let due = slot
+                    .last_poll
+                    .is_none_or(|t| t.elapsed() >= slot.provider.interval());
+                if due {
+                    slot.last_result = slot.provider.poll();
+                    slot.last_poll = Some(Instant::now());
+                }
+            }
+
+            // Highest-priority provider that currently knows the track wins
+            let best = slots.iter().find_map(|slot| slot.last_result.clone());
+            service.set(best);
+
+            thread::sleep(TICK);
+        });
+    }
+
+    /// Current track, if any provider knows it
+    pub fn current(&self) -> Option<NowPlaying> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Send the current track to a newly connected player
+    pub fn send_current_to(&self, subscriber_id: u64) {
+        let event = now_playing_event(&self.current());
+        self.hub.send_text_to(subscriber_id, &event);
+    }
+
+    fn set(&self, now_playing: Option<NowPlaying>) {
+        let mut current = self.current.lock().unwrap();
+        if *current == now_playing {
+            return;
+        }
+
+        match &now_playing {
+            Some(np) => log::info!("[METADATA] Now playing: {} - {} ({})", np.artist, np.title, np.source),
+            None => log::info!("[METADATA] Now playing: unknown"),
+        }
+        *current = now_playing;
+        self.hub.publish_text(&now_playing_event(&current));
+    }
+}
+
+fn now_playing_event(now_playing: &Option<NowPlaying>) -> String {
+    serde_json::json!({ "type": "now_playing", "now_playing": now_playing }).to_string()
+}
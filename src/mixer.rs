@@ -0,0 +1,373 @@
+//! Microphone mixer
+//! Mixes a second input over the captured audio, ducking the captured audio while the mic is in use
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+
+use crate::audio::{self, AudioSample};
+use crate::config::{MicConfig, MicMode};
+use crate::drops::Stage;
+use crate::dsp::AudioProcessor;
+use crate::queue::{self, PipelineSender, QueueBudget};
+
+/// Mic audio queued beyond this is dropped (the two devices' clocks drift apart)
+const MAX_QUEUE_MS: u32 = 200;
+/// Talking keeps the duck down this long after the mic falls below the threshold
+const HOLD_MS: f32 = 300.0;
+/// How fast the captured audio is ducked / comes back
+const DUCK_ATTACK_MS: f32 = 10.0;
+const DUCK_RELEASE_MS: f32 = 400.0;
+/// Mic level detector decay
+const DETECTOR_RELEASE_MS: f32 = 50.0;
+/// Noise floor estimate follows quieter input quickly and louder input slowly
+const NOISE_FALL_MS: f32 = 200.0;
+const NOISE_RISE_MS: f32 = 10_000.0;
+/// Voice has to be this far above the noise floor (dB)
+const VAD_MARGIN_DB: f32 = 10.0;
+/// How fast the mic opens / closes in `vad` and `ptt` mode
+const GATE_ATTACK_MS: f32 = 5.0;
+const GATE_RELEASE_MS: f32 = 100.0;
+/// Meters are published about ten times a second
+const METER_INTERVAL_MS: u32 = 100;
+/// Reported level of digital silence
+const FLOOR_DB: f32 = -96.0;
+/// Push-to-talk key polling interval
+#[cfg(windows)]
+const PTT_POLL: std::time::Duration = std::time::Duration::from_millis(20);
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn linear_to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant
+fn coefficient(ms: f32, sample_rate: u32) -> f32 {
+    let frames = (ms.max(0.1) / 1000.0 * sample_rate as f32).max(1.0);
+    1.0 - (-1.0 / frames).exp()
+}
+
+/// Voice activity and push-to-talk state, shared with the GUI, /status and the control API
+#[derive(Debug)]
+pub struct MicMeter {
+    mode: MicMode,
+    level_db: AtomicU32,
+    noise_db: AtomicU32,
+    voice: AtomicBool,
+    /// Mic currently heard in the stream
+    open: AtomicBool,
+    /// Total time voice was detected (ms)
+    voice_ms: AtomicU64,
+    ptt_held: AtomicBool,
+}
+
+impl MicMeter {
+    fn new(mode: MicMode) -> Arc<Self> {
+        Arc::new(Self {
+            mode,
+            level_db: AtomicU32::new(FLOOR_DB.to_bits()),
+            noise_db: AtomicU32::new(FLOOR_DB.to_bits()),
+            voice: AtomicBool::new(false),
+            open: AtomicBool::new(mode == MicMode::Always),
+            voice_ms: AtomicU64::new(0),
+            ptt_held: AtomicBool::new(false),
+        })
+    }
+
+    pub fn mode(&self) -> MicMode {
+        self.mode
+    }
+
+    /// Peak level of the mic (dBFS)
+    pub fn level_db(&self) -> f32 {
+        f32::from_bits(self.level_db.load(Ordering::Relaxed))
+    }
+
+    /// Estimated background noise of the mic (dBFS)
+    pub fn noise_db(&self) -> f32 {
+        f32::from_bits(self.noise_db.load(Ordering::Relaxed))
+    }
+
+    pub fn is_voice(&self) -> bool {
+        self.voice.load(Ordering::Relaxed)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    pub fn is_ptt_held(&self) -> bool {
+        self.ptt_held.load(Ordering::Relaxed)
+    }
+
+    /// Press or release push-to-talk (hotkey poller, control API)
+    pub fn set_ptt(&self, held: bool) {
+        if self.ptt_held.swap(held, Ordering::Relaxed) != held {
+            log::debug!("[MIC] Push-to-talk {}", if held { "pressed" } else { "released" });
+        }
+    }
+
+    /// Values for /status
+    pub fn info(&self) -> serde_json::Value {
+        serde_json::json!({
+            "mode": match self.mode {
+                MicMode::Always => "always",
+                MicMode::Vad => "vad",
+                MicMode::Ptt => "ptt",
+            },
+            "level_db": (self.level_db() * 10.0).round() / 10.0,
+            "noise_db": (self.noise_db() * 10.0).round() / 10.0,
+            "voice": self.is_voice(),
+            "open": self.is_open(),
+            "ptt_held": self.is_ptt_held(),
+            "voice_secs": self.voice_ms.load(Ordering::Relaxed) / 1000,
+        })
+    }
+}
+
+/// Mixer stage, first in the DSP chain. The mic itself is captured next to the main
+/// source (see `main.rs`) and arrives through the sender returned by `new`.
+pub struct MicMixer {
+    meter: Arc<MicMeter>,
+    rx: Receiver<AudioSample>,
+    mic_channels: u16,
+    channels: usize,
+    /// Mic frames, already remixed to `channels`
+    queue: VecDeque<f32>,
+    max_queue: usize,
+    /// Mic frames advanced per output frame (mic rate / stream rate)
+    step: f64,
+    /// Read position between the first two queued frames
+    position: f64,
+    mic_gain: f32,
+    /// Gain of the captured audio while ducked (1.0 = ducking off)
+    duck_gain: f32,
+    gain: f32,
+    attack: f32,
+    release: f32,
+    /// Peak envelope of the mic
+    envelope: f32,
+    envelope_release: f32,
+    threshold: f32,
+    hold_frames: u32,
+    frames_since_voice: u32,
+    /// Background noise estimate of the mic, for voice detection
+    noise: f32,
+    noise_fall: f32,
+    noise_rise: f32,
+    vad_margin: f32,
+    /// Mic level in the mix (0 = gated, 1 = open)
+    gate: f32,
+    gate_attack: f32,
+    gate_release: f32,
+    meter_frames: u32,
+    frames_since_meter: u32,
+    voice_frames: u64,
+    sample_rate: u32,
+}
+
+impl MicMixer {
+    pub fn new(
+        config: &MicConfig,
+        mic_rate: u32,
+        mic_channels: u16,
+        sample_rate: u32,
+        channels: u16,
+    ) -> (Self, PipelineSender<AudioSample>, Arc<MicMeter>) {
+        let frame_rate = mic_rate.max(1) as f64 * mic_channels.max(1) as f64;
+        let (tx, rx) = queue::channel("mic", Stage::Capture, QueueBudget::fixed(MAX_QUEUE_MS), move |samples: &AudioSample| {
+            Duration::from_secs_f64(samples.len() as f64 / frame_rate)
+        });
+        let channels = channels.max(1) as usize;
+        let hold_frames = (HOLD_MS / 1000.0 * sample_rate as f32) as u32;
+        let meter = MicMeter::new(config.mode);
+        log::info!(
+            "[MIC] {}Hz {}ch mixed at {:+.1} dB ({:?}), ducking {:.1} dB above {:.1} dBFS",
+            mic_rate, mic_channels, config.gain_db, config.mode, config.duck_db, config.threshold_db
+        );
+        let mixer = Self {
+            meter: meter.clone(),
+            rx,
+            mic_channels: mic_channels.max(1),
+            channels,
+            queue: VecDeque::new(),
+            max_queue: (mic_rate * MAX_QUEUE_MS / 1000) as usize * channels,
+            step: mic_rate as f64 / sample_rate.max(1) as f64,
+            position: 0.0,
+            mic_gain: db_to_linear(config.gain_db),
+            duck_gain: db_to_linear(-config.duck_db.abs()),
+            gain: 1.0,
+            attack: coefficient(DUCK_ATTACK_MS, sample_rate),
+            release: coefficient(DUCK_RELEASE_MS, sample_rate),
+            envelope: 0.0,
+            envelope_release: coefficient(DETECTOR_RELEASE_MS, sample_rate),
+            threshold: db_to_linear(config.threshold_db),
+            hold_frames,
+            frames_since_voice: hold_frames,
+            noise: db_to_linear(config.threshold_db),
+            noise_fall: coefficient(NOISE_FALL_MS, sample_rate),
+            noise_rise: coefficient(NOISE_RISE_MS, sample_rate),
+            vad_margin: db_to_linear(VAD_MARGIN_DB),
+            gate: if config.mode == MicMode::Always { 1.0 } else { 0.0 },
+            gate_attack: coefficient(GATE_ATTACK_MS, sample_rate),
+            gate_release: coefficient(GATE_RELEASE_MS, sample_rate),
+            meter_frames: sample_rate * METER_INTERVAL_MS / 1000,
+            frames_since_meter: 0,
+            voice_frames: 0,
+            sample_rate,
+        };
+        (mixer, tx, meter)
+    }
+
+    /// Move captured mic audio into the queue
+    fn fill(&mut self) {
+        while let Ok(samples) = self.rx.try_recv() {
+            self.queue.extend(audio::remix(&samples, self.mic_channels, self.channels as u16));
+        }
+        if self.queue.len() > self.max_queue {
+            let excess = (self.queue.len() - self.max_queue) / self.channels * self.channels;
+            self.queue.drain(..excess);
+        }
+    }
+
+    /// Next mic frame at the stream rate (linear interpolation), silence when starved
+    fn next_frame(&mut self, frame: &mut [f32]) {
+        let ch = self.channels;
+        if self.queue.len() < 2 * ch {
+            frame.iter_mut().for_each(|s| *s = 0.0);
+            return;
+        }
+        let t = self.position as f32;
+        for (c, out) in frame.iter_mut().enumerate() {
+            let (a, b) = (self.queue[c], self.queue[ch + c]);
+            *out = a + (b - a) * t;
+        }
+        self.position += self.step;
+        while self.position >= 1.0 && self.queue.len() >= 2 * ch {
+            self.queue.drain(..ch);
+            self.position -= 1.0;
+        }
+    }
+
+    /// Publish levels and voice state to the shared meter
+    fn publish(&self, voice: bool, open: bool) {
+        let meter = &self.meter;
+        meter.level_db.store(linear_to_db(self.envelope).to_bits(), Ordering::Relaxed);
+        meter.noise_db.store(linear_to_db(self.noise).to_bits(), Ordering::Relaxed);
+        meter.voice.store(voice, Ordering::Relaxed);
+        meter.open.store(open, Ordering::Relaxed);
+        meter
+            .voice_ms
+            .store(self.voice_frames * 1000 / self.sample_rate.max(1) as u64, Ordering::Relaxed);
+    }
+}
+
+impl AudioProcessor for MicMixer {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.fill();
+
+        let mut mic = vec![0.0; self.channels];
+        for frame in samples.chunks_mut(self.channels) {
+            self.next_frame(&mut mic);
+
+            // Voice: above the threshold and clearly above the mic's own background noise
+            let peak = mic.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            if peak > self.envelope {
+                self.envelope = peak;
+            } else {
+                self.envelope += self.envelope_release * (peak - self.envelope);
+            }
+            let noise_speed = if self.envelope < self.noise { self.noise_fall } else { self.noise_rise };
+            self.noise += noise_speed * (self.envelope - self.noise);
+            if self.envelope > self.threshold && self.envelope > self.noise * self.vad_margin {
+                self.frames_since_voice = 0;
+                self.voice_frames += 1;
+            } else {
+                self.frames_since_voice = self.frames_since_voice.saturating_add(1);
+            }
+            let voice = self.frames_since_voice < self.hold_frames;
+
+            // Talking opens the gate (outside `always` mode) and ducks the captured audio
+            let talking = match self.meter.mode {
+                MicMode::Always | MicMode::Vad => voice,
+                MicMode::Ptt => self.meter.is_ptt_held(),
+            };
+            let open = self.meter.mode == MicMode::Always || talking;
+            let gate_target = if open { 1.0 } else { 0.0 };
+            let gate_speed = if open { self.gate_attack } else { self.gate_release };
+            self.gate += gate_speed * (gate_target - self.gate);
+
+            let target = if talking { self.duck_gain } else { 1.0 };
+            let speed = if target < self.gain { self.attack } else { self.release };
+            self.gain += speed * (target - self.gain);
+
+            let mic_gain = self.mic_gain * self.gate;
+            for (s, m) in frame.iter_mut().zip(&mic) {
+                *s = *s * self.gain + m * mic_gain;
+            }
+
+            self.frames_since_meter += 1;
+            if self.frames_since_meter >= self.meter_frames {
+                self.frames_since_meter = 0;
+                self.publish(voice, open);
+            }
+        }
+    }
+}
+
+/// Hold push-to-talk while `key` is down. RegisterHotKey only reports presses, so the key is polled;
+/// the state is only written on changes, leaving presses from the control API alone.
+#[cfg(windows)]
+pub fn spawn_ptt_poller(key: &str, meter: Arc<MicMeter>) {
+    let Some(vk) = virtual_key(key) else {
+        log::error!("[MIC] Unknown push-to-talk key {:?}", key);
+        return;
+    };
+    log::info!("[MIC] Push-to-talk on {}", key);
+    std::thread::spawn(move || {
+        let mut was_held = false;
+        loop {
+            let held = unsafe { winapi::um::winuser::GetAsyncKeyState(vk) } as u16 & 0x8000 != 0;
+            if held != was_held {
+                meter.set_ptt(held);
+                was_held = held;
+            }
+            std::thread::sleep(PTT_POLL);
+        }
+    });
+}
+
+/// Windows virtual-key code of a `ptt_key` name
+#[cfg(windows)]
+fn virtual_key(name: &str) -> Option<i32> {
+    let name = name.trim().to_ascii_uppercase();
+    let code = match name.as_str() {
+        "SPACE" => 0x20,
+        "CAPSLOCK" => 0x14,
+        "SCROLLLOCK" => 0x91,
+        "PAUSE" => 0x13,
+        "MOUSE4" => 0x05,
+        "MOUSE5" => 0x06,
+        _ => {
+            if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<i32>().ok()) {
+                return (1..=24).contains(&n).then_some(0x70 + n - 1);
+            }
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_alphanumeric() => c as i32,
+                _ => return None,
+            }
+        }
+    };
+    Some(code)
+}
@@ -0,0 +1,195 @@
+//! Ogg/Opus muxing
+//! Pages for HTTP listeners: per listener, or shared per tier and page grouping so each page is built once
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::hub::{self, Packet, PacketTap, Tier};
+use crate::opus_encoder::{OpusEncoder, PAGE_EOS};
+use crate::server::OpusStreamInfo;
+
+/// Upper bound for `http.ogg_frames_per_page` and `?frames=N` (10 x 20ms = 200ms per page)
+pub const MAX_OGG_FRAMES_PER_PAGE: usize = 10;
+
+/// Packets a shared stream remembers the page of: a full buffered queue plus its pre-roll
+const SHARED_HISTORY: usize = 2 * hub::DEFAULT_QUEUE_CAPACITY + hub::PREROLL_PACKETS;
+
+/// Generate a random serial number for Ogg stream
+pub fn generate_serial() -> u32 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    let time_part = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u32)
+        .unwrap_or(0);
+
+    let counter_part = COUNTER.fetch_add(1, Ordering::SeqCst);
+
+    time_part.wrapping_add(counter_part)
+}
+
+/// Ogg page state of one logical stream (serial, granule, page sequence, grouping)
+#[derive(Debug)]
+pub struct OggPager {
+    pub serial: u32,
+    /// Granule step of packets without a sample count
+    frame_size: u64,
+    granule_position: u64,
+    page_sequence: u32,
+    /// Packets grouped into one page (1 = a page per packet, lowest latency)
+    frames_per_page: usize,
+    /// Packets waiting for a full page
+    pending: Vec<Packet>,
+    /// Segment table entries the pending packets take
+    pending_lacing: usize,
+}
+
+impl OggPager {
+    pub fn new(frame_size: usize, frames_per_page: usize) -> Self {
+        Self {
+            // Generate unique serial for this Ogg stream
+            serial: generate_serial(),
+            frame_size: frame_size as u64,
+            granule_position: 0,
+            page_sequence: 2, // 0 and 1 used by headers
+            frames_per_page: frames_per_page.clamp(1, MAX_OGG_FRAMES_PER_PAGE),
+            pending: Vec::new(),
+            pending_lacing: 0,
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size as usize
+    }
+
+    /// Add a packet. Returns the page(s) it completed, empty while a group is filling.
+    /// The granule advances by the samples the encoder reported for the packet.
+    pub fn push(&mut self, packet: &Packet) -> Vec<u8> {
+        let samples = match packet.samples() {
+            0 => self.frame_size,
+            samples => samples as u64,
+        };
+        if self.frames_per_page == 1 {
+            self.granule_position += samples;
+
+            // Use our manual Ogg page creation (proper flags)
+            let ogg_page = OpusEncoder::wrap_opus_packet(
+                packet,
+                self.serial,
+                self.granule_position,
+                self.page_sequence
+            );
+            self.page_sequence += 1;
+            return ogg_page;
+        }
+
+        // A page holds at most 255 lacing values; close it early for unusually large packets
+        let lacing = OpusEncoder::lacing_len(packet);
+        let mut data = Vec::new();
+        if !self.pending.is_empty() && self.pending_lacing + lacing > 255 {
+            data = self.flush_page();
+        }
+        self.granule_position += samples;
+        self.pending.push(packet.clone());
+        self.pending_lacing += lacing;
+        if self.pending.len() >= self.frames_per_page {
+            data.extend(self.flush_page());
+        }
+        data
+    }
+
+    /// Last page of the stream: the pending packets (or none) with the EOS flag
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.page(PAGE_EOS)
+    }
+
+    /// Page of the pending packets (granule = end of the last one)
+    fn flush_page(&mut self) -> Vec<u8> {
+        self.page(0)
+    }
+
+    fn page(&mut self, header_type: u8) -> Vec<u8> {
+        let packets: Vec<&[u8]> = self.pending.iter().map(|packet| &packet[..]).collect();
+        let page = OpusEncoder::create_ogg_page_packets(&packets, self.serial, self.granule_position, self.page_sequence, header_type);
+        self.page_sequence += 1;
+        self.pending.clear();
+        self.pending_lacing = 0;
+        page
+    }
+}
+
+/// One shared stream and the pages recent packets produced
+struct SharedStream {
+    pager: OggPager,
+    /// Page completed by each remembered packet (keyed by the packet's address, which stays
+    /// unique while `order` keeps the packet alive)
+    pages: HashMap<usize, Option<Packet>>,
+    order: VecDeque<Packet>,
+}
+
+/// Pages built once at publish time and handed to every listener of the same tier and grouping,
+/// instead of each listener paging (and checksumming) every packet itself. All of them get
+/// identical bytes under one serial; a listener joins at the next page.
+pub struct SharedOggMux {
+    frame_size: usize,
+    streams: Mutex<HashMap<(Tier, usize), SharedStream>>,
+}
+
+impl SharedOggMux {
+    /// Streams for the groupings listeners are expected to use exist from the start,
+    /// so buffered listeners find pages for their pre-roll
+    pub fn new(info: &OpusStreamInfo, frames_per_page: &[usize]) -> Arc<Self> {
+        let mux = Self { frame_size: info.frame_size, streams: Mutex::new(HashMap::new()) };
+        for &frames in frames_per_page {
+            for tier in [Tier::Full, Tier::Low] {
+                mux.serial(tier, frames);
+            }
+        }
+        Arc::new(mux)
+    }
+
+    /// Serial of a stream, started on first use
+    pub fn serial(&self, tier: Tier, frames_per_page: usize) -> u32 {
+        let frames = frames_per_page.clamp(1, MAX_OGG_FRAMES_PER_PAGE);
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.entry((tier, frames)).or_insert_with(|| SharedStream {
+            pager: OggPager::new(self.frame_size, frames),
+            pages: HashMap::new(),
+            order: VecDeque::new(),
+        });
+        stream.pager.serial
+    }
+
+    /// Page completed by `packet` (None while a group fills, or for packets published before
+    /// the stream started or too long ago). Looked up by packet, so a listener moved to the
+    /// other tier gets that tier's pages.
+    pub fn page(&self, frames_per_page: usize, packet: &[u8]) -> Option<Packet> {
+        let frames = frames_per_page.clamp(1, MAX_OGG_FRAMES_PER_PAGE);
+        let key = packet.as_ptr() as usize;
+        let streams = self.streams.lock().unwrap();
+        streams
+            .iter()
+            .filter(|((_, stream_frames), _)| *stream_frames == frames)
+            .find_map(|(_, stream)| stream.pages.get(&key))?
+            .clone()
+    }
+}
+
+impl PacketTap for SharedOggMux {
+    fn published(&self, tier: Tier, packet: &Packet) {
+        let mut streams = self.streams.lock().unwrap();
+        for ((_, _), stream) in streams.iter_mut().filter(|((stream_tier, _), _)| *stream_tier == tier) {
+            let page = stream.pager.push(packet);
+            stream.pages.insert(packet.as_ptr() as usize, (!page.is_empty()).then(|| Packet::from(page)));
+            stream.order.push_back(packet.clone());
+            if stream.order.len() > SHARED_HISTORY {
+                if let Some(old) = stream.order.pop_front() {
+                    stream.pages.remove(&(old.as_ptr() as usize));
+                }
+            }
+        }
+    }
+}
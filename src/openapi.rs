@@ -0,0 +1,492 @@
+//! OpenAPI description of the control API
+//! Served at /api/openapi.json so integrations can be generated against it; keep in step with control::route
+
+use serde_json::{json, Value};
+
+/// Reference to a schema under components
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// JSON response with a schema
+fn body(description: &str, schema: Value) -> Value {
+    json!({ "description": description, "content": { "application/json": { "schema": schema } } })
+}
+
+fn error(description: &str) -> Value {
+    body(description, schema("Error"))
+}
+
+/// One operation; `role` is the lowest token role allowed to call it
+fn operation(id: &str, summary: &str, role: &str, request: Option<Value>, responses: Value) -> Value {
+    let mut op = json!({
+        "operationId": id,
+        "summary": summary,
+        "x-rustcast-role": role,
+        "responses": responses,
+    });
+    if let Some(request) = request {
+        op["requestBody"] = json!({ "required": true, "content": { "application/json": { "schema": request } } });
+    }
+    if role == "admin" {
+        op["responses"]["403"] = error("The token's role is too low");
+    }
+    op["responses"]["401"] = error("Missing or invalid token");
+    op["responses"]["429"] = json!({
+        "description": "Rate limited or banned after failed logins",
+        "headers": { "Retry-After": { "schema": { "type": "integer" } } },
+        "content": { "application/json": { "schema": schema("Error") } },
+    });
+    op
+}
+
+/// Object with the given (all required) properties
+fn object(properties: Value) -> Value {
+    let required: Vec<&String> = properties.as_object().map(|p| p.keys().collect()).unwrap_or_default();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn station() -> Value {
+    let string = json!({ "type": "string" });
+    object(json!({ "name": string, "description": string, "genre": string, "website": string }))
+}
+
+/// Input device of the main source in /status (null for other sources)
+fn device() -> Value {
+    let nullable = json!({ "type": "string", "nullable": true });
+    let mut device = object(json!({
+        "device": { "type": "string", "description": "Configured name, lowercase (empty = default input)" },
+        "using": nullable,
+        "state": { "type": "string", "enum": ["active", "waiting", "fallback", "stopped", "standby"] },
+        "on_lost": { "type": "string", "enum": ["wait", "follow_default", "stop"] },
+        "standby": nullable,
+        "error": nullable,
+    }));
+    device["nullable"] = json!(true);
+    device
+}
+
+/// Health score and troubleshooting list in /status (null during the first second)
+fn health() -> Value {
+    let codes = [
+        "capture_drops", "encode_drops", "encoder_load", "encoder_late", "slow_listener", "listener_drops",
+        "high_cpu", "device_lost", "failover", "bitstream",
+    ];
+    let finding = json!({
+        "type": "object",
+        "required": ["code", "penalty", "message"],
+        "description": "Other properties depend on code (per_min, load_percent, ip, behind_ms, ...)",
+        "additionalProperties": true,
+        "properties": {
+            "code": { "type": "string", "enum": codes },
+            "penalty": { "type": "integer" },
+            "message": { "type": "string", "description": "What is wrong and what to do about it" },
+        },
+    });
+    let mut health = object(json!({
+        "score": { "type": "integer", "minimum": 0, "maximum": 100 },
+        "grade": { "type": "string", "enum": ["good", "fair", "poor"] },
+        "issues": { "type": "array", "items": finding },
+    }));
+    health["nullable"] = json!(true);
+    health
+}
+
+/// Windows master volume in /status (null elsewhere)
+fn system_volume() -> Value {
+    let mut volume = object(json!({
+        "volume_percent": { "type": "integer" },
+        "muted": { "type": "boolean" },
+        "linked": { "type": "boolean", "description": "Stream mute follows the Windows mute" },
+        "compensation_db": { "type": "number", "description": "Boost undoing the volume (volume_compensation), 0 when off" },
+    }));
+    volume["nullable"] = json!(true);
+    volume
+}
+
+/// Last minute of levels as JSON or a PNG thumbnail
+fn waveform() -> Value {
+    let levels = json!({ "type": "array", "items": { "type": "number" }, "description": "dBFS per step, oldest first" });
+    let mut op = operation("getWaveform", "Peak and RMS of the last 60 seconds", "admin", None, json!({
+        "200": {
+            "description": "Levels in 100ms steps",
+            "content": {
+                "application/json": { "schema": object(json!({
+                    "interval_ms": { "type": "integer" },
+                    "peak_db": levels,
+                    "rms_db": levels,
+                })) },
+                "image/png": { "schema": { "type": "string", "format": "binary" } },
+            },
+        },
+    }));
+    op["parameters"] = json!([
+        { "name": "format", "in": "query", "schema": { "type": "string", "enum": ["json", "png"], "default": "json" } },
+        { "name": "height", "in": "query", "description": "PNG height in pixels (16-400)",
+            "schema": { "type": "integer", "default": 80 } },
+    ]);
+    op
+}
+
+/// GET /api/events: recent events, or the pushed feed
+fn events() -> Value {
+    let integer = json!({ "type": "integer" });
+    let string = json!({ "type": "string" });
+    let types = ["stream_started", "stream_stopped", "client_connected", "client_disconnected", "device_changed", "error"];
+    let event = json!({
+        "type": "object",
+        "required": ["at", "type"],
+        "description": "Other properties depend on type: id, kind, ip (client_*), state, device (device_changed), message (error)",
+        "properties": {
+            "at": integer,
+            "type": { "type": "string", "enum": types },
+            "id": integer,
+            "kind": string,
+            "ip": { "type": "string", "nullable": true },
+            "state": { "type": "string", "enum": ["active", "waiting", "fallback", "stopped", "standby"] },
+            "device": string,
+            "message": string,
+        },
+    });
+    let mut op = operation("getEvents", "Recent stream, listener, device and error events", "admin", None, json!({
+        "200": body("Last 50 events, oldest first", object(json!({ "events": { "type": "array", "items": event } }))),
+    }));
+    op["description"] = json!("`at` is Unix time in milliseconds. With `Upgrade: websocket` the connection \
+        stays open and every new event arrives as a JSON text frame of the same shape.");
+    op
+}
+
+/// Stats feed sample (also pushed over WebSocket)
+fn stats() -> Value {
+    let integer = || json!({ "type": "integer" });
+    let number = || json!({ "type": "number" });
+    let boolean = || json!({ "type": "boolean" });
+    let string = || json!({ "type": "string" });
+    let listener = object(json!({
+        "id": integer(), "kind": string(), "tier": string(), "mode": string(),
+        "dropped": integer(), "backlog": integer(), "kiosk": boolean(),
+        "ip": { "type": "string", "nullable": true }, "device": { "type": "string", "nullable": true },
+        "location": { "type": "string", "nullable": true },
+    }));
+    let mut agc = object(json!({ "input_db": number(), "output_db": number(), "gain_db": number(), "bypassed": boolean() }));
+    agc["nullable"] = json!(true);
+    let mut mic = object(json!({ "level_db": number(), "noise_db": number(), "voice": boolean(), "open": boolean() }));
+    mic["nullable"] = json!(true);
+    let mut op = operation("getStats", "Listeners, levels and drops", "admin", None, json!({
+        "200": body("Current sample", object(json!({
+            "clients": object(json!({ "total": integer(), "websocket": integer(), "http": integer(), "tcp": integer() })),
+            "listeners": { "type": "array", "items": listener },
+            "peak_db": number(),
+            "agc": agc,
+            "mic": mic,
+            "drops": object(json!({ "capture": integer(), "encode": integer(), "client": integer() })),
+            "capture_glitches": object(json!({ "gaps": integer(), "errors": integer(), "filled_ms": integer() })),
+        }))),
+    }));
+    op["description"] = json!("With `Upgrade: websocket` the connection stays open and every change \
+        (sampled every 250ms) arrives as a JSON text frame of the same shape.");
+    op
+}
+
+/// Everything /status reports (more keys may be added)
+fn status() -> Value {
+    let integer = || json!({ "type": "integer" });
+    let string = || json!({ "type": "string" });
+    let boolean = || json!({ "type": "boolean" });
+    let number = || json!({ "type": "number" });
+    json!({
+        "type": "object",
+        "required": ["clients", "running", "streaming"],
+        "additionalProperties": true,
+        "properties": {
+            "clients": integer(),
+            "clients_by_type": object(json!({ "websocket": integer(), "http": integer(), "tcp": integer() })),
+            "running": boolean(),
+            "streaming": boolean(),
+            "drops": object(json!({ "capture": integer(), "encode": integer(), "client": integer() })),
+            "capture_glitches": object(json!({ "gaps": integer(), "errors": integer(), "filled_ms": integer() })),
+            "write_timeouts": integer(),
+            "encoder": object(json!({
+                "load_percent": number(),
+                "peak_percent": number(),
+                "setting": string(),
+                "lowered": integer(),
+            })),
+            "encoders": { "type": "array", "items": { "type": "object" } },
+            "resources": object(json!({
+                "cpu_percent": { "type": "number", "nullable": true },
+                "memory_bytes": { "type": "integer", "nullable": true },
+                "send_bytes_per_sec": integer(),
+                "bytes_sent": integer(),
+            })),
+            "privacy": object(json!({
+                "apps": { "type": "array", "items": string() },
+                "action": { "type": "string", "enum": ["mute", "stop"] },
+                "active_app": { "type": "string", "nullable": true },
+            })),
+            "device": schema("Device"),
+            "health": schema("Health"),
+            "system_volume": schema("SystemVolume"),
+            "validation": {
+                "type": "array",
+                "description": "Encoder output checks (empty unless debug.validate_output is on)",
+                "items": object(json!({
+                    "stream": { "type": "string", "enum": ["opus", "vorbis", "mp3"] },
+                    "units": integer(),
+                    "malformed": integer(),
+                    "first_error": { "type": "string", "nullable": true },
+                })),
+            },
+            "tunnel": {
+                "type": "object",
+                "nullable": true,
+                "description": "Relay tunnel (null unless tunnel.enabled)",
+                "properties": {
+                    "relay": string(),
+                    "connected": boolean(),
+                    "public_url": { "type": "string", "nullable": true },
+                    "streams": integer(),
+                    "error": { "type": "string", "nullable": true },
+                },
+            },
+            "dsp_errors": {
+                "type": "array",
+                "description": "Problems in the dsp config stages; those stages were skipped",
+                "items": string(),
+            },
+            "station": schema("Station"),
+            "mic": schema("Mic"),
+            "disabled_endpoints": { "type": "array", "items": string() },
+        },
+    })
+}
+
+/// OpenAPI 3.0 document of every control API route
+pub fn control_api() -> Value {
+    let ok = || body("Done", schema("Ok"));
+    let integer = || json!({ "type": "integer" });
+    let string = || json!({ "type": "string" });
+    let boolean = || json!({ "type": "boolean" });
+    let number = || json!({ "type": "number" });
+    let name_param = |name: &str, description: &str| {
+        json!([{ "name": name, "in": "path", "required": true, "description": description, "schema": { "type": "string" } }])
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "RustCast control API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Management API on control_port. With control_tokens set, every request needs \
+                `Authorization: Bearer <token>`; listener tokens may only call operations marked \
+                x-rustcast-role: listener.",
+        },
+        "security": [{ "bearer": [] }],
+        "paths": {
+            "/status": { "get": operation("getStatus", "Server status", "listener", None, json!({
+                "200": body("Status", schema("Status")),
+            })) },
+            "/metrics": { "get": operation("getMetrics", "Prometheus text exposition", "listener", None, json!({
+                "200": { "description": "Metrics", "content": { "text/plain": { "schema": string() } } },
+            })) },
+            "/api/openapi.json": { "get": operation("getOpenApi", "This document", "listener", None, json!({
+                "200": body("OpenAPI document", json!({ "type": "object" })),
+            })) },
+            "/api/stream": { "post": operation("setStreaming", "Start or stop streaming", "listener",
+                Some(object(json!({ "streaming": boolean() }))),
+                json!({
+                    "200": body("Requested", object(json!({ "ok": boolean(), "streaming": boolean() }))),
+                    "400": error("Body is not {\"streaming\": bool}"),
+                })) },
+            "/api/chat/mute": { "post": operation("muteChat", "Mute a chat user by address", "admin",
+                Some(object(json!({ "ip": string() }))),
+                json!({ "200": ok(), "400": error("Body is not {\"ip\": string}") })) },
+            "/api/chat/clear": { "post": operation("clearChat", "Clear the chat history", "admin", None, json!({
+                "200": ok(),
+            })) },
+            "/api/clients": { "get": operation("listClients", "Connected listeners", "admin", None, json!({
+                "200": body("Listeners", object(json!({ "clients": { "type": "array", "items": schema("Client") } }))),
+            })) },
+            "/api/rate-limit": { "get": operation("listRateLimited", "Addresses currently limited or banned", "admin", None, json!({
+                "200": body("Limited addresses", object(json!({
+                    "refused": integer(),
+                    "limited": { "type": "array", "items": schema("LimitedIp") },
+                }))),
+            })) },
+            "/api/rate-limit/{ip}": {
+                "parameters": name_param("ip", "IPv4 or IPv6 address"),
+                "delete": operation("unban", "Lift a ban / reset an address", "admin", None, json!({
+                    "200": ok(),
+                    "400": error("Not an IP address"),
+                    "404": error("Address is not limited"),
+                })),
+            },
+            "/api/sinks": {
+                "get": operation("listSinks", "Server-side outputs", "admin", None, json!({
+                    "200": body("Outputs", json!({ "type": "array", "items": schema("Sink") })),
+                })),
+                "post": operation("addSink", "Start an output (same object as a `sinks` config entry)", "admin",
+                    Some(schema("SinkConfig")),
+                    json!({ "200": ok(), "400": error("Invalid sink config"), "409": error("Could not start") })),
+            },
+            "/api/sinks/{name}": {
+                "parameters": name_param("name", "Sink name"),
+                "delete": operation("removeSink", "Stop and remove an output", "admin", None, json!({
+                    "200": ok(),
+                    "404": error("No such sink"),
+                })),
+            },
+            "/api/cue": { "post": operation("markCue", "Mark a cue point (recordings and players)", "admin",
+                Some(json!({ "type": "object", "properties": { "label": string() } })),
+                json!({
+                    "200": body("Marked", object(json!({ "ok": boolean(), "cue": { "type": "object" }, "recordings": integer() }))),
+                })) },
+            "/api/test-tone": { "post": operation("playTestTone", "Play a short 440 Hz tone into the stream", "admin",
+                None,
+                json!({
+                    "200": body("Playing", object(json!({ "ok": boolean(), "duration_ms": integer() }))),
+                    "409": error("Not streaming, or the stream is muted"),
+                })) },
+            "/api/spectrum": { "post": operation("setSpectrum", "Turn the spectrum analyzer on or off", "admin",
+                Some(object(json!({ "enabled": boolean() }))),
+                json!({
+                    "200": body("Changed", object(json!({ "ok": boolean(), "enabled": boolean() }))),
+                    "400": error("Body is not {\"enabled\": bool}"),
+                    "409": error("Spectrum analyzer is not configured"),
+                })) },
+            "/api/tier": { "post": operation("setTier", "Move a listener to the full or low tier", "admin",
+                Some(object(json!({ "client_id": integer(), "tier": { "type": "string", "enum": ["full", "low"] } }))),
+                json!({
+                    "200": body("Moved", object(json!({ "ok": boolean(), "tier": string() }))),
+                    "400": error("Invalid body"),
+                    "404": error("No such client"),
+                    "409": error("Low tier is not configured"),
+                })) },
+            "/api/station": {
+                "get": operation("getStation", "Station metadata", "admin", None, json!({
+                    "200": body("Station", schema("Station")),
+                })),
+                "post": operation("setStation", "Change station metadata (omitted fields are kept)", "admin",
+                    Some(json!({ "type": "object", "properties": station()["properties"].clone() })),
+                    json!({
+                        "200": body("Changed", object(json!({ "ok": boolean(), "changed": boolean(), "station": schema("Station") }))),
+                        "400": error("Invalid station metadata"),
+                    })),
+            },
+            "/api/mic": { "post": operation("setPushToTalk", "Hold or release push-to-talk", "admin",
+                Some(object(json!({ "ptt": boolean() }))),
+                json!({
+                    "200": body("Changed", object(json!({ "ok": boolean(), "mic": schema("Mic") }))),
+                    "400": error("Body is not {\"ptt\": bool}"),
+                    "409": error("Mic is not configured"),
+                })) },
+            "/api/waveform": { "get": waveform() },
+            "/api/stats": { "get": stats() },
+            "/api/events": { "get": events() },
+            "/api/config": {
+                "get": operation("getConfig", "Running bitrate, AGC and DSP stages", "admin", None, json!({
+                    "200": body("Live settings", schema("LiveSettings")),
+                })),
+                "patch": operation("patchConfig",
+                    "Merge fields into config.json; bitrate, agc and dsp apply at once, the rest after a restart", "admin",
+                    Some(json!({ "type": "object", "description": "Any config fields (JSON merge patch, arrays replace)" })),
+                    json!({
+                        "200": body("Patched", object(json!({
+                            "ok": boolean(),
+                            "applied": { "type": "array", "items": string() },
+                            "restart_required": { "type": "array", "items": string() },
+                            "config": schema("LiveSettings"),
+                        }))),
+                        "400": error("Unknown field, wrong type or invalid dsp stages (listed in dsp_errors)"),
+                        "500": error("config.json could not be written"),
+                    })),
+            },
+            "/api/delay/dump": { "post": operation("dumpDelay", "Drop everything in the broadcast delay", "admin", None, json!({
+                "200": body("Dumped", object(json!({ "ok": boolean(), "dumped_frames": integer() }))),
+                "409": error("Broadcast delay is off"),
+            })) },
+        },
+        "components": {
+            "securitySchemes": { "bearer": { "type": "http", "scheme": "bearer" } },
+            "schemas": {
+                "Ok": object(json!({ "ok": boolean() })),
+                "Error": object(json!({ "error": string() })),
+                "Station": station(),
+                "LiveSettings": object(json!({
+                    "bitrate": integer(),
+                    "agc": { "type": "object", "description": "As in config.json" },
+                    "dsp": { "type": "array", "items": { "type": "object", "description": "Stage with a type: gain, eq, limiter, agc or downmix" } },
+                    "live_fields": { "type": "array", "items": string() },
+                })),
+                "Client": object(json!({
+                    "id": integer(),
+                    "kind": { "type": "string", "enum": ["websocket", "http", "tcp"] },
+                    "tier": { "type": "string", "enum": ["full", "low"] },
+                    "mode": { "type": "string", "enum": ["realtime", "buffered"] },
+                    "queued": integer(),
+                    "dropped": integer(),
+                    "bytes_sent": integer(),
+                    "backlog": integer(),
+                    "kiosk": { "type": "boolean", "description": "Unattended player opened at /kiosk" },
+                    "ip": { "type": "string", "nullable": true },
+                    "device": { "type": "string", "nullable": true, "description": "Device ID the web player sent (blocklist.devices)" },
+                    "location": { "type": "string", "nullable": true, "description": "\"City, CC\" of a remote listener (geoip.database)" },
+                    "socket": { "type": "object", "description": "Effective nodelay, send_buffer, write_timeout_ms" },
+                })),
+                "LimitedIp": object(json!({
+                    "ip": string(),
+                    "banned_secs": { "type": "integer", "nullable": true },
+                    "failures": integer(),
+                    "refused": integer(),
+                    "last_refused_secs": { "type": "integer", "nullable": true },
+                })),
+                "Sink": object(json!({
+                    "name": string(),
+                    "kind": string(),
+                    "running": boolean(),
+                    "stats": { "type": "object" },
+                })),
+                "SinkConfig": {
+                    "description": "Same as an entry of `sinks` in config.json",
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "required": ["type", "name", "path"],
+                            "properties": {
+                                "type": { "type": "string", "enum": ["file"] },
+                                "name": string(),
+                                "path": { "type": "string", "description": "{time} becomes the UTC start time" },
+                            },
+                        },
+                        {
+                            "type": "object",
+                            "required": ["type", "name", "dir"],
+                            "properties": {
+                                "type": { "type": "string", "enum": ["archive"] },
+                                "name": string(),
+                                "dir": string(),
+                                "segment": { "type": "string", "enum": ["hourly", "daily"] },
+                                "retention_days": integer(),
+                                "only_with_listeners": boolean(),
+                            },
+                        },
+                    ],
+                    "discriminator": { "propertyName": "type" },
+                },
+                "Mic": object(json!({
+                    "mode": { "type": "string", "enum": ["always", "vad", "ptt"] },
+                    "level_db": number(),
+                    "noise_db": number(),
+                    "voice": boolean(),
+                    "open": boolean(),
+                    "ptt_held": boolean(),
+                    "voice_secs": integer(),
+                })),
+                "Device": device(),
+                "Health": health(),
+                "SystemVolume": system_volume(),
+                "Status": status(),
+            },
+        },
+    })
+}
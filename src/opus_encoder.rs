@@ -1,7 +1,54 @@
 //! Opus encoding module for low-latency audio streaming
 //! Opus is optimized for real-time audio with latency as low as 5ms
 
-use audiopus::{coder::Encoder, Application, Channels, SampleRate};
+use audiopus::{coder::Decoder, coder::Encoder, packet::Packet, Application, Bandwidth, Channels, MutSignals, SampleRate, Signal};
+
+use crate::config::{OpusBandwidth, OpusConfig, OpusSignal};
+use crate::hub::{self, FramePriority};
+use crate::signal::{SignalDetector, SignalKind};
+
+/// Samples per encoded frame (20ms at 48kHz)
+pub const FRAME_SIZE: usize = 960;
+
+/// Complexity unless `opus.complexity` says otherwise
+const DEFAULT_COMPLEXITY: u8 = 5;
+
+/// Ogg page header type flags (RFC 3533)
+pub const PAGE_BOS: u8 = 0x02;
+pub const PAGE_EOS: u8 = 0x04;
+
+/// Opus packets up to this size carry silence (DTX / digital silence)
+const SILENT_PACKET_MAX: usize = 3;
+
+/// Whether an encoded packet carries only silence
+pub fn is_silent_packet(packet: &[u8]) -> bool {
+    packet.len() <= SILENT_PACKET_MAX
+}
+
+/// A 20ms packet of digital silence (CELT fullband TOC, mono or stereo), valid without an encoder
+pub fn silent_packet(channels: u16) -> Vec<u8> {
+    let toc = if channels >= 2 { 0xFC } else { 0xF8 };
+    vec![toc, 0xFF, 0xFE]
+}
+
+/// Marks encoded packets for congestion handling in the hub
+#[derive(Debug, Default)]
+pub struct FrameClassifier {
+    in_silence: bool,
+}
+
+impl FrameClassifier {
+    /// Silence is droppable; the first sound after silence is critical
+    pub fn classify(&mut self, packet: &[u8]) -> FramePriority {
+        let silent = is_silent_packet(packet);
+        let was_silent = std::mem::replace(&mut self.in_silence, silent);
+        match (silent, was_silent) {
+            (true, _) => FramePriority::Droppable,
+            (false, true) => FramePriority::Critical,
+            (false, false) => FramePriority::Normal,
+        }
+    }
+}
 
 /// Opus encoder wrapper
 pub struct OpusEncoder {
@@ -11,6 +58,9 @@ pub struct OpusEncoder {
     frame_size: usize,
     // Buffer for accumulating samples until we have a full frame
     sample_buffer: Vec<i16>,
+    /// Switches the signal hint between voice and music (`opus.signal = "auto"`)
+    detector: Option<SignalDetector>,
+    complexity: u8,
 }
 
 impl OpusEncoder {
@@ -49,13 +99,13 @@ impl OpusEncoder {
             .map_err(|e| format!("Failed to set DTX: {:?}", e))?;
         
         // Set complexity (0-10, lower = faster encoding)
-        encoder.set_complexity(5)
+        encoder.set_complexity(DEFAULT_COMPLEXITY)
             .map_err(|e| format!("Failed to set complexity: {:?}", e))?;
         
         // Frame size in samples at 48kHz
         // Opus supports: 2.5, 5, 10, 20, 40, 60, 80, 100, 120ms
         // 20ms = 960 samples at 48kHz (Discord-style, good efficiency)
-        let frame_size = 960; // 20ms at 48kHz
+        let frame_size = FRAME_SIZE;
         
         log::info!(
             "Opus encoder created: {}Hz -> 48kHz, {} channels, {}kbps, {}ms frame",
@@ -71,14 +121,75 @@ impl OpusEncoder {
             channels,
             frame_size,
             sample_buffer: Vec::with_capacity(frame_size * channels as usize * 2),
+            detector: None,
+            complexity: DEFAULT_COMPLEXITY,
         })
     }
+
+    /// Apply the signal type and bandwidth hints
+    pub fn tune(&mut self, config: &OpusConfig) -> Result<(), String> {
+        let bandwidth = match config.max_bandwidth {
+            OpusBandwidth::Auto => None,
+            OpusBandwidth::Narrowband => Some(Bandwidth::Narrowband),
+            OpusBandwidth::Mediumband => Some(Bandwidth::Mediumband),
+            OpusBandwidth::Wideband => Some(Bandwidth::Wideband),
+            OpusBandwidth::Superwideband => Some(Bandwidth::Superwideband),
+            OpusBandwidth::Fullband => Some(Bandwidth::Fullband),
+        };
+        if let Some(bandwidth) = bandwidth {
+            self.encoder.set_max_bandwidth(bandwidth)
+                .map_err(|e| format!("Failed to set max bandwidth: {:?}", e))?;
+        }
+
+        let signal = match config.signal {
+            OpusSignal::Auto => None,
+            OpusSignal::Music => Some(Signal::Music),
+            OpusSignal::Voice => Some(Signal::Voice),
+        };
+        match signal {
+            Some(signal) => {
+                self.encoder.set_signal(signal)
+                    .map_err(|e| format!("Failed to set signal: {:?}", e))?;
+                self.detector = None;
+            }
+            None => self.detector = Some(SignalDetector::new(self.channels)),
+        }
+        self.set_complexity(config.complexity)
+    }
+
+    /// Change the bitrate (kbps) between frames
+    pub fn set_bitrate(&mut self, bitrate: u32) -> Result<(), String> {
+        self.encoder.set_bitrate(audiopus::Bitrate::BitsPerSecond((bitrate * 1000) as i32))
+            .map_err(|e| format!("Failed to set bitrate: {:?}", e))
+    }
+
+    pub fn complexity(&self) -> u8 {
+        self.complexity
+    }
+
+    /// Change the complexity (0-10) between frames
+    pub fn set_complexity(&mut self, complexity: u8) -> Result<(), String> {
+        let complexity = complexity.min(10);
+        self.encoder.set_complexity(complexity)
+            .map_err(|e| format!("Failed to set complexity: {:?}", e))?;
+        self.complexity = complexity;
+        Ok(())
+    }
     
     /// Create a raw Ogg page with proper flags (no BOS for audio data pages)
     /// This is needed because PacketWriter always sets BOS on first packet
     pub fn create_ogg_page(data: &[u8], serial: u32, granule: u64, page_sequence: u32, is_bos: bool) -> Vec<u8> {
+        Self::create_ogg_page_packets(&[data], serial, granule, page_sequence, if is_bos { PAGE_BOS } else { 0 })
+    }
+
+    /// Create an Ogg page holding several complete packets (granule = end of the last one).
+    /// The lacing values of all packets must fit the 255-entry segment table.
+    /// `header_type` holds the page flags (`PAGE_BOS`, `PAGE_EOS`).
+    pub fn create_ogg_page_packets(packets: &[&[u8]], serial: u32, granule: u64, page_sequence: u32, header_type: u8) -> Vec<u8> {
+        let data_len: usize = packets.iter().map(|packet| packet.len()).sum();
+
         // Ogg page structure (RFC 3533)
-        let mut page = Vec::with_capacity(27 + 255 + data.len());
+        let mut page = Vec::with_capacity(27 + 255 + data_len);
         
         // Capture pattern
         page.extend_from_slice(b"OggS");
@@ -87,7 +198,6 @@ impl OpusEncoder {
         page.push(0);
         
         // Header type flags: 0x02 = BOS, 0x04 = EOS, 0x01 = continued
-        let header_type = if is_bos { 0x02u8 } else { 0x00u8 };
         page.push(header_type);
         
         // Granule position (8 bytes, little-endian)
@@ -105,20 +215,12 @@ impl OpusEncoder {
         
         // Calculate segment table
         let mut segments = Vec::new();
-        let mut remaining = data.len();
-        while remaining > 0 {
-            if remaining >= 255 {
-                segments.push(255u8);
-                remaining -= 255;
-            } else {
-                segments.push(remaining as u8);
-                remaining = 0;
-            }
-        }
-        // Ensure we have at least one segment for empty packets
-        if segments.is_empty() {
-            segments.push(0);
+        for packet in packets {
+            segments.resize(segments.len() + packet.len() / 255, 255u8);
+            // A packet ends with a lacing value below 255 (empty packets and exact multiples of 255 get a 0)
+            segments.push((packet.len() % 255) as u8);
         }
+        debug_assert!(segments.len() <= 255, "Ogg page segment table overflow");
         
         // Number of segments
         page.push(segments.len() as u8);
@@ -127,7 +229,9 @@ impl OpusEncoder {
         page.extend_from_slice(&segments);
         
         // Page data
-        page.extend_from_slice(data);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
         
         // Calculate and insert CRC-32
         let crc = ogg_crc32(&page);
@@ -137,7 +241,9 @@ impl OpusEncoder {
     }
     
     /// Get Ogg Opus headers with a specific serial (for new client streams)
-    pub fn get_headers_with_serial(channels: u16, sample_rate: u32, serial: u32) -> Vec<u8> {
+    ///
+    /// `comments` are Vorbis comments (`KEY=value`) written to OpusTags.
+    pub fn get_headers_with_serial(channels: u16, sample_rate: u32, serial: u32, comments: &[String]) -> Vec<u8> {
         // OpusHead header (RFC 7845)
         let mut opus_head = Vec::with_capacity(19);
         opus_head.extend_from_slice(b"OpusHead");           // Magic signature
@@ -154,7 +260,11 @@ impl OpusEncoder {
         opus_tags.extend_from_slice(b"OpusTags");
         opus_tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
         opus_tags.extend_from_slice(vendor);
-        opus_tags.extend_from_slice(&0u32.to_le_bytes()); // No user comments
+        opus_tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            opus_tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            opus_tags.extend_from_slice(comment.as_bytes());
+        }
         
         let mut result = Vec::new();
         
@@ -171,16 +281,16 @@ impl OpusEncoder {
     pub fn wrap_opus_packet(packet: &[u8], serial: u32, granule: u64, page_sequence: u32) -> Vec<u8> {
         Self::create_ogg_page(packet, serial, granule, page_sequence, false)
     }
-    
-    /// Get frame size in samples
-    pub fn frame_size(&self) -> usize {
-        self.frame_size
-    }
 
+    /// Lacing values a packet takes in a page's segment table
+    pub fn lacing_len(packet: &[u8]) -> usize {
+        packet.len() / 255 + 1
+    }
+    
     /// Encode PCM samples to raw Opus packets (without Ogg container)
-    /// Returns a list of encoded Opus packets
+    /// Returns a list of encoded Opus packets, each with the samples it covers (for Ogg granules)
     /// Buffers samples until a full frame (20ms) is available
-    pub fn encode_raw(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>, String> {
+    pub fn encode_raw(&mut self, samples: &[f32]) -> Result<Vec<hub::Packet>, String> {
         if samples.is_empty() {
             return Ok(Vec::new());
         }
@@ -225,7 +335,18 @@ impl OpusEncoder {
         // Encode as many complete frames as we have
         while self.sample_buffer.len() >= samples_per_frame {
             let frame: Vec<i16> = self.sample_buffer.drain(..samples_per_frame).collect();
-            packets.push(self.encode_frame_raw(&frame)?);
+            if let Some(kind) = self.detector.as_mut().and_then(|detector| detector.push(&frame)) {
+                let signal = match kind {
+                    SignalKind::Voice => Signal::Voice,
+                    SignalKind::Music => Signal::Music,
+                };
+                match self.encoder.set_signal(signal) {
+                    Ok(()) => log::info!("[OPUS] Sounds like {}, signal hint switched", kind.as_str()),
+                    Err(e) => log::warn!("[OPUS] Failed to set signal: {:?}", e),
+                }
+            }
+            let frame_samples = (frame.len() / self.channels as usize) as u32;
+            packets.push(hub::Packet::with_samples(self.encode_frame_raw(&frame)?, frame_samples));
         }
         
         Ok(packets)
@@ -245,13 +366,89 @@ impl OpusEncoder {
     }
 }
 
-/// CRC-32 lookup table for Ogg (polynomial 0x04C11DB7)
-const CRC_LOOKUP: [u32; 256] = generate_crc_table();
+/// Longest Opus frame (120ms at 48kHz), in samples per channel
+const MAX_FRAME_SAMPLES: usize = 5760;
+
+/// Opus decoder for clients that can only play raw PCM
+pub struct OpusDecoder {
+    decoder: Decoder,
+    /// Output rate: Opus decodes to 8/12/16/24/48kHz only
+    sample_rate: u32,
+    channels: usize,
+    pcm: Vec<i16>,
+}
+
+impl OpusDecoder {
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self, String> {
+        let (opus_sample_rate, sample_rate) = match sample_rate {
+            8000 => (SampleRate::Hz8000, 8000),
+            12000 => (SampleRate::Hz12000, 12000),
+            16000 => (SampleRate::Hz16000, 16000),
+            24000 => (SampleRate::Hz24000, 24000),
+            _ => (SampleRate::Hz48000, 48000),
+        };
+        let (opus_channels, channels) = match channels {
+            1 => (Channels::Mono, 1),
+            _ => (Channels::Stereo, 2),
+        };
+        let decoder = Decoder::new(opus_sample_rate, opus_channels)
+            .map_err(|e| format!("Failed to create Opus decoder: {:?}", e))?;
+        Ok(Self {
+            decoder,
+            sample_rate,
+            channels,
+            pcm: vec![0; MAX_FRAME_SAMPLES * channels],
+        })
+    }
+
+    /// Rate of the decoded PCM (the nearest one Opus decodes to, 48kHz unless requested lower)
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Channels of the decoded PCM (mono or stereo; more are downmixed to stereo)
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Decode one packet to interleaved 16-bit little-endian PCM
+    pub fn decode_le(&mut self, packet: &[u8]) -> Result<Vec<u8>, String> {
+        let packet = Packet::try_from(packet).map_err(|e| format!("{:?}", e))?;
+        let output = MutSignals::try_from(&mut self.pcm[..]).map_err(|e| format!("{:?}", e))?;
+        let samples = self
+            .decoder
+            .decode(Some(packet), output, false)
+            .map_err(|e| format!("Opus decode failed: {:?}", e))?;
+        Ok(self.pcm[..samples * self.channels]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect())
+    }
+
+    /// Decode one packet to interleaved f32 samples
+    pub fn decode_float(&mut self, packet: &[u8]) -> Result<Vec<f32>, String> {
+        let packet = Packet::try_from(packet).map_err(|e| format!("{:?}", e))?;
+        let mut pcm = vec![0.0f32; MAX_FRAME_SAMPLES * self.channels];
+        let output = MutSignals::try_from(&mut pcm[..]).map_err(|e| format!("{:?}", e))?;
+        let samples = self
+            .decoder
+            .decode_float(Some(packet), output, false)
+            .map_err(|e| format!("Opus decode failed: {:?}", e))?;
+        pcm.truncate(samples * self.channels);
+        Ok(pcm)
+    }
+}
+
+/// CRC-32 lookup tables for Ogg (polynomial 0x04C11DB7, MSB first, no reflection or final XOR).
+/// `CRC_LOOKUP[0]` is the classic byte table; `CRC_LOOKUP[k]` advances a byte by k more zero bytes,
+/// so eight bytes are folded per step (slicing-by-8). The SSE4.2 `crc32` instruction only does the
+/// reflected Castagnoli polynomial, which no table transformation turns into this one.
+const CRC_LOOKUP: [[u32; 256]; 8] = generate_crc_tables();
 
-/// Generate CRC lookup table at compile time
-const fn generate_crc_table() -> [u32; 256] {
+/// Generate the CRC lookup tables at compile time
+const fn generate_crc_tables() -> [[u32; 256]; 8] {
     const POLYNOMIAL: u32 = 0x04C11DB7;
-    let mut table = [0u32; 256];
+    let mut tables = [[0u32; 256]; 8];
     let mut i = 0;
     while i < 256 {
         let mut crc = (i as u32) << 24;
@@ -264,18 +461,40 @@ const fn generate_crc_table() -> [u32; 256] {
             }
             j += 1;
         }
-        table[i] = crc;
+        tables[0][i] = crc;
         i += 1;
     }
-    table
+    let mut k = 1;
+    while k < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let previous = tables[k - 1][i];
+            tables[k][i] = (previous << 8) ^ tables[0][(previous >> 24) as usize];
+            i += 1;
+        }
+        k += 1;
+    }
+    tables
 }
 
 /// CRC-32 for Ogg pages
-fn ogg_crc32(data: &[u8]) -> u32 {
+pub fn ogg_crc32(data: &[u8]) -> u32 {
     let mut crc: u32 = 0;
-    for &byte in data {
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let high = crc ^ u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        crc = CRC_LOOKUP[7][(high >> 24) as usize]
+            ^ CRC_LOOKUP[6][(high >> 16) as u8 as usize]
+            ^ CRC_LOOKUP[5][(high >> 8) as u8 as usize]
+            ^ CRC_LOOKUP[4][high as u8 as usize]
+            ^ CRC_LOOKUP[3][chunk[4] as usize]
+            ^ CRC_LOOKUP[2][chunk[5] as usize]
+            ^ CRC_LOOKUP[1][chunk[6] as usize]
+            ^ CRC_LOOKUP[0][chunk[7] as usize];
+    }
+    for &byte in chunks.remainder() {
         let index = ((crc >> 24) ^ (byte as u32)) as u8;
-        crc = (crc << 8) ^ CRC_LOOKUP[index as usize];
+        crc = (crc << 8) ^ CRC_LOOKUP[0][index as usize];
     }
     crc
 }
@@ -288,3 +507,66 @@ fn rand_serial() -> u32 {
         .map(|d| d.as_nanos() as u32)
         .unwrap_or(12345)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The byte-at-a-time CRC the slicing version replaced
+    fn ogg_crc32_bytewise(data: &[u8]) -> u32 {
+        let mut crc: u32 = 0;
+        for &byte in data {
+            let index = ((crc >> 24) ^ (byte as u32)) as u8;
+            crc = (crc << 8) ^ CRC_LOOKUP[0][index as usize];
+        }
+        crc
+    }
+
+    /// Deterministic test bytes (xorshift)
+    fn bytes(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc_check_value() {
+        // CRC-32/CKSUM without its final XOR
+        assert_eq!(ogg_crc32(b"123456789"), 0x89A1_897F);
+        assert_eq!(ogg_crc32(&[]), 0);
+    }
+
+    #[test]
+    fn crc_matches_bytewise_for_every_length_and_alignment() {
+        let data = bytes(300, 0x1234_5678);
+        for start in 0..8 {
+            for end in start..data.len() {
+                let slice = &data[start..end];
+                assert_eq!(ogg_crc32(slice), ogg_crc32_bytewise(slice), "bytes {}..{}", start, end);
+            }
+        }
+    }
+
+    #[test]
+    fn crc_matches_bytewise_on_large_pages() {
+        for seed in 1..20 {
+            let data = bytes(65_307, seed);
+            assert_eq!(ogg_crc32(&data), ogg_crc32_bytewise(&data));
+        }
+    }
+
+    #[test]
+    fn pages_carry_a_valid_crc() {
+        let packets: [&[u8]; 3] = [&[0xfc; 120], &[0x01; 400], &[]];
+        let page = OpusEncoder::create_ogg_page_packets(&packets, 0xdead_beef, 2880, 7, 0);
+        let stored = u32::from_le_bytes([page[22], page[23], page[24], page[25]]);
+        let mut zeroed = page.clone();
+        zeroed[22..26].fill(0);
+        assert_eq!(stored, ogg_crc32_bytewise(&zeroed));
+    }
+}
@@ -0,0 +1,84 @@
+//! Bitstream passthrough detection
+//! Dolby/DTS sent to the output device as IEC 61937 bitstream (S/PDIF or HDMI passthrough) is
+//! captured as full-scale noise; it is recognized by its burst preamble and replaced by silence
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::dsp::AudioProcessor;
+
+/// IEC 61937 burst preamble words Pa and Pb, as 16-bit samples in the first two channels
+const SYNC_PA: i16 = 0xF872u16 as i16;
+const SYNC_PB: i16 = 0x4E1F;
+/// Bursts repeat every 512-4096 frames; PCM is assumed again after this long without one
+const HOLD_MS: u64 = 1000;
+
+/// Bitstream seen within the last `HOLD_MS`, for /status and the GUI
+static DETECTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the capture currently carries a bitstream instead of PCM
+pub fn detected() -> bool {
+    DETECTED.load(Ordering::Relaxed)
+}
+
+/// Mutes captured audio while it carries a bitstream. Runs on the samples as captured,
+/// before any remix or gain, since only untouched 16-bit words show the preamble.
+pub struct BitstreamGuard {
+    channels: usize,
+    hold_frames: u64,
+    /// Frames since the last preamble (None = never seen, or PCM again)
+    since_sync: Option<u64>,
+}
+
+impl BitstreamGuard {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        DETECTED.store(false, Ordering::Relaxed);
+        Self {
+            channels: channels.max(1) as usize,
+            hold_frames: sample_rate as u64 * HOLD_MS / 1000,
+            since_sync: None,
+        }
+    }
+}
+
+impl AudioProcessor for BitstreamGuard {
+    fn process(&mut self, samples: &mut [f32]) {
+        // IEC 61937 needs a channel pair
+        if self.channels < 2 {
+            return;
+        }
+        let frames = (samples.len() / self.channels) as u64;
+        let synced = samples
+            .chunks_exact(self.channels)
+            .any(|frame| word(frame[0]) == Some(SYNC_PA) && word(frame[1]) == Some(SYNC_PB));
+
+        let was_detected = self.since_sync.is_some();
+        self.since_sync = match self.since_sync {
+            _ if synced => Some(0),
+            Some(since) if since + frames < self.hold_frames => Some(since + frames),
+            _ => None,
+        };
+        let detected = self.since_sync.is_some();
+        if detected != was_detected {
+            if detected {
+                log::warn!(
+                    "[AUDIO] The output device receives a Dolby/DTS bitstream (passthrough), which can't be captured; \
+                     streaming silence instead. Set the player or game to PCM/stereo output, or turn off exclusive \
+                     mode in the Windows sound device settings"
+                );
+            } else {
+                log::info!("[AUDIO] PCM audio again, bitstream gone");
+            }
+            DETECTED.store(detected, Ordering::Relaxed);
+        }
+        if detected {
+            samples.fill(0.0);
+        }
+    }
+}
+
+/// The 16-bit word a sample was converted from, if it is exactly one
+fn word(sample: f32) -> Option<i16> {
+    let scaled = sample * 32768.0;
+    let rounded = scaled.round();
+    (rounded == scaled && (-32768.0..=32767.0).contains(&rounded)).then_some(rounded as i16)
+}
@@ -0,0 +1,191 @@
+//! Raw capture dump (debugging)
+//! Tees the captured f32 PCM, before any mixing, DSP or encoding, to a float WAV file of limited length
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::sink::utc_timestamp;
+
+/// Length of a dump unless `--dump-secs` says otherwise
+pub const DEFAULT_DUMP_SECS: u32 = 60;
+
+/// Length of the header written by `write_header`
+const HEADER_LEN: u32 = 58;
+
+/// WAV file being written
+struct Dump {
+    file: BufWriter<File>,
+    path: PathBuf,
+    /// Sample frames written so far / allowed
+    frames: u64,
+    max_frames: u64,
+    /// Frames written since the sizes in the header were last updated
+    unpatched: u64,
+}
+
+/// Capture dump shared by the encoder thread (writes) and the front ends (start/stop)
+pub struct PcmDump {
+    sample_rate: u32,
+    channels: u16,
+    active: AtomicBool,
+    dump: Mutex<Option<Dump>>,
+}
+
+impl PcmDump {
+    pub fn new(sample_rate: u32, channels: u16) -> Arc<Self> {
+        Arc::new(Self {
+            sample_rate,
+            channels: channels.max(1),
+            active: AtomicBool::new(false),
+            dump: Mutex::new(None),
+        })
+    }
+
+    /// Start dumping to `path` (default: `capture-<time>.wav` in the data directory) for at most
+    /// `max_secs`. A dump already running is finished first.
+    pub fn start(&self, path: Option<PathBuf>, max_secs: u32) -> Result<PathBuf, String> {
+        let path = match path {
+            Some(path) => path,
+            None => Config::data_dir()
+                .ok_or("No data directory")?
+                .join(format!("capture-{}.wav", utc_timestamp(SystemTime::now()))),
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let mut file = BufWriter::new(File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?);
+        self.write_header(&mut file, 0).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.stop();
+        *self.dump.lock().unwrap() = Some(Dump {
+            file,
+            path: path.clone(),
+            frames: 0,
+            max_frames: max_secs.max(1) as u64 * self.sample_rate as u64,
+            unpatched: 0,
+        });
+        self.active.store(true, Ordering::Relaxed);
+        log::info!(
+            "[DUMP] Writing raw capture ({}Hz, {} ch, up to {}s) to {}",
+            self.sample_rate,
+            self.channels,
+            max_secs.max(1),
+            path.display()
+        );
+        Ok(path)
+    }
+
+    /// Finish the running dump. Returns its file, if there was one.
+    pub fn stop(&self) -> Option<PathBuf> {
+        self.active.store(false, Ordering::Relaxed);
+        let mut dump = self.dump.lock().unwrap().take()?;
+        if let Err(e) = self.write_header(&mut dump.file, dump.frames).and_then(|_| dump.file.flush()) {
+            log::error!("[DUMP] {}: {}", dump.path.display(), e);
+        }
+        log::info!(
+            "[DUMP] Wrote {:.1}s of raw capture to {}",
+            dump.frames as f64 / self.sample_rate as f64,
+            dump.path.display()
+        );
+        Some(dump.path)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Append captured samples (interleaved, capture channel count). Cheap while no dump runs.
+    pub fn push(&self, samples: &[f32]) {
+        if !self.is_active() {
+            return;
+        }
+        let finished = {
+            let mut guard = self.dump.lock().unwrap();
+            let Some(dump) = guard.as_mut() else { return };
+            let channels = self.channels as usize;
+            let frames = ((samples.len() / channels) as u64).min(dump.max_frames - dump.frames);
+            let result = samples[..frames as usize * channels]
+                .iter()
+                .try_for_each(|sample| dump.file.write_all(&sample.to_le_bytes()))
+                .and_then(|_| {
+                    dump.frames += frames;
+                    dump.unpatched += frames;
+                    // Keep the header current about once a second, so a crash still leaves a playable file
+                    if dump.unpatched >= self.sample_rate as u64 {
+                        dump.unpatched = 0;
+                        self.write_header(&mut dump.file, dump.frames)?;
+                    }
+                    Ok(())
+                });
+            if let Err(e) = &result {
+                log::error!("[DUMP] {}: {}", dump.path.display(), e);
+            }
+            result.is_err() || dump.frames >= dump.max_frames
+        };
+        if finished {
+            self.stop();
+        }
+    }
+
+    /// RIFF/WAVE header for IEEE float samples (rewritten in place with the final sizes)
+    fn write_header(&self, file: &mut BufWriter<File>, frames: u64) -> std::io::Result<()> {
+        let block_align = self.channels as u32 * 4;
+        let data_len = (frames * block_align as u64).min((u32::MAX - HEADER_LEN) as u64) as u32;
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_LEN - 8 + data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&18u32.to_le_bytes());
+        header.extend_from_slice(&3u16.to_le_bytes()); // WAVE_FORMAT_IEEE_FLOAT
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align).to_le_bytes());
+        header.extend_from_slice(&(block_align as u16).to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // no extension
+        header.extend_from_slice(b"fact");
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&(frames.min(u32::MAX as u64) as u32).to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_len.to_le_bytes());
+        debug_assert_eq!(header.len(), HEADER_LEN as usize);
+
+        let end = file.stream_position()?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        if end > HEADER_LEN as u64 {
+            file.seek(SeekFrom::Start(end))?;
+        }
+        Ok(())
+    }
+}
+
+/// Start a dump from `--dump-pcm[=path]` and `--dump-secs=N` on the command line
+pub fn start_from_args(dump: &PcmDump) {
+    let mut requested = None;
+    let mut secs = DEFAULT_DUMP_SECS;
+    for arg in std::env::args() {
+        if arg == "--dump-pcm" {
+            requested = Some(None);
+        } else if let Some(path) = arg.strip_prefix("--dump-pcm=") {
+            requested = Some(Some(PathBuf::from(path)));
+        } else if let Some(value) = arg.strip_prefix("--dump-secs=") {
+            match value.parse() {
+                Ok(value) => secs = value,
+                Err(_) => log::warn!("[DUMP] Ignoring --dump-secs={} (expected seconds)", value),
+            }
+        }
+    }
+    if let Some(path) = requested {
+        if let Err(e) = dump.start(path, secs) {
+            log::error!("[DUMP] {}", e);
+        }
+    }
+}
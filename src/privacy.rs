@@ -0,0 +1,169 @@
+//! Privacy pause
+//! Mutes or stops the stream while a listed application (meeting apps, ...) is running
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{PrivacyAction, PrivacyConfig};
+use crate::dsp::FadeControl;
+
+struct State {
+    /// Executable names, lowercase
+    apps: Vec<String>,
+    action: PrivacyAction,
+    /// Listed application found on the last poll
+    active: Option<String>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { apps: Vec::new(), action: PrivacyAction::Mute, active: None });
+
+/// Replace the watched applications (GUI list editor); takes effect on the next poll
+pub fn set_apps(apps: &[String]) {
+    let apps: Vec<String> = apps
+        .iter()
+        .map(|app| app.trim().to_lowercase())
+        .filter(|app| !app.is_empty())
+        .collect();
+    log::info!("[PRIVACY] Watching {}", if apps.is_empty() { "nothing".to_string() } else { apps.join(", ") });
+    STATE.lock().unwrap().apps = apps;
+}
+
+/// Listed application currently running, if any
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn active_app() -> Option<String> {
+    STATE.lock().unwrap().active.clone()
+}
+
+/// Start the watcher thread. Mute silences the stream through `fade`; stop clears
+/// `should_stream` and sets it again afterwards, unless it was already off.
+pub fn start(config: &PrivacyConfig, fade: Arc<FadeControl>, should_stream: Arc<AtomicBool>) {
+    STATE.lock().unwrap().action = config.action;
+    set_apps(&config.apps);
+    let interval = Duration::from_secs(config.poll_secs.max(1) as u64);
+    let action = config.action;
+    let result = thread::Builder::new()
+        .name("privacy".into())
+        .spawn(move || run(interval, action, fade, should_stream));
+    if let Err(e) = result {
+        log::warn!("[PRIVACY] Watcher not started: {}", e);
+    }
+}
+
+fn run(interval: Duration, action: PrivacyAction, fade: Arc<FadeControl>, should_stream: Arc<AtomicBool>) {
+    let mut stopped = false;
+    loop {
+        let apps = STATE.lock().unwrap().apps.clone();
+        let found = if apps.is_empty() {
+            None
+        } else {
+            platform::running()
+                .unwrap_or_default()
+                .into_iter()
+                .find_map(|process| apps.iter().find(|app| matches(app, &process)).cloned())
+        };
+
+        let previous = std::mem::replace(&mut STATE.lock().unwrap().active, found.clone());
+        if found != previous {
+            match &found {
+                Some(app) => {
+                    log::warn!("[PRIVACY] {} is running, {} the stream", app, action.as_str());
+                    match action {
+                        PrivacyAction::Mute => fade.set_privacy_muted(true),
+                        PrivacyAction::Stop => stopped = should_stream.swap(false, Ordering::SeqCst),
+                    }
+                }
+                None => {
+                    log::info!("[PRIVACY] {} closed, resuming", previous.unwrap_or_default());
+                    fade.set_privacy_muted(false);
+                    if std::mem::take(&mut stopped) {
+                        // Only restart what we stopped; a manual start meanwhile is kept as is
+                        should_stream.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+/// Case-insensitive name match; ".exe" is optional on either side, and Linux truncates
+/// process names to 15 characters
+fn matches(app: &str, process: &str) -> bool {
+    let process = process.to_lowercase();
+    let app = app.strip_suffix(".exe").unwrap_or(app);
+    let process = process.strip_suffix(".exe").unwrap_or(&process);
+    app == process || (process.len() == 15 && app.starts_with(process))
+}
+
+/// Privacy pause as shown in /status
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivacyStatus {
+    pub apps: Vec<String>,
+    pub action: PrivacyAction,
+    /// Listed application that is running (stream muted or stopped)
+    pub active_app: Option<String>,
+}
+
+pub fn snapshot() -> PrivacyStatus {
+    let state = STATE.lock().unwrap();
+    PrivacyStatus { apps: state.apps.clone(), action: state.action, active_app: state.active.clone() }
+}
+
+#[cfg(windows)]
+mod platform {
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::tlhelp32::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+
+    /// Executable names of all processes
+    pub fn running() -> Option<Vec<String>> {
+        // SAFETY: plain call; the handle is checked and closed below
+        let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+        if snapshot == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        // SAFETY: PROCESSENTRY32W is plain data, zeroed is a valid value
+        let mut entry: PROCESSENTRY32W = unsafe { std::mem::zeroed() };
+        entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+
+        let mut names = Vec::new();
+        // SAFETY: `snapshot` is valid and `entry` is a local with dwSize set
+        let mut ok = unsafe { Process32FirstW(snapshot, &mut entry) };
+        while ok != 0 {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+            names.push(String::from_utf16_lossy(&entry.szExeFile[..len]));
+            // SAFETY: as above
+            ok = unsafe { Process32NextW(snapshot, &mut entry) };
+        }
+        // SAFETY: opened above, closed once
+        unsafe { CloseHandle(snapshot) };
+        Some(names)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    /// Process names from /proc/<pid>/comm
+    pub fn running() -> Option<Vec<String>> {
+        let names = std::fs::read_dir("/proc")
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+            .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+            .map(|comm| comm.trim_end().to_string())
+            .collect();
+        Some(names)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    pub fn running() -> Option<Vec<String>> {
+        None
+    }
+}
@@ -0,0 +1,95 @@
+//! Installable web player (PWA)
+//! Web app manifest, app icons and a service worker that caches the player shell
+
+use crate::branding::PlayerBranding;
+
+/// App icons embedded from resources/
+pub const ICON_256: &[u8] = include_bytes!("../resources/rustcast_envelope_256.png");
+pub const ICON_512: &[u8] = include_bytes!("../resources/rustcast_envelope_512.png");
+
+/// Web app manifest for /manifest.json
+pub fn manifest_json(branding: &PlayerBranding) -> serde_json::Value {
+    let mut icons = vec![
+        serde_json::json!({ "src": "/icon-256.png", "sizes": "256x256", "type": "image/png" }),
+        serde_json::json!({ "src": "/icon-512.png", "sizes": "512x512", "type": "image/png", "purpose": "any" }),
+    ];
+    // A configured logo is the station's own icon, list it first
+    if let Some(src) = &branding.logo_src {
+        let mut logo = serde_json::json!({ "src": src, "sizes": "any" });
+        if let Some(file) = &branding.logo_file {
+            logo["type"] = file.content_type.into();
+        }
+        icons.insert(0, logo);
+    }
+
+    serde_json::json!({
+        "name": branding.station.display_name(),
+        "short_name": branding.station.display_name(),
+        "start_url": "/",
+        "scope": "/",
+        "display": "fullscreen",
+        "orientation": "portrait",
+        "background_color": "#1a1a2e",
+        "theme_color": branding.accent_color,
+        "icons": icons,
+    })
+}
+
+/// Service worker for /sw.js
+///
+/// Caches the player page, icons and the Opus decoder so the installed app opens
+/// without the network; live audio, WebSocket and API requests are never cached.
+pub const SERVICE_WORKER: &str = r#"const CACHE = 'rustcast-player-v1';
+const SHELL = ['/', '/manifest.json', '/icon-256.png', '/icon-512.png'];
+
+self.addEventListener('install', (event) => {
+    event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(SHELL)));
+    self.skipWaiting();
+});
+
+self.addEventListener('activate', (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(keys.filter((k) => k !== CACHE).map((k) => caches.delete(k))))
+            .then(() => self.clients.claim())
+    );
+});
+
+self.addEventListener('fetch', (event) => {
+    const request = event.request;
+    if (request.method !== 'GET') return;
+    const url = new URL(request.url);
+
+    if (url.origin === location.origin) {
+        // Live data always goes straight to the server
+        if (url.pathname.startsWith('/stream') || url.pathname.startsWith('/api/') ||
+            url.pathname === '/ws' || url.pathname === '/status') {
+            return;
+        }
+        // Network first, so config changes (name, colors) show up when online
+        event.respondWith(
+            fetch(request)
+                .then((response) => {
+                    if (response.ok) {
+                        const copy = response.clone();
+                        caches.open(CACHE).then((cache) => cache.put(request, copy));
+                    }
+                    return response;
+                })
+                .catch(() => caches.match(request))
+        );
+        return;
+    }
+
+    // Versioned decoder module from the CDN: cache first
+    if (url.hostname === 'cdn.jsdelivr.net') {
+        event.respondWith(
+            caches.match(request).then((cached) => cached || fetch(request).then((response) => {
+                const copy = response.clone();
+                caches.open(CACHE).then((cache) => cache.put(request, copy));
+                return response;
+            }))
+        );
+    }
+});
+"#;
@@ -0,0 +1,187 @@
+//! Pipeline queues
+//! Hand-offs between pipeline threads, sized by the latency they may add instead of an item count
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::PipelineConfig;
+use crate::drops::{self, Stage};
+
+/// Item limit of the underlying channel; the latency budget cuts in long before
+const HARD_CAPACITY: usize = 256;
+/// Never fewer items than this, so a single late wakeup of the receiver isn't a drop
+const MIN_ITEMS: usize = 2;
+/// This many drops within `TUNE_WINDOW` count as sustained and grow the budget
+const SUSTAINED_DROPS: u32 = 3;
+const TUNE_WINDOW: Duration = Duration::from_secs(10);
+/// A grown budget shrinks back step by step after this long without drops
+const SHRINK_AFTER: Duration = Duration::from_secs(120);
+
+/// Latency a queue may add: where it starts and how far auto-tuning may grow it
+#[derive(Debug, Clone, Copy)]
+pub struct QueueBudget {
+    pub target_ms: u32,
+    pub max_ms: u32,
+}
+
+impl QueueBudget {
+    pub fn from_config(config: &PipelineConfig) -> Self {
+        let target_ms = config.latency_ms.max(1);
+        Self { target_ms, max_ms: config.max_latency_ms.max(target_ms) }
+    }
+
+    /// A budget auto-tuning never changes
+    pub fn fixed(ms: u32) -> Self {
+        Self { target_ms: ms.max(1), max_ms: ms.max(1) }
+    }
+}
+
+struct Tuning {
+    window_start: Instant,
+    window_drops: u32,
+    last_drop: Option<Instant>,
+    last_change: Instant,
+}
+
+struct Sizing<T> {
+    name: &'static str,
+    stage: Stage,
+    /// Playback time of one item
+    duration: Box<dyn Fn(&T) -> Duration + Send + Sync>,
+    budget: QueueBudget,
+    /// Current budget (ms), between `budget.target_ms` and `budget.max_ms`
+    budget_ms: AtomicU32,
+    /// Running average item length (µs)
+    item_us: AtomicU32,
+    tuning: Mutex<Tuning>,
+}
+
+/// Sending end of a pipeline queue. Never blocks: items over the budget are dropped
+/// (and counted at the queue's stage) so latency can't silently pile up.
+pub struct PipelineSender<T> {
+    tx: Sender<T>,
+    sizing: Arc<Sizing<T>>,
+}
+
+impl<T> Clone for PipelineSender<T> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone(), sizing: self.sizing.clone() }
+    }
+}
+
+/// Create a queue; `duration` tells how much audio an item holds
+pub fn channel<T>(
+    name: &'static str,
+    stage: Stage,
+    budget: QueueBudget,
+    duration: impl Fn(&T) -> Duration + Send + Sync + 'static,
+) -> (PipelineSender<T>, Receiver<T>) {
+    let (tx, rx) = crossbeam_channel::bounded(HARD_CAPACITY);
+    let now = Instant::now();
+    let sizing = Arc::new(Sizing {
+        name,
+        stage,
+        duration: Box::new(duration),
+        budget,
+        budget_ms: AtomicU32::new(budget.target_ms),
+        item_us: AtomicU32::new(0),
+        tuning: Mutex::new(Tuning { window_start: now, window_drops: 0, last_drop: None, last_change: now }),
+    });
+    (PipelineSender { tx, sizing }, rx)
+}
+
+impl<T> PipelineSender<T> {
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        let sizing = &self.sizing;
+        let us = (sizing.duration)(&item).as_micros().min(u32::MAX as u128) as u32;
+        let average = match sizing.item_us.load(Ordering::Relaxed) {
+            0 => us,
+            average => average - average / 8 + us / 8,
+        };
+        sizing.item_us.store(average, Ordering::Relaxed);
+
+        if self.tx.len() >= self.limit() {
+            self.dropped();
+            return Err(TrySendError::Full(item));
+        }
+        match self.tx.try_send(item) {
+            Err(TrySendError::Full(item)) => {
+                self.dropped();
+                Err(TrySendError::Full(item))
+            }
+            result => {
+                if sizing.budget_ms.load(Ordering::Relaxed) > sizing.budget.target_ms {
+                    self.maybe_shrink();
+                }
+                result
+            }
+        }
+    }
+
+    /// Items the queue may hold at the current budget
+    pub fn limit(&self) -> usize {
+        let item_us = self.sizing.item_us.load(Ordering::Relaxed).max(1) as u64;
+        let budget_us = self.sizing.budget_ms.load(Ordering::Relaxed) as u64 * 1000;
+        (budget_us.div_ceil(item_us) as usize).clamp(MIN_ITEMS, HARD_CAPACITY)
+    }
+
+    /// Count a drop; sustained drops grow the budget up to its ceiling
+    fn dropped(&self) {
+        let sizing = &self.sizing;
+        drops::record(sizing.stage);
+
+        let mut tuning = sizing.tuning.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(tuning.window_start) > TUNE_WINDOW {
+            tuning.window_start = now;
+            tuning.window_drops = 0;
+        }
+        tuning.window_drops += 1;
+        tuning.last_drop = Some(now);
+
+        let budget = sizing.budget_ms.load(Ordering::Relaxed);
+        if tuning.window_drops < SUSTAINED_DROPS || budget >= sizing.budget.max_ms {
+            return;
+        }
+        let grown = (budget + budget / 2).min(sizing.budget.max_ms);
+        sizing.budget_ms.store(grown, Ordering::Relaxed);
+        log::warn!(
+            "[PIPELINE] {} queue: {} drops in {}s, latency budget raised {}ms -> {}ms ({} items)",
+            sizing.name,
+            tuning.window_drops,
+            TUNE_WINDOW.as_secs(),
+            budget,
+            grown,
+            self.limit()
+        );
+        tuning.window_start = now;
+        tuning.window_drops = 0;
+        tuning.last_change = now;
+    }
+
+    /// Step a grown budget back toward the target once drops have stopped
+    fn maybe_shrink(&self) {
+        let sizing = &self.sizing;
+        let Ok(mut tuning) = sizing.tuning.try_lock() else {
+            return;
+        };
+        let recent_drop = matches!(tuning.last_drop, Some(at) if at.elapsed() < SHRINK_AFTER);
+        if recent_drop || tuning.last_change.elapsed() < SHRINK_AFTER {
+            return;
+        }
+        let budget = sizing.budget_ms.load(Ordering::Relaxed);
+        let shrunk = (budget - budget / 4).max(sizing.budget.target_ms);
+        sizing.budget_ms.store(shrunk, Ordering::Relaxed);
+        tuning.last_change = Instant::now();
+        log::info!(
+            "[PIPELINE] {} queue: no drops for {}s, latency budget lowered {}ms -> {}ms ({} items)",
+            sizing.name,
+            SHRINK_AFTER.as_secs(),
+            budget,
+            shrunk,
+            self.limit()
+        );
+    }
+}
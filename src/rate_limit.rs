@@ -0,0 +1,290 @@
+//! Per-IP rate limiting
+//! Token buckets for the listener APIs and the control API, plus bans after repeated failed logins
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tiny_http::Response;
+
+use crate::config::RateLimitConfig;
+use crate::server::json_response;
+
+/// Idle entries are forgotten once the table grows past this many addresses
+const PRUNE_ABOVE: usize = 1024;
+/// An address counts as "currently limited" in the listing for this long after its last refusal
+const RECENT: Duration = Duration::from_secs(60);
+
+/// Why a request was refused
+#[derive(Debug, Clone, Copy)]
+pub enum Refusal {
+    /// Bucket empty; retry after this long
+    Throttled(Duration),
+    /// Too many failed logins; banned for this much longer
+    Banned(Duration),
+}
+
+impl Refusal {
+    /// Seconds for the `Retry-After` header
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            Refusal::Throttled(wait) | Refusal::Banned(wait) => wait.as_secs_f64().ceil().max(1.0) as u64,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Refusal::Throttled(_) => "Too many requests",
+            Refusal::Banned(_) => "Too many failed logins, try again later",
+        }
+    }
+
+    /// 429 with `Retry-After`
+    pub fn response(&self) -> Response<Cursor<Vec<u8>>> {
+        json_response(serde_json::json!({ "error": self.message() }), 429).with_header(
+            tiny_http::Header::from_bytes(&b"Retry-After"[..], self.retry_after_secs().to_string().as_bytes()).unwrap(),
+        )
+    }
+}
+
+struct Entry {
+    tokens: f64,
+    refilled: Instant,
+    failures: u32,
+    banned_until: Option<Instant>,
+    refused: u64,
+    last_refused: Option<Instant>,
+}
+
+impl Entry {
+    fn new(burst: u32) -> Self {
+        Self {
+            tokens: burst.max(1) as f64,
+            refilled: Instant::now(),
+            failures: 0,
+            banned_until: None,
+            refused: 0,
+            last_refused: None,
+        }
+    }
+}
+
+/// Buckets and bans by client address, shared by the public server and the control API
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    entries: Mutex<HashMap<IpAddr, Entry>>,
+    refused: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Arc<Self> {
+        Arc::new(Self { config: config.clone(), entries: Mutex::new(HashMap::new()), refused: AtomicU64::new(0) })
+    }
+
+    fn exempt(&self, ip: IpAddr) -> bool {
+        !self.config.enabled || (self.config.exempt_loopback && ip.is_loopback())
+    }
+
+    /// Take a token for a request from `ip`
+    pub fn check(&self, ip: Option<IpAddr>) -> Result<(), Refusal> {
+        let Some(ip) = ip.filter(|ip| !self.exempt(*ip)) else {
+            return Ok(());
+        };
+        let rate = self.config.requests_per_sec.max(0.01);
+        let burst = self.config.burst.max(1) as f64;
+        let now = Instant::now();
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > PRUNE_ABOVE {
+            prune(&mut entries, now, burst / rate);
+        }
+        let entry = entries.entry(ip).or_insert_with(|| Entry::new(self.config.burst));
+
+        let refusal = match entry.banned_until {
+            Some(until) if until > now => Some(Refusal::Banned(until - now)),
+            _ => {
+                entry.tokens = (entry.tokens + now.duration_since(entry.refilled).as_secs_f64() * rate).min(burst);
+                entry.refilled = now;
+                if entry.tokens >= 1.0 {
+                    entry.tokens -= 1.0;
+                    None
+                } else {
+                    Some(Refusal::Throttled(Duration::from_secs_f64((1.0 - entry.tokens) / rate)))
+                }
+            }
+        };
+        match refusal {
+            Some(refusal) => {
+                entry.refused += 1;
+                // One log line per burst of refusals
+                if !matches!(entry.last_refused, Some(at) if now.duration_since(at) <= RECENT) {
+                    log::warn!("[RATE] {} refused: {}", ip, refusal.message());
+                }
+                entry.last_refused = Some(now);
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(refusal)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count a failed login; bans the address after `ban_after_failures` in a row
+    pub fn auth_failed(&self, ip: Option<IpAddr>) {
+        let Some(ip) = ip.filter(|ip| !self.exempt(*ip)) else {
+            return;
+        };
+        let limit = self.config.ban_after_failures;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(ip).or_insert_with(|| Entry::new(self.config.burst));
+        entry.failures += 1;
+        if limit > 0 && entry.failures >= limit {
+            entry.failures = 0;
+            entry.banned_until = Some(Instant::now() + Duration::from_secs(self.config.ban_secs as u64));
+            log::warn!("[RATE] {} banned for {}s after {} failed logins", ip, self.config.ban_secs, limit);
+        }
+    }
+
+    /// A successful login clears the failure count
+    pub fn auth_succeeded(&self, ip: Option<IpAddr>) {
+        let Some(ip) = ip else {
+            return;
+        };
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&ip) {
+            entry.failures = 0;
+        }
+    }
+
+    /// Lift a ban and refill the bucket. Returns false if the address wasn't known.
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.entries.lock().unwrap().remove(&ip).is_some()
+    }
+
+    /// Addresses that are banned or were refused within the last minute
+    pub fn limited(&self) -> Vec<LimitedIp> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let mut limited: Vec<LimitedIp> = entries
+            .iter()
+            .filter_map(|(ip, entry)| {
+                let banned_secs = entry.banned_until.filter(|until| *until > now).map(|until| (until - now).as_secs());
+                let recent = entry.last_refused.is_some_and(|at| now.duration_since(at) <= RECENT);
+                (banned_secs.is_some() || recent).then(|| LimitedIp {
+                    ip: *ip,
+                    banned_secs,
+                    failures: entry.failures,
+                    refused: entry.refused,
+                    last_refused_secs: entry.last_refused.map(|at| now.duration_since(at).as_secs()),
+                })
+            })
+            .collect();
+        limited.sort_by_key(|entry| std::cmp::Reverse(entry.refused));
+        limited
+    }
+
+    /// Requests refused since startup
+    pub fn refused(&self) -> u64 {
+        self.refused.load(Ordering::Relaxed)
+    }
+
+    /// Prometheus text exposition
+    pub fn metrics(&self) -> String {
+        format!(
+            "# HELP rustcast_rate_limited_total Requests refused by rate limiting or bans\n\
+             # TYPE rustcast_rate_limited_total counter\n\
+             rustcast_rate_limited_total {}\n",
+            self.refused()
+        )
+    }
+}
+
+/// Drop entries whose bucket is full again and that carry no ban or failures
+fn prune(entries: &mut HashMap<IpAddr, Entry>, now: Instant, refill_secs: f64) {
+    entries.retain(|_, entry| {
+        let idle = now.duration_since(entry.refilled).as_secs_f64() > refill_secs.max(RECENT.as_secs_f64());
+        !idle || entry.failures > 0 || entry.banned_until.is_some_and(|until| until > now)
+    });
+}
+
+/// One limited address as shown by `GET /api/rate-limit`
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitedIp {
+    pub ip: IpAddr,
+    /// Remaining ban (None = only throttled)
+    pub banned_secs: Option<u64>,
+    /// Failed logins since the last ban or success
+    pub failures: u32,
+    /// Requests refused since first seen
+    pub refused: u64,
+    pub last_refused_secs: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Option<IpAddr> = Some(IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1)));
+
+    fn limiter(requests_per_sec: f64, burst: u32, ban_after_failures: u32) -> Arc<RateLimiter> {
+        RateLimiter::new(&RateLimitConfig { requests_per_sec, burst, ban_after_failures, ..RateLimitConfig::default() })
+    }
+
+    #[test]
+    fn bucket_refills() {
+        let limiter = limiter(100.0, 2, 5);
+        assert!(limiter.check(IP).is_ok());
+        assert!(limiter.check(IP).is_ok());
+        assert!(matches!(limiter.check(IP), Err(Refusal::Throttled(_))));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(IP).is_ok());
+        assert_eq!(limiter.refused(), 1);
+    }
+
+    #[test]
+    fn ban_after_failures() {
+        let limiter = limiter(100.0, 20, 3);
+        limiter.auth_failed(IP);
+        limiter.auth_failed(IP);
+        assert!(limiter.check(IP).is_ok());
+        limiter.auth_failed(IP);
+        assert!(matches!(limiter.check(IP), Err(Refusal::Banned(_))));
+        assert!(limiter.unban(IP.unwrap()));
+        assert!(limiter.check(IP).is_ok());
+    }
+
+    #[test]
+    fn success_clears_failures() {
+        let limiter = limiter(100.0, 20, 3);
+        limiter.auth_failed(IP);
+        limiter.auth_failed(IP);
+        limiter.auth_succeeded(IP);
+        limiter.auth_failed(IP);
+        limiter.auth_failed(IP);
+        assert!(limiter.check(IP).is_ok());
+    }
+
+    #[test]
+    fn throttling_is_no_failed_login() {
+        let limiter = limiter(0.01, 1, 1);
+        assert!(limiter.check(IP).is_ok());
+        for _ in 0..10 {
+            assert!(matches!(limiter.check(IP), Err(Refusal::Throttled(_))));
+        }
+        let limited = limiter.limited();
+        assert_eq!(limited.len(), 1);
+        assert_eq!(limited[0].failures, 0);
+        assert_eq!(limited[0].banned_secs, None);
+    }
+
+    #[test]
+    fn loopback_is_exempt() {
+        let limiter = limiter(0.01, 1, 1);
+        let local = Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+        limiter.auth_failed(local);
+        for _ in 0..5 {
+            assert!(limiter.check(local).is_ok());
+        }
+    }
+}
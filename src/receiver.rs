@@ -0,0 +1,379 @@
+//! Receiver mode
+//! Plays another RustCast server on this machine's default output (`--receive [URL]`). Without
+//! an address the LAN is searched (`mdns.rs`) and the servers found are offered in a list: on
+//! the console here, in a window on Windows (`receiver_gui.rs`).
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample, Stream, StreamConfig};
+use ogg::reading::{BasePacketReader, PageParser};
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::audio;
+use crate::mdns::{self, Server};
+use crate::opus_encoder::OpusDecoder;
+
+/// How long the LAN is asked for servers
+pub const BROWSE_TIME: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// The server sends silence at least every `http.keepalive_secs`; this much nothing is a dead link
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Audio buffered before playback starts, as in the web player
+const TARGET_BUFFER: Duration = Duration::from_millis(60);
+/// Past this, the oldest audio is dropped back to the target (a late receiver catches up)
+const MAX_BUFFER: Duration = Duration::from_millis(250);
+const MAX_HEAD: usize = 8192;
+/// Opus always decodes at 48kHz here
+const OPUS_RATE: u32 = 48000;
+
+/// What a receiver is doing, for the console and the window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiverState {
+    Connecting,
+    Playing,
+    /// Connection failed or ended; tried again after `RETRY_DELAY`
+    Retrying(String),
+}
+
+/// Plays one server until dropped, reconnecting when the connection ends
+pub struct Receiver {
+    stop: Arc<AtomicBool>,
+    state: Arc<Mutex<ReceiverState>>,
+}
+
+impl Receiver {
+    pub fn start(url: &str) -> Result<Self, String> {
+        let (addr, host) = parse_url(url)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(Mutex::new(ReceiverState::Connecting));
+        let (stop_flag, shared_state) = (stop.clone(), state.clone());
+        thread::Builder::new()
+            .name("receiver".into())
+            .spawn(move || {
+                while !stop_flag.load(Ordering::SeqCst) {
+                    *shared_state.lock().unwrap() = ReceiverState::Connecting;
+                    let error = match play(&addr, &host, &stop_flag, &shared_state) {
+                        Ok(()) => "The server ended the stream".to_string(),
+                        Err(e) => e,
+                    };
+                    if stop_flag.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    log::warn!("[RECEIVER] {}; retrying in {}s", error, RETRY_DELAY.as_secs());
+                    *shared_state.lock().unwrap() = ReceiverState::Retrying(error);
+                    thread::sleep(RETRY_DELAY);
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(Self { stop, state })
+    }
+
+    pub fn state(&self) -> ReceiverState {
+        self.state.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Console receiver: play `target`, or the server picked from the ones found on the LAN
+pub fn run(target: Option<String>) -> Result<(), String> {
+    let url = match target {
+        Some(url) => url,
+        None => pick()?,
+    };
+    println!("Playing {} (Ctrl+C to stop)", url);
+    let receiver = Receiver::start(&url)?;
+    let mut shown = None;
+    loop {
+        let state = receiver.state();
+        if shown.as_ref() != Some(&state) {
+            match &state {
+                ReceiverState::Connecting => println!("Connecting..."),
+                ReceiverState::Playing => println!("Playing"),
+                ReceiverState::Retrying(error) => println!("{}; retrying", error),
+            }
+            shown = Some(state);
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Numbered list of the servers on the LAN; Enter takes the first, `r` searches again
+fn pick() -> Result<String, String> {
+    let stdin = std::io::stdin();
+    loop {
+        println!("Searching the LAN for RustCast servers...");
+        let servers = mdns::browse(BROWSE_TIME).map_err(|e| format!("LAN search failed: {}", e))?;
+        if servers.is_empty() {
+            return Err("No RustCast server answered on the LAN; give the address: --receive http://HOST:PORT".to_string());
+        }
+        for (index, server) in servers.iter().enumerate() {
+            println!("  {}) {}", index + 1, describe(server));
+        }
+        print!("Play which one? [1, r = search again]: ");
+        let _ = std::io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            // No console to ask (stdin closed): the first one
+            return Ok(servers[0].url());
+        }
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("r") {
+            continue;
+        }
+        let choice = if line.is_empty() { Some(1) } else { line.parse::<usize>().ok() };
+        match choice.and_then(|choice| servers.get(choice.wrapping_sub(1))) {
+            Some(server) => return Ok(server.url()),
+            None => println!("Enter a number from 1 to {}", servers.len()),
+        }
+    }
+}
+
+/// "Station (http://192.168.0.10:3000/)" for the lists
+pub fn describe(server: &Server) -> String {
+    format!("{} ({})", server.name, server.url())
+}
+
+/// Socket address and Host header of an `http://host:port/...` address (`http://` optional)
+fn parse_url(url: &str) -> Result<(std::net::SocketAddr, String), String> {
+    let url = url.trim();
+    if url.starts_with("https://") {
+        return Err("https:// servers aren't supported; use the server's http:// address".to_string());
+    }
+    let host = url.strip_prefix("http://").unwrap_or(url).split('/').next().unwrap_or_default();
+    if host.is_empty() {
+        return Err(format!("Not a server address: {:?}", url));
+    }
+    let with_port = if host.contains(':') && !host.ends_with(']') { host.to_string() } else { format!("{}:80", host) };
+    let addr = with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("{}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("{}: no address", host))?;
+    Ok((addr, host.to_string()))
+}
+
+/// One connection: /stream.opus in realtime mode, decoded to the default output until it ends
+fn play(addr: &std::net::SocketAddr, host: &str, stop: &AtomicBool, state: &Mutex<ReceiverState>) -> Result<(), String> {
+    let mut socket = TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).map_err(|e| format!("{}: {}", host, e))?;
+    let _ = socket.set_nodelay(true);
+    socket.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+    let request = format!(
+        "GET /stream.opus?mode=realtime HTTP/1.1\r\nHost: {}\r\nUser-Agent: RustCast/{} (receiver)\r\n\r\n",
+        host,
+        env!("CARGO_PKG_VERSION")
+    );
+    socket.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let leftover = read_head(&mut socket)?;
+    let mut stream = std::io::Cursor::new(leftover).chain(socket);
+
+    // OpusHead, then OpusTags, then audio
+    let mut packets = OggPackets::new();
+    let head = packets.next(&mut stream)?;
+    if !head.starts_with(b"OpusHead") || head.len() < 19 {
+        return Err("The server didn't send an Opus stream".to_string());
+    }
+    let channels = head[9].clamp(1, 2) as u16;
+    let mut decoder = OpusDecoder::new(OPUS_RATE, channels)?;
+    packets.next(&mut stream)?;
+
+    let output = Output::open(channels)?;
+    log::info!("[RECEIVER] Playing {} on {}", host, output.describe());
+    *state.lock().unwrap() = ReceiverState::Playing;
+    while !stop.load(Ordering::SeqCst) {
+        let packet = packets.next(&mut stream)?;
+        match decoder.decode_float(&packet) {
+            Ok(pcm) => output.push(&pcm),
+            Err(e) => log::debug!("[RECEIVER] {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Read the response head; returns the bytes read past it
+fn read_head(socket: &mut TcpStream) -> Result<Vec<u8>, String> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_HEAD {
+            return Err("The server sent an oversized response".to_string());
+        }
+        let read = socket.read(&mut chunk).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("The server closed the connection".to_string());
+        }
+        head.extend_from_slice(&chunk[..read]);
+    };
+    let text = String::from_utf8_lossy(&head[..end]);
+    let status_line = text.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(head[end..].to_vec()),
+        Some("404") => Err("No Opus stream there (the server may be streaming MP3, which the receiver can't play)".to_string()),
+        _ => Err(format!("The server answered {:?}", status_line)),
+    }
+}
+
+/// Ogg packets off a live stream (no seeking, unlike `ogg::PacketReader`)
+struct OggPackets {
+    reader: BasePacketReader,
+}
+
+impl OggPackets {
+    fn new() -> Self {
+        Self { reader: BasePacketReader::new() }
+    }
+
+    fn next(&mut self, stream: &mut impl Read) -> Result<Vec<u8>, String> {
+        loop {
+            if let Some(packet) = self.reader.read_packet() {
+                return Ok(packet.data);
+            }
+            let mut header = [0u8; 27];
+            stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+            let (mut parser, segments) = PageParser::new(header).map_err(|e| e.to_string())?;
+            let mut table = vec![0u8; segments];
+            stream.read_exact(&mut table).map_err(|e| e.to_string())?;
+            let mut data = vec![0u8; parser.parse_segments(table)];
+            stream.read_exact(&mut data).map_err(|e| e.to_string())?;
+            let page = parser.parse_packet_data(data).map_err(|e| e.to_string())?;
+            self.reader.push_page(page).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+/// The default output device, fed from a small buffer at its own rate and channel count
+struct Output {
+    _stream: Stream,
+    name: String,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    converter: Mutex<Converter>,
+    /// Buffered samples (all channels) at the target and the limit
+    target: usize,
+    limit: usize,
+}
+
+impl Output {
+    fn open(channels: u16) -> Result<Self, String> {
+        let device = cpal::default_host().default_output_device().ok_or("No output device available")?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+        let stream_config: StreamConfig = config.clone().into();
+        let per_second = stream_config.sample_rate.0 as usize * stream_config.channels as usize;
+        let target = per_second * TARGET_BUFFER.as_millis() as usize / 1000;
+        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(target * 4)));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => output_stream::<f32>(&device, &stream_config, buffer.clone(), target),
+            cpal::SampleFormat::I16 => output_stream::<i16>(&device, &stream_config, buffer.clone(), target),
+            cpal::SampleFormat::U16 => output_stream::<u16>(&device, &stream_config, buffer.clone(), target),
+            format => return Err(format!("Unsupported output sample format {:?}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(Self {
+            _stream: stream,
+            name: device.name().unwrap_or_default(),
+            buffer,
+            converter: Mutex::new(Converter::new(channels, stream_config.channels, stream_config.sample_rate.0)),
+            target,
+            limit: per_second * MAX_BUFFER.as_millis() as usize / 1000,
+        })
+    }
+
+    fn describe(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Queue decoded 48kHz audio
+    fn push(&self, pcm: &[f32]) {
+        let samples = self.converter.lock().unwrap().convert(pcm);
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+        if buffer.len() > self.limit {
+            let excess = buffer.len() - self.target;
+            buffer.drain(..excess);
+        }
+    }
+}
+
+fn output_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    target: usize,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    // Silent until the target is buffered, again after running dry
+    let mut primed = false;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut buffer = buffer.lock().unwrap();
+                primed = (primed && !buffer.is_empty()) || buffer.len() >= target;
+                for sample in data.iter_mut() {
+                    let value = if primed { buffer.pop_front().unwrap_or(0.0) } else { 0.0 };
+                    *sample = T::from_sample(value);
+                }
+            },
+            |err| log::error!("[RECEIVER] Output stream error: {}", err),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+/// 48kHz decoder output to the device's channels (`audio::remix`) and rate (linear)
+struct Converter {
+    from: u16,
+    to: u16,
+    /// Input frames per output frame
+    step: f64,
+    /// Position of the next output frame; -1 is `last`, the previous call's final frame
+    position: f64,
+    last: Vec<f32>,
+}
+
+impl Converter {
+    fn new(from: u16, to: u16, rate: u32) -> Self {
+        Self { from, to, step: OPUS_RATE as f64 / rate as f64, position: -1.0, last: vec![0.0; to as usize] }
+    }
+
+    fn convert(&mut self, pcm: &[f32]) -> Vec<f32> {
+        let input = audio::remix(pcm, self.from, self.to);
+        let channels = self.to as usize;
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return Vec::new();
+        }
+        let frame = |index: isize| -> &[f32] {
+            if index < 0 {
+                &self.last
+            } else {
+                &input[index as usize * channels..(index as usize + 1) * channels]
+            }
+        };
+        let mut out = Vec::with_capacity(((frames as f64 / self.step) as usize + 1) * channels);
+        while self.position < (frames - 1) as f64 {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let (a, b) = (frame(index as isize), frame(index as isize + 1));
+            out.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * fraction));
+            self.position += self.step;
+        }
+        self.position -= frames as f64;
+        self.last = input[(frames - 1) * channels..].to_vec();
+        out
+    }
+}
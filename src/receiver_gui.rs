@@ -0,0 +1,265 @@
+//! Receiver window
+//! Windows face of receiver mode (`--receive`): the RustCast servers found on the LAN in a
+//! dropdown with play/stop, instead of a typed address
+
+#![cfg(windows)]
+
+use native_windows_gui as nwg;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver as Inbox, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::mdns::{self, Server};
+use crate::receiver::{self, Receiver, ReceiverState, BROWSE_TIME};
+
+type Found = Result<Vec<Server>, String>;
+
+struct ReceiverWindow {
+    window: nwg::Window,
+    /// Owned so they live as long as the window
+    #[allow(dead_code)]
+    layout: nwg::GridLayout,
+    #[allow(dead_code)]
+    servers_label: nwg::Label,
+    server_combo: nwg::ComboBox<String>,
+    search_button: nwg::Button,
+    play_button: nwg::Button,
+    status_label: nwg::Label,
+    /// Receiver state shown every 500ms while playing
+    status_timer: nwg::AnimationTimer,
+    /// A LAN search finished; its result is on `found_rx`
+    found_notice: nwg::Notice,
+    found_tx: Sender<Found>,
+    found_rx: Inbox<Found>,
+    /// Address of each dropdown entry, in order
+    urls: RefCell<Vec<String>>,
+    /// Given with `--receive URL`: listed first even if it doesn't answer searches
+    target: Option<String>,
+    receiver: RefCell<Option<Receiver>>,
+}
+
+impl ReceiverWindow {
+    fn build(target: Option<String>) -> Result<Rc<Self>, nwg::NwgError> {
+        nwg::init()?;
+        nwg::Font::set_global_family("Segoe UI")?;
+
+        let mut window = nwg::Window::default();
+        nwg::Window::builder()
+            .size((460, 150))
+            .position((300, 300))
+            .title("RustCast 수신기")
+            .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::VISIBLE | nwg::WindowFlags::MINIMIZE_BOX)
+            .build(&mut window)?;
+
+        let mut servers_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&window)
+            .text("LAN의 RustCast 서버:")
+            .build(&mut servers_label)?;
+
+        let mut server_combo = nwg::ComboBox::default();
+        nwg::ComboBox::builder()
+            .parent(&window)
+            .collection(Vec::new())
+            .build(&mut server_combo)?;
+
+        let mut search_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&window)
+            .text("다시 찾기")
+            .build(&mut search_button)?;
+
+        let mut play_button = nwg::Button::default();
+        nwg::Button::builder()
+            .parent(&window)
+            .text("재생")
+            .build(&mut play_button)?;
+
+        let mut status_label = nwg::Label::default();
+        nwg::Label::builder()
+            .parent(&window)
+            .text("")
+            .build(&mut status_label)?;
+
+        let layout = nwg::GridLayout::default();
+        nwg::GridLayout::builder()
+            .parent(&window)
+            .spacing(6)
+            .child_item(nwg::GridLayoutItem::new(&servers_label, 0, 0, 4, 1))
+            .child_item(nwg::GridLayoutItem::new(&server_combo, 0, 1, 3, 1))
+            .child(3, 1, &search_button)
+            .child(0, 2, &play_button)
+            .child_item(nwg::GridLayoutItem::new(&status_label, 1, 2, 3, 1))
+            .build(&layout)?;
+
+        let mut status_timer = nwg::AnimationTimer::default();
+        nwg::AnimationTimer::builder()
+            .parent(&window)
+            .interval(Duration::from_millis(500))
+            .build(&mut status_timer)?;
+
+        let mut found_notice = nwg::Notice::default();
+        nwg::Notice::builder().parent(&window).build(&mut found_notice)?;
+        let (found_tx, found_rx) = mpsc::channel();
+
+        Ok(Rc::new(Self {
+            window,
+            layout,
+            servers_label,
+            server_combo,
+            search_button,
+            play_button,
+            status_label,
+            status_timer,
+            found_notice,
+            found_tx,
+            found_rx,
+            urls: RefCell::new(Vec::new()),
+            target,
+            receiver: RefCell::new(None),
+        }))
+    }
+
+    /// Ask the LAN for servers on a background thread; the list is refreshed when it answers
+    fn search(&self) {
+        self.search_button.set_enabled(false);
+        self.status_label.set_text("LAN에서 서버를 찾는 중...");
+        let (tx, notice) = (self.found_tx.clone(), self.found_notice.sender());
+        let result = thread::Builder::new().name("receiver-search".into()).spawn(move || {
+            let _ = tx.send(mdns::browse(BROWSE_TIME).map_err(|e| e.to_string()));
+            notice.notice();
+        });
+        if let Err(e) = result {
+            self.search_button.set_enabled(true);
+            self.status_label.set_text(&format!("검색 실패: {}", e));
+        }
+    }
+
+    /// Fill the dropdown from a finished search, keeping the selected server selected
+    fn show_found(&self) {
+        let Ok(found) = self.found_rx.try_recv() else {
+            return;
+        };
+        self.search_button.set_enabled(true);
+        let selected = self.selected_url();
+        let mut urls = Vec::new();
+        let mut names = Vec::new();
+        if let Some(target) = &self.target {
+            urls.push(target.clone());
+            names.push(target.clone());
+        }
+        match found {
+            Ok(servers) => {
+                let count = servers.len();
+                for server in servers {
+                    if !urls.contains(&server.url()) {
+                        names.push(receiver::describe(&server));
+                        urls.push(server.url());
+                    }
+                }
+                if self.receiver.borrow().is_none() {
+                    self.status_label.set_text(&match count {
+                        0 => "찾은 서버가 없습니다. 서버의 mdns 설정을 확인하세요.".to_string(),
+                        count => format!("서버 {}개를 찾았습니다", count),
+                    });
+                }
+            }
+            Err(e) => self.status_label.set_text(&format!("검색 실패: {}", e)),
+        }
+        let index = selected.and_then(|url| urls.iter().position(|u| *u == url));
+        self.server_combo.set_collection(names);
+        self.server_combo.set_selection(index.or(if urls.is_empty() { None } else { Some(0) }));
+        *self.urls.borrow_mut() = urls;
+    }
+
+    fn selected_url(&self) -> Option<String> {
+        let index = self.server_combo.selection()?;
+        self.urls.borrow().get(index).cloned()
+    }
+
+    fn toggle_play(&self) {
+        if self.receiver.borrow_mut().take().is_some() {
+            self.stopped("정지됨");
+        } else {
+            self.play();
+        }
+    }
+
+    /// Play the selected server, replacing the one playing
+    fn play(&self) {
+        self.receiver.borrow_mut().take();
+        let Some(url) = self.selected_url() else {
+            self.stopped("재생할 서버를 고르세요");
+            return;
+        };
+        match Receiver::start(&url) {
+            Ok(receiver) => {
+                *self.receiver.borrow_mut() = Some(receiver);
+                self.play_button.set_text("정지");
+                self.status_timer.start();
+                self.update_status();
+            }
+            Err(e) => self.stopped(&format!("재생 실패: {}", e)),
+        }
+    }
+
+    fn stopped(&self, status: &str) {
+        self.status_timer.stop();
+        self.play_button.set_text("재생");
+        self.status_label.set_text(status);
+    }
+
+    fn update_status(&self) {
+        let Some(state) = self.receiver.borrow().as_ref().map(Receiver::state) else {
+            return;
+        };
+        self.status_label.set_text(&match state {
+            ReceiverState::Connecting => "연결 중...".to_string(),
+            ReceiverState::Playing => "재생 중".to_string(),
+            ReceiverState::Retrying(error) => format!("연결 끊김 ({}), 다시 연결하는 중", error),
+        });
+    }
+}
+
+/// Run the receiver window until it is closed; with a URL that server is played right away
+pub fn run(target: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let ui = ReceiverWindow::build(target)?;
+
+    let events = Rc::downgrade(&ui);
+    let handler = nwg::full_bind_event_handler(&ui.window.handle, move |event, _data, handle| {
+        let Some(ui) = events.upgrade() else {
+            return;
+        };
+        match event {
+            nwg::Event::OnWindowClose if &handle == &ui.window => {
+                ui.receiver.borrow_mut().take();
+                nwg::stop_thread_dispatch();
+            }
+            nwg::Event::OnButtonClick if &handle == &ui.search_button => ui.search(),
+            nwg::Event::OnButtonClick if &handle == &ui.play_button => ui.toggle_play(),
+            // Picking another server while playing switches to it
+            nwg::Event::OnComboxBoxSelection if &handle == &ui.server_combo => {
+                if ui.receiver.borrow().is_some() {
+                    ui.play();
+                }
+            }
+            nwg::Event::OnNotice if &handle == &ui.found_notice => ui.show_found(),
+            nwg::Event::OnTimerTick if &handle == &ui.status_timer => ui.update_status(),
+            _ => {}
+        }
+    });
+
+    if let Some(target) = &ui.target {
+        ui.server_combo.set_collection(vec![target.clone()]);
+        ui.server_combo.set_selection(Some(0));
+        *ui.urls.borrow_mut() = vec![target.clone()];
+        ui.play();
+    }
+    ui.search();
+
+    nwg::dispatch_thread_events();
+    nwg::unbind_event_handler(&handler);
+    Ok(())
+}
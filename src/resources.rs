@@ -0,0 +1,188 @@
+//! Process resource usage
+//! RustCast's own CPU, memory and send rate, sampled once a second for /status and the GUI
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between samples
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// CPU of the last interval (‰ of the whole machine), resident memory, and send rate
+static CPU: AtomicU32 = AtomicU32::new(0);
+static MEMORY: AtomicU64 = AtomicU64::new(0);
+static SEND_RATE: AtomicU64 = AtomicU64::new(0);
+/// Whether the platform reports CPU and memory at all
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+/// Bytes written to listeners since startup (every hub)
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+/// Count bytes written to a listener
+pub fn record_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Start the sampler thread
+pub fn start() {
+    let result = thread::Builder::new()
+        .name("resources".into())
+        .spawn(run);
+    if let Err(e) = result {
+        log::warn!("[RESOURCES] Sampler not started: {}", e);
+    }
+}
+
+fn run() {
+    let cores = thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    let mut last_at = Instant::now();
+    let mut last_cpu = platform::cpu_time();
+    let mut last_sent = BYTES_SENT.load(Ordering::Relaxed);
+    AVAILABLE.store(last_cpu.is_some(), Ordering::Relaxed);
+
+    loop {
+        thread::sleep(INTERVAL);
+        let now = Instant::now();
+        let wall = now.duration_since(last_at).as_secs_f64().max(0.001);
+
+        let cpu = platform::cpu_time();
+        if let (Some(before), Some(after)) = (last_cpu, cpu) {
+            let share = after.saturating_sub(before).as_secs_f64() / (wall * cores);
+            CPU.store((share * 1000.0).round() as u32, Ordering::Relaxed);
+        }
+        if let Some(memory) = platform::resident_bytes() {
+            MEMORY.store(memory, Ordering::Relaxed);
+        }
+        let sent = BYTES_SENT.load(Ordering::Relaxed);
+        SEND_RATE.store((sent.saturating_sub(last_sent) as f64 / wall) as u64, Ordering::Relaxed);
+
+        last_at = now;
+        last_cpu = cpu;
+        last_sent = sent;
+    }
+}
+
+/// Resource usage as shown in /status
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsage {
+    /// CPU time of the last second as a share of all cores (%); None where unsupported
+    pub cpu_percent: Option<f32>,
+    /// Resident memory (bytes)
+    pub memory_bytes: Option<u64>,
+    /// Bytes per second written to listeners
+    pub send_bytes_per_sec: u64,
+    pub bytes_sent: u64,
+}
+
+impl ResourceUsage {
+    /// Prometheus text exposition
+    pub fn metrics(&self) -> String {
+        let mut out = format!(
+            "# HELP rustcast_sent_bytes_total Bytes written to listeners\n\
+             # TYPE rustcast_sent_bytes_total counter\n\
+             rustcast_sent_bytes_total {}\n",
+            self.bytes_sent
+        );
+        if let Some(cpu) = self.cpu_percent {
+            out.push_str(&format!(
+                "# HELP rustcast_cpu_usage Process CPU time as a share of all cores\n\
+                 # TYPE rustcast_cpu_usage gauge\n\
+                 rustcast_cpu_usage {}\n",
+                cpu / 100.0
+            ));
+        }
+        if let Some(memory) = self.memory_bytes {
+            out.push_str(&format!(
+                "# HELP rustcast_resident_memory_bytes Resident memory of the process\n\
+                 # TYPE rustcast_resident_memory_bytes gauge\n\
+                 rustcast_resident_memory_bytes {}\n",
+                memory
+            ));
+        }
+        out
+    }
+}
+
+pub fn snapshot() -> ResourceUsage {
+    let available = AVAILABLE.load(Ordering::Relaxed);
+    ResourceUsage {
+        cpu_percent: available.then(|| CPU.load(Ordering::Relaxed) as f32 / 10.0),
+        memory_bytes: available.then(|| MEMORY.load(Ordering::Relaxed)),
+        send_bytes_per_sec: SEND_RATE.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::time::Duration;
+    use winapi::shared::minwindef::FILETIME;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, GetProcessTimes};
+    use winapi::um::psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+
+    fn filetime_100ns(time: &FILETIME) -> u64 {
+        ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64
+    }
+
+    /// Kernel + user time of the process
+    pub fn cpu_time() -> Option<Duration> {
+        let empty = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+        let (mut creation, mut exit, mut kernel, mut user) = (empty, empty, empty, empty);
+        // SAFETY: the pseudo handle of the current process is always valid; all pointers are locals
+        let ok = unsafe { GetProcessTimes(GetCurrentProcess(), &mut creation, &mut exit, &mut kernel, &mut user) };
+        if ok == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos((filetime_100ns(&kernel) + filetime_100ns(&user)) * 100))
+    }
+
+    /// Working set of the process
+    pub fn resident_bytes() -> Option<u64> {
+        // SAFETY: PROCESS_MEMORY_COUNTERS is plain data, zeroed is a valid value
+        let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        counters.cb = size;
+        // SAFETY: `counters` is a local of the size passed
+        let ok = unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) };
+        (ok != 0).then_some(counters.WorkingSetSize as u64)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::time::Duration;
+
+    /// Kernel clock ticks per second (USER_HZ, 100 on every mainstream architecture)
+    const TICKS_PER_SEC: u64 = 100;
+    const PAGE_SIZE: u64 = 4096;
+
+    /// utime + stime from /proc/self/stat
+    pub fn cpu_time() -> Option<Duration> {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        // The command name may contain spaces; fields after it are space separated
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        Some(Duration::from_millis((utime + stime) * 1000 / TICKS_PER_SEC))
+    }
+
+    /// Resident set size from /proc/self/statm
+    pub fn resident_bytes() -> Option<u64> {
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        Some(pages * PAGE_SIZE)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use std::time::Duration;
+
+    pub fn cpu_time() -> Option<Duration> {
+        None
+    }
+
+    pub fn resident_bytes() -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,114 @@
+//! HTTP response header policy
+//! Adds the configured Server identity, icy-* stream headers, and custom headers to every response
+
+use std::io::Read;
+use std::sync::Arc;
+use tiny_http::{Header, Response};
+
+use crate::config::Config;
+use crate::station::Station;
+
+/// What a response carries, decides which extra headers apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    /// HTML player pages (get `http.page_headers`, e.g. a CSP)
+    Page,
+    /// JSON APIs, WebSocket handshakes, plain errors
+    Api,
+    /// Audio streams (get the icy-* station headers)
+    Stream,
+}
+
+/// Headers added to every response, built once from Config
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHeaders {
+    server: String,
+    /// Read per response, so renames apply to the next listener
+    station: Arc<Station>,
+    custom: Vec<(String, String)>,
+    page: Vec<(String, String)>,
+}
+
+impl ResponseHeaders {
+    pub fn from_config(config: &Config, station: Arc<Station>) -> Self {
+        Self {
+            server: sanitize_value(&config.http.server_name),
+            station,
+            custom: valid_headers(&config.http.headers),
+            page: valid_headers(&config.http.page_headers),
+        }
+    }
+
+    /// Headers for a response of the given kind, in the order they are sent
+    fn for_kind(&self, kind: ContentKind) -> Vec<(&str, String)> {
+        let mut headers = Vec::new();
+        if !self.server.is_empty() {
+            headers.push(("Server", self.server.clone()));
+        }
+        if kind == ContentKind::Stream {
+            let station = self.station.get();
+            for (name, value) in [
+                ("icy-name", station.name),
+                ("icy-description", station.description),
+                ("icy-genre", station.genre),
+                ("icy-url", station.website),
+            ] {
+                if !value.is_empty() {
+                    headers.push((name, value));
+                }
+            }
+        }
+        headers.extend(self.custom.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        if kind == ContentKind::Page {
+            headers.extend(self.page.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        }
+        headers
+    }
+
+    /// Add the configured headers to a tiny_http response
+    pub fn apply<R: Read>(&self, mut response: Response<R>, kind: ContentKind) -> Response<R> {
+        for (name, value) in self.for_kind(kind) {
+            if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+                response.add_header(header);
+            }
+        }
+        response
+    }
+
+    /// Raw HTTP/1.1 response head for responses written straight to the socket
+    ///
+    /// `status` is e.g. "200 OK"; `headers` are the response's own headers.
+    pub fn raw_head(&self, status: &str, kind: ContentKind, headers: &[(&str, &str)]) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {}\r\n", status);
+        let own = headers.iter().map(|(name, value)| (*name, value.to_string()));
+        for (name, value) in own.chain(self.for_kind(kind)) {
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(&value);
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+}
+
+/// Drop headers that would break the response (bad names, CR/LF in values)
+fn valid_headers(headers: &std::collections::BTreeMap<String, String>) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, value)| {
+            let ok = !name.is_empty()
+                && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+                && !value.contains(['\r', '\n']);
+            if !ok {
+                log::warn!("Ignoring invalid custom header: {:?}", name);
+            }
+            ok
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+fn sanitize_value(value: &str) -> String {
+    value.chars().filter(|c| !c.is_control()).collect()
+}
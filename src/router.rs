@@ -0,0 +1,302 @@
+//! Request routing
+//! Method + path routes with typed query/header extractors for the public HTTP server
+
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tiny_http::{Method, Request, Response, StatusCode};
+
+use crate::response::{ContentKind, ResponseHeaders};
+use crate::server::json_response;
+use crate::tunnel;
+
+pub type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+/// What a handler wants done with the request
+pub enum Reply {
+    /// Send this response (the configured headers are added for `ContentKind`)
+    Respond(HttpResponse, ContentKind),
+    /// Take over the connection (audio streams, WebSocket upgrades)
+    Takeover(Box<dyn FnOnce(Request) + Send>),
+}
+
+impl Reply {
+    pub fn api(response: HttpResponse) -> Self {
+        Reply::Respond(response, ContentKind::Api)
+    }
+
+    pub fn json(value: serde_json::Value, status: u16) -> Self {
+        Reply::Respond(json_response(value, status), ContentKind::Api)
+    }
+
+    pub fn not_found(text: &str) -> Self {
+        Reply::Respond(Response::from_string(text).with_status_code(StatusCode(404)), ContentKind::Api)
+    }
+}
+
+/// The request being routed, with typed accessors
+pub struct Ctx<'a> {
+    request: &'a mut Request,
+    path: String,
+    query: String,
+}
+
+impl<'a> Ctx<'a> {
+    pub fn new(request: &'a mut Request) -> Self {
+        let url = request.url().to_string();
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (url, String::new()),
+        };
+        Self { request, path, query }
+    }
+
+    /// Path without the query string
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Query parameter parsed as `T` (None if missing or invalid)
+    pub fn query<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// Request header parsed as `T` (None if missing or invalid)
+    pub fn header<T: FromStr>(&self, name: &'static str) -> Option<T> {
+        self.request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(name))
+            .and_then(|h| h.value.as_str().trim().parse().ok())
+    }
+
+    /// The listener's address (the relay's peer for a tunnel stream)
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        client_addr(self.request)
+    }
+
+    /// The underlying request, e.g. to read the body
+    pub fn request(&mut self) -> &mut Request {
+        self.request
+    }
+}
+
+/// Address of the client behind `request`: tunnel streams resolve to the listener on the relay
+pub fn client_addr(request: &Request) -> Option<SocketAddr> {
+    request.remote_addr().map(|peer| tunnel::client_addr(*peer))
+}
+
+type Handler<S> = fn(&S, &mut Ctx) -> Reply;
+/// Runs before a matched handler; a reply from it is sent instead
+type Guard<S> = fn(&S, &mut Ctx) -> Option<Reply>;
+
+struct Route<S> {
+    method: Method,
+    paths: &'static [&'static str],
+    handler: Handler<S>,
+}
+
+/// Routes of a server with shared state `S`
+pub struct Router<S> {
+    routes: Vec<Route<S>>,
+    guards: Vec<Guard<S>>,
+}
+
+impl<S> Default for Router<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Router<S> {
+    pub fn new() -> Self {
+        Self { routes: Vec::new(), guards: Vec::new() }
+    }
+
+    /// GET (and HEAD) on any of `paths`
+    pub fn get(self, paths: &'static [&'static str], handler: Handler<S>) -> Self {
+        self.route(Method::Get, paths, handler)
+    }
+
+    pub fn post(self, paths: &'static [&'static str], handler: Handler<S>) -> Self {
+        self.route(Method::Post, paths, handler)
+    }
+
+    pub fn route(mut self, method: Method, paths: &'static [&'static str], handler: Handler<S>) -> Self {
+        self.routes.push(Route { method, paths, handler });
+        self
+    }
+
+    /// Check every routed request with `guard` first (rate limits, ...)
+    pub fn guard(mut self, guard: Guard<S>) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    /// Run the matching handler and send its response; unknown paths get 404, known paths
+    /// with another method 405
+    pub fn dispatch(&self, state: &S, mut request: Request, headers: &ResponseHeaders) {
+        match self.reply(state, &mut request) {
+            Reply::Respond(response, kind) => {
+                let _ = request.respond(headers.apply(response, kind));
+            }
+            Reply::Takeover(takeover) => takeover(request),
+        }
+    }
+
+    /// The reply for `request`: guards in the order added, then the handler. Unmatched requests
+    /// don't reach the guards.
+    fn reply(&self, state: &S, request: &mut Request) -> Reply {
+        let mut ctx = Ctx::new(request);
+        let method = ctx.request.method().clone();
+        let method = if method == Method::Head { Method::Get } else { method };
+
+        let mut path_matched = false;
+        let mut handler = None;
+        for route in self.routes.iter().filter(|r| r.paths.contains(&ctx.path())) {
+            path_matched = true;
+            if route.method == method {
+                handler = Some(route.handler);
+                break;
+            }
+        }
+
+        match handler {
+            Some(handler) => match self.guards.iter().find_map(|guard| guard(state, &mut ctx)) {
+                Some(reply) => reply,
+                None => handler(state, &mut ctx),
+            },
+            None if path_matched => Reply::json(serde_json::json!({ "error": "Method not allowed" }), 405),
+            None => Reply::not_found("Not Found"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records which guards and handlers ran, in order
+    #[derive(Default)]
+    struct Calls(RefCell<Vec<&'static str>>);
+
+    impl Calls {
+        fn push(&self, call: &'static str) {
+            self.0.borrow_mut().push(call);
+        }
+
+        fn take(&self) -> Vec<&'static str> {
+            self.0.take()
+        }
+    }
+
+    fn status(reply: Reply) -> u16 {
+        match reply {
+            Reply::Respond(response, _) => response.status_code().0,
+            Reply::Takeover(_) => panic!("takeover instead of a response"),
+        }
+    }
+
+    fn request(method: Method, url: &str) -> Request {
+        tiny_http::TestRequest::new().with_method(method).with_path(url).into()
+    }
+
+    fn get_handler(calls: &Calls, _ctx: &mut Ctx) -> Reply {
+        calls.push("get");
+        Reply::json(serde_json::json!({}), 200)
+    }
+
+    fn post_handler(calls: &Calls, _ctx: &mut Ctx) -> Reply {
+        calls.push("post");
+        Reply::json(serde_json::json!({}), 201)
+    }
+
+    fn query_handler(calls: &Calls, ctx: &mut Ctx) -> Reply {
+        calls.push("query");
+        assert_eq!(ctx.path(), "/query");
+        Reply::json(serde_json::json!({}), ctx.query("status").unwrap_or(500))
+    }
+
+    /// Stands in for the server's `blocked`: refuses everything
+    fn blocked(calls: &Calls, _ctx: &mut Ctx) -> Option<Reply> {
+        calls.push("blocked");
+        Some(Reply::json(serde_json::json!({}), 403))
+    }
+
+    /// Stands in for the server's `rate_limited`: refuses everything
+    fn rate_limited(calls: &Calls, _ctx: &mut Ctx) -> Option<Reply> {
+        calls.push("rate_limited");
+        Some(Reply::json(serde_json::json!({}), 429))
+    }
+
+    fn pass(calls: &Calls, _ctx: &mut Ctx) -> Option<Reply> {
+        calls.push("pass");
+        None
+    }
+
+    fn routes() -> Router<Calls> {
+        Router::new()
+            .get(&["/a", "/a/"], get_handler)
+            .post(&["/a"], post_handler)
+            .get(&["/query"], query_handler)
+    }
+
+    #[test]
+    fn method_and_path_select_the_handler() {
+        let (router, calls) = (routes(), Calls::default());
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/a"))), 200);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/a/"))), 200);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Post, "/a"))), 201);
+        // HEAD is answered by the GET route
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Head, "/a"))), 200);
+        assert_eq!(calls.take(), ["get", "get", "post", "get"]);
+    }
+
+    #[test]
+    fn query_string_is_not_part_of_the_path() {
+        let (router, calls) = (routes(), Calls::default());
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/query?x=1&status=204"))), 204);
+        // A parameter whose name only starts like the one asked for doesn't count
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/query?statuses=204"))), 500);
+        assert_eq!(calls.take(), ["query", "query"]);
+    }
+
+    #[test]
+    fn unknown_path_is_404_and_wrong_method_is_405() {
+        let (router, calls) = (routes(), Calls::default());
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/missing"))), 404);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/A"))), 404);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Delete, "/a"))), 405);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Post, "/query"))), 405);
+        assert!(calls.take().is_empty());
+    }
+
+    #[test]
+    fn guards_run_in_order_and_the_first_reply_wins() {
+        let router = routes().guard(pass).guard(blocked).guard(rate_limited);
+        let calls = Calls::default();
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/a"))), 403);
+        assert_eq!(calls.take(), ["pass", "blocked"]);
+
+        let router = Router::new().guard(pass).guard(rate_limited).get(&["/a"], get_handler);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/a"))), 429);
+        assert_eq!(calls.take(), ["pass", "rate_limited"]);
+    }
+
+    #[test]
+    fn guards_skip_unmatched_requests() {
+        let router = routes().guard(pass).guard(blocked);
+        let calls = Calls::default();
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/missing"))), 404);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Delete, "/a"))), 405);
+        assert!(calls.take().is_empty());
+
+        let router = routes().guard(pass);
+        assert_eq!(status(router.reply(&calls, &mut request(Method::Get, "/a"))), 200);
+        assert_eq!(calls.take(), ["pass", "get"]);
+    }
+}
@@ -2,48 +2,185 @@
 //! Serves Opus/Ogg audio stream to connected clients
 
 use crossbeam_channel::Receiver;
-use std::io::Write;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use tiny_http::{Response, Server, StatusCode};
 
-use crate::opus_encoder::OpusEncoder;
+use crate::branding::PlayerBranding;
+use crate::chat::ChatRoom;
+use crate::codec::CodecSwitch;
+use crate::config::{ChaosConfig, Codec, EndpointsConfig, ListenerConfig, WebTransportConfig};
+use crate::metadata::NowPlayingService;
+use crate::sleep_timer::SleepTimers;
+use crate::test_tone;
+use crate::timeshift::{self, TimeShiftBuffer};
+use crate::interaction::{InteractionError, Interactions};
+use crate::links;
+use crate::delay::BroadcastDelay;
+use crate::drops;
+use crate::glitches;
+use crate::encode_pool;
+use crate::encoder_load;
+use crate::audio;
+use crate::health;
+use crate::privacy;
+use crate::system_volume;
+use crate::resources;
+use crate::validate;
+use crate::dsp;
+use crate::tunnel;
+use crate::webtransport;
+use crate::ws;
+use crate::tier::LowTier;
+use crate::app_channel::{self, AppChannel};
+use crate::loudness::LoudnessLog;
+use crate::mixer::MicMeter;
+use crate::station::Station;
+use crate::sockopt::{SocketKind, SocketOptions};
+use crate::hub::{self, BroadcastHub, ClientClass, FramePriority, Packet, SinkAdapter, SinkKind, Tier};
+use crate::ogg_mux::{OggPager, SharedOggMux, MAX_OGG_FRAMES_PER_PAGE};
+use crate::opus_encoder::{self, FrameClassifier, OpusDecoder, OpusEncoder};
+use crate::pwa;
+use crate::rate_limit::RateLimiter;
+use crate::blocklist::{self, Blocklist};
+use crate::chaos;
+use crate::calibration::{self, Calibration, ChirpError};
+use crate::compare::{self, Compare};
+use crate::response::{ContentKind, ResponseHeaders};
+use crate::router::{self, Ctx, Reply, Router};
+
+/// Ports tried after a taken one
+const PORT_PROBE_RANGE: u16 = 100;
+
+/// Whether the stream port can be bound right now
+pub fn port_available(port: u16) -> bool {
+    std::net::TcpListener::bind(("0.0.0.0", port)).is_ok()
+}
+
+/// First port after `port` that can be bound
+pub fn next_free_port(port: u16) -> Option<u16> {
+    (port.saturating_add(1)..=port.saturating_add(PORT_PROBE_RANGE)).find(|&p| port_available(p))
+}
 
 /// Opus stream info for each client to create proper Ogg stream
-#[derive(Clone)]
-struct OpusStreamInfo {
-    channels: u16,
-    sample_rate: u32,
-    frame_size: usize,
+#[derive(Debug, Clone)]
+pub struct OpusStreamInfo {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub frame_size: usize,
 }
 
 /// HTTP streaming server
 pub struct StreamServer {
     port: u16,
     is_running: Arc<AtomicBool>,
-    client_count: Arc<AtomicUsize>,
     opus_info: Option<OpusStreamInfo>,
+    hub: Arc<BroadcastHub>,
+    chat: Option<Arc<ChatRoom>>,
+    interactions: Option<Arc<Interactions>>,
+    now_playing: Option<Arc<NowPlayingService>>,
+    sleep_timers: Option<Arc<SleepTimers>>,
+    /// Serve /status on the public port (off when a separate control port is used)
+    status_enabled: bool,
+    /// Endpoints turned off in the config
+    endpoints: EndpointsConfig,
+    /// Ogg Vorbis pages and the header pages new listeners start with
+    vorbis: Option<(Arc<BroadcastHub>, Packet)>,
+    /// Two extra encodes for the /compare A/B page
+    compare: Option<Arc<Compare>>,
+    /// Chirp and per-device offsets for the /calibrate latency page
+    calibration: Option<Arc<Calibration>>,
+    /// Server identity and custom headers added to every response
+    headers: ResponseHeaders,
+    /// Station name, accent color and logo of the player pages
+    branding: PlayerBranding,
+    /// Recent packets for pause/rewind in the player
+    timeshift: Option<Arc<TimeShiftBuffer>>,
+    /// Loudness values shown in /status
+    loudness: Option<Arc<LoudnessLog>>,
+    /// Fixed delay before packets reach listeners
+    delay: Option<Arc<BroadcastDelay>>,
+    /// Low-bitrate tier for slow listeners
+    low_tier: Option<Arc<LowTier>>,
+    /// Application sent as a second channel to `opus-mux` WebSocket players
+    app_channel: Option<Arc<AppChannel>>,
+    /// Active main codec and the hub MP3 frames are published to
+    codec: Option<(Arc<CodecSwitch>, Arc<BroadcastHub>)>,
+    /// Silent MP3 frame for the keepalive (None: MP3 listeners get none)
+    mp3_silence: Option<Vec<u8>>,
+    /// Base URL listeners reach us at (`public_url`), used for the player's WebSocket URL
+    public_url: Option<String>,
+    /// Mic voice activity shown in /status
+    mic: Option<Arc<MicMeter>>,
+    /// Station name, description, genre and website
+    station: Arc<Station>,
+    /// Ports served next to `port`
+    listeners: Vec<ListenerConfig>,
+    /// TCP_NODELAY / SO_SNDBUF for the listener sockets
+    sockets: Arc<SocketOptions>,
+    /// Opus frames per Ogg page on /stream.opus
+    ogg_frames_per_page: usize,
+    /// Page Opus once for all HTTP listeners instead of per listener
+    shared_ogg: bool,
+    /// Silence sent while no audio flows, so idle connections aren't timed out (None = off)
+    keepalive: Option<std::time::Duration>,
+    /// Per-IP limits for /api/* (None = unlimited)
+    rate_limit: Option<Arc<RateLimiter>>,
+    /// Banned addresses and devices, refused with a 403 page (None = nobody)
+    blocklist: Option<Arc<Blocklist>>,
+    /// Simulated bad network for test listeners (None = `debug.chaos` off)
+    chaos: Option<ChaosConfig>,
+    /// Negotiate permessage-deflate for WebSocket text frames
+    ws_compression: bool,
+    /// Opus over QUIC datagrams next to /ws
+    webtransport: WebTransportConfig,
 }
 
 impl StreamServer {
     /// Create a new stream server
     pub fn new(port: u16) -> Self {
-        Self {
-            port,
-            is_running: Arc::new(AtomicBool::new(false)),
-            client_count: Arc::new(AtomicUsize::new(0)),
-            opus_info: None,
-        }
+        Self::with_hub(port, Arc::new(BroadcastHub::new()))
     }
 
-    /// Create a new stream server with shared client count
-    pub fn with_client_count(port: u16, client_count: Arc<AtomicUsize>) -> Self {
+    /// Create a new stream server publishing into a shared hub
+    pub fn with_hub(port: u16, hub: Arc<BroadcastHub>) -> Self {
         Self {
             port,
             is_running: Arc::new(AtomicBool::new(false)),
-            client_count,
             opus_info: None,
+            hub,
+            chat: None,
+            interactions: None,
+            now_playing: None,
+            sleep_timers: None,
+            status_enabled: true,
+            endpoints: EndpointsConfig::default(),
+            vorbis: None,
+            compare: None,
+            calibration: None,
+            headers: ResponseHeaders::default(),
+            branding: PlayerBranding::default(),
+            timeshift: None,
+            loudness: None,
+            delay: None,
+            low_tier: None,
+            app_channel: None,
+            codec: None,
+            mp3_silence: None,
+            public_url: None,
+            mic: None,
+            station: Arc::default(),
+            listeners: Vec::new(),
+            sockets: SocketOptions::new(&Default::default()),
+            ogg_frames_per_page: 1,
+            shared_ogg: false,
+            keepalive: None,
+            rate_limit: None,
+            blocklist: None,
+            chaos: None,
+            ws_compression: false,
+            webtransport: WebTransportConfig::default(),
         }
     }
     
@@ -52,9 +189,168 @@ impl StreamServer {
         self.opus_info = Some(OpusStreamInfo { channels, sample_rate, frame_size });
     }
 
+    /// Enable listener chat (must be called before start)
+    pub fn set_chat(&mut self, chat: Arc<ChatRoom>) {
+        self.chat = Some(chat);
+    }
+
+    /// Enable reactions and song requests (must be called before start)
+    pub fn set_interactions(&mut self, interactions: Arc<Interactions>) {
+        self.interactions = Some(interactions);
+    }
+
+    /// Share now-playing metadata with players (must be called before start)
+    pub fn set_now_playing(&mut self, now_playing: Arc<NowPlayingService>) {
+        self.now_playing = Some(now_playing);
+    }
+
+    /// Let players request a sleep timer (must be called before start)
+    pub fn set_sleep_timers(&mut self, sleep_timers: Arc<SleepTimers>) {
+        self.sleep_timers = Some(sleep_timers);
+    }
+
+    /// Hide /status from the public port (must be called before start)
+    pub fn set_status_enabled(&mut self, enabled: bool) {
+        self.status_enabled = enabled;
+    }
+
+    /// Turn off unused endpoints (must be called before start)
+    pub fn set_endpoints(&mut self, endpoints: EndpointsConfig) {
+        if endpoints.player && !endpoints.ws {
+            log::warn!("[SERVER] The player page is on but /ws is off; the player will not play");
+        }
+        self.endpoints = endpoints;
+    }
+
+    /// Serve the Ogg Vorbis stream (must be called before start)
+    pub fn set_vorbis(&mut self, hub: Arc<BroadcastHub>, headers: Packet) {
+        self.vorbis = Some((hub, headers));
+    }
+
+    /// Serve the /compare A/B page (must be called before start)
+    pub fn set_compare(&mut self, compare: Arc<Compare>) {
+        self.compare = Some(compare);
+    }
+
+    /// Serve the /calibrate latency page and its API (must be called before start)
+    pub fn set_calibration(&mut self, calibration: Arc<Calibration>) {
+        self.calibration = Some(calibration);
+    }
+
+    /// Set the headers added to every response (must be called before start)
+    pub fn set_response_headers(&mut self, headers: ResponseHeaders) {
+        self.headers = headers;
+    }
+
+    /// Let players pause and rewind (must be called before start)
+    pub fn set_timeshift(&mut self, timeshift: Arc<TimeShiftBuffer>) {
+        self.timeshift = Some(timeshift);
+    }
+
+    /// Hold packets back before broadcasting (must be called before start)
+    pub fn set_delay(&mut self, delay: Arc<BroadcastDelay>) {
+        self.delay = Some(delay);
+    }
+
+    /// Send the low tier to matching listeners (must be called before start)
+    pub fn set_low_tier(&mut self, low_tier: Arc<LowTier>) {
+        self.low_tier = Some(low_tier);
+    }
+
+    /// Offer the application channel to `opus-mux` WebSocket players (must be called before start)
+    pub fn set_app_channel(&mut self, app_channel: Arc<AppChannel>) {
+        self.app_channel = Some(app_channel);
+    }
+
+    /// Follow the main codec, serving MP3 from `mp3_hub` while it is active; `silence` is the
+    /// MP3 frame the keepalive sends (`encoder::silent_frame`) (must be called before start)
+    pub fn set_codec(&mut self, codec: Arc<CodecSwitch>, mp3_hub: Arc<BroadcastHub>, silence: Option<Vec<u8>>) {
+        self.codec = Some((codec, mp3_hub));
+        self.mp3_silence = silence;
+    }
+
+    /// Point players at a reverse proxy / DDNS address (must be called before start)
+    pub fn set_public_url(&mut self, base: String) {
+        self.public_url = Some(base);
+    }
+
+    /// Include loudness values in /status (must be called before start)
+    pub fn set_loudness(&mut self, loudness: Arc<LoudnessLog>) {
+        self.loudness = Some(loudness);
+    }
+
+    /// Socket options for every port (must be called before start)
+    pub fn set_socket_options(&mut self, sockets: Arc<SocketOptions>) {
+        self.sockets = sockets;
+    }
+
+    /// Group Opus frames into fewer Ogg pages for HTTP listeners (must be called before start)
+    pub fn set_ogg_frames_per_page(&mut self, frames: usize) {
+        self.ogg_frames_per_page = frames.clamp(1, MAX_OGG_FRAMES_PER_PAGE);
+    }
+
+    /// Build each Ogg page once and send the same bytes to every HTTP listener
+    /// (must be called before start)
+    pub fn set_shared_ogg(&mut self, shared: bool) {
+        self.shared_ogg = shared;
+    }
+
+    /// While no audio flows (stream stopped), publish a burst of silent Opus frames, and MP3
+    /// frames to MP3 listeners, every `interval` so players and proxies keep the connection
+    /// (must be called before start)
+    pub fn set_keepalive(&mut self, interval: Option<std::time::Duration>) {
+        self.keepalive = interval.filter(|interval| !interval.is_zero());
+    }
+
+    /// Limit /api/* requests per client address (must be called before start)
+    pub fn set_rate_limit(&mut self, rate_limit: Arc<RateLimiter>) {
+        self.rate_limit = Some(rate_limit);
+    }
+
+    /// Refuse banned addresses and player devices (must be called before start)
+    pub fn set_blocklist(&mut self, blocklist: Arc<Blocklist>) {
+        self.blocklist = Some(blocklist);
+    }
+
+    /// Delay, jitter and drop packets for designated test listeners (must be called before start)
+    pub fn set_chaos(&mut self, chaos: &ChaosConfig) {
+        self.chaos = chaos.enabled.then(|| chaos.clone());
+    }
+
+    /// Compress WebSocket text frames for clients that offer permessage-deflate
+    /// (must be called before start)
+    pub fn set_ws_compression(&mut self, enabled: bool) {
+        self.ws_compression = enabled;
+    }
+
+    /// Also offer the player a WebTransport endpoint (must be called before start)
+    pub fn set_webtransport(&mut self, webtransport: &WebTransportConfig) {
+        self.webtransport = webtransport.clone();
+    }
+
+    /// Also serve on another port, optionally over TLS (must be called before start)
+    pub fn add_listener(&mut self, listener: ListenerConfig) {
+        self.listeners.push(listener);
+    }
+
+    /// Share the station metadata that GUI and control API edit (must be called before start)
+    pub fn set_station(&mut self, station: Arc<Station>) {
+        self.station = station;
+    }
+
+    /// Include mic voice activity in /status (must be called before start)
+    pub fn set_mic(&mut self, mic: Arc<MicMeter>) {
+        self.mic = Some(mic);
+    }
+
+    /// Set the player page branding (must be called before start)
+    pub fn set_branding(&mut self, branding: PlayerBranding) {
+        self.branding = branding;
+    }
+
     /// Get current client count
     pub fn client_count(&self) -> usize {
-        self.client_count.load(Ordering::SeqCst)
+        self.hub.client_counts().total()
     }
 
     /// Check if server is running
@@ -65,33 +361,105 @@ impl StreamServer {
     /// Start the server
     pub fn start(
         &mut self,
-        audio_rx: Receiver<Vec<u8>>,
+        audio_rx: Receiver<Packet>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         if self.is_running.load(Ordering::SeqCst) {
             return Ok(());
         }
 
         let addr = format!("0.0.0.0:{}", self.port);
-        let server = Server::http(&addr).map_err(|e| format!("Failed to start server: {}", e))?;
+        let listener = self.sockets.bind(&addr, SocketKind::Web).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => {
+                format!("Port {} is already in use by another program; change \"port\" in the settings", self.port)
+            }
+            _ => format!("Failed to start server: {}", e),
+        })?;
+        let server = Server::from_listener(listener, None).map_err(|e| format!("Failed to start server: {}", e))?;
         
         log::info!("Server started on http://{}", addr);
+
+        // Extra ports are optional: one that fails to bind doesn't stop the main one
+        let mut servers = vec![server];
+        for listener in &self.listeners {
+            match bind_listener(listener, &self.sockets) {
+                Ok(server) => servers.push(server),
+                Err(e) => log::error!("[SERVER] {}", e),
+            }
+        }
         
         self.is_running.store(true, Ordering::SeqCst);
         let is_running = self.is_running.clone();
-        let client_count = self.client_count.clone();
-        let port = self.port;
         let opus_info = Arc::new(self.opus_info.clone().unwrap_or(OpusStreamInfo {
             channels: 2,
             sample_rate: 48000,
             frame_size: 960, // 20ms at 48kHz
         }));
 
+        // Shared pages are built as packets are published, for the groupings listeners use by default
+        let shared_ogg = self.shared_ogg.then(|| {
+            let mux = SharedOggMux::new(&opus_info, &[self.ogg_frames_per_page, MAX_OGG_FRAMES_PER_PAGE]);
+            self.hub.add_tap(mux.clone());
+            mux
+        });
+
+        let hub = self.hub.clone();
+        let status_enabled = self.status_enabled;
+        let endpoints = self.endpoints.clone();
+        let delay = self.delay.clone();
+        let keepalive = self.keepalive;
+        let silence = opus_encoder::silent_packet(opus_info.channels);
+        // MP3 frames go to their hub straight from the encoder thread; a tap tells when it went quiet
+        let mp3_keepalive = match (&self.codec, &self.mp3_silence, keepalive) {
+            (Some((_, mp3_hub)), Some(frame), Some(_)) => {
+                let last = Arc::new(LastPublished::new());
+                mp3_hub.add_tap(last.clone());
+                Some((mp3_hub.clone(), frame.clone(), last))
+            }
+            _ => None,
+        };
+        let last_audio = Arc::new(Mutex::new(None));
+        let state = Arc::new(ServerState {
+            port: self.port,
+            opus_info,
+            hub: self.hub.clone(),
+            chat: self.chat.clone(),
+            interactions: self.interactions.clone(),
+            now_playing: self.now_playing.clone(),
+            sleep_timers: self.sleep_timers.clone(),
+            vorbis: self.vorbis.clone(),
+            compare: self.compare.clone(),
+            calibration: self.calibration.clone(),
+            headers: Arc::new(self.headers.clone()),
+            branding: self.branding.clone(),
+            timeshift: self.timeshift.clone(),
+            loudness: self.loudness.clone(),
+            delay: self.delay.clone(),
+            low_tier: self.low_tier.clone(),
+            app_channel: self.app_channel.clone(),
+            codec: self.codec.clone(),
+            public_url: self.public_url.clone(),
+            mic: self.mic.clone(),
+            station: self.station.clone(),
+            ogg_frames_per_page: self.ogg_frames_per_page,
+            shared_ogg,
+            endpoints: self.endpoints.clone(),
+            status_enabled,
+            keepalive,
+            rate_limit: self.rate_limit.clone(),
+            blocklist: self.blocklist.clone(),
+            chaos: self.chaos.clone(),
+            ws_compression: self.ws_compression,
+            last_audio: last_audio.clone(),
+        });
+
+        // A player transport like /ws, so it goes with that endpoint group
+        if self.webtransport.enabled && self.endpoints.ws {
+            let state = state.clone();
+            webtransport::spawn(&self.webtransport, self.port, Arc::new(move |request| webtransport_session(&state, request)));
+        }
+
         thread::spawn(move || {
-            // Use a broadcast mechanism for multiple clients
-            let clients: Arc<std::sync::Mutex<Vec<std::sync::mpsc::Sender<Vec<u8>>>>> =
-                Arc::new(std::sync::Mutex::new(Vec::new()));
-            
-            let clients_clone = clients.clone();
+            let hub_clone = hub.clone();
             let is_running_clone = is_running.clone();
 
             // Audio broadcast thread
@@ -99,174 +467,66 @@ impl StreamServer {
                 let mut total_received = 0u64;
                 let mut total_broadcast = 0u64;
                 let mut last_log = std::time::Instant::now();
+                let mut classifier = FrameClassifier::default();
+                let mut last_sent = std::time::Instant::now();
                 
                 while is_running_clone.load(Ordering::SeqCst) {
-                    if let Ok(data) = audio_rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                    let received = audio_rx.recv_timeout(std::time::Duration::from_millis(100));
+                    // Nothing to send for a while: a page's worth of silence for every grouping
+                    if received.is_err() && keepalive.is_some_and(|interval| last_sent.elapsed() >= interval) {
+                        // Separate packets: shared Ogg pages are looked up by packet
+                        for _ in 0..MAX_OGG_FRAMES_PER_PAGE {
+                            let packet = Packet::with_samples(silence.clone(), opus_encoder::FRAME_SIZE as u32);
+                            hub_clone.publish_with_priority(packet, FramePriority::Droppable);
+                        }
+                        last_sent = std::time::Instant::now();
+                    }
+                    if let (Some(interval), Some((mp3_hub, frame, last))) = (keepalive, &mp3_keepalive) {
+                        if last.elapsed() >= interval {
+                            for _ in 0..MP3_KEEPALIVE_FRAMES {
+                                mp3_hub.publish(frame.clone().into());
+                            }
+                        }
+                    }
+                    if let Ok(data) = received {
+                        last_sent = std::time::Instant::now();
+                        *last_audio.lock().unwrap() = Some(last_sent);
                         total_received += 1;
-                        let mut clients_guard = clients_clone.lock().unwrap();
-                        let client_count = clients_guard.len();
-                        clients_guard.retain(|client| client.send(data.clone()).is_ok());
-                        if client_count > 0 {
-                            total_broadcast += 1;
+                        let packets = match &delay {
+                            Some(delay) => delay.push(data),
+                            None => vec![data],
+                        };
+                        for packet in packets {
+                            let priority = classifier.classify(&packet);
+                            if hub_clone.publish_with_priority(packet, priority) > 0 {
+                                total_broadcast += 1;
+                            }
                         }
                         
                         // 5초마다 통계 출력
                         if last_log.elapsed().as_secs() >= 5 {
-                            log::info!("[SERVER] 통계: 수신됨={}, 브로드캐스트={}, 연결된 클라이언트={}", 
-                                total_received, total_broadcast, client_count);
+                            let subscribers = hub_clone.snapshot();
+                            let dropped: u64 = subscribers.iter().map(|s| s.dropped).sum();
+                            log::info!("[SERVER] 통계: 수신됨={}, 브로드캐스트={}, 연결된 클라이언트={}, 클라이언트 드롭={}", 
+                                total_received, total_broadcast, subscribers.len(), dropped);
                             last_log = std::time::Instant::now();
                         }
                     }
                 }
             });
 
-            // Accept connections
-            for request in server.incoming_requests() {
-                if !is_running.load(Ordering::SeqCst) {
-                    break;
-                }
-
-                let url = request.url().to_string();
-                // Strip query string for matching (e.g., "/stream.opus?123456" -> "/stream.opus")
-                let path = url.split('?').next().unwrap_or(&url);
-                
-                match path {
-                    "/" => {
-                        // Serve main page (low-latency WebSocket player)
-                        let html = Self::get_low_latency_html(port);
-                        let response = Response::from_string(html)
-                            .with_header(
-                                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap()
-                            );
-                        let _ = request.respond(response);
-                    }
-                    "/legacy" => {
-                        // Serve legacy player (for compatibility)
-                        let html = Self::get_index_html(port);
-                        let response = Response::from_string(html)
-                            .with_header(
-                                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap()
-                            );
-                        let _ = request.respond(response);
-                    }
-                    "/ws" | "/ws/" => {
-                        // WebSocket upgrade for ultra-low latency streaming
-                        let (tx, rx) = std::sync::mpsc::channel::<Vec<u8>>();
-                        
-                        {
-                            let mut clients_guard = clients.lock().unwrap();
-                            clients_guard.push(tx);
-                        }
-                        
-                        client_count.fetch_add(1, Ordering::SeqCst);
-                        log::info!("WebSocket client connecting. Total: {}", client_count.load(Ordering::SeqCst));
-                        
-                        let client_count_clone = client_count.clone();
-                        
-                        // Handle WebSocket in separate thread
-                        thread::spawn(move || {
-                            if let Err(e) = handle_websocket(request, rx) {
-                                log::debug!("WebSocket error: {}", e);
-                            }
-                            client_count_clone.fetch_sub(1, Ordering::SeqCst);
-                            log::info!("WebSocket client disconnected. Total: {}", client_count_clone.load(Ordering::SeqCst));
-                        });
-                    }
-                    "/stream" | "/stream.opus" | "/stream.ogg" => {
-                        // Create channel for this client
-                        let (tx, rx) = std::sync::mpsc::channel::<Vec<u8>>();
-                        
-                        {
-                            let mut clients_guard = clients.lock().unwrap();
-                            clients_guard.push(tx);
+            // Accept connections, one thread per port
+            let router = Arc::new(routes(&endpoints, status_enabled));
+            for server in servers {
+                let (router, state, is_running) = (router.clone(), state.clone(), is_running.clone());
+                thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        if !is_running.load(Ordering::SeqCst) {
+                            break;
                         }
-                        
-                        client_count.fetch_add(1, Ordering::SeqCst);
-                        log::info!("Client connected (Opus). Total: {}", client_count.load(Ordering::SeqCst));
-
-                        let client_count_clone = client_count.clone();
-                        let info = opus_info.clone();
-                        
-                        // Stream in a separate thread
-                        thread::spawn(move || {
-                            // Get raw TCP stream from the request
-                            let mut stream = request.into_writer();
-                            
-                            // Manually write HTTP response headers for Ogg/Opus
-                            let http_headers = b"HTTP/1.1 200 OK\r\n\
-                                Content-Type: audio/ogg\r\n\
-                                Cache-Control: no-cache, no-store\r\n\
-                                Connection: keep-alive\r\n\
-                                Access-Control-Allow-Origin: *\r\n\
-                                \r\n";
-                            
-                            if stream.write_all(http_headers).is_err() {
-                                client_count_clone.fetch_sub(1, Ordering::SeqCst);
-                                log::info!("Client disconnected (header write failed). Total: {}", client_count_clone.load(Ordering::SeqCst));
-                                return;
-                            }
-                            
-                            // Generate unique serial for this client's Ogg stream
-                            let serial = generate_serial();
-                            
-                            // Send Ogg/Opus headers (unique per client)
-                            let headers = OpusEncoder::get_headers_with_serial(info.channels, info.sample_rate, serial);
-                            if stream.write_all(&headers).is_err() {
-                                client_count_clone.fetch_sub(1, Ordering::SeqCst);
-                                log::info!("Client disconnected (Opus header write failed). Total: {}", client_count_clone.load(Ordering::SeqCst));
-                                return;
-                            }
-                            
-                            if stream.flush().is_err() {
-                                client_count_clone.fetch_sub(1, Ordering::SeqCst);
-                                log::info!("Client disconnected (header flush failed). Total: {}", client_count_clone.load(Ordering::SeqCst));
-                                return;
-                            }
-                            
-                            // Track granule position and page sequence for this client
-                            let mut granule_position: u64 = 0;
-                            let mut page_sequence: u32 = 2; // 0 and 1 used by headers
-                            let frame_size = info.frame_size as u64;
-                            
-                            // Stream audio data - wrap each raw Opus packet in Ogg
-                            while let Ok(opus_packet) = rx.recv() {
-                                granule_position += frame_size;
-                                
-                                // Use our manual Ogg page creation (proper flags)
-                                let ogg_page = OpusEncoder::wrap_opus_packet(
-                                    &opus_packet, 
-                                    serial, 
-                                    granule_position, 
-                                    page_sequence
-                                );
-                                page_sequence += 1;
-                                
-                                if stream.write_all(&ogg_page).is_err() {
-                                    break;
-                                }
-                                if stream.flush().is_err() {
-                                    break;
-                                }
-                            }
-                            client_count_clone.fetch_sub(1, Ordering::SeqCst);
-                            log::info!("Client disconnected. Total: {}", client_count_clone.load(Ordering::SeqCst));
-                        });
+                        router.dispatch(&state, request, &state.headers);
                     }
-                    "/status" => {
-                        let status = format!(r#"{{"clients": {}, "running": true}}"#, 
-                            client_count.load(Ordering::SeqCst));
-                        let response = Response::from_string(status)
-                            .with_header(
-                                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
-                            );
-                        let _ = request.respond(response);
-                    }
-                    _ => {
-                        let response = Response::from_string("Not Found")
-                            .with_status_code(StatusCode(404));
-                        let _ = request.respond(response);
-                    }
-                }
+                });
             }
         });
 
@@ -280,14 +540,27 @@ impl StreamServer {
     }
 
     /// Get ultra-low latency HTML page with WebSocket + Web Audio API
-    fn get_low_latency_html(port: u16) -> String {
+    fn get_low_latency_html(
+        branding: &PlayerBranding,
+        chat_enabled: bool,
+        interactions_enabled: bool,
+        timeshift_enabled: bool,
+        kiosk: bool,
+    ) -> String {
         format!(r##"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>🎵 RustCast - Ultra Low Latency</title>
+    <title>🎵 {station} - Ultra Low Latency</title>
+    <link rel="manifest" href="/manifest.json">
+    <meta name="theme-color" content="{accent}">
+    <meta name="mobile-web-app-capable" content="yes">
+    <meta name="apple-mobile-web-app-capable" content="yes">
+    <meta name="apple-mobile-web-app-status-bar-style" content="black-translucent">
+    <link rel="apple-touch-icon" href="/icon-256.png">
     <style>
+        :root {{ --accent: {accent}; }}
         * {{ margin: 0; padding: 0; box-sizing: border-box; }}
         body {{
             font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
@@ -308,6 +581,11 @@ impl StreamServer {
             min-width: 380px;
             max-width: 450px;
         }}
+        .logo {{
+            max-width: 120px;
+            max-height: 120px;
+            margin-bottom: 0.5rem;
+        }}
         h1 {{
             font-size: 2.5rem;
             margin-bottom: 0.5rem;
@@ -320,6 +598,15 @@ impl StreamServer {
             color: #888;
             margin-bottom: 1rem;
         }}
+        .station-meta {{
+            color: #888;
+            font-size: 0.85rem;
+            margin-top: -0.5rem;
+            margin-bottom: 1rem;
+        }}
+        .station-meta:empty {{
+            display: none;
+        }}
         .codec-badge {{
             display: inline-block;
             padding: 4px 12px;
@@ -422,7 +709,7 @@ impl StreamServer {
         .buffer-btn {{
             padding: 8px 16px;
             min-width: 50px;
-            background: linear-gradient(45deg, #3498db, #2980b9);
+            background: linear-gradient(45deg, var(--accent), #2980b9);
             border: none;
             border-radius: 8px;
             color: white;
@@ -440,7 +727,7 @@ impl StreamServer {
         .buffer-input {{
             width: 70px;
             padding: 8px 12px;
-            border: 2px solid #3498db;
+            border: 2px solid var(--accent);
             border-radius: 8px;
             background: rgba(0,0,0,0.3);
             color: white;
@@ -462,13 +749,38 @@ impl StreamServer {
             color: #888;
             font-size: 0.9rem;
         }}
+        .sleep-select {{
+            padding: 8px 12px;
+            border: 2px solid var(--accent);
+            border-radius: 8px;
+            background: rgba(0,0,0,0.3);
+            color: white;
+            font-size: 1rem;
+        }}
+        .timeshift-buttons {{
+            display: flex;
+            gap: 8px;
+            justify-content: center;
+        }}
+        .timeshift-position {{
+            margin-top: 0.5rem;
+            font-size: 0.8rem;
+            color: #888;
+        }}
+        .timeshift-position.behind {{ color: #f39c12; }}
+        .sleep-remaining {{
+            margin-top: 0.5rem;
+            font-size: 0.8rem;
+            color: #888;
+            min-height: 1em;
+        }}
         .info {{
             margin-top: 1.5rem;
             font-size: 0.75rem;
             color: #666;
         }}
         .info a {{
-            color: #3498db;
+            color: var(--accent);
             text-decoration: none;
         }}
         .visualizer {{
@@ -489,12 +801,156 @@ impl StreamServer {
             border-radius: 2px;
             transition: height 0.05s ease;
         }}
+        .now-playing {{
+            margin-bottom: 1rem;
+            font-size: 0.9rem;
+            color: #f39c12;
+        }}
+        .chat {{
+            margin-top: 1rem;
+            padding: 1rem;
+            background: rgba(0,0,0,0.2);
+            border-radius: 10px;
+            text-align: left;
+        }}
+        .chat-messages {{
+            height: 160px;
+            overflow-y: auto;
+            font-size: 0.85rem;
+            margin-bottom: 0.5rem;
+        }}
+        .chat-line {{
+            padding: 2px 0;
+            word-wrap: break-word;
+        }}
+        .chat-line.system {{
+            color: #888;
+            font-style: italic;
+        }}
+        .chat-author {{
+            color: #f39c12;
+            font-weight: 600;
+        }}
+        .chat-form {{
+            display: flex;
+            gap: 6px;
+        }}
+        .chat-form input {{
+            padding: 8px;
+            border: 1px solid var(--accent);
+            border-radius: 8px;
+            background: rgba(0,0,0,0.3);
+            color: white;
+            min-width: 0;
+        }}
+        .chat-name {{ width: 90px; }}
+        .chat-text {{ flex: 1; }}
+        .chat-form button {{
+            padding: 8px 14px;
+            background: linear-gradient(45deg, var(--accent), #2980b9);
+            color: white;
+        }}
+        .interact {{
+            margin-top: 1rem;
+            padding: 1rem;
+            background: rgba(0,0,0,0.2);
+            border-radius: 10px;
+        }}
+        .reactions {{
+            display: flex;
+            gap: 6px;
+            justify-content: center;
+            margin-bottom: 0.5rem;
+        }}
+        .reaction-btn {{
+            padding: 6px 10px;
+            background: rgba(255,255,255,0.1);
+            font-size: 1.2rem;
+        }}
+        .request-form {{
+            display: flex;
+            gap: 6px;
+        }}
+        .request-form input {{
+            flex: 1;
+            padding: 8px;
+            border: 1px solid #9b59b6;
+            border-radius: 8px;
+            background: rgba(0,0,0,0.3);
+            color: white;
+            min-width: 0;
+        }}
+        .request-form button {{
+            padding: 8px 14px;
+            background: linear-gradient(45deg, #9b59b6, #8e44ad);
+            color: white;
+        }}
+        .interact-status {{
+            font-size: 0.75rem;
+            color: #888;
+            margin-top: 0.5rem;
+            min-height: 1em;
+        }}
+        .status a {{
+            color: inherit;
+            margin-left: 6px;
+        }}
+        .diag-list {{
+            list-style: none;
+            text-align: left;
+            font-size: 0.85rem;
+            margin-bottom: 0.5rem;
+        }}
+        .diag-list li {{
+            padding: 4px 0;
+        }}
+        .diag-hint {{
+            color: #aaa;
+            font-size: 0.75rem;
+            margin-left: 1.6rem;
+        }}
+        /* player.theme: light */
+        body.light {{
+            background: linear-gradient(135deg, #f5f7fa 0%, #dde3ea 100%);
+            color: #222;
+        }}
+        body.light .container {{
+            background: rgba(255,255,255,0.7);
+            box-shadow: 0 8px 32px rgba(0,0,0,0.1);
+        }}
+        body.light .stat-box, body.light .buffer-control, body.light .visualizer,
+        body.light .chat, body.light .interact {{
+            background: rgba(0,0,0,0.05);
+        }}
+        body.light .buffer-input, body.light .sleep-select,
+        body.light .chat-form input, body.light .request-form input {{
+            background: #fff;
+            color: #222;
+        }}
+        body.light .stat-label, body.light .subtitle, body.light .station-meta, body.light .info {{
+            color: #555;
+        }}
+        /* /kiosk: station, what's playing, visualizer and status only */
+        body.kiosk .container > :not(.logo):not(h1):not(.subtitle):not(.station-meta):not(.now-playing):not(.visualizer):not(.status) {{
+            display: none !important;
+        }}
+        body.kiosk .status a {{
+            display: none;
+        }}
     </style>
 </head>
-<body>
+<body class="{theme_class}{kiosk_class}">
+    <script>
+        if (document.body.classList.contains('theme-auto') && matchMedia('(prefers-color-scheme: light)').matches) {{
+            document.body.classList.add('light');
+        }}
+    </script>
     <div class="container">
-        <h1>🚀 RustCast</h1>
-        <p class="subtitle">Ultra Low Latency Audio</p>
+        {logo}
+        <h1>🚀 <span id="stationName">{station}</span></h1>
+        <p class="subtitle" id="stationSubtitle">{subtitle}</p>
+        <p class="station-meta" id="stationMeta">{station_meta}</p>
+        <div class="now-playing" id="nowPlaying" hidden></div>
         <span class="codec-badge">⚡ WebSocket + Web Audio API</span>
         
         <div class="visualizer" id="visualizer"></div>
@@ -536,15 +992,96 @@ impl StreamServer {
             </div>
         </div>
         
+        <div class="buffer-control" id="balance" hidden>
+            <label>🎚 Mix ↔ <span id="balanceApp"></span></label>
+            <input type="range" id="balanceSlider" min="0" max="100" value="50" style="width: 100%">
+        </div>
+        
+        <div class="buffer-control">
+            <label>😴 Sleep Timer (fades out, then disconnects)</label>
+            <select class="sleep-select" id="sleepTimer">
+                <option value="0">Off</option>
+                <option value="15">15 min</option>
+                <option value="30">30 min</option>
+                <option value="60">60 min</option>
+                <option value="90">90 min</option>
+                <option value="120">120 min</option>
+            </select>
+            <div class="sleep-remaining" id="sleepRemaining"></div>
+        </div>
+        
+        <div class="buffer-control" id="timeshift" hidden>
+            <label>⏯ Time-shift</label>
+            <div class="timeshift-buttons">
+                <button class="buffer-btn" id="tsBack">⏪ 30s</button>
+                <button class="buffer-btn" id="tsPause">⏸ Pause</button>
+                <button class="buffer-btn" id="tsLive">🔴 Live</button>
+            </div>
+            <div class="timeshift-position" id="tsPosition">LIVE</div>
+        </div>
+        
+        <div class="buffer-control" id="diagnostics" hidden>
+            <label>🩺 Diagnostics</label>
+            <ul class="diag-list" id="diagList"></ul>
+            <button class="buffer-btn" id="diagRun">↻ Run again</button>
+        </div>
+        
+        <div class="chat" id="chat">
+            <div class="chat-messages" id="chatMessages">
+                <div class="chat-line system">💬 Press Play to join the chat</div>
+            </div>
+            <form class="chat-form" id="chatForm">
+                <input type="text" class="chat-name" id="chatName" placeholder="Name" maxlength="24">
+                <input type="text" class="chat-text" id="chatText" placeholder="Say something..." maxlength="300">
+                <button type="submit">Send</button>
+            </form>
+        </div>
+        
+        <div class="interact" id="interact">
+            <div class="reactions">
+                <button class="reaction-btn">👍</button>
+                <button class="reaction-btn">❤️</button>
+                <button class="reaction-btn">🔥</button>
+                <button class="reaction-btn">😂</button>
+                <button class="reaction-btn">👏</button>
+                <button class="reaction-btn">🎵</button>
+            </div>
+            <form class="request-form" id="requestForm">
+                <input type="text" id="requestText" placeholder="Request a song..." maxlength="120">
+                <button type="submit">Request</button>
+            </form>
+            <div class="interact-status" id="interactStatus"></div>
+        </div>
+        
         <div class="info">
-            <p>WebSocket: ws://localhost:{}/ws | <a href="/legacy">Legacy Player</a></p>
-            <p>Opus 48kHz Stereo | 20ms frames</p>
+            <p>WebSocket: <span id="wsInfo"></span> | <a href="/legacy">Legacy Player</a> | <a href="?diagnostics" id="diagLink">Diagnostics</a></p>
+            <p id="formatInfo"></p>
         </div>
     </div>
 
     <script type="module">
-        // Import opus-decoder as ES module
-        import {{ OpusDecoder }} from 'https://cdn.jsdelivr.net/npm/opus-decoder@0.7.11/+esm';
+        // opus-decoder is loaded on first Play so a blocked CDN can fall back to HTTP
+        const OPUS_DECODER_URL = 'https://cdn.jsdelivr.net/npm/opus-decoder@0.7.11/+esm';
+        let OpusDecoder = null;
+        // Open the page as /?tier=low to get the low-bitrate tier (if the server has one)
+        const TIER = new URLSearchParams(location.search).get('tier');
+        // /?mode=buffered trades latency for robustness (pre-roll, later drops)
+        const MODE = new URLSearchParams(location.search).get('mode');
+        // /?chaos=1 asks for the simulated bad network (debug.chaos) on this player's stream
+        const CHAOS = new URLSearchParams(location.search).has('chaos');
+        // Connection and decoder settings from /api/player-config (defaults if unreachable)
+        const PLAYER = await fetch('/api/player-config').then((res) => res.json()).catch(() => ({{}}));
+        // Main stream codec; MP3 is played by the browser itself (no Web Audio path)
+        const CODEC = PLAYER.codec || 'opus';
+        // Set from public_url when behind a reverse proxy; otherwise the page's own host
+        const WS_URL = PLAYER.ws_url || `${{location.protocol === 'https:' ? 'wss' : 'ws'}}://${{location.host}}/ws`;
+        const SAMPLE_RATE = PLAYER.sample_rate || 48000;
+        const CHANNELS = PLAYER.channels || 2;
+        // One application sent next to the mix (opus-mux), balanced against it with a slider
+        const APP_CHANNEL = PLAYER.app_channel || null;
+        document.getElementById('wsInfo').textContent = WS_URL;
+        document.getElementById('formatInfo').textContent = `${{CODEC === 'mp3' ? 'MP3' : 'Opus'}} ${{SAMPLE_RATE / 1000}}kHz ` +
+            `${{CHANNELS === 1 ? 'Mono' : 'Stereo'}}` + (PLAYER.frame_ms ? ` | ${{PLAYER.frame_ms}}ms frames` : '');
         
         // UI Elements
         const statusEl = document.getElementById('status');
@@ -557,6 +1094,36 @@ impl StreamServer {
         const bufferMinusBtn = document.getElementById('bufferMinus');
         const bufferPlusBtn = document.getElementById('bufferPlus');
         const visualizer = document.getElementById('visualizer');
+        const chatEl = document.getElementById('chat');
+        const chatMessagesEl = document.getElementById('chatMessages');
+        const chatForm = document.getElementById('chatForm');
+        const chatNameInput = document.getElementById('chatName');
+        const chatTextInput = document.getElementById('chatText');
+        const CHAT_ENABLED = {chat_enabled};
+        let stationName = {station_js};
+        const ARTWORK_URL = {artwork_js};
+        const nowPlayingEl = document.getElementById('nowPlaying');
+        const sleepSelect = document.getElementById('sleepTimer');
+        const sleepRemainingEl = document.getElementById('sleepRemaining');
+        const interactEl = document.getElementById('interact');
+        const requestForm = document.getElementById('requestForm');
+        const requestTextInput = document.getElementById('requestText');
+        const interactStatusEl = document.getElementById('interactStatus');
+        const INTERACTIONS_ENABLED = {interactions_enabled};
+        const TIMESHIFT_ENABLED = {timeshift_enabled};
+        // Served as /kiosk: unattended, see startKiosk()
+        const KIOSK = {kiosk};
+        // Random ID kept in this browser, so the host can ban a device rather than an address
+        let DEVICE_ID = localStorage.getItem('rustcast_device');
+        if (!DEVICE_ID) {{
+            DEVICE_ID = Math.random().toString(36).slice(2, 10) + Date.now().toString(36);
+            localStorage.setItem('rustcast_device', DEVICE_ID);
+        }}
+        const timeshiftEl = document.getElementById('timeshift');
+        const tsPauseBtn = document.getElementById('tsPause');
+        const tsPositionEl = document.getElementById('tsPosition');
+        const balanceEl = document.getElementById('balance');
+        const balanceSlider = document.getElementById('balanceSlider');
         
         // Audio state
         let isPlaying = false;
@@ -568,12 +1135,43 @@ impl StreamServer {
         let packetsPerSecond = 0;
         let lastPacketCount = 0;
         let statsInterval = null;
-        let targetBufferMs = 60;
+        let targetBufferMs = PLAYER.buffer_ms || 60;
         let audioQueue = [];
         let isProcessing = false;
         let startTime = 0;
         let totalSamplesPlayed = 0;
         
+        // Transport fallback chain: WebTransport (datagrams, if the server offers it) ->
+        // WebSocket + Web Audio -> HTTP Ogg via <audio>
+        const WS_ATTEMPTS_BEFORE_FALLBACK = 2;
+        let transport = null;
+        let wsFailures = 0;
+        let httpAudio = null;
+        let webTransport = null;
+        // One failed WebTransport attempt is enough: WebSocket until the page reloads
+        let webTransportFailed = false;
+        
+        // Kiosk: sound allowed yet (autoplay permitted or tapped), HTTP stream playing muted, retry delay
+        const KIOSK_RETRY_MIN_MS = 2000;
+        const KIOSK_RETRY_MAX_MS = 30000;
+        let kioskUnlocked = false;
+        let kioskMuted = false;
+        let kioskRetryMs = KIOSK_RETRY_MIN_MS;
+        
+        // Control channel / sleep timer state
+        let clientId = null;
+        // Session to resume after a dropped connection (kept across stop/start)
+        let sessionId = null;
+        let masterGain = null;
+        // Mix and application channel under masterGain, plus the application's own timeline
+        let mixGain = null;
+        let appGain = null;
+        let appDecoder = null;
+        let appNextPlayTime = 0;
+        let sleepDeadline = null;
+        let sleepFading = false;
+        const SLEEP_FADE_SECONDS = 30;
+        
         // Adaptive sync state
         let currentSource = null;
         let syncCount = 0;
@@ -588,8 +1186,10 @@ impl StreamServer {
             visualizer.appendChild(bar);
         }}
         const bars = visualizer.querySelectorAll('.bar');
+        if (PLAYER.visualizer === false) visualizer.style.display = 'none';
         
-        // Load saved preference
+        // Load saved preference (server default otherwise)
+        targetBufferInput.value = targetBufferMs;
         const savedBuffer = localStorage.getItem('rustcast_target_buffer');
         if (savedBuffer) {{
             targetBufferMs = parseInt(savedBuffer);
@@ -603,7 +1203,7 @@ impl StreamServer {
         }}
         
         targetBufferInput.addEventListener('input', (e) => {{
-            updateTargetBuffer(parseInt(e.target.value) || 60);
+            updateTargetBuffer(parseInt(e.target.value) || PLAYER.buffer_ms || 60);
         }});
         
         bufferMinusBtn.addEventListener('click', () => {{
@@ -616,82 +1216,366 @@ impl StreamServer {
         
         playBtn.addEventListener('click', togglePlay);
         
-        async function togglePlay() {{
-            if (isPlaying) {{
-                stop();
-            }} else {{
-                await start();
+        // Chat
+        if (!CHAT_ENABLED) chatEl.style.display = 'none';
+        chatNameInput.value = localStorage.getItem('rustcast_chat_name') || '';
+        
+        function addChatLine(name, text, system) {{
+            const line = document.createElement('div');
+            line.className = 'chat-line' + (system ? ' system' : '');
+            if (name) {{
+                const author = document.createElement('span');
+                author.className = 'chat-author';
+                author.textContent = name + ' ';
+                line.appendChild(author);
+            }}
+            line.appendChild(document.createTextNode(text));
+            chatMessagesEl.appendChild(line);
+            while (chatMessagesEl.children.length > 100) {{
+                chatMessagesEl.removeChild(chatMessagesEl.firstChild);
             }}
+            chatMessagesEl.scrollTop = chatMessagesEl.scrollHeight;
         }}
         
-        async function start() {{
+        // Text frames carry JSON control messages; binary frames carry audio
+        function handleControlMessage(data) {{
+            let msg;
             try {{
-                statusEl.textContent = '⏳ Initializing...';
-                statusEl.className = 'status buffering';
-                playBtn.disabled = true;
-                
-                // Initialize Audio Context
-                audioContext = new (window.AudioContext || window.webkitAudioContext)({{
-                    sampleRate: 48000,
-                    latencyHint: 'interactive'
-                }});
-                
-                // Resume if suspended (browser autoplay policy)
-                if (audioContext.state === 'suspended') {{
-                    await audioContext.resume();
-                }}
-                
-                // Initialize Opus decoder
-                statusEl.textContent = '⏳ Loading Opus decoder...';
-                opusDecoder = new OpusDecoder({{
-                    channels: 2,
-                    sampleRate: 48000
-                }});
-                await opusDecoder.ready;
-                
-                // Connect WebSocket
-                statusEl.textContent = '⏳ Connecting...';
-                const wsUrl = `ws://${{location.host}}/ws`;
-                ws = new WebSocket(wsUrl);
-                ws.binaryType = 'arraybuffer';
+                msg = JSON.parse(data);
+            }} catch (e) {{
+                return;
+            }}
+            switch (msg.type) {{
+                case 'hello':
+                    clientId = msg.client_id;
+                    sessionId = msg.client_id;
+                    // Re-arm the sleep timer after a reconnect
+                    if (sleepDeadline) sendSleepTimer();
+                    break;
+                case 'sleep':
+                    sleepEnded();
+                    break;
+                case 'chat':
+                    addChatLine(msg.message.name, msg.message.text, false);
+                    break;
+                case 'chat_clear':
+                    chatMessagesEl.innerHTML = '';
+                    addChatLine('', 'Chat was cleared by the host', true);
+                    break;
+                case 'cue':
+                    addChatLine('', '📍 ' + msg.label, true);
+                    break;
+                case 'codec':
+                    // The host switched codecs: reload to pick the matching playback path
+                    if (msg.codec !== CODEC) location.reload();
+                    break;
+                case 'spectrum':
+                    if (isPlaying && timeshiftSeq === null) showSpectrum(msg.bands);
+                    break;
+                case 'station':
+                    showStation(msg.station);
+                    break;
+                case 'now_playing': {{
+                    const np = msg.now_playing;
+                    nowPlayingEl.hidden = !np;
+                    if (np) {{
+                        nowPlayingEl.textContent = '🎶 ' + (np.artist ? np.artist + ' - ' : '') + np.title;
+                    }}
+                    nowPlaying = np;
+                    updateMediaSession();
+                    break;
+                }}
+            }}
+        }}
+        
+        // Station renamed by the host while we're listening
+        function showStation(station) {{
+            stationName = station.name || 'RustCast';
+            document.getElementById('stationName').textContent = stationName;
+            document.title = '🎵 ' + stationName + ' - Ultra Low Latency';
+            document.getElementById('stationSubtitle').textContent = station.description || 'Ultra Low Latency Audio';
+            const meta = document.getElementById('stationMeta');
+            meta.textContent = station.genre || '';
+            if (/^https?:\/\//.test(station.website || '')) {{
+                if (station.genre) meta.append(' · ');
+                const link = document.createElement('a');
+                link.href = station.website;
+                link.target = '_blank';
+                link.rel = 'noopener';
+                link.textContent = station.website;
+                meta.append(link);
+            }}
+            updateMediaSession();
+        }}
+        
+        // Lock screen / headset controls
+        let nowPlaying = null;
+        function updateMediaSession() {{
+            if (!('mediaSession' in navigator)) return;
+            navigator.mediaSession.metadata = new MediaMetadata({{
+                title: nowPlaying ? nowPlaying.title : stationName,
+                artist: nowPlaying ? (nowPlaying.artist || '') : 'Live',
+                album: stationName,
+                artwork: [{{ src: ARTWORK_URL, sizes: '512x512' }}]
+            }});
+            navigator.mediaSession.playbackState = isPlaying ? 'playing' : 'paused';
+        }}
+        if ('mediaSession' in navigator) {{
+            const actions = {{
+                play: () => {{ if (!isPlaying) start(); }},
+                pause: () => {{ if (isPlaying) stop(); }},
+                stop: () => {{ if (isPlaying) stop(); }}
+            }};
+            for (const [action, handler] of Object.entries(actions)) {{
+                try {{
+                    navigator.mediaSession.setActionHandler(action, handler);
+                }} catch (e) {{
+                    // Action not supported by this browser
+                }}
+            }}
+            updateMediaSession();
+        }}
+        
+        // Sleep timer: the server disconnects us, we fade out beforehand
+        function sendSleepTimer() {{
+            if (clientId === null) return;
+            const seconds = sleepDeadline
+                ? Math.max(1, Math.round((sleepDeadline - Date.now()) / 1000))
+                : 0;
+            fetch('/api/sleep', {{
+                method: 'POST',
+                headers: {{ 'Content-Type': 'application/json' }},
+                body: JSON.stringify({{ client_id: clientId, seconds }})
+            }}).catch(() => {{}});
+        }}
+        
+        function restoreVolume() {{
+            sleepFading = false;
+            if (masterGain && audioContext) {{
+                masterGain.gain.cancelScheduledValues(audioContext.currentTime);
+                masterGain.gain.setValueAtTime(1, audioContext.currentTime);
+            }}
+        }}
+        
+        function sleepEnded() {{
+            sleepDeadline = null;
+            sleepSelect.value = '0';
+            sleepRemainingEl.textContent = '';
+            sleepFading = false;
+            stop();
+            statusEl.textContent = '😴 Sleep timer ended';
+        }}
+        
+        sleepSelect.addEventListener('change', () => {{
+            const minutes = parseInt(sleepSelect.value) || 0;
+            sleepDeadline = minutes > 0 ? Date.now() + minutes * 60000 : null;
+            restoreVolume();
+            sendSleepTimer();
+        }});
+        
+        setInterval(() => {{
+            if (!sleepDeadline) {{
+                sleepRemainingEl.textContent = '';
+                return;
+            }}
+            const remaining = sleepDeadline - Date.now();
+            const mins = Math.floor(Math.max(0, remaining) / 60000);
+            const secs = Math.floor((Math.max(0, remaining) % 60000) / 1000);
+            sleepRemainingEl.textContent = `Stopping in ${{mins}}:${{String(secs).padStart(2, '0')}}`;
+            
+            if (remaining <= SLEEP_FADE_SECONDS * 1000 && !sleepFading && masterGain && audioContext) {{
+                sleepFading = true;
+                const now = audioContext.currentTime;
+                masterGain.gain.setValueAtTime(masterGain.gain.value, now);
+                masterGain.gain.linearRampToValueAtTime(0, now + Math.max(0, remaining) / 1000);
+            }}
+            // Fallback in case the server's notice never arrives
+            if (remaining <= 0 && isPlaying) sleepEnded();
+        }}, 1000);
+        
+        // Reactions and song requests
+        if (!INTERACTIONS_ENABLED) interactEl.style.display = 'none';
+        
+        async function postInteraction(path, payload, okText) {{
+            try {{
+                const res = await fetch(path, {{
+                    method: 'POST',
+                    headers: {{ 'Content-Type': 'application/json' }},
+                    body: JSON.stringify(payload)
+                }});
+                const body = await res.json().catch(() => ({{}}));
+                interactStatusEl.textContent = res.ok ? okText : '⚠ ' + (body.error || 'Not sent');
+                return res.ok;
+            }} catch (err) {{
+                interactStatusEl.textContent = '⚠ Not sent';
+                return false;
+            }}
+        }}
+        
+        interactEl.querySelectorAll('.reaction-btn').forEach(btn => {{
+            btn.addEventListener('click', () => {{
+                postInteraction('/api/react', {{ emoji: btn.textContent }}, btn.textContent + ' sent!');
+            }});
+        }});
+        
+        requestForm.addEventListener('submit', async (e) => {{
+            e.preventDefault();
+            const text = requestTextInput.value.trim();
+            if (!text) return;
+            if (await postInteraction('/api/request', {{ text }}, '🎶 Request sent!')) {{
+                requestTextInput.value = '';
+            }}
+        }});
+        
+        chatForm.addEventListener('submit', async (e) => {{
+            e.preventDefault();
+            const text = chatTextInput.value.trim();
+            if (!text) return;
+            const name = chatNameInput.value.trim();
+            localStorage.setItem('rustcast_chat_name', name);
+            try {{
+                const res = await fetch('/api/chat', {{
+                    method: 'POST',
+                    headers: {{ 'Content-Type': 'application/json' }},
+                    body: JSON.stringify({{ name, text }})
+                }});
+                if (res.ok) {{
+                    chatTextInput.value = '';
+                }} else {{
+                    const body = await res.json().catch(() => ({{}}));
+                    addChatLine('', '⚠ ' + (body.error || 'Message not sent'), true);
+                }}
+            }} catch (err) {{
+                addChatLine('', '⚠ Message not sent', true);
+            }}
+        }});
+        
+        async function togglePlay() {{
+            if (isPlaying) {{
+                stop();
+            }} else {{
+                // Mobile browsers only allow play() inside the click handler
+                startKeepAlive();
+                await start();
+            }}
+        }}
+        
+        async function start() {{
+            if (CODEC === 'mp3') {{
+                startHttpFallback('MP3 stream');
+                return;
+            }}
+            if (!('WebSocket' in window) || !(window.AudioContext || window.webkitAudioContext)) {{
+                startHttpFallback('browser lacks WebSocket/Web Audio');
+                return;
+            }}
+            try {{
+                statusEl.textContent = '⏳ Initializing...';
+                statusEl.className = 'status buffering';
+                playBtn.disabled = true;
+                
+                // Initialize Audio Context
+                audioContext = new (window.AudioContext || window.webkitAudioContext)({{
+                    sampleRate: SAMPLE_RATE,
+                    latencyHint: 'interactive'
+                }});
+                
+                // Resume if suspended (browser autoplay policy)
+                if (audioContext.state === 'suspended') {{
+                    if (KIOSK && !kioskUnlocked && !await resumesWithin(audioContext, 1000)) {{
+                        // No sound before a tap: play the HTTP stream muted until then
+                        startHttpFallback('waiting for a tap to unmute');
+                        return;
+                    }}
+                    await audioContext.resume();
+                }}
+                if (KIOSK) kioskUnlocked = true;
+                
+                // All audio goes through one gain node (sleep timer fade)
+                masterGain = audioContext.createGain();
+                masterGain.connect(audioContext.destination);
+                connectChannels();
+                sleepFading = false;
+                
+                // Initialize Opus decoder
+                statusEl.textContent = '⏳ Loading Opus decoder...';
+                if (!OpusDecoder) {{
+                    ({{ OpusDecoder }} = await import(OPUS_DECODER_URL));
+                }}
+                opusDecoder = new OpusDecoder({{
+                    channels: CHANNELS,
+                    sampleRate: SAMPLE_RATE
+                }});
+                await opusDecoder.ready;
+                if (APP_CHANNEL) {{
+                    appDecoder = new OpusDecoder({{
+                        channels: CHANNELS,
+                        sampleRate: SAMPLE_RATE
+                    }});
+                    await appDecoder.ready;
+                }}
+                
+                statusEl.textContent = '⏳ Connecting...';
+                const wsParams = new URLSearchParams();
+                if (sessionId !== null) wsParams.set('resume', sessionId);
+                if (TIER) wsParams.set('tier', TIER);
+                if (MODE) wsParams.set('mode', MODE);
+                if (KIOSK) wsParams.set('kiosk', '1');
+                wsParams.set('device', DEVICE_ID);
+                if (CHAOS) wsParams.set('chaos', '1');
+                // WebTransport carries only the mix, so not with the application channel
+                if (PLAYER.webtransport && 'WebTransport' in window && !APP_CHANNEL && !webTransportFailed) {{
+                    try {{
+                        await startWebTransport(wsParams);
+                        return;
+                    }} catch (e) {{
+                        console.warn('WebTransport unavailable:', e);
+                        webTransportFailed = true;
+                    }}
+                }}
+                
+                // Connect WebSocket
+                const wsUrl = WS_URL + (wsParams.toString() ? `?${{wsParams}}` : '');
+                // opus-mux: every frame starts with its channel (0 = mix, 1 = application)
+                ws = APP_CHANNEL ? new WebSocket(wsUrl, 'opus-mux') : new WebSocket(wsUrl);
+                ws.binaryType = 'arraybuffer';
                 
+                let wsOpened = false;
                 ws.onopen = () => {{
-                    statusEl.textContent = '🟢 Streaming (Ultra Low Latency)';
-                    statusEl.className = 'status';
-                    isPlaying = true;
-                    playBtn.disabled = false;
-                    playBtn.textContent = '⏹ Stop';
-                    playBtn.className = 'stop-btn';
-                    startTime = audioContext.currentTime;
-                    // Start with minimal buffer - first packet plays almost immediately
-                    nextPlayTime = audioContext.currentTime + 0.001;
-                    syncCount = 0;
-                    totalSamplesPlayed = 0;
-                    startStats();
+                    wsOpened = true;
+                    wsFailures = 0;
+                    transportOpened('websocket');
                 }};
                 
                 ws.onmessage = async (event) => {{
-                    packetsReceived++;
-                    const opusData = new Uint8Array(event.data);
-                    
-                    // Decode Opus to PCM
-                    try {{
-                        const decoded = await opusDecoder.decodeFrame(opusData);
-                        if (decoded && decoded.channelData && decoded.channelData.length > 0) {{
-                            scheduleAudio(decoded.channelData, decoded.samplesDecoded);
-                        }}
-                    }} catch (e) {{
-                        console.warn('Decode error:', e);
+                    if (typeof event.data === 'string') {{
+                        handleControlMessage(event.data);
+                        return;
                     }}
+                    const frame = new Uint8Array(event.data);
+                    if (APP_CHANNEL && frame[0] !== 0) {{
+                        playAppChannel(frame.subarray(1));
+                        return;
+                    }}
+                    await playOpusPacket(APP_CHANNEL ? frame.subarray(1) : frame);
                 }};
                 
                 ws.onerror = (e) => {{
                     console.error('WebSocket error:', e);
-                    statusEl.textContent = '❌ Connection error';
-                    statusEl.className = 'status error';
+                    showError('Connection error');
                 }};
                 
                 ws.onclose = () => {{
+                    if (!wsOpened && ws) {{
+                        // Never connected: retry, then fall back to plain HTTP
+                        wsFailures++;
+                        if (wsFailures >= WS_ATTEMPTS_BEFORE_FALLBACK) {{
+                            startHttpFallback('WebSocket unavailable');
+                        }} else {{
+                            setTimeout(start, 1000);
+                        }}
+                        return;
+                    }}
                     if (isPlaying) {{
                         statusEl.textContent = '🔄 Reconnecting...';
                         statusEl.className = 'status buffering';
@@ -703,11 +1587,149 @@ impl StreamServer {
                 
             }} catch (e) {{
                 console.error('Start error:', e);
-                statusEl.textContent = '❌ ' + e.message;
-                statusEl.className = 'status error';
+                startHttpFallback(e.message);
+            }}
+        }}
+        
+        // Connected over WebTransport or WebSocket: start playing what arrives
+        function transportOpened(name) {{
+            kioskRetryMs = KIOSK_RETRY_MIN_MS;
+            transport = name;
+            statusEl.textContent = '🟢 Streaming (Ultra Low Latency)';
+            statusEl.className = 'status';
+            isPlaying = true;
+            playBtn.disabled = false;
+            playBtn.textContent = '⏹ Stop';
+            playBtn.className = 'stop-btn';
+            startTime = audioContext.currentTime;
+            // Server replays recent chat on every (re)connect
+            chatMessagesEl.innerHTML = '';
+            // Start with minimal buffer - first packet plays almost immediately
+            nextPlayTime = audioContext.currentTime + 0.001;
+            syncCount = 0;
+            totalSamplesPlayed = 0;
+            startStats();
+            updateMediaSession();
+            startKeepAlive();
+        }}
+        
+        async function playOpusPacket(opusData) {{
+            packetsReceived++;
+            // Playing from the time-shift buffer: live audio is not used
+            if (timeshiftSeq !== null) return;
+            
+            // Decode Opus to PCM
+            try {{
+                const decoded = await opusDecoder.decodeFrame(opusData);
+                if (decoded && decoded.channelData && decoded.channelData.length > 0) {{
+                    scheduleAudio(decoded.channelData, decoded.samplesDecoded);
+                }}
+            }} catch (e) {{
+                console.warn('Decode error:', e);
+            }}
+        }}
+        
+        // Opus packets as datagrams (a lost one is a gap, not a stall), control messages as one
+        // JSON per line on a stream the server opens; same query parameters as /ws
+        async function startWebTransport(params) {{
+            const advert = PLAYER.webtransport;
+            const options = {{}};
+            if (advert.cert_hash) {{
+                // Self-signed certificate: pinned by hash instead of checked against a CA
+                const hash = new Uint8Array(advert.cert_hash.split(':').map((byte) => parseInt(byte, 16)));
+                options.serverCertificateHashes = [{{ algorithm: 'sha-256', value: hash }}];
+            }}
+            const session = new WebTransport(`https://${{location.hostname}}:${{advert.port}}/wt?${{params}}`, options);
+            await session.ready;
+            webTransport = session;
+            transportOpened('webtransport');
+            readControlStream(session);
+            readDatagrams(session);
+            session.closed.catch(() => {{}}).then(() => {{
+                if (webTransport !== session) return;
+                webTransport = null;
+                if (isPlaying) {{
+                    statusEl.textContent = '🔄 Reconnecting...';
+                    statusEl.className = 'status buffering';
+                    setTimeout(() => {{
+                        if (isPlaying) start();
+                    }}, 1000);
+                }}
+            }});
+        }}
+        
+        async function readDatagrams(session) {{
+            const reader = session.datagrams.readable.getReader();
+            try {{
+                while (true) {{
+                    const {{ value, done }} = await reader.read();
+                    if (done) break;
+                    playOpusPacket(value);
+                }}
+            }} catch (e) {{
+                // Session closed
+            }}
+        }}
+        
+        async function readControlStream(session) {{
+            try {{
+                const {{ value: stream }} = await session.incomingUnidirectionalStreams.getReader().read();
+                if (!stream) return;
+                const reader = stream.pipeThrough(new TextDecoderStream()).getReader();
+                let pending = '';
+                while (true) {{
+                    const {{ value, done }} = await reader.read();
+                    if (done) break;
+                    pending += value;
+                    let newline;
+                    while ((newline = pending.indexOf('\n')) >= 0) {{
+                        handleControlMessage(pending.slice(0, newline));
+                        pending = pending.slice(newline + 1);
+                    }}
+                }}
+            }} catch (e) {{
+                // Session closed
+            }}
+        }}
+        
+        // Last resort: let the browser play the Ogg/Opus stream itself (higher latency)
+        function startHttpFallback(reason) {{
+            console.warn('Falling back to HTTP stream:', reason);
+            stop();
+            playBtn.disabled = true;
+            statusEl.textContent = '⏳ Connecting (HTTP)...';
+            statusEl.className = 'status buffering';
+            
+            const streamUrl = PLAYER.stream_url || (CODEC === 'mp3' ? '/stream.mp3' : '/stream.opus');
+            httpAudio = new Audio(streamUrl + '?' + (TIER && CODEC !== 'mp3' ? `tier=${{TIER}}&` : '') + (MODE ? `mode=${{MODE}}&` : '') +
+                (KIOSK ? 'kiosk=1&' : '') + (CHAOS ? 'chaos=1&' : '') + `device=${{DEVICE_ID}}&` + Date.now());
+            // Browsers allow muted autoplay; the kiosk unmutes on the first tap
+            if (KIOSK && !kioskUnlocked) kioskMuted = true;
+            httpAudio.muted = kioskMuted;
+            httpAudio.onerror = () => {{
+                if (KIOSK && isPlaying) {{
+                    stop();
+                    kioskRetry();
+                }}
+            }};
+            // Switching codecs ends the HTTP stream; come back in the new format
+            httpAudio.onended = () => {{
+                if (isPlaying) location.reload();
+            }};
+            httpAudio.play().then(() => {{
+                transport = 'http';
+                isPlaying = true;
+                kioskRetryMs = KIOSK_RETRY_MIN_MS;
+                statusEl.textContent = kioskMuted ? '🔇 Tap anywhere for sound' : '🟡 Streaming (HTTP fallback: ' + reason + ')';
+                statusEl.className = 'status buffering';
                 playBtn.disabled = false;
+                playBtn.textContent = '⏹ Stop';
+                playBtn.className = 'stop-btn';
+                updateMediaSession();
+            }}).catch((e) => {{
                 stop();
-            }}
+                showError(e.message);
+            }});
         }}
         
         function scheduleAudio(channelData, samples) {{
@@ -715,13 +1737,13 @@ impl StreamServer {
             
             const now = audioContext.currentTime;
             const targetBufferSec = targetBufferMs / 1000;
-            const bufferDuration = samples / 48000;
+            const bufferDuration = samples / SAMPLE_RATE;
             
             // Create buffer
             const buffer = audioContext.createBuffer(
                 channelData.length,
                 samples,
-                48000
+                SAMPLE_RATE
             );
             
             // Copy channel data
@@ -746,7 +1768,8 @@ impl StreamServer {
             
             // Case 2: Buffer is too large - hard sync to target
             // Use a smaller threshold for tighter latency control
-            if (bufferAhead > targetBufferSec) {{
+            // (not when time-shifted: the buffer is filled seconds ahead on purpose)
+            if (timeshiftSeq === null && bufferAhead > targetBufferSec) {{
                 const oldBuffer = bufferAhead * 1000;
                 nextPlayTime = now + targetBufferSec;
                 syncCount++;
@@ -756,7 +1779,7 @@ impl StreamServer {
             // Schedule playback
             const source = audioContext.createBufferSource();
             source.buffer = buffer;
-            source.connect(audioContext.destination);
+            source.connect(mixGain);
             source.start(nextPlayTime);
             
             // Advance nextPlayTime for the next packet
@@ -784,7 +1807,66 @@ impl StreamServer {
             latencyEl.className = 'stat-value' + (estimatedLatency > 100 ? ' warn' : '');
         }}
         
+        // Application channel: decoded on its own, kept on the mix's timeline
+        async function playAppChannel(opusData) {{
+            if (!appDecoder || !audioContext || !isPlaying || timeshiftSeq !== null) return;
+            try {{
+                const decoded = await appDecoder.decodeFrame(opusData);
+                if (!decoded || !decoded.samplesDecoded || !audioContext) return;
+                const now = audioContext.currentTime;
+                if (appNextPlayTime <= now || Math.abs(appNextPlayTime - nextPlayTime) > 0.1) {{
+                    appNextPlayTime = Math.max(now + 0.001, nextPlayTime);
+                }}
+                const buffer = audioContext.createBuffer(decoded.channelData.length, decoded.samplesDecoded, SAMPLE_RATE);
+                decoded.channelData.forEach((data, ch) => buffer.copyToChannel(data, ch));
+                const source = audioContext.createBufferSource();
+                source.buffer = buffer;
+                source.connect(appGain);
+                source.start(appNextPlayTime);
+                appNextPlayTime += decoded.samplesDecoded / SAMPLE_RATE;
+            }} catch (e) {{
+                console.warn('App channel decode error:', e);
+            }}
+        }}
+        
+        // Slider in the middle plays both at full level; either end fades the other one out
+        function applyBalance() {{
+            if (!mixGain) return;
+            const balance = APP_CHANNEL ? balanceSlider.value / 100 : 0.5;
+            mixGain.gain.value = Math.min(1, 2 * (1 - balance));
+            appGain.gain.value = Math.min(1, 2 * balance);
+        }}
+        
+        function connectChannels() {{
+            mixGain = audioContext.createGain();
+            mixGain.connect(masterGain);
+            appGain = audioContext.createGain();
+            appGain.connect(masterGain);
+            applyBalance();
+        }}
+        
+        balanceEl.hidden = !APP_CHANNEL;
+        if (APP_CHANNEL) {{
+            document.getElementById('balanceApp').textContent = APP_CHANNEL;
+            balanceSlider.value = localStorage.getItem('rustcast_balance') || 50;
+        }}
+        balanceSlider.addEventListener('input', () => {{
+            localStorage.setItem('rustcast_balance', balanceSlider.value);
+            applyBalance();
+        }});
+        
+        // Server-side spectrum replaces the local amplitude bars once it arrives
+        let serverSpectrum = false;
+        function showSpectrum(bands) {{
+            serverSpectrum = true;
+            for (let i = 0; i < NUM_BARS; i++) {{
+                const level = bands[Math.floor(i * bands.length / NUM_BARS)] || 0;
+                bars[i].style.height = Math.max(2, level / 255 * 50) + 'px';
+            }}
+        }}
+        
         function updateVisualizer(samples) {{
+            if (serverSpectrum && timeshiftSeq === null) return;
             const step = Math.floor(samples.length / NUM_BARS);
             for (let i = 0; i < NUM_BARS; i++) {{
                 let sum = 0;
@@ -807,21 +1889,47 @@ impl StreamServer {
         
         function stop() {{
             isPlaying = false;
+            transport = null;
             
             if (ws) {{
-                ws.close();
+                const closing = ws;
                 ws = null;
+                closing.close();
+            }}
+            if (webTransport) {{
+                const closing = webTransport;
+                webTransport = null;
+                closing.close();
+            }}
+            
+            if (httpAudio) {{
+                httpAudio.pause();
+                httpAudio.removeAttribute('src');
+                httpAudio.load();
+                httpAudio = null;
             }}
             
             if (opusDecoder) {{
                 opusDecoder.free();
                 opusDecoder = null;
             }}
+            if (appDecoder) {{
+                appDecoder.free();
+                appDecoder = null;
+            }}
             
             if (audioContext) {{
                 audioContext.close();
                 audioContext = null;
+                masterGain = null;
+                mixGain = null;
+                appGain = null;
             }}
+            clientId = null;
+            stopKeepAlive();
+            timeshiftSeq = null;
+            timeshiftPaused = false;
+            updateTimeshiftUi();
             
             if (statsInterval) {{
                 clearInterval(statsInterval);
@@ -842,28 +1950,467 @@ impl StreamServer {
             
             // Reset visualizer
             bars.forEach(bar => bar.style.height = '2px');
+            updateMediaSession();
+        }}
+        
+        // Time-shift: play from the server's buffer instead of the live packets
+        const FRAME_SEC = 0.02;
+        let timeshiftSeq = null;      // next buffer packet to fetch, null = live
+        let timeshiftNewest = 0;
+        let timeshiftPaused = false;
+        let timeshiftPumping = false;
+        timeshiftEl.hidden = !TIMESHIFT_ENABLED;
+        
+        // Packet currently heard (the rest up to timeshiftSeq is scheduled ahead)
+        function playingSeq() {{
+            const ahead = Math.max(0, nextPlayTime - audioContext.currentTime);
+            return timeshiftSeq - Math.round(ahead / FRAME_SEC);
+        }}
+        
+        // Silence everything already scheduled
+        function flushScheduled() {{
+            masterGain.disconnect();
+            masterGain = audioContext.createGain();
+            masterGain.connect(audioContext.destination);
+            connectChannels();
+            nextPlayTime = audioContext.currentTime + 0.05;
+        }}
+        
+        async function enterTimeshift() {{
+            if (timeshiftSeq !== null) return true;
+            try {{
+                const info = await (await fetch('/api/timeshift')).json();
+                // Everything up to the newest packet has been scheduled already
+                timeshiftSeq = info.newest_seq + 1;
+                timeshiftNewest = info.newest_seq;
+                return true;
+            }} catch (e) {{
+                return false;
+            }}
+        }}
+        
+        async function timeshiftTogglePause() {{
+            if (!isPlaying || transport === 'http') return;
+            if (!timeshiftPaused) {{
+                if (!await enterTimeshift()) return;
+                timeshiftPaused = true;
+                await audioContext.suspend();
+            }} else {{
+                timeshiftPaused = false;
+                await audioContext.resume();
+                timeshiftPump();
+            }}
+            updateTimeshiftUi();
+        }}
+        
+        async function timeshiftBack(seconds) {{
+            if (!isPlaying || transport === 'http') return;
+            if (!await enterTimeshift()) return;
+            timeshiftSeq = Math.max(0, playingSeq() - Math.round(seconds / FRAME_SEC));
+            flushScheduled();
+            if (!timeshiftPaused) timeshiftPump();
+            updateTimeshiftUi();
+        }}
+        
+        function goLive(flush) {{
+            if (timeshiftSeq === null) return;
+            timeshiftSeq = null;
+            timeshiftPaused = false;
+            if (audioContext) {{
+                if (audioContext.state === 'suspended') audioContext.resume();
+                if (flush) flushScheduled();
+            }}
+            updateTimeshiftUi();
+        }}
+        
+        async function timeshiftPump() {{
+            if (timeshiftPumping) return;
+            timeshiftPumping = true;
+            try {{
+                while (timeshiftSeq !== null && !timeshiftPaused && isPlaying) {{
+                    if (nextPlayTime - audioContext.currentTime > 3) {{
+                        await new Promise(resolve => setTimeout(resolve, 250));
+                        continue;
+                    }}
+                    const requested = timeshiftSeq;
+                    const res = await fetch(`/timeshift?from=${{requested}}&count=100`);
+                    if (!res.ok) {{
+                        goLive(true);
+                        break;
+                    }}
+                    const from = Number(res.headers.get('X-Timeshift-From'));
+                    timeshiftNewest = Number(res.headers.get('X-Timeshift-Newest'));
+                    const data = new Uint8Array(await res.arrayBuffer());
+                    // Jumped or went live while the request was in flight
+                    if (timeshiftSeq !== requested || !opusDecoder) continue;
+                    
+                    let offset = 0;
+                    let count = 0;
+                    while (offset + 2 <= data.length) {{
+                        const len = (data[offset] << 8) | data[offset + 1];
+                        const packet = data.subarray(offset + 2, offset + 2 + len);
+                        offset += 2 + len;
+                        count++;
+                        const decoded = await opusDecoder.decodeFrame(packet);
+                        if (decoded && decoded.samplesDecoded) {{
+                            scheduleAudio(decoded.channelData, decoded.samplesDecoded);
+                        }}
+                    }}
+                    if (count === 0) {{
+                        // Caught up with the live stream
+                        goLive(false);
+                        break;
+                    }}
+                    timeshiftSeq = from + count;
+                    updateTimeshiftUi();
+                }}
+            }} catch (e) {{
+                console.warn('Time-shift:', e);
+                goLive(true);
+            }} finally {{
+                timeshiftPumping = false;
+            }}
+        }}
+        
+        function updateTimeshiftUi() {{
+            tsPauseBtn.textContent = timeshiftPaused ? '▶ Resume' : '⏸ Pause';
+            if (timeshiftSeq === null || !audioContext) {{
+                tsPositionEl.textContent = 'LIVE';
+                tsPositionEl.className = 'timeshift-position';
+                return;
+            }}
+            const behind = Math.max(0, Math.round((timeshiftNewest - playingSeq()) * FRAME_SEC));
+            tsPositionEl.textContent = (timeshiftPaused ? 'Paused, ' : '') +
+                `-${{Math.floor(behind / 60)}}:${{String(behind % 60).padStart(2, '0')}} behind live`;
+            tsPositionEl.className = 'timeshift-position behind';
+        }}
+        
+        tsPauseBtn.addEventListener('click', timeshiftTogglePause);
+        document.getElementById('tsBack').addEventListener('click', () => timeshiftBack(30));
+        document.getElementById('tsLive').addEventListener('click', () => goLive(true));
+        
+        // Mobile keep-alive: a looping silent <audio> keeps the page a "media" page
+        // (not frozen in the background), and a wake lock keeps the screen from sleeping
+        let keepAliveAudio = null;
+        let wakeLock = null;
+        function silentWavUrl() {{
+            const rate = 8000;
+            const view = new DataView(new ArrayBuffer(44 + rate));
+            const text = (offset, s) => [...s].forEach((c, i) => view.setUint8(offset + i, c.charCodeAt(0)));
+            text(0, 'RIFF'); view.setUint32(4, 36 + rate, true); text(8, 'WAVE');
+            text(12, 'fmt '); view.setUint32(16, 16, true); view.setUint16(20, 1, true);
+            view.setUint16(22, 1, true); view.setUint32(24, rate, true); view.setUint32(28, rate, true);
+            view.setUint16(32, 1, true); view.setUint16(34, 8, true);
+            text(36, 'data'); view.setUint32(40, rate, true);
+            for (let i = 0; i < rate; i++) view.setUint8(44 + i, 128);
+            return URL.createObjectURL(new Blob([view], {{ type: 'audio/wav' }}));
+        }}
+        function startKeepAlive() {{
+            if (!keepAliveAudio) {{
+                keepAliveAudio = new Audio(silentWavUrl());
+                keepAliveAudio.loop = true;
+                keepAliveAudio.setAttribute('playsinline', '');
+            }}
+            keepAliveAudio.play().catch(() => {{}});
+            requestWakeLock();
+        }}
+        function stopKeepAlive() {{
+            if (keepAliveAudio) keepAliveAudio.pause();
+            if (wakeLock) {{
+                wakeLock.release().catch(() => {{}});
+                wakeLock = null;
+            }}
+        }}
+        async function requestWakeLock() {{
+            if (!('wakeLock' in navigator) || wakeLock || document.hidden) return;
+            try {{
+                wakeLock = await navigator.wakeLock.request('screen');
+                wakeLock.addEventListener('release', () => {{ wakeLock = null; }});
+            }} catch (e) {{
+                // Denied (e.g. battery saver) - audio keeps playing anyway
+            }}
         }}
         
-        // Handle page visibility for reconnection
+        // Reconnect when the (installed) app comes back to the foreground
+        function resumeIfDropped() {{
+            if (!isPlaying) return;
+            requestWakeLock();
+            if (transport === 'websocket' || transport === 'webtransport') {{
+                if (audioContext && audioContext.state === 'suspended' && !timeshiftPaused) {{
+                    audioContext.resume();
+                }}
+                // A closed WebTransport session reconnects by itself
+                if (transport === 'websocket' && (!ws || ws.readyState > WebSocket.OPEN)) {{
+                    stop();
+                    start();
+                }}
+            }} else if (transport === 'http' && httpAudio && httpAudio.paused) {{
+                httpAudio.play().catch(() => {{}});
+            }}
+        }}
         document.addEventListener('visibilitychange', () => {{
-            if (document.hidden && isPlaying) {{
-                // Could pause here if needed
+            if (!document.hidden) resumeIfDropped();
+        }});
+        window.addEventListener('focus', resumeIfDropped);
+        
+        // Errors link to the diagnostics mode instead of leaving the listener guessing
+        function showError(message) {{
+            statusEl.textContent = '❌ ' + message;
+            statusEl.className = 'status error';
+            if (KIOSK) kioskRetry();
+            const link = document.createElement('a');
+            link.href = '?diagnostics';
+            link.textContent = 'Troubleshoot';
+            link.onclick = (e) => {{
+                e.preventDefault();
+                runDiagnostics();
+            }};
+            statusEl.appendChild(link);
+        }}
+        
+        // Diagnostics mode (/?diagnostics or the Troubleshoot link): server state, /status,
+        // WebSocket reachability, decoder and autoplay policy, each with a hint when it fails
+        const diagEl = document.getElementById('diagnostics');
+        const diagListEl = document.getElementById('diagList');
+        let diagRunning = false;
+        function diagRow(name) {{
+            const li = document.createElement('li');
+            li.textContent = '⏳ ' + name;
+            diagListEl.appendChild(li);
+            return (result, detail, hint) => {{
+                const icon = {{ ok: '✅', warn: '⚠️', fail: '❌' }}[result];
+                li.textContent = `${{icon}} ${{name}}: ${{detail}}`;
+                if (hint && result !== 'ok') {{
+                    const hintEl = document.createElement('div');
+                    hintEl.className = 'diag-hint';
+                    hintEl.textContent = hint;
+                    li.appendChild(hintEl);
+                }}
+            }};
+        }}
+        function fetchWithTimeout(url, ms) {{
+            const abort = new AbortController();
+            const timer = setTimeout(() => abort.abort(), ms);
+            return fetch(url, {{ cache: 'no-store', signal: abort.signal }}).finally(() => clearTimeout(timer));
+        }}
+        // Open a separate connection and wait for the first audio packet
+        function probeWebSocket() {{
+            return new Promise((resolve) => {{
+                const started = performance.now();
+                let opened = false;
+                const probe = new WebSocket(WS_URL + (TIER ? `?tier=${{TIER}}` : ''));
+                probe.binaryType = 'arraybuffer';
+                const finish = (result) => {{
+                    clearTimeout(timer);
+                    probe.onopen = probe.onmessage = probe.onerror = probe.onclose = null;
+                    probe.close();
+                    resolve(result);
+                }};
+                const timer = setTimeout(() => finish({{ opened, audio: false }}), 5000);
+                probe.onopen = () => {{ opened = true; }};
+                probe.onmessage = (event) => {{
+                    if (typeof event.data !== 'string') {{
+                        finish({{ opened: true, audio: true, ms: Math.round(performance.now() - started) }});
+                    }}
+                }};
+                probe.onerror = probe.onclose = () => finish({{ opened, audio: false }});
+            }});
+        }}
+        async function runDiagnostics() {{
+            if (diagRunning) return;
+            diagRunning = true;
+            diagEl.hidden = false;
+            diagListEl.innerHTML = '';
+            diagEl.scrollIntoView({{ behavior: 'smooth' }});
+            
+            const serverRow = diagRow('Server');
+            const audioRow = diagRow('Audio');
+            let server = null;
+            try {{
+                const res = await fetchWithTimeout('/api/diagnostics', 5000);
+                if (!res.ok) throw new Error(`HTTP ${{res.status}}`);
+                server = await res.json();
+                serverRow('ok', `reachable, ${{server.clients}} listener(s), ${{server.codec}}`);
+            }} catch (e) {{
+                serverRow('fail', e.name === 'AbortError' ? 'no answer within 5s' : e.message,
+                    'The page loaded but the server is not answering now. Check that RustCast is still running ' +
+                    'and the host PC is awake, and that nothing between you (VPN, proxy) dropped the connection.');
+            }}
+            if (!server) {{
+                audioRow('fail', 'unknown (server unreachable)');
+            }} else if (server.audio_age_ms === null) {{
+                audioRow('fail', 'no audio since the server started',
+                    'Streaming is stopped on the host. Press "Start streaming" in RustCast.');
+            }} else if (server.audio_age_ms > 2000) {{
+                audioRow('warn', `no audio for ${{Math.round(server.audio_age_ms / 1000)}}s` +
+                    (server.keepalive ? ' (silence keeps the connection open)' : ''),
+                    'The host stopped streaming or its capture device went away. Listeners stay connected ' +
+                    'and hear audio again once it is restarted.');
+            }} else {{
+                const drops = server.drops.capture + server.drops.encode;
+                audioRow(drops > 0 ? 'warn' : 'ok', drops > 0 ? `flowing, ${{drops}} buffers dropped on the host` : 'flowing',
+                    'The host PC is dropping audio before it reaches the network: it is short on CPU ' +
+                    '(a game or other heavy program) - ask the host to check RustCast\'s resource panel.');
             }}
+            
+            const statusRow = diagRow('/status');
+            if (server && !server.endpoints.status) {{
+                statusRow('warn', 'turned off on this server', 'Not needed for listening.');
+            }} else {{
+                try {{
+                    const res = await fetchWithTimeout('/status', 5000);
+                    if (!res.ok) throw new Error(`HTTP ${{res.status}}`);
+                    const status = await res.json();
+                    statusRow('ok', `running, ${{status.clients}} client(s), ${{status.drops.client}} packets dropped to slow listeners`);
+                }} catch (e) {{
+                    statusRow('fail', e.message, 'A reverse proxy may only forward some paths; make sure it passes every path to RustCast.');
+                }}
+            }}
+            
+            const wsRow = diagRow('WebSocket');
+            if (CODEC === 'mp3') {{
+                wsRow('ok', 'not used (MP3 plays over HTTP)');
+            }} else if (server && !server.endpoints.ws) {{
+                wsRow('fail', 'turned off on this server', 'The player uses the HTTP stream instead (higher latency).');
+            }} else if (!('WebSocket' in window)) {{
+                wsRow('fail', 'not supported by this browser', 'The player uses the HTTP stream instead (higher latency).');
+            }} else {{
+                const probe = await probeWebSocket();
+                if (probe.audio) {{
+                    wsRow('ok', `${{WS_URL}} connected, first audio after ${{probe.ms}} ms`);
+                }} else if (probe.opened) {{
+                    wsRow('warn', 'connected, but no audio within 5s', 'See "Audio" above: the host is probably not streaming.');
+                }} else {{
+                    wsRow('fail', `could not connect to ${{WS_URL}}`,
+                        'A firewall, antivirus or proxy blocks WebSocket upgrades. Behind a reverse proxy, forward the ' +
+                        'Upgrade/Connection headers and set public_url on the server. The player falls back to HTTP.');
+                }}
+            }}
+            
+            const decoderRow = diagRow('Decoder');
+            if (CODEC === 'mp3') {{
+                decoderRow('ok', 'the browser plays MP3 itself');
+            }} else if (!(window.AudioContext || window.webkitAudioContext)) {{
+                decoderRow('fail', 'no Web Audio in this browser', 'The player uses the HTTP stream instead (higher latency).');
+            }} else {{
+                try {{
+                    if (!OpusDecoder) {{
+                        ({{ OpusDecoder }} = await import(OPUS_DECODER_URL));
+                    }}
+                    const decoder = new OpusDecoder({{ channels: CHANNELS, sampleRate: SAMPLE_RATE }});
+                    await decoder.ready;
+                    decoder.free();
+                    decoderRow('ok', 'Opus decoder loaded');
+                }} catch (e) {{
+                    decoderRow('fail', `Opus decoder failed to load (${{e.message}})`,
+                        'It is downloaded from cdn.jsdelivr.net: an ad blocker, content filter or offline network ' +
+                        'blocks it. Allow that site, or use the HTTP fallback (higher latency).');
+                }}
+            }}
+            
+            const autoplayRow = diagRow('Autoplay');
+            const autoplayHint = 'Normal for most browsers: audio starts when you press Play. If it stays silent ' +
+                'after that, allow sound for this site in the browser\'s site settings.';
+            let policy = null;
+            if (navigator.getAutoplayPolicy) {{
+                policy = navigator.getAutoplayPolicy('audiocontext');
+            }} else if (window.AudioContext || window.webkitAudioContext) {{
+                const context = new (window.AudioContext || window.webkitAudioContext)();
+                policy = context.state === 'running' ? 'allowed' : 'disallowed';
+                context.close().catch(() => {{}});
+            }}
+            if (policy === 'allowed') {{
+                autoplayRow('ok', 'audio may start without a click');
+            }} else {{
+                autoplayRow('warn', policy ? `blocked until you interact (${{policy}})` : 'unknown', autoplayHint);
+            }}
+            diagRunning = false;
+        }}
+        document.getElementById('diagRun').addEventListener('click', runDiagnostics);
+        document.getElementById('diagLink').addEventListener('click', (e) => {{
+            e.preventDefault();
+            runDiagnostics();
         }});
+        if (new URLSearchParams(location.search).has('diagnostics')) runDiagnostics();
+        
+        // Resolves false if the browser keeps the context suspended (no tap yet)
+        function resumesWithin(context, ms) {{
+            return Promise.race([
+                context.resume().then(() => true),
+                new Promise((resolve) => setTimeout(() => resolve(false), ms)),
+            ]);
+        }}
+        
+        // Kiosk: try again later, backing off up to KIOSK_RETRY_MAX_MS, for as long as the page is open
+        function kioskRetry() {{
+            setTimeout(() => {{
+                if (!isPlaying) start();
+            }}, kioskRetryMs);
+            kioskRetryMs = Math.min(kioskRetryMs * 2, KIOSK_RETRY_MAX_MS);
+        }}
+        
+        // Start on load; the first tap or key anywhere turns the sound on
+        function startKiosk() {{
+            const unmute = () => {{
+                document.removeEventListener('pointerdown', unmute);
+                document.removeEventListener('keydown', unmute);
+                kioskUnlocked = true;
+                if (kioskMuted) {{
+                    // Switch to the low-latency path while the tap allows starting audio
+                    kioskMuted = false;
+                    stop();
+                    start();
+                }} else if (audioContext && audioContext.state === 'suspended' && !timeshiftPaused) {{
+                    audioContext.resume();
+                }}
+            }};
+            document.addEventListener('pointerdown', unmute);
+            document.addEventListener('keydown', unmute);
+            togglePlay();
+        }}
+        if (KIOSK) startKiosk();
+        
+        // player.autoplay: start right away. Without a prior tap most browsers keep the
+        // AudioContext suspended, so the first tap or key anywhere on the page releases it.
+        if (PLAYER.autoplay && !KIOSK && !new URLSearchParams(location.search).has('diagnostics')) {{
+            const unlock = (e) => {{
+                document.removeEventListener('pointerdown', unlock);
+                document.removeEventListener('keydown', unlock);
+                if (audioContext && audioContext.state === 'suspended' && !timeshiftPaused) {{
+                    audioContext.resume();
+                }} else if (!isPlaying && !playBtn.disabled && !playBtn.contains(e.target)) {{
+                    // The HTTP stream (MP3, fallback) refused to start without a tap
+                    togglePlay();
+                }}
+            }};
+            document.addEventListener('pointerdown', unlock);
+            document.addEventListener('keydown', unlock);
+            togglePlay();
+        }}
+        
+        // Installable player (needs https or localhost)
+        if ('serviceWorker' in navigator) {{
+            navigator.serviceWorker.register('/sw.js').catch((e) => console.warn('Service worker:', e));
+        }}
     </script>
 </body>
-</html>"##, port)
+</html>"##, station = branding.station_html(), subtitle = branding.subtitle_html("Ultra Low Latency Audio"),
+        station_meta = branding.station_meta_html(), accent = branding.accent_color,
+        logo = branding.logo_html(), station_js = branding.station_js(), artwork_js = branding.artwork_js(), chat_enabled = chat_enabled, interactions_enabled = interactions_enabled,
+        timeshift_enabled = timeshift_enabled, theme_class = branding.theme_class(),
+        kiosk_class = if kiosk { " kiosk" } else { "" }, kiosk = kiosk)
     }
 
     /// Get index HTML page (legacy player)
-    fn get_index_html(port: u16) -> String {
+    fn get_index_html(port: u16, branding: &PlayerBranding, codec: Codec) -> String {
         format!(r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>🎵 RustCast - Low Latency Audio</title>
+    <title>🎵 {station} - Low Latency Audio</title>
     <style>
+        :root {{ --accent: {accent}; }}
         * {{ margin: 0; padding: 0; box-sizing: border-box; }}
         body {{
             font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
@@ -883,10 +2430,15 @@ impl StreamServer {
             box-shadow: 0 8px 32px rgba(0,0,0,0.3);
             min-width: 350px;
         }}
+        .logo {{
+            max-width: 120px;
+            max-height: 120px;
+            margin-bottom: 0.5rem;
+        }}
         h1 {{
             font-size: 2.5rem;
             margin-bottom: 0.5rem;
-            background: linear-gradient(45deg, #9b59b6, #3498db);
+            background: linear-gradient(45deg, #9b59b6, var(--accent));
             -webkit-background-clip: text;
             -webkit-text-fill-color: transparent;
             background-clip: text;
@@ -895,6 +2447,15 @@ impl StreamServer {
             color: #888;
             margin-bottom: 1rem;
         }}
+        .station-meta {{
+            color: #888;
+            font-size: 0.85rem;
+            margin-top: -0.5rem;
+            margin-bottom: 1rem;
+        }}
+        .station-meta:empty {{
+            display: none;
+        }}
         .codec-badge {{
             display: inline-block;
             padding: 4px 12px;
@@ -978,15 +2539,17 @@ impl StreamServer {
             color: #aaa;
         }}
         a {{
-            color: #3498db;
+            color: var(--accent);
             text-decoration: none;
         }}
     </style>
 </head>
 <body>
     <div class="container">
-        <h1>🎵 RustCast</h1>
-        <p class="subtitle">Windows System Audio Streaming</p>
+        {logo}
+        <h1>🎵 {station}</h1>
+        <p class="subtitle">{subtitle}</p>
+        <p class="station-meta">{station_meta}</p>
         <span class="codec-badge">🚀 Opus Low-Latency</span>
         
         <div class="player">
@@ -1012,8 +2575,8 @@ impl StreamServer {
         </div>
         
         <div class="info">
-            <p>Direct stream: <a href="/stream.opus">/stream.opus</a></p>
-            <p>Port: {} | Codec: Opus</p>
+            <p>Direct stream: <a href="/stream">/stream</a></p>
+            <p>Port: {port} | Codec: {codec}</p>
         </div>
     </div>
     
@@ -1058,13 +2621,13 @@ impl StreamServer {
                     bufferCheckInterval = null;
                 }}
             }} else {{
-                // Reload stream for fresh start with Opus
-                audio.src = '/stream.opus?' + Date.now();
+                // Reload stream for fresh start (/stream follows the active codec)
+                audio.src = '/stream?' + Date.now();
                 audio.load();
                 audio.play().then(() => {{
                     isPlaying = true;
                     playBtn.textContent = '⏹ Stop';
-                    status.textContent = '🟢 Streaming Live (Opus)';
+                    status.textContent = '🟢 Streaming Live ({codec})';
                     status.className = 'status';
                     startBufferMonitor();
                 }}).catch(e => {{
@@ -1134,101 +2697,1114 @@ impl StreamServer {
         }});
     </script>
 </body>
-</html>"#, port)
+</html>"#, port = port, station = branding.station_html(), subtitle = branding.subtitle_html("Windows System Audio Streaming"),
+        station_meta = branding.station_meta_html(), accent = branding.accent_color, logo = branding.logo_html(),
+        codec = match codec {
+            Codec::Opus => "Opus",
+            Codec::Mp3 => "MP3",
+        })
     }
 }
 
-/// Generate a random serial number for Ogg stream
-fn generate_serial() -> u32 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    use std::sync::atomic::{AtomicU32, Ordering};
-    
-    static COUNTER: AtomicU32 = AtomicU32::new(0);
+/// Server status shared by /status and the control API
+pub fn status_json(hub: &BroadcastHub) -> serde_json::Value {
+    let subscribers: Vec<serde_json::Value> = hub.snapshot()
+        .iter()
+        .map(|sub| serde_json::json!({
+            "id": sub.id,
+            "kind": sub.kind.as_str(),
+            "tier": sub.tier.as_str(),
+            "mode": sub.class.as_str(),
+            "queued": sub.queued,
+            "dropped": sub.dropped,
+            "bytes_sent": sub.bytes_sent,
+            "backlog": sub.backlog,
+        }))
+        .collect();
+    let counts = hub.client_counts();
+    serde_json::json!({
+        "clients": counts.total(),
+        "clients_by_type": {
+            "websocket": counts.websocket,
+            "http": counts.http,
+            "tcp": counts.tcp,
+        },
+        "running": true,
+        "subscribers": subscribers,
+        "drops": drops::snapshot(),
+        "capture_glitches": glitches::snapshot(),
+        "write_timeouts": hub::write_timeouts(),
+        "encoder": encoder_load::snapshot(),
+        "encoders": encode_pool::snapshot(),
+        "resources": resources::snapshot(),
+        "privacy": privacy::snapshot(),
+        "device": audio::device_status(),
+        "health": health::snapshot(),
+        "system_volume": system_volume::snapshot(),
+        "validation": validate::snapshot(),
+        "dsp_errors": dsp::config_errors(),
+        "tunnel": tunnel::snapshot(),
+    })
+}
+
+/// Build an HTML page response
+fn html_response(html: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(html)
+        .with_header(
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap()
+        )
+}
+
+/// Build a JSON response with the given status code
+pub fn json_response(value: serde_json::Value, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(value.to_string())
+        .with_status_code(StatusCode(status))
+        .with_header(
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+        )
+}
+
+/// Read a small JSON request body (capped at 4KB)
+pub fn read_json_body(request: &mut tiny_http::Request) -> Option<serde_json::Value> {
+    use std::io::Read;
     
-    let time_part = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_nanos() as u32)
-        .unwrap_or(0);
+    let mut body = String::new();
+    request.as_reader().take(4096).read_to_string(&mut body).ok()?;
+    serde_json::from_str(&body).ok()
+}
+
+/// Everything the request handlers share
+/// Silent MP3 frames per keepalive: about 200ms, like the Opus page
+const MP3_KEEPALIVE_FRAMES: usize = 8;
+
+/// When a hub last published audio, for the keepalive of a hub fed outside the broadcast thread
+struct LastPublished(Mutex<std::time::Instant>);
+
+impl LastPublished {
+    fn new() -> Self {
+        Self(Mutex::new(std::time::Instant::now()))
+    }
+
+    fn elapsed(&self) -> std::time::Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+}
+
+impl hub::PacketTap for LastPublished {
+    fn published(&self, _tier: Tier, _packet: &Packet) {
+        *self.0.lock().unwrap() = std::time::Instant::now();
+    }
+}
+
+struct ServerState {
+    port: u16,
+    opus_info: Arc<OpusStreamInfo>,
+    hub: Arc<BroadcastHub>,
+    chat: Option<Arc<ChatRoom>>,
+    interactions: Option<Arc<Interactions>>,
+    now_playing: Option<Arc<NowPlayingService>>,
+    sleep_timers: Option<Arc<SleepTimers>>,
+    vorbis: Option<(Arc<BroadcastHub>, Packet)>,
+    compare: Option<Arc<Compare>>,
+    calibration: Option<Arc<Calibration>>,
+    headers: Arc<ResponseHeaders>,
+    branding: PlayerBranding,
+    timeshift: Option<Arc<TimeShiftBuffer>>,
+    loudness: Option<Arc<LoudnessLog>>,
+    delay: Option<Arc<BroadcastDelay>>,
+    low_tier: Option<Arc<LowTier>>,
+    app_channel: Option<Arc<AppChannel>>,
+    codec: Option<(Arc<CodecSwitch>, Arc<BroadcastHub>)>,
+    public_url: Option<String>,
+    mic: Option<Arc<MicMeter>>,
+    station: Arc<Station>,
+    /// Default Opus frames per Ogg page for HTTP listeners
+    ogg_frames_per_page: usize,
+    shared_ogg: Option<Arc<SharedOggMux>>,
+    /// Routes served (for /api/diagnostics)
+    endpoints: EndpointsConfig,
+    status_enabled: bool,
+    keepalive: Option<std::time::Duration>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    blocklist: Option<Arc<Blocklist>>,
+    chaos: Option<ChaosConfig>,
+    ws_compression: bool,
+    /// When the last encoded packet arrived from the pipeline (keepalive silence doesn't count)
+    last_audio: Arc<Mutex<Option<std::time::Instant>>>,
+}
+
+impl ServerState {
+    /// Player branding with the current station metadata
+    fn branding(&self) -> PlayerBranding {
+        self.branding.with_station(self.station.get())
+    }
+
+    /// Codec of the main stream right now
+    fn codec(&self) -> Codec {
+        self.codec.as_ref().map(|(codec, _)| codec.current()).unwrap_or_default()
+    }
+
+    /// Tier for a new listener (`?tier=low|full`, or the User-Agent list)
+    fn tier(&self, ctx: &Ctx) -> Tier {
+        match &self.low_tier {
+            Some(low_tier) => low_tier.select(ctx.query("tier"), ctx.header::<String>("User-Agent").as_deref()),
+            None => Tier::Full,
+        }
+    }
+
+    /// Client class for a new listener (`?mode=realtime|buffered`, realtime by default)
+    fn class(&self, ctx: &Ctx) -> ClientClass {
+        ctx.query("mode").unwrap_or_default()
+    }
+
+    /// `debug.chaos` for a designated test listener (`?chaos=1` or one of `chaos.ips`)
+    fn chaos(&self, ctx: &Ctx) -> Option<ChaosConfig> {
+        let chaos = self.chaos.as_ref()?;
+        let by_query = ctx.query::<u8>("chaos").is_some_and(|chaos| chaos != 0);
+        let by_ip = ctx.remote_addr().is_some_and(|addr| chaos.ips.contains(&addr.ip()));
+        (by_query || by_ip).then(|| chaos.clone())
+    }
+}
+
+/// Device ID the web player keeps in local storage (`?device=`), if it looks like one
+fn device_id(ctx: &Ctx) -> Option<String> {
+    ctx.query::<String>("device")
+        .filter(|id| !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'))
+}
+
+/// Tag a new listener for the client lists: `/kiosk` players (`?kiosk=1`) and the player's device ID
+fn identify(hub: &BroadcastHub, subscription: &hub::Subscription, ctx: &Ctx) {
+    if ctx.query::<u8>("kiosk").is_some_and(|kiosk| kiosk != 0) {
+        hub.mark_kiosk(subscription.id);
+    }
+    if let Some(device) = device_id(ctx) {
+        hub.set_device(subscription.id, device);
+    }
+}
+
+/// Public routes; endpoint groups turned off in the config are not registered (404)
+fn routes(endpoints: &EndpointsConfig, status_enabled: bool) -> Router<ServerState> {
+    // Banned addresses are refused before they use up rate limit tokens
+    let mut router = Router::new().guard(blocked).guard(rate_limited);
+    if endpoints.player {
+        router = router
+            .get(&["/"], player_page)
+            .get(&["/kiosk"], kiosk_page)
+            .get(&["/compare"], compare_page)
+            .get(&["/calibrate"], calibrate_page)
+            .get(&["/check"], check_page)
+            .get(&["/api/player-config"], player_config)
+            .get(&["/api/diagnostics"], diagnostics)
+            .get(&["/manifest.json"], manifest)
+            .get(&["/sw.js"], service_worker)
+            .get(&["/icon-256.png", "/icon-512.png"], app_icon)
+            .get(&["/logo"], logo);
+    }
+    if endpoints.legacy {
+        router = router.get(&["/legacy"], legacy_page);
+    }
+    if endpoints.ws {
+        router = router.get(&["/ws", "/ws/"], websocket).get(&["/compare/ws"], compare_websocket);
+    }
+    if endpoints.stream {
+        router = router
+            .get(&["/stream"], main_stream)
+            .get(&["/stream.opus", "/stream.ogg"], opus_stream)
+            .get(&["/stream.mp3"], mp3_stream)
+            .get(&["/stream.vorbis", "/stream.vorbis.ogg"], vorbis_stream);
+    }
+    if endpoints.api {
+        router = router
+            .post(&["/api/chat"], chat_post)
+            .post(&["/api/sleep"], sleep_post)
+            .get(&["/api/now-playing"], now_playing)
+            .post(&["/api/react", "/api/request"], interaction_post)
+            .post(&["/api/calibration/chirp"], calibration_chirp)
+            .get(&["/api/calibration"], calibration_offset)
+            .post(&["/api/calibration"], calibration_post)
+            .get(&["/api/timeshift"], timeshift_info)
+            .get(&["/timeshift"], timeshift_packets);
+    }
+    if endpoints.status && status_enabled {
+        router = router.get(&["/status"], status);
+    }
+    router
+}
+
+/// Banned listeners get the 403 page for everything, streams included
+fn blocked(state: &ServerState, ctx: &mut Ctx) -> Option<Reply> {
+    let blocklist = state.blocklist.as_ref()?;
+    if !blocklist.is_blocked(ctx.remote_addr().map(|addr| addr.ip()), device_id(ctx).as_deref()) {
+        return None;
+    }
+    log::debug!("[BLOCK] Refused {:?} {}", ctx.remote_addr(), ctx.path());
+    Some(Reply::Respond(blocklist::forbidden_response(), ContentKind::Page))
+}
+
+/// Per-IP limit on the listener APIs
+fn rate_limited(state: &ServerState, ctx: &mut Ctx) -> Option<Reply> {
+    let rate_limit = state.rate_limit.as_ref()?;
+    if !is_limited(ctx.path()) {
+        return None;
+    }
+    let refusal = rate_limit.check(ctx.remote_addr().map(|addr| addr.ip())).err()?;
+    Some(Reply::api(refusal.response()))
+}
+
+/// Paths under the per-IP limit: the `/api/*` routes and `/timeshift`, which answers every
+/// request from the buffer like one of them. Pages, streams and WebSocket upgrades are not.
+fn is_limited(path: &str) -> bool {
+    path.starts_with("/api/") || path == "/timeshift"
+}
+
+/// Low-latency WebSocket player
+fn player_page(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let html = StreamServer::get_low_latency_html(
+        &state.branding(),
+        state.chat.is_some(),
+        state.interactions.is_some(),
+        state.timeshift.is_some(),
+        false,
+    );
+    Reply::Respond(html_response(html), ContentKind::Page)
+}
+
+/// The player for unattended screens: starts on load (muted if the browser insists, sound
+/// on the first tap), reconnects forever, shows only the station, status and visualizer
+fn kiosk_page(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let html = StreamServer::get_low_latency_html(
+        &state.branding(),
+        state.chat.is_some(),
+        state.interactions.is_some(),
+        state.timeshift.is_some(),
+        true,
+    );
+    Reply::Respond(html_response(html), ContentKind::Page)
+}
+
+/// Two encoder settings side by side (`compare`), switched in place to pick one by ear
+fn compare_page(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    if state.compare.is_none() {
+        return Reply::not_found("A/B comparison is disabled (compare.enabled)");
+    }
+    Reply::Respond(html_response(compare::PAGE.to_string()), ContentKind::Page)
+}
+
+/// Times a chirp from here to this device (`calibration`) and keeps the result per device
+fn calibrate_page(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    if state.calibration.is_none() {
+        return Reply::not_found("Latency calibration is disabled (calibration.enabled)");
+    }
+    Reply::Respond(html_response(calibration::page()), ContentKind::Page)
+}
+
+/// Listener-side check that audio frames arrive (pairs with the test tone)
+fn check_page(_state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    Reply::Respond(html_response(test_tone::CHECK_PAGE.to_string()), ContentKind::Page)
+}
+
+/// What the player needs to connect and decode, fetched before it starts
+fn player_config(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let codec = state.codec();
+    let info = &state.opus_info;
+    Reply::json(serde_json::json!({
+        "codec": codec.as_str(),
+        // null = same host as the page
+        "ws_url": state.public_url.as_deref().map(links::ws_url),
+        "stream_url": match codec {
+            Codec::Opus => "/stream.opus",
+            Codec::Mp3 => "/stream.mp3",
+        },
+        // Opus always decodes at 48kHz
+        "sample_rate": match codec {
+            Codec::Opus => 48000,
+            Codec::Mp3 => info.sample_rate,
+        },
+        "channels": info.channels,
+        "frame_ms": info.frame_size as u32 * 1000 / 48000,
+        "buffer_ms": state.branding.buffer_ms,
+        "autoplay": state.branding.autoplay,
+        "theme": state.branding.theme.as_str(),
+        "visualizer": state.branding.visualizer,
+        // Executable name of the opus-mux application channel (null = off)
+        "app_channel": state.app_channel.as_ref().map(|app_channel| app_channel.app()),
+        // UDP port and pinned certificate hash (null = not listening)
+        "webtransport": webtransport::advert(),
+        "station": state.station.get(),
+    }), 200)
+}
+
+/// Server side of the player's diagnostics mode: is audio flowing, which routes are on
+fn diagnostics(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let audio_age_ms = state.last_audio.lock().unwrap().map(|at| at.elapsed().as_millis() as u64);
+    Reply::json(serde_json::json!({
+        "running": true,
+        "codec": state.codec().as_str(),
+        // null = no audio since the server started (streaming stopped on the host)
+        "audio_age_ms": audio_age_ms,
+        "keepalive": state.keepalive.map(|interval| interval.as_secs()),
+        "endpoints": {
+            "ws": state.endpoints.ws,
+            "stream": state.endpoints.stream,
+            "status": state.endpoints.status && state.status_enabled,
+        },
+        "ws_url": state.public_url.as_deref().map(links::ws_url),
+        "clients": state.hub.client_counts().total(),
+        "drops": drops::snapshot(),
+        "write_timeouts": hub::write_timeouts(),
+    }), 200)
+}
+
+/// HTML5 audio player (for compatibility)
+fn legacy_page(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let html = StreamServer::get_index_html(state.port, &state.branding(), state.codec());
+    Reply::Respond(html_response(html), ContentKind::Page)
+}
+
+fn manifest(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    Reply::api(
+        Response::from_string(pwa::manifest_json(&state.branding()).to_string())
+            .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/manifest+json"[..]).unwrap()),
+    )
+}
+
+fn service_worker(_state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    Reply::api(
+        Response::from_string(pwa::SERVICE_WORKER)
+            .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/javascript"[..]).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"Cache-Control"[..], &b"no-cache"[..]).unwrap()),
+    )
+}
+
+fn app_icon(_state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let icon = if ctx.path() == "/icon-256.png" { pwa::ICON_256 } else { pwa::ICON_512 };
+    Reply::api(
+        Response::from_data(icon)
+            .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"image/png"[..]).unwrap()),
+    )
+}
+
+fn logo(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    match &state.branding.logo_file {
+        Some(logo) => Reply::api(
+            Response::from_data(logo.bytes.as_slice())
+                .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], logo.content_type.as_bytes()).unwrap()),
+        ),
+        None => Reply::not_found("Not Found"),
+    }
+}
+
+/// WebSocket upgrade for ultra-low latency streaming, `?resume=<client_id>` continues a session,
+/// `?tier=low|full` overrides the tier, `?mode=buffered` trades latency for robustness
+/// (the client is counted until the subscription is dropped)
+fn websocket(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some(ws_key) = ctx.header::<String>("Sec-WebSocket-Key") else {
+        return Reply::json(serde_json::json!({ "error": "Missing Sec-WebSocket-Key" }), 400);
+    };
+    let requested = ctx.header::<String>("Sec-WebSocket-Protocol");
+    let Some(payload) = WsPayload::negotiate(requested.as_deref(), state.app_channel.is_some()) else {
+        let supported = if state.app_channel.is_some() { "opus-raw, opus-mux, ogg, pcm16" } else { "opus-raw, ogg, pcm16" };
+        return Reply::json(serde_json::json!({ "error": format!("Supported subprotocols: {}", supported) }), 400);
+    };
+    let deflate = if state.ws_compression {
+        ws::Deflate::negotiate(ctx.header::<String>("Sec-WebSocket-Extensions").as_deref())
+    } else {
+        None
+    };
+    // Set up before the upgrade, so a decoder that can't be created is still an HTTP error
+    let info = &state.opus_info;
+    let encoding = match payload {
+        WsPayload::OpusRaw => WsEncoding::OpusRaw,
+        WsPayload::OpusMux => WsEncoding::OpusMux,
+        WsPayload::Ogg => WsEncoding::Ogg(OggSink::new(info, Vec::new(), state.station.get().vorbis_comments())),
+        WsPayload::Pcm16 => match OpusDecoder::new(info.sample_rate, info.channels) {
+            Ok(decoder) => WsEncoding::Pcm16(decoder),
+            Err(e) => {
+                log::error!("WebSocket PCM client refused: {}", e);
+                return Reply::json(serde_json::json!({ "error": e }), 500);
+            }
+        },
+    };
+    // PCM comes out at the decoder's rate and channels, not necessarily the capture's
+    let (sample_rate, channels) = match &encoding {
+        WsEncoding::Pcm16(decoder) => (decoder.sample_rate(), decoder.channels()),
+        _ => (info.sample_rate, info.channels),
+    };
+    let subscription = join_player(state, ctx, payload, sample_rate, channels);
+
+    // Handle WebSocket in separate thread
+    let chaos = state.chaos(ctx);
+    let headers = state.headers.clone();
+    let protocol = requested.is_some().then(|| payload.name());
+    Reply::Takeover(Box::new(move |request| {
+        thread::spawn(move || {
+            let mut sink = websocket_sink(&ws_key, protocol, encoding, deflate, &headers);
+            let mut stream = request.into_writer();
+            if let Err(e) = chaos::pump(&subscription, &mut sink, &mut stream, chaos.as_ref()) {
+                log::debug!("WebSocket error: {}", e);
+            }
+        });
+    }))
+}
+
+/// Subscribe a live player (/ws, WebTransport), or take its dropped session back (`?resume=`),
+/// and queue the hello, chat history and now playing for it
+fn join_player(state: &ServerState, ctx: &Ctx, payload: WsPayload, sample_rate: u32, channels: u16) -> hub::Subscription {
+    let remote_addr = ctx.remote_addr();
+    let tier = state.tier(ctx);
+    let class = state.class(ctx);
+    let subscription = match ctx.query::<u64>("resume") {
+        Some(previous_id) => state.hub.resume(remote_addr, previous_id, tier, class),
+        None => state.hub.subscribe_class(SinkKind::WebSocket, remote_addr, tier, class),
+    };
+    identify(&state.hub, &subscription, ctx);
+
+    // Players use their id to address the HTTP control API
+    let mut hello = serde_json::json!({
+        "type": "hello",
+        "client_id": subscription.id,
+        "payload": payload.name(),
+        "tier": tier.as_str(),
+        "mode": class.as_str(),
+        "sample_rate": sample_rate,
+        "channels": channels,
+    });
+    if let (WsPayload::OpusMux, Some(app_channel)) = (payload, &state.app_channel) {
+        state.hub.join_channels(subscription.id);
+        hello["mux"] = serde_json::json!([
+            { "id": 0, "name": "mix" },
+            { "id": app_channel::CHANNEL_ID, "name": app_channel.app() },
+        ]);
+    }
+    // Measured on /calibrate, for lining this device up with others
+    if let Some(offset) = device_id(ctx).and_then(|device| state.calibration.as_ref()?.offset(&device)) {
+        hello["latency_offset_ms"] = serde_json::json!(offset.latency_ms);
+    }
+    state.hub.send_text_to(subscription.id, &hello.to_string());
+
+    // Catch the new player up on the conversation
+    if let Some(chat) = &state.chat {
+        chat.send_history_to(subscription.id);
+    }
+    if let Some(now_playing) = &state.now_playing {
+        now_playing.send_current_to(subscription.id);
+    }
+    subscription
+}
+
+/// A WebTransport session (`webtransport.rs`) as the GET of its path: refused like any request
+/// by `blocked` and the rate limit, then a player on opus-raw like /ws
+fn webtransport_session(state: &ServerState, mut request: tiny_http::Request) -> Result<hub::Subscription, u16> {
+    let mut ctx = Ctx::new(&mut request);
+    if blocked(state, &mut ctx).is_some() {
+        return Err(403);
+    }
+    if let Some(rate_limit) = &state.rate_limit {
+        rate_limit.check(ctx.remote_addr().map(|addr| addr.ip())).map_err(|_| 429u16)?;
+    }
+    let info = &state.opus_info;
+    Ok(join_player(state, &ctx, WsPayload::OpusRaw, info.sample_rate, info.channels))
+}
+
+/// Both /compare encodes, one frame per packet pair (see `compare`)
+fn compare_websocket(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some(compare) = &state.compare else {
+        return Reply::json(serde_json::json!({ "error": "A/B comparison is disabled" }), 404);
+    };
+    let Some(ws_key) = ctx.header::<String>("Sec-WebSocket-Key") else {
+        return Reply::json(serde_json::json!({ "error": "Missing Sec-WebSocket-Key" }), 400);
+    };
+    let deflate = if state.ws_compression {
+        ws::Deflate::negotiate(ctx.header::<String>("Sec-WebSocket-Extensions").as_deref())
+    } else {
+        None
+    };
+    let subscription = compare.subscribe(ctx.remote_addr());
+    let headers = state.headers.clone();
+    Reply::Takeover(Box::new(move |request| {
+        thread::spawn(move || {
+            let mut sink = websocket_sink(&ws_key, None, WsEncoding::OpusRaw, deflate, &headers);
+            let mut stream = request.into_writer();
+            if let Err(e) = hub::pump(&subscription, &mut sink, &mut stream) {
+                log::debug!("Compare WebSocket error: {}", e);
+            }
+        });
+    }))
+}
+
+/// Whichever codec is active, for players that don't care
+fn main_stream(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    match state.codec() {
+        Codec::Opus => opus_stream(state, ctx),
+        Codec::Mp3 => mp3_stream(state, ctx),
+    }
+}
+
+/// Ogg/Opus over HTTP, `?tier=low|full` overrides the tier, `?frames=1..10` the page grouping,
+/// `?mode=buffered` starts with pre-roll and groups pages fully unless `frames` is given
+fn opus_stream(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    if state.codec() != Codec::Opus {
+        return Reply::not_found("The stream is MP3 right now (/stream.mp3)");
+    }
+    // Register this client with the hub
+    let class = state.class(ctx);
+    let tier = state.tier(ctx);
+    let subscription = state.hub.subscribe_class(SinkKind::Http, ctx.remote_addr(), tier, class);
+    identify(&state.hub, &subscription, ctx);
+    let info = state.opus_info.clone();
+    let shared_ogg = state.shared_ogg.clone();
+    let comments = state.station.get().vorbis_comments();
+    let head = state.headers.raw_head("200 OK", ContentKind::Stream, STREAM_HEADERS);
+    // `?frames=N` overrides the configured page grouping for this listener
+    let frames_per_page = ctx.query("frames").unwrap_or(match class {
+        ClientClass::Realtime => state.ogg_frames_per_page,
+        ClientClass::Buffered => MAX_OGG_FRAMES_PER_PAGE,
+    });
+    let chaos = state.chaos(ctx);
+
+    // Stream in a separate thread
+    Reply::Takeover(Box::new(move |request| {
+        thread::spawn(move || {
+            // Get raw TCP stream from the request
+            let mut stream = request.into_writer();
+
+            // Each client gets its own Ogg stream (unique serial, headers first), or joins the shared one
+            let result = match shared_ogg {
+                Some(mux) => {
+                    let mut sink = SharedOggSink::new(&info, head, comments, mux, tier, frames_per_page);
+                    chaos::pump(&subscription, &mut sink, &mut stream, chaos.as_ref())
+                }
+                None => {
+                    let mut sink = OggSink::new(&info, head, comments).with_frames_per_page(frames_per_page);
+                    chaos::pump(&subscription, &mut sink, &mut stream, chaos.as_ref())
+                }
+            };
+            if let Err(e) = result {
+                log::debug!("HTTP stream error: {}", e);
+            }
+        });
+    }))
+}
+
+fn vorbis_stream(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some((vorbis_hub, vorbis_headers)) = &state.vorbis else {
+        return Reply::not_found("Vorbis stream is disabled");
+    };
+    let subscription = vorbis_hub.subscribe_class(SinkKind::Http, ctx.remote_addr(), Tier::Full, state.class(ctx));
+    let mut head = state.headers.raw_head("200 OK", ContentKind::Stream, STREAM_HEADERS);
+    head.extend_from_slice(vorbis_headers);
+    let chaos = state.chaos(ctx);
+
+    Reply::Takeover(Box::new(move |request| {
+        thread::spawn(move || {
+            let mut stream = request.into_writer();
+            let mut sink = VorbisSink { head };
+            if let Err(e) = chaos::pump(&subscription, &mut sink, &mut stream, chaos.as_ref()) {
+                log::debug!("Vorbis stream error: {}", e);
+            }
+        });
+    }))
+}
+
+fn mp3_stream(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some((_, mp3_hub)) = state.codec.as_ref().filter(|_| state.codec() == Codec::Mp3) else {
+        return Reply::not_found("The stream is Opus right now (/stream.opus)");
+    };
+    let subscription = mp3_hub.subscribe_class(SinkKind::Http, ctx.remote_addr(), Tier::Full, state.class(ctx));
+    identify(mp3_hub, &subscription, ctx);
+    let head = state.headers.raw_head("200 OK", ContentKind::Stream, MP3_STREAM_HEADERS);
+    let chaos = state.chaos(ctx);
+
+    Reply::Takeover(Box::new(move |request| {
+        thread::spawn(move || {
+            let mut stream = request.into_writer();
+            let mut sink = Mp3Sink { head };
+            if let Err(e) = chaos::pump(&subscription, &mut sink, &mut stream, chaos.as_ref()) {
+                log::debug!("MP3 stream error: {}", e);
+            }
+        });
+    }))
+}
+
+fn chat_post(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    match &state.chat {
+        Some(chat) => Reply::api(handle_chat_post(ctx.request(), chat)),
+        None => Reply::json(serde_json::json!({ "error": "Chat is disabled" }), 404),
+    }
+}
+
+fn sleep_post(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    match &state.sleep_timers {
+        Some(timers) => Reply::api(handle_sleep_post(ctx.request(), &state.hub, timers)),
+        None => Reply::json(serde_json::json!({ "error": "Sleep timer is disabled" }), 404),
+    }
+}
+
+fn now_playing(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let current = state.now_playing.as_ref().and_then(|np| np.current());
+    Reply::json(serde_json::json!({ "now_playing": current }), 200)
+}
+
+fn interaction_post(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    match &state.interactions {
+        Some(interactions) => Reply::api(handle_interaction_post(ctx.request(), interactions)),
+        None => Reply::json(serde_json::json!({ "error": "Interactions are disabled" }), 404),
+    }
+}
+
+/// Send the chirp; answers once it is in the stream, with how long that took
+fn calibration_chirp(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let Some(calibration) = &state.calibration else {
+        return Reply::json(serde_json::json!({ "error": "Latency calibration is disabled" }), 404);
+    };
+    match calibration.chirp() {
+        Ok(waited) => Reply::json(serde_json::json!({ "ok": true, "waited_ms": waited.as_secs_f64() * 1000.0 }), 200),
+        Err(e @ ChirpError::TooSoon) => Reply::json(serde_json::json!({ "error": e.to_string() }), 429),
+        Err(e @ ChirpError::NoAudio) => Reply::json(serde_json::json!({ "error": e.to_string() }), 503),
+    }
+}
+
+/// ?device=<id> - the last measurement of a device
+fn calibration_offset(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some(calibration) = &state.calibration else {
+        return Reply::json(serde_json::json!({ "error": "Latency calibration is disabled" }), 404);
+    };
+    let offset = ctx.query::<String>("device").and_then(|device| calibration.offset(&device));
+    Reply::json(serde_json::json!({ "offset": offset }), 200)
+}
+
+/// {"device": "...", "latency_ms": 123.4, "method": "microphone" | "stream"}
+fn calibration_post(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some(calibration) = &state.calibration else {
+        return Reply::json(serde_json::json!({ "error": "Latency calibration is disabled" }), 404);
+    };
+    let Some(body) = read_json_body(ctx.request()) else {
+        return Reply::json(serde_json::json!({ "error": "Invalid JSON" }), 400);
+    };
+    let (device, method) = (body["device"].as_str().unwrap_or_default(), body["method"].as_str().unwrap_or_default());
+    let Some(latency_ms) = body["latency_ms"].as_f64() else {
+        return Reply::json(serde_json::json!({ "error": "latency_ms is required" }), 400);
+    };
+    match calibration.record(device, latency_ms, method) {
+        Ok(()) => Reply::json(serde_json::json!({ "ok": true }), 200),
+        Err(e) => Reply::json(serde_json::json!({ "error": e }), 400),
+    }
+}
+
+fn timeshift_info(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    match &state.timeshift {
+        Some(timeshift) => Reply::json(timeshift.info(), 200),
+        None => Reply::json(serde_json::json!({ "error": "Time-shift is disabled" }), 404),
+    }
+}
+
+/// ?from=<seq>&count=<n> - packets from the time-shift buffer
+fn timeshift_packets(state: &ServerState, ctx: &mut Ctx) -> Reply {
+    let Some(timeshift) = &state.timeshift else {
+        return Reply::not_found("Time-shift is disabled");
+    };
+    let from = ctx.query("from").unwrap_or(0);
+    let count = ctx.query("count").unwrap_or(100);
+    let (from, newest, packets) = timeshift.range(from, count);
+    Reply::api(
+        Response::from_data(timeshift::encode_packets(&packets))
+            .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/octet-stream"[..]).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"X-Timeshift-From"[..], from.to_string().as_bytes()).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"X-Timeshift-Newest"[..], newest.to_string().as_bytes()).unwrap()),
+    )
+}
+
+fn status(state: &ServerState, _ctx: &mut Ctx) -> Reply {
+    let mut status = status_json(&state.hub);
+    if let Some(loudness) = &state.loudness {
+        status["loudness"] = loudness.summary();
+    }
+    if let Some(delay) = &state.delay {
+        status["delay"] = delay.info();
+    }
+    if let Some(low_tier) = &state.low_tier {
+        status["low_tier"] = low_tier.info(&state.hub);
+    }
+    if let Some(app_channel) = &state.app_channel {
+        status["app_channel"] = app_channel.info(&state.hub);
+    }
+    if let Some(compare) = &state.compare {
+        status["compare"] = compare.info();
+    }
+    if let Some(mic) = &state.mic {
+        status["mic"] = mic.info();
+    }
+    status["station"] = serde_json::json!(state.station.get());
+    status["codec"] = state.codec().as_str().into();
+    if let Some((_, mp3_hub)) = &state.codec {
+        status["mp3_clients"] = mp3_hub.client_counts().total().into();
+    }
+    Reply::json(status, 200)
+}
+
+/// POST /api/chat - {"name": "...", "text": "..."}
+fn handle_chat_post(request: &mut tiny_http::Request, chat: &ChatRoom) -> Response<std::io::Cursor<Vec<u8>>> {
+    let ip = router::client_addr(request).map(|a| a.ip().to_string()).unwrap_or_default();
     
-    let counter_part = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let Some(body) = read_json_body(request) else {
+        return json_response(serde_json::json!({ "error": "Invalid JSON" }), 400);
+    };
+    let name = body["name"].as_str().unwrap_or("");
+    let text = body["text"].as_str().unwrap_or("");
     
-    time_part.wrapping_add(counter_part)
+    match chat.post(&ip, name, text) {
+        Ok(message) => json_response(serde_json::json!({ "ok": true, "id": message.id }), 200),
+        Err(e @ crate::chat::ChatError::Muted) => json_response(serde_json::json!({ "error": e.to_string() }), 403),
+        Err(e) => json_response(serde_json::json!({ "error": e.to_string() }), 400),
+    }
 }
 
-/// Handle WebSocket connection for ultra-low latency streaming
-fn handle_websocket(
-    request: tiny_http::Request,
-    rx: std::sync::mpsc::Receiver<Vec<u8>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    use sha1::{Sha1, Digest};
-    use base64::Engine;
+/// POST /api/sleep - {"client_id": N, "seconds": S} (S = 0 or missing cancels)
+fn handle_sleep_post(request: &mut tiny_http::Request, hub: &BroadcastHub, timers: &SleepTimers) -> Response<std::io::Cursor<Vec<u8>>> {
+    let ip = router::client_addr(request).map(|a| a.ip());
     
-    // Get WebSocket key from headers
-    let ws_key = request.headers()
-        .iter()
-        .find(|h| h.field.as_str().to_ascii_lowercase() == "sec-websocket-key")
-        .map(|h| h.value.as_str().to_string())
-        .ok_or("Missing Sec-WebSocket-Key")?;
+    let Some(body) = read_json_body(request) else {
+        return json_response(serde_json::json!({ "error": "Invalid JSON" }), 400);
+    };
+    let Some(client_id) = body["client_id"].as_u64() else {
+        return json_response(serde_json::json!({ "error": "Missing client_id" }), 400);
+    };
     
-    // Generate accept key
-    let mut hasher = Sha1::new();
-    hasher.update(ws_key.as_bytes());
-    hasher.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
-    let accept_key = base64::engine::general_purpose::STANDARD.encode(hasher.finalize());
+    // Only the player itself may schedule its own disconnect
+    match hub.remote_addr(client_id) {
+        Some(addr) if Some(addr.ip()) == ip => {}
+        Some(_) => return json_response(serde_json::json!({ "error": "Not your connection" }), 403),
+        None => return json_response(serde_json::json!({ "error": "Unknown client" }), 404),
+    }
     
-    // Get raw TCP stream
-    let mut stream = request.into_writer();
+    let after = body["seconds"]
+        .as_u64()
+        .filter(|s| *s > 0)
+        .map(std::time::Duration::from_secs);
+    timers.set(client_id, after);
     
-    // Send WebSocket handshake response
-    let response = format!(
-        "HTTP/1.1 101 Switching Protocols\r\n\
-         Upgrade: websocket\r\n\
-         Connection: Upgrade\r\n\
-         Sec-WebSocket-Accept: {}\r\n\
-         \r\n",
-        accept_key
-    );
-    stream.write_all(response.as_bytes())?;
-    stream.flush()?;
+    json_response(serde_json::json!({ "ok": true }), 200)
+}
+
+/// POST /api/react - {"emoji": "👍"}, POST /api/request - {"text": "..."}
+fn handle_interaction_post(request: &mut tiny_http::Request, interactions: &Interactions) -> Response<std::io::Cursor<Vec<u8>>> {
+    let ip = router::client_addr(request).map(|a| a.ip().to_string()).unwrap_or_default();
+    let is_react = request.url().starts_with("/api/react");
     
-    log::info!("WebSocket handshake complete");
+    let Some(body) = read_json_body(request) else {
+        return json_response(serde_json::json!({ "error": "Invalid JSON" }), 400);
+    };
+    
+    let result = if is_react {
+        interactions
+            .react(&ip, body["emoji"].as_str().unwrap_or(""))
+            .map(|_| serde_json::json!({ "ok": true }))
+    } else {
+        interactions
+            .request_song(&ip, body["text"].as_str().unwrap_or(""))
+            .map(|r| serde_json::json!({ "ok": true, "id": r.id }))
+    };
     
-    // Stream Opus packets as binary WebSocket frames
-    while let Ok(opus_packet) = rx.recv() {
-        // Create WebSocket binary frame
-        let frame = create_websocket_frame(&opus_packet);
-        if stream.write_all(&frame).is_err() {
-            break;
+    match result {
+        Ok(value) => json_response(value, 200),
+        Err(e @ InteractionError::RateLimited) => json_response(serde_json::json!({ "error": e.to_string() }), 429),
+        Err(e) => json_response(serde_json::json!({ "error": e.to_string() }), 400),
+    }
+}
+
+/// Response headers of the audio streams (written straight to the socket)
+const STREAM_HEADERS: &[(&str, &str)] = &[
+    ("Content-Type", "audio/ogg"),
+    ("Cache-Control", "no-cache, no-store"),
+    ("Connection", "keep-alive"),
+    ("Access-Control-Allow-Origin", "*"),
+];
+
+const MP3_STREAM_HEADERS: &[(&str, &str)] = &[
+    ("Content-Type", "audio/mpeg"),
+    ("Cache-Control", "no-cache, no-store"),
+    ("Connection", "keep-alive"),
+    ("Access-Control-Allow-Origin", "*"),
+];
+
+/// Bind an extra port, with TLS when a certificate and key are configured
+fn bind_listener(listener: &ListenerConfig, sockets: &SocketOptions) -> Result<Server, String> {
+    let addr = format!("{}:{}", listener.bind, listener.port);
+    let ssl = if listener.is_tls() {
+        let read = |path: &str| std::fs::read(path).map_err(|e| format!("TLS file {:?} for port {}: {}", path, listener.port, e));
+        Some(tiny_http::SslConfig { certificate: read(&listener.tls_cert)?, private_key: read(&listener.tls_key)? })
+    } else {
+        None
+    };
+    let server = sockets
+        .bind(&addr, SocketKind::Web)
+        .map_err(|e| e.into())
+        .and_then(|socket| Server::from_listener(socket, ssl))
+        .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    log::info!("Server also listening on {}://{}", if listener.is_tls() { "https" } else { "http" }, addr);
+    Ok(server)
+}
+
+/// Ogg/Opus sink for HTTP clients - each client gets its own Ogg stream
+pub struct OggSink {
+    /// HTTP response head
+    head: Vec<u8>,
+    channels: u16,
+    sample_rate: u32,
+    /// OpusTags comments (station metadata)
+    comments: Vec<String>,
+    pager: OggPager,
+}
+
+impl OggSink {
+    pub fn new(info: &OpusStreamInfo, head: Vec<u8>, comments: Vec<String>) -> Self {
+        Self {
+            head,
+            comments,
+            channels: info.channels,
+            sample_rate: info.sample_rate,
+            pager: OggPager::new(info.frame_size, 1),
+        }
+    }
+
+    /// Group up to `frames` packets per page (less page overhead for clients that buffer anyway)
+    pub fn with_frames_per_page(mut self, frames: usize) -> Self {
+        self.pager = OggPager::new(self.pager.frame_size(), frames);
+        self
+    }
+}
+
+impl SinkAdapter for OggSink {
+    fn preamble(&mut self) -> Vec<u8> {
+        let mut data = std::mem::take(&mut self.head);
+        
+        // Ogg/Opus headers (unique per client)
+        data.extend(OpusEncoder::get_headers_with_serial(self.channels, self.sample_rate, self.pager.serial, &self.comments));
+        data
+    }
+
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+        self.pager.push(packet)
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.pager.finish()
+    }
+}
+
+/// Ogg/Opus sink on the shared mux - the pages were built at publish time, identical for
+/// every listener of the same tier and grouping
+struct SharedOggSink {
+    /// HTTP response head followed by the shared stream's header pages
+    head: Vec<u8>,
+    mux: Arc<SharedOggMux>,
+    frames_per_page: usize,
+}
+
+impl SharedOggSink {
+    fn new(
+        info: &OpusStreamInfo,
+        mut head: Vec<u8>,
+        comments: Vec<String>,
+        mux: Arc<SharedOggMux>,
+        tier: Tier,
+        frames_per_page: usize,
+    ) -> Self {
+        let serial = mux.serial(tier, frames_per_page);
+        head.extend(OpusEncoder::get_headers_with_serial(info.channels, info.sample_rate, serial, &comments));
+        Self { head, mux, frames_per_page }
+    }
+}
+
+impl SinkAdapter for SharedOggSink {
+    fn preamble(&mut self) -> Vec<u8> {
+        // Header pages, then we join the running stream at the next page
+        std::mem::take(&mut self.head)
+    }
+
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+        self.mux.page(self.frames_per_page, packet).map(|page| page.to_vec()).unwrap_or_default()
+    }
+}
+
+/// Ogg Vorbis sink - the encoder already produced whole Ogg pages, shared by all listeners
+struct VorbisSink {
+    /// HTTP response head followed by the Vorbis header pages
+    head: Vec<u8>,
+}
+
+impl SinkAdapter for VorbisSink {
+    fn preamble(&mut self) -> Vec<u8> {
+        // Header pages first, then we join the running stream at the next page
+        std::mem::take(&mut self.head)
+    }
+
+    fn wrap(&mut self, pages: &Packet) -> Vec<u8> {
+        pages.to_vec()
+    }
+}
+
+/// MP3 sink - frames go out as encoded; players sync to the next frame header
+struct Mp3Sink {
+    /// HTTP response head
+    head: Vec<u8>,
+}
+
+impl SinkAdapter for Mp3Sink {
+    fn preamble(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.head)
+    }
+
+    fn wrap(&mut self, frames: &Packet) -> Vec<u8> {
+        frames.to_vec()
+    }
+}
+
+/// WebSocket sink - raw Opus packets as binary frames (no Ogg wrapping)
+struct WebSocketSink {
+    /// Handshake response
+    head: Vec<u8>,
+    encoding: WsEncoding,
+    /// Compressor for text frames, if the client negotiated permessage-deflate
+    deflate: Option<ws::Deflate>,
+}
+
+/// Binary payload a WebSocket client asked for with `Sec-WebSocket-Protocol`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WsPayload {
+    /// One Opus packet per frame (browsers; the default without a subprotocol)
+    OpusRaw,
+    /// One Opus packet per frame behind a channel id byte: 0 = full mix, 1 = application
+    OpusMux,
+    /// Ogg pages, the first frame carries the Opus headers
+    Ogg,
+    /// Decoded interleaved 16-bit little-endian PCM (microcontrollers)
+    Pcm16,
+}
+
+impl WsPayload {
+    /// First supported protocol in the client's list; opus-raw if it sent none.
+    /// opus-mux is only offered while the application channel is configured.
+    fn negotiate(requested: Option<&str>, mux: bool) -> Option<Self> {
+        let Some(requested) = requested else {
+            return Some(WsPayload::OpusRaw);
+        };
+        requested.split(',').find_map(|name| match name.trim() {
+            "opus-raw" => Some(WsPayload::OpusRaw),
+            "opus-mux" if mux => Some(WsPayload::OpusMux),
+            "ogg" => Some(WsPayload::Ogg),
+            "pcm16" => Some(WsPayload::Pcm16),
+            _ => None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WsPayload::OpusRaw => "opus-raw",
+            WsPayload::OpusMux => "opus-mux",
+            WsPayload::Ogg => "ogg",
+            WsPayload::Pcm16 => "pcm16",
+        }
+    }
+}
+
+/// Per-connection converter for the negotiated payload
+enum WsEncoding {
+    OpusRaw,
+    OpusMux,
+    Ogg(OggSink),
+    Pcm16(OpusDecoder),
+}
+
+impl SinkAdapter for WebSocketSink {
+    fn preamble(&mut self) -> Vec<u8> {
+        let mut data = std::mem::take(&mut self.head);
+        if let WsEncoding::Ogg(ogg) = &mut self.encoding {
+            data.extend(ws::binary_frame(&ogg.preamble()));
         }
+        data
+    }
+
+    fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+        match &mut self.encoding {
+            WsEncoding::OpusRaw => ws::binary_frame(packet),
+            WsEncoding::OpusMux => ws::binary_frame(&[&[0], &packet[..]].concat()),
+            WsEncoding::Ogg(ogg) => ws::binary_frame(&ogg.wrap(packet)),
+            WsEncoding::Pcm16(decoder) => match decoder.decode_le(packet) {
+                Ok(pcm) => ws::binary_frame(&pcm),
+                Err(e) => {
+                    log::debug!("WebSocket PCM decode error: {}", e);
+                    Vec::new()
+                }
+            },
+        }
+    }
+
+    fn wrap_text(&mut self, text: &str) -> Option<Vec<u8>> {
+        let compressed = self.deflate.as_mut().and_then(|deflate| deflate.text_frame(text));
+        Some(compressed.unwrap_or_else(|| ws::text_frame(text)))
+    }
+
+    fn wrap_channel(&mut self, channel: u8, packet: &[u8]) -> Option<Vec<u8>> {
+        match self.encoding {
+            WsEncoding::OpusMux => Some(ws::binary_frame(&[&[channel], packet].concat())),
+            _ => None,
+        }
+    }
+
+    fn flush_each_packet(&self) -> bool {
         // Don't flush every packet - let TCP handle buffering for efficiency
+        false
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let mut data = match &mut self.encoding {
+            WsEncoding::Ogg(ogg) => ws::binary_frame(&ogg.finish()),
+            _ => Vec::new(),
+        };
+        // The player reconnects after any close; this just spares it waiting for a timeout
+        data.extend(ws::close_frame(ws::close_code::GOING_AWAY, ""));
+        data
     }
-    
-    Ok(())
 }
 
-/// Create a WebSocket binary frame
-fn create_websocket_frame(data: &[u8]) -> Vec<u8> {
-    let len = data.len();
-    let mut frame = Vec::with_capacity(10 + len);
-    
-    // FIN + Binary opcode (0x82)
-    frame.push(0x82);
-    
-    // Payload length (no masking for server->client)
-    if len <= 125 {
-        frame.push(len as u8);
-    } else if len <= 65535 {
-        frame.push(126);
-        frame.push((len >> 8) as u8);
-        frame.push((len & 0xFF) as u8);
-    } else {
-        frame.push(127);
-        for i in (0..8).rev() {
-            frame.push((len >> (i * 8)) as u8);
+/// Sink for an ultra-low latency WebSocket connection: the handshake goes out as its preamble,
+/// then packets as binary WebSocket frames
+fn websocket_sink(
+    ws_key: &str,
+    protocol: Option<&str>,
+    encoding: WsEncoding,
+    deflate: Option<(ws::Deflate, String)>,
+    headers: &ResponseHeaders,
+) -> WebSocketSink {
+    let accept_key = ws::accept_key(ws_key);
+    let mut handshake = vec![
+        ("Upgrade", "websocket"),
+        ("Connection", "Upgrade"),
+        ("Sec-WebSocket-Accept", accept_key.as_str()),
+    ];
+    if let Some(protocol) = protocol {
+        handshake.push(("Sec-WebSocket-Protocol", protocol));
+    }
+    let (deflate, extension) = deflate.unzip();
+    if let Some(extension) = &extension {
+        handshake.push(("Sec-WebSocket-Extensions", extension.as_str()));
+    }
+    let head = headers.raw_head("101 Switching Protocols", ContentKind::Api, &handshake);
+    WebSocketSink { head, encoding, deflate }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_covers_api_and_timeshift() {
+        for path in ["/api/chat", "/api/player-config", "/api/timeshift", "/timeshift"] {
+            assert!(is_limited(path), "{} not limited", path);
+        }
+        for path in ["/", "/ws", "/stream.opus", "/status", "/timeshift/", "/api"] {
+            assert!(!is_limited(path), "{} limited", path);
         }
     }
-    
-    // Payload
-    frame.extend_from_slice(data);
-    frame
 }
@@ -0,0 +1,623 @@
+//! Per-application capture
+//! Audio sessions of the default output device captured one by one (process loopback) and mixed,
+//! so System Sounds can be left out and every app included, excluded or trimmed (stream only)
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audio::{AudioSample, CaptureSource, PacedThread};
+use crate::config::AppMix;
+use crate::queue::PipelineSender;
+
+/// How often new and closed sessions are picked up
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Audio an application must have buffered before it joins the mix (absorbs capture jitter)
+const PRIME_MS: u32 = 30;
+/// Older audio is dropped beyond this, so a stalled mix never lags behind
+const MAX_BUFFER_MS: u32 = 200;
+
+/// Per-app rules of the stream mix, keyed by lowercase executable name
+static RULES: Mutex<BTreeMap<String, AppMix>> = Mutex::new(BTreeMap::new());
+/// A SessionCapture is running (rules apply live)
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+/// Last session list fetched in the background for the GUI, and whether a fetch is under way
+static LATEST: Mutex<Vec<SessionInfo>> = Mutex::new(Vec::new());
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+/// Replace every rule (config load / save)
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn set_rules(rules: &BTreeMap<String, AppMix>) {
+    *RULES.lock().unwrap() = rules.iter().map(|(name, mix)| (name.to_lowercase(), *mix)).collect();
+}
+
+/// Rule of one app (the default when it has none)
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn rule(name: &str) -> AppMix {
+    RULES.lock().unwrap().get(&name.to_lowercase()).copied().unwrap_or_default()
+}
+
+/// Change one app; a rule back at the default is removed
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn set_rule(name: &str, mix: AppMix) {
+    let mut rules = RULES.lock().unwrap();
+    if mix == AppMix::default() {
+        rules.remove(&name.to_lowercase());
+    } else {
+        rules.insert(name.to_lowercase(), mix);
+    }
+    log::info!("[SESSIONS] {}: {}", name, if mix.include { format!("{:+.0} dB", mix.gain_db) } else { "excluded".to_string() });
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn rules() -> BTreeMap<String, AppMix> {
+    RULES.lock().unwrap().clone()
+}
+
+/// Whether the stream is currently mixed per app (otherwise rules wait for a restart)
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn is_capturing() -> bool {
+    CAPTURING.load(Ordering::SeqCst)
+}
+
+/// Session list from the last background refresh. Listing goes through COM, which must stay
+/// off the GUI thread, so callers poll this and call `refresh` periodically.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn latest() -> Vec<SessionInfo> {
+    LATEST.lock().unwrap().clone()
+}
+
+/// Fetch the session list in the background (no-op while a fetch is running)
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn refresh() {
+    if REFRESHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let result = thread::Builder::new().name("session-list".into()).spawn(|| {
+        *LATEST.lock().unwrap() = platform::sessions();
+        REFRESHING.store(false, Ordering::SeqCst);
+    });
+    if result.is_err() {
+        REFRESHING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// One audio session of the output device
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub pid: u32,
+    /// Executable name ("System Sounds" for the system session)
+    pub name: String,
+    /// The Windows "System Sounds" session
+    pub system: bool,
+    /// Currently playing
+    pub active: bool,
+    /// Peak level of the session (0-1)
+    pub peak: f32,
+}
+
+/// Audio captured from one application, waiting for the mixer
+pub struct Track {
+    buffer: Mutex<TrackBuffer>,
+    prime: usize,
+    max: usize,
+}
+
+struct TrackBuffer {
+    samples: VecDeque<f32>,
+    /// Enough audio arrived to start mixing; cleared when it runs dry
+    primed: bool,
+}
+
+impl Track {
+    fn new(sample_rate: u32, channels: u16) -> Arc<Self> {
+        let per_ms = (sample_rate as usize * channels as usize / 1000).max(1);
+        Arc::new(Self {
+            buffer: Mutex::new(TrackBuffer { samples: VecDeque::new(), primed: false }),
+            prime: per_ms * PRIME_MS as usize,
+            max: per_ms * MAX_BUFFER_MS as usize,
+        })
+    }
+
+    /// Append interleaved samples from the capture thread
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn push(&self, samples: &[f32]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.samples.extend(samples);
+        let excess = buffer.samples.len().saturating_sub(self.max);
+        buffer.samples.drain(..excess);
+    }
+
+    /// Append silence (packets the system flagged as silent)
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn push_silence(&self, len: usize) {
+        self.push(&vec![0.0; len]);
+    }
+
+    /// Add buffered audio onto `out` at `gain` (0 still drains, so re-including is instant)
+    fn mix_into(&self, out: &mut [f32], gain: f32) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if !buffer.primed {
+            if buffer.samples.len() < self.prime {
+                return;
+            }
+            buffer.primed = true;
+        }
+        let take = out.len().min(buffer.samples.len());
+        for (slot, sample) in out.iter_mut().zip(buffer.samples.drain(..take)) {
+            *slot += sample * gain;
+        }
+        if buffer.samples.is_empty() {
+            buffer.primed = false;
+        }
+    }
+}
+
+/// A running capture of one application
+struct Capture {
+    /// Executable name, the key of its rule
+    name: String,
+    track: Arc<Track>,
+    running: Arc<AtomicBool>,
+}
+
+type Captures = Arc<Mutex<HashMap<u32, Capture>>>;
+
+/// Which sessions go into the mix
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub exclude_system_sounds: bool,
+    /// Capture only this executable (lowercase), at full level whatever its rule says
+    pub only: Option<String>,
+}
+
+impl SessionFilter {
+    fn wants(&self, session: &SessionInfo) -> bool {
+        let selected = match &self.only {
+            Some(only) => session.name.eq_ignore_ascii_case(only),
+            None => true,
+        };
+        // Multi-process sessions have no single pid to capture; RustCast itself plays nothing
+        selected
+            && session.pid != 0
+            && session.pid != std::process::id()
+            && !(session.system && self.exclude_system_sounds)
+    }
+}
+
+/// The output device's applications mixed together, minus what the filter leaves out
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct SessionCapture {
+    filter: SessionFilter,
+    sample_rate: u32,
+    channels: u16,
+    running: Option<Running>,
+}
+
+struct Running {
+    mixer: PacedThread,
+    scanning: Arc<AtomicBool>,
+    scanner: Option<JoinHandle<()>>,
+    captures: Captures,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl SessionCapture {
+    /// Mix at the output device's rate, in stereo at most
+    pub fn new(filter: SessionFilter) -> Result<Self, Box<dyn std::error::Error>> {
+        let (sample_rate, channels) = platform::output_format()?;
+        Ok(Self { filter, sample_rate, channels: channels.min(2), running: None })
+    }
+}
+
+impl CaptureSource for SessionCapture {
+    fn describe(&self) -> String {
+        let scope = match &self.filter.only {
+            Some(only) => format!(" of {}", only),
+            None if self.filter.exclude_system_sounds => " without System Sounds".to_string(),
+            None => String::new(),
+        };
+        format!(
+            "per-application loopback{} ({}Hz, {}ch)",
+            scope,
+            self.sample_rate,
+            self.channels
+        )
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn start(&mut self, tx: PipelineSender<AudioSample>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.running.is_some() {
+            return Ok(());
+        }
+
+        let captures: Captures = Arc::new(Mutex::new(HashMap::new()));
+        let mix_from = captures.clone();
+        let use_rules = self.filter.only.is_none();
+        let mixer = PacedThread::spawn(self.sample_rate, self.channels, tx, move |out| {
+            let rules = RULES.lock().unwrap();
+            for capture in mix_from.lock().unwrap().values() {
+                let gain = match rules.get(&capture.name.to_lowercase()) {
+                    Some(mix) if use_rules => mix.gain(),
+                    _ => 1.0,
+                };
+                capture.track.mix_into(out, gain);
+            }
+        });
+
+        let scanning = Arc::new(AtomicBool::new(true));
+        let (flag, scan_into) = (scanning.clone(), captures.clone());
+        let (filter, sample_rate, channels) = (self.filter.clone(), self.sample_rate, self.channels);
+        let scanner = thread::Builder::new()
+            .name("sessions".into())
+            .spawn(move || {
+                while flag.load(Ordering::SeqCst) {
+                    scan(&filter, sample_rate, channels, &scan_into);
+                    thread::sleep(SCAN_INTERVAL);
+                }
+            })?;
+
+        self.running = Some(Running { mixer, scanning, scanner: Some(scanner), captures });
+        if use_rules {
+            CAPTURING.store(true, Ordering::SeqCst);
+        }
+        log::info!("Audio capture started");
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut running) = self.running.take() {
+            running.scanning.store(false, Ordering::SeqCst);
+            if let Some(scanner) = running.scanner.take() {
+                let _ = scanner.join();
+            }
+            for (_, capture) in running.captures.lock().unwrap().drain() {
+                capture.running.store(false, Ordering::SeqCst);
+            }
+            drop(running.mixer);
+            if self.filter.only.is_none() {
+                CAPTURING.store(false, Ordering::SeqCst);
+            }
+            log::info!("Audio capture stopped");
+        }
+    }
+}
+
+/// Start captures for new sessions, end those whose session is gone
+fn scan(filter: &SessionFilter, sample_rate: u32, channels: u16, captures: &Captures) {
+    let sessions = platform::sessions();
+    let mut captures = captures.lock().unwrap();
+
+    captures.retain(|pid, capture| {
+        let present = sessions.iter().any(|session| session.pid == *pid && filter.wants(session));
+        if !present {
+            capture.running.store(false, Ordering::SeqCst);
+            log::info!("[SESSIONS] Stopped capturing pid {}", pid);
+        }
+        present
+    });
+
+    for session in sessions.iter().filter(|session| filter.wants(session)) {
+        // A capture that failed or ended stays in the map, so it isn't retried until the session reappears
+        if captures.contains_key(&session.pid) {
+            continue;
+        }
+        let track = Track::new(sample_rate, channels);
+        let running = Arc::new(AtomicBool::new(true));
+        match platform::capture(session.pid, sample_rate, channels, track.clone(), running.clone()) {
+            Ok(()) => log::info!("[SESSIONS] Capturing {} (pid {})", session.name, session.pid),
+            Err(e) => log::warn!("[SESSIONS] Cannot capture {} (pid {}): {}", session.name, session.pid, e),
+        }
+        captures.insert(session.pid, Capture { name: session.name.clone(), track, running });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use windows::core::{Interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PROPVARIANT, PWSTR};
+    use windows::Win32::Foundation::{CloseHandle, E_FAIL, E_NOINTERFACE, S_OK};
+    use windows::Win32::Media::Audio::Endpoints::IAudioMeterInformation;
+    use windows::Win32::Media::Audio::{
+        eConsole, eRender, ActivateAudioInterfaceAsync, AudioSessionStateActive, AudioSessionStateExpired,
+        IActivateAudioInterfaceCompletionHandler, IActivateAudioInterfaceCompletionHandler_Vtbl, IAudioCaptureClient,
+        IAudioClient, IAudioSessionControl2, IAudioSessionManager2, IMMDeviceEnumerator, MMDeviceEnumerator,
+        AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY, AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_SHAREMODE_SHARED,
+        AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM, AUDCLNT_STREAMFLAGS_LOOPBACK, AUDIOCLIENT_ACTIVATION_PARAMS,
+        AUDIOCLIENT_ACTIVATION_PARAMS_0, AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK, AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS,
+        PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE, VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK, WAVEFORMATEX,
+    };
+    use windows::Win32::Media::Multimedia::WAVE_FORMAT_IEEE_FLOAT;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, IAgileObject, CLSCTX_ALL, COINIT_MULTITHREADED};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::System::Variant::VT_BLOB;
+
+    use super::{SessionInfo, Track};
+    use crate::glitches::{self, Glitch};
+
+    /// How often a capture thread drains its buffer
+    const POLL: Duration = Duration::from_millis(10);
+    /// Shared-mode buffer of a capture (100ns units)
+    const BUFFER_100NS: i64 = 2_000_000;
+    const ACTIVATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Rate and channels to capture sessions in: 48kHz whatever the device mixes at (process
+    /// loopback converts with AUTOCONVERTPCM, so Opus needs no resampling) and the default output
+    /// device's channels
+    pub fn output_format() -> Result<(u32, u16), Box<dyn std::error::Error>> {
+        use cpal::traits::{DeviceTrait, HostTrait};
+        let host = cpal::host_from_id(cpal::HostId::Wasapi)?;
+        let device = host.default_output_device().ok_or("No output device available")?;
+        let config = device.default_output_config()?;
+        Ok((48_000, config.channels()))
+    }
+
+    fn com_init() {
+        // Already initialized (or in another mode) is fine; COM stays usable on this thread
+        // SAFETY: no reserved pointer
+        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+    }
+
+    pub fn sessions() -> Vec<SessionInfo> {
+        com_init();
+        // SAFETY: COM is initialized on this thread; every interface is used on it only
+        match unsafe { list_sessions() } {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log::warn!("[SESSIONS] Listing failed: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    unsafe fn list_sessions() -> windows::core::Result<Vec<SessionInfo>> {
+        let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+        let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+        let sessions = manager.GetSessionEnumerator()?;
+
+        let mut list = Vec::new();
+        for index in 0..sessions.GetCount()? {
+            let control: IAudioSessionControl2 = sessions.GetSession(index)?.cast()?;
+            let state = control.GetState()?;
+            if state == AudioSessionStateExpired {
+                continue;
+            }
+            let pid = control.GetProcessId().unwrap_or(0);
+            let system = control.IsSystemSoundsSession() == S_OK;
+            let peak = control.cast::<IAudioMeterInformation>().and_then(|meter| meter.GetPeakValue()).unwrap_or(0.0);
+            list.push(SessionInfo {
+                pid,
+                name: if system { "System Sounds".to_string() } else { process_name(pid) },
+                system,
+                active: state == AudioSessionStateActive,
+                peak,
+            });
+        }
+        Ok(list)
+    }
+
+    /// Executable name of a process ("pid N" if it can't be opened)
+    fn process_name(pid: u32) -> String {
+        let fallback = || format!("pid {}", pid);
+        // SAFETY: the handle is closed below; the buffer outlives the call and its size is passed
+        unsafe {
+            let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+                return fallback();
+            };
+            let mut path = [0u16; 260];
+            let mut len = path.len() as u32;
+            let queried = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut len);
+            let _ = CloseHandle(process);
+            if queried.is_err() {
+                return fallback();
+            }
+            let path = String::from_utf16_lossy(&path[..len as usize]);
+            path.rsplit('\\').next().map(str::to_string).unwrap_or_else(fallback)
+        }
+    }
+
+    /// Start capturing `pid` and its child processes into `track` until `running` clears
+    pub fn capture(pid: u32, sample_rate: u32, channels: u16, track: Arc<Track>, running: Arc<AtomicBool>) -> Result<(), String> {
+        thread::Builder::new()
+            .name(format!("session-{}", pid))
+            .spawn(move || {
+                com_init();
+                // SAFETY: COM is initialized on this thread; the client never leaves it
+                if let Err(e) = unsafe { run_capture(pid, sample_rate, channels, &track, &running) } {
+                    log::warn!("[SESSIONS] Capture of pid {} ended: {}", pid, e);
+                }
+            })
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    unsafe fn run_capture(pid: u32, sample_rate: u32, channels: u16, track: &Track, running: &AtomicBool) -> windows::core::Result<()> {
+        let client = activate(pid)?;
+        let block = channels * 4;
+        let format = WAVEFORMATEX {
+            wFormatTag: WAVE_FORMAT_IEEE_FLOAT as u16,
+            nChannels: channels,
+            nSamplesPerSec: sample_rate,
+            nAvgBytesPerSec: sample_rate * block as u32,
+            nBlockAlign: block,
+            wBitsPerSample: 32,
+            cbSize: 0,
+        };
+        client.Initialize(
+            AUDCLNT_SHAREMODE_SHARED,
+            AUDCLNT_STREAMFLAGS_LOOPBACK | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+            BUFFER_100NS,
+            0,
+            &format,
+            None,
+        )?;
+        let capture: IAudioCaptureClient = client.GetService()?;
+        client.Start()?;
+
+        // Device position (frames) the next packet should start at
+        let mut next: Option<u64> = None;
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(POLL);
+            while capture.GetNextPacketSize()? > 0 {
+                let mut data = std::ptr::null_mut();
+                let mut frames = 0u32;
+                let mut flags = 0u32;
+                let mut position = 0u64;
+                capture.GetBuffer(&mut data, &mut frames, &mut flags, Some(&mut position), None)?;
+                // The first packet is always flagged
+                if let Some(expected) = next.filter(|_| flags & AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0) {
+                    glitches::record(Glitch::Gap);
+                    let missing = position.saturating_sub(expected);
+                    log::debug!("[SESSIONS] Capture discontinuity in pid {} ({} frames missing)", pid, missing);
+                    let gap = Duration::from_secs_f64(missing as f64 / sample_rate as f64);
+                    if let Some(len) = glitches::fill_len(gap, sample_rate, channels).filter(|len| *len > 0) {
+                        track.push_silence(len);
+                    }
+                }
+                next = Some(position + frames as u64);
+                let len = frames as usize * channels as usize;
+                if data.is_null() || flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 {
+                    track.push_silence(len);
+                } else {
+                    track.push(std::slice::from_raw_parts(data as *const f32, len));
+                }
+                capture.ReleaseBuffer(frames)?;
+            }
+        }
+        client.Stop()
+    }
+
+    /// PROPVARIANT holding a VT_BLOB, the only shape ActivateAudioInterfaceAsync reads
+    #[repr(C)]
+    struct BlobVariant {
+        vt: u16,
+        reserved: [u16; 3],
+        size: u32,
+        data: *const u8,
+    }
+
+    /// Process loopback client for `pid` and its children
+    unsafe fn activate(pid: u32) -> windows::core::Result<IAudioClient> {
+        let params = AUDIOCLIENT_ACTIVATION_PARAMS {
+            ActivationType: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+            Anonymous: AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+                ProcessLoopbackParams: AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                    TargetProcessId: pid,
+                    ProcessLoopbackMode: PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE,
+                },
+            },
+        };
+        let variant = BlobVariant {
+            vt: VT_BLOB.0,
+            reserved: [0; 3],
+            size: std::mem::size_of::<AUDIOCLIENT_ACTIVATION_PARAMS>() as u32,
+            data: &params as *const _ as *const u8,
+        };
+
+        let (done_tx, done_rx) = crossbeam_channel::bounded(1);
+        let handler = completion_handler(done_tx);
+        let operation = ActivateAudioInterfaceAsync(
+            VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+            &IAudioClient::IID,
+            Some(&variant as *const BlobVariant as *const PROPVARIANT),
+            &handler,
+        )?;
+        done_rx.recv_timeout(ACTIVATE_TIMEOUT).map_err(|_| windows::core::Error::from(E_FAIL))?;
+
+        let mut result = HRESULT(0);
+        let mut interface: Option<IUnknown> = None;
+        operation.GetActivateResult(&mut result, &mut interface)?;
+        result.ok()?;
+        interface.ok_or_else(|| windows::core::Error::from(E_NOINTERFACE))?.cast()
+    }
+
+    /// Minimal agile COM object that signals when activation completes
+    #[repr(C)]
+    struct Completion {
+        vtable: *const IActivateAudioInterfaceCompletionHandler_Vtbl,
+        refs: AtomicU32,
+        done: crossbeam_channel::Sender<()>,
+    }
+
+    static COMPLETION_VTABLE: IActivateAudioInterfaceCompletionHandler_Vtbl = IActivateAudioInterfaceCompletionHandler_Vtbl {
+        base__: IUnknown_Vtbl {
+            QueryInterface: completion_query_interface,
+            AddRef: completion_add_ref,
+            Release: completion_release,
+        },
+        ActivateCompleted: completion_activate_completed,
+    };
+
+    fn completion_handler(done: crossbeam_channel::Sender<()>) -> IActivateAudioInterfaceCompletionHandler {
+        let completion = Box::new(Completion { vtable: &COMPLETION_VTABLE, refs: AtomicU32::new(1), done });
+        // SAFETY: `Completion` starts with the vtable pointer and owns one reference
+        unsafe { IActivateAudioInterfaceCompletionHandler::from_raw(Box::into_raw(completion) as *mut c_void) }
+    }
+
+    unsafe extern "system" fn completion_query_interface(this: *mut c_void, iid: *const GUID, out: *mut *mut c_void) -> HRESULT {
+        let iid = *iid;
+        if iid == IUnknown::IID || iid == IActivateAudioInterfaceCompletionHandler::IID || iid == IAgileObject::IID {
+            completion_add_ref(this);
+            *out = this;
+            S_OK
+        } else {
+            *out = std::ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn completion_add_ref(this: *mut c_void) -> u32 {
+        (*(this as *const Completion)).refs.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    unsafe extern "system" fn completion_release(this: *mut c_void) -> u32 {
+        let left = (*(this as *const Completion)).refs.fetch_sub(1, Ordering::SeqCst) - 1;
+        if left == 0 {
+            drop(Box::from_raw(this as *mut Completion));
+        }
+        left
+    }
+
+    unsafe extern "system" fn completion_activate_completed(this: *mut c_void, _operation: *mut c_void) -> HRESULT {
+        let _ = (*(this as *const Completion)).done.try_send(());
+        S_OK
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use super::{SessionInfo, Track};
+
+    pub fn output_format() -> Result<(u32, u16), Box<dyn std::error::Error>> {
+        Err("Per-application capture needs Windows 10 (2004) or later".into())
+    }
+
+    pub fn sessions() -> Vec<SessionInfo> {
+        Vec::new()
+    }
+
+    pub fn capture(_pid: u32, _sample_rate: u32, _channels: u16, _track: Arc<Track>, _running: Arc<AtomicBool>) -> Result<(), String> {
+        Err("not supported".into())
+    }
+}
@@ -0,0 +1,177 @@
+//! Graceful shutdown
+//! One path for Quit, Ctrl+C and service stops: fade out, let the encoders drain, then end recordings and client streams
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hub::{self, BroadcastHub};
+use crate::sink::SinkRegistry;
+
+/// Longest wait for capture to fade out and stop
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+/// Time for the encoder thread to encode and publish the last captured audio
+const DRAIN: Duration = Duration::from_millis(200);
+/// Longest wait for recordings to write their last page
+const SINK_TIMEOUT: Duration = Duration::from_secs(3);
+/// Longest wait for listeners to get their last bytes (a stalled client doesn't hold us up)
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Set by a console/signal handler; the watcher thread does the actual work
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Set once the shutdown finished (Windows close events wait for it before returning)
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+pub struct Shutdown {
+    should_stream: Arc<AtomicBool>,
+    is_streaming: Arc<AtomicBool>,
+    app_quit: Arc<AtomicBool>,
+    hub: Arc<BroadcastHub>,
+    sinks: Arc<SinkRegistry>,
+    started: AtomicBool,
+}
+
+impl Shutdown {
+    pub fn new(
+        should_stream: Arc<AtomicBool>,
+        is_streaming: Arc<AtomicBool>,
+        app_quit: Arc<AtomicBool>,
+        hub: Arc<BroadcastHub>,
+        sinks: Arc<SinkRegistry>,
+    ) -> Arc<Self> {
+        Arc::new(Self { should_stream, is_streaming, app_quit, hub, sinks, started: AtomicBool::new(false) })
+    }
+
+    /// Stop streaming, close every output, then set `app_quit`.
+    /// A second call (Quit during a Ctrl+C shutdown) waits for the first to finish.
+    pub fn run(&self) {
+        if self.started.swap(true, Ordering::SeqCst) {
+            wait_until(STOP_TIMEOUT + SINK_TIMEOUT + CLIENT_TIMEOUT + DRAIN, || FINISHED.load(Ordering::SeqCst));
+            return;
+        }
+        log::info!("[SHUTDOWN] Stopping the stream...");
+
+        // The audio thread fades out and stops capture; what was captured still gets encoded
+        self.should_stream.store(false, Ordering::SeqCst);
+        if !wait_until(STOP_TIMEOUT, || !self.is_streaming.load(Ordering::SeqCst)) {
+            log::warn!("[SHUTDOWN] Capture did not stop in time");
+        }
+        thread::sleep(DRAIN);
+
+        // Recordings write their end-of-stream page and flush
+        self.sinks.stop_all(SINK_TIMEOUT);
+
+        // Listeners get the end of their stream (Ogg EOS, WebSocket close) instead of a dead socket
+        let clients = self.hub.disconnect_all();
+        if clients > 0 {
+            log::info!("[SHUTDOWN] Closing {} client stream(s)", clients);
+            if !wait_until(CLIENT_TIMEOUT, || hub::pumping() == 0) {
+                log::warn!("[SHUTDOWN] {} client(s) did not take their last bytes", hub::pumping());
+            }
+        }
+
+        self.app_quit.store(true, Ordering::SeqCst);
+        FINISHED.store(true, Ordering::SeqCst);
+        log::info!("[SHUTDOWN] Done");
+    }
+}
+
+/// Poll `done` until it holds or `timeout` passes. Returns whether it held.
+fn wait_until(timeout: Duration, done: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !done() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    true
+}
+
+/// Run `shutdown` on Ctrl+C and SIGTERM (console close, logoff and system shutdown on Windows).
+/// A second Ctrl+C while shutting down exits at once.
+pub fn handle_signals(shutdown: Arc<Shutdown>) {
+    if let Err(e) = platform::install() {
+        log::warn!("[SHUTDOWN] Could not install the stop handler: {}", e);
+        return;
+    }
+    let _ = thread::Builder::new().name("shutdown".into()).spawn(move || {
+        while !REQUESTED.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(100));
+        }
+        log::info!("[SHUTDOWN] Stop requested");
+        shutdown.run();
+    });
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use winapi::shared::minwindef::{BOOL, DWORD, TRUE};
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    /// Windows ends the process 5s after a close/logoff/shutdown event; return just before
+    const CLOSE_WAIT: Duration = Duration::from_millis(4500);
+
+    /// Runs on a thread the console creates for the event
+    unsafe extern "system" fn on_event(event: DWORD) -> BOOL {
+        let again = super::REQUESTED.swap(true, Ordering::SeqCst);
+        match event {
+            CTRL_C_EVENT | CTRL_BREAK_EVENT => {
+                if again {
+                    std::process::exit(130);
+                }
+            }
+            // The process ends as soon as this returns: finish the shutdown first
+            _ => {
+                super::wait_until(CLOSE_WAIT, || super::FINISHED.load(Ordering::SeqCst));
+            }
+        }
+        TRUE
+    }
+
+    pub fn install() -> Result<(), String> {
+        // SAFETY: `on_event` has the HandlerRoutine signature and lives for the whole process
+        if unsafe { SetConsoleCtrlHandler(Some(on_event), TRUE) } == 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    /// `SIG_ERR`
+    const SIGNAL_FAILED: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    /// Only async-signal-safe work here: flip the flag the watcher thread polls
+    extern "C" fn on_signal(_: c_int) {
+        if super::REQUESTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { _exit(130) };
+        }
+    }
+
+    pub fn install() -> Result<(), String> {
+        for signum in [SIGINT, SIGTERM] {
+            // SAFETY: `on_signal` only touches an atomic
+            if unsafe { signal(signum, on_signal) } == SIGNAL_FAILED {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+        }
+        Ok(())
+    }
+}
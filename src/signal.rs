@@ -0,0 +1,111 @@
+//! Voice/music detection
+//! Low-energy frame ratio and spectral tilt over one-second windows, used to pick the Opus signal hint
+
+/// Frames per analysis window (1s of 20ms frames)
+const WINDOW_FRAMES: usize = 50;
+/// Windows quieter than this mean power (~-60 dBFS) keep the current decision
+const SILENCE_POWER: f32 = 1e-6;
+/// Speech pauses between syllables, so many of its frames sit below half the window's mean
+/// energy; music rarely does
+const VOICE_LOW_ENERGY_RATIO: f32 = 0.35;
+/// Normalized first-difference energy (0 = DC, 1 = Nyquist). Hiss and dense cymbals are above it
+const VOICE_TILT_MAX: f32 = 0.1;
+/// Weight of the newest window's vote (the decision follows over ~3-6s)
+const VOTE_SMOOTHING: f32 = 0.2;
+/// Confidence needed to switch to voice, and below which it switches back to music
+const VOICE_ON: f32 = 0.7;
+const VOICE_OFF: f32 = 0.3;
+
+/// What the audio sounds like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalKind {
+    Voice,
+    Music,
+}
+
+impl SignalKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalKind::Voice => "voice",
+            SignalKind::Music => "music",
+        }
+    }
+}
+
+/// Classifies encoder frames as voice or music, with hysteresis
+#[derive(Debug)]
+pub struct SignalDetector {
+    channels: usize,
+    /// Mean power of each frame in the current window
+    energies: Vec<f32>,
+    /// Summed power of the signal and of its first difference in the current window
+    power: f32,
+    diff_power: f32,
+    /// Last mono sample of the previous frame
+    previous: f32,
+    /// 0 = surely music, 1 = surely voice
+    confidence: f32,
+    current: Option<SignalKind>,
+}
+
+impl SignalDetector {
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            energies: Vec::with_capacity(WINDOW_FRAMES),
+            power: 0.0,
+            diff_power: 0.0,
+            previous: 0.0,
+            confidence: 0.5,
+            current: None,
+        }
+    }
+
+    /// Feed one frame of interleaved samples. Returns the new kind when the decision changes.
+    pub fn push(&mut self, frame: &[i16]) -> Option<SignalKind> {
+        let scale = 1.0 / (self.channels as f32 * 32768.0);
+        let mut power = 0.0;
+        let mut frames = 0usize;
+        for chunk in frame.chunks_exact(self.channels) {
+            let sample = chunk.iter().map(|&s| s as f32).sum::<f32>() * scale;
+            power += sample * sample;
+            self.diff_power += (sample - self.previous) * (sample - self.previous);
+            self.previous = sample;
+            frames += 1;
+        }
+        if frames == 0 {
+            return None;
+        }
+        self.power += power;
+        self.energies.push(power / frames as f32);
+        if self.energies.len() < WINDOW_FRAMES {
+            return None;
+        }
+
+        let mean = self.energies.iter().sum::<f32>() / self.energies.len() as f32;
+        let low_energy = self.energies.iter().filter(|&&e| e < mean * 0.5).count() as f32 / self.energies.len() as f32;
+        // A first difference has at most 4x the power (at Nyquist)
+        let tilt = if self.power > 0.0 { self.diff_power / (4.0 * self.power) } else { 0.0 };
+        self.energies.clear();
+        self.power = 0.0;
+        self.diff_power = 0.0;
+        if mean < SILENCE_POWER {
+            return None;
+        }
+
+        let vote = if low_energy >= VOICE_LOW_ENERGY_RATIO && tilt <= VOICE_TILT_MAX { 1.0 } else { 0.0 };
+        self.confidence += (vote - self.confidence) * VOTE_SMOOTHING;
+        let next = if self.confidence > VOICE_ON {
+            SignalKind::Voice
+        } else if self.confidence < VOICE_OFF {
+            SignalKind::Music
+        } else {
+            return None;
+        };
+        if self.current == Some(next) {
+            return None;
+        }
+        self.current = Some(next);
+        Some(next)
+    }
+}
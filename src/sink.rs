@@ -0,0 +1,483 @@
+//! Server-side output sinks
+//! StreamSink trait and a registry that feeds each registered sink from the hub on its own thread
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::{Segment, SinkConfig};
+use crate::hub::{BroadcastHub, Outgoing, Packet, SinkAdapter, SinkKind};
+use crate::server::{OggSink, OpusStreamInfo};
+use crate::station::Station;
+
+/// An output that consumes the encoded stream (recorder, push to another server, ...)
+///
+/// Listener connections (WebSocket, HTTP) are per-client and stay in server.rs.
+pub trait StreamSink: Send {
+    /// Short type name shown in /api/sinks ("file", ...)
+    fn kind(&self) -> &'static str;
+
+    /// Open the output. Called on the sink's thread before the first packet.
+    fn start(&mut self) -> Result<(), String>;
+
+    /// Write one encoded Opus packet
+    fn send(&mut self, packet: &Packet) -> Result<(), String>;
+
+    /// Flush and close the output
+    fn stop(&mut self);
+
+    /// Sink-specific counters for /api/sinks
+    fn stats(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// Record a cue point at the current position. Returns false if the sink keeps no cues.
+    fn cue(&mut self, _label: &str) -> bool {
+        false
+    }
+}
+
+struct RegisteredSink {
+    subscriber_id: u64,
+    sink: Arc<Mutex<Box<dyn StreamSink>>>,
+    running: Arc<AtomicBool>,
+}
+
+/// Named sinks that can be added and removed while streaming
+pub struct SinkRegistry {
+    hub: Arc<BroadcastHub>,
+    info: OpusStreamInfo,
+    /// Station metadata written to the OpusTags of new recordings
+    station: Arc<Station>,
+    sinks: Mutex<BTreeMap<String, RegisteredSink>>,
+}
+
+impl SinkRegistry {
+    pub fn new(hub: Arc<BroadcastHub>, info: OpusStreamInfo, station: Arc<Station>) -> Arc<Self> {
+        Arc::new(Self {
+            hub,
+            info,
+            station,
+            sinks: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Build a sink from its config and register it
+    pub fn add_from_config(&self, config: &SinkConfig) -> Result<(), String> {
+        let sink: Box<dyn StreamSink> = match config {
+            SinkConfig::File { path, .. } => Box::new(FileRecorder::new(path, self.info.clone(), self.station.clone())),
+            SinkConfig::Archive { dir, segment, retention_days, only_with_listeners, .. } => {
+                Box::new(ArchiveRecorder::new(
+                    dir,
+                    *segment,
+                    *retention_days,
+                    only_with_listeners.then(|| self.hub.clone()),
+                    self.info.clone(),
+                    self.station.clone(),
+                ))
+            }
+        };
+        self.add(config.name(), sink)
+    }
+
+    /// Register a sink under `name` and start feeding it
+    pub fn add(&self, name: &str, sink: Box<dyn StreamSink>) -> Result<(), String> {
+        let mut sinks = self.sinks.lock().unwrap();
+        if name.is_empty() || sinks.contains_key(name) {
+            return Err(format!("Sink name {:?} is empty or already used", name));
+        }
+
+        let subscription = self.hub.subscribe(SinkKind::Output, None);
+        let sink = Arc::new(Mutex::new(sink));
+        let running = Arc::new(AtomicBool::new(true));
+        sinks.insert(name.to_string(), RegisteredSink {
+            subscriber_id: subscription.id,
+            sink: sink.clone(),
+            running: running.clone(),
+        });
+
+        let name = name.to_string();
+        thread::spawn(move || {
+            if let Err(e) = sink.lock().unwrap().start() {
+                log::error!("[SINK] {} failed to start: {}", name, e);
+                running.store(false, Ordering::SeqCst);
+                return;
+            }
+            log::info!("[SINK] {} started", name);
+
+            while let Some(item) = subscription.recv() {
+                let Outgoing::Audio(packet) = item else { continue };
+                if let Err(e) = sink.lock().unwrap().send(&packet) {
+                    log::error!("[SINK] {} failed: {}", name, e);
+                    break;
+                }
+            }
+
+            sink.lock().unwrap().stop();
+            running.store(false, Ordering::SeqCst);
+            log::info!("[SINK] {} stopped", name);
+        });
+
+        Ok(())
+    }
+
+    /// Stop and unregister a sink. Returns false if there is no such sink.
+    pub fn remove(&self, name: &str) -> bool {
+        match self.sinks.lock().unwrap().remove(name) {
+            Some(entry) => {
+                // Ending the subscription lets the sink thread drain and call stop()
+                self.hub.disconnect(entry.subscriber_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every sink and wait (up to `timeout`) for them to close their outputs
+    pub fn stop_all(&self, timeout: Duration) {
+        let stopping: Vec<_> = std::mem::take(&mut *self.sinks.lock().unwrap()).into_values().collect();
+        for entry in &stopping {
+            self.hub.disconnect(entry.subscriber_id);
+        }
+        let deadline = Instant::now() + timeout;
+        while stopping.iter().any(|entry| entry.running.load(Ordering::SeqCst)) {
+            if Instant::now() >= deadline {
+                log::warn!("[SINK] Gave up waiting for sinks to close");
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Pass a cue point to every sink. Returns how many recorded it.
+    pub fn cue(&self, label: &str) -> usize {
+        let sinks = self.sinks.lock().unwrap();
+        sinks
+            .values()
+            .filter(|entry| entry.sink.lock().unwrap().cue(label))
+            .count()
+    }
+
+    /// Registered sinks and their state
+    pub fn list(&self) -> serde_json::Value {
+        let sinks = self.sinks.lock().unwrap();
+        sinks
+            .iter()
+            .map(|(name, entry)| {
+                let sink = entry.sink.lock().unwrap();
+                serde_json::json!({
+                    "name": name,
+                    "kind": sink.kind(),
+                    "running": entry.running.load(Ordering::SeqCst),
+                    "stats": sink.stats(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// An open Ogg/Opus file with its own logical stream
+struct OggFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    ogg: OggSink,
+    /// Audio written by this session (for cue positions)
+    packets: u64,
+    packet_secs: f64,
+}
+
+impl OggFile {
+    /// Create (or append a new chained stream to) `path` and write the Opus headers
+    fn open(path: PathBuf, info: &OpusStreamInfo, station: &Station, append: bool) -> Result<(Self, u64), String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        // Same Ogg framing as an HTTP listener, without the HTTP head
+        let mut ogg = OggSink::new(info, Vec::new(), station.get().vorbis_comments());
+        let headers = ogg.preamble();
+        writer.write_all(&headers).map_err(|e| e.to_string())?;
+
+        let packet_secs = info.frame_size as f64 / info.sample_rate.max(1) as f64;
+        log::info!("[SINK] Recording to {}", path.display());
+        Ok((Self { path, writer, ogg, packets: 0, packet_secs }, headers.len() as u64))
+    }
+
+    fn write(&mut self, packet: &Packet) -> Result<u64, String> {
+        let page = self.ogg.wrap(packet);
+        self.writer.write_all(&page).map_err(|e| e.to_string())?;
+        self.packets += 1;
+        Ok(page.len() as u64)
+    }
+
+    /// Append a cue to the sidecar label file (Audacity label track format).
+    /// Positions count from the start of this session's stream; the label carries the UTC time
+    /// so cues stay unambiguous in appended (chained) files.
+    fn cue(&self, label: &str) -> bool {
+        let position = self.packets as f64 * self.packet_secs;
+        let label: String = label.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        let label = format!("{} {}", utc_timestamp(SystemTime::now()), label);
+        let path = cue_path(&self.path);
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{:.3}\t{:.3}\t{}", position, position, label));
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("[SINK] Failed to write cue to {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    /// End the logical stream (EOS page) and flush the file
+    fn close(mut self) {
+        let eos = self.ogg.finish();
+        if let Err(e) = self.writer.write_all(&eos).and_then(|()| self.writer.flush()) {
+            log::error!("[SINK] Failed to finish {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Records the stream to an Ogg/Opus file
+pub struct FileRecorder {
+    /// Path template (`{time}` is replaced with the UTC start time)
+    path: String,
+    info: OpusStreamInfo,
+    station: Arc<Station>,
+    current: Option<OggFile>,
+    bytes_written: u64,
+}
+
+impl FileRecorder {
+    pub fn new(path: &str, info: OpusStreamInfo, station: Arc<Station>) -> Self {
+        Self {
+            path: path.to_string(),
+            info,
+            station,
+            current: None,
+            bytes_written: 0,
+        }
+    }
+}
+
+impl StreamSink for FileRecorder {
+    fn kind(&self) -> &'static str {
+        "file"
+    }
+
+    fn start(&mut self) -> Result<(), String> {
+        let path = PathBuf::from(self.path.replace("{time}", &utc_timestamp(SystemTime::now())));
+        let (file, written) = OggFile::open(path, &self.info, &self.station, false)?;
+        self.bytes_written = written;
+        self.current = Some(file);
+        Ok(())
+    }
+
+    fn send(&mut self, packet: &Packet) -> Result<(), String> {
+        let Some(file) = &mut self.current else {
+            return Err("Recorder is not started".to_string());
+        };
+        self.bytes_written += file.write(packet)?;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(file) = self.current.take() {
+            file.close();
+        }
+    }
+
+    fn stats(&self) -> serde_json::Value {
+        serde_json::json!({
+            "path": self.current.as_ref().map(|file| file.path.display().to_string()),
+            "bytes_written": self.bytes_written,
+        })
+    }
+
+    fn cue(&mut self, label: &str) -> bool {
+        self.current.as_ref().is_some_and(|file| file.cue(label))
+    }
+}
+
+/// Continuous aircheck recording split into hourly or daily files, with old files pruned
+pub struct ArchiveRecorder {
+    dir: PathBuf,
+    segment: Segment,
+    retention_days: u32,
+    /// Pause recording (close the file) while nobody is listening
+    only_with_listeners: Option<Arc<BroadcastHub>>,
+    info: OpusStreamInfo,
+    station: Arc<Station>,
+    /// Open file and the segment it belongs to
+    current: Option<(String, OggFile)>,
+    bytes_written: u64,
+    files_pruned: u64,
+}
+
+impl ArchiveRecorder {
+    pub fn new(
+        dir: &str,
+        segment: Segment,
+        retention_days: u32,
+        only_with_listeners: Option<Arc<BroadcastHub>>,
+        info: OpusStreamInfo,
+        station: Arc<Station>,
+    ) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            segment,
+            retention_days,
+            only_with_listeners,
+            info,
+            station,
+            current: None,
+            bytes_written: 0,
+            files_pruned: 0,
+        }
+    }
+
+    /// File name for the segment that contains `time`
+    fn segment_name(&self, time: SystemTime) -> String {
+        let stamp = utc_timestamp(time);
+        match self.segment {
+            // "YYYYMMDD"
+            Segment::Daily => format!("{}{}.opus", ARCHIVE_PREFIX, &stamp[..8]),
+            // "YYYYMMDD-HH"
+            Segment::Hourly => format!("{}{}.opus", ARCHIVE_PREFIX, &stamp[..11]),
+        }
+    }
+
+    /// Delete archive files older than the retention period
+    fn prune(&mut self) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let max_age = Duration::from_secs(u64::from(self.retention_days) * 86_400);
+        let Ok(entries) = std::fs::read_dir(&self.dir) else { return };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(ARCHIVE_PREFIX) || !name.ends_with(".opus") {
+                continue;
+            }
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > max_age);
+            if expired {
+                match std::fs::remove_file(entry.path()) {
+                    Ok(()) => {
+                        self.files_pruned += 1;
+                        log::info!("[SINK] Archive: removed {}", name);
+                    }
+                    Err(e) => log::warn!("[SINK] Archive: failed to remove {}: {}", name, e),
+                }
+            }
+        }
+    }
+}
+
+/// Archive file names start with this, retention only touches such files
+const ARCHIVE_PREFIX: &str = "rustcast-";
+
+impl StreamSink for ArchiveRecorder {
+    fn kind(&self) -> &'static str {
+        "archive"
+    }
+
+    fn start(&mut self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+        self.prune();
+        Ok(())
+    }
+
+    fn send(&mut self, packet: &Packet) -> Result<(), String> {
+        if let Some(hub) = &self.only_with_listeners {
+            if hub.client_counts().total() == 0 {
+                self.stop();
+                return Ok(());
+            }
+        }
+
+        let segment = self.segment_name(SystemTime::now());
+        if self.current.as_ref().map(|(name, _)| name) != Some(&segment) {
+            self.stop();
+            // A segment that was paused (no listeners) continues as a chained Ogg stream
+            let (file, written) = OggFile::open(self.dir.join(&segment), &self.info, &self.station, true)?;
+            self.bytes_written += written;
+            self.current = Some((segment, file));
+            self.prune();
+        }
+
+        if let Some((_, file)) = &mut self.current {
+            self.bytes_written += file.write(packet)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some((_, file)) = self.current.take() {
+            file.close();
+        }
+    }
+
+    fn stats(&self) -> serde_json::Value {
+        serde_json::json!({
+            "path": self.current.as_ref().map(|(_, file)| file.path.display().to_string()),
+            "bytes_written": self.bytes_written,
+            "files_pruned": self.files_pruned,
+        })
+    }
+
+    fn cue(&mut self, label: &str) -> bool {
+        self.current.as_ref().is_some_and(|(_, file)| file.cue(label))
+    }
+}
+
+/// Cue sidecar of a recording: "rec.opus" -> "rec.labels.txt"
+fn cue_path(recording: &std::path::Path) -> PathBuf {
+    recording.with_extension("labels.txt")
+}
+
+/// "YYYYMMDD-HHMMSS" in UTC, safe for file names
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
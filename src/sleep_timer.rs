@@ -0,0 +1,76 @@
+//! Per-listener sleep timers
+//! Players ask to be disconnected after a while; the player fades out, the server enforces the cut-off
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hub::BroadcastHub;
+
+/// Longest sleep timer a player may request
+pub const MAX_SLEEP: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Sleep deadlines keyed by hub subscriber id
+pub struct SleepTimers {
+    hub: Arc<BroadcastHub>,
+    deadlines: Mutex<HashMap<u64, Instant>>,
+}
+
+impl SleepTimers {
+    /// Create the timers and start the enforcement thread
+    pub fn spawn(hub: Arc<BroadcastHub>) -> Arc<Self> {
+        let timers = Arc::new(Self {
+            hub,
+            deadlines: Mutex::new(HashMap::new()),
+        });
+
+        let worker = timers.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            worker.expire();
+        });
+
+        timers
+    }
+
+    /// Disconnect `subscriber_id` after `after`, or cancel its timer when `None`
+    pub fn set(&self, subscriber_id: u64, after: Option<Duration>) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        match after {
+            Some(after) => {
+                let after = after.min(MAX_SLEEP);
+                log::info!("[SLEEP] Client #{} will be disconnected in {}s", subscriber_id, after.as_secs());
+                deadlines.insert(subscriber_id, Instant::now() + after);
+            }
+            None => {
+                if deadlines.remove(&subscriber_id).is_some() {
+                    log::info!("[SLEEP] Client #{} cancelled its sleep timer", subscriber_id);
+                }
+            }
+        }
+    }
+
+    fn expire(&self) {
+        let now = Instant::now();
+        let due: Vec<u64> = {
+            let mut deadlines = self.deadlines.lock().unwrap();
+            let due = deadlines
+                .iter()
+                .filter(|(_, deadline)| **deadline <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            // Forget expired timers and timers of clients that left for good
+            deadlines.retain(|id, deadline| *deadline > now && self.hub.is_alive(*id));
+            due
+        };
+
+        for id in due {
+            // Tell the player to stop instead of reconnecting, then close the stream
+            self.hub.send_text_to(id, r#"{"type":"sleep"}"#);
+            if self.hub.disconnect(id) {
+                log::info!("[SLEEP] Client #{} sleep timer expired", id);
+            }
+        }
+    }
+}
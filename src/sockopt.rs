@@ -0,0 +1,105 @@
+//! Socket options
+//! TCP_NODELAY and send buffer sizes for listener sockets, and the values the OS actually applied
+
+use serde::Serialize;
+use socket2::SockRef;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::SocketConfig;
+
+/// Connection type the options are chosen for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    /// Player ports served by tiny_http (WebSocket and HTTP streams share a listener)
+    Web,
+    /// Raw TCP receivers on `embedded_port`
+    Embedded,
+}
+
+/// Options read back from a socket (send buffer as reported by the OS; Linux reports double)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Effective {
+    pub nodelay: bool,
+    pub send_buffer: usize,
+    /// SO_SNDTIMEO in ms (0 = writes may block forever)
+    pub write_timeout_ms: u64,
+}
+
+/// Configured options plus what each listener ended up with
+pub struct SocketOptions {
+    config: SocketConfig,
+    web: Mutex<Option<Effective>>,
+    embedded: Mutex<Option<Effective>>,
+}
+
+impl SocketOptions {
+    pub fn new(config: &SocketConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            web: Mutex::new(None),
+            embedded: Mutex::new(None),
+        })
+    }
+
+    /// Bind a listener with the options for `kind`. tiny_http never hands out the accepted
+    /// sockets, so the options go on the listener, which accepted connections inherit.
+    pub fn bind(&self, addr: impl ToSocketAddrs, kind: SocketKind) -> std::io::Result<TcpListener> {
+        let listener = TcpListener::bind(addr)?;
+        self.apply(SockRef::from(&listener), kind);
+        Ok(listener)
+    }
+
+    /// Apply the options to a connection we accepted ourselves
+    pub fn apply_stream(&self, stream: &TcpStream, kind: SocketKind) {
+        self.apply(SockRef::from(stream), kind);
+    }
+
+    fn apply(&self, socket: SockRef<'_>, kind: SocketKind) {
+        let send_buffer_kb = match kind {
+            SocketKind::Web => self.config.web_send_buffer_kb,
+            SocketKind::Embedded => self.config.embedded_send_buffer_kb,
+        };
+        if let Err(e) = socket.set_nodelay(self.config.nodelay) {
+            log::warn!("[SOCKET] TCP_NODELAY not set: {}", e);
+        }
+        if send_buffer_kb > 0 {
+            if let Err(e) = socket.set_send_buffer_size(send_buffer_kb as usize * 1024) {
+                log::warn!("[SOCKET] SO_SNDBUF of {} KB not set: {}", send_buffer_kb, e);
+            }
+        }
+        // Listeners that stop reading but keep the connection open fail their next write
+        // after this long, instead of pinning their thread forever
+        if self.config.write_timeout_secs > 0 {
+            let timeout = Duration::from_secs(self.config.write_timeout_secs as u64);
+            if let Err(e) = socket.set_write_timeout(Some(timeout)) {
+                log::warn!("[SOCKET] SO_SNDTIMEO of {}s not set: {}", self.config.write_timeout_secs, e);
+            }
+        }
+
+        let effective = Effective {
+            nodelay: socket.nodelay().unwrap_or(false),
+            send_buffer: socket.send_buffer_size().unwrap_or(0),
+            write_timeout_ms: socket.write_timeout().ok().flatten().map_or(0, |t| t.as_millis() as u64),
+        };
+        let mut slot = match kind {
+            SocketKind::Web => self.web.lock().unwrap(),
+            SocketKind::Embedded => self.embedded.lock().unwrap(),
+        };
+        if slot.replace(effective) != Some(effective) {
+            log::debug!(
+                "[SOCKET] {:?}: nodelay={}, send buffer={} bytes, write timeout={}ms",
+                kind, effective.nodelay, effective.send_buffer, effective.write_timeout_ms
+            );
+        }
+    }
+
+    /// Effective options of a connection type (None = no such listener)
+    pub fn effective(&self, kind: SocketKind) -> Option<Effective> {
+        match kind {
+            SocketKind::Web => *self.web.lock().unwrap(),
+            SocketKind::Embedded => *self.embedded.lock().unwrap(),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Spectrum analyzer
+//! Band levels of the outgoing audio, pushed to players so visualizers don't have to analyze themselves
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::SpectrumConfig;
+use crate::dsp::AudioProcessor;
+use crate::hub::BroadcastHub;
+
+/// FFT length (about 43ms at 48kHz, 23Hz per bin)
+const FFT_SIZE: usize = 2048;
+const LOWEST_HZ: f32 = 20.0;
+const HIGHEST_HZ: f32 = 20_000.0;
+/// Band levels map -80..0 dBFS onto 0..255
+const RANGE_DB: f32 = 80.0;
+
+/// Runtime switch, shared with the control API
+#[derive(Debug)]
+pub struct SpectrumControl {
+    enabled: AtomicBool,
+}
+
+impl SpectrumControl {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        log::info!("[SPECTRUM] {}", if enabled { "On" } else { "Off" });
+    }
+}
+
+/// Publishes `{"type":"spectrum","bands":[0..255, ...]}` over the WebSocket event channel
+pub struct SpectrumAnalyzer {
+    hub: Arc<BroadcastHub>,
+    control: Arc<SpectrumControl>,
+    channels: usize,
+    /// Mono mix of the most recent FFT_SIZE frames (ring)
+    history: Vec<f32>,
+    write_pos: usize,
+    window: Vec<f32>,
+    /// FFT bin range of each band
+    bands: Vec<(usize, usize)>,
+    /// Frames between updates
+    hop: usize,
+    frames_since_update: usize,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(config: &SpectrumConfig, hub: Arc<BroadcastHub>, sample_rate: u32, channels: u16) -> (Self, Arc<SpectrumControl>) {
+        let control = Arc::new(SpectrumControl { enabled: AtomicBool::new(true) });
+        let band_count = config.bands.clamp(4, 128);
+        let rate_hz = config.rate_hz.clamp(1, 60);
+
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+        let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+        let highest = HIGHEST_HZ.min(sample_rate as f32 / 2.0);
+        let bands = (0..band_count)
+            .map(|i| {
+                let edge = |i: usize| LOWEST_HZ * (highest / LOWEST_HZ).powf(i as f32 / band_count as f32);
+                let low = ((edge(i) / bin_hz) as usize).max(1);
+                let high = ((edge(i + 1) / bin_hz).ceil() as usize).clamp(low + 1, FFT_SIZE / 2);
+                (low.min(FFT_SIZE / 2 - 1), high)
+            })
+            .collect();
+
+        log::info!("[SPECTRUM] {} bands at {}Hz", band_count, rate_hz);
+        let analyzer = Self {
+            hub,
+            control: control.clone(),
+            channels: channels.max(1) as usize,
+            history: vec![0.0; FFT_SIZE],
+            write_pos: 0,
+            window,
+            bands,
+            hop: (sample_rate / rate_hz).max(1) as usize,
+            frames_since_update: 0,
+        };
+        (analyzer, control)
+    }
+
+    /// Band levels (0..255) of the current history
+    fn levels(&self) -> Vec<u8> {
+        let mut re: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| self.history[(self.write_pos + i) % FFT_SIZE] * self.window[i])
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        // A full-scale sine peaks at FFT_SIZE / 4 with a Hann window
+        let scale = 4.0 / FFT_SIZE as f32;
+        self.bands
+            .iter()
+            .map(|&(low, high)| {
+                let peak = (low..high)
+                    .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+                    .fold(0.0f32, f32::max);
+                let db = 20.0 * (peak * scale).max(1e-9).log10();
+                (((db + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0) * 255.0) as u8
+            })
+            .collect()
+    }
+}
+
+impl AudioProcessor for SpectrumAnalyzer {
+    fn process(&mut self, samples: &mut [f32]) {
+        if !self.control.is_enabled() {
+            return;
+        }
+
+        for frame in samples.chunks(self.channels) {
+            self.history[self.write_pos] = frame.iter().sum::<f32>() / frame.len() as f32;
+            self.write_pos = (self.write_pos + 1) % FFT_SIZE;
+            self.frames_since_update += 1;
+        }
+
+        if self.frames_since_update >= self.hop {
+            self.frames_since_update = 0;
+            // Nobody to show it to
+            if self.hub.client_counts().websocket == 0 {
+                return;
+            }
+            let event = serde_json::json!({ "type": "spectrum", "bands": self.levels() });
+            self.hub.publish_text(&event.to_string());
+        }
+    }
+}
+
+/// In-place iterative radix-2 FFT (length must be a power of two)
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1.0f32, 0.0f32);
+            for k in 0..len / 2 {
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cur_re - im[b] * cur_im;
+                let t_im = re[b] * cur_im + im[b] * cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,108 @@
+//! Station metadata
+//! Name, description, genre and website; sent as icy-* headers and OpusTags, shown by the players
+
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+use crate::config::Config;
+use crate::hub::BroadcastHub;
+
+/// Longest accepted value (characters)
+const MAX_LEN: usize = 200;
+
+/// Stream metadata, changeable while streaming (GUI, control API)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StationInfo {
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+    /// Station homepage (http/https only)
+    pub website: String,
+}
+
+impl StationInfo {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            name: config.stream_name.clone(),
+            description: config.stream_description.clone(),
+            genre: config.stream_genre.clone(),
+            website: config.stream_website.clone(),
+        }
+        .sanitized()
+    }
+
+    /// Control characters removed, values trimmed and capped, non-http(s) websites dropped
+    pub fn sanitized(self) -> Self {
+        let clean = |value: &str| -> String {
+            value.chars().filter(|c| !c.is_control()).take(MAX_LEN).collect::<String>().trim().to_string()
+        };
+        let website = clean(&self.website);
+        let website = if website.is_empty() || website.starts_with("http://") || website.starts_with("https://") {
+            website
+        } else {
+            log::warn!("[STATION] Website must start with http:// or https:// (got {:?}), ignoring it", website);
+            String::new()
+        };
+        Self {
+            name: clean(&self.name),
+            description: clean(&self.description),
+            genre: clean(&self.genre),
+            website,
+        }
+    }
+
+    /// Name shown to listeners (falls back to the app name)
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            "RustCast"
+        } else {
+            &self.name
+        }
+    }
+
+    /// Vorbis comments for OpusTags (`KEY=value`)
+    pub fn vorbis_comments(&self) -> Vec<String> {
+        [
+            ("TITLE", self.display_name()),
+            ("DESCRIPTION", self.description.as_str()),
+            ("GENRE", self.genre.as_str()),
+            ("CONTACT", self.website.as_str()),
+        ]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+    }
+}
+
+/// Current station metadata, shared by the server, sinks and front ends
+#[derive(Debug, Default)]
+pub struct Station {
+    info: RwLock<StationInfo>,
+}
+
+impl Station {
+    pub fn new(info: StationInfo) -> Self {
+        Self { info: RwLock::new(info) }
+    }
+
+    pub fn get(&self) -> StationInfo {
+        self.info.read().unwrap().clone()
+    }
+
+    /// Replace the metadata and push it to connected players. Returns false if nothing changed.
+    pub fn set(&self, info: StationInfo, hub: &BroadcastHub) -> bool {
+        let info = info.sanitized();
+        {
+            let mut current = self.info.write().unwrap();
+            if *current == info {
+                return false;
+            }
+            *current = info.clone();
+        }
+        log::info!("[STATION] Metadata changed: {}", info.display_name());
+        hub.publish_text(&serde_json::json!({ "type": "station", "station": info }).to_string());
+        true
+    }
+}
@@ -0,0 +1,210 @@
+//! Stats feed
+//! Client list, levels, drop and glitch counters pushed to subscribers when they change:
+//! in-process to the GUI, as JSON over WebSocket to dashboards (control API `GET /api/stats`)
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::agc::AgcMeter;
+use crate::drops::{self, DropCounts};
+use crate::glitches::{self, GlitchCounts};
+use crate::dsp::PeakLevel;
+use crate::geoip::GeoIp;
+use crate::hub::{BroadcastHub, SinkKind};
+use crate::mixer::MicMeter;
+
+/// How often the stats are sampled (sent only when something changed)
+const INTERVAL: Duration = Duration::from_millis(250);
+/// Snapshots a subscriber may fall behind; older ones are skipped, the next one replaces them
+const QUEUE: usize = 4;
+
+/// One sample of everything the feed reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    pub clients: Clients,
+    pub listeners: Vec<Listener>,
+    /// Peak of the outgoing audio (dBFS)
+    pub peak_db: f32,
+    pub agc: Option<AgcLevels>,
+    pub mic: Option<MicLevels>,
+    pub drops: DropCounts,
+    pub capture_glitches: GlitchCounts,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Clients {
+    pub total: usize,
+    pub websocket: usize,
+    pub http: usize,
+    pub tcp: usize,
+}
+
+/// One connected listener (server-side outputs are left out)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Listener {
+    pub id: u64,
+    pub kind: &'static str,
+    pub tier: &'static str,
+    pub mode: &'static str,
+    pub dropped: u64,
+    pub backlog: usize,
+    pub kiosk: bool,
+    pub ip: Option<IpAddr>,
+    /// Device ID the web player sent
+    pub device: Option<String>,
+    /// "City, CC" of a remote listener (`geoip.database`)
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AgcLevels {
+    pub input_db: f32,
+    pub output_db: f32,
+    pub gain_db: f32,
+    pub bypassed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MicLevels {
+    pub level_db: f32,
+    pub noise_db: f32,
+    pub voice: bool,
+    pub open: bool,
+}
+
+/// What the feed samples
+pub struct StatsSources {
+    pub hub: Arc<BroadcastHub>,
+    pub level: Arc<PeakLevel>,
+    pub agc: Option<Arc<AgcMeter>>,
+    pub mic: Option<Arc<MicMeter>>,
+    pub geoip: Option<Arc<GeoIp>>,
+}
+
+impl StatsSources {
+    fn sample(&self) -> Stats {
+        let counts = self.hub.client_counts();
+        let listeners = self
+            .hub
+            .snapshot()
+            .into_iter()
+            .filter(|client| client.kind != SinkKind::Output)
+            .map(|client| Listener {
+                id: client.id,
+                kind: client.kind.as_str(),
+                tier: client.tier.as_str(),
+                mode: client.class.as_str(),
+                dropped: client.dropped,
+                backlog: client.backlog,
+                kiosk: client.kiosk,
+                ip: client.remote_addr.map(|addr| addr.ip()),
+                device: client.device,
+                location: self.locate(client.remote_addr),
+            })
+            .collect();
+        Stats {
+            clients: Clients { total: counts.total(), websocket: counts.websocket, http: counts.http, tcp: counts.tcp },
+            listeners,
+            peak_db: round(self.level.db()),
+            agc: self.agc.as_ref().map(|agc| AgcLevels {
+                input_db: round(agc.input_db()),
+                output_db: round(agc.output_db()),
+                gain_db: round(agc.gain_db()),
+                bypassed: agc.is_bypassed(),
+            }),
+            mic: self.mic.as_ref().map(|mic| MicLevels {
+                level_db: round(mic.level_db()),
+                noise_db: round(mic.noise_db()),
+                voice: mic.is_voice(),
+                open: mic.is_open(),
+            }),
+            drops: drops::snapshot(),
+            capture_glitches: glitches::snapshot(),
+        }
+    }
+
+    /// "City, CC" of a remote listener
+    fn locate(&self, addr: Option<SocketAddr>) -> Option<String> {
+        Some(self.geoip.as_ref()?.locate(addr?.ip())?.to_string())
+    }
+}
+
+/// Levels to 0.1 dB, so a steady signal doesn't count as a change
+fn round(db: f32) -> f32 {
+    (db * 10.0).round() / 10.0
+}
+
+struct Subscriber {
+    tx: Sender<Arc<Stats>>,
+    /// Called after each send, e.g. to wake the GUI thread
+    wake: Option<Arc<dyn Fn() + Send + Sync>>,
+}
+
+pub struct StatsFeed {
+    sources: StatsSources,
+    subscribers: Mutex<Vec<Subscriber>>,
+    last: Mutex<Option<Arc<Stats>>>,
+}
+
+impl StatsFeed {
+    /// Start sampling on a background thread
+    pub fn spawn(sources: StatsSources) -> Arc<Self> {
+        let feed = Arc::new(Self { sources, subscribers: Mutex::new(Vec::new()), last: Mutex::new(None) });
+        let sampler = feed.clone();
+        let result = thread::Builder::new().name("stats-feed".into()).spawn(move || loop {
+            thread::sleep(INTERVAL);
+            // Nobody listening: don't walk the hub
+            if sampler.subscribers.lock().unwrap().is_empty() {
+                continue;
+            }
+            let stats = sampler.sources.sample();
+            let changed = sampler.last.lock().unwrap().as_deref() != Some(&stats);
+            if changed {
+                sampler.publish(Arc::new(stats));
+            }
+        });
+        if let Err(e) = result {
+            log::warn!("[STATS] Feed not started: {}", e);
+        }
+        feed
+    }
+
+    /// Receive a sample now and every change from then on.
+    /// The subscription ends when the receiver is dropped.
+    pub fn subscribe(&self, wake: Option<Box<dyn Fn() + Send + Sync>>) -> Receiver<Arc<Stats>> {
+        let (tx, rx) = crossbeam_channel::bounded(QUEUE);
+        let _ = tx.try_send(Arc::new(self.current()));
+        if let Some(wake) = &wake {
+            wake();
+        }
+        self.subscribers.lock().unwrap().push(Subscriber { tx, wake: wake.map(Arc::from) });
+        rx
+    }
+
+    /// Sample right away, for one-off requests
+    pub fn current(&self) -> Stats {
+        self.sources.sample()
+    }
+
+    fn publish(&self, stats: Arc<Stats>) {
+        *self.last.lock().unwrap() = Some(stats.clone());
+        // Wakers run after the lock is released, like in events.rs
+        let mut wakes = Vec::new();
+        self.subscribers.lock().unwrap().retain(|sub| match sub.tx.try_send(stats.clone()) {
+            Ok(()) => {
+                wakes.extend(sub.wake.clone());
+                true
+            }
+            // A slow reader misses this one and catches up with the next
+            Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+        for wake in wakes {
+            wake();
+        }
+    }
+}
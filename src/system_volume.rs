@@ -0,0 +1,259 @@
+//! Windows master volume
+//! Shows the default output's volume and mute, optionally links that mute with the stream mute and
+//! undoes the volume on the loopback capture
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::VolumeCompensationConfig;
+use crate::dsp::{AudioProcessor, FadeControl, Limiter};
+
+/// Time between reads of the endpoint (short: the compensation lags a volume change by up to this)
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The default output is looked up again this often (2s), so a device switch is followed
+const REOPEN_EVERY: u32 = 20;
+/// Time constant of the compensation gain following a new volume
+const COMPENSATION_SMOOTHING_MS: f32 = 30.0;
+
+static LATEST: Mutex<Option<SystemVolume>> = Mutex::new(None);
+static LINKED: AtomicBool = AtomicBool::new(false);
+
+/// Master volume of the default output
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SystemVolume {
+    /// 0-100
+    pub volume_percent: u32,
+    pub muted: bool,
+    /// Whether the stream mute follows it (and the other way round)
+    pub linked: bool,
+    /// Boost applied to undo the volume (dB, 0 when compensation is off)
+    pub compensation_db: f32,
+}
+
+/// Compensation gain the watcher sets and the DSP stage follows
+pub struct Compensation {
+    /// Linear gain (f32 bits)
+    gain: AtomicU32,
+    max_gain_db: f32,
+}
+
+impl Compensation {
+    pub fn new(config: &VolumeCompensationConfig) -> Arc<Self> {
+        Arc::new(Self { gain: AtomicU32::new(1f32.to_bits()), max_gain_db: config.max_gain_db.max(0.0) })
+    }
+
+    fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    /// Follow a new reading. Hardware volume (applied after the loopback point) and mute (nothing
+    /// left to boost) need no compensation. Returns the boost in dB.
+    fn follow(&self, reading: Option<Reading>) -> f32 {
+        let boost_db = match reading {
+            Some(reading) if !reading.muted && !reading.hardware => (-reading.level_db).clamp(0.0, self.max_gain_db),
+            _ => 0.0,
+        };
+        self.gain.store(10f32.powf(boost_db / 20.0).to_bits(), Ordering::Relaxed);
+        boost_db
+    }
+}
+
+/// DSP stage: the inverse of the master volume, ramped, with a limiter so the boost can't clip
+pub struct VolumeCompensation {
+    compensation: Arc<Compensation>,
+    channels: usize,
+    /// One-pole smoothing coefficient per frame
+    smoothing: f32,
+    gain: f32,
+    limiter: Limiter,
+}
+
+impl VolumeCompensation {
+    pub fn new(compensation: Arc<Compensation>, config: &VolumeCompensationConfig, sample_rate: u32, channels: u16) -> Self {
+        let frames = (COMPENSATION_SMOOTHING_MS / 1000.0 * sample_rate as f32).max(1.0);
+        Self {
+            compensation,
+            channels: channels.max(1) as usize,
+            smoothing: 1.0 - (-1.0 / frames).exp(),
+            gain: 1.0,
+            limiter: Limiter::new(&config.limiter, sample_rate, channels),
+        }
+    }
+}
+
+impl AudioProcessor for VolumeCompensation {
+    fn process(&mut self, samples: &mut [f32]) {
+        let target = self.compensation.gain();
+        // Volume at 100% (or hardware volume): the capture is already at full level
+        if target == 1.0 && self.gain == 1.0 {
+            return;
+        }
+        for frame in samples.chunks_mut(self.channels) {
+            self.gain += self.smoothing * (target - self.gain);
+            if (self.gain - target).abs() < 1e-4 {
+                self.gain = target;
+            }
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+        self.limiter.process(samples);
+    }
+}
+
+/// Link or unlink the stream mute with the Windows mute (tray menu)
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn set_linked(linked: bool) {
+    LINKED.store(linked, Ordering::SeqCst);
+    log::info!("[VOLUME] Stream mute {} the Windows mute", if linked { "linked with" } else { "independent of" });
+}
+
+/// Start the watcher thread (Windows only; elsewhere there is nothing to show)
+pub fn start(link_mute: bool, compensation: Option<Arc<Compensation>>, fade: Arc<FadeControl>) {
+    LINKED.store(link_mute, Ordering::SeqCst);
+    if !cfg!(windows) {
+        return;
+    }
+    let result = thread::Builder::new()
+        .name("system-volume".into())
+        .spawn(move || run(compensation, fade));
+    if let Err(e) = result {
+        log::warn!("[VOLUME] Watcher not started: {}", e);
+    }
+}
+
+fn run(compensation: Option<Arc<Compensation>>, fade: Arc<FadeControl>) {
+    let mut endpoint = None;
+    let mut reopen_in = 0u32;
+    // Last mute state seen on each side; whichever side changes wins
+    let mut last: Option<(bool, bool)> = None;
+    loop {
+        if endpoint.is_none() || reopen_in == 0 {
+            endpoint = platform::Endpoint::open()
+                .map_err(|e| log::debug!("[VOLUME] No default output: {}", e))
+                .ok();
+            reopen_in = REOPEN_EVERY;
+        }
+        reopen_in -= 1;
+
+        let read = endpoint.as_ref().and_then(|endpoint| endpoint.read());
+        let linked = LINKED.load(Ordering::SeqCst);
+        let compensation_db = compensation.as_ref().map_or(0.0, |compensation| compensation.follow(read));
+        *LATEST.lock().unwrap() = read.map(|reading| SystemVolume {
+            volume_percent: (reading.scalar * 100.0).round() as u32,
+            muted: reading.muted,
+            linked,
+            compensation_db,
+        });
+
+        match (read.map(|reading| reading.muted), endpoint.as_ref()) {
+            (Some(system_muted), Some(endpoint)) if linked => {
+                let stream_muted = fade.is_muted();
+                let system_now = match last {
+                    Some((system_was, _)) if system_muted != system_was => {
+                        fade.set_muted(system_muted);
+                        system_muted
+                    }
+                    Some((_, stream_was)) if stream_muted != stream_was => {
+                        log::info!("[VOLUME] Windows {}", if stream_muted { "muted" } else { "unmuted" });
+                        endpoint.set_muted(stream_muted);
+                        stream_muted
+                    }
+                    // Linking starts from the Windows state
+                    None if stream_muted != system_muted => {
+                        fade.set_muted(system_muted);
+                        system_muted
+                    }
+                    _ => system_muted,
+                };
+                last = Some((system_now, fade.is_muted()));
+            }
+            _ => last = None,
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Latest reading (None off Windows or without an output device)
+pub fn snapshot() -> Option<SystemVolume> {
+    *LATEST.lock().unwrap()
+}
+
+/// One read of the endpoint
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(windows), allow(dead_code))]
+struct Reading {
+    /// Slider position, 0.0-1.0
+    scalar: f32,
+    /// Attenuation it stands for (dB, 0 at the top)
+    level_db: f32,
+    muted: bool,
+    /// The device applies the volume itself, after the loopback point
+    hardware: bool,
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::Reading;
+    use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+    use windows::Win32::Media::Audio::{eConsole, eRender, IMMDeviceEnumerator, MMDeviceEnumerator, ENDPOINT_HARDWARE_SUPPORT_VOLUME};
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED};
+
+    /// Volume control of the default output, used on the thread that opened it
+    pub struct Endpoint(IAudioEndpointVolume);
+
+    impl Endpoint {
+        pub fn open() -> windows::core::Result<Self> {
+            // Already initialized on this thread is fine
+            // SAFETY: no reserved pointer
+            let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+            // SAFETY: COM is initialized on this thread; the interfaces stay on it
+            unsafe {
+                let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+                let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+                Ok(Self(device.Activate(CLSCTX_ALL, None)?))
+            }
+        }
+
+        pub fn read(&self) -> Option<Reading> {
+            // SAFETY: plain getters on a live interface
+            unsafe {
+                Some(Reading {
+                    scalar: self.0.GetMasterVolumeLevelScalar().ok()?,
+                    level_db: self.0.GetMasterVolumeLevel().ok()?,
+                    muted: self.0.GetMute().ok()?.as_bool(),
+                    hardware: self.0.QueryHardwareSupport().ok()? & ENDPOINT_HARDWARE_SUPPORT_VOLUME != 0,
+                })
+            }
+        }
+
+        pub fn set_muted(&self, muted: bool) {
+            // SAFETY: no event context GUID
+            if let Err(e) = unsafe { self.0.SetMute(muted, std::ptr::null()) } {
+                log::warn!("[VOLUME] Setting the Windows mute failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::Reading;
+
+    /// No system volume here; the watcher isn't started
+    pub struct Endpoint;
+
+    impl Endpoint {
+        pub fn open() -> Result<Self, String> {
+            Err("not supported on this platform".to_string())
+        }
+
+        pub fn read(&self) -> Option<Reading> {
+            None
+        }
+
+        pub fn set_muted(&self, _muted: bool) {}
+    }
+}
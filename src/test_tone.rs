@@ -0,0 +1,317 @@
+//! Test tone
+//! A few seconds of a steady tone in place of the captured audio (GUI button, `POST /api/test-tone`),
+//! so a first setup can tell whether the PC side or the listener side is broken. `/check` is the
+//! listener half.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dsp::{AudioProcessor, FadeControl};
+use crate::queue::PipelineSender;
+
+/// How long one tone plays
+pub const DURATION: Duration = Duration::from_secs(3);
+const FREQUENCY_HZ: f32 = 440.0;
+/// -12 dBFS
+const LEVEL: f32 = 0.25;
+/// Fade at both ends, so the tone doesn't click
+const FADE_SECS: f32 = 0.01;
+/// Silent chunk the pipeline is fed while nothing is captured
+const FILL_CHUNK: Duration = Duration::from_millis(20);
+/// No chunk processed within this long: the capture is idle (an idle output's loopback delivers
+/// nothing) and the tone needs chunks of its own
+const IDLE_PROBE: Duration = Duration::from_millis(60);
+
+/// Why no tone was started
+#[derive(Debug)]
+pub enum ToneError {
+    /// Streaming is stopped, so nothing is encoded
+    NotStreaming,
+    /// The stream is muted (by hand or by the privacy guard); the tone would be silent too
+    Muted,
+}
+
+impl std::fmt::Display for ToneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToneError::NotStreaming => write!(f, "Streaming is stopped; start it first"),
+            ToneError::Muted => write!(f, "The stream is muted; unmute it first"),
+        }
+    }
+}
+
+impl std::error::Error for ToneError {}
+
+/// Asks the `ToneInjector` for a tone and keeps the pipeline fed while it plays
+pub struct TestTone {
+    /// A tone was asked for; the injector starts (or restarts) it at its next chunk
+    requested: AtomicBool,
+    /// A tone is playing or about to
+    active: AtomicBool,
+    /// Bumped by every `start`; a filler from an earlier one stops
+    generation: AtomicU64,
+    /// Chunks the injector has processed
+    processed: AtomicU64,
+    is_streaming: Arc<AtomicBool>,
+    fade: Arc<FadeControl>,
+    capture: PipelineSender<Vec<f32>>,
+    /// One `FILL_CHUNK` of silence at the capture rate and channels
+    silence: Vec<f32>,
+}
+
+impl TestTone {
+    /// `capture` is the capture queue the filler feeds; the injector runs on `channels`-channel audio
+    pub fn new(
+        sample_rate: u32,
+        capture_channels: u16,
+        channels: u16,
+        capture: PipelineSender<Vec<f32>>,
+        is_streaming: Arc<AtomicBool>,
+        fade: Arc<FadeControl>,
+    ) -> (Arc<Self>, ToneInjector) {
+        let frames = (sample_rate as f32 * FILL_CHUNK.as_secs_f32()) as usize;
+        let tone = Arc::new(Self {
+            requested: AtomicBool::new(false),
+            active: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            processed: AtomicU64::new(0),
+            is_streaming,
+            fade,
+            capture,
+            silence: vec![0.0; frames * capture_channels.max(1) as usize],
+        });
+        let total = (sample_rate as f32 * DURATION.as_secs_f32()) as usize;
+        let injector = ToneInjector {
+            tone: tone.clone(),
+            channels: channels.max(1) as usize,
+            step: std::f32::consts::TAU * FREQUENCY_HZ / sample_rate.max(1) as f32,
+            phase: 0.0,
+            position: total,
+            total,
+            fade_frames: (sample_rate as f32 * FADE_SECS).max(1.0),
+        };
+        (tone, injector)
+    }
+
+    /// Play the tone (asking again while it plays makes it last `DURATION` from now)
+    pub fn start(self: &Arc<Self>) -> Result<Duration, ToneError> {
+        if !self.is_streaming.load(Ordering::SeqCst) {
+            return Err(ToneError::NotStreaming);
+        }
+        if self.fade.is_muted() || self.fade.is_privacy_muted() {
+            return Err(ToneError::Muted);
+        }
+        self.requested.store(true, Ordering::SeqCst);
+        self.active.store(true, Ordering::SeqCst);
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let tone = self.clone();
+        let spawned = thread::Builder::new().name("test-tone".into()).spawn(move || tone.fill(generation));
+        if let Err(e) = spawned {
+            log::warn!("[TONE] Filler not started: {}", e);
+        }
+        log::info!("[TONE] Test tone for {}s", DURATION.as_secs());
+        Ok(DURATION)
+    }
+
+    /// Feed silent chunks for the injector to replace while the capture is idle; stops once the
+    /// tone is over or real audio shows up
+    fn fill(&self, generation: u64) {
+        let current = || self.generation.load(Ordering::SeqCst) == generation;
+        // (a filler being replaced may still send one chunk meanwhile)
+        let seen = self.processed.load(Ordering::SeqCst);
+        thread::sleep(IDLE_PROBE);
+        if self.processed.load(Ordering::SeqCst) - seen > 1 || !current() {
+            return;
+        }
+
+        let start = self.processed.load(Ordering::SeqCst);
+        let deadline = Instant::now() + DURATION * 2;
+        let mut next = Instant::now();
+        let mut sent = 0u64;
+        while self.active.load(Ordering::SeqCst) && Instant::now() < deadline {
+            // Replaced, or more chunks than we sent: the capture is delivering again
+            if !current() || self.processed.load(Ordering::SeqCst) - start > sent + 2 {
+                return;
+            }
+            if self.capture.try_send(self.silence.clone()).is_ok() {
+                sent += 1;
+            }
+            next += FILL_CHUNK;
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        // Nothing consumed the chunks (pipeline stopped under us)
+        if current() {
+            self.active.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// DSP stage that replaces the audio with the tone when `TestTone` asks
+pub struct ToneInjector {
+    tone: Arc<TestTone>,
+    channels: usize,
+    /// Phase advance per frame (radians)
+    step: f32,
+    phase: f32,
+    /// Frames of the tone played (`total` = not playing)
+    position: usize,
+    total: usize,
+    fade_frames: f32,
+}
+
+impl AudioProcessor for ToneInjector {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.tone.processed.fetch_add(1, Ordering::SeqCst);
+        if self.tone.requested.swap(false, Ordering::SeqCst) {
+            // Restarting mid-tone keeps the level (no fade back in)
+            let fade = self.fade_frames as usize;
+            self.position = if self.position < self.total { self.position.min(fade) } else { 0 };
+        }
+        if self.position >= self.total {
+            return;
+        }
+        for frame in samples.chunks_mut(self.channels) {
+            if self.position >= self.total {
+                break;
+            }
+            let edge = self.position.min(self.total - self.position) as f32;
+            let value = LEVEL * (edge / self.fade_frames).min(1.0) * self.phase.sin();
+            frame.fill(value);
+            self.phase = (self.phase + self.step) % std::f32::consts::TAU;
+            self.position += 1;
+        }
+        if self.position >= self.total {
+            self.tone.active.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Listener-side check: connects like a player and shows whether frames arrive and carry sound
+pub const CHECK_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>RustCast connection check</title>
+<style>
+    body { font-family: system-ui, sans-serif; background: #15161a; color: #e8e8ea; margin: 0; padding: 32px 16px; }
+    main { max-width: 520px; margin: 0 auto; }
+    h1 { font-size: 1.4em; margin-bottom: 4px; text-align: center; }
+    p { color: #9a9aa2; }
+    ul { list-style: none; padding: 0; }
+    li { background: #1f2026; border-radius: 8px; padding: 12px 14px; margin: 8px 0; }
+    li b { display: inline-block; width: 1.6em; }
+    li small { display: block; color: #9a9aa2; margin: 4px 0 0 1.6em; }
+    a { color: #4f8cff; }
+</style>
+</head>
+<body>
+<main>
+    <h1>Connection check</h1>
+    <p>Checks from this device that the station's audio reaches it. Press <em>Test tone</em> on the PC
+        (or <code>POST /api/test-tone</code>) to send a tone: the sound row turns green without anything
+        playing on the PC.</p>
+    <ul>
+        <li id="server"><b>…</b>Server reachable<small></small></li>
+        <li id="websocket"><b>…</b>Low-latency stream (WebSocket)<small></small></li>
+        <li id="frames"><b>…</b>Audio frames arriving<small></small></li>
+        <li id="sound"><b>…</b>Frames carry sound<small></small></li>
+        <li id="http"><b>…</b>HTTP stream (/stream)<small></small></li>
+    </ul>
+    <p><a href="/">Open the player</a></p>
+</main>
+<script>
+    // Opus frames of this size or less are silence (DTX / digital silence)
+    const SILENT_FRAME_MAX = 3;
+    const HTTP_CHECK_MS = 4000;
+
+    function row(id, state, detail) {
+        const el = document.getElementById(id);
+        el.querySelector('b').textContent = { ok: '✅', bad: '❌', warn: '⚠️', wait: '…' }[state];
+        el.querySelector('small').textContent = detail || '';
+    }
+
+    function checkWebSocket() {
+        let frames = 0, soundFrames = 0, lastSound = 0, lastFrames = 0;
+        const scheme = location.protocol === 'https:' ? 'wss:' : 'ws:';
+        const ws = new WebSocket(`${scheme}//${location.host}/ws`);
+        ws.binaryType = 'arraybuffer';
+        ws.onmessage = (event) => {
+            if (typeof event.data === 'string') {
+                const message = JSON.parse(event.data);
+                if (message.type === 'hello') {
+                    row('websocket', 'ok', `Connected as client #${message.client_id}, ${message.sample_rate} Hz, ${message.channels} ch`);
+                }
+                return;
+            }
+            frames++;
+            if (event.data.byteLength > SILENT_FRAME_MAX) {
+                soundFrames++;
+                lastSound = performance.now();
+            }
+        };
+        ws.onerror = ws.onclose = () => row('websocket', 'bad',
+            'Could not connect or disconnected (a proxy without WebSocket support, or the MP3 codec)');
+
+        setInterval(() => {
+            const rate = frames - lastFrames;
+            lastFrames = frames;
+            if (ws.readyState !== WebSocket.OPEN) return;
+            if (rate >= 40) row('frames', 'ok', `${rate} frames/s`);
+            else if (rate > 0) row('frames', 'warn', `${rate} frames/s (expected about 50; a slow network or a busy PC)`);
+            else row('frames', 'bad', 'No frames; is streaming started on the PC?');
+
+            if (!frames) return;
+            if (performance.now() - lastSound < 1500) row('sound', 'ok', `Sound in ${soundFrames} of ${frames} frames`);
+            else row('sound', 'warn', 'Only silence so far: play something on the PC or send a test tone');
+        }, 1000);
+    }
+
+    async function checkHttp() {
+        const controller = new AbortController();
+        setTimeout(() => controller.abort(), HTTP_CHECK_MS);
+        let bytes = 0;
+        try {
+            const response = await fetch('/stream', { signal: controller.signal, cache: 'no-store' });
+            if (!response.ok) throw new Error(`HTTP ${response.status}`);
+            const reader = response.body.getReader();
+            for (;;) {
+                const { done, value } = await reader.read();
+                if (done) break;
+                bytes += value.length;
+            }
+            row('http', 'bad', 'The stream ended early');
+        } catch (e) {
+            if (e.name !== 'AbortError') row('http', 'bad', e.message);
+            else if (bytes > 0) row('http', 'ok', `${(bytes / 1024).toFixed(1)} KB in ${HTTP_CHECK_MS / 1000} s (what players without WebSocket use)`);
+            else row('http', 'bad', 'Connected but received nothing');
+        }
+    }
+
+    (async () => {
+        let diagnostics;
+        try {
+            diagnostics = await (await fetch('/api/diagnostics', { cache: 'no-store' })).json();
+        } catch (e) {
+            row('server', 'bad', `No answer from ${location.host}: ${e.message}`);
+            return;
+        }
+        if (diagnostics.audio_age_ms === null) {
+            row('server', 'warn', `Reachable (${diagnostics.codec}), but the PC hasn't sent audio yet: start streaming`);
+        } else {
+            row('server', 'ok', `Reachable (${diagnostics.codec}), ${diagnostics.clients} listener(s) connected`);
+        }
+        if (diagnostics.endpoints.ws) {
+            checkWebSocket();
+        } else {
+            for (const id of ['websocket', 'frames', 'sound']) row(id, 'warn', 'Turned off on the station');
+        }
+        if (diagnostics.endpoints.stream) checkHttp();
+        else row('http', 'warn', 'Turned off on the station');
+    })();
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,94 @@
+//! Low-bitrate tier
+//! A second, smaller encode of the broadcast for listeners on slow connections
+
+use std::sync::Arc;
+use std::thread;
+
+use crate::config::{LowTierConfig, OpusConfig};
+use crate::hub::{BroadcastHub, Outgoing, SinkKind, Tier};
+use crate::opus_encoder::{FrameClassifier, OpusDecoder, OpusEncoder};
+use crate::server::OpusStreamInfo;
+
+/// Decides which listeners get the low tier
+#[derive(Debug)]
+pub struct LowTier {
+    bitrate: u32,
+    /// Lowercase User-Agent substrings that select the low tier
+    user_agents: Vec<String>,
+}
+
+impl LowTier {
+    /// Start transcoding the hub's packets. Works on what listeners actually hear
+    /// (after fades and the broadcast delay) and idles while nobody is on the low tier.
+    pub fn spawn(
+        config: &LowTierConfig,
+        opus: &OpusConfig,
+        hub: Arc<BroadcastHub>,
+        info: &OpusStreamInfo,
+    ) -> Result<Arc<Self>, String> {
+        let bitrate = config.bitrate.clamp(6, 128);
+        let mut decoder = OpusDecoder::new(info.sample_rate, info.channels)?;
+        let mut encoder = OpusEncoder::new(info.sample_rate, info.channels, bitrate)?;
+        encoder.tune(opus)?;
+        let subscription = hub.subscribe(SinkKind::Output, None);
+
+        thread::spawn(move || {
+            let mut classifier = FrameClassifier::default();
+            while let Some(item) = subscription.recv() {
+                let Outgoing::Audio(packet) = item else { continue };
+                if hub.tier_count(Tier::Low) == 0 {
+                    continue;
+                }
+
+                let pcm = match decoder.decode_float(&packet) {
+                    Ok(pcm) => pcm,
+                    Err(e) => {
+                        log::warn!("[TIER] {}", e);
+                        continue;
+                    }
+                };
+                match encoder.encode_raw(&pcm) {
+                    Ok(packets) => {
+                        for packet in packets.into_iter().filter(|p| !p.is_empty()) {
+                            let priority = classifier.classify(&packet);
+                            hub.publish_tier(Tier::Low, packet, priority);
+                        }
+                    }
+                    Err(e) => log::warn!("[TIER] {}", e),
+                }
+            }
+        });
+
+        log::info!("[TIER] Low tier at {}kbps", bitrate);
+        Ok(Arc::new(Self {
+            bitrate,
+            user_agents: config
+                .user_agents
+                .iter()
+                .filter(|ua| !ua.is_empty())
+                .map(|ua| ua.to_lowercase())
+                .collect(),
+        }))
+    }
+
+    /// Tier for a new listener: an explicit `?tier=` wins, then the User-Agent list
+    pub fn select(&self, requested: Option<Tier>, user_agent: Option<&str>) -> Tier {
+        if let Some(tier) = requested {
+            return tier;
+        }
+        let user_agent = user_agent.unwrap_or("").to_lowercase();
+        if self.user_agents.iter().any(|ua| user_agent.contains(ua.as_str())) {
+            Tier::Low
+        } else {
+            Tier::Full
+        }
+    }
+
+    /// For /status
+    pub fn info(&self, hub: &BroadcastHub) -> serde_json::Value {
+        serde_json::json!({
+            "bitrate": self.bitrate,
+            "listeners": hub.tier_count(Tier::Low),
+        })
+    }
+}
@@ -0,0 +1,100 @@
+//! Time-shift buffer
+//! Keeps the last few minutes of encoded packets so players can pause and rewind the live stream
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::hub::{BroadcastHub, Outgoing, Packet, SinkKind};
+
+/// Most packets a single /timeshift request returns
+pub const MAX_RANGE_PACKETS: usize = 500;
+
+struct Frames {
+    packets: VecDeque<Packet>,
+    /// Sequence number of `packets[0]`
+    first_seq: u64,
+}
+
+/// Ring of recent Opus packets, addressed by a running sequence number
+pub struct TimeShiftBuffer {
+    frames: Mutex<Frames>,
+    capacity: usize,
+    frame_ms: u32,
+}
+
+impl TimeShiftBuffer {
+    /// Start recording the hub's packets into a buffer of `minutes` length
+    pub fn spawn(hub: Arc<BroadcastHub>, minutes: u32, frame_ms: u32) -> Arc<Self> {
+        let frame_ms = frame_ms.max(1);
+        let capacity = (minutes as usize * 60_000 / frame_ms as usize).max(1);
+        let buffer = Arc::new(Self {
+            frames: Mutex::new(Frames {
+                packets: VecDeque::with_capacity(capacity),
+                first_seq: 0,
+            }),
+            capacity,
+            frame_ms,
+        });
+
+        let subscription = hub.subscribe(SinkKind::Output, None);
+        let writer = buffer.clone();
+        thread::spawn(move || {
+            while let Some(item) = subscription.recv() {
+                if let Outgoing::Audio(packet) = item {
+                    writer.push(packet);
+                }
+            }
+        });
+
+        log::info!("[TIMESHIFT] Keeping the last {} minutes ({} packets)", minutes, capacity);
+        buffer
+    }
+
+    fn push(&self, packet: Packet) {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.packets.len() == self.capacity {
+            frames.packets.pop_front();
+            frames.first_seq += 1;
+        }
+        frames.packets.push_back(packet);
+    }
+
+    /// `{"oldest_seq", "newest_seq", "frame_ms"}` (newest is -1 while empty)
+    pub fn info(&self) -> serde_json::Value {
+        let frames = self.frames.lock().unwrap();
+        serde_json::json!({
+            "oldest_seq": frames.first_seq,
+            "newest_seq": frames.first_seq as i64 + frames.packets.len() as i64 - 1,
+            "frame_ms": self.frame_ms,
+        })
+    }
+
+    /// Up to `count` packets starting at `from` (moved forward to the oldest kept packet).
+    /// Returns the sequence number of the first returned packet, the newest sequence
+    /// number, and the packets.
+    pub fn range(&self, from: u64, count: usize) -> (u64, i64, Vec<Packet>) {
+        let frames = self.frames.lock().unwrap();
+        let from = from.max(frames.first_seq);
+        let start = (from - frames.first_seq) as usize;
+        let packets = frames
+            .packets
+            .iter()
+            .skip(start)
+            .take(count.min(MAX_RANGE_PACKETS))
+            .cloned()
+            .collect();
+        let newest = frames.first_seq as i64 + frames.packets.len() as i64 - 1;
+        (from, newest, packets)
+    }
+}
+
+/// Packets as one body: 2-byte big-endian length, then the packet, repeated
+pub fn encode_packets(packets: &[Packet]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(packets.iter().map(|p| p.len() + 2).sum());
+    for packet in packets {
+        body.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        body.extend_from_slice(packet);
+    }
+    body
+}
@@ -0,0 +1,222 @@
+//! Live tuning
+//! Settings the control API changes while streaming (`PATCH /api/config`): the Opus/MP3 bitrate
+//! and the `dsp` / `agc` stages here, the codec, station info, blocklist and mute link through the
+//! config store's subscribers; everything else is saved for the next start
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::agc::AgcMeter;
+use crate::config::{AgcConfig, Config, DspStage};
+use crate::config_store::{ConfigStore, Origin, SaveError};
+use crate::dsp::{AudioProcessor, ChainBuilder, DspChain};
+
+/// Config fields applied without a restart
+pub const LIVE_FIELDS: &[&str] = &[
+    "bitrate",
+    "dsp",
+    "agc",
+    "codec",
+    "stream_name",
+    "stream_genre",
+    "stream_description",
+    "stream_website",
+    "blocklist",
+    "link_system_mute",
+];
+/// Live fields applied here; the rest of `LIVE_FIELDS` by the store's subscribers
+const TUNED_FIELDS: &[&str] = &["bitrate", "dsp", "agc"];
+
+/// What is running now (GET /api/config)
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveSettings {
+    pub bitrate: u32,
+    pub agc: AgcConfig,
+    pub dsp: Vec<DspStage>,
+}
+
+/// Fields a patch changed, by when they take effect
+#[derive(Debug, Default, Serialize)]
+pub struct PatchOutcome {
+    pub applied: Vec<String>,
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// Not a config field, or a value of the wrong type
+    Invalid(String),
+    /// The `dsp` / `agc` stages don't validate
+    Dsp(Vec<String>),
+    Save(String),
+}
+
+impl From<SaveError> for PatchError {
+    fn from(e: SaveError) -> Self {
+        PatchError::Save(e.to_string())
+    }
+}
+
+pub struct LiveTuning {
+    store: Arc<ConfigStore>,
+    builder: ChainBuilder,
+    settings: Mutex<LiveSettings>,
+    /// Read by the encoder thread with every captured chunk
+    bitrate: AtomicU32,
+    /// Meter of the running AGC, kept across rebuilds. The GUI and stats feed hold it from
+    /// startup, so adding or removing the AGC waits for a restart.
+    agc: Option<Arc<AgcMeter>>,
+    /// Rebuilt chain waiting for the encoder thread
+    pending: Mutex<Option<DspChain>>,
+}
+
+impl LiveTuning {
+    /// Build the configured stages; put the returned `LiveChain` in the DSP chain
+    pub fn new(config: &Config, store: Arc<ConfigStore>, sample_rate: u32, channels: u16) -> (Arc<Self>, LiveChain) {
+        let builder = ChainBuilder::new(sample_rate, channels);
+        let built = builder.build(&config.dsp_stages());
+        let tuning = Arc::new(Self {
+            store,
+            builder,
+            settings: Mutex::new(LiveSettings { bitrate: config.bitrate, agc: config.agc.clone(), dsp: config.dsp.clone() }),
+            bitrate: AtomicU32::new(config.bitrate),
+            agc: built.agc,
+            pending: Mutex::new(None),
+        });
+        let chain = LiveChain {
+            chain: built.chain,
+            tuning: tuning.clone(),
+            channels: channels.max(1) as usize,
+            scratch: Vec::new(),
+        };
+        (tuning, chain)
+    }
+
+    pub fn settings(&self) -> LiveSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn bitrate(&self) -> u32 {
+        self.bitrate.load(Ordering::Relaxed)
+    }
+
+    /// Meter of the AGC stage, if there is one
+    pub fn agc_meter(&self) -> Option<Arc<AgcMeter>> {
+        self.agc.clone()
+    }
+
+    /// Merge `patch` (JSON merge patch: objects merge, everything else replaces) into the saved
+    /// config, save it and apply what can change live
+    pub fn patch(&self, patch: serde_json::Map<String, serde_json::Value>) -> Result<PatchOutcome, PatchError> {
+        let mut saved = serde_json::Value::Null;
+        let mut merged = serde_json::Value::Null;
+        let config = self.store.try_update(Origin::Api, |config| {
+            saved = serde_json::to_value(&*config).map_err(|e| PatchError::Invalid(e.to_string()))?;
+            merged = saved.clone();
+            for (key, value) in &patch {
+                if saved.get(key).is_none() {
+                    return Err(PatchError::Invalid(format!("Unknown field: {}", key)));
+                }
+                merge(&mut merged[key], value);
+            }
+            let patched: Config =
+                serde_json::from_value(merged.clone()).map_err(|e| PatchError::Invalid(format!("Invalid config: {}", e)))?;
+            let errors = self.builder.validate(&patched.dsp_stages());
+            if !errors.is_empty() {
+                return Err(PatchError::Dsp(errors));
+            }
+            if !(6..=510).contains(&patched.bitrate) {
+                return Err(PatchError::Invalid("bitrate must be 6-510 kbps".to_string()));
+            }
+            *config = patched;
+            Ok(())
+        })?;
+
+        // Compared with what runs, not with the file: a field saved earlier for a restart
+        // still counts as pending
+        let mut outcome = PatchOutcome::default();
+        let mut settings = self.settings.lock().unwrap();
+        let running = serde_json::to_value(&*settings).unwrap_or_default();
+        let changed = |key: &str| patch.contains_key(key) && running[key] != merged[key];
+
+        if changed("bitrate") {
+            settings.bitrate = config.bitrate;
+            self.bitrate.store(config.bitrate, Ordering::Relaxed);
+            outcome.applied.push("bitrate".to_string());
+        }
+        let stages_changed: Vec<String> = ["dsp", "agc"].into_iter().filter(|key| changed(key)).map(String::from).collect();
+        if !stages_changed.is_empty() {
+            let stages = config.dsp_stages();
+            let has_agc = stages.iter().any(|stage| matches!(stage, DspStage::Agc(_)));
+            if has_agc == self.agc.is_some() {
+                let built = self.builder.build_with_meter(&stages, self.agc.clone());
+                *self.pending.lock().unwrap() = Some(built.chain);
+                settings.dsp = config.dsp.clone();
+                settings.agc = config.agc.clone();
+                outcome.applied.extend(stages_changed);
+            } else {
+                outcome.restart_required.extend(stages_changed);
+            }
+        }
+        for key in patch.keys().filter(|key| !TUNED_FIELDS.contains(&key.as_str()) && saved[key.as_str()] != merged[key.as_str()]) {
+            if LIVE_FIELDS.contains(&key.as_str()) {
+                outcome.applied.push(key.clone());
+            } else {
+                outcome.restart_required.push(key.clone());
+            }
+        }
+        drop(settings);
+
+        log::info!(
+            "[CONFIG] Patched over the control API: applied {:?}, after restart {:?}",
+            outcome.applied, outcome.restart_required
+        );
+        Ok(outcome)
+    }
+}
+
+/// RFC 7396 merge, except that null replaces instead of removing (config fields can't be removed)
+fn merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// The configured stages in the encoder thread; swaps in chains rebuilt by `LiveTuning::patch`
+pub struct LiveChain {
+    chain: DspChain,
+    tuning: Arc<LiveTuning>,
+    channels: usize,
+    scratch: Vec<f32>,
+}
+
+impl AudioProcessor for LiveChain {
+    fn process(&mut self, samples: &mut [f32]) {
+        // Never wait on the control thread here
+        let next = self.tuning.pending.try_lock().ok().and_then(|mut pending| pending.take());
+        let Some(mut next) = next else {
+            self.chain.process(samples);
+            return;
+        };
+        // Both chains run on this chunk and are crossfaded, so filters starting from silence
+        // and a changed gain don't click
+        self.scratch.clear();
+        self.scratch.extend_from_slice(samples);
+        self.chain.process(&mut self.scratch);
+        next.process(samples);
+        let frames = (samples.len() / self.channels).max(1) as f32;
+        for (i, (frame, old)) in samples.chunks_mut(self.channels).zip(self.scratch.chunks(self.channels)).enumerate() {
+            let mix = (i + 1) as f32 / frames;
+            for (sample, old) in frame.iter_mut().zip(old) {
+                *sample = *old + (*sample - *old) * mix;
+            }
+        }
+        self.chain = next;
+    }
+}
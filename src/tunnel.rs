@@ -0,0 +1,330 @@
+//! Relay tunnel client
+//! Outbound WebSocket to a relay server (`tunnel.relay`, the `rustcast-relay` binary) that
+//! listeners connect to instead of this machine, for networks without port forwarding. Each
+//! listener connection on the relay becomes a connection to the local server.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::TunnelConfig;
+use crate::tunnel_protocol::{self, FrameReader, Message, MAX_DATA};
+use crate::ws::{self, Opcode};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A relay that doesn't take our data for this long is treated as gone
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Reconnect delays double up to this
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A session that lasted this long starts the backoff over
+const STABLE: Duration = Duration::from_secs(30);
+/// Longest handshake response accepted
+const MAX_HEAD: usize = 8192;
+
+/// Tunnel state for /status
+#[derive(Debug, Clone, Serialize)]
+pub struct TunnelStatus {
+    pub relay: String,
+    pub connected: bool,
+    /// Where listeners reach the station, as the relay announced it
+    pub public_url: Option<String>,
+    /// Listener connections carried right now
+    pub streams: usize,
+    /// Why the last connection failed or ended
+    pub error: Option<String>,
+}
+
+static STATUS: Mutex<Option<TunnelStatus>> = Mutex::new(None);
+/// Listener behind each tunnel stream, by the local address of its connection to the server
+static REMOTES: Mutex<Option<HashMap<SocketAddr, SocketAddr>>> = Mutex::new(None);
+
+/// Connections to the local server, by tunnel stream id
+type Streams = Arc<Mutex<HashMap<u32, Arc<TcpStream>>>>;
+
+/// Tunnel state, None when the tunnel is off
+pub fn snapshot() -> Option<TunnelStatus> {
+    STATUS.lock().unwrap().clone()
+}
+
+/// The listener a connection to the server comes from: the relay's peer for a tunnel stream,
+/// `peer` itself for everything else. Only our own tunnel sockets are in the map, so a local
+/// client can't claim another address.
+pub fn client_addr(peer: SocketAddr) -> SocketAddr {
+    if !peer.ip().is_loopback() {
+        return peer;
+    }
+    REMOTES.lock().unwrap().as_ref().and_then(|remotes| remotes.get(&peer).copied()).unwrap_or(peer)
+}
+
+fn update(change: impl FnOnce(&mut TunnelStatus)) {
+    if let Some(status) = STATUS.lock().unwrap().as_mut() {
+        change(status);
+    }
+}
+
+/// Keep a tunnel to `config.relay` open, reconnecting with backoff, and carry its streams to
+/// the server on `local_port`
+pub fn spawn(config: &TunnelConfig, local_port: u16) {
+    let config = config.clone();
+    *STATUS.lock().unwrap() = Some(TunnelStatus {
+        relay: config.relay.clone(),
+        connected: false,
+        public_url: None,
+        streams: 0,
+        error: None,
+    });
+    let result = thread::Builder::new().name("tunnel".into()).spawn(move || {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let started = Instant::now();
+            let error = match run(&config, local_port) {
+                Ok(()) => "relay closed the tunnel".to_string(),
+                Err(e) => e,
+            };
+            log::warn!("[TUNNEL] {}; reconnecting in {}s", error, backoff.as_secs());
+            update(|status| {
+                status.connected = false;
+                status.streams = 0;
+                status.error = Some(error);
+            });
+            if started.elapsed() >= STABLE {
+                backoff = Duration::from_secs(1);
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+    if let Err(e) = result {
+        log::error!("[TUNNEL] Not started: {}", e);
+    }
+}
+
+/// `host:port` of a `ws://` relay address
+fn relay_addr(relay: &str) -> Result<String, String> {
+    if relay.starts_with("wss://") {
+        return Err("wss:// relays aren't supported; use ws://".to_string());
+    }
+    let rest = relay.strip_prefix("ws://").ok_or_else(|| format!("Relay address must start with ws://: {:?}", relay))?;
+    let host = rest.split('/').next().unwrap_or_default();
+    if host.is_empty() {
+        return Err("No relay address (tunnel.relay)".to_string());
+    }
+    Ok(if host.contains(':') && !host.ends_with(']') { host.to_string() } else { format!("{}:80", host) })
+}
+
+/// Frames to the relay, shared by the threads reading local connections
+#[derive(Clone)]
+struct Uplink {
+    socket: Arc<Mutex<TcpStream>>,
+}
+
+impl Uplink {
+    fn send(&self, first_byte: u8, payload: &[u8]) -> std::io::Result<()> {
+        let frame = ws::masked_frame(first_byte, payload, mask());
+        self.socket.lock().unwrap().write_all(&frame)
+    }
+
+    fn message(&self, message: &Message) -> std::io::Result<()> {
+        self.send(0x82, &message.encode())
+    }
+}
+
+/// Client frames must be masked; any unpredictable-enough key does (RFC 6455 section 5.3)
+fn mask() -> [u8; 4] {
+    static STATE: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    let mut x = STATE.fetch_add(0x9E37_79B9, Ordering::Relaxed) ^ nanos;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    x.to_be_bytes()
+}
+
+/// One tunnel session; returns when the relay goes away
+fn run(config: &TunnelConfig, local_port: u16) -> Result<(), String> {
+    let addr = relay_addr(&config.relay)?;
+    let target = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("{}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("{}: no address", addr))?;
+    let mut socket = TcpStream::connect_timeout(&target, CONNECT_TIMEOUT).map_err(|e| format!("{}: {}", addr, e))?;
+    let _ = socket.set_nodelay(true);
+    socket.set_write_timeout(Some(WRITE_TIMEOUT)).map_err(|e| e.to_string())?;
+    socket.set_read_timeout(Some(tunnel_protocol::IDLE_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let leftover = handshake(&mut socket, &addr, &config.token)?;
+    log::info!("[TUNNEL] Connected to {}", config.relay);
+    update(|status| {
+        status.connected = true;
+        status.error = None;
+    });
+
+    let uplink = Uplink { socket: Arc::new(Mutex::new(socket.try_clone().map_err(|e| e.to_string())?)) };
+    let streams: Streams = Arc::new(Mutex::new(HashMap::new()));
+    let mut reader = FrameReader::new(socket, leftover, true);
+    let result = carry(&mut reader, &uplink, &streams, local_port);
+
+    for (_, local) in streams.lock().unwrap().drain() {
+        let _ = local.shutdown(Shutdown::Both);
+    }
+    result
+}
+
+/// Send the upgrade request; returns the bytes read past the response
+fn handshake(socket: &mut TcpStream, host: &str, token: &str) -> Result<Vec<u8>, String> {
+    use base64::Engine;
+    let nonce: Vec<u8> = (0..4).flat_map(|_| mask()).collect();
+    let key = base64::engine::general_purpose::STANDARD.encode(nonce);
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: {}\r\n\
+         Authorization: Bearer {}\r\nUser-Agent: RustCast/{}\r\n\r\n",
+        tunnel_protocol::PATH,
+        host,
+        key,
+        tunnel_protocol::PROTOCOL,
+        token,
+        env!("CARGO_PKG_VERSION")
+    );
+    socket.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_HEAD {
+            return Err("Relay sent an oversized response".to_string());
+        }
+        let read = socket.read(&mut chunk).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("Relay closed the connection during the handshake".to_string());
+        }
+        head.extend_from_slice(&chunk[..read]);
+    };
+    let text = String::from_utf8_lossy(&head[..end]);
+    let status_line = text.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("101") => {}
+        Some("401") => return Err("Relay refused the token (tunnel.token)".to_string()),
+        _ => return Err(format!("Relay answered {:?}", status_line)),
+    }
+    let accept = text.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("Sec-WebSocket-Accept").then(|| value.trim().to_string())
+    });
+    if accept.as_deref() != Some(ws::accept_key(&key).as_str()) {
+        return Err("Relay sent a wrong Sec-WebSocket-Accept".to_string());
+    }
+    Ok(head[end..].to_vec())
+}
+
+/// Dispatch the relay's messages until it goes away
+fn carry(
+    reader: &mut FrameReader<TcpStream>,
+    uplink: &Uplink,
+    streams: &Streams,
+    local_port: u16,
+) -> Result<(), String> {
+    loop {
+        let frame = reader.next().map_err(|e| format!("Tunnel read: {}", e))?;
+        match frame.opcode {
+            Opcode::Ping => uplink.send(0x8A, &frame.payload).map_err(|e| e.to_string())?,
+            Opcode::Close => return Ok(()),
+            Opcode::Text => {
+                let hello: serde_json::Value = serde_json::from_slice(&frame.payload).unwrap_or_default();
+                if let Some(url) = hello["public_url"].as_str().filter(|url| !url.is_empty()) {
+                    log::info!("[TUNNEL] Listeners can connect at {}", url);
+                    let url = url.to_string();
+                    update(|status| status.public_url = Some(url));
+                }
+            }
+            Opcode::Binary => match Message::decode(&frame.payload) {
+                Some(Message::Open { stream, remote }) => open(stream, &remote, uplink, streams, local_port),
+                Some(Message::Data { stream, data }) => {
+                    // Written without the lock: a slow local stream must not hold up the others
+                    let local = streams.lock().unwrap().get(&stream).cloned();
+                    if let Some(Err(e)) = local.map(|local| (&*local).write_all(&data)) {
+                        log::debug!("[TUNNEL] Stream #{}: {}", stream, e);
+                        if let Some(local) = streams.lock().unwrap().remove(&stream) {
+                            let _ = local.shutdown(Shutdown::Both);
+                        }
+                        uplink.message(&Message::Close { stream }).map_err(|e| e.to_string())?;
+                    }
+                }
+                Some(Message::Close { stream }) => {
+                    if let Some(local) = streams.lock().unwrap().remove(&stream) {
+                        let _ = local.shutdown(Shutdown::Both);
+                    }
+                }
+                None => log::debug!("[TUNNEL] Unknown message from the relay"),
+            },
+            Opcode::Pong | Opcode::Continuation => {}
+        }
+        let count = streams.lock().unwrap().len();
+        update(|status| status.streams = count);
+    }
+}
+
+/// A listener connected to the relay: connect to the local server and copy its answers back
+fn open(stream: u32, remote: &str, uplink: &Uplink, streams: &Streams, local_port: u16) {
+    let local = match TcpStream::connect(("127.0.0.1", local_port)) {
+        Ok(local) => local,
+        Err(e) => {
+            log::warn!("[TUNNEL] Local server unreachable: {}", e);
+            let _ = uplink.message(&Message::Close { stream });
+            return;
+        }
+    };
+    let _ = local.set_nodelay(true);
+    let Ok(mut reading) = local.try_clone() else {
+        let _ = uplink.message(&Message::Close { stream });
+        return;
+    };
+    log::debug!("[TUNNEL] Stream #{} from {}", stream, remote);
+    // Registered before any data goes to the server, removed when the connection ends. An
+    // address the relay didn't give still isn't loopback.
+    let local_addr = local.local_addr().ok();
+    if let Some(local_addr) = local_addr {
+        let remote = remote.parse().unwrap_or(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        REMOTES.lock().unwrap().get_or_insert_with(HashMap::new).insert(local_addr, remote);
+    }
+    streams.lock().unwrap().insert(stream, Arc::new(local));
+
+    let (uplink, streams) = (uplink.clone(), streams.clone());
+    let result = thread::Builder::new().name("tunnel-stream".into()).spawn(move || {
+        let mut buffer = vec![0u8; MAX_DATA];
+        loop {
+            let read = match reading.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            if uplink.message(&Message::Data { stream, data: buffer[..read].to_vec() }).is_err() {
+                break;
+            }
+        }
+        // Closed here or by the relay; tell the relay only if it doesn't know yet
+        if streams.lock().unwrap().remove(&stream).is_some() {
+            let _ = uplink.message(&Message::Close { stream });
+        }
+        forget(local_addr);
+    });
+    if let Err(e) = result {
+        log::warn!("[TUNNEL] Stream #{} not started: {}", stream, e);
+        forget(local_addr);
+    }
+}
+
+/// The connection from `local_addr` ended; its port may be reused by anyone
+fn forget(local_addr: Option<SocketAddr>) {
+    if let (Some(local_addr), Some(remotes)) = (local_addr, REMOTES.lock().unwrap().as_mut()) {
+        remotes.remove(&local_addr);
+    }
+}
@@ -0,0 +1,127 @@
+//! Relay tunnel protocol
+//! Shared by the tunnel client (tunnel.rs) and the relay server (bin/relay.rs). The station
+//! opens one WebSocket to the relay; every listener connection on the relay travels over it as
+//! a numbered byte stream.
+//!
+//! Binary messages are a kind byte, the stream number (u32, big-endian) and a payload:
+//! - `OPEN`, relay to station: a listener connected; the payload is its address as text
+//! - `DATA`, both ways: bytes of the stream
+//! - `CLOSE`, both ways: the stream ended
+//!
+//! After the handshake the relay sends one text message, `{"type": "hello", "public_url": "..."}`.
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use crate::ws::{self, Frame};
+
+/// Path the station connects to
+pub const PATH: &str = "/_rustcast/tunnel";
+/// `Sec-WebSocket-Protocol` of this version
+pub const PROTOCOL: &str = "rustcast-tunnel.1";
+/// Most bytes in one DATA message
+pub const MAX_DATA: usize = 16 * 1024;
+/// Nothing received for this long: the other side is gone (the relay pings well within it)
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+
+const OPEN: u8 = 1;
+const DATA: u8 = 2;
+const CLOSE: u8 = 3;
+/// Kind and stream number
+const HEADER: usize = 5;
+/// Largest frame either side reads
+const MAX_FRAME: usize = HEADER + MAX_DATA + 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Open { stream: u32, remote: String },
+    Data { stream: u32, data: Vec<u8> },
+    Close { stream: u32 },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let (kind, stream, payload) = match self {
+            Message::Open { stream, remote } => (OPEN, stream, remote.as_bytes()),
+            Message::Data { stream, data } => (DATA, stream, data.as_slice()),
+            Message::Close { stream } => (CLOSE, stream, &[][..]),
+        };
+        let mut message = Vec::with_capacity(HEADER + payload.len());
+        message.push(kind);
+        message.extend_from_slice(&stream.to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    /// None for a message of an unknown kind or too short to have a stream number
+    pub fn decode(message: &[u8]) -> Option<Self> {
+        let (&kind, rest) = message.split_first()?;
+        let stream = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+        let payload = &rest[4..];
+        match kind {
+            OPEN => Some(Message::Open { stream, remote: String::from_utf8_lossy(payload).into_owned() }),
+            DATA => Some(Message::Data { stream, data: payload.to_vec() }),
+            CLOSE => Some(Message::Close { stream }),
+            _ => None,
+        }
+    }
+}
+
+/// Reads whole frames off the tunnel socket (neither side fragments its messages)
+pub struct FrameReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    /// Frames come from the relay (unmasked) rather than the station
+    from_server: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// `leftover`: bytes read past the handshake
+    pub fn new(inner: R, leftover: Vec<u8>, from_server: bool) -> Self {
+        Self { inner, buffer: leftover, from_server }
+    }
+
+    pub fn next(&mut self) -> io::Result<Frame> {
+        let mut chunk = [0u8; 8192];
+        loop {
+            let decoded = if self.from_server {
+                ws::decode_server_frame(&self.buffer, MAX_FRAME)
+            } else {
+                ws::decode_frame(&self.buffer, MAX_FRAME)
+            };
+            match decoded {
+                Ok(Some((frame, used))) => {
+                    self.buffer.drain(..used);
+                    return Ok(frame);
+                }
+                Ok(None) => {}
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+            let read = self.inner.read(&mut chunk)?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for message in [
+            Message::Open { stream: 7, remote: "203.0.113.5:51234".into() },
+            Message::Data { stream: u32::MAX, data: vec![0, 1, 2, 255] },
+            Message::Data { stream: 1, data: Vec::new() },
+            Message::Close { stream: 0x0102_0304 },
+        ] {
+            assert_eq!(Message::decode(&message.encode()), Some(message));
+        }
+        assert_eq!(Message::Close { stream: 0x0102_0304 }.encode(), [CLOSE, 1, 2, 3, 4]);
+        assert_eq!(Message::decode(&[DATA, 0, 0]), None);
+        assert_eq!(Message::decode(&[9, 0, 0, 0, 1]), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! Encoder output validation
+//! Debug check (`debug.validate_output`) of everything published to the hubs: Opus packets and
+//! the Ogg pages HTTP listeners get from them, Vorbis pages and MP3 frames. Malformed units are
+//! counted for /status and /metrics, and the first one of each stream is logged with its bytes.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::hub::{Packet, PacketTap, Tier};
+use crate::ogg_mux::OggPager;
+use crate::opus_encoder::{self, OpusEncoder};
+use crate::server::OpusStreamInfo;
+
+/// Bytes of a malformed unit shown in the log
+const DUMP_BYTES: usize = 32;
+/// Longest Opus packet (RFC 6716: 120ms at 48kHz)
+const MAX_OPUS_SAMPLES: u64 = 5760;
+
+/// Validators of the running streams, for /status and /metrics
+static VALIDATORS: Mutex<Vec<Arc<OutputValidator>>> = Mutex::new(Vec::new());
+
+/// Counters of one validated stream
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationStats {
+    pub stream: &'static str,
+    /// Packets, pages or frames checked
+    pub units: u64,
+    pub malformed: u64,
+    /// What was wrong with the first malformed unit
+    pub first_error: Option<String>,
+}
+
+/// Counters of all validated streams (empty unless `debug.validate_output` is on)
+pub fn snapshot() -> Vec<ValidationStats> {
+    VALIDATORS.lock().unwrap().iter().map(|validator| validator.stats()).collect()
+}
+
+/// Prometheus text exposition (empty unless validation is on)
+pub fn metrics() -> String {
+    let stats = snapshot();
+    if stats.is_empty() {
+        return String::new();
+    }
+    let mut text = String::from(
+        "# HELP rustcast_malformed_units_total Encoder output units that failed validation\n\
+         # TYPE rustcast_malformed_units_total counter\n",
+    );
+    for stream in stats {
+        text.push_str(&format!("rustcast_malformed_units_total{{stream=\"{}\"}} {}\n", stream.stream, stream.malformed));
+    }
+    text
+}
+
+/// What a hub carries
+enum Format {
+    /// Raw Opus packets, paged per tier the way an HTTP listener would get them
+    Opus { info: OpusStreamInfo, tiers: HashMap<Tier, OpusPaging> },
+    /// Whole Ogg pages (Vorbis)
+    OggPages(OggCheck),
+    /// MP3 frames; a frame may span publishes
+    Mp3 { pending: Vec<u8> },
+}
+
+/// Ogg paging of one Opus tier and the granule its packets add up to
+struct OpusPaging {
+    pager: OggPager,
+    pages: OggCheck,
+    expected_granule: u64,
+}
+
+/// Checks one hub's output; add it to the hub with `BroadcastHub::add_tap`
+pub struct OutputValidator {
+    stream: &'static str,
+    format: Mutex<Format>,
+    units: AtomicU64,
+    malformed: AtomicU64,
+    first_error: Mutex<Option<String>>,
+}
+
+impl OutputValidator {
+    /// Raw Opus packets of the main hub (all tiers)
+    pub fn opus(info: &OpusStreamInfo) -> Arc<Self> {
+        Self::register("opus", Format::Opus { info: info.clone(), tiers: HashMap::new() })
+    }
+
+    /// Ogg Vorbis pages, starting with the stream's header pages
+    pub fn vorbis(headers: &[u8]) -> Arc<Self> {
+        let validator = Self::register("vorbis", Format::OggPages(OggCheck::default()));
+        if let Format::OggPages(pages) = &mut *validator.format.lock().unwrap() {
+            pages.check(headers, &mut |result| validator.record(result.map(|_| ()), headers));
+        }
+        validator
+    }
+
+    /// MP3 frames
+    pub fn mp3() -> Arc<Self> {
+        Self::register("mp3", Format::Mp3 { pending: Vec::new() })
+    }
+
+    fn register(stream: &'static str, format: Format) -> Arc<Self> {
+        let validator = Arc::new(Self {
+            stream,
+            format: Mutex::new(format),
+            units: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+            first_error: Mutex::new(None),
+        });
+        VALIDATORS.lock().unwrap().push(validator.clone());
+        log::info!("[VALIDATE] Checking {} output", stream);
+        validator
+    }
+
+    fn stats(&self) -> ValidationStats {
+        ValidationStats {
+            stream: self.stream,
+            units: self.units.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            first_error: self.first_error.lock().unwrap().clone(),
+        }
+    }
+
+    /// Count one unit; the first malformed one is logged with the bytes it came in
+    fn record(&self, result: Result<(), String>, bytes: &[u8]) {
+        self.units.fetch_add(1, Ordering::Relaxed);
+        let Err(error) = result else {
+            return;
+        };
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+        let mut first = self.first_error.lock().unwrap();
+        if first.is_none() {
+            let dump: Vec<String> = bytes.iter().take(DUMP_BYTES).map(|byte| format!("{:02x}", byte)).collect();
+            log::warn!("[VALIDATE] {}: {} ({} bytes: {})", self.stream, error, bytes.len(), dump.join(" "));
+            *first = Some(error);
+        } else {
+            log::debug!("[VALIDATE] {}: {}", self.stream, error);
+        }
+    }
+}
+
+impl PacketTap for OutputValidator {
+    fn published(&self, tier: Tier, packet: &Packet) {
+        let mut format = self.format.lock().unwrap();
+        match &mut *format {
+            Format::Opus { info, tiers } => {
+                let duration = opus_samples(packet).and_then(|samples| match packet.samples() {
+                    0 => Ok(samples),
+                    reported if reported as u64 == samples => Ok(samples),
+                    reported => Err(format!("Encoder reported {} samples, the packet holds {}", reported, samples)),
+                });
+                let samples = duration.as_ref().copied().unwrap_or(0);
+                self.record(duration.map(|_| ()), packet);
+                let paging = tiers.entry(tier).or_insert_with(|| {
+                    let pager = OggPager::new(info.frame_size, 1);
+                    let mut pages = OggCheck::default();
+                    let headers = OpusEncoder::get_headers_with_serial(info.channels, info.sample_rate, pager.serial, &[]);
+                    pages.check(&headers, &mut |result| self.record(result.map(|_| ()), &headers));
+                    OpusPaging { pager, pages, expected_granule: 0 }
+                });
+                paging.expected_granule += samples;
+                let page = paging.pager.push(packet);
+                let expected = paging.expected_granule;
+                paging.pages.check(&page, &mut |result| {
+                    let result = result.and_then(|granule| match granule {
+                        Some(granule) if granule != expected => {
+                            Err(format!("Page granule {} but the packets add up to {}", granule, expected))
+                        }
+                        _ => Ok(()),
+                    });
+                    self.record(result, &page)
+                });
+            }
+            Format::OggPages(pages) => pages.check(packet, &mut |result| self.record(result.map(|_| ()), packet)),
+            Format::Mp3 { pending } => {
+                pending.extend_from_slice(packet);
+                let mut offset = 0;
+                while let Some(next) = mp3_unit(&pending[offset..]) {
+                    let (len, result) = next;
+                    self.record(result, &pending[offset..offset + len]);
+                    offset += len;
+                }
+                pending.drain(..offset);
+            }
+        }
+    }
+}
+
+/// Samples (at 48kHz) an Opus packet decodes to, from its TOC byte (RFC 6716 3.1)
+fn opus_samples(packet: &[u8]) -> Result<u64, String> {
+    let Some(&toc) = packet.first() else {
+        return Err("Empty Opus packet".to_string());
+    };
+    let config = toc >> 3;
+    let frame = match config {
+        0..=11 => [480, 960, 1920, 2880][config as usize % 4],
+        12..=15 => [480, 960][config as usize % 2],
+        _ => [120, 240, 480, 960][config as usize % 4],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 if packet.len() & 1 == 0 => return Err("Opus packet with two equal frames has an odd length".to_string()),
+        1 | 2 => 2,
+        _ => match packet.get(1) {
+            Some(&count) if count & 0x3F > 0 => (count & 0x3F) as u64,
+            _ => return Err("Opus packet without a valid frame count".to_string()),
+        },
+    };
+    match frame * frames {
+        samples if samples > MAX_OPUS_SAMPLES => Err(format!("Opus packet of {} samples (over 120ms)", samples)),
+        samples => Ok(samples),
+    }
+}
+
+/// Sequence and granule of each Ogg logical stream seen so far
+#[derive(Default)]
+struct OggCheck {
+    streams: HashMap<u32, (u32, u64)>,
+}
+
+impl OggCheck {
+    /// Check every page in `data`; `page` gets each page's result with its granule
+    /// (None for pages where no packet ends)
+    fn check(&mut self, data: &[u8], page: &mut dyn FnMut(Result<Option<u64>, String>)) {
+        let mut rest = data;
+        while !rest.is_empty() {
+            match self.page(rest) {
+                Ok((len, granule)) => {
+                    page(Ok(granule));
+                    rest = &rest[len..];
+                }
+                Err(error) => {
+                    // Can't find the next page boundary after a broken one
+                    page(Err(error));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Check the page at the start of `data`; its length and granule
+    fn page(&mut self, data: &[u8]) -> Result<(usize, Option<u64>), String> {
+        if data.len() < 27 {
+            return Err(format!("Ogg page header cut off at {} bytes", data.len()));
+        }
+        if &data[..4] != b"OggS" || data[4] != 0 {
+            return Err("Not an Ogg page (capture pattern or version)".to_string());
+        }
+        let flags = data[5];
+        let granule = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        let serial = u32::from_le_bytes(data[14..18].try_into().unwrap());
+        let sequence = u32::from_le_bytes(data[18..22].try_into().unwrap());
+        let crc = u32::from_le_bytes(data[22..26].try_into().unwrap());
+        let segments = data[26] as usize;
+        let Some(lacing) = data.get(27..27 + segments) else {
+            return Err("Ogg segment table cut off".to_string());
+        };
+        let len = 27 + segments + lacing.iter().map(|&value| value as usize).sum::<usize>();
+        if data.len() < len {
+            return Err(format!("Ogg page of {} bytes cut off at {}", len, data.len()));
+        }
+
+        let mut checked = data[..len].to_vec();
+        checked[22..26].fill(0);
+        if opus_encoder::ogg_crc32(&checked) != crc {
+            return Err(format!("Bad CRC on page {} of stream {:08x}", sequence, serial));
+        }
+        // No packet ends on this page
+        let granule = (granule != u64::MAX).then_some(granule);
+        let bos = flags & 0x02 != 0;
+        match self.streams.get(&serial).copied() {
+            None if bos && sequence != 0 => return Err(format!("BOS page with sequence {}", sequence)),
+            None => {}
+            Some(_) if bos => return Err(format!("BOS flag on page {} of a running stream", sequence)),
+            Some((last, _)) if sequence != last.wrapping_add(1) => {
+                self.streams.insert(serial, (sequence, granule.unwrap_or(0)));
+                return Err(format!("Page sequence {} after {}", sequence, last));
+            }
+            Some((_, last)) if granule.is_some_and(|granule| granule < last) => {
+                self.streams.insert(serial, (sequence, last));
+                return Err(format!("Granule went back from {} to {}", last, granule.unwrap_or_default()));
+            }
+            Some(_) => {}
+        }
+        let last = self.streams.get(&serial).map(|&(_, last)| last).unwrap_or(0);
+        self.streams.insert(serial, (sequence, granule.unwrap_or(last)));
+        Ok((len, granule))
+    }
+}
+
+/// Length and check result of the MP3 unit at the start of `data`: a frame, an ID3v2 tag, or
+/// garbage up to the next frame sync. None until the unit is complete.
+fn mp3_unit(data: &[u8]) -> Option<(usize, Result<(), String>)> {
+    if data.len() < 4 {
+        return None;
+    }
+    if data.starts_with(b"ID3") {
+        let header = data.get(..10)?;
+        let size = header[6..10].iter().fold(0usize, |size, &byte| (size << 7) | (byte & 0x7F) as usize);
+        return (data.len() >= 10 + size).then_some((10 + size, Ok(())));
+    }
+    match mp3_frame_len(&data[..4]) {
+        Ok(len) => (data.len() >= len).then_some((len, Ok(()))),
+        Err(error) => {
+            // Skip to the next byte that could start a frame
+            let skip = data[1..].iter().position(|&byte| byte == 0xFF).map(|at| at + 1).unwrap_or(data.len());
+            Some((skip, Err(error)))
+        }
+    }
+}
+
+/// Frame length from an MPEG audio Layer III header
+fn mp3_frame_len(header: &[u8]) -> Result<usize, String> {
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return Err("No MP3 frame sync".to_string());
+    }
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let rate_index = ((header[2] >> 2) & 0x03) as usize;
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+    if version == 1 {
+        return Err("Reserved MPEG version".to_string());
+    }
+    if layer != 1 {
+        return Err(format!("Not Layer III (layer bits {})", layer));
+    }
+    if bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return Err(format!("Invalid bitrate or sample rate index ({}, {})", bitrate_index, rate_index));
+    }
+    let mpeg1 = version == 3;
+    let bitrate = if mpeg1 {
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320][bitrate_index]
+    } else {
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160][bitrate_index]
+    };
+    let sample_rate = [44100, 48000, 32000][rate_index]
+        >> match version {
+            3 => 0,
+            2 => 1,
+            _ => 2,
+        };
+    let per_kbps = if mpeg1 { 144_000 } else { 72_000 };
+    Ok(per_kbps * bitrate / sample_rate + padding)
+}
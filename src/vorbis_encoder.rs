@@ -0,0 +1,147 @@
+//! Ogg Vorbis encoding module for legacy network players
+//! Higher latency than Opus, but decoded by older devices that predate Opus
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::num::{NonZeroU32, NonZeroU8};
+use std::rc::Rc;
+use std::sync::Arc;
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder as Encoder, VorbisEncoderBuilder};
+
+use crate::encode_pool::{EncodePool, PcmEncoder, PcmFeed};
+use crate::hub::{BroadcastHub, Packet};
+
+/// Collects the Ogg pages libvorbis writes so they can be broadcast
+#[derive(Clone, Default)]
+struct PageBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl PageBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+}
+
+impl Write for PageBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Vorbis encoder producing one continuous Ogg stream
+pub struct VorbisEncoder {
+    encoder: Encoder<PageBuffer>,
+    pages: PageBuffer,
+    channels: usize,
+    /// Ogg pages holding the three Vorbis header packets
+    headers: Vec<u8>,
+    /// Reused planar buffers (libvorbis wants one slice per channel)
+    planar: Vec<Vec<f32>>,
+}
+
+impl VorbisEncoder {
+    /// Create a new Vorbis encoder
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Input sample rate
+    /// * `channels` - Number of channels
+    /// * `bitrate` - Target (VBR) bitrate in kbps
+    pub fn new(sample_rate: u32, channels: u16, bitrate: u32) -> Result<Self, String> {
+        let pages = PageBuffer::default();
+
+        let sample_rate_nz = NonZeroU32::new(sample_rate).ok_or("Invalid sample rate")?;
+        let channels_nz = u8::try_from(channels)
+            .ok()
+            .and_then(NonZeroU8::new)
+            .ok_or("Invalid channel count")?;
+        let target_bitrate = NonZeroU32::new(bitrate * 1000).ok_or("Invalid bitrate")?;
+
+        let mut builder = VorbisEncoderBuilder::new(sample_rate_nz, channels_nz, pages.clone())
+            .map_err(|e| format!("Failed to create Vorbis encoder: {}", e))?;
+        builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::Vbr { target_bitrate });
+        let encoder = builder
+            .build()
+            .map_err(|e| format!("Failed to create Vorbis encoder: {}", e))?;
+
+        // Header pages are flushed as soon as the encoder is built
+        let headers = pages.take();
+
+        log::info!(
+            "Vorbis encoder: {}Hz, {} channels, {}kbps VBR",
+            sample_rate, channels, bitrate
+        );
+
+        Ok(Self {
+            encoder,
+            pages,
+            channels: channels as usize,
+            headers,
+            planar: vec![Vec::new(); channels as usize],
+        })
+    }
+
+    /// Ogg header pages every new listener needs before any audio page
+    pub fn headers(&self) -> &[u8] {
+        &self.headers
+    }
+
+    /// Encode interleaved samples. Returns the complete Ogg pages produced (often empty,
+    /// libvorbis only emits a page every few KB).
+    pub fn encode(&mut self, samples: &[f32]) -> Result<Vec<u8>, String> {
+        for channel in self.planar.iter_mut() {
+            channel.clear();
+        }
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in self.planar.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+
+        self.encoder
+            .encode_audio_block(&self.planar)
+            .map_err(|e| format!("Vorbis encode failed: {}", e))?;
+
+        Ok(self.pages.take())
+    }
+}
+
+/// Publishes each encoded buffer's pages to the Vorbis hub
+struct VorbisJob {
+    encoder: VorbisEncoder,
+    hub: Arc<BroadcastHub>,
+}
+
+impl PcmEncoder for VorbisJob {
+    fn encode(&mut self, samples: &[f32]) {
+        match self.encoder.encode(samples) {
+            Ok(pages) if !pages.is_empty() => {
+                self.hub.publish(Packet::from(pages));
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("[VORBIS] {}", e),
+        }
+    }
+}
+
+/// Start the Vorbis encoder on the encoder pool. Returns the PCM input and the stream's header pages.
+///
+/// Pages are published to `hub`; every published packet starts and ends on a page boundary,
+/// so listeners can join at any packet after receiving the headers.
+pub fn spawn(
+    pool: &EncodePool,
+    sample_rate: u32,
+    channels: u16,
+    bitrate: u32,
+    hub: Arc<BroadcastHub>,
+) -> Result<(PcmFeed, Packet), String> {
+    // The encoder isn't Send, so it is created on its worker thread
+    pool.add("vorbis", sample_rate, channels, move || {
+        let encoder = VorbisEncoder::new(sample_rate, channels, bitrate)?;
+        let headers = Packet::from(encoder.headers());
+        Ok((Box::new(VorbisJob { encoder, hub }) as Box<dyn PcmEncoder>, headers))
+    })
+}
@@ -0,0 +1,155 @@
+//! Waveform history
+//! Peak and RMS of the last minute in 100ms steps, as JSON or a PNG thumbnail for dashboards
+//! (control API `GET /api/waveform`)
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::dsp::AudioProcessor;
+
+/// Length of one step
+pub const INTERVAL_MS: u32 = 100;
+/// Steps kept (60s)
+const STEPS: usize = 600;
+/// Lowest level recorded (digital silence)
+const FLOOR_DB: f32 = -96.0;
+/// Thumbnail colors: background, peak, RMS
+const BACKGROUND: [u8; 3] = [0x1e, 0x1e, 0x1e];
+const PEAK_COLOR: [u8; 3] = [0x2e, 0x7d, 0x32];
+const RMS_COLOR: [u8; 3] = [0x81, 0xc7, 0x84];
+
+/// One 100ms step (dBFS)
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    peak_db: f32,
+    rms_db: f32,
+}
+
+/// Levels of the last minute, oldest first
+#[derive(Debug, Default)]
+pub struct WaveformHistory {
+    steps: Mutex<VecDeque<Step>>,
+}
+
+impl WaveformHistory {
+    /// `{"interval_ms", "peak_db": [...], "rms_db": [...]}`, oldest first
+    pub fn json(&self) -> serde_json::Value {
+        let steps = self.steps.lock().unwrap();
+        let round = |db: f32| (db * 10.0).round() / 10.0;
+        serde_json::json!({
+            "interval_ms": INTERVAL_MS,
+            "peak_db": steps.iter().map(|step| round(step.peak_db)).collect::<Vec<_>>(),
+            "rms_db": steps.iter().map(|step| round(step.rms_db)).collect::<Vec<_>>(),
+        })
+    }
+
+    /// PNG thumbnail, one column per step (newest on the right), mirrored around the middle:
+    /// peak in dark green, RMS in light green
+    pub fn png(&self, height: u32) -> Vec<u8> {
+        let height = height.clamp(16, 400) as usize;
+        let steps: Vec<Step> = self.steps.lock().unwrap().iter().copied().collect();
+        let width = STEPS;
+        // Pad on the left so the newest step is always at the right edge
+        let offset = width - steps.len();
+        let half = height as f32 / 2.0;
+
+        let mut pixels = Vec::with_capacity((width * 3 + 1) * height);
+        for y in 0..height {
+            pixels.push(0); // filter: none
+            let distance = ((y as f32 + 0.5) - half).abs();
+            for x in 0..width {
+                let color = match x.checked_sub(offset).map(|i| steps[i]) {
+                    Some(step) if distance <= amplitude(step.rms_db) * half => RMS_COLOR,
+                    Some(step) if distance <= amplitude(step.peak_db) * half => PEAK_COLOR,
+                    _ => BACKGROUND,
+                };
+                pixels.extend_from_slice(&color);
+            }
+        }
+        encode_png(width as u32, height as u32, &pixels)
+    }
+
+    fn push(&self, step: Step) {
+        let mut steps = self.steps.lock().unwrap();
+        if steps.len() >= STEPS {
+            steps.pop_front();
+        }
+        steps.push_back(step);
+    }
+}
+
+/// Measures what goes out; leaves the samples alone
+pub struct WaveformTap {
+    history: Arc<WaveformHistory>,
+    /// Samples (all channels) per step
+    step_len: usize,
+    count: usize,
+    peak: f32,
+    sum_squares: f64,
+}
+
+impl WaveformTap {
+    pub fn new(sample_rate: u32, channels: u16) -> (Self, Arc<WaveformHistory>) {
+        let history = Arc::new(WaveformHistory::default());
+        let step_len = (sample_rate as usize * channels.max(1) as usize * INTERVAL_MS as usize / 1000).max(1);
+        let tap = Self { history: history.clone(), step_len, count: 0, peak: 0.0, sum_squares: 0.0 };
+        (tap, history)
+    }
+}
+
+impl AudioProcessor for WaveformTap {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter() {
+            self.peak = self.peak.max(sample.abs());
+            self.sum_squares += (*sample as f64) * (*sample as f64);
+            self.count += 1;
+            if self.count == self.step_len {
+                let rms = (self.sum_squares / self.count as f64).sqrt() as f32;
+                self.history.push(Step { peak_db: to_db(self.peak), rms_db: to_db(rms) });
+                self.count = 0;
+                self.peak = 0.0;
+                self.sum_squares = 0.0;
+            }
+        }
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// Linear amplitude (0-1) of a level, on a 60 dB scale so quiet passages stay visible
+fn amplitude(db: f32) -> f32 {
+    ((db + 60.0) / 60.0).clamp(0.0, 1.0)
+}
+
+/// 8-bit RGB PNG from filtered scanlines
+fn encode_png(width: u32, height: u32, scanlines: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    // Writing into a Vec can't fail
+    let _ = zlib.write_all(scanlines);
+    let data = zlib.finish().unwrap_or_default();
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, body) in [(b"IHDR", &header[..]), (b"IDAT", &data[..]), (b"IEND", &[][..])] {
+        png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(body);
+        png.extend_from_slice(kind);
+        png.extend_from_slice(body);
+        png.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+    png
+}
@@ -0,0 +1,218 @@
+//! WebTransport endpoint
+//! Opus packets as QUIC datagrams for the web player (`webtransport`, built with `--features
+//! webtransport`): a lost packet is a gap the decoder conceals instead of a stall behind TCP
+//! retransmits. The player tries it before /ws; sessions are admitted like /ws by `server.rs`.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use crate::config::WebTransportConfig;
+use crate::hub::Subscription;
+
+/// Session path on the endpoint
+#[cfg_attr(not(feature = "webtransport"), allow(dead_code))]
+pub const PATH: &str = "/wt";
+
+/// Admits a session, given as a GET of its path and query from the client's address: the
+/// subscription with the hello queued, or the HTTP status to refuse it with
+pub type Admit = Arc<dyn Fn(tiny_http::Request) -> Result<Subscription, u16> + Send + Sync>;
+
+/// What the player needs to connect, for /api/player-config
+#[cfg_attr(not(feature = "webtransport"), allow(dead_code))]
+#[derive(Debug, Clone, Serialize)]
+pub struct Advert {
+    /// UDP port on the page's host
+    pub port: u16,
+    /// SHA-256 of the self-signed certificate (colon-separated hex) for `serverCertificateHashes`;
+    /// None with a configured certificate, which the browser checks as usual
+    pub cert_hash: Option<String>,
+}
+
+/// Set while the endpoint is listening
+static ADVERT: Mutex<Option<Advert>> = Mutex::new(None);
+
+pub fn advert() -> Option<Advert> {
+    ADVERT.lock().unwrap().clone()
+}
+
+/// Start the endpoint on a thread of its own; `http_port` is the default UDP port
+#[cfg(feature = "webtransport")]
+pub fn spawn(config: &WebTransportConfig, http_port: u16, admit: Admit) {
+    quic::spawn(config.clone(), http_port, admit);
+}
+
+#[cfg(not(feature = "webtransport"))]
+pub fn spawn(_config: &WebTransportConfig, _http_port: u16, _admit: Admit) {
+    log::warn!("[WEBTRANSPORT] webtransport.enabled needs a build with --features webtransport; players use /ws");
+}
+
+#[cfg(feature = "webtransport")]
+mod quic {
+    use std::io::{self, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    use tokio::runtime::Handle;
+    use wtransport::endpoint::IncomingSession;
+    use wtransport::error::SendDatagramError;
+    use wtransport::tls::Sha256DigestFmt;
+    use wtransport::{Connection, Endpoint, Identity, SendStream, ServerConfig};
+
+    use super::{Admit, Advert, ADVERT, PATH};
+    use crate::config::WebTransportConfig;
+    use crate::hub::{self, Packet, SinkAdapter};
+
+    /// Browsers drop a session that is silent this long; audio and keepalives are more frequent
+    const KEEPALIVE: Duration = Duration::from_secs(5);
+    /// A player that doesn't take control messages for this long is treated as gone
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// First byte `Tagged` puts in front of what `SessionWriter` gets
+    const DATAGRAM: u8 = 0;
+    const CONTROL: u8 = 1;
+
+    pub fn spawn(config: WebTransportConfig, http_port: u16, admit: Admit) {
+        let port = if config.port == 0 { http_port } else { config.port };
+        let result = thread::Builder::new().name("webtransport".into()).spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("[WEBTRANSPORT] Runtime: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = runtime.block_on(serve(&config, port, admit)) {
+                log::error!("[WEBTRANSPORT] {}", e);
+            }
+            *ADVERT.lock().unwrap() = None;
+        });
+        if let Err(e) = result {
+            log::error!("[WEBTRANSPORT] Failed to start: {}", e);
+        }
+    }
+
+    async fn serve(config: &WebTransportConfig, port: u16, admit: Admit) -> Result<(), String> {
+        // Browsers pin a self-signed certificate by hash only if it is valid for at most 14 days,
+        // which `self_signed` keeps to; a new one is made on every start
+        let (identity, cert_hash) = if config.cert.is_empty() {
+            let identity = Identity::self_signed(["localhost", "127.0.0.1", "::1"])
+                .map_err(|e| format!("Self-signed certificate: {}", e))?;
+            let hash = identity.certificate_chain().as_slice()[0].hash().fmt(Sha256DigestFmt::DottedHex);
+            (identity, Some(hash))
+        } else {
+            let identity = Identity::load_pemfiles(&config.cert, &config.key)
+                .await
+                .map_err(|e| format!("Certificate {}: {}", config.cert, e))?;
+            (identity, None)
+        };
+        let server_config = ServerConfig::builder()
+            .with_bind_default(port)
+            .with_identity(identity)
+            .keep_alive_interval(Some(KEEPALIVE))
+            .build();
+        let endpoint = Endpoint::server(server_config).map_err(|e| format!("UDP port {}: {}", port, e))?;
+        *ADVERT.lock().unwrap() = Some(Advert { port, cert_hash });
+        log::info!("[WEBTRANSPORT] Listening on UDP port {}", port);
+
+        loop {
+            let incoming = endpoint.accept().await;
+            let admit = admit.clone();
+            tokio::spawn(async move {
+                if let Err(e) = session(incoming, admit).await {
+                    log::debug!("[WEBTRANSPORT] Session error: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn session(incoming: IncomingSession, admit: Admit) -> Result<(), String> {
+        let request = incoming.await.map_err(|e| e.to_string())?;
+        let path = request.path().to_string();
+        if path.split('?').next() != Some(PATH) {
+            request.not_found().await;
+            return Ok(());
+        }
+        // Same checks and hello as /ws, through the server's own request handling
+        let mut probe = tiny_http::TestRequest::new().with_path(&path).with_remote_addr(request.remote_address());
+        if let Some(agent) = request.headers().get("user-agent") {
+            if let Ok(header) = tiny_http::Header::from_bytes("User-Agent", agent) {
+                probe = probe.with_header(header);
+            }
+        }
+        let subscription = match admit(probe.into()) {
+            Ok(subscription) => subscription,
+            Err(status) => {
+                log::debug!("[WEBTRANSPORT] Refused {} ({})", request.remote_address(), status);
+                request.forbidden().await;
+                return Ok(());
+            }
+        };
+        let connection = request.accept().await.map_err(|e| e.to_string())?;
+        let control = connection
+            .open_uni()
+            .await
+            .map_err(|e| e.to_string())?
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // hub::pump blocks; it gets a thread like the other sinks
+        let mut writer = SessionWriter { connection, control, runtime: Handle::current() };
+        thread::spawn(move || {
+            if let Err(e) = hub::pump(&subscription, &mut Tagged, &mut writer) {
+                log::debug!("[WEBTRANSPORT] Client #{}: {}", subscription.id, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Marks each item for `SessionWriter`: audio goes out as a datagram, text on the control stream
+    struct Tagged;
+
+    impl SinkAdapter for Tagged {
+        fn wrap(&mut self, packet: &Packet) -> Vec<u8> {
+            let mut data = Vec::with_capacity(packet.len() + 1);
+            data.push(DATAGRAM);
+            data.extend_from_slice(packet);
+            data
+        }
+
+        /// One JSON message per line
+        fn wrap_text(&mut self, text: &str) -> Option<Vec<u8>> {
+            let mut data = Vec::with_capacity(text.len() + 2);
+            data.push(CONTROL);
+            data.extend_from_slice(text.as_bytes());
+            data.push(b'\n');
+            Some(data)
+        }
+    }
+
+    struct SessionWriter {
+        connection: Connection,
+        control: SendStream,
+        runtime: Handle,
+    }
+
+    impl Write for SessionWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match buf.split_first() {
+                Some((&DATAGRAM, packet)) => match self.connection.send_datagram(packet) {
+                    // Bigger than the path allows: lost, like any other datagram
+                    Ok(()) | Err(SendDatagramError::TooLarge) => {}
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, e)),
+                },
+                Some((_, text)) => {
+                    let written = self
+                        .runtime
+                        .block_on(tokio::time::timeout(WRITE_TIMEOUT, self.control.write_all(text)))
+                        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?;
+                    written.map_err(|e| io::Error::new(io::ErrorKind::ConnectionAborted, e))?;
+                }
+                None => {}
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,589 @@
+//! WebSocket protocol (RFC 6455)
+//! Handshake key, server frame encoding with optional permessage-deflate, and client frame decoding;
+//! the other direction too for the relay tunnel, where RustCast is the client
+
+// Clients only receive so far; the decoder is there for reading their messages
+#![cfg_attr(not(test), allow(dead_code))]
+
+use flate2::{Compress, Compression, FlushCompress};
+
+/// Appended to `Sec-WebSocket-Key` before hashing (RFC 6455 section 1.3)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Text messages shorter than this go out uncompressed (the deflate block overhead isn't worth it)
+const DEFLATE_MIN_BYTES: usize = 64;
+/// Control frames carry at most this much payload
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    use base64::Engine;
+    use sha1::{Digest, Sha1};
+
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Binary frame (audio)
+pub fn binary_frame(data: &[u8]) -> Vec<u8> {
+    // FIN + Binary opcode (0x82)
+    encode_frame(0x82, data)
+}
+
+/// Text frame (chat and other control messages)
+pub fn text_frame(text: &str) -> Vec<u8> {
+    // FIN + Text opcode (0x81)
+    encode_frame(0x81, text.as_bytes())
+}
+
+/// Close frame with a status code and a reason (cut to fit a control frame)
+pub fn close_frame(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    let mut end = reason.len().min(MAX_CONTROL_PAYLOAD - 2);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    payload.extend_from_slice(&reason.as_bytes()[..end]);
+    // FIN + Close opcode (0x88)
+    encode_frame(0x88, &payload)
+}
+
+/// One unmasked (server to client) frame; `first_byte` holds FIN, RSV and the opcode
+pub fn encode_frame(first_byte: u8, data: &[u8]) -> Vec<u8> {
+    let len = data.len();
+    let mut frame = Vec::with_capacity(10 + len);
+
+    frame.push(first_byte);
+
+    // Payload length (no masking for server->client)
+    if len <= 125 {
+        frame.push(len as u8);
+    } else if len <= 65535 {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+
+    // Payload
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// One masked (client to server) frame
+pub fn masked_frame(first_byte: u8, data: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = encode_frame(first_byte, data);
+    let header = frame.len() - data.len();
+    frame[1] |= 0x80;
+    for (i, byte) in frame[header..].iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    frame.splice(header..header, mask);
+    frame
+}
+
+/// permessage-deflate (RFC 7692), used for text frames only: Opus packets don't compress, and
+/// leaving them alone keeps the audio path as cheap as before. The extension lets every
+/// message choose, so only the server's own side matters.
+pub struct Deflate {
+    compress: Compress,
+    /// Client asked for `server_no_context_takeover`: every message starts a fresh window
+    no_context_takeover: bool,
+}
+
+impl Deflate {
+    /// Accept the first offer in `Sec-WebSocket-Extensions` we can honour. Returns the
+    /// compressor and the value for the response header.
+    pub fn negotiate(offers: Option<&str>) -> Option<(Self, String)> {
+        offers?.split(',').find_map(|offer| {
+            let mut params = offer.split(';').map(str::trim);
+            if params.next()? != "permessage-deflate" {
+                return None;
+            }
+            let mut no_context_takeover = false;
+            for param in params {
+                let (name, value) = match param.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                    None => (param, None),
+                };
+                match (name, value) {
+                    ("server_no_context_takeover", None) => no_context_takeover = true,
+                    ("client_no_context_takeover", None) | ("client_max_window_bits", _) => {}
+                    // The compressor always uses the full 32KB window
+                    ("server_max_window_bits", Some("15")) => {}
+                    _ => return None,
+                }
+            }
+            let mut response = String::from("permessage-deflate");
+            if no_context_takeover {
+                response.push_str("; server_no_context_takeover");
+            }
+            let deflate = Self { compress: Compress::new(Compression::fast(), false), no_context_takeover };
+            Some((deflate, response))
+        })
+    }
+
+    /// Compressed text frame, or None to send the message as it is
+    pub fn text_frame(&mut self, text: &str) -> Option<Vec<u8>> {
+        // FIN + RSV1 (compressed) + Text opcode
+        self.compress(text.as_bytes()).map(|data| encode_frame(0xC1, &data))
+    }
+
+    /// Compressed payload of one message, or None to send it as it is
+    fn compress(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < DEFLATE_MIN_BYTES {
+            return None;
+        }
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+        let start = self.compress.total_in();
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            if let Err(e) = self.compress.compress_vec(&data[consumed..], &mut out, FlushCompress::Sync) {
+                log::debug!("WebSocket deflate error: {}", e);
+                return None;
+            }
+            // A sync flush is complete once it stops filling the whole buffer
+            if (self.compress.total_in() - start) as usize == data.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.capacity().max(64));
+        }
+        // The sync flush ends in an empty stored block; RFC 7692 leaves its 4 bytes out
+        if out.ends_with(&[0x00, 0x00, 0xFF, 0xFF]) {
+            out.truncate(out.len() - 4);
+        }
+        Some(out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        Some(match bits {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xA => Opcode::Pong,
+            _ => return None,
+        })
+    }
+
+    fn is_control(self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// One received frame, unmasked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    /// RSV1: the message is compressed (permessage-deflate)
+    pub compressed: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+/// Why a client's frames were rejected; `close_code()` is the code to close the connection with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// Client frames must be masked
+    Unmasked,
+    /// Server frames must not be
+    Masked,
+    /// RSV2/RSV3 set, or RSV1 on a control or continuation frame
+    ReservedBits,
+    UnknownOpcode(u8),
+    /// Control frame fragmented or longer than 125 bytes
+    InvalidControl,
+    /// Frame or message longer than the reader accepts
+    TooLarge(u64),
+    /// Continuation without a message, or a new message before the last one finished
+    UnexpectedFragment,
+    /// Compressed client messages aren't inflated
+    Compressed,
+    InvalidUtf8,
+    InvalidClose,
+    InvalidCloseCode(u16),
+}
+
+impl FrameError {
+    pub fn close_code(&self) -> u16 {
+        match self {
+            FrameError::Compressed => close_code::UNSUPPORTED,
+            FrameError::InvalidUtf8 => close_code::INVALID_PAYLOAD,
+            FrameError::TooLarge(_) => close_code::TOO_BIG,
+            _ => close_code::PROTOCOL_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Unmasked => write!(f, "unmasked client frame"),
+            FrameError::Masked => write!(f, "masked server frame"),
+            FrameError::ReservedBits => write!(f, "reserved bits set"),
+            FrameError::UnknownOpcode(bits) => write!(f, "unknown opcode {:#x}", bits),
+            FrameError::InvalidControl => write!(f, "fragmented or oversized control frame"),
+            FrameError::TooLarge(len) => write!(f, "{} bytes is too large", len),
+            FrameError::UnexpectedFragment => write!(f, "unexpected fragment"),
+            FrameError::Compressed => write!(f, "compressed messages aren't supported"),
+            FrameError::InvalidUtf8 => write!(f, "text is not valid UTF-8"),
+            FrameError::InvalidClose => write!(f, "malformed close payload"),
+            FrameError::InvalidCloseCode(code) => write!(f, "invalid close code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Close status codes (RFC 6455 section 7.4.1)
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const UNSUPPORTED: u16 = 1003;
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    pub const TOO_BIG: u16 = 1009;
+
+    /// Whether a peer may send `code`. 1005, 1006 and 1015 only exist locally, 1004 is reserved,
+    /// 3000-4999 belong to libraries and applications.
+    pub fn is_valid(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+/// Status code and reason of a close frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseReason {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Code and reason of a close frame's payload; None if it carried no code
+pub fn parse_close(payload: &[u8]) -> Result<Option<CloseReason>, FrameError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(FrameError::InvalidClose),
+        [high, low, reason @ ..] => {
+            let code = u16::from_be_bytes([*high, *low]);
+            if !close_code::is_valid(code) {
+                return Err(FrameError::InvalidCloseCode(code));
+            }
+            let reason = std::str::from_utf8(reason).map_err(|_| FrameError::InvalidUtf8)?;
+            Ok(Some(CloseReason { code, reason: reason.to_string() }))
+        }
+    }
+}
+
+/// Decode one client frame from the start of `data`. Returns the frame and the bytes it used,
+/// or None if `data` doesn't hold a whole frame yet.
+pub fn decode_frame(data: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, FrameError> {
+    decode(data, max_payload, true)
+}
+
+/// `decode_frame` for frames a server sent (unmasked)
+pub fn decode_server_frame(data: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, FrameError> {
+    decode(data, max_payload, false)
+}
+
+fn decode(data: &[u8], max_payload: usize, masked: bool) -> Result<Option<(Frame, usize)>, FrameError> {
+    let [first, second, ..] = *data else {
+        return Ok(None);
+    };
+    let fin = first & 0x80 != 0;
+    let compressed = first & 0x40 != 0;
+    if first & 0x30 != 0 {
+        return Err(FrameError::ReservedBits);
+    }
+    let opcode = Opcode::from_bits(first & 0x0F).ok_or(FrameError::UnknownOpcode(first & 0x0F))?;
+    match (masked, second & 0x80 != 0) {
+        (true, false) => return Err(FrameError::Unmasked),
+        (false, true) => return Err(FrameError::Masked),
+        _ => {}
+    }
+    if compressed && (opcode.is_control() || opcode == Opcode::Continuation) {
+        return Err(FrameError::ReservedBits);
+    }
+
+    let (len, mut offset) = match second & 0x7F {
+        126 => match data.get(2..4) {
+            Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match data.get(2..10) {
+            Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap_or_default()), 10),
+            None => return Ok(None),
+        },
+        len => (len as u64, 2),
+    };
+    if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+        return Err(FrameError::InvalidControl);
+    }
+    if len > max_payload as u64 {
+        return Err(FrameError::TooLarge(len));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        let Some(key) = data.get(offset..offset + 4) else {
+            return Ok(None);
+        };
+        mask.copy_from_slice(key);
+        offset += 4;
+    }
+    let end = offset + len as usize;
+    let Some(payload) = data.get(offset..end) else {
+        return Ok(None);
+    };
+
+    let payload = payload.iter().zip(mask.iter().cycle()).map(|(byte, key)| byte ^ key).collect();
+    Ok(Some((Frame { fin, compressed, opcode, payload }, end)))
+}
+
+/// A complete client message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Close(Option<CloseReason>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+/// Joins fragmented data frames into messages; control frames may arrive between fragments
+pub struct MessageReader {
+    /// Opcode and payload so far of an unfinished message
+    partial: Option<(Opcode, Vec<u8>)>,
+    max_message: usize,
+}
+
+impl MessageReader {
+    pub fn new(max_message: usize) -> Self {
+        Self { partial: None, max_message }
+    }
+
+    /// Feed one frame; returns the message it completes, if any
+    pub fn push(&mut self, frame: Frame) -> Result<Option<Message>, FrameError> {
+        if frame.compressed {
+            return Err(FrameError::Compressed);
+        }
+        let (opcode, payload) = match (frame.opcode, self.partial.take()) {
+            (Opcode::Close, partial) => {
+                self.partial = partial;
+                return parse_close(&frame.payload).map(|reason| Some(Message::Close(reason)));
+            }
+            (Opcode::Ping, partial) => {
+                self.partial = partial;
+                return Ok(Some(Message::Ping(frame.payload)));
+            }
+            (Opcode::Pong, partial) => {
+                self.partial = partial;
+                return Ok(Some(Message::Pong(frame.payload)));
+            }
+            (Opcode::Continuation, Some((opcode, mut payload))) => {
+                payload.extend_from_slice(&frame.payload);
+                (opcode, payload)
+            }
+            (Opcode::Text | Opcode::Binary, None) => (frame.opcode, frame.payload),
+            (Opcode::Continuation, None) | (Opcode::Text | Opcode::Binary, Some(_)) => {
+                return Err(FrameError::UnexpectedFragment);
+            }
+        };
+        if payload.len() > self.max_message {
+            return Err(FrameError::TooLarge(payload.len() as u64));
+        }
+        if !frame.fin {
+            self.partial = Some((opcode, payload));
+            return Ok(None);
+        }
+        Ok(Some(match opcode {
+            Opcode::Text => Message::Text(String::from_utf8(payload).map_err(|_| FrameError::InvalidUtf8)?),
+            _ => Message::Binary(payload),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 64 * 1024;
+
+    use super::masked_frame as masked;
+
+    fn decode(data: &[u8]) -> Frame {
+        let (frame, used) = decode_frame(data, MAX).unwrap().unwrap();
+        assert_eq!(used, data.len());
+        frame
+    }
+
+    #[test]
+    fn accept_key_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn encode_length_forms() {
+        assert_eq!(binary_frame(&[7; 125])[..2], [0x82, 125]);
+        assert_eq!(binary_frame(&[7; 126])[..4], [0x82, 126, 0, 126]);
+        assert_eq!(binary_frame(&[7; 65535])[..4], [0x82, 126, 0xFF, 0xFF]);
+        assert_eq!(binary_frame(&[7; 65536])[..10], [0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(text_frame("Hello"), [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']);
+    }
+
+    #[test]
+    fn masked_client_frame_rfc_example() {
+        // RFC 6455 section 5.7: a single-frame masked text message
+        let data = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let frame = decode(&data);
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(frame.payload, b"Hello");
+    }
+
+    #[test]
+    fn masked_client_frame_long_lengths() {
+        for len in [126, 1000, 65535, 65536] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let frame = decode(&masked(0x82, &payload, [0xA1, 0x02, 0xF3, 0x44]));
+            assert_eq!(frame.opcode, Opcode::Binary);
+            assert_eq!(frame.payload, payload);
+        }
+    }
+
+    #[test]
+    fn partial_frames_wait_for_more() {
+        let data = masked(0x81, &[b'x'; 300], [1, 2, 3, 4]);
+        for len in [0, 1, 3, 7, data.len() - 1] {
+            assert_eq!(decode_frame(&data[..len], MAX), Ok(None), "{} bytes", len);
+        }
+        // Trailing bytes belong to the next frame
+        let mut two = data.clone();
+        two.extend_from_slice(&data);
+        assert_eq!(decode_frame(&two, MAX).unwrap().unwrap().1, data.len());
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert_eq!(decode_frame(&text_frame("Hello"), MAX), Err(FrameError::Unmasked));
+        assert_eq!(decode_frame(&masked(0xA1, b"x", [0; 4]), MAX), Err(FrameError::ReservedBits));
+        assert_eq!(decode_frame(&masked(0x83, b"x", [0; 4]), MAX), Err(FrameError::UnknownOpcode(3)));
+        // Fragmented or oversized control frames
+        assert_eq!(decode_frame(&masked(0x09, b"", [0; 4]), MAX), Err(FrameError::InvalidControl));
+        assert_eq!(decode_frame(&masked(0x89, &[0; 126], [0; 4]), MAX), Err(FrameError::InvalidControl));
+        assert_eq!(decode_frame(&masked(0x82, &[0; 100], [0; 4]), 99), Err(FrameError::TooLarge(100)));
+    }
+
+    #[test]
+    fn server_frames() {
+        let data = binary_frame(&[1, 2, 3]);
+        let (frame, used) = decode_server_frame(&data, MAX).unwrap().unwrap();
+        assert_eq!((frame.opcode, frame.payload, used), (Opcode::Binary, vec![1, 2, 3], data.len()));
+        assert_eq!(decode_server_frame(&data[..2], MAX), Ok(None));
+        assert_eq!(decode_server_frame(&masked(0x82, b"x", [1; 4]), MAX), Err(FrameError::Masked));
+    }
+
+    #[test]
+    fn fragmented_message() {
+        // RFC 6455 section 5.7 "Hel" + "lo", masked, with a ping in between
+        let mut reader = MessageReader::new(MAX);
+        let frames = [
+            masked(0x01, b"Hel", [9, 8, 7, 6]),
+            masked(0x89, b"ping", [1, 1, 1, 1]),
+            masked(0x80, b"lo", [5, 4, 3, 2]),
+        ];
+        assert_eq!(reader.push(decode(&frames[0])), Ok(None));
+        assert_eq!(reader.push(decode(&frames[1])), Ok(Some(Message::Ping(b"ping".to_vec()))));
+        assert_eq!(reader.push(decode(&frames[2])), Ok(Some(Message::Text("Hello".into()))));
+
+        // Binary in three pieces
+        let parts: [&[u8]; 3] = [&[1, 2], &[3], &[4, 5, 6]];
+        assert_eq!(reader.push(decode(&masked(0x02, parts[0], [3; 4]))), Ok(None));
+        assert_eq!(reader.push(decode(&masked(0x00, parts[1], [3; 4]))), Ok(None));
+        assert_eq!(
+            reader.push(decode(&masked(0x80, parts[2], [3; 4]))),
+            Ok(Some(Message::Binary(vec![1, 2, 3, 4, 5, 6])))
+        );
+    }
+
+    #[test]
+    fn fragmented_message_errors() {
+        let mut reader = MessageReader::new(MAX);
+        assert_eq!(reader.push(decode(&masked(0x80, b"lo", [0; 4]))), Err(FrameError::UnexpectedFragment));
+
+        let mut reader = MessageReader::new(MAX);
+        assert_eq!(reader.push(decode(&masked(0x01, b"Hel", [0; 4]))), Ok(None));
+        assert_eq!(reader.push(decode(&masked(0x81, b"lo", [0; 4]))), Err(FrameError::UnexpectedFragment));
+
+        let mut reader = MessageReader::new(4);
+        assert_eq!(reader.push(decode(&masked(0x02, &[0; 3], [0; 4]))), Ok(None));
+        assert_eq!(reader.push(decode(&masked(0x80, &[0; 2], [0; 4]))), Err(FrameError::TooLarge(5)));
+
+        // UTF-8 is checked on the whole message: a character may span fragments
+        let mut reader = MessageReader::new(MAX);
+        let text = "가".as_bytes();
+        assert_eq!(reader.push(decode(&masked(0x01, &text[..1], [0; 4]))), Ok(None));
+        assert_eq!(reader.push(decode(&masked(0x80, &text[1..], [0; 4]))), Ok(Some(Message::Text("가".into()))));
+        assert_eq!(reader.push(decode(&masked(0x81, &[0xC3, 0x28], [0; 4]))), Err(FrameError::InvalidUtf8));
+    }
+
+    #[test]
+    fn close_codes() {
+        let close = |payload: &[u8]| MessageReader::new(MAX).push(decode(&masked(0x88, payload, [7, 7, 7, 7])));
+
+        assert_eq!(close(&[]), Ok(Some(Message::Close(None))));
+        assert_eq!(
+            close(&[0x03, 0xE8, b'b', b'y', b'e']),
+            Ok(Some(Message::Close(Some(CloseReason { code: close_code::NORMAL, reason: "bye".into() }))))
+        );
+        assert!(close(&4000u16.to_be_bytes()).is_ok());
+        assert_eq!(close(&[0x03]), Err(FrameError::InvalidClose));
+        for code in [999, 1004, 1005, 1006, 1015, 2999, 5000] {
+            assert_eq!(close(&u16::to_be_bytes(code)), Err(FrameError::InvalidCloseCode(code)));
+        }
+        assert_eq!(close(&[0x03, 0xE8, 0xFF]), Err(FrameError::InvalidUtf8));
+        assert_eq!(FrameError::InvalidCloseCode(1005).close_code(), close_code::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn close_frame_round_trip() {
+        let frame = close_frame(close_code::GOING_AWAY, &"가".repeat(100));
+        assert_eq!(frame[0], 0x88);
+        assert!(frame.len() - 2 <= MAX_CONTROL_PAYLOAD);
+        let payload = &frame[2..];
+        let reason = parse_close(payload).unwrap().unwrap();
+        assert_eq!(reason.code, close_code::GOING_AWAY);
+        assert_eq!(reason.reason, "가".repeat(41));
+    }
+
+    #[test]
+    fn deflate_negotiation() {
+        assert!(Deflate::negotiate(None).is_none());
+        assert!(Deflate::negotiate(Some("x-webkit-deflate-frame")).is_none());
+        let (_, response) = Deflate::negotiate(Some("permessage-deflate; client_max_window_bits")).unwrap();
+        assert_eq!(response, "permessage-deflate");
+        let offers = "permessage-deflate; server_max_window_bits=10, permessage-deflate; server_no_context_takeover";
+        let (mut deflate, response) = Deflate::negotiate(Some(offers)).unwrap();
+        assert_eq!(response, "permessage-deflate; server_no_context_takeover");
+        assert!(deflate.text_frame("short").is_none());
+        let frame = deflate.text_frame(&"chat ".repeat(40)).unwrap();
+        assert_eq!(frame[0], 0xC1);
+        assert!(frame.len() < 200);
+    }
+}